@@ -0,0 +1,48 @@
+#[macro_use]
+extern crate bencher;
+
+extern crate exr;
+use exr::prelude::*;
+
+use bencher::Bencher;
+use std::fs;
+use std::io::Cursor;
+
+// compare the deflate backends by running these benchmarks once with and once without the `fast-deflate` feature:
+// `cargo bench --bench deflate` and `cargo bench --bench deflate --features fast-deflate`
+
+/// Decompress all blocks of an in-memory zip16 file on a single core, measuring the deflate backend
+fn decompress_zip16_blocks_non_parallel(bench: &mut Bencher) {
+    let mut file = fs::read("tests/images/valid/custom/crowskull/crow_zips.exr").unwrap();
+
+    bench.iter(||{
+        bencher::black_box(&mut file);
+
+        let mut byte_count = 0;
+        exr::block::reader::SliceReader::read_from_slice(file.as_slice(), false).unwrap()
+            .all_chunks(false).unwrap()
+            .decompress_sequential(false, |_, block| { byte_count += block.data.len(); Ok(()) })
+            .unwrap();
+
+        bencher::black_box(byte_count);
+    })
+}
+
+/// Compress an image with zip16 on a single core, measuring the deflate backend
+fn compress_zip16_non_parallel(bench: &mut Bencher) {
+    let mut image = read_first_flat_layer_from_file("tests/images/valid/custom/crowskull/crow_zips.exr").unwrap();
+    image.layer_data.encoding.compression = Compression::ZIP16;
+
+    bench.iter(||{
+        let mut result = Vec::new();
+        image.write().non_parallel().to_buffered(Cursor::new(&mut result)).unwrap();
+        bencher::black_box(result);
+    })
+}
+
+benchmark_group!(deflate,
+    decompress_zip16_blocks_non_parallel,
+    compress_zip16_non_parallel
+);
+
+benchmark_main!(deflate);
@@ -8,6 +8,7 @@ use bencher::Bencher;
 use std::fs;
 use std::io::Cursor;
 use exr::image::pixel_vec::PixelVec;
+use exr::block::samples::IntoNativeSample;
 
 /// Read uncompressed (always single core)
 fn read_single_image_uncompressed_non_parallel_rgba(bench: &mut Bencher) {
@@ -43,6 +44,23 @@ fn read_single_image_uncompressed_rgba(bench: &mut Bencher) {
     })
 }
 
+/// Read uncompressed blocks that borrow their bytes from an in-memory file
+fn read_single_image_uncompressed_blocks_from_slice(bench: &mut Bencher) {
+    let mut file = fs::read("tests/images/valid/custom/crowskull/crow_uncompressed.exr").unwrap();
+
+    bench.iter(||{
+        bencher::black_box(&mut file);
+
+        let mut byte_count = 0;
+        exr::block::reader::SliceReader::read_from_slice(file.as_slice(), false).unwrap()
+            .all_chunks(false).unwrap()
+            .decompress_sequential(false, |_, block| { byte_count += block.data.len(); Ok(()) })
+            .unwrap();
+
+        bencher::black_box(byte_count);
+    })
+}
+
 /// Read with multi-core RLE decompression
 fn read_single_image_rle_all_channels(bench: &mut Bencher) {
     let mut file = fs::read("tests/images/valid/custom/crowskull/crow_rle.exr").unwrap();
@@ -158,15 +176,181 @@ fn read_single_image_zips_non_parallel_rgba(bench: &mut Bencher) {
     })
 }
 
+/// Decompress the blocks of many small layers in parallel, where cloning the meta data would be expensive
+fn read_many_small_layers_rle_blocks(bench: &mut Bencher) {
+    let layers: Vec<_> = (0..200).map(|index| Layer::new(
+        Vec2(16, 16),
+        LayerAttributes::named(format!("layer {}", index).as_str()),
+        Encoding { compression: Compression::RLE, .. Encoding::default() },
+        SpecificChannels::rgba(move |Vec2(x, y)| (x as f32, y as f32, index as f32, 1.0_f32))
+    )).collect();
+
+    let mut file = Vec::new();
+    Image::from_layers(ImageAttributes::new(IntegerBounds::from_dimensions(Vec2(16, 16))), layers)
+        .write().to_buffered(Cursor::new(&mut file)).unwrap();
+
+    bench.iter(||{
+        bencher::black_box(&mut file);
+
+        let chunks = exr::block::read(Cursor::new(file.as_slice()), false).unwrap()
+            .all_chunks(false).unwrap();
+
+        exr::block::reader::ChunksReader::decompress_parallel(chunks, false, |_, block| {
+            bencher::black_box(block);
+            Ok(())
+        }).unwrap();
+    })
+}
+
+/// Decompress all zip blocks in parallel, reading the chunks through a shared buffered reader
+fn read_single_image_zips_blocks_from_buffered(bench: &mut Bencher) {
+    let mut file = fs::read("tests/images/valid/custom/crowskull/crow_zips.exr").unwrap();
+
+    bench.iter(||{
+        bencher::black_box(&mut file);
+
+        let chunks = exr::block::read(Cursor::new(file.as_slice()), false).unwrap()
+            .all_chunks(false).unwrap();
+
+        let mut byte_count = 0;
+        exr::block::reader::ChunksReader::decompress_parallel(chunks, false, |_, block| {
+            byte_count += block.data.len();
+            Ok(())
+        }).unwrap();
+
+        bencher::black_box(byte_count);
+    })
+}
+
+/// Parse and decompress all zip blocks in parallel, borrowing each chunk from an in-memory file
+fn read_single_image_zips_blocks_from_slice(bench: &mut Bencher) {
+    let mut file = fs::read("tests/images/valid/custom/crowskull/crow_zips.exr").unwrap();
+
+    bench.iter(||{
+        bencher::black_box(&mut file);
+
+        let mut byte_count = 0;
+        exr::block::reader::SliceReader::read_from_slice(file.as_slice(), false).unwrap()
+            .all_chunks(false).unwrap()
+            .decompress_parallel(false, |_, block| { byte_count += block.data.len(); Ok(()) })
+            .unwrap();
+
+        bencher::black_box(byte_count);
+    })
+}
+
+/// Decompress all blocks of an in-memory rle file on a single core, measuring the rle decoder itself
+fn read_single_image_rle_blocks_non_parallel(bench: &mut Bencher) {
+    let mut file = fs::read("tests/images/valid/custom/crowskull/crow_rle.exr").unwrap();
+
+    bench.iter(||{
+        bencher::black_box(&mut file);
+
+        let mut byte_count = 0;
+        exr::block::reader::SliceReader::read_from_slice(file.as_slice(), false).unwrap()
+            .all_chunks(false).unwrap()
+            .decompress_sequential(false, |_, block| { byte_count += block.data.len(); Ok(()) })
+            .unwrap();
+
+        bencher::black_box(byte_count);
+    })
+}
+
+/// Decompress all blocks of an in-memory piz file on a single core, measuring the piz decoder itself
+fn read_single_image_piz_blocks_non_parallel(bench: &mut Bencher) {
+    let mut image = read_first_flat_layer_from_file("tests/images/valid/custom/crowskull/crow_rle.exr").unwrap();
+    image.layer_data.encoding.compression = Compression::PIZ;
+
+    let mut file = Vec::new();
+    image.write().non_parallel().to_buffered(Cursor::new(&mut file)).unwrap();
+
+    bench.iter(||{
+        bencher::black_box(&mut file);
+
+        let mut byte_count = 0;
+        exr::block::reader::SliceReader::read_from_slice(file.as_slice(), false).unwrap()
+            .all_chunks(false).unwrap()
+            .decompress_sequential(false, |_, block| { byte_count += block.data.len(); Ok(()) })
+            .unwrap();
+
+        bencher::black_box(byte_count);
+    })
+}
+
+/// An 8k rgb image, written as a single huge tile
+fn single_tile_8k_file(compression: Compression) -> Vec<u8> {
+    let size = Vec2(7680, 4320);
+
+    let pixels = SpecificChannels::rgb(|Vec2(x, y)| (
+        ((x as f32 * 0.01).sin() + (y % 7) as f32 * 0.01).to_f16(),
+        (y as f32 / 4320.0).to_f16(),
+        ((x + y) as f32 * 0.003).cos().to_f16(),
+    ));
+
+    let encoding = Encoding { compression, blocks: Blocks::Tiles(size), line_order: LineOrder::Increasing };
+    let image = Image::from_layer(Layer::new(size, LayerAttributes::named("8k"), encoding, pixels));
+
+    let mut file = Vec::new();
+    image.write().to_buffered(Cursor::new(&mut file)).unwrap();
+    file
+}
+
+fn decompress_all_blocks(file: &[u8], parallel: bool) -> usize {
+    let chunks = exr::block::reader::SliceReader::read_from_slice(file, false).unwrap()
+        .all_chunks(false).unwrap();
+
+    let mut byte_count = 0;
+    let insert_block = |_: &exr::meta::MetaData, block: exr::block::UncompressedBlock| { byte_count += block.data.len(); Ok(()) };
+
+    if parallel { chunks.decompress_parallel(false, insert_block).unwrap() }
+    else { chunks.decompress_sequential(false, insert_block).unwrap() }
+
+    byte_count
+}
+
+/// Decompress a single huge zip tile on a single core
+fn read_single_tile_8k_zip_non_parallel(bench: &mut Bencher) {
+    let file = single_tile_8k_file(Compression::ZIP16);
+    bench.iter(|| bencher::black_box(decompress_all_blocks(&file, false)))
+}
+
+/// Decompress a single huge zip tile, where multiple cores help with the reordering of the bytes
+fn read_single_tile_8k_zip_parallel(bench: &mut Bencher) {
+    let file = single_tile_8k_file(Compression::ZIP16);
+    bench.iter(|| bencher::black_box(decompress_all_blocks(&file, true)))
+}
+
+/// Decompress a single huge piz tile on a single core
+fn read_single_tile_8k_piz_non_parallel(bench: &mut Bencher) {
+    let file = single_tile_8k_file(Compression::PIZ);
+    bench.iter(|| bencher::black_box(decompress_all_blocks(&file, false)))
+}
+
+/// Decompress a single huge piz tile, where multiple cores transform the channels
+fn read_single_tile_8k_piz_parallel(bench: &mut Bencher) {
+    let file = single_tile_8k_file(Compression::PIZ);
+    bench.iter(|| bencher::black_box(decompress_all_blocks(&file, true)))
+}
+
 benchmark_group!(read,
     read_single_image_uncompressed_rgba,
     read_single_image_uncompressed_non_parallel_rgba,
+    read_single_image_uncompressed_blocks_from_slice,
     read_single_image_rle_rgba,
     read_single_image_rle_non_parallel_rgba,
     read_single_image_rle_all_channels,
     read_single_image_rle_non_parallel_all_channels,
     read_single_image_zips_rgba,
     read_single_image_zips_non_parallel_rgba,
+    read_single_image_zips_blocks_from_buffered,
+    read_single_image_zips_blocks_from_slice,
+    read_single_image_rle_blocks_non_parallel,
+    read_single_image_piz_blocks_non_parallel,
+    read_many_small_layers_rle_blocks,
+    read_single_tile_8k_zip_non_parallel,
+    read_single_tile_8k_zip_parallel,
+    read_single_tile_8k_piz_non_parallel,
+    read_single_tile_8k_piz_parallel,
 );
 
 benchmark_main!(read);
\ No newline at end of file
@@ -57,6 +57,18 @@ fn write_parallel_zip16_to_buffered(bench: &mut Bencher) {
     })
 }
 
+fn write_nonparallel_rle_to_buffered(bench: &mut Bencher) {
+    let path = "tests/images/valid/custom/crowskull/crow_rle.exr";
+    let image = read_first_flat_layer_from_file(path).unwrap();
+    assert_eq!(image.layer_data.encoding.compression, Compression::RLE);
+
+    bench.iter(||{
+        let mut result = Vec::new();
+        image.write().non_parallel().to_buffered(Cursor::new(&mut result)).unwrap();
+        bencher::black_box(result);
+    })
+}
+
 fn write_uncompressed_to_buffered(bench: &mut Bencher) {
     let path = "tests/images/valid/custom/crowskull/crow_uncompressed.exr";
     let image = read_all_flat_layers_from_file(path).unwrap();
@@ -74,6 +86,7 @@ benchmark_group!(write,
     write_nonparallel_zip1_to_buffered,
     write_parallel_zip1_to_buffered,
     write_parallel_zip16_to_buffered,
+    write_nonparallel_rle_to_buffered,
     write_uncompressed_to_buffered
 );
 
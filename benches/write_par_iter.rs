@@ -0,0 +1,90 @@
+#[macro_use]
+extern crate bencher;
+
+extern crate exr;
+extern crate rayon;
+
+use exr::prelude::*;
+use exr::block::UncompressedBlock;
+use exr::block::BlockIndex;
+use exr::block::writer::ChunksWriter;
+use exr::meta::MetaData;
+use exr::meta::attribute::LevelMode;
+use exr::math::RoundingMode;
+
+use bencher::Bencher;
+use rayon::prelude::*;
+use std::convert::TryInto;
+use std::io::Cursor;
+
+/// Simulates an expensive renderer that computes each sample on demand.
+fn slow_sample(x: usize, y: usize, channel: usize) -> f32 {
+    let mut value = (x * 7 + y * 13 + channel) as f32;
+    for _ in 0 .. 20 { value = (value * 0.731).sin() * 3.0 + 0.5; }
+    value
+}
+
+fn headers() -> exr::meta::Headers {
+    let header = exr::meta::header::Header::new(
+        "slow".try_into().unwrap(), (256, 256),
+        smallvec::smallvec![
+            attribute::ChannelDescription::new("B", SampleType::F32, true),
+            attribute::ChannelDescription::new("G", SampleType::F32, true),
+            attribute::ChannelDescription::new("R", SampleType::F32, true),
+        ],
+    );
+
+    smallvec::smallvec![ header.with_encoding(
+        Compression::ZIP16,
+        exr::meta::BlockDescription::Tiles(TileDescription {
+            tile_size: Vec2(64, 64), level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down
+        }),
+        LineOrder::Increasing
+    ) ]
+}
+
+fn produce_block(meta: &MetaData, block_index: BlockIndex) -> UncompressedBlock {
+    UncompressedBlock::from_lines(&meta.headers[block_index.layer].channels, block_index, |line| {
+        let location = line.location;
+        line.write_samples(|index| slow_sample(location.position.x() + index, location.position.y(), location.channel))
+            .expect("write to line bug");
+    })
+}
+
+/// Produce the blocks in this thread, and compress them on multiple threads
+fn write_slow_blocks_from_iter(bench: &mut Bencher) {
+    bench.iter(||{
+        let mut result = Vec::new();
+
+        exr::block::write(Cursor::new(&mut result), headers(), true, |meta, chunk_writer| {
+            let blocks = meta.collect_ordered_blocks(|block_index| produce_block(&meta, block_index));
+            chunk_writer.parallel_blocks_compressor(&meta).unwrap().compress_all_blocks(blocks)
+        }).unwrap();
+
+        bencher::black_box(result);
+    })
+}
+
+/// Produce and compress the blocks on multiple threads
+fn write_slow_blocks_from_par_iter(bench: &mut Bencher) {
+    bench.iter(||{
+        let mut result = Vec::new();
+
+        exr::block::write(Cursor::new(&mut result), headers(), true, |meta, chunk_writer| {
+            let block_indices: Vec<_> = meta.enumerate_ordered_header_block_indices().collect();
+            let blocks = block_indices.into_par_iter()
+                .map(|(index_in_header, block_index)| (index_in_header, produce_block(&meta, block_index)));
+
+            chunk_writer.compress_all_blocks_from_par_iter(&meta, blocks)
+        }).unwrap();
+
+        bencher::black_box(result);
+    })
+}
+
+benchmark_group!(write_par_iter,
+    write_slow_blocks_from_iter,
+    write_slow_blocks_from_par_iter,
+);
+
+benchmark_main!(write_par_iter);
@@ -23,7 +23,7 @@ fn main() {
     layer_attributes.software_name = Some(Text::from("EXRS Project"));
     layer_attributes.exposure = Some(1.0);
     layer_attributes.focus = Some(12.4);
-    layer_attributes.frames_per_second = Some((60, 1));
+    layer_attributes.frames_per_second = Some(Rational::FPS_60);
     layer_attributes.other.insert(
         Text::from("Layer Purpose (Custom Layer Attribute)"),
         AttributeValue::Text(Text::from("This layer contains the rgb pixel data"))
@@ -0,0 +1,61 @@
+
+#[macro_use]
+extern crate smallvec;
+extern crate half;
+
+use std::convert::TryInto;
+
+// exr imports
+extern crate exr;
+
+/// Compress and decompress the pixels of single blocks, without reading or writing a file.
+/// This is useful when chunks are stored somewhere else, for example in a cache,
+/// and only decompressed when they are actually needed.
+fn main() {
+    use exr::prelude::*;
+    use exr::meta::header::Header;
+    use exr::math::Vec2;
+
+    let header = Header::new(
+        "cached".try_into().unwrap(), (300, 100),
+        smallvec![
+            ChannelDescription::new("ID", SampleType::U32, false),
+            ChannelDescription::new("Y", SampleType::F16, true),
+        ],
+    );
+
+    let header = header.with_encoding(Compression::ZIP16, exr::meta::BlockDescription::ScanLines, LineOrder::Increasing);
+
+    // the compressed chunks, as they would be stored in the cache, with the index of the block
+    let mut cache = Vec::new();
+
+    for block_y in 0 .. header.chunk_count {
+        let tile = exr::block::chunk::TileCoordinates { tile_index: Vec2(0, block_y), level_index: Vec2(0, 0) };
+        let pixel_section = header.get_absolute_block_pixel_coordinates(tile).unwrap();
+
+        // the samples are interleaved line by line: all ids of a line, then all lumas of the same line
+        let mut pixels = Vec::new();
+        for y in pixel_section.position.y() .. pixel_section.end().y() {
+            for x in 0 .. pixel_section.size.width() {
+                pixels.extend_from_slice(&((x / 10 + y as usize / 10) as u32).to_ne_bytes());
+            }
+
+            for x in 0 .. pixel_section.size.width() {
+                pixels.extend_from_slice(&f16::from_f32(x as f32 / 300.0).to_ne_bytes());
+            }
+        }
+
+        let compressed = header.compression.compress_image_section(&header, pixels, pixel_section).unwrap();
+        cache.push((pixel_section, compressed));
+    }
+
+    let compressed_byte_count: usize = cache.iter().map(|(_, bytes)| bytes.len()).sum();
+    println!("compressed {} blocks into {} bytes", cache.len(), compressed_byte_count);
+
+    // later, possibly in a different process, decompress a single block using only the header
+    let (pixel_section, compressed) = cache[3].clone();
+    let pixels = header.compression.decompress_image_section(&header, compressed, pixel_section, true).unwrap();
+
+    let first_id = u32::from_ne_bytes(pixels[0 .. 4].try_into().unwrap());
+    println!("the block at {:?} starts with the id {}", pixel_section.position, first_id);
+}
@@ -71,6 +71,46 @@ pub struct CompressedTileBlock {
     pub compressed_pixels: Vec<u8>,
 }
 
+/// A chunk of flat pixel data whose compressed bytes are borrowed from a larger byte buffer,
+/// for example a memory-mapped file. Unlike `Chunk`, reading this does not copy the compressed bytes.
+/// Deep data is not supported yet.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkRef<'b> {
+
+    /// The index of the layer that the block belongs to.
+    /// This is required as the pixel data can appear in any order in a file.
+    pub layer_index: usize,
+
+    /// The compressed pixel contents.
+    pub compressed_block: CompressedBlockRef<'b>,
+}
+
+/// The raw, possibly compressed pixel data of a file, borrowed from a byte buffer.
+/// Exists inside a `ChunkRef`.
+#[derive(Debug, Clone, Copy)]
+pub enum CompressedBlockRef<'b> {
+
+    /// Scan line blocks of flat data.
+    ScanLine {
+
+        /// The block's y coordinate is the pixel space y coordinate of the top scan line in the block.
+        y_coordinate: i32,
+
+        /// The possibly compressed pixels of the scan line block.
+        compressed_pixels: &'b [u8],
+    },
+
+    /// Tiles of flat data.
+    Tile {
+
+        /// The tile location.
+        coordinates: TileCoordinates,
+
+        /// The possibly compressed pixels of the tile.
+        compressed_pixels: &'b [u8],
+    },
+}
+
 /// Indicates the position and resolution level of a `TileBlock` or `DeepTileBlock`.
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
 pub struct TileCoordinates {
@@ -91,18 +131,21 @@ pub struct CompressedDeepScanLineBlock {
     /// The top scan line block in the image is aligned with the top edge of the data window.
     pub y_coordinate: i32,
 
-    /// Count of samples.
+    /// The number of bytes of the samples after decompression.
+    /// The size of the decompressed sample count table is not stored, as it
+    /// always contains one 32-bit integer for each pixel in the block.
     pub decompressed_sample_data_size: usize,
 
-    /// The pixel offset table is a list of integers, one for each pixel column within the data window.
+    /// The possibly compressed sample count table.
+    /// After decompression, the table contains one integer for each pixel in the block, row after row.
     /// Each entry in the table indicates the total number of samples required
-    /// to store the pixel in it as well as all pixels to the left of it.
-    pub compressed_pixel_offset_table: Vec<i8>,
+    /// to store the pixel in it as well as all pixels to the left of it in the same row.
+    pub compressed_sample_counts: ByteVec,
 
-    /// One or more scan lines may be stored together as a scan line block.
-    /// The number of scan lines per block depends on how the pixel data are compressed.
-    /// For each line in the tile, for each channel, the row values are contiguous.
-    pub compressed_sample_data: Vec<u8>,
+    /// The possibly compressed samples of all pixels in the block.
+    /// After decompression, for each line in the block, for each channel,
+    /// the samples of all pixels in the row are contiguous.
+    pub compressed_samples: ByteVec,
 }
 
 /// This `Block` is a tile of deep data.
@@ -113,18 +156,21 @@ pub struct CompressedDeepTileBlock {
     /// The tile location.
     pub coordinates: TileCoordinates,
 
-    /// Count of samples.
+    /// The number of bytes of the samples after decompression.
+    /// The size of the decompressed sample count table is not stored, as it
+    /// always contains one 32-bit integer for each pixel in the block.
     pub decompressed_sample_data_size: usize,
 
-    /// The pixel offset table is a list of integers, one for each pixel column within the data window.
+    /// The possibly compressed sample count table.
+    /// After decompression, the table contains one integer for each pixel in the block, row after row.
     /// Each entry in the table indicates the total number of samples required
-    /// to store the pixel in it as well as all pixels to the left of it.
-    pub compressed_pixel_offset_table: Vec<i8>,
+    /// to store the pixel in it as well as all pixels to the left of it in the same row.
+    pub compressed_sample_counts: ByteVec,
 
-    /// One or more scan lines may be stored together as a scan line block.
-    /// The number of scan lines per block depends on how the pixel data are compressed.
-    /// For each line in the tile, for each channel, the row values are contiguous.
-    pub compressed_sample_data: Vec<u8>,
+    /// The possibly compressed samples of all pixels in the block.
+    /// After decompression, for each line in the block, for each channel,
+    /// the samples of all pixels in the row are contiguous.
+    pub compressed_samples: ByteVec,
 }
 
 
@@ -240,42 +286,21 @@ impl CompressedDeepScanLineBlock {
 
     /// Without validation, write this instance to the byte stream.
     pub fn write<W: Write>(&self, write: &mut W) -> UnitResult {
-        debug_assert_ne!(self.compressed_sample_data.len(), 0, "empty blocks should not be put in the file bug");
-
         i32::write(self.y_coordinate, write)?;
-        u64::write(self.compressed_pixel_offset_table.len() as u64, write)?;
-        u64::write(self.compressed_sample_data.len() as u64, write)?; // TODO just guessed
-        u64::write(self.decompressed_sample_data_size as u64, write)?;
-        i8::write_slice(write, &self.compressed_pixel_offset_table)?;
-        u8::write_slice(write, &self.compressed_sample_data)?;
-        Ok(())
+        write_deep_block_data(write, &self.compressed_sample_counts, &self.compressed_samples, self.decompressed_sample_data_size)
     }
 
     /// Read the value without validating.
     pub fn read(read: &mut impl Read, max_block_byte_size: usize) -> Result<Self> {
         let y_coordinate = i32::read(read)?;
-        let compressed_pixel_offset_table_size = u64_to_usize(u64::read(read)?);
-        let compressed_sample_data_size = u64_to_usize(u64::read(read)?);
-        let decompressed_sample_data_size = u64_to_usize(u64::read(read)?);
-
-        // doc said i32, try u8
-        let compressed_pixel_offset_table = i8::read_vec(
-            read, compressed_pixel_offset_table_size,
-            6 * u16::MAX as usize, Some(max_block_byte_size),
-            "deep scan line block table size"
-        )?;
-
-        let compressed_sample_data = u8::read_vec(
-            read, compressed_sample_data_size,
-            6 * u16::MAX as usize, Some(max_block_byte_size),
-            "deep scan line block sample count"
-        )?;
+        let (compressed_sample_counts, compressed_samples, decompressed_sample_data_size) =
+            read_deep_block_data(read, max_block_byte_size, "deep scan line block")?;
 
         Ok(CompressedDeepScanLineBlock {
             y_coordinate,
             decompressed_sample_data_size,
-            compressed_pixel_offset_table,
-            compressed_sample_data,
+            compressed_sample_counts,
+            compressed_samples,
         })
     }
 }
@@ -285,46 +310,58 @@ impl CompressedDeepTileBlock {
 
     /// Without validation, write this instance to the byte stream.
     pub fn write<W: Write>(&self, write: &mut W) -> UnitResult {
-        debug_assert_ne!(self.compressed_sample_data.len(), 0, "empty blocks should not be put in the file bug");
-
         self.coordinates.write(write)?;
-        u64::write(self.compressed_pixel_offset_table.len() as u64, write)?;
-        u64::write(self.compressed_sample_data.len() as u64, write)?; // TODO just guessed
-        u64::write(self.decompressed_sample_data_size as u64, write)?;
-        i8::write_slice(write, &self.compressed_pixel_offset_table)?;
-        u8::write_slice(write, &self.compressed_sample_data)?;
-        Ok(())
+        write_deep_block_data(write, &self.compressed_sample_counts, &self.compressed_samples, self.decompressed_sample_data_size)
     }
 
     /// Read the value without validating.
     pub fn read(read: &mut impl Read, hard_max_block_byte_size: usize) -> Result<Self> {
         let coordinates = TileCoordinates::read(read)?;
-        let compressed_pixel_offset_table_size = u64_to_usize(u64::read(read)?);
-        let compressed_sample_data_size = u64_to_usize(u64::read(read)?); // TODO u64 just guessed
-        let decompressed_sample_data_size = u64_to_usize(u64::read(read)?);
-
-        let compressed_pixel_offset_table = i8::read_vec(
-            read, compressed_pixel_offset_table_size,
-            6 * u16::MAX as usize, Some(hard_max_block_byte_size),
-            "deep tile block table size"
-        )?;
-
-        let compressed_sample_data = u8::read_vec(
-            read, compressed_sample_data_size,
-            6 * u16::MAX as usize, Some(hard_max_block_byte_size),
-            "deep tile block sample count"
-        )?;
+        let (compressed_sample_counts, compressed_samples, decompressed_sample_data_size) =
+            read_deep_block_data(read, hard_max_block_byte_size, "deep tile block")?;
 
         Ok(CompressedDeepTileBlock {
             coordinates,
             decompressed_sample_data_size,
-            compressed_pixel_offset_table,
-            compressed_sample_data,
+            compressed_sample_counts,
+            compressed_samples,
         })
     }
 }
 
-use crate::error::{UnitResult, Result, Error, u64_to_usize, usize_to_i32, i32_to_usize};
+/// Write the sizes of a deep block, followed by the compressed sample count table and the compressed samples.
+fn write_deep_block_data(write: &mut impl Write, sample_counts: &[u8], samples: &[u8], decompressed_sample_data_size: usize) -> UnitResult {
+    u64::write(usize_to_u64(sample_counts.len()), write)?;
+    u64::write(usize_to_u64(samples.len()), write)?;
+    u64::write(usize_to_u64(decompressed_sample_data_size), write)?;
+    u8::write_slice(write, sample_counts)?;
+    u8::write_slice(write, samples)?;
+    Ok(())
+}
+
+/// Read the sizes of a deep block, followed by the compressed sample count table and the compressed samples.
+/// Returns the compressed sample count table, the compressed samples, and the decompressed size of the samples.
+fn read_deep_block_data(read: &mut impl Read, hard_max_block_byte_size: usize, purpose: &'static str) -> Result<(ByteVec, ByteVec, usize)> {
+    let compressed_sample_counts_size = usize::try_from(u64::read(read)?)?;
+    let compressed_samples_size = usize::try_from(u64::read(read)?)?;
+    let decompressed_sample_data_size = usize::try_from(u64::read(read)?)?;
+
+    let compressed_sample_counts = u8::read_vec(
+        read, compressed_sample_counts_size,
+        6 * u16::MAX as usize, Some(hard_max_block_byte_size), purpose
+    )?;
+
+    let compressed_samples = u8::read_vec(
+        read, compressed_samples_size,
+        6 * u16::MAX as usize, Some(hard_max_block_byte_size), purpose
+    )?;
+
+    Ok((compressed_sample_counts, compressed_samples, decompressed_sample_data_size))
+}
+
+use crate::error::{UnitResult, Result, Error, usize_to_u64, usize_to_i32, i32_to_usize};
+use std::convert::TryFrom;
+use crate::compression::ByteVec;
 use crate::math::Vec2;
 
 /// Validation of chunks is done while reading and writing the actual data. (For example in exr::full_image)
@@ -345,17 +382,24 @@ impl Chunk {
         }
     }
 
+    /// The number of compressed bytes in this chunk,
+    /// including the deep sample count table, but excluding the chunk header.
+    pub fn compressed_byte_size(&self) -> usize {
+        match self.compressed_block {
+            CompressedBlock::ScanLine(ref block) => block.compressed_pixels.len(),
+            CompressedBlock::Tile(ref block) => block.compressed_pixels.len(),
+
+            CompressedBlock::DeepScanLine(ref block) =>
+                block.compressed_sample_counts.len() + block.compressed_samples.len(),
+
+            CompressedBlock::DeepTile(ref block) =>
+                block.compressed_sample_counts.len() + block.compressed_samples.len(),
+        }
+    }
+
     /// Read the value without validating.
     pub fn read(read: &mut impl Read, meta_data: &MetaData) -> Result<Self> {
-        let layer_number = i32_to_usize(
-            if meta_data.requirements.is_multilayer() { i32::read(read)? } // documentation says u64, but is i32
-            else { 0_i32 }, // reference the first header for single-layer images
-            "chunk data part number"
-        )?;
-
-        if layer_number >= meta_data.headers.len() {
-            return Err(Error::invalid("chunk data part number"));
-        }
+        let layer_number = read_layer_index(read, meta_data)?;
 
         let header = &meta_data.headers[layer_number];
         let max_block_byte_size = header.max_block_byte_size();
@@ -368,8 +412,8 @@ impl Chunk {
                 BlockDescription::Tiles(_) if !header.deep     => CompressedBlock::Tile(CompressedTileBlock::read(read, max_block_byte_size)?),
 
                 // deep data
-                BlockDescription::ScanLines   => CompressedBlock::DeepScanLine(CompressedDeepScanLineBlock::read(read, max_block_byte_size)?),
-                BlockDescription::Tiles(_)    => CompressedBlock::DeepTile(CompressedDeepTileBlock::read(read, max_block_byte_size)?),
+                BlockDescription::ScanLines   => CompressedBlock::DeepScanLine(CompressedDeepScanLineBlock::read(read, header.max_deep_block_byte_size())?),
+                BlockDescription::Tiles(_)    => CompressedBlock::DeepTile(CompressedDeepTileBlock::read(read, header.max_deep_block_byte_size())?),
             },
         };
 
@@ -377,3 +421,147 @@ impl Chunk {
     }
 }
 
+/// Read the index of the layer that a chunk belongs to, and check that the layer exists.
+fn read_layer_index(read: &mut impl Read, meta_data: &MetaData) -> Result<usize> {
+    let layer_number = i32_to_usize(
+        if meta_data.requirements.is_multilayer() { i32::read(read)? } // documentation says u64, but is i32
+        else { 0_i32 }, // reference the first header for single-layer images
+        "chunk data part number"
+    )?;
+
+    if layer_number >= meta_data.headers.len() {
+        return Err(Error::invalid("chunk data part number"));
+    }
+
+    Ok(layer_number)
+}
+
+/// The location of a chunk and the number of its compressed bytes,
+/// obtained without reading the compressed bytes themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSize {
+
+    /// The index of the layer that the chunk belongs to.
+    pub layer_index: usize,
+
+    /// The location of the chunk in the layer.
+    /// For scan line blocks, this is computed from the y coordinate of the block.
+    pub tile: TileCoordinates,
+
+    /// The number of compressed bytes in this chunk,
+    /// including the deep sample count table, but excluding the chunk header.
+    /// Equal to `Chunk::compressed_byte_size`.
+    pub compressed_byte_size: usize,
+}
+
+impl ChunkSize {
+
+    /// Read the header of a chunk, and then skip its compressed bytes without allocating memory for them.
+    pub fn read_skipping_compressed_bytes(read: &mut impl Read, meta_data: &MetaData) -> Result<Self> {
+        let layer_index = read_layer_index(read, meta_data)?;
+
+        let header = &meta_data.headers[layer_index];
+        let max_block_byte_size = if header.deep { header.max_deep_block_byte_size() } else { header.max_block_byte_size() };
+
+        let read_byte_size = |byte_size: usize, purpose: &'static str| {
+            if byte_size > max_block_byte_size { Err(Error::invalid(purpose)) }
+            else { Ok(byte_size) }
+        };
+
+        let tile = match header.blocks {
+            BlockDescription::ScanLines => header.get_scan_line_block_tile_coordinates(i32::read(read)?)?,
+            BlockDescription::Tiles(_) => TileCoordinates::read(read)?,
+        };
+
+        let compressed_byte_size = {
+            if header.deep {
+                let table_size = read_byte_size(usize::try_from(u64::read(read)?)?, "deep block table size")?;
+                let sample_data_size = read_byte_size(usize::try_from(u64::read(read)?)?, "deep block sample count")?;
+                let _decompressed_sample_data_size = u64::read(read)?;
+                table_size + sample_data_size
+            }
+            else {
+                read_byte_size(i32_to_usize(i32::read(read)?, "block sample count")?, "block sample count")?
+            }
+        };
+
+        skip_bytes(read, compressed_byte_size)?;
+        Ok(ChunkSize { layer_index, tile, compressed_byte_size })
+    }
+}
+
+/// Read the header and the compressed sample count table of a deep chunk, without reading its samples.
+/// Returns the index of the layer, the location of the chunk in the layer, and the compressed table.
+pub(crate) fn read_deep_sample_count_table(read: &mut impl Read, meta_data: &MetaData) -> Result<(usize, TileCoordinates, Vec<u8>)> {
+    let layer_index = read_layer_index(read, meta_data)?;
+
+    let header = &meta_data.headers[layer_index];
+    if !header.deep { return Err(Error::invalid("flat chunk in deep data")); }
+
+    let tile = match header.blocks {
+        BlockDescription::ScanLines => header.get_scan_line_block_tile_coordinates(i32::read(read)?)?,
+        BlockDescription::Tiles(_) => TileCoordinates::read(read)?,
+    };
+
+    let table_size = usize::try_from(u64::read(read)?)?;
+    let _compressed_sample_data_size = u64::read(read)?;
+    let _decompressed_sample_data_size = u64::read(read)?;
+
+    let max_table_size = header.max_deep_block_byte_size();
+    let table = u8::read_vec(read, table_size, 6 * u16::MAX as usize, Some(max_table_size), "deep block table size")?;
+    Ok((layer_index, tile, table))
+}
+
+impl<'b> ChunkRef<'b> {
+
+    /// Read the value without validating, borrowing the compressed bytes from the slice.
+    /// Advances the slice to the end of the chunk.
+    pub fn read_from_slice(bytes: &mut &'b [u8], meta_data: &MetaData) -> Result<Self> {
+        let layer_number = read_layer_index(bytes, meta_data)?;
+
+        let header = &meta_data.headers[layer_number];
+        if header.deep { return Err(Error::unsupported("deep data not supported yet")); }
+
+        let max_block_byte_size = header.max_block_byte_size();
+
+        let compressed_block = match header.blocks {
+            BlockDescription::ScanLines => {
+                let y_coordinate = i32::read(bytes)?;
+                let compressed_pixels = take_i32_sized_slice(bytes, max_block_byte_size, "scan line block sample count")?;
+                CompressedBlockRef::ScanLine { y_coordinate, compressed_pixels }
+            },
+
+            BlockDescription::Tiles(_) => {
+                let coordinates = TileCoordinates::read(bytes)?;
+                let compressed_pixels = take_i32_sized_slice(bytes, max_block_byte_size, "tile block sample count")?;
+                CompressedBlockRef::Tile { coordinates, compressed_pixels }
+            },
+        };
+
+        Ok(ChunkRef { layer_index: layer_number, compressed_block })
+    }
+}
+
+impl CompressedBlockRef<'_> {
+
+    /// The possibly compressed pixels of this block.
+    pub fn compressed_pixels(&self) -> &[u8] {
+        match *self {
+            CompressedBlockRef::ScanLine { compressed_pixels, .. } |
+            CompressedBlockRef::Tile { compressed_pixels, .. } => compressed_pixels,
+        }
+    }
+}
+
+/// Split off a slice whose size is stored as an `i32` in front of it.
+fn take_i32_sized_slice<'b>(bytes: &mut &'b [u8], max_byte_size: usize, purpose: &'static str) -> Result<&'b [u8]> {
+    let byte_size = i32_to_usize(i32::read(bytes)?, purpose)?;
+
+    if byte_size > max_byte_size || byte_size > bytes.len() {
+        return Err(Error::invalid(purpose));
+    }
+
+    let (taken, remaining) = bytes.split_at(byte_size);
+    *bytes = remaining;
+    Ok(taken)
+}
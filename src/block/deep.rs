@@ -0,0 +1,447 @@
+//! Compress and decompress deep data blocks, where each pixel contains a varying number of samples.
+
+use std::convert::TryFrom;
+use smallvec::SmallVec;
+use half::f16;
+use crate::block::BlockIndex;
+use crate::block::chunk::{Chunk, CompressedBlock, CompressedDeepScanLineBlock, CompressedDeepTileBlock, TileCoordinates};
+use crate::compression::{ByteVec, Bytes};
+use crate::error::{Result, UnitResult, Error, usize_to_i32};
+use crate::io::Data;
+use crate::meta::{MetaData, BlockDescription, ValidationOptions, ReadLimits};
+use crate::meta::header::Header;
+use crate::meta::attribute::{SampleType, ChannelList};
+use std::ops::RangeInclusive;
+use crate::block::samples::Sample;
+
+
+/// Contains the decompressed samples of a deep block and where that data should be placed in the actual image.
+/// Each pixel of a deep block contains any number of samples, including none.
+#[derive(Clone, PartialEq, Debug)]
+pub struct DeepUncompressedBlock {
+
+    /// Location of the data inside the image.
+    pub index: BlockIndex,
+
+    /// The number of samples in each pixel of this block, row after row.
+    pub sample_counts: Vec<u32>,
+
+    /// The samples of each channel, in the same order as the channels of the header.
+    /// Each channel contains the samples of the first pixel, then the samples of the second pixel,
+    /// and so on, row after row. The number of samples of each pixel is found in `sample_counts`.
+    pub channels: SmallVec<[DeepChannelSamples; 5]>,
+}
+
+/// All samples of a single channel in a deep block.
+/// The precision of all values is either `f16`, `f32` or `u32`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum DeepChannelSamples {
+
+    /// A vector of deep `f16` values.
+    F16(Vec<f16>),
+
+    /// A vector of deep `f32` values.
+    F32(Vec<f32>),
+
+    /// A vector of deep `u32` values.
+    U32(Vec<u32>),
+}
+
+
+impl DeepUncompressedBlock {
+
+    /// Decompress the sample counts and the samples of a deep chunk, either a scan line block or a tile.
+    /// Tiles of any resolution level can be decompressed, including the partial tiles at the edges of a level.
+    /// Returns an error if the chunk does not contain deep data.
+    #[must_use]
+    pub fn decompress_chunk(chunk: Chunk, meta_data: &MetaData, validation: impl Into<ValidationOptions>) -> Result<Self> {
+        Self::decompress_chunk_with_sample_limit(chunk, meta_data, validation.into(), None)
+    }
+
+    /// Decompress the sample counts and the samples of a deep chunk, like `decompress_chunk`.
+    /// Returns an error before decompressing the samples if any pixel
+    /// contains more samples than the specified maximum.
+    /// Use this to avoid allocating large amounts of memory for untrusted files.
+    #[must_use]
+    pub fn decompress_chunk_with_sample_limit(chunk: Chunk, meta_data: &MetaData, validation: impl Into<ValidationOptions>, max_samples_per_pixel: Option<usize>) -> Result<Self> {
+        let validation = validation.into();
+        let header: &Header = meta_data.headers.get(chunk.layer_index)
+            .ok_or(Error::invalid("chunk layer index"))?;
+
+        match chunk.compressed_block {
+            CompressedBlock::DeepScanLine(CompressedDeepScanLineBlock {
+                y_coordinate, decompressed_sample_data_size,
+                compressed_sample_counts, compressed_samples
+            }) => {
+                let tile = header.get_scan_line_block_tile_coordinates(y_coordinate)?;
+
+                Self::decompress_samples(
+                    header, chunk.layer_index, tile, &compressed_sample_counts,
+                    &compressed_samples, decompressed_sample_data_size, validation, max_samples_per_pixel
+                )
+            },
+
+            CompressedBlock::DeepTile(CompressedDeepTileBlock {
+                coordinates, decompressed_sample_data_size,
+                compressed_sample_counts, compressed_samples
+            }) => {
+                Self::decompress_samples(
+                    header, chunk.layer_index, coordinates, &compressed_sample_counts,
+                    &compressed_samples, decompressed_sample_data_size, validation, max_samples_per_pixel
+                )
+            },
+
+            _ => Err(Error::invalid("flat chunk in deep data")),
+        }
+    }
+
+    /// Consume this block by compressing it, returning a `Chunk`.
+    /// The sample counts are stored in a table that precedes the samples in the chunk,
+    /// and both are compressed separately, using the compression method of the header.
+    /// Returns an error if the block does not match the header,
+    /// for example if a pixel contains more samples than `max_samples_per_pixel`.
+    #[must_use]
+    pub fn compress_to_chunk(self, headers: &[Header]) -> Result<Chunk> {
+        let DeepUncompressedBlock { index, sample_counts, channels } = self;
+
+        let header: &Header = headers.get(index.layer)
+            .expect("block layer index bug");
+
+        if !header.deep {
+            return Err(Error::invalid("deep block in flat layer"));
+        }
+
+        let tile_coordinates = TileCoordinates {
+            tile_index: index.pixel_position / header.max_block_pixel_size(),
+            level_index: index.level,
+        };
+
+        let absolute_indices = header.get_absolute_block_pixel_coordinates(tile_coordinates)?;
+        absolute_indices.validate(Some(header.layer_size))?;
+
+        if absolute_indices.position.to_usize("data indices start")? != index.pixel_position || absolute_indices.size != index.pixel_size {
+            return Err(Error::invalid("deep block position or size"));
+        }
+
+        if sample_counts.len() != index.pixel_size.area() {
+            return Err(Error::invalid("deep block sample count table size"));
+        }
+
+        if let Some(max) = header.max_samples_per_pixel {
+            if sample_counts.iter().any(|&count| count as usize > max) {
+                return Err(Error::invalid("deep sample count exceeds max samples per pixel"));
+            }
+        }
+
+        let total_sample_count: usize = sample_counts.iter().map(|&count| count as usize).sum();
+
+        let channels_match_header = channels.len() == header.channels.list.len()
+            && channels.iter().zip(&header.channels.list).all(|(samples, channel)|
+                samples.sample_type() == channel.sample_type && samples.len() == total_sample_count
+            );
+
+        if !channels_match_header {
+            return Err(Error::invalid("deep block channels do not match the header"));
+        }
+
+        let block_width = index.pixel_size.width().max(1);
+        let mut sample_count_table = Vec::with_capacity(sample_counts.len() * std::mem::size_of::<i32>());
+        let mut sample_bytes = Vec::with_capacity(total_sample_count * header.channels.bytes_per_pixel);
+        let mut line_sample_start = 0;
+
+        // for each line, for each channel, the samples of all pixels in that line are contiguous
+        for line_sample_counts in sample_counts.chunks(block_width) {
+            let mut accumulated_count: i32 = 0;
+
+            for &count in line_sample_counts {
+                accumulated_count = i32::try_from(count).ok()
+                    .and_then(|count| accumulated_count.checked_add(count))
+                    .ok_or(Error::invalid("deep sample count"))?;
+
+                accumulated_count.write(&mut sample_count_table)?;
+            }
+
+            let line_sample_end = line_sample_start + accumulated_count as usize;
+
+            for channel in &channels {
+                channel.write_samples(&mut sample_bytes, line_sample_start .. line_sample_end)?;
+            }
+
+            line_sample_start = line_sample_end;
+        }
+
+        let decompressed_sample_data_size = sample_bytes.len();
+        let compressed_samples = header.compression.compress_deep_bytes(header, sample_bytes)?;
+        let compressed_sample_counts = header.compression.compress_deep_bytes(header, sample_count_table)?;
+
+        Ok(Chunk {
+            layer_index: index.layer,
+            compressed_block: match header.blocks {
+                BlockDescription::ScanLines => CompressedBlock::DeepScanLine(CompressedDeepScanLineBlock {
+                    y_coordinate: usize_to_i32(index.pixel_position.y()) + header.own_attributes.layer_position.y(),
+                    decompressed_sample_data_size,
+                    compressed_sample_counts,
+                    compressed_samples,
+                }),
+
+                BlockDescription::Tiles(_) => CompressedBlock::DeepTile(CompressedDeepTileBlock {
+                    coordinates: tile_coordinates,
+                    decompressed_sample_data_size,
+                    compressed_sample_counts,
+                    compressed_samples,
+                }),
+            }
+        })
+    }
+
+    /// The total number of samples of each channel in this block.
+    pub fn total_sample_count(&self) -> usize {
+        self.sample_counts.iter().map(|&count| count as usize).sum()
+    }
+
+    /// Remove all samples that are not inside the depth range, adjusting the sample counts of the pixels.
+    /// A sample is inside the range if its `Z` value, and also its `ZBack` value if the channel exists,
+    /// are inside the range, including its bounds. Samples with a `NaN` depth are removed.
+    /// Returns an error if the channels do not contain a `Z` channel.
+    pub fn retain_samples_in_depth_range(&mut self, channels: &ChannelList, depth_range: RangeInclusive<f32>) -> UnitResult {
+        let find_channel = |name: &str| channels.list.iter().position(|channel| channel.name.eq(name));
+
+        let depth = find_channel("Z")
+            .ok_or(Error::invalid("deep samples can only be filtered by depth if the layer contains a `Z` channel"))?;
+
+        let depth_back = find_channel("ZBack");
+
+        let channel_in_range = |channel: usize, index: usize|
+            depth_range.contains(&self.channels[channel].value_by_index(index).to_f32());
+
+        let retained: Vec<bool> = (0 .. self.total_sample_count())
+            .map(|index| channel_in_range(depth, index) && depth_back.map_or(true, |depth_back| channel_in_range(depth_back, index)))
+            .collect();
+
+        let mut remaining_retained = retained.as_slice();
+        for count in &mut self.sample_counts {
+            let (pixel_retained, rest) = remaining_retained.split_at(*count as usize);
+            *count = pixel_retained.iter().filter(|&&retain| retain).count() as u32;
+            remaining_retained = rest;
+        }
+
+        for channel in &mut self.channels {
+            channel.retain_samples(&retained);
+        }
+
+        Ok(())
+    }
+
+    /// Locate the block in the layer, then decompress the sample counts and the samples.
+    fn decompress_samples(
+        header: &Header, layer_index: usize, tile: TileCoordinates,
+        compressed_sample_count_table: Bytes<'_>, compressed_samples: Bytes<'_>,
+        decompressed_sample_data_size: usize, validation: ValidationOptions, max_samples_per_pixel: Option<usize>
+    ) -> Result<Self>
+    {
+        let (index, sample_counts) = decompress_sample_counts(
+            header, layer_index, tile, compressed_sample_count_table, validation, max_samples_per_pixel
+        )?;
+        let total_sample_count: usize = sample_counts.iter().map(|&count| count as usize).sum();
+        let expected_byte_size = total_sample_count.checked_mul(header.channels.bytes_per_pixel)
+            .ok_or(Error::invalid("deep sample count"))?;
+
+        ReadLimits::check(expected_byte_size, validation.limits.max_chunk_byte_size, "max_chunk_byte_size")?;
+
+        if decompressed_sample_data_size != expected_byte_size {
+            return Err(Error::invalid("deep sample data size"));
+        }
+
+        let sample_bytes = header.compression.decompress_deep_bytes(compressed_samples, expected_byte_size, validation.verify_decompressed_sizes)?;
+
+        let mut channels: SmallVec<[DeepChannelSamples; 5]> = header.channels.list.iter()
+            .map(|channel| DeepChannelSamples::with_capacity(channel.sample_type, total_sample_count))
+            .collect();
+
+        // for each line, for each channel, the samples of all pixels in that line are contiguous
+        let mut remaining_bytes = sample_bytes.as_slice();
+        for line_sample_counts in sample_counts.chunks(index.pixel_size.width().max(1)) {
+            let line_sample_count: usize = line_sample_counts.iter().map(|&count| count as usize).sum();
+
+            for channel in &mut channels {
+                channel.read_samples(&mut remaining_bytes, line_sample_count)?;
+            }
+        }
+
+        Ok(DeepUncompressedBlock { index, sample_counts, channels })
+    }
+}
+
+/// Locate the block in the layer, then decompress only its sample count table.
+/// Returns the number of samples in each pixel of the block, row after row.
+/// The maximum number of samples per pixel of the header is only checked if decompressed sizes are verified.
+pub(crate) fn decompress_sample_counts(
+    header: &Header, layer_index: usize, tile: TileCoordinates,
+    compressed_sample_count_table: Bytes<'_>, validation: ValidationOptions, max_samples_per_pixel: Option<usize>
+) -> Result<(BlockIndex, Vec<u32>)>
+{
+    let absolute_indices = header.get_absolute_block_pixel_coordinates(tile)?;
+    absolute_indices.validate(Some(header.layer_size))?;
+
+    let index = BlockIndex {
+        layer: layer_index,
+        pixel_position: absolute_indices.position.to_usize("data indices start")?,
+        level: tile.level_index,
+        pixel_size: absolute_indices.size,
+    };
+
+    let sample_count_table = header.compression.decompress_deep_bytes(
+        compressed_sample_count_table, index.pixel_size.area() * std::mem::size_of::<i32>(), validation.verify_decompressed_sizes
+    )?;
+
+    let header_max_samples_per_pixel = if validation.verify_decompressed_sizes { header.max_samples_per_pixel } else { None };
+    let max_samples_per_pixel = match (header_max_samples_per_pixel, max_samples_per_pixel) {
+        (Some(header_max), Some(max)) => Some(header_max.min(max)),
+        (header_max, max) => header_max.or(max),
+    };
+
+    let sample_counts = sample_counts_from_table(&sample_count_table, index.pixel_size.width(), max_samples_per_pixel)?;
+
+    Ok((index, sample_counts))
+}
+
+/// The table contains one little-endian `i32` for each pixel,
+/// which is the number of samples in that pixel and in all pixels to the left of it in the same line.
+fn sample_counts_from_table(table: Bytes<'_>, width: usize, max_samples_per_pixel: Option<usize>) -> Result<Vec<u32>> {
+    if width == 0 && !table.is_empty() {
+        return Err(Error::invalid("deep sample count table"));
+    }
+
+    let mut remaining = table;
+    let mut sample_counts = Vec::with_capacity(table.len() / std::mem::size_of::<i32>());
+
+    while !remaining.is_empty() {
+        let mut previous_accumulated_count = 0;
+
+        for _ in 0 .. width {
+            let accumulated_count = i32::read(&mut remaining)?;
+
+            // the accumulated count of a line never decreases
+            let count = accumulated_count.checked_sub(previous_accumulated_count)
+                .filter(|&count| count >= 0)
+                .ok_or(Error::invalid("deep sample count table"))?;
+
+            if let Some(max) = max_samples_per_pixel {
+                if count as usize > max {
+                    return Err(Error::invalid("deep sample count exceeds max samples per pixel"));
+                }
+            }
+
+            sample_counts.push(count as u32);
+            previous_accumulated_count = accumulated_count;
+        }
+    }
+
+    Ok(sample_counts)
+}
+
+impl DeepChannelSamples {
+
+    /// Create an empty sample vector with the specified type.
+    pub fn with_capacity(sample_type: SampleType, capacity: usize) -> Self {
+        match sample_type {
+            SampleType::F16 => DeepChannelSamples::F16(Vec::with_capacity(capacity)),
+            SampleType::F32 => DeepChannelSamples::F32(Vec::with_capacity(capacity)),
+            SampleType::U32 => DeepChannelSamples::U32(Vec::with_capacity(capacity)),
+        }
+    }
+
+    /// The number of samples in this channel.
+    pub fn len(&self) -> usize {
+        match self {
+            DeepChannelSamples::F16(vec) => vec.len(),
+            DeepChannelSamples::F32(vec) => vec.len(),
+            DeepChannelSamples::U32(vec) => vec.len(),
+        }
+    }
+
+    /// Lookup a single sample by its index.
+    pub fn value_by_index(&self, index: usize) -> Sample {
+        match self {
+            DeepChannelSamples::F16(vec) => Sample::F16(vec[index]),
+            DeepChannelSamples::F32(vec) => Sample::F32(vec[index]),
+            DeepChannelSamples::U32(vec) => Sample::U32(vec[index]),
+        }
+    }
+
+    /// The type of all samples in this channel.
+    pub fn sample_type(&self) -> SampleType {
+        match self {
+            DeepChannelSamples::F16(_) => SampleType::F16,
+            DeepChannelSamples::F32(_) => SampleType::F32,
+            DeepChannelSamples::U32(_) => SampleType::U32,
+        }
+    }
+
+    /// Keep only the samples whose flag is true, in the same order.
+    fn retain_samples(&mut self, retained: &[bool]) {
+        fn retain<T>(vec: &mut Vec<T>, retained: &[bool]) {
+            let mut flags = retained.iter();
+            vec.retain(|_| flags.next().copied().unwrap_or(false));
+        }
+
+        match self {
+            DeepChannelSamples::F16(vec) => retain(vec, retained),
+            DeepChannelSamples::F32(vec) => retain(vec, retained),
+            DeepChannelSamples::U32(vec) => retain(vec, retained),
+        }
+    }
+
+    /// Append the samples in the specified range to the bytes, in little-endian byte order.
+    fn write_samples(&self, bytes: &mut ByteVec, range: std::ops::Range<usize>) -> UnitResult {
+        match self {
+            DeepChannelSamples::F16(vec) => f16::write_slice(bytes, &vec[range]),
+            DeepChannelSamples::F32(vec) => f32::write_slice(bytes, &vec[range]),
+            DeepChannelSamples::U32(vec) => u32::write_slice(bytes, &vec[range]),
+        }
+    }
+
+    /// Append the specified number of little-endian samples from the bytes.
+    fn read_samples(&mut self, bytes: &mut Bytes<'_>, count: usize) -> UnitResult {
+        let soft_max = u16::MAX as usize;
+
+        match self {
+            DeepChannelSamples::F16(vec) => f16::read_into_vec(bytes, vec, count, soft_max, None, "deep f16 samples"),
+            DeepChannelSamples::F32(vec) => f32::read_into_vec(bytes, vec, count, soft_max, None, "deep f32 samples"),
+            DeepChannelSamples::U32(vec) => u32::read_into_vec(bytes, vec, count, soft_max, None, "deep u32 samples"),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table(accumulated_counts: &[i32]) -> ByteVec {
+        accumulated_counts.iter().flat_map(|count| count.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn accumulated_counts_restart_in_each_line() {
+        let counts = sample_counts_from_table(&table(&[ 1, 1, 4,   0, 2, 2 ]), 3, None).unwrap();
+        assert_eq!(counts, vec![ 1, 0, 3,   0, 2, 0 ]);
+    }
+
+    #[test]
+    fn reject_decreasing_accumulated_counts() {
+        assert!(sample_counts_from_table(&table(&[ 3, 2 ]), 2, None).is_err());
+        assert!(sample_counts_from_table(&table(&[ -1, 2 ]), 2, None).is_err());
+    }
+
+    #[test]
+    fn reject_counts_above_max_samples_per_pixel() {
+        assert!(sample_counts_from_table(&table(&[ 2, 5 ]), 2, Some(3)).is_ok());
+        assert!(sample_counts_from_table(&table(&[ 2, 6 ]), 2, Some(3)).is_err());
+    }
+
+    #[test]
+    fn reject_incomplete_table() {
+        assert!(sample_counts_from_table(&table(&[ 1, 2, 3 ]), 2, None).is_err());
+    }
+}
@@ -2,11 +2,14 @@
 
 use crate::math::*;
 use std::io::{Cursor};
-use crate::error::{Result, UnitResult};
+use crate::error::{Result, UnitResult, Error};
 use smallvec::SmallVec;
 use std::ops::Range;
+use half::f16;
 use crate::block::{BlockIndex};
-use crate::meta::attribute::ChannelList;
+use crate::block::samples::Sample;
+use crate::io::Data;
+use crate::meta::attribute::{ChannelList, SampleType, IntegerBounds};
 
 
 /// A single line of pixels.
@@ -138,6 +141,44 @@ impl LineIndex {
             y: block.pixel_position.y()
         }
     }
+
+    /// Iterates the lines of this block index that intersect the specified area, in interleaved fashion.
+    /// Each byte range only contains the samples of the line that are inside the area,
+    /// and the line index describes the clipped line.
+    /// The area uses the same coordinates as the pixel position of the block and may exceed the block.
+    /// Returns no lines if the area does not overlap the block.
+    #[inline]
+    #[must_use]
+    pub fn lines_in_block_subsection(block: BlockIndex, channels: &ChannelList, area: IntegerBounds)
+        -> impl Iterator<Item=(Range<usize>, LineIndex)> + '_
+    {
+        let block_bounds = IntegerBounds::new(block.pixel_position.to_i32(), block.pixel_size);
+
+        let clipped = block_bounds.intersection(area).map(|clipped| {
+            let start = clipped.position.to_usize("clipped position").expect("clipped position bug");
+            (start, clipped.size)
+        });
+
+        clipped.into_iter().flat_map(move |(start, size)| {
+            let x_offset = start.x() - block.pixel_position.x();
+            let y_range = start.y() .. start.y() + size.height();
+
+            Self::lines_in_block(block, channels)
+                .filter(move |(_, line)| y_range.contains(&line.position.y()))
+                .map(move |(bytes, line)| {
+                    let bytes_per_sample = channels.list[line.channel].sample_type.bytes_per_sample();
+                    let clipped_start = bytes.start + x_offset * bytes_per_sample;
+
+                    let clipped_line = LineIndex {
+                        position: Vec2(start.x(), line.position.y()),
+                        sample_count: size.width(),
+                        .. line
+                    };
+
+                    (clipped_start .. clipped_start + size.width() * bytes_per_sample, clipped_line)
+                })
+        })
+    }
 }
 
 
@@ -173,6 +214,20 @@ impl<'s> LineRefMut<'s> {
 
         Ok(())
     }
+
+    /// Write the samples into this line, converting each `f32` to the specified sample type of the channel.
+    /// Returns an error if the slice length or the sample type does not match the line.
+    #[inline]
+    #[must_use]
+    pub fn write_samples_from_f32(self, sample_type: SampleType, samples: &[f32]) -> UnitResult {
+        validate_line_size(self.location, self.value.len(), sample_type, samples.len())?;
+
+        match sample_type {
+            SampleType::F16 => self.write_samples(|index| f16::from_f32(samples[index])),
+            SampleType::F32 => self.write_samples_from_slice(samples),
+            SampleType::U32 => self.write_samples(|index| Sample::F32(samples[index]).to_u32()),
+        }
+    }
 }
 
 impl LineRef<'_> {
@@ -194,4 +249,162 @@ impl LineRef<'_> {
         let mut read = self.value; // FIXME deep data
         (0..self.location.sample_count).map(move |_| T::read(&mut read))
     }
-}
\ No newline at end of file
+
+    /// Read the samples of this line into the slice, converting from the specified sample type of the channel to `f32`.
+    /// Returns an error if the slice length or the sample type does not match the line.
+    pub fn read_samples_into_f32(&self, sample_type: SampleType, samples: &mut [f32]) -> UnitResult {
+        validate_line_size(self.location, self.value.len(), sample_type, samples.len())?;
+
+        match sample_type {
+            SampleType::F32 => f32::read_slice(&mut Cursor::new(self.value), samples),
+            _ => {
+                for (target, sample) in samples.iter_mut().zip(self.samples_as_f32(sample_type)) {
+                    *target = sample;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Iterate over all samples in this line, from left to right,
+    /// converting from the specified sample type of the channel to `f32`.
+    /// If the sample type does not match the line byte size, the iterator returns fewer samples.
+    pub fn samples_as_f32(&self, sample_type: SampleType) -> impl Iterator<Item = f32> + '_ {
+        self.samples(sample_type).map(Sample::to_f32)
+    }
+
+    /// Iterate over all samples in this line, from left to right,
+    /// interpreting the bytes as the specified sample type of the channel.
+    /// If the sample type does not match the line byte size, the iterator returns fewer samples.
+    pub fn samples(&self, sample_type: SampleType) -> impl Iterator<Item = Sample> + '_ {
+        self.value.chunks_exact(sample_type.bytes_per_sample()).map(move |mut bytes| {
+            let sample = match sample_type {
+                SampleType::F16 => f16::read(&mut bytes).map(Sample::F16),
+                SampleType::F32 => f32::read(&mut bytes).map(Sample::F32),
+                SampleType::U32 => u32::read(&mut bytes).map(Sample::U32),
+            };
+
+            sample.expect("sample byte size bug")
+        })
+    }
+}
+
+/// Check that the line has exactly the requested number of samples of the specified type.
+fn validate_line_size(location: LineIndex, byte_size: usize, sample_type: SampleType, sample_count: usize) -> UnitResult {
+    if sample_count != location.sample_count {
+        return Err(Error::invalid("sample count does not match the line width"));
+    }
+
+    if byte_size != location.sample_count * sample_type.bytes_per_sample() {
+        return Err(Error::invalid("sample type does not match the line byte size"));
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn line_index(sample_count: usize) -> LineIndex {
+        LineIndex { layer: 0, channel: 0, level: Vec2(0, 0), position: Vec2(0, 0), sample_count }
+    }
+
+    fn roundtrip_f32(sample_type: SampleType, samples: &[f32]) -> Vec<f32> {
+        let location = line_index(samples.len());
+        let mut bytes = vec![0_u8; samples.len() * sample_type.bytes_per_sample()];
+
+        LineRefMut { location, value: &mut bytes }.write_samples_from_f32(sample_type, samples).unwrap();
+
+        let line = LineRef { location, value: &bytes };
+        let mut read = vec![0.0; samples.len()];
+        line.read_samples_into_f32(sample_type, &mut read).unwrap();
+
+        let iterated: Vec<f32> = line.samples_as_f32(sample_type).collect();
+        assert_eq!(iterated, read);
+
+        read
+    }
+
+    #[test]
+    fn roundtrip_f32_samples(){
+        let samples = [0.0, -1.5, 0.1, 3.0e20, f32::MIN_POSITIVE, f32::INFINITY];
+        assert_eq!(roundtrip_f32(SampleType::F32, &samples), samples);
+    }
+
+    #[test]
+    fn roundtrip_f16_samples(){
+        let samples = [0.0, -1.5, 0.1, 3.0e20, 65504.0, 1.0e-7];
+        let expected: Vec<f32> = samples.iter().map(|&sample| f16::from_f32(sample).to_f32()).collect();
+        assert_eq!(roundtrip_f32(SampleType::F16, &samples), expected);
+
+        // precision is lost, and values out of range become infinite
+        let read = roundtrip_f32(SampleType::F16, &samples);
+        assert_ne!(read[2], 0.1);
+        assert_eq!(read[3], f32::INFINITY);
+        assert_eq!(read[4], 65504.0);
+    }
+
+    #[test]
+    fn roundtrip_u32_samples(){
+        let samples = [0.0, 1.0, 42.0, 4_000_000.0, 1.7];
+        assert_eq!(roundtrip_f32(SampleType::U32, &samples), [0.0, 1.0, 42.0, 4_000_000.0, 1.0]);
+    }
+
+    #[test]
+    fn mismatched_line_sizes_are_errors(){
+        let mut bytes = vec![0_u8; 4 * 4];
+        let location = line_index(4);
+
+        let mut too_short = [0.0; 3];
+        assert!(LineRef { location, value: &bytes }.read_samples_into_f32(SampleType::F32, &mut too_short).is_err());
+        assert!(LineRef { location, value: &bytes }.read_samples_into_f32(SampleType::F16, &mut [0.0; 4]).is_err());
+
+        assert!(LineRefMut { location, value: &mut bytes }.write_samples_from_f32(SampleType::F32, &[0.0; 5]).is_err());
+        assert!(LineRefMut { location, value: &mut bytes }.write_samples_from_f32(SampleType::U32, &[0.0; 4]).is_ok());
+    }
+
+    #[test]
+    fn lines_in_block_subsection_offsets(){
+        use crate::meta::attribute::ChannelDescription;
+
+        let channels = ChannelList::new(smallvec::smallvec![
+            ChannelDescription::named("A", SampleType::F16),
+            ChannelDescription::named("B", SampleType::F32),
+        ]);
+
+        // a 4x3 block at (10, 20), each line is 4*2 bytes of A, followed by 4*4 bytes of B
+        let block = BlockIndex { layer: 0, level: Vec2(0, 0), pixel_position: Vec2(10, 20), pixel_size: Vec2(4, 3) };
+        let line_bytes = 4*2 + 4*4;
+
+        let area = IntegerBounds::new(Vec2(11, 21), Vec2(2, 5)); // exceeds the block at the bottom
+        let lines: Vec<_> = LineIndex::lines_in_block_subsection(block, &channels, area).collect();
+
+        assert_eq!(lines.len(), 2 * 2);
+
+        assert_eq!(lines[0].0, line_bytes + 2 .. line_bytes + 2 + 2*2);
+        assert_eq!(lines[0].1, LineIndex { layer: 0, channel: 0, level: Vec2(0, 0), position: Vec2(11, 21), sample_count: 2 });
+
+        assert_eq!(lines[1].0, line_bytes + 4*2 + 4 .. line_bytes + 4*2 + 4 + 2*4);
+        assert_eq!(lines[1].1, LineIndex { layer: 0, channel: 1, level: Vec2(0, 0), position: Vec2(11, 21), sample_count: 2 });
+
+        assert_eq!(lines[2].0, 2*line_bytes + 2 .. 2*line_bytes + 2 + 2*2);
+        assert_eq!(lines[3].1.position, Vec2(11, 22));
+
+        // the byte ranges select the expected samples from a block with a known byte pattern
+        let bytes: Vec<u8> = (0 .. 3 * line_bytes).map(|byte| byte as u8).collect();
+        let all_lines: Vec<_> = LineIndex::lines_in_block(block, &channels).collect();
+        assert_eq!(&bytes[lines[1].0.clone()], &bytes[all_lines[3].0.clone()][4 .. 12]);
+
+        // areas larger than the block are clamped
+        let everything = IntegerBounds::new(Vec2(-100, -100), Vec2(1000, 1000));
+        let clamped: Vec<_> = LineIndex::lines_in_block_subsection(block, &channels, everything).collect();
+        assert_eq!(clamped, all_lines);
+
+        // areas that do not touch the block yield nothing
+        let outside = IntegerBounds::new(Vec2(14, 20), Vec2(5, 5));
+        assert_eq!(LineIndex::lines_in_block_subsection(block, &channels, outside).count(), 0);
+    }
+}
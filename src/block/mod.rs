@@ -11,20 +11,29 @@
 pub mod writer;
 pub mod reader;
 
+#[cfg(feature = "async")]
+pub mod read_async;
+
 pub mod lines;
 pub mod samples;
 pub mod chunk;
+pub mod pool;
+pub mod deep;
 
 
 use std::io::{Read, Seek, Write};
 use crate::error::{Result, UnitResult, Error, usize_to_i32};
-use crate::meta::{Headers, MetaData, BlockDescription};
+use crate::meta::{Headers, MetaData, BlockDescription, ValidationOptions, ReadLimits};
 use crate::math::Vec2;
-use crate::compression::ByteVec;
-use crate::block::chunk::{CompressedBlock, CompressedTileBlock, CompressedScanLineBlock, Chunk, TileCoordinates};
+use crate::compression::{ByteVec, Compression};
+use crate::block::chunk::{CompressedBlock, CompressedTileBlock, CompressedScanLineBlock, Chunk, TileCoordinates, ChunkRef, CompressedBlockRef};
 use crate::meta::header::Header;
+use crate::block::pool::BlockBufferPool;
 use crate::block::lines::{LineIndex, LineRef, LineSlice, LineRefMut};
-use crate::meta::attribute::ChannelList;
+use crate::meta::attribute::{ChannelList, ChannelDescription, IntegerBounds, SampleType};
+use crate::block::samples::Sample;
+use crate::io::Data;
+use half::f16;
 
 
 /// Specifies where a block of pixel data should be placed in the actual image.
@@ -67,8 +76,8 @@ pub struct UncompressedBlock {
 /// From the reader, you can pull each compressed chunk from the file.
 /// Alternatively, you can create a decompressor, and pull the uncompressed data from it.
 /// The reader is assumed to be buffered.
-pub fn read<R: Read + Seek>(buffered_read: R, pedantic: bool) -> Result<self::reader::Reader<R>> {
-    self::reader::Reader::read_from_buffered(buffered_read, pedantic)
+pub fn read<R: Read + Seek>(buffered_read: R, validation: impl Into<ValidationOptions>) -> Result<self::reader::Reader<R>> {
+    self::reader::Reader::read_from_buffered(buffered_read, validation)
 }
 
 /// Immediately writes the meta data to the file.
@@ -113,49 +122,120 @@ pub fn enumerate_ordered_header_block_indices(headers: &[Header]) -> impl '_ + I
 impl UncompressedBlock {
 
     /// Decompress the possibly compressed chunk and returns an `UncompressedBlock`.
-    // for uncompressed data, the ByteVec in the chunk is moved all the way
+    /// If the pixels in the chunk are stored without compression,
+    /// the byte vector of the chunk becomes the data of the block, without copying or allocating.
     #[inline]
     #[must_use]
-    pub fn decompress_chunk(chunk: Chunk, meta_data: &MetaData, pedantic: bool) -> Result<Self> {
+    pub fn decompress_chunk(chunk: Chunk, meta_data: &MetaData, validation: impl Into<ValidationOptions>) -> Result<Self> {
+        Self::decompress_chunk_into(chunk, meta_data, validation.into(), None)
+    }
+
+    /// Decompress the possibly compressed chunk and returns an `UncompressedBlock`.
+    /// Reuses a buffer from the pool for the decompressed pixels where possible.
+    /// Hand the block back to the pool using `BlockBufferPool::recycle` when its pixels are not needed anymore.
+    #[inline]
+    #[must_use]
+    pub fn decompress_chunk_with_buffer_pool(chunk: Chunk, meta_data: &MetaData, validation: impl Into<ValidationOptions>, buffer_pool: &BlockBufferPool) -> Result<Self> {
+        Self::decompress_chunk_into(chunk, meta_data, validation.into(), Some(buffer_pool))
+    }
+
+    fn decompress_chunk_into(chunk: Chunk, meta_data: &MetaData, validation: ValidationOptions, buffer_pool: Option<&BlockBufferPool>) -> Result<Self> {
         let header: &Header = meta_data.headers.get(chunk.layer_index)
             .ok_or(Error::invalid("chunk layer index"))?;
 
         let tile_data_indices = header.get_block_data_indices(&chunk.compressed_block)?;
-        let absolute_indices = header.get_absolute_block_pixel_coordinates(tile_data_indices)?;
-
-        absolute_indices.validate(Some(header.layer_size))?;
 
         match chunk.compressed_block {
             CompressedBlock::Tile(CompressedTileBlock { compressed_pixels, .. }) |
             CompressedBlock::ScanLine(CompressedScanLineBlock { compressed_pixels, .. }) => {
-                Ok(UncompressedBlock {
-                    data: header.compression.decompress_image_section(header, compressed_pixels, absolute_indices, pedantic)?,
-                    index: BlockIndex {
-                        layer: chunk.layer_index,
-                        pixel_position: absolute_indices.position.to_usize("data indices start")?,
-                        level: tile_data_indices.level_index,
-                        pixel_size: absolute_indices.size,
-                    }
+                Self::decompress_block_pixels(header, chunk.layer_index, tile_data_indices, validation.limits, |absolute_indices| match buffer_pool {
+                    Some(buffer_pool) => header.compression.decompress_image_section_with_buffer_pool(
+                        header, compressed_pixels, absolute_indices, validation.verify_decompressed_sizes, buffer_pool
+                    ),
+
+                    None => header.compression.decompress_image_section(header, compressed_pixels, absolute_indices, validation.verify_decompressed_sizes),
                 })
             },
 
-            _ => return Err(Error::unsupported("deep data not supported yet"))
+            _ => return Err(Error::unsupported("deep data in flat block, see `DeepUncompressedBlock`"))
         }
     }
 
+    /// Decompress the possibly compressed chunk, which borrows its bytes, and returns an `UncompressedBlock`.
+    /// The compressed bytes are only copied if the block is not compressed.
+    #[inline]
+    #[must_use]
+    pub fn decompress_chunk_ref(chunk: ChunkRef<'_>, meta_data: &MetaData, validation: impl Into<ValidationOptions>) -> Result<Self> {
+        let validation = validation.into();
+        let header: &Header = meta_data.headers.get(chunk.layer_index)
+            .ok_or(Error::invalid("chunk layer index"))?;
+
+        let tile_data_indices = match chunk.compressed_block {
+            CompressedBlockRef::Tile { coordinates, .. } => coordinates,
+            CompressedBlockRef::ScanLine { y_coordinate, .. } => header.get_scan_line_block_tile_coordinates(y_coordinate)?,
+        };
+
+        Self::decompress_block_pixels(header, chunk.layer_index, tile_data_indices, validation.limits, |absolute_indices|
+            header.compression.decompress_image_section_from_slice(
+                header, chunk.compressed_block.compressed_pixels(), absolute_indices, validation.verify_decompressed_sizes
+            )
+        )
+    }
+
+    /// Locate the block in the layer and decompress its pixels.
+    /// Checks the byte size of the pixels against the limits before decompressing.
+    fn decompress_block_pixels(
+        header: &Header, layer_index: usize, tile_data_indices: TileCoordinates, limits: ReadLimits,
+        decompress: impl FnOnce(IntegerBounds) -> Result<ByteVec>
+    ) -> Result<Self>
+    {
+        let absolute_indices = header.get_absolute_block_pixel_coordinates(tile_data_indices)?;
+        absolute_indices.validate(Some(header.layer_size))?;
+
+        let byte_size = header.channels.byte_size_of_section(absolute_indices);
+        ReadLimits::check(byte_size, limits.max_chunk_byte_size, "max_chunk_byte_size")?;
+
+        Ok(UncompressedBlock {
+            data: decompress(absolute_indices)?,
+            index: BlockIndex {
+                layer: layer_index,
+                pixel_position: absolute_indices.position.to_usize("data indices start")?,
+                level: tile_data_indices.level_index,
+                pixel_size: absolute_indices.size,
+            }
+        })
+    }
+
     /// Consume this block by compressing it, returning a `Chunk`.
-    // for uncompressed data, the ByteVec in the chunk is moved all the way
+    /// If the layer is not compressed, the data of this block becomes the byte vector of the chunk,
+    /// without copying or allocating.
     #[inline]
     #[must_use]
     pub fn compress_to_chunk(self, headers: &[Header]) -> Result<Chunk> {
+        self.compress_to_chunk_and_verify(headers, false)
+    }
+
+    /// Consume this block by compressing it, returning a `Chunk`.
+    /// Then decompresses the chunk again, and returns an error
+    /// if the decompressed pixels differ from the original pixels of this block.
+    /// Compression methods that may lose data are not verified.
+    #[inline]
+    #[must_use]
+    pub fn compress_to_chunk_verified(self, headers: &[Header]) -> Result<Chunk> {
+        self.compress_to_chunk_and_verify(headers, true)
+    }
+
+    fn compress_to_chunk_and_verify(self, headers: &[Header], verify: bool) -> Result<Chunk> {
         let UncompressedBlock { data, index } = self;
 
         let header: &Header = headers.get(index.layer)
             .expect("block layer index bug");
 
-        let expected_byte_size = header.channels.bytes_per_pixel * self.index.pixel_size.area(); // TODO sampling??
+        let expected_byte_size = header.channels.byte_size_of_section(IntegerBounds::new(self.index.pixel_position.to_i32(), self.index.pixel_size));
         if expected_byte_size != data.len() {
-            panic!("get_line byte size should be {} but was {}", expected_byte_size, data.len());
+            return Err(Error::invalid(format!(
+                "block byte size should be {} but was {}", expected_byte_size, data.len()
+            )));
         }
 
         let tile_coordinates = TileCoordinates {
@@ -167,18 +247,30 @@ impl UncompressedBlock {
         let absolute_indices = header.get_absolute_block_pixel_coordinates(tile_coordinates)?;
         absolute_indices.validate(Some(header.layer_size))?;
 
-        if !header.compression.may_loose_data() { debug_assert_eq!(
-            &header.compression.decompress_image_section(
-                header,
-                header.compression.compress_image_section(header, data.clone(), absolute_indices)?,
-                absolute_indices,
-                true
-            ).unwrap(),
-            &data,
-            "compression method not round trippin'"
-        ); }
+        // the feature allows enabling the verification for all blocks, for example in continuous integration
+        let verify = verify || cfg!(feature = "validate-compression");
+
+        // uncompressed pixels are moved into the chunk, and cannot differ from the original pixels
+        let verify = verify && header.compression != Compression::Uncompressed;
+
+        let compressed_data = if verify && !header.compression.may_loose_data() {
+            let compressed_data = header.compression.compress_image_section(header, data.clone(), absolute_indices)?;
+
+            let decompressed = header.compression.decompress_image_section(
+                header, compressed_data.clone(), absolute_indices, true
+            );
+
+            if decompressed.ok().as_ref() != Some(&data) {
+                return Err(Error::invalid(format!(
+                    "compressed chunk does not decompress to the original pixels of block {:?}", index
+                )));
+            }
 
-        let compressed_data = header.compression.compress_image_section(header, data, absolute_indices)?;
+            compressed_data
+        }
+        else {
+            header.compression.compress_image_section(header, data, absolute_indices)?
+        };
 
         Ok(Chunk {
             layer_index: index.layer,
@@ -205,6 +297,66 @@ impl UncompressedBlock {
             .map(move |(bytes, line)| LineSlice { location: line, value: &self.data[bytes] })
     }
 
+    /// Copy the pixels of this block that are inside the specified rectangle into a new, smaller block.
+    /// The rectangle uses the same coordinates as the pixel position of this block.
+    /// Returns `None` if the block does not overlap the rectangle.
+    pub fn crop(&self, channels: &ChannelList, bounds: IntegerBounds) -> Option<Self> {
+        let block_bounds = IntegerBounds::new(self.index.pixel_position.to_i32(), self.index.pixel_size);
+        let cropped_bounds = block_bounds.intersection(bounds)?;
+
+        let cropped_index = BlockIndex {
+            pixel_position: cropped_bounds.position.to_usize("cropped block position").ok()?,
+            pixel_size: cropped_bounds.size,
+            .. self.index
+        };
+
+        let mut source_lines = LineIndex::lines_in_block_subsection(self.index, channels, bounds);
+
+        let data = Self::collect_block_data_from_lines(channels, cropped_index, |line| {
+            let (source_bytes, _) = source_lines.next().expect("cropped line count bug");
+            line.value.copy_from_slice(&self.data[source_bytes]);
+        });
+
+        Some(UncompressedBlock { index: cropped_index, data })
+    }
+
+    /// Convert the samples of all channels to the specified sample type,
+    /// returning the channel list that describes the layout of the converted block.
+    /// Channels that already have the target type are copied unchanged.
+    /// Conversions saturate: Negative or too large floats become `0` or `u32::MAX`,
+    /// and integers too large for `f16` become the largest finite `f16`.
+    /// Not-a-number floats become zero when converted to integers.
+    pub fn convert_samples(&self, channels: &ChannelList, target_type: SampleType) -> (ChannelList, UncompressedBlock) {
+        let converted_channels = ChannelList::new(
+            channels.list.iter()
+                .map(|channel| ChannelDescription { sample_type: target_type, .. channel.clone() })
+                .collect()
+        );
+
+        let mut source_lines = self.lines(channels);
+
+        let converted_block = Self::from_lines(&converted_channels, self.index, |target_line| {
+            let source_line = source_lines.next().expect("line count bug");
+            let source_type = channels.list[source_line.location.channel].sample_type;
+
+            if source_type == target_type {
+                target_line.value.copy_from_slice(source_line.value);
+                return;
+            }
+
+            let mut write = target_line.value;
+            for sample in source_line.samples(source_type) {
+                match target_type {
+                    SampleType::F16 => sample_to_f16_saturating(sample).write(&mut write),
+                    SampleType::F32 => sample.to_f32().write(&mut write),
+                    SampleType::U32 => sample.to_u32().write(&mut write), // `as` saturates, and converts nan to zero
+                }.expect("converted line byte size bug");
+            }
+        });
+
+        (converted_channels, converted_block)
+    }
+
     /* TODO pub fn lines_mut<'s>(&'s mut self, header: &Header) -> impl 's + Iterator<Item=LineRefMut<'s>> {
         LineIndex::lines_in_block(self.index, &header.channels)
             .map(move |(bytes, line)| LineSlice { location: line, value: &mut self.data[bytes] })
@@ -254,4 +406,148 @@ impl UncompressedBlock {
             data: Self::collect_block_data_from_lines(channels, block_index, extract_line)
         }
     }
-}
\ No newline at end of file
+}
+
+/// Convert to f16, clamping integers that are too large instead of returning infinity.
+fn sample_to_f16_saturating(sample: Sample) -> f16 {
+    match sample {
+        Sample::U32(integer) => f16::from_f32((integer as f32).min(f16::MAX.to_f32())),
+        float => float.to_f16(),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::math::RoundingMode;
+    use crate::meta::attribute::{LevelMode, LineOrder, Text, TileDescription};
+
+    #[test]
+    fn uncompressed_chunks_are_moved_without_copying() {
+        let tile_size = Vec2(512, 512);
+        let channels = smallvec::smallvec![ ChannelDescription::new("Y", SampleType::F32, true) ];
+
+        let tiles = TileDescription { tile_size, level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down };
+        let header = Header::new(Text::from("layer"), Vec2(1024, 1024), channels)
+            .with_encoding(Compression::Uncompressed, BlockDescription::Tiles(tiles), LineOrder::Increasing);
+
+        let headers: Headers = smallvec::smallvec![ header ];
+        let meta_data = MetaData { requirements: MetaData::validate(&headers, true).unwrap(), headers };
+
+        let data: ByteVec = (0 .. tile_size.area() * 4).map(|index| index as u8).collect();
+        let (pointer, capacity) = (data.as_ptr(), data.capacity());
+
+        let index = BlockIndex { layer: 0, pixel_position: Vec2(512, 0), pixel_size: tile_size, level: Vec2(0, 0) };
+        let chunk = UncompressedBlock { index, data }.compress_to_chunk_verified(&meta_data.headers).unwrap();
+
+        match &chunk.compressed_block {
+            CompressedBlock::Tile(tile) => {
+                assert_eq!(tile.compressed_pixels.as_ptr(), pointer, "compressing copied the pixels");
+                assert_eq!(tile.compressed_pixels.capacity(), capacity);
+            },
+
+            _ => panic!("expected a tile"),
+        }
+
+        let block = UncompressedBlock::decompress_chunk(chunk, &meta_data, true).unwrap();
+        assert_eq!(block.index, index);
+        assert_eq!(block.data.as_ptr(), pointer, "decompressing copied the pixels");
+        assert_eq!(block.data.capacity(), capacity);
+    }
+
+    /// A file that consists of zeroes, except for a few byte ranges.
+    /// Reports a huge size without allocating it.
+    struct SparseFile {
+        segments: Vec<(u64, Vec<u8>)>,
+        byte_size: u64,
+        position: u64,
+    }
+
+    impl Read for SparseFile {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            let position = self.position;
+            let remaining = self.byte_size.saturating_sub(position);
+
+            let (count, bytes) = match self.segments.iter().find(|(start, bytes)| position >= *start && position < start + bytes.len() as u64) {
+                Some((start, bytes)) => {
+                    let bytes = &bytes[(position - start) as usize ..];
+                    (bytes.len().min(buffer.len()), Some(bytes))
+                },
+
+                None => {
+                    let next_segment = self.segments.iter().map(|(start, _)| *start)
+                        .filter(|&start| start > position).min().unwrap_or(self.byte_size);
+
+                    ((next_segment - position).min(remaining).min(buffer.len() as u64) as usize, None)
+                },
+            };
+
+            match bytes {
+                Some(bytes) => buffer[.. count].copy_from_slice(&bytes[.. count]),
+                None => buffer[.. count].iter_mut().for_each(|byte| *byte = 0),
+            }
+
+            self.position += count as u64;
+            Ok(count)
+        }
+    }
+
+    impl Seek for SparseFile {
+        fn seek(&mut self, target: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.position = match target {
+                std::io::SeekFrom::Start(position) => position,
+                std::io::SeekFrom::End(delta) => (self.byte_size as i64 + delta) as u64,
+                std::io::SeekFrom::Current(delta) => (self.position as i64 + delta) as u64,
+            };
+
+            Ok(self.position)
+        }
+    }
+
+    #[test]
+    fn read_chunk_located_beyond_four_gibibytes() {
+        let size = Vec2(1 << 16, 1 << 16);
+        let channels = smallvec::smallvec![ ChannelDescription::new("Y", SampleType::F16, true) ];
+        let header = Header::new(Text::from("layer"), size, channels)
+            .with_encoding(Compression::Uncompressed, BlockDescription::ScanLines, LineOrder::Increasing);
+
+        let headers: Headers = smallvec::smallvec![ header ];
+        let mut meta_bytes = Vec::new();
+        MetaData::write_validating_to_buffered(&mut meta_bytes, &headers, true).unwrap();
+
+        let index = BlockIndex { layer: 0, pixel_position: Vec2(0, 0), pixel_size: Vec2(size.width(), 1), level: Vec2(0, 0) };
+        let data: ByteVec = (0 .. size.width() * 2).map(|index| index as u8).collect();
+        let block = UncompressedBlock { index, data };
+
+        let mut chunk_bytes = Vec::new();
+        block.clone().compress_to_chunk(&headers).unwrap().write(&mut chunk_bytes, 1).unwrap();
+
+        // every offset points to the same chunk, which is placed far beyond 4 GiB
+        let chunk_offset: u64 = 5 << 30;
+        let mut offset_table = Vec::new();
+        for _ in 0 .. headers[0].chunk_count { u64::write(chunk_offset, &mut offset_table).unwrap(); }
+
+        let file = SparseFile {
+            byte_size: chunk_offset + chunk_bytes.len() as u64,
+            segments: vec![
+                (0, meta_bytes.clone()),
+                (meta_bytes.len() as u64, offset_table),
+                (chunk_offset, chunk_bytes),
+            ],
+            position: 0,
+        };
+
+        let mut reader = read(file, true).unwrap();
+        let ranges = reader.chunk_byte_ranges().unwrap();
+        assert!(ranges.iter().all(|range| range.byte_offset == chunk_offset));
+
+        let meta_data = MetaData { requirements: MetaData::validate(&headers, true).unwrap(), headers };
+        let mut chunks: Vec<Chunk> = reader
+            .filter_chunks(true, |_, tile, _| tile.tile_index == Vec2(0, 0)).unwrap()
+            .collect::<Result<_>>().unwrap();
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(UncompressedBlock::decompress_chunk(chunks.remove(0), &meta_data, true).unwrap(), block);
+    }
+}
@@ -0,0 +1,81 @@
+//! Reuse the memory of decompressed blocks for subsequent blocks.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::compression::ByteVec;
+use crate::block::UncompressedBlock;
+
+
+/// A thread-safe collection of byte buffers that can be reused for decompressing blocks.
+/// Cloning the pool is cheap and returns a handle to the same buffers,
+/// so a single pool can be shared between decompressor threads.
+///
+/// Pass a pool to a block decompressor, copy the pixels out of each decompressed block,
+/// and then hand the block back to the pool using `recycle`.
+/// Subsequent blocks of the same byte size will then reuse that memory.
+#[derive(Debug, Clone)]
+pub struct BlockBufferPool {
+    buffers_by_byte_size: Arc<Mutex<HashMap<usize, Vec<ByteVec>>>>,
+    max_buffers_per_byte_size: usize,
+}
+
+impl Default for BlockBufferPool {
+    fn default() -> Self { Self::new() }
+}
+
+impl BlockBufferPool {
+
+    /// Create an empty pool, which keeps at most 64 buffers of each byte size.
+    pub fn new() -> Self {
+        Self::with_max_buffers_per_byte_size(64)
+    }
+
+    /// Create an empty pool, which keeps at most the specified number of buffers of each byte size.
+    /// Recycled buffers beyond that number are deallocated instead.
+    pub fn with_max_buffers_per_byte_size(max_buffers_per_byte_size: usize) -> Self {
+        Self {
+            buffers_by_byte_size: Arc::new(Mutex::new(HashMap::new())),
+            max_buffers_per_byte_size,
+        }
+    }
+
+    /// Return the pixel memory of a block to the pool, after its contents are not needed anymore.
+    pub fn recycle(&self, block: UncompressedBlock) {
+        self.recycle_bytes(block.data)
+    }
+
+    /// Return a byte buffer to the pool, such that a later block of the same byte size can reuse it.
+    pub fn recycle_bytes(&self, bytes: ByteVec) {
+        if bytes.is_empty() { return; }
+
+        let mut buffers = self.lock();
+        let buffers = buffers.entry(bytes.len()).or_insert_with(Vec::new);
+
+        if buffers.len() < self.max_buffers_per_byte_size {
+            buffers.push(bytes);
+        }
+    }
+
+    /// Take a buffer with exactly the specified length out of the pool,
+    /// or allocate a new zeroed buffer if no such buffer is available.
+    /// The contents of a reused buffer are unspecified and must be overwritten by the caller.
+    pub fn take(&self, byte_size: usize) -> ByteVec {
+        let reused = self.lock().get_mut(&byte_size).and_then(Vec::pop);
+        reused.unwrap_or_else(|| vec![0_u8; byte_size])
+    }
+
+    /// The number of buffers that are currently waiting to be reused.
+    pub fn available_buffer_count(&self) -> usize {
+        self.lock().values().map(Vec::len).sum()
+    }
+
+    /// Deallocate all buffers that are currently waiting to be reused.
+    pub fn clear(&self) {
+        self.lock().clear()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<usize, Vec<ByteVec>>> {
+        // the buffers are plain bytes, so a panic in another thread cannot leave them in an invalid state
+        self.buffers_by_byte_size.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,220 @@
+//! Read the blocks of an image from an asynchronous byte source.
+//! Only available with the `async` feature.
+//!
+//! The meta data and the compressed chunks are read asynchronously,
+//! while the decompression happens on a thread pool.
+//! The decompressed blocks are returned as a `Stream`.
+
+
+use std::io::{Cursor, SeekFrom};
+use std::ops::Range;
+use std::convert::TryFrom;
+
+use futures::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use futures::stream::Stream;
+use rayon_core::ThreadPool;
+use smallvec::alloc::sync::Arc;
+
+use crate::block::UncompressedBlock;
+use crate::block::chunk::Chunk;
+use crate::error::{Error, Result};
+use crate::io::{PeekRead, Tracking};
+use crate::meta::{MetaData, ValidationOptions};
+use crate::meta::header::Header;
+
+
+/// Decode the meta data from an asynchronous byte source,
+/// keeping the source ready for further reading.
+/// Continue decoding the remaining bytes by calling `blocks`.
+#[derive(Debug)]
+pub struct AsyncReader<R> {
+    meta_data: MetaData,
+    chunk_byte_ranges: Vec<Range<u64>>,
+    read: R,
+    validation: ValidationOptions,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncReader<R> {
+
+    /// Start the reading process.
+    /// Immediately decodes the meta data and the offset tables.
+    /// Access the meta data via `meta_data()`.
+    /// The reader is assumed to be buffered.
+    pub async fn read_from_buffered(mut read: R, validation: impl Into<ValidationOptions>) -> Result<Self> {
+        let validation = validation.into();
+        let mut bytes = Vec::new();
+        let mut reached_end = false;
+
+        // the size of the meta data is not known in advance,
+        // so read more bytes until the meta data and offset tables can be decoded.
+        // this means that the complete file might be loaded into memory if the meta data is invalid
+        let (meta_data, offset_tables) = loop {
+            let mut remaining_bytes = PeekRead::new(Tracking::new(Cursor::new(bytes.as_slice())));
+
+            let meta_and_tables = MetaData::read_validated_from_buffered_peekable(&mut remaining_bytes, validation)
+                .and_then(|meta| {
+                    let tables = MetaData::read_offset_tables(&mut remaining_bytes, &meta.headers)?;
+                    Ok((meta, tables))
+                });
+
+            // an error may be caused by missing bytes, so only fail if there are no more bytes to read
+            match meta_and_tables {
+                Err(_) if !reached_end => {
+                    let previous_len = bytes.len();
+                    bytes.resize((previous_len * 2).max(1024 * 16), 0);
+
+                    let count = read.read(&mut bytes[previous_len ..]).await?;
+                    bytes.truncate(previous_len + count);
+                    reached_end = count == 0;
+                },
+
+                meta_and_tables => break meta_and_tables?,
+            }
+        };
+
+        let file_byte_size = read.seek(SeekFrom::End(0)).await?;
+
+        // each chunk ends where the next chunk starts
+        let mut chunk_starts: Vec<u64> = offset_tables.into_iter().flatten().collect();
+        chunk_starts.sort_unstable();
+
+        if chunk_starts.windows(2).any(|pair| pair[0] == pair[1])
+            || chunk_starts.last().map_or(false, |&last| last >= file_byte_size)
+        {
+            return Err(Error::invalid("chunk offset table"));
+        }
+
+        let chunk_byte_ranges = chunk_starts.iter()
+            .zip(chunk_starts.iter().skip(1).chain(std::iter::once(&file_byte_size)))
+            .map(|(&start, &end)| start .. end)
+            .collect();
+
+        Ok(Self { meta_data, chunk_byte_ranges, read, validation })
+    }
+
+    /// The decoded exr meta data from the file.
+    pub fn meta_data(&self) -> &MetaData { &self.meta_data }
+
+    /// The decoded exr headers from the file.
+    pub fn headers(&self) -> &[Header] { &self.meta_data.headers }
+
+    /// Obtain the meta data ownership.
+    pub fn into_meta_data(self) -> MetaData { self.meta_data }
+
+    /// Decompress all blocks in the file, using multiple cpu cores.
+    /// The order of the blocks is not deterministic.
+    /// By default, this uses as many threads as there are CPUs,
+    /// and decompresses at most two more blocks than threads ahead of the consumer.
+    /// Will fallback to decompressing in the polling task where threads are not available.
+    pub fn blocks(self) -> impl Stream<Item = Result<UncompressedBlock>> {
+        let pool = rayon_core::ThreadPoolBuilder::new()
+            .thread_name(|index| format!("OpenEXR Async Block Decompressor Thread #{}", index))
+            .build().ok().map(Arc::new);
+
+        let max_in_flight_blocks = pool.as_ref()
+            .map_or(1, |pool| pool.current_num_threads().max(1) + 2);
+
+        self.blocks_with_thread_pool(pool, max_in_flight_blocks)
+    }
+
+    /// Decompress all blocks in the file, using the specified thread pool.
+    /// The order of the blocks is not deterministic.
+    /// No more than `max_in_flight_blocks` blocks are decompressed ahead of the consumer.
+    /// If no pool is specified, the blocks are decompressed in the polling task.
+    pub fn blocks_with_thread_pool(self, pool: Option<Arc<ThreadPool>>, max_in_flight_blocks: usize)
+        -> impl Stream<Item = Result<UncompressedBlock>>
+    {
+        let (sender, receiver) = flume::unbounded();
+
+        let decompressor = AsyncBlockDecompressor {
+            remaining_chunk_byte_ranges: self.chunk_byte_ranges.into_iter(),
+            meta_data: Arc::new(self.meta_data),
+            max_in_flight_blocks: max_in_flight_blocks.max(1),
+            currently_decompressing_count: 0,
+            validation: self.validation,
+            read: self.read,
+            sender, receiver, pool,
+        };
+
+        futures::stream::unfold(decompressor, |mut decompressor| async move {
+            let block = decompressor.decompress_next_block().await?;
+            Some((block, decompressor))
+        })
+    }
+}
+
+
+/// The state of the stream returned by `AsyncReader::blocks`.
+struct AsyncBlockDecompressor<R> {
+    read: R,
+    remaining_chunk_byte_ranges: std::vec::IntoIter<Range<u64>>,
+    meta_data: Arc<MetaData>,
+    validation: ValidationOptions,
+
+    sender: flume::Sender<Result<UncompressedBlock>>,
+    receiver: flume::Receiver<Result<UncompressedBlock>>,
+    currently_decompressing_count: usize,
+    max_in_flight_blocks: usize,
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncBlockDecompressor<R> {
+
+    /// Read the compressed bytes of a single chunk.
+    async fn read_chunk(&mut self, byte_range: Range<u64>) -> Result<Chunk> {
+        let mut bytes = vec![0_u8; usize::try_from(byte_range.end - byte_range.start)?];
+        self.read.seek(SeekFrom::Start(byte_range.start)).await?;
+        self.read.read_exact(&mut bytes).await?;
+        Chunk::read(&mut bytes.as_slice(), &self.meta_data)
+    }
+
+    /// Fill the pool with decompression jobs. Returns the first job that finishes.
+    async fn decompress_next_block(&mut self) -> Option<Result<UncompressedBlock>> {
+        let pool = match &self.pool {
+            Some(pool) => pool.clone(),
+            None => {
+                let byte_range = self.remaining_chunk_byte_ranges.next()?;
+                return Some(self.read_chunk(byte_range).await.and_then(|chunk|
+                    UncompressedBlock::decompress_chunk(chunk, &self.meta_data, self.validation)
+                ));
+            }
+        };
+
+        while self.currently_decompressing_count < self.max_in_flight_blocks {
+            let byte_range = match self.remaining_chunk_byte_ranges.next() {
+                Some(byte_range) => byte_range,
+                None => break, // there are no chunks left to decompress
+            };
+
+            let chunk = match self.read_chunk(byte_range).await {
+                Ok(chunk) => chunk,
+                Err(error) => return Some(Err(error)),
+            };
+
+            let sender = self.sender.clone();
+            let meta = self.meta_data.clone();
+            let validation = self.validation;
+
+            self.currently_decompressing_count += 1;
+
+            pool.spawn(move || {
+                let decompressed_or_err = UncompressedBlock::decompress_chunk(chunk, &meta, validation);
+
+                // by now, the stream could have been dropped,
+                // so we simply don't send the decompressed block and do nothing
+                let _ = sender.send(decompressed_or_err);
+            });
+        }
+
+        if self.currently_decompressing_count > 0 {
+            let next = self.receiver.recv_async().await
+                .expect("all decompressing senders hung up but more messages were expected");
+
+            self.currently_decompressing_count -= 1;
+            Some(next)
+        }
+        else {
+            None
+        }
+    }
+}
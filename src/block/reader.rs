@@ -2,26 +2,40 @@
 
 
 use std::convert::TryFrom;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
 use std::io::{Read, Seek};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
 use rayon_core::{ThreadPool, ThreadPoolBuildError};
 
 use smallvec::alloc::sync::Arc;
 
 use crate::block::{BlockIndex, UncompressedBlock};
-use crate::block::chunk::{Chunk, TileCoordinates};
-use crate::compression::Compression;
-use crate::error::{Error, Result, u64_to_usize, UnitResult};
+use crate::block::deep::{DeepUncompressedBlock, decompress_sample_counts};
+use crate::block::chunk::{Chunk, ChunkRef, ChunkSize, TileCoordinates, read_deep_sample_count_table};
+use crate::compression::{Bytes, Compression};
+use crate::error::{Error, Result, usize_to_u64, UnitResult};
 use crate::io::{PeekRead, Tracking};
-use crate::meta::{MetaData, OffsetTables};
+use crate::meta::{MetaData, OffsetTables, ValidationOptions};
 use crate::meta::header::Header;
+use crate::math::Vec2;
+use crate::block::samples::Sample;
+use crate::block::pool::BlockBufferPool;
+use crate::compression::CodecRegistry;
+use crate::meta::attribute::{SampleType, AttributeParserRegistry};
 
 /// Decode the meta data from a byte source, keeping the source ready for further reading.
 /// Continue decoding the remaining bytes by calling `filtered_chunks` or `all_chunks`.
 #[derive(Debug)]
 pub struct Reader<R> {
-    meta_data: MetaData,
+    meta_data: Arc<MetaData>,
     remaining_reader: PeekRead<Tracking<R>>, // TODO does R need to be Seek or is Tracking enough?
+
+    /// The offset tables, loaded lazily when reading a single block.
+    offset_tables: Option<OffsetTables>,
+
+    validation: ValidationOptions,
 }
 
 impl<R: Read + Seek> Reader<R> {
@@ -29,10 +43,24 @@ impl<R: Read + Seek> Reader<R> {
     /// Start the reading process.
     /// Immediately decodes the meta data into an internal field.
     /// Access it via`meta_data()`.
-    pub fn read_from_buffered(read: R, pedantic: bool) -> Result<Self> {
+    pub fn read_from_buffered(read: R, validation: impl Into<ValidationOptions>) -> Result<Self> {
+        Self::read_from_unseekable(read, validation)
+    }
+}
+
+impl<R: Read> Reader<R> {
+
+    /// Start the reading process from a byte source that cannot seek, for example a pipe or a socket.
+    /// Immediately decodes the meta data into an internal field.
+    /// Access it via`meta_data()`.
+    /// Without seeking, the chunks can only be read in the order they appear in the file,
+    /// using `all_chunks` or `filter_chunks_sequentially`.
+    /// The reader is assumed to be buffered.
+    pub fn read_from_unseekable(read: R, validation: impl Into<ValidationOptions>) -> Result<Self> {
         let mut remaining_reader = PeekRead::new(Tracking::new(read));
-        let meta_data = MetaData::read_validated_from_buffered_peekable(&mut remaining_reader, pedantic)?;
-        Ok(Self { meta_data, remaining_reader })
+        let validation = validation.into();
+        let meta_data = MetaData::read_validated_from_buffered_peekable(&mut remaining_reader, validation)?;
+        Ok(Self { meta_data: Arc::new(meta_data), remaining_reader, offset_tables: None, validation })
     }
 
     // must not be mutable, as reading the file later on relies on the meta data
@@ -43,14 +71,45 @@ impl<R: Read + Seek> Reader<R> {
     pub fn headers(&self) -> &[Header] { &self.meta_data.headers }
 
     /// Obtain the meta data ownership.
-    pub fn into_meta_data(self) -> MetaData { self.meta_data }
+    /// Only clones the meta data if it is still shared with another reader.
+    pub fn into_meta_data(self) -> MetaData {
+        Arc::try_unwrap(self.meta_data).unwrap_or_else(|shared| MetaData::clone(&shared))
+    }
+
+    /// Specify the codecs used for layers with `Compression::Custom`, as they are not stored in the file.
+    /// Without a codec for the compression id of a layer, its pixels cannot be decompressed.
+    /// __Custom compression methods are not part of the OpenEXR standard.__
+    pub fn with_custom_codecs(mut self, codecs: &CodecRegistry) -> Self {
+        for header in &mut Arc::make_mut(&mut self.meta_data).headers {
+            header.custom_codecs = codecs.clone();
+        }
+
+        self
+    }
+
+    /// Parse the custom attributes of all headers whose types are in the registry.
+    /// Attributes of other custom types keep their raw bytes.
+    /// If a value cannot be parsed, returns the error if attributes are strict, or otherwise keeps the raw bytes.
+    pub fn with_attribute_parsers(mut self, parsers: &AttributeParserRegistry) -> Result<Self> {
+        if !parsers.is_empty() {
+            let validation = self.validation;
+            Arc::make_mut(&mut self.meta_data).parse_custom_attributes(parsers, validation)?;
+        }
+
+        Ok(self)
+    }
+
+    /// The decoded exr meta data from the file, shared without cloning it.
+    pub fn shared_meta_data(&self) -> Arc<MetaData> { self.meta_data.clone() }
 
     /// Prepare to read all the chunks from the file.
     /// Does not decode the chunks now, but returns a decoder.
     /// Reading all chunks reduces seeking the file, but some chunks might be read without being used.
-    pub fn all_chunks(mut self, pedantic: bool) -> Result<AllChunksReader<R>> {
+    pub fn all_chunks(mut self, validation: impl Into<ValidationOptions>) -> Result<AllChunksReader<R>> {
+        let validation = validation.into();
+
         let total_chunk_count = {
-            if pedantic {
+            if validation.strict_offset_tables {
                 let offset_tables = MetaData::read_offset_tables(&mut self.remaining_reader, &self.meta_data.headers)?;
                 validate_offset_tables(self.meta_data.headers.as_slice(), &offset_tables, self.remaining_reader.byte_position())?;
                 offset_tables.iter().map(|table| table.len()).sum()
@@ -65,33 +124,48 @@ impl<R: Read + Seek> Reader<R> {
             meta_data: self.meta_data,
             remaining_chunks: 0 .. total_chunk_count,
             remaining_bytes: self.remaining_reader,
-            pedantic
+            validation
         })
     }
 
-    /// Prepare to read some the chunks from the file.
+    /// Prepare to read all the chunks from the file, without looking at the offset tables.
+    /// Use this to recover files with corrupt offset tables,
+    /// as long as all chunks are stored back to back after the offset tables.
+    /// The position of each block is taken from the chunk itself.
     /// Does not decode the chunks now, but returns a decoder.
-    /// Reading only some chunks may seeking the file, potentially skipping many bytes.
-    // TODO tile indices add no new information to block index??
-    pub fn filter_chunks(mut self, pedantic: bool, mut filter: impl FnMut(&MetaData, TileCoordinates, BlockIndex) -> bool) -> Result<FilteredChunksReader<R>> {
-        let offset_tables = MetaData::read_offset_tables(&mut self.remaining_reader, &self.meta_data.headers)?;
+    pub fn all_chunks_ignoring_offsets(mut self, validation: impl Into<ValidationOptions>) -> Result<AllChunksReader<R>> {
+        let total_chunk_count = MetaData::skip_offset_tables(&mut self.remaining_reader, &self.meta_data.headers)?;
 
-        // TODO regardless of pedantic, if invalid, read all chunks instead, and filter after reading each chunk?
-        if pedantic {
-            validate_offset_tables(
-                self.meta_data.headers.as_slice(), &offset_tables,
-                self.remaining_reader.byte_position()
-            )?;
-        }
+        Ok(AllChunksReader {
+            meta_data: self.meta_data,
+            remaining_chunks: 0 .. total_chunk_count,
+            remaining_bytes: self.remaining_reader,
+            validation: validation.into()
+        })
+    }
 
-        let mut filtered_offsets = Vec::with_capacity(
-            (self.meta_data.headers.len() * 32).min(2*2048)
-        );
+    /// Scan all chunks of the file without seeking, only reading the header of each chunk and skipping its compressed bytes.
+    /// Returns where the pixels of each chunk are placed in the image, and the number of compressed bytes,
+    /// in the order of the chunks in the file. Use `Reader::chunk_sizes_from_offset_tables` if the byte source can seek.
+    pub fn chunk_sizes(mut self) -> Result<Vec<(BlockIndex, usize)>> {
+        let chunk_count = MetaData::skip_offset_tables(&mut self.remaining_reader, &self.meta_data.headers)?;
+
+        (0 .. chunk_count).map(|_| {
+            let chunk = ChunkSize::read_skipping_compressed_bytes(&mut self.remaining_reader, &self.meta_data)?;
+            let block = block_index_of_tile(&self.meta_data.headers[chunk.layer_index], chunk.layer_index, chunk.tile)?;
+            Ok((block, chunk.compressed_byte_size))
+        }).collect()
+    }
 
-        // TODO detect whether the filter actually would skip chunks, and aviod sorting etc when not filtering is applied
+    /// Prepare to read some of the chunks from the file, without seeking.
+    /// Does not decode the chunks now, but returns a decoder.
+    /// All chunks are read from the byte source, but the chunks that are not desired are discarded.
+    /// Use `filter_chunks` instead if the byte source can seek.
+    pub fn filter_chunks_sequentially(self, validation: impl Into<ValidationOptions>, mut filter: impl FnMut(&MetaData, TileCoordinates, BlockIndex) -> bool) -> Result<SequentialFilteredChunksReader<R>> {
+        let mut desired_chunks = HashSet::new();
 
-        for (header_index, header) in self.meta_data.headers.iter().enumerate() { // offset tables are stored same order as headers
-            for (block_index, tile) in header.blocks_increasing_y_order().enumerate() { // in increasing_y order
+        for (header_index, header) in self.meta_data.headers.iter().enumerate() {
+            for tile in header.blocks_increasing_y_order() {
                 let data_indices = header.get_absolute_block_pixel_coordinates(tile.location)?;
 
                 let block = BlockIndex {
@@ -102,19 +176,31 @@ impl<R: Read + Seek> Reader<R> {
                 };
 
                 if filter(&self.meta_data, tile.location, block) {
-                    filtered_offsets.push(offset_tables[header_index][block_index]) // safe indexing from `enumerate()`
+                    desired_chunks.insert((header_index, tile.location));
                 }
-            };
+            }
         }
 
-        filtered_offsets.sort_unstable(); // enables reading continuously if possible (already sorted where line order increasing)
+        Ok(SequentialFilteredChunksReader {
+            expected_filtered_chunk_count: desired_chunks.len(),
+            remaining_desired_chunks: desired_chunks,
+            all_chunks: self.all_chunks(validation)?,
+        })
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
 
-        if pedantic {
-            // table is sorted. if any two neighbours are equal, we have duplicates. this is invalid.
-            if filtered_offsets.windows(2).any(|pair| pair[0] == pair[1]) {
-                return Err(Error::invalid("chunk offset table"))
-            }
-        }
+    /// Prepare to read some the chunks from the file.
+    /// Does not decode the chunks now, but returns a decoder.
+    /// Reading only some chunks may seeking the file, potentially skipping many bytes.
+    /// If the offset tables are corrupt and `strict_offset_tables` is false,
+    /// the offsets are reconstructed by reading all chunks once.
+    // TODO tile indices add no new information to block index??
+    pub fn filter_chunks(mut self, validation: impl Into<ValidationOptions>, filter: impl FnMut(&MetaData, TileCoordinates, BlockIndex) -> bool) -> Result<FilteredChunksReader<R>> {
+        let validation = validation.into();
+        let offset_tables = self.read_valid_offset_tables(validation)?;
+        let filtered_offsets = filter_offsets(&self.meta_data, &offset_tables, validation, filter)?;
 
         Ok(FilteredChunksReader {
             meta_data: self.meta_data,
@@ -123,24 +209,756 @@ impl<R: Read + Seek> Reader<R> {
             remaining_bytes: self.remaining_reader
         })
     }
+
+    /// Prepare to read only the chunks of the layers that pass the filter.
+    /// Does not decode the chunks now, but returns a decoder.
+    /// Returns an error if no layer passes the filter.
+    pub fn filter_layers(self, validation: impl Into<ValidationOptions>, layer_filter: impl Fn(&Header) -> bool) -> Result<FilteredChunksReader<R>> {
+        let desired_layers: Vec<bool> = self.meta_data.headers.iter()
+            .map(|header| layer_filter(header)).collect();
+
+        if !desired_layers.contains(&true) {
+            return Err(Error::invalid("no layer matched the layer filter"));
+        }
+
+        self.filter_chunks(validation, |_, _, block| desired_layers[block.layer])
+    }
+
+    /// Prepare to read only the chunks of the layers with the specified names.
+    /// A layer name is found either in the layer name attribute,
+    /// or as a prefix of the channel names, as in `diffuse.R`.
+    /// Does not decode the chunks now, but returns a decoder.
+    /// Returns an error if any of the names does not exist in the file.
+    pub fn filter_chunks_by_layer_names(self, validation: impl Into<ValidationOptions>, layer_names: &[&str]) -> Result<FilteredChunksReader<R>> {
+        for &layer_name in layer_names {
+            if !self.meta_data.headers.iter().any(|header| header.has_layer_name(layer_name)) {
+                return Err(Error::invalid(format!("no layer named `{}`", layer_name)));
+            }
+        }
+
+        self.filter_layers(validation, |header| {
+            layer_names.iter().any(|&layer_name| header.has_layer_name(layer_name))
+        })
+    }
+
+    /// Read and decompress a single block, seeking directly to its chunk.
+    /// For scan line images, the tile index is `(0, block_y_index)`, and the level is always `(0, 0)`.
+    /// The offset tables are read when this is first called, and then kept in memory.
+    /// Returns an error if the block does not exist in the file.
+    /// If the offset tables are corrupt and the reader does not have strict offset tables,
+    /// the offsets are reconstructed by reading all chunks once.
+    pub fn read_block(&mut self, layer: usize, tile: TileCoordinates) -> Result<UncompressedBlock> {
+        let chunk = self.read_chunk(layer, tile)?;
+        UncompressedBlock::decompress_chunk(chunk, &self.meta_data, self.validation)
+    }
+
+    /// Read and decompress a single block of deep data, seeking directly to its chunk.
+    /// For deep scan line images, the tile index is `(0, block_y_index)`, and the level is always `(0, 0)`.
+    /// The offset tables are read when this is first called, and then kept in memory.
+    /// Returns an error if the block does not exist in the file, or if the layer does not contain deep data.
+    pub fn read_deep_block(&mut self, layer: usize, tile: TileCoordinates) -> Result<DeepUncompressedBlock> {
+        let chunk = self.read_chunk(layer, tile)?;
+        DeepUncompressedBlock::decompress_chunk(chunk, &self.meta_data, self.validation)
+    }
+
+    /// Read and decompress only the sample count tables of the deep blocks that pass the filter,
+    /// seeking over the samples of each block instead of reading them.
+    /// Returns the number of samples in each pixel of each block, row after row.
+    /// This is much cheaper than reading the whole deep block, and can be used to estimate memory usage.
+    /// The blocks of flat layers do not contain sample counts, and are always skipped.
+    /// The offset tables are read when this is first called, and then kept in memory.
+    pub fn deep_sample_counts(
+        &mut self, mut filter: impl FnMut(&MetaData, TileCoordinates, BlockIndex) -> bool
+    ) -> Result<HashMap<BlockIndex, Vec<u32>>>
+    {
+        let offset_tables_start = self.remaining_reader.byte_position();
+        self.load_offset_tables()?;
+
+        let offset_tables = self.offset_tables.as_ref().expect("offset table bug");
+        let filtered_offsets = filter_offsets(&self.meta_data, offset_tables, self.validation, |meta, tile, block|
+            meta.headers[block.layer].deep && filter(meta, tile, block)
+        )?;
+
+        let mut sample_counts = HashMap::with_capacity(filtered_offsets.len());
+
+        for offset in filtered_offsets {
+            self.remaining_reader.skip_to(offset)?;
+
+            let (layer_index, tile, table) = read_deep_sample_count_table(&mut self.remaining_reader, &self.meta_data)?;
+            let header = &self.meta_data.headers[layer_index];
+
+            let (block, block_sample_counts) = decompress_sample_counts(header, layer_index, tile, &table, self.validation, None)?;
+            sample_counts.insert(block, block_sample_counts);
+        }
+
+        // return to the offset tables, such that all the chunks can still be read afterwards
+        self.remaining_reader.skip_to(offset_tables_start)?;
+        Ok(sample_counts)
+    }
+
+    /// Read a single chunk without decompressing it, seeking directly to the chunk.
+    /// For scan line images, the tile index is `(0, block_y_index)`, and the level is always `(0, 0)`.
+    /// The offset tables are read when this is first called, and then kept in memory.
+    /// Returns an error if the chunk does not exist in the file.
+    pub fn read_chunk(&mut self, layer: usize, tile: TileCoordinates) -> Result<Chunk> {
+        let header = self.meta_data.headers.get(layer)
+            .ok_or(Error::invalid("layer index"))?;
+
+        // offset tables are stored in increasing y order
+        let chunk_index = header.blocks_increasing_y_order()
+            .position(|block| block.location == tile)
+            .ok_or(Error::invalid("tile coordinates"))?;
+
+        self.read_chunk_at_index(layer, chunk_index, tile)
+    }
+
+    /// Read a single chunk without decompressing it, using the index of the chunk in increasing y order.
+    /// Also checks that the chunk has the expected tile coordinates.
+    pub(crate) fn read_chunk_at_index(&mut self, layer: usize, index_in_header_increasing_y: usize, tile: TileCoordinates) -> Result<Chunk> {
+        let offset_tables_start = self.remaining_reader.byte_position();
+        self.load_offset_tables()?;
+
+        let offset_tables = self.offset_tables.as_ref().expect("offset table bug");
+        let &chunk_offset = offset_tables.get(layer).and_then(|table| table.get(index_in_header_increasing_y))
+            .ok_or(Error::invalid("chunk index"))?;
+
+        if chunk_offset == 0 { return Err(Error::invalid("missing chunk")); }
+
+        self.remaining_reader.skip_to(chunk_offset)?;
+        let chunk = Chunk::read(&mut self.remaining_reader, &self.meta_data);
+
+        // return to the offset tables, such that all the chunks can still be read afterwards
+        self.remaining_reader.skip_to(offset_tables_start)?;
+        let chunk = chunk?;
+
+        let header = &self.meta_data.headers[layer];
+        if chunk.layer_index != layer || header.get_block_data_indices(&chunk.compressed_block)? != tile {
+            return Err(Error::invalid("chunk offset table"));
+        }
+
+        Ok(chunk)
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
+
+    /// Prepare to read arbitrary blocks from the file, in any order.
+    /// Reads the offset tables, and keeps them in memory.
+    /// Recently decompressed blocks are cached, using at most
+    /// the specified number of bytes for the cached pixel data.
+    pub fn cached_blocks(mut self, validation: impl Into<ValidationOptions>, max_cache_byte_size: usize) -> Result<CachedBlockReader<R>> {
+        let validation = validation.into();
+        let offset_tables = self.read_valid_offset_tables(validation)?;
+
+        Ok(CachedBlockReader {
+            chunk_offsets: chunk_offsets_by_tile(&self.meta_data, &offset_tables),
+            meta_data: self.meta_data,
+            remaining_bytes: self.remaining_reader,
+            cache: BlockCache::new(max_cache_byte_size),
+            validation,
+        })
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
+
+    /// The byte offset of each chunk in the file, for each layer, in increasing y order.
+    /// The offset tables are read when this is first called, and then kept in memory.
+    /// Does not change the position of the reader, so the chunks can still be read afterwards.
+    /// If the offset tables are corrupt and the reader does not have strict offset tables,
+    /// the offsets are reconstructed by reading all chunks once.
+    pub fn offset_tables(&mut self) -> Result<OffsetTables> {
+        self.load_offset_tables()?;
+        Ok(self.offset_tables.clone().expect("offset table bug"))
+    }
+
+    /// The byte range of each chunk in the file, and where its pixels are placed in the image,
+    /// sorted by the position of the chunk in the file.
+    /// Each chunk is assumed to end where the next chunk starts, and the last chunk ends at the end of the file.
+    /// Does not change the position of the reader, so the chunks can still be read afterwards.
+    pub fn chunk_byte_ranges(&mut self) -> Result<Vec<ChunkByteRange>> {
+        let file_byte_size = self.remaining_reader.total_byte_size()?;
+        self.load_offset_tables()?;
+        let offset_tables = self.offset_tables.as_ref().expect("offset table bug");
+
+        let mut chunks = Vec::with_capacity(offset_tables.iter().map(Vec::len).sum());
+
+        for (layer_index, (header, offset_table)) in self.meta_data.headers.iter().zip(offset_tables).enumerate() {
+            // offset tables are stored in increasing y order
+            for (tile, &byte_offset) in header.blocks_increasing_y_order().zip(offset_table) {
+                chunks.push(ChunkByteRange {
+                    layer: layer_index,
+                    byte_offset, byte_size: 0,
+                    tile: tile.location,
+                    block: block_index_of_tile(header, layer_index, tile.location)?,
+                });
+            }
+        }
+
+        chunks.sort_by_key(|chunk| chunk.byte_offset);
+
+        // each chunk ends where the next chunk starts
+        let chunk_ends: Vec<u64> = chunks.iter().skip(1).map(|chunk| chunk.byte_offset)
+            .chain(std::iter::once(file_byte_size)).collect();
+
+        for (chunk, end) in chunks.iter_mut().zip(chunk_ends) {
+            chunk.byte_size = end.checked_sub(chunk.byte_offset)
+                .ok_or(Error::invalid("chunk offset table"))?;
+        }
+
+        Ok(chunks)
+    }
+
+    /// Jump to each chunk using the offset tables, only reading the header of each chunk.
+    /// Returns where the pixels of each chunk are placed in the image, and the number of compressed bytes,
+    /// in the order of the chunks in the file.
+    /// Does not change the position of the reader, so the chunks can still be read afterwards.
+    pub fn chunk_sizes_from_offset_tables(&mut self) -> Result<Vec<(BlockIndex, usize)>> {
+        self.load_offset_tables()?;
+
+        let offset_tables_start = self.remaining_reader.byte_position();
+        let mut chunk_offsets: Vec<u64> = self.offset_tables.iter().flatten().flatten().copied().collect();
+        chunk_offsets.sort_unstable();
+
+        let chunk_sizes = chunk_offsets.into_iter().map(|offset| {
+            self.remaining_reader.skip_to(offset)?;
+
+            let chunk = ChunkSize::read_skipping_compressed_bytes(&mut self.remaining_reader, &self.meta_data)?;
+            let block = block_index_of_tile(&self.meta_data.headers[chunk.layer_index], chunk.layer_index, chunk.tile)?;
+            Ok((block, chunk.compressed_byte_size))
+        }).collect();
+
+        self.remaining_reader.skip_to(offset_tables_start)?;
+        chunk_sizes
+    }
+
+    /// Read the offset tables if they have not been read yet,
+    /// and return to the start of the tables afterwards.
+    fn load_offset_tables(&mut self) -> UnitResult {
+        if self.offset_tables.is_none() {
+            let offset_tables_start = self.remaining_reader.byte_position();
+            let offset_tables = self.read_valid_offset_tables(self.validation)?;
+            self.remaining_reader.skip_to(offset_tables_start)?;
+            self.offset_tables = Some(offset_tables);
+        }
+
+        Ok(())
+    }
+
+    /// Read the offset tables, starting at the current position.
+    /// If the tables are corrupt, returns an error if the offset tables are strict,
+    /// and otherwise reconstructs the tables by reading all chunks.
+    /// If not strict, zero offsets of a partially written file are kept,
+    /// and the chunks they refer to are skipped later.
+    fn read_valid_offset_tables(&mut self, validation: ValidationOptions) -> Result<OffsetTables> {
+        let offset_tables = MetaData::read_offset_tables(&mut self.remaining_reader, &self.meta_data.headers)?;
+        let chunks_start_byte = self.remaining_reader.byte_position();
+
+        match validate_offset_tables(self.meta_data.headers.as_slice(), &offset_tables, chunks_start_byte) {
+            Ok(()) => Ok(offset_tables),
+            Err(error) if validation.strict_offset_tables => Err(error),
+
+            Err(_) if is_partially_written(self.meta_data.headers.as_slice(), &offset_tables, chunks_start_byte)
+                => Ok(offset_tables),
+
+            Err(_) => self.reconstruct_offset_tables(),
+        }
+    }
+
+    /// Read all chunks back to back, starting at the current position,
+    /// and remember where each chunk starts, ignoring the offset tables in the file.
+    fn reconstruct_offset_tables(&mut self) -> Result<OffsetTables> {
+        let mut chunk_indices = HashMap::new();
+        let mut offset_tables = OffsetTables::with_capacity(self.meta_data.headers.len());
+
+        for (layer_index, header) in self.meta_data.headers.iter().enumerate() {
+            for (chunk_index, tile) in header.blocks_increasing_y_order().enumerate() {
+                chunk_indices.insert((layer_index, tile.location), chunk_index);
+            }
+
+            offset_tables.push(vec![0; header.chunk_count]);
+        }
+
+        for _ in 0 .. chunk_indices.len() {
+            let chunk_start = self.remaining_reader.byte_position();
+            let chunk = Chunk::read(&mut self.remaining_reader, &self.meta_data)?;
+            let tile = self.meta_data.headers[chunk.layer_index].get_block_data_indices(&chunk.compressed_block)?;
+
+            let &chunk_index = chunk_indices.get(&(chunk.layer_index, tile))
+                .ok_or(Error::invalid("chunk tile coordinates"))?;
+
+            offset_tables[chunk.layer_index][chunk_index] = chunk_start;
+        }
+
+        if offset_tables.iter().flatten().any(|&offset| offset == 0) {
+            return Err(Error::invalid("missing chunk"));
+        }
+
+        Ok(offset_tables)
+    }
+}
+
+/// The location of a single chunk in the file, and where its pixels are placed in the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkByteRange {
+
+    /// The index of the layer that contains the chunk.
+    pub layer: usize,
+
+    /// The position of the first byte of the chunk in the file.
+    pub byte_offset: u64,
+
+    /// The number of bytes of the chunk, including the chunk header.
+    pub byte_size: u64,
+
+    /// The location of the chunk in the layer.
+    pub tile: TileCoordinates,
+
+    /// Where the pixels of the chunk are placed in the image.
+    pub block: BlockIndex,
+}
+
+impl ChunkByteRange {
+
+    /// Sort the chunks by layer, then by resolution level, and then by their pixel position, row by row.
+    /// Useful to process the chunks in image order after prefetching them in file order.
+    pub fn sort_by_block_index(chunk_ranges: &mut [ChunkByteRange]) {
+        chunk_ranges.sort_by_key(|chunk| (
+            chunk.layer,
+            chunk.block.level.y(), chunk.block.level.x(),
+            chunk.block.pixel_position.y(), chunk.block.pixel_position.x(),
+        ));
+    }
+}
+
+/// Where the pixels of the tile are placed in the image.
+fn block_index_of_tile(header: &Header, layer_index: usize, tile: TileCoordinates) -> Result<BlockIndex> {
+    let data_indices = header.get_absolute_block_pixel_coordinates(tile)?;
+
+    Ok(BlockIndex {
+        layer: layer_index,
+        level: tile.level_index,
+        pixel_position: data_indices.position.to_usize("data indices start")?,
+        pixel_size: data_indices.size,
+    })
+}
+
+/// Look up the offset of each chunk by its layer index and tile coordinates.
+fn chunk_offsets_by_tile(meta_data: &MetaData, offset_tables: &OffsetTables) -> HashMap<(usize, TileCoordinates), u64> {
+    meta_data.headers.iter().zip(offset_tables).enumerate()
+        .flat_map(|(layer_index, (header, offset_table))| {
+            // offset tables are stored in increasing y order
+            header.blocks_increasing_y_order().zip(offset_table)
+                .map(move |(tile, &offset)| ((layer_index, tile.location), offset))
+        })
+        .collect()
+}
+
+/// Select the offsets of the chunks that pass the filter, sorted by their position in the file.
+fn filter_offsets(
+    meta_data: &MetaData, offset_tables: &OffsetTables, validation: ValidationOptions,
+    mut filter: impl FnMut(&MetaData, TileCoordinates, BlockIndex) -> bool
+) -> Result<Vec<u64>>
+{
+    let mut filtered_offsets = Vec::with_capacity(
+        (meta_data.headers.len() * 32).min(2*2048)
+    );
+
+    // TODO detect whether the filter actually would skip chunks, and aviod sorting etc when not filtering is applied
+
+    for (header_index, header) in meta_data.headers.iter().enumerate() { // offset tables are stored same order as headers
+        for (block_index, tile) in header.blocks_increasing_y_order().enumerate() { // in increasing_y order
+            let data_indices = header.get_absolute_block_pixel_coordinates(tile.location)?;
+
+            let block = BlockIndex {
+                layer: header_index,
+                level: tile.location.level_index,
+                pixel_position: data_indices.position.to_usize("data indices start")?,
+                pixel_size: data_indices.size,
+            };
+
+            let offset = offset_tables[header_index][block_index]; // safe indexing from `enumerate()`
+
+            // a zero offset marks a chunk that has not been written to an incomplete file
+            let is_unwritten = offset == 0 && !validation.strict_offset_tables;
+
+            if !is_unwritten && filter(meta_data, tile.location, block) {
+                filtered_offsets.push(offset)
+            }
+        };
+    }
+
+    filtered_offsets.sort_unstable(); // enables reading continuously if possible (already sorted where line order increasing)
+
+    if validation.strict_offset_tables {
+        // table is sorted. if any two neighbours are equal, we have duplicates. this is invalid.
+        if filtered_offsets.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(Error::invalid("chunk offset table"))
+        }
+    }
+
+    Ok(filtered_offsets)
+}
+
+
+fn validate_offset_tables(headers: &[Header], offset_tables: &OffsetTables, chunks_start_byte: u64) -> UnitResult {
+    let is_valid = are_offsets_in_bounds(headers, offset_tables.iter().flatten().copied(), chunks_start_byte);
+
+    if is_valid { Ok(()) }
+    else { Err(Error::invalid("offset table")) }
 }
 
+/// Whether the offset tables were written incompletely, for example by `ChunkWriter::checkpoint`.
+/// This is the case if some offsets are zero, and all other offsets are valid.
+fn is_partially_written(headers: &[Header], offset_tables: &OffsetTables, chunks_start_byte: u64) -> bool {
+    let mut written_offsets = offset_tables.iter().flatten().copied().filter(|&offset| offset != 0).peekable();
+    written_offsets.peek().is_some() && are_offsets_in_bounds(headers, written_offsets, chunks_start_byte)
+}
 
-fn validate_offset_tables(headers: &[Header], offset_tables: &OffsetTables, chunks_start_byte: usize) -> UnitResult {
-    let max_pixel_bytes: usize = headers.iter() // when compressed, chunks are smaller, but never larger than max
-        .map(|header| header.max_pixel_file_bytes())
-        .sum();
+fn are_offsets_in_bounds(headers: &[Header], mut offsets: impl Iterator<Item=u64>, chunks_start_byte: u64) -> bool {
+    // computed in u64, as the pixels of all layers may exceed the address space of this machine
+    let max_pixel_bytes: u64 = headers.iter() // when compressed, chunks are smaller, but never larger than max
+        .map(|header| usize_to_u64(header.max_pixel_file_bytes()))
+        .fold(0, u64::saturating_add);
 
     // check that each offset is within the bounds
-    let end_byte = chunks_start_byte + max_pixel_bytes;
-    let is_invalid = offset_tables.iter().flatten().map(|&u64| u64_to_usize(u64))
-        .any(|chunk_start| chunk_start < chunks_start_byte || chunk_start > end_byte);
+    let end_byte = chunks_start_byte.saturating_add(max_pixel_bytes);
+    offsets.all(|chunk_start| chunk_start >= chunks_start_byte && chunk_start <= end_byte)
+}
+
 
-    if is_invalid { Err(Error::invalid("offset table")) }
-    else { Ok(()) }
+
+
+/// Decode the meta data from a byte slice, for example a memory-mapped file.
+/// The chunks of this reader borrow their compressed bytes from the slice instead of copying them.
+/// Continue decoding the remaining bytes by calling `filter_chunks` or `all_chunks`.
+#[derive(Debug)]
+pub struct SliceReader<'b> {
+    meta_data: MetaData,
+    offset_tables: OffsetTables,
+    bytes: Bytes<'b>,
 }
 
+impl<'b> SliceReader<'b> {
 
+    /// Start the reading process.
+    /// Immediately decodes the meta data and the offset tables.
+    /// Access the meta data via`meta_data()`.
+    pub fn read_from_slice(bytes: Bytes<'b>, validation: impl Into<ValidationOptions>) -> Result<Self> {
+        let mut remaining_reader = PeekRead::new(Tracking::new(bytes));
+        let validation = validation.into();
+        let meta_data = MetaData::read_validated_from_buffered_peekable(&mut remaining_reader, validation)?;
+        let offset_tables = MetaData::read_offset_tables(&mut remaining_reader, &meta_data.headers)?;
+
+        if validation.strict_offset_tables {
+            validate_offset_tables(meta_data.headers.as_slice(), &offset_tables, remaining_reader.byte_position())?;
+        }
+
+        Ok(Self { meta_data, offset_tables, bytes })
+    }
+
+    /// The decoded exr meta data from the file.
+    pub fn meta_data(&self) -> &MetaData { &self.meta_data }
+
+    /// The decoded exr headers from the file.
+    pub fn headers(&self) -> &[Header] { &self.meta_data.headers }
+
+    /// Obtain the meta data ownership.
+    pub fn into_meta_data(self) -> MetaData { self.meta_data }
+
+    /// Prepare to read all the chunks from the slice.
+    /// Does not decode the chunks now, but returns a decoder.
+    pub fn all_chunks(self, validation: impl Into<ValidationOptions>) -> Result<SliceChunksReader<'b>> {
+        self.filter_chunks(validation, |_, _, _| true)
+    }
+
+    /// Prepare to read some of the chunks from the slice.
+    /// Does not decode the chunks now, but returns a decoder.
+    pub fn filter_chunks(self, validation: impl Into<ValidationOptions>, filter: impl FnMut(&MetaData, TileCoordinates, BlockIndex) -> bool) -> Result<SliceChunksReader<'b>> {
+        let filtered_offsets = filter_offsets(&self.meta_data, &self.offset_tables, validation.into(), filter)?;
+
+        Ok(SliceChunksReader {
+            meta_data: self.meta_data,
+            remaining_filtered_chunk_indices: filtered_offsets.into_iter(),
+            bytes: self.bytes,
+        })
+    }
+}
+
+/// Decode the desired chunks from a byte slice, borrowing the compressed bytes.
+/// The decoded chunks can be decompressed by calling `decompress_sequential` or `decompress_parallel`.
+/// Also contains the image meta data.
+#[derive(Debug)]
+pub struct SliceChunksReader<'b> {
+    meta_data: MetaData,
+    remaining_filtered_chunk_indices: std::vec::IntoIter<u64>,
+    bytes: Bytes<'b>,
+}
+
+impl<'b> SliceChunksReader<'b> {
+
+    /// The decoded exr meta data from the file.
+    pub fn meta_data(&self) -> &MetaData { &self.meta_data }
+
+    /// The decoded exr headers from the file.
+    pub fn headers(&self) -> &[Header] { &self.meta_data.headers }
+
+    /// Decompress all chunks in this thread and call the supplied closure for each block.
+    /// The compressed bytes are only copied where the pixels are not compressed.
+    pub fn decompress_sequential(
+        mut self, validation: impl Into<ValidationOptions>,
+        mut insert_block: impl FnMut(&MetaData, UncompressedBlock) -> UnitResult
+    ) -> UnitResult
+    {
+        let validation = validation.into();
+
+        while let Some(chunk) = self.next() {
+            let block = UncompressedBlock::decompress_chunk_ref(chunk?, &self.meta_data, validation)?;
+            insert_block(&self.meta_data, block)?;
+        }
+
+        Ok(())
+    }
+
+    /// Parse and decompress all chunks in parallel, and call the supplied closure for each block in this thread.
+    /// As the whole file is in memory, each chunk is parsed independently, without sharing a reader between threads.
+    /// The order of the blocks is not deterministic.
+    /// Uses the global thread pool. Use `decompress_parallel_with_pool` to specify a thread pool.
+    pub fn decompress_parallel(
+        self, validation: impl Into<ValidationOptions>,
+        insert_block: impl FnMut(&MetaData, UncompressedBlock) -> UnitResult
+    ) -> UnitResult
+    {
+        self.decompress_in_parallel_scope(validation.into(), None, insert_block)
+    }
+
+    /// Parse and decompress all chunks in parallel on the specified thread pool,
+    /// and call the supplied closure for each block in this thread.
+    /// The order of the blocks is not deterministic.
+    pub fn decompress_parallel_with_pool(
+        self, validation: impl Into<ValidationOptions>, pool: &ThreadPool,
+        insert_block: impl FnMut(&MetaData, UncompressedBlock) -> UnitResult
+    ) -> UnitResult
+    {
+        self.decompress_in_parallel_scope(validation.into(), Some(pool), insert_block)
+    }
+
+    fn decompress_in_parallel_scope(
+        self, validation: ValidationOptions, pool: Option<&ThreadPool>,
+        insert_block: impl FnMut(&MetaData, UncompressedBlock) -> UnitResult
+    ) -> UnitResult
+    {
+        let Self { meta_data, remaining_filtered_chunk_indices: chunk_offsets, bytes } = self;
+        let thread_count = pool.map_or_else(rayon_core::current_num_threads, ThreadPool::current_num_threads);
+
+        // set when decompressing fails, such that the jobs that have not started are skipped
+        let cancelled = AtomicBool::new(false);
+
+        let (meta_data, cancelled) = (&meta_data, &cancelled);
+
+        match pool {
+            Some(pool) => pool.in_place_scope(|scope| Self::decompress_in_scope(
+                scope, meta_data, bytes, chunk_offsets, thread_count, cancelled, validation, insert_block
+            )),
+
+            None => rayon_core::in_place_scope(|scope| Self::decompress_in_scope(
+                scope, meta_data, bytes, chunk_offsets, thread_count, cancelled, validation, insert_block
+            )),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn decompress_in_scope<'s>(
+        scope: &rayon_core::Scope<'s>, meta_data: &'s MetaData, bytes: Bytes<'s>,
+        chunk_offsets: std::vec::IntoIter<u64>, thread_count: usize, cancelled: &'s AtomicBool, validation: ValidationOptions,
+        mut insert_block: impl FnMut(&MetaData, UncompressedBlock) -> UnitResult
+    ) -> UnitResult
+    {
+        let chunk_count = chunk_offsets.len();
+
+        // at most a few decompressed blocks wait for the closure at any time.
+        // the receiver is dropped when this function returns early,
+        // such that jobs waiting to send their block are released before the scope waits for all jobs to finish
+        let (sender, receiver) = flume::bounded(thread_count.max(1) * 2);
+
+        for chunk_offset in chunk_offsets {
+            let sender = sender.clone();
+
+            scope.spawn(move |_| {
+                if cancelled.load(Ordering::Relaxed) { return; }
+
+                let block = usize::try_from(chunk_offset).ok()
+                    .and_then(|chunk_start| bytes.get(chunk_start ..))
+                    .ok_or(Error::invalid("chunk offset table"))
+                    .and_then(|mut chunk_bytes| ChunkRef::read_from_slice(&mut chunk_bytes, meta_data))
+                    .and_then(|chunk| UncompressedBlock::decompress_chunk_ref(chunk, meta_data, validation));
+
+                // the receiver is gone if decompressing has been aborted
+                let _ = sender.send(block);
+            });
+        }
+
+        drop(sender);
+
+        for _ in 0 .. chunk_count {
+            let block = receiver.recv().expect("all decompressing senders hung up but more blocks were expected");
+
+            if let Err(error) = block.and_then(|block| insert_block(meta_data, block)) {
+                cancelled.store(true, Ordering::Relaxed);
+                return Err(error);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'b> ExactSizeIterator for SliceChunksReader<'b> {}
+impl<'b> Iterator for SliceChunksReader<'b> {
+    type Item = Result<ChunkRef<'b>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.remaining_filtered_chunk_indices.next().map(|chunk_location| {
+            let mut chunk_bytes = usize::try_from(chunk_location).ok()
+                .and_then(|chunk_start| self.bytes.get(chunk_start ..))
+                .ok_or(Error::invalid("chunk offset table"))?;
+
+            ChunkRef::read_from_slice(&mut chunk_bytes, &self.meta_data)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining_filtered_chunk_indices.len(), Some(self.remaining_filtered_chunk_indices.len()))
+    }
+}
+
+
+/// Read arbitrary blocks from the file, in any order, seeking to each chunk.
+/// Keeps the offset tables in memory, and caches the most recently used blocks.
+/// Useful for viewers that repeatedly need the same tiles.
+/// Obtain an instance with `Reader::cached_blocks`.
+#[derive(Debug)]
+pub struct CachedBlockReader<R> {
+    meta_data: Arc<MetaData>,
+    chunk_offsets: HashMap<(usize, TileCoordinates), u64>,
+    remaining_bytes: PeekRead<Tracking<R>>,
+    cache: BlockCache,
+    validation: ValidationOptions,
+}
+
+impl<R: Read + Seek> CachedBlockReader<R> {
+
+    /// The decoded exr meta data from the file.
+    pub fn meta_data(&self) -> &MetaData { &self.meta_data }
+
+    /// The decoded exr headers from the file.
+    pub fn headers(&self) -> &[Header] { &self.meta_data.headers }
+
+    /// Return the block at the specified tile in the specified layer and resolution level.
+    /// For scan line images, the tile index is `(0, block_y_index)`, and the level is always `(0, 0)`.
+    /// Returns a cached block if the block has been read recently.
+    /// Otherwise, seeks to the chunk, reads it, and decompresses it.
+    /// Returns an error if the block does not exist in the file.
+    pub fn block_at(&mut self, layer: usize, level: Vec2<usize>, tile: Vec2<usize>) -> Result<Arc<UncompressedBlock>> {
+        let tile = TileCoordinates { tile_index: tile, level_index: level };
+
+        let header = self.meta_data.headers.get(layer)
+            .ok_or(Error::invalid("layer index"))?;
+
+        let &chunk_offset = self.chunk_offsets.get(&(layer, tile))
+            .ok_or(Error::invalid("tile coordinates"))?;
+
+        if chunk_offset == 0 { return Err(Error::invalid("missing chunk")); }
+
+        let pixel_bounds = header.get_absolute_block_pixel_coordinates(tile)?;
+        let block_index = BlockIndex {
+            layer, level,
+            pixel_position: pixel_bounds.position.to_usize("data indices start")?,
+            pixel_size: pixel_bounds.size,
+        };
+
+        if let Some(block) = self.cache.get(block_index) {
+            return Ok(block);
+        }
+
+        self.remaining_bytes.skip_to(chunk_offset)?;
+        let chunk = Chunk::read(&mut self.remaining_bytes, &self.meta_data)?;
+
+        if chunk.layer_index != layer || header.get_block_data_indices(&chunk.compressed_block)? != tile {
+            return Err(Error::invalid("chunk offset table"));
+        }
+
+        let block = Arc::new(UncompressedBlock::decompress_chunk(chunk, &self.meta_data, self.validation)?);
+        self.cache.insert(block.clone());
+        Ok(block)
+    }
+
+    /// The number of bytes of pixel data that are currently cached.
+    pub fn cached_byte_size(&self) -> usize { self.cache.byte_size }
+
+    /// Remove all blocks from the cache.
+    pub fn clear_cache(&mut self) { self.cache.clear() }
+}
+
+/// Remembers the most recently used blocks, up to a maximum number of bytes.
+#[derive(Debug)]
+struct BlockCache {
+    blocks: HashMap<BlockIndex, (Arc<UncompressedBlock>, usize)>,
+    blocks_by_last_use: BTreeMap<usize, BlockIndex>,
+    next_use: usize,
+    byte_size: usize,
+    max_byte_size: usize,
+}
+
+impl BlockCache {
+    fn new(max_byte_size: usize) -> Self {
+        Self {
+            blocks: HashMap::new(), blocks_by_last_use: BTreeMap::new(),
+            next_use: 0, byte_size: 0, max_byte_size
+        }
+    }
+
+    fn get(&mut self, index: BlockIndex) -> Option<Arc<UncompressedBlock>> {
+        let next_use = self.next_use;
+        let (block, last_use) = self.blocks.get_mut(&index)?;
+
+        self.blocks_by_last_use.remove(last_use);
+        self.blocks_by_last_use.insert(next_use, index);
+        *last_use = next_use;
+        self.next_use += 1;
+
+        Some(block.clone())
+    }
+
+    fn insert(&mut self, block: Arc<UncompressedBlock>) {
+        let block_byte_size = block.data.len();
+        if block_byte_size > self.max_byte_size { return; }
+
+        // evict the least recently used blocks until the new block fits
+        while self.byte_size + block_byte_size > self.max_byte_size {
+            let (&last_use, &least_recently_used) = self.blocks_by_last_use.iter().next()
+                .expect("block cache byte size bug");
+
+            self.blocks_by_last_use.remove(&last_use);
+
+            let (evicted, _) = self.blocks.remove(&least_recently_used)
+                .expect("block cache index bug");
+
+            self.byte_size -= evicted.data.len();
+        }
+
+        self.blocks_by_last_use.insert(self.next_use, block.index);
+        self.blocks.insert(block.index, (block, self.next_use));
+        self.byte_size += block_byte_size;
+        self.next_use += 1;
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear();
+        self.blocks_by_last_use.clear();
+        self.byte_size = 0;
+    }
+}
 
 
 /// Decode the desired chunks and skip the unimportant chunks in the file.
@@ -150,7 +968,7 @@ fn validate_offset_tables(headers: &[Header], offset_tables: &OffsetTables, chun
 /// Also contains the image meta data.
 #[derive(Debug)]
 pub struct FilteredChunksReader<R> {
-    meta_data: MetaData,
+    meta_data: Arc<MetaData>,
     expected_filtered_chunk_count: usize,
     remaining_filtered_chunk_indices: std::vec::IntoIter<u64>,
     remaining_bytes: PeekRead<Tracking<R>>,
@@ -163,10 +981,22 @@ pub struct FilteredChunksReader<R> {
 /// Also contains the image meta data.
 #[derive(Debug)]
 pub struct AllChunksReader<R> {
-    meta_data: MetaData,
+    meta_data: Arc<MetaData>,
     remaining_chunks: std::ops::Range<usize>,
     remaining_bytes: PeekRead<Tracking<R>>,
-    pedantic: bool,
+    validation: ValidationOptions,
+}
+
+/// Decode all chunks in the file without seeking, discarding the chunks that are not desired.
+/// The decoded chunks can be decompressed by calling
+/// `decompress_parallel`, `decompress_sequential`, or `sequential_decompressor` or `parallel_decompressor`.
+/// Call `on_progress` to have a callback with each block.
+/// Also contains the image meta data.
+#[derive(Debug)]
+pub struct SequentialFilteredChunksReader<R> {
+    all_chunks: AllChunksReader<R>,
+    expected_filtered_chunk_count: usize,
+    remaining_desired_chunks: HashSet<(usize, TileCoordinates)>,
 }
 
 /// Decode chunks in the file without seeking.
@@ -181,6 +1011,40 @@ pub struct OnProgressChunksReader<R, F> {
     callback: F,
 }
 
+/// Decode chunks in the file without seeking.
+/// Calls the supplied closure with detailed progress information for each chunk.
+/// The decoded chunks can be decompressed by calling
+/// `decompress_parallel`, `decompress_sequential`, or `sequential_decompressor`.
+/// Also contains the image meta data.
+#[derive(Debug)]
+pub struct OnBlockProgressChunksReader<R, F> {
+    chunks_reader: R,
+    decoded_chunks: usize,
+    compressed_bytes: u64,
+    callback: F,
+}
+
+/// Detailed information about the reading progress,
+/// passed to the closure of `on_block_progress` after each chunk has been read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockProgress {
+
+    /// The number of chunks that have been read so far, including this chunk.
+    pub decoded_chunk_count: usize,
+
+    /// The number of chunks that the reader will return in total.
+    pub expected_chunk_count: usize,
+
+    /// The location of the chunk that has just been read.
+    pub tile: TileCoordinates,
+
+    /// Where the pixels of the chunk that has just been read are placed in the image.
+    pub block: BlockIndex,
+
+    /// The number of compressed bytes that have been read so far, including this chunk.
+    pub compressed_byte_count: u64,
+}
+
 /// Decode chunks in the file.
 /// The decoded chunks can be decompressed by calling
 /// `decompress_parallel`, `decompress_sequential`, or `sequential_decompressor`.
@@ -191,6 +1055,11 @@ pub trait ChunksReader: Sized + Iterator<Item=Result<Chunk>> + ExactSizeIterator
     /// The decoded exr meta data from the file.
     fn meta_data(&self) -> &MetaData;
 
+    /// The decoded exr meta data from the file, shared for example with decompression threads.
+    /// The readers of this crate share their meta data without cloning it,
+    /// but the default implementation clones the meta data.
+    fn shared_meta_data(&self) -> Arc<MetaData> { Arc::new(self.meta_data().clone()) }
+
     /// The decoded exr headers from the file.
     fn headers(&self) -> &[Header] { &self.meta_data().headers }
 
@@ -211,27 +1080,74 @@ pub trait ChunksReader: Sized + Iterator<Item=Result<Chunk>> + ExactSizeIterator
         OnProgressChunksReader { chunks_reader: self, callback: on_progress, decoded_chunks: 0 }
     }
 
+    /// Create a new reader that calls the provided callback for each chunk that is read from the file,
+    /// with detailed information about the chunk and the overall progress.
+    fn on_block_progress<F>(self, on_progress: F) -> OnBlockProgressChunksReader<Self, F> where F: FnMut(BlockProgress) {
+        OnBlockProgressChunksReader { chunks_reader: self, callback: on_progress, decoded_chunks: 0, compressed_bytes: 0 }
+    }
+
     /// Decompress all blocks in the file, using multiple cpu cores, and call the supplied closure for each block.
     /// The order of the blocks is not deterministic.
     /// You can also use `parallel_decompressor` to obtain an iterator instead.
     /// Will fallback to sequential processing where threads are not available, or where it would not speed up the process.
     // FIXME try async + futures instead of rayon! Maybe even allows for external async decoding? (-> impl Stream<UncompressedBlock>)
     fn decompress_parallel(
-        self, pedantic: bool,
-        mut insert_block: impl FnMut(&MetaData, UncompressedBlock) -> UnitResult
+        self, validation: impl Into<ValidationOptions>,
+        insert_block: impl FnMut(&MetaData, UncompressedBlock) -> UnitResult
     ) -> UnitResult
     {
-        let mut decompressor = match self.parallel_decompressor(pedantic) {
-            Err(old_self) => return old_self.decompress_sequential(pedantic, insert_block),
-            Ok(decompressor) => decompressor,
-        };
+        let validation = validation.into();
 
-        while let Some(block) = decompressor.next() {
-            insert_block(decompressor.meta_data(), block?)?;
+        match self.parallel_decompressor(validation) {
+            Err(old_self) => old_self.decompress_sequential(validation, insert_block),
+            Ok(decompressor) => decompressor.decompress_all_blocks(insert_block),
         }
+    }
 
-        debug_assert_eq!(decompressor.len(), 0, "compressed blocks left after decompressing all blocks");
-        Ok(())
+    /// Decompress blocks in the file, using multiple cpu cores, and call the supplied closure for each block,
+    /// until the closure returns `ControlFlow::Break`.
+    /// After cancelling, no more chunks are read, and the remaining decompression jobs are skipped
+    /// without waiting for them. Returns `ControlFlow::Break` if the closure cancelled the process.
+    /// The order of the blocks is not deterministic.
+    /// Will fallback to sequential processing where threads are not available, or where it would not speed up the process.
+    fn decompress_parallel_cancellable(
+        self, validation: impl Into<ValidationOptions>,
+        mut insert_block: impl FnMut(&MetaData, UncompressedBlock) -> Result<ControlFlow<()>>
+    ) -> Result<ControlFlow<()>>
+    {
+        let validation = validation.into();
+
+        match self.parallel_decompressor(validation) {
+            Ok(decompressor) => decompressor.decompress_blocks_cancellable(insert_block),
+            Err(old_self) => {
+                let mut decompressor = old_self.sequential_decompressor(validation);
+                while let Some(block) = decompressor.next() {
+                    if insert_block(decompressor.meta_data(), block?)?.is_break() {
+                        return Ok(ControlFlow::Break(()));
+                    }
+                }
+
+                Ok(ControlFlow::Continue(()))
+            },
+        }
+    }
+
+    /// Decompress all blocks in the file, using the threads of the specified pool,
+    /// and call the supplied closure for each block.
+    /// The pool is only borrowed, so a single pool can be shared by many concurrent reads.
+    /// The order of the blocks is not deterministic.
+    /// Will fallback to sequential processing where it would not speed up the process.
+    fn decompress_parallel_with_pool(
+        self, validation: impl Into<ValidationOptions>, pool: &ThreadPool,
+        insert_block: impl FnMut(&MetaData, UncompressedBlock) -> UnitResult
+    ) -> UnitResult
+    {
+        let validation = validation.into();
+
+        match self.parallel_decompressor_with_pool(validation, pool) {
+            Err(old_self) => old_self.decompress_sequential(validation, insert_block),
+            Ok(decompressor) => decompressor.decompress_all_blocks(insert_block),
+        }
     }
 
     /// Return an iterator that decompresses the chunks with multiple threads.
@@ -239,18 +1155,26 @@ pub trait ChunksReader: Sized + Iterator<Item=Result<Chunk>> + ExactSizeIterator
     /// Use `ParallelBlockDecompressor::new` if you want to use your own thread pool.
     /// By default, this uses as many threads as there are CPUs.
     /// Returns the `self` if there is no need for parallel decompression.
-    fn parallel_decompressor(self, pedantic: bool) -> std::result::Result<ParallelBlockDecompressor<Self>, Self> {
-        ParallelBlockDecompressor::new(self, pedantic)
+    fn parallel_decompressor(self, validation: impl Into<ValidationOptions>) -> std::result::Result<ParallelBlockDecompressor<'static, Self>, Self> {
+        ParallelBlockDecompressor::new(self, validation)
+    }
+
+    /// Return an iterator that decompresses the chunks using the threads of the specified pool.
+    /// The pool is only borrowed, so no additional threads are created.
+    /// The order of the blocks is not deterministic.
+    /// Returns the `self` if there is no need for parallel decompression.
+    fn parallel_decompressor_with_pool(self, validation: impl Into<ValidationOptions>, pool: &ThreadPool) -> std::result::Result<ParallelBlockDecompressor<'_, Self>, Self> {
+        ParallelBlockDecompressor::new_with_shared_thread_pool(self, validation, pool)
     }
 
     /// Return an iterator that decompresses the chunks in this thread.
     /// You can alternatively use `sequential_decompressor` if you prefer an external iterator.
     fn decompress_sequential(
-        self, pedantic: bool,
+        self, validation: impl Into<ValidationOptions>,
         mut insert_block: impl FnMut(&MetaData, UncompressedBlock) -> UnitResult
     ) -> UnitResult
     {
-        let mut decompressor = self.sequential_decompressor(pedantic);
+        let mut decompressor = self.sequential_decompressor(validation);
         while let Some(block) = decompressor.next() {
             insert_block(decompressor.meta_data(), block?)?;
         }
@@ -260,13 +1184,18 @@ pub trait ChunksReader: Sized + Iterator<Item=Result<Chunk>> + ExactSizeIterator
     }
 
     /// Prepare reading the chunks sequentially, only a single thread, but with less memory overhead.
-    fn sequential_decompressor(self, pedantic: bool) -> SequentialBlockDecompressor<Self> {
-        SequentialBlockDecompressor { remaining_chunks_reader: self, pedantic }
+    fn sequential_decompressor(self, validation: impl Into<ValidationOptions>) -> SequentialBlockDecompressor<Self> {
+        SequentialBlockDecompressor {
+            remaining_chunks_reader: self, validation: validation.into(),
+            broken_block_fill: None, broken_blocks: Vec::new(),
+            buffer_pool: None,
+        }
     }
 }
 
 impl<R, F> ChunksReader for OnProgressChunksReader<R, F> where R: ChunksReader, F: FnMut(f64) {
     fn meta_data(&self) -> &MetaData { self.chunks_reader.meta_data() }
+    fn shared_meta_data(&self) -> Arc<MetaData> { self.chunks_reader.shared_meta_data() }
     fn expected_chunk_count(&self) -> usize { self.chunks_reader.expected_chunk_count() }
 }
 
@@ -302,13 +1231,71 @@ impl<R, F> Iterator for OnProgressChunksReader<R, F> where R: ChunksReader, F: F
     }
 }
 
-impl<R: Read + Seek> ChunksReader for AllChunksReader<R> {
+impl<R, F> ChunksReader for OnBlockProgressChunksReader<R, F> where R: ChunksReader, F: FnMut(BlockProgress) {
+    fn meta_data(&self) -> &MetaData { self.chunks_reader.meta_data() }
+    fn shared_meta_data(&self) -> Arc<MetaData> { self.chunks_reader.shared_meta_data() }
+    fn expected_chunk_count(&self) -> usize { self.chunks_reader.expected_chunk_count() }
+}
+
+impl<R, F> ExactSizeIterator for OnBlockProgressChunksReader<R, F> where R: ChunksReader, F: FnMut(BlockProgress) {}
+impl<R, F> Iterator for OnBlockProgressChunksReader<R, F> where R: ChunksReader, F: FnMut(BlockProgress) {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = match self.chunks_reader.next()? {
+            Ok(chunk) => chunk,
+            Err(error) => return Some(Err(error)),
+        };
+
+        self.decoded_chunks += 1;
+        self.compressed_bytes += usize_to_u64(chunk.compressed_byte_size());
+
+        let progress = {
+            let header = &self.meta_data().headers[chunk.layer_index]; // layer index is validated when reading the chunk
+
+            header.get_block_data_indices(&chunk.compressed_block).and_then(|tile| {
+                let data_indices = header.get_absolute_block_pixel_coordinates(tile)?;
+
+                Ok(BlockProgress {
+                    decoded_chunk_count: self.decoded_chunks,
+                    expected_chunk_count: self.expected_chunk_count(),
+                    compressed_byte_count: self.compressed_bytes,
+                    tile,
+
+                    block: BlockIndex {
+                        layer: chunk.layer_index,
+                        level: tile.level_index,
+                        pixel_position: data_indices.position.to_usize("data indices start")?,
+                        pixel_size: data_indices.size,
+                    },
+                })
+            })
+        };
+
+        match progress {
+            Ok(progress) => {
+                let callback = &mut self.callback;
+                callback(progress);
+                Some(Ok(chunk))
+            },
+
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks_reader.size_hint()
+    }
+}
+
+impl<R: Read> ChunksReader for AllChunksReader<R> {
     fn meta_data(&self) -> &MetaData { &self.meta_data }
+    fn shared_meta_data(&self) -> Arc<MetaData> { self.meta_data.clone() }
     fn expected_chunk_count(&self) -> usize { self.remaining_chunks.end }
 }
 
-impl<R: Read + Seek> ExactSizeIterator for AllChunksReader<R> {}
-impl<R: Read + Seek> Iterator for AllChunksReader<R> {
+impl<R: Read> ExactSizeIterator for AllChunksReader<R> {}
+impl<R: Read> Iterator for AllChunksReader<R> {
     type Item = Result<Chunk>;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -317,7 +1304,7 @@ impl<R: Read + Seek> Iterator for AllChunksReader<R> {
             .map(|_| Chunk::read(&mut self.remaining_bytes, &self.meta_data));
 
         // if no chunks are left, but some bytes remain, return error
-        if self.pedantic && next_chunk.is_none() && self.remaining_bytes.peek_u8().is_ok() {
+        if self.validation.require_end_of_file && next_chunk.is_none() && self.remaining_bytes.peek_u8().is_ok() {
             return Some(Err(Error::invalid("end of file expected")));
         }
 
@@ -331,6 +1318,7 @@ impl<R: Read + Seek> Iterator for AllChunksReader<R> {
 
 impl<R: Read + Seek> ChunksReader for FilteredChunksReader<R> {
     fn meta_data(&self) -> &MetaData { &self.meta_data }
+    fn shared_meta_data(&self) -> Arc<MetaData> { self.meta_data.clone() }
     fn expected_chunk_count(&self) -> usize { self.expected_filtered_chunk_count }
 }
 
@@ -341,10 +1329,8 @@ impl<R: Read + Seek> Iterator for FilteredChunksReader<R> {
     fn next(&mut self) -> Option<Self::Item> {
         // read as many chunks as we have desired chunk offsets
         self.remaining_filtered_chunk_indices.next().map(|next_chunk_location|{
-            self.remaining_bytes.skip_to( // no-op for seek at current position, uses skip_bytes for small amounts
-                                          usize::try_from(next_chunk_location)
-                                              .expect("too large chunk position for this machine")
-            )?;
+            // no-op for seek at current position, uses skip_bytes for small amounts
+            self.remaining_bytes.skip_to(next_chunk_location)?;
 
             let meta_data = &self.meta_data;
             Chunk::read(&mut self.remaining_bytes, meta_data)
@@ -358,12 +1344,56 @@ impl<R: Read + Seek> Iterator for FilteredChunksReader<R> {
     }
 }
 
+impl<R: Read> ChunksReader for SequentialFilteredChunksReader<R> {
+    fn meta_data(&self) -> &MetaData { self.all_chunks.meta_data() }
+    fn shared_meta_data(&self) -> Arc<MetaData> { self.all_chunks.shared_meta_data() }
+    fn expected_chunk_count(&self) -> usize { self.expected_filtered_chunk_count }
+}
+
+impl<R: Read> ExactSizeIterator for SequentialFilteredChunksReader<R> {}
+impl<R: Read> Iterator for SequentialFilteredChunksReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining_desired_chunks.is_empty() {
+            return None;
+        }
+
+        // read chunks until one is desired, discarding all others
+        while let Some(chunk) = self.all_chunks.next() {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(error) => return Some(Err(error)),
+            };
+
+            let header = &self.all_chunks.meta_data.headers[chunk.layer_index]; // layer index is validated when reading the chunk
+            let tile = match header.get_block_data_indices(&chunk.compressed_block) {
+                Ok(tile) => tile,
+                Err(error) => return Some(Err(error)),
+            };
+
+            if self.remaining_desired_chunks.remove(&(chunk.layer_index, tile)) {
+                return Some(Ok(chunk));
+            }
+        }
+
+        Some(Err(Error::invalid("missing chunk")))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining_desired_chunks.len(), Some(self.remaining_desired_chunks.len()))
+    }
+}
+
 /// Read all chunks from the file, decompressing each chunk immediately.
 /// Implements iterator.
 #[derive(Debug)]
 pub struct SequentialBlockDecompressor<R: ChunksReader> {
     remaining_chunks_reader: R,
-    pedantic: bool,
+    validation: ValidationOptions,
+    broken_block_fill: Option<f32>,
+    broken_blocks: Vec<BlockIndex>,
+    buffer_pool: Option<BlockBufferPool>,
 }
 
 impl<R: ChunksReader> SequentialBlockDecompressor<R> {
@@ -371,41 +1401,147 @@ impl<R: ChunksReader> SequentialBlockDecompressor<R> {
     /// The extracted meta data from the image file.
     pub fn meta_data(&self) -> &MetaData { self.remaining_chunks_reader.meta_data() }
 
+    /// Specify that chunks whose pixels cannot be decompressed should not abort the process.
+    /// Instead, a block filled with the specified value is returned,
+    /// and the block is remembered in `broken_blocks()`.
+    /// Chunks that cannot be read at all are still an error.
+    pub fn skip_broken_blocks(self, fill_value: f32) -> Self {
+        Self { broken_block_fill: Some(fill_value), ..self }
+    }
+
+    /// Decompress the pixels into buffers from the pool where possible, instead of allocating new memory for each block.
+    /// Call `BlockBufferPool::recycle` with each block after its pixels have been used.
+    pub fn buffer_pool(self, buffer_pool: BlockBufferPool) -> Self {
+        Self { buffer_pool: Some(buffer_pool), ..self }
+    }
+
+    /// The blocks that could not be decompressed so far, and were filled instead.
+    /// Always empty, unless `skip_broken_blocks` was called.
+    pub fn broken_blocks(&self) -> &[BlockIndex] { &self.broken_blocks }
+
     /// Read and then decompress a single block of pixels from the byte source.
     pub fn decompress_next_block(&mut self) -> Option<Result<UncompressedBlock>> {
         self.remaining_chunks_reader.read_next_chunk().map(|compressed_chunk|{
-            UncompressedBlock::decompress_chunk(compressed_chunk?, &self.remaining_chunks_reader.meta_data(), self.pedantic)
+            let (block, broken_block) = decompress_chunk_or_fill(
+                compressed_chunk?, self.remaining_chunks_reader.meta_data(),
+                self.validation, self.broken_block_fill, self.buffer_pool.as_ref()
+            )?;
+
+            self.broken_blocks.extend(broken_block);
+            Ok(block)
         })
     }
 }
 
+/// Decompress the chunk. If a fill value is specified and the pixels cannot be decompressed,
+/// returns a block filled with that value instead, along with the index of the broken block.
+fn decompress_chunk_or_fill(
+    chunk: Chunk, meta_data: &MetaData, validation: ValidationOptions,
+    broken_block_fill: Option<f32>, buffer_pool: Option<&BlockBufferPool>
+) -> Result<(UncompressedBlock, Option<BlockIndex>)>
+{
+    let decompress = |chunk| match buffer_pool {
+        Some(buffer_pool) => UncompressedBlock::decompress_chunk_with_buffer_pool(chunk, meta_data, validation, buffer_pool),
+        None => UncompressedBlock::decompress_chunk(chunk, meta_data, validation),
+    };
+
+    let fill_value = match broken_block_fill {
+        Some(fill_value) => fill_value,
+        None => return Ok((decompress(chunk)?, None)),
+    };
+
+    let header = &meta_data.headers[chunk.layer_index]; // layer index is validated when reading the chunk
+    let tile = header.get_block_data_indices(&chunk.compressed_block)?;
+    let data_indices = header.get_absolute_block_pixel_coordinates(tile)?;
+
+    let block_index = BlockIndex {
+        layer: chunk.layer_index,
+        level: tile.level_index,
+        pixel_position: data_indices.position.to_usize("data indices start")?,
+        pixel_size: data_indices.size,
+    };
+
+    match decompress(chunk) {
+        Ok(block) => Ok((block, None)),
+
+        Err(Error::Invalid(_)) | Err(Error::Io(_)) => {
+            let fill_value = Sample::F32(fill_value);
+
+            let filled_block = UncompressedBlock::from_lines(&header.channels, block_index, |line| {
+                match header.channels.list[line.location.channel].sample_type {
+                    SampleType::F16 => line.write_samples(|_| fill_value.to_f16()),
+                    SampleType::F32 => line.write_samples(|_| fill_value.to_f32()),
+                    SampleType::U32 => line.write_samples(|_| fill_value.to_u32()),
+                }.expect("writing line bytes failed");
+            });
+
+            Ok((filled_block, Some(block_index)))
+        },
+
+        Err(error) => Err(error),
+    }
+}
+
 /// Decompress the chunks in a file in parallel.
 /// The first call to `next` will fill the thread pool with jobs,
 /// starting to decompress the next few blocks.
-/// These jobs will finish, even if you stop reading more blocks.
+/// If this decompressor is dropped before all blocks have been returned,
+/// the jobs that have not started yet will be skipped.
+/// The lifetime refers to a borrowed thread pool, and is `'static` if the pool is owned.
 /// Implements iterator.
 #[derive(Debug)]
-pub struct ParallelBlockDecompressor<R: ChunksReader> {
+pub struct ParallelBlockDecompressor<'p, R: ChunksReader> {
     remaining_chunks: R,
-    sender: flume::Sender<Result<UncompressedBlock>>,
-    receiver: flume::Receiver<Result<UncompressedBlock>>,
+    sender: flume::Sender<(usize, DecompressedBlockResult)>,
+    receiver: flume::Receiver<(usize, DecompressedBlockResult)>,
     currently_decompressing_count: usize,
-    max_threads: usize,
+    max_in_flight: usize,
+
+    /// If present, blocks that finished too early wait here, keyed by their chunk sequence number,
+    /// until all previous blocks have been returned.
+    reorder_buffer: Option<BTreeMap<usize, DecompressedBlockResult>>,
+    next_spawned_sequence_number: usize,
+    next_returned_sequence_number: usize,
 
     shared_meta_data_ref: Arc<MetaData>,
-    pedantic: bool,
+    validation: ValidationOptions,
+    broken_block_fill: Option<f32>,
+    broken_blocks: Vec<BlockIndex>,
+    buffer_pool: Option<BlockBufferPool>,
+
+    /// Set when this decompressor is dropped, such that queued jobs do not decompress anymore.
+    cancelled: Arc<AtomicBool>,
+
+    pool: DecompressionPool<'p>,
+}
+
+/// A decompressed block, or a filled block and its index if the block is broken.
+type DecompressedBlockResult = Result<(UncompressedBlock, Option<BlockIndex>)>;
+
+/// The thread pool is either created by the decompressor or supplied by the user.
+#[derive(Debug)]
+enum DecompressionPool<'p> {
+    Owned(ThreadPool),
+    Borrowed(&'p ThreadPool),
+}
 
-    pool: ThreadPool,
+impl DecompressionPool<'_> {
+    fn get(&self) -> &ThreadPool {
+        match self {
+            DecompressionPool::Owned(pool) => pool,
+            DecompressionPool::Borrowed(pool) => pool,
+        }
+    }
 }
 
-impl<R: ChunksReader> ParallelBlockDecompressor<R> {
+impl<R: ChunksReader> ParallelBlockDecompressor<'static, R> {
 
     /// Create a new decompressor. Does not immediately spawn any tasks.
     /// Decompression starts after the first call to `next`.
     /// Returns the chunks if parallel decompression should not be used.
     /// Use `new_with_thread_pool` to customize the threadpool.
-    pub fn new(chunks: R, pedantic: bool) -> std::result::Result<Self, R> {
-        Self::new_with_thread_pool(chunks, pedantic, ||{
+    pub fn new(chunks: R, validation: impl Into<ValidationOptions>) -> std::result::Result<Self, R> {
+        Self::new_with_thread_pool(chunks, validation, ||{
             rayon_core::ThreadPoolBuilder::new()
                 .thread_name(|index| format!("OpenEXR Block Decompressor Thread #{}", index))
                 .build()
@@ -415,47 +1551,147 @@ impl<R: ChunksReader> ParallelBlockDecompressor<R> {
     /// Create a new decompressor. Does not immediately spawn any tasks.
     /// Decompression starts after the first call to `next`.
     /// Returns the chunks if parallel decompression should not be used.
-    pub fn new_with_thread_pool<CreatePool>(chunks: R, pedantic: bool, try_create_thread_pool: CreatePool)
+    pub fn new_with_thread_pool<CreatePool>(chunks: R, validation: impl Into<ValidationOptions>, try_create_thread_pool: CreatePool)
         -> std::result::Result<Self, R>
         where CreatePool: FnOnce() -> std::result::Result<ThreadPool, ThreadPoolBuildError>
     {
         // if no compression is used in the file, don't use a threadpool
-        if chunks.meta_data().headers.iter()
-            .all(|head|head.compression == Compression::Uncompressed)
-        {
+        if !Self::requires_decompression(&chunks) {
             return Err(chunks);
         }
 
         // in case thread pool creation fails (for example on WASM currently),
         // we revert to sequential decompression
-        let pool = match try_create_thread_pool() {
-            Ok(pool) => pool,
+        match try_create_thread_pool() {
+            Ok(pool) => Ok(Self::with_pool(chunks, validation.into(), DecompressionPool::Owned(pool))),
 
             // TODO print warning?
-            Err(_) => return Err(chunks),
-        };
+            Err(_) => Err(chunks),
+        }
+    }
+}
+
+impl<'p, R: ChunksReader> ParallelBlockDecompressor<'p, R> {
 
-        let max_threads = pool.current_num_threads().max(1).min(chunks.len()) + 2; // ca one block for each thread at all times
+    /// Create a new decompressor that spawns its jobs on an existing thread pool.
+    /// The pool is only borrowed, so a single pool can serve many concurrent decompressors.
+    /// Does not immediately spawn any tasks.
+    /// Decompression starts after the first call to `next`.
+    /// Returns the chunks if parallel decompression should not be used.
+    pub fn new_with_shared_thread_pool(chunks: R, validation: impl Into<ValidationOptions>, pool: &'p ThreadPool) -> std::result::Result<Self, R> {
+        if !Self::requires_decompression(&chunks) {
+            return Err(chunks);
+        }
+
+        Ok(Self::with_pool(chunks, validation.into(), DecompressionPool::Borrowed(pool)))
+    }
+
+    fn requires_decompression(chunks: &R) -> bool {
+        chunks.meta_data().headers.iter()
+            .any(|head| head.compression != Compression::Uncompressed)
+    }
+
+    fn with_pool(chunks: R, validation: ValidationOptions, pool: DecompressionPool<'p>) -> Self {
+        let max_in_flight = pool.get().current_num_threads().max(1).min(chunks.len()) + 2; // ca one block for each thread at all times
 
         let (send, recv) = flume::unbounded(); // TODO bounded channel simplifies logic?
 
-        Ok(Self {
-            shared_meta_data_ref: Arc::new(chunks.meta_data().clone()),
+        Self {
+            shared_meta_data_ref: chunks.shared_meta_data(),
             currently_decompressing_count: 0,
             remaining_chunks: chunks,
             sender: send,
             receiver: recv,
-            pedantic,
-            max_threads,
+            validation,
+            max_in_flight,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            broken_block_fill: None,
+            broken_blocks: Vec::new(),
+            buffer_pool: None,
+            reorder_buffer: None,
+            next_spawned_sequence_number: 0,
+            next_returned_sequence_number: 0,
 
             pool,
-        })
+        }
     }
 
-    /// Fill the pool with decompression jobs. Returns the first job that finishes.
+    /// Specify how many blocks may be decompressed at the same time.
+    /// More blocks use more cores, but also hold more memory.
+    /// By default, this is two more than the number of threads in the pool.
+    /// The value is clamped to at least one, and at most the number of remaining chunks.
+    pub fn max_in_flight(mut self, max_in_flight_blocks: usize) -> Self {
+        self.max_in_flight = max_in_flight_blocks.min(self.remaining_chunks.len()).max(1);
+        self
+    }
+
+    /// Specify that the blocks should be returned in the same order as the chunks are read,
+    /// instead of returning each block as soon as it is decompressed.
+    /// Blocks that finish early are held back, but never more than the maximum number of blocks in flight.
+    /// This may be slower, as a single slow block delays all following blocks.
+    pub fn preserve_order(mut self) -> Self {
+        self.reorder_buffer = Some(BTreeMap::new());
+        self
+    }
+
+    /// Specify that chunks whose pixels cannot be decompressed should not abort the process.
+    /// Instead, a block filled with the specified value is returned,
+    /// and the block is remembered in `broken_blocks()`.
+    /// Chunks that cannot be read at all are still an error.
+    pub fn skip_broken_blocks(mut self, fill_value: f32) -> Self {
+        self.broken_block_fill = Some(fill_value);
+        self
+    }
+
+    /// Decompress the pixels into buffers from the pool where possible, instead of allocating new memory for each block.
+    /// Call `BlockBufferPool::recycle` with each block after its pixels have been used.
+    /// The pool is shared by all decompression threads.
+    pub fn buffer_pool(mut self, buffer_pool: BlockBufferPool) -> Self {
+        self.buffer_pool = Some(buffer_pool);
+        self
+    }
+
+    /// The blocks that could not be decompressed so far, and were filled instead.
+    /// Always empty, unless `skip_broken_blocks` was called.
+    pub fn broken_blocks(&self) -> &[BlockIndex] { &self.broken_blocks }
+
+    /// Decompress all remaining blocks and call the supplied closure for each block.
+    /// The order of the blocks is not deterministic, unless `preserve_order` was called.
+    pub fn decompress_all_blocks(
+        mut self, mut insert_block: impl FnMut(&MetaData, UncompressedBlock) -> UnitResult
+    ) -> UnitResult
+    {
+        while let Some(block) = self.next() {
+            insert_block(self.meta_data(), block?)?;
+        }
+
+        debug_assert_eq!(self.len(), 0, "compressed blocks left after decompressing all blocks");
+        Ok(())
+    }
+
+    /// Decompress the remaining blocks and call the supplied closure for each block,
+    /// until the closure returns `ControlFlow::Break`.
+    /// After cancelling, the blocks that are still being decompressed are not awaited,
+    /// and the jobs that have not started yet are skipped.
+    /// Returns `ControlFlow::Break` if the closure cancelled the process.
+    pub fn decompress_blocks_cancellable(
+        mut self, mut insert_block: impl FnMut(&MetaData, UncompressedBlock) -> Result<ControlFlow<()>>
+    ) -> Result<ControlFlow<()>>
+    {
+        while let Some(block) = self.next() {
+            if insert_block(self.meta_data(), block?)?.is_break() {
+                return Ok(ControlFlow::Break(())); // dropping self cancels the queued jobs
+            }
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Fill the pool with decompression jobs. Returns the first job that finishes,
+    /// or the next block in chunk order if `preserve_order` was called.
     pub fn decompress_next_block(&mut self) -> Option<Result<UncompressedBlock>> {
 
-        while self.currently_decompressing_count < self.max_threads {
+        while self.currently_decompressing_count < self.max_in_flight {
             let block = self.remaining_chunks.next();
             if let Some(block) = block {
                 let block = match block {
@@ -465,19 +1701,29 @@ impl<R: ChunksReader> ParallelBlockDecompressor<R> {
 
                 let sender = self.sender.clone();
                 let meta = self.shared_meta_data_ref.clone();
-                let pedantic = self.pedantic;
+                let cancelled = self.cancelled.clone();
+                let validation = self.validation;
+                let broken_block_fill = self.broken_block_fill;
+                let buffer_pool = self.buffer_pool.clone();
+                let sequence_number = self.next_spawned_sequence_number;
 
                 self.currently_decompressing_count += 1;
+                self.next_spawned_sequence_number += 1;
 
-                self.pool.spawn(move || {
-                    let decompressed_or_err = UncompressedBlock::decompress_chunk(
-                        block, &meta, pedantic
+                self.pool.get().spawn(move || {
+                    // the decompressor has been dropped, nobody will receive this block
+                    if cancelled.load(Ordering::Relaxed) {
+                        return;
+                    }
+
+                    let decompressed_or_err = decompress_chunk_or_fill(
+                        block, &meta, validation, broken_block_fill, buffer_pool.as_ref()
                     );
 
                     // by now, decompressing could have failed in another thread.
                     // the error is then already handled, so we simply
                     // don't send the decompressed block and do nothing
-                    let _ = sender.send(decompressed_or_err);
+                    let _ = sender.send((sequence_number, decompressed_or_err));
                 });
             }
             else {
@@ -487,11 +1733,29 @@ impl<R: ChunksReader> ParallelBlockDecompressor<R> {
         }
 
         if self.currently_decompressing_count > 0 {
-            let next = self.receiver.recv()
-                .expect("all decompressing senders hung up but more messages were expected");
+            let next = match &mut self.reorder_buffer {
+                None => self.receiver.recv()
+                    .expect("all decompressing senders hung up but more messages were expected").1,
+
+                Some(reorder_buffer) => loop {
+                    if let Some(next) = reorder_buffer.remove(&self.next_returned_sequence_number) {
+                        self.next_returned_sequence_number += 1;
+                        break next;
+                    }
+
+                    let (sequence_number, block) = self.receiver.recv()
+                        .expect("all decompressing senders hung up but more messages were expected");
+
+                    reorder_buffer.insert(sequence_number, block);
+                },
+            };
 
             self.currently_decompressing_count -= 1;
-            Some(next)
+
+            Some(next.map(|(block, broken_block)| {
+                self.broken_blocks.extend(broken_block);
+                block
+            }))
         }
         else {
             debug_assert!(self.receiver.try_recv().is_err(), "uncompressed chunks left in channel after decompressing all chunks"); // TODO not reliable
@@ -504,6 +1768,12 @@ impl<R: ChunksReader> ParallelBlockDecompressor<R> {
     pub fn meta_data(&self) -> &MetaData { self.remaining_chunks.meta_data() }
 }
 
+impl<R: ChunksReader> Drop for ParallelBlockDecompressor<'_, R> {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
 impl<R: ChunksReader> ExactSizeIterator for SequentialBlockDecompressor<R> {}
 impl<R: ChunksReader> Iterator for SequentialBlockDecompressor<R> {
     type Item = Result<UncompressedBlock>;
@@ -511,8 +1781,8 @@ impl<R: ChunksReader> Iterator for SequentialBlockDecompressor<R> {
     fn size_hint(&self) -> (usize, Option<usize>) { self.remaining_chunks_reader.size_hint() }
 }
 
-impl<R: ChunksReader> ExactSizeIterator for ParallelBlockDecompressor<R> {}
-impl<R: ChunksReader> Iterator for ParallelBlockDecompressor<R> {
+impl<R: ChunksReader> ExactSizeIterator for ParallelBlockDecompressor<'_, R> {}
+impl<R: ChunksReader> Iterator for ParallelBlockDecompressor<'_, R> {
     type Item = Result<UncompressedBlock>;
     fn next(&mut self) -> Option<Self::Item> { self.decompress_next_block() }
     fn size_hint(&self) -> (usize, Option<usize>) {
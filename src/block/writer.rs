@@ -2,20 +2,28 @@
 
 
 use std::fmt::Debug;
-use std::io::Seek;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
 use std::iter::Peekable;
 use std::ops::Not;
+use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
 use rayon_core::{ThreadPool, ThreadPoolBuildError};
 
 use smallvec::alloc::collections::BTreeMap;
 
-use crate::block::UncompressedBlock;
-use crate::block::chunk::{Chunk};
+use crate::block::{BlockIndex, UncompressedBlock};
+use crate::block::chunk::{Chunk, TileCoordinates};
+use crate::block::lines::{LineIndex, LineRefMut};
 use crate::compression::Compression;
 use crate::error::{Error, Result, UnitResult, usize_to_u64};
-use crate::io::{Data, Tracking, Write};
+use crate::io::{Data, TemporaryFile, Tracking, Write};
 use crate::meta::{Headers, MetaData, OffsetTables};
+use crate::meta::header::Header;
 use crate::meta::attribute::LineOrder;
+use crate::meta::BlockDescription;
+use crate::math::Vec2;
+use crate::compression::ByteVec;
 
 /// Write an exr file by writing one chunk after another in a closure.
 /// In the closure, you are provided a chunk writer, which should be used to write all the chunks.
@@ -24,10 +32,351 @@ pub fn write_chunks_with<W: Write + Seek>(
     buffered_write: W, headers: Headers, pedantic: bool,
     write_chunks: impl FnOnce(MetaData, &mut ChunkWriter<W>) -> UnitResult
 ) -> UnitResult {
+    write_chunks_with_statistics(buffered_write, headers, pedantic, write_chunks)?;
+    Ok(())
+}
+
+/// Write an exr file by writing one chunk after another in a closure.
+/// In the closure, you are provided a chunk writer, which should be used to write all the chunks.
+/// Returns how many bytes have been written, in total and for each layer.
+/// Assumes the your write destination is buffered.
+pub fn write_chunks_with_statistics<W: Write + Seek>(
+    buffered_write: W, headers: Headers, pedantic: bool,
+    write_chunks: impl FnOnce(MetaData, &mut ChunkWriter<W>) -> UnitResult
+) -> Result<WriteStatistics> {
     // this closure approach ensures that after writing all chunks, the file is always completed and checked and flushed
     let (meta, mut writer) = ChunkWriter::new_for_buffered(buffered_write, headers, pedantic)?;
     write_chunks(meta, &mut writer)?;
-    writer.complete_meta_data()
+
+    let statistics = writer.statistics();
+    writer.complete_meta_data()?;
+    Ok(statistics)
+}
+
+/// Write an exr file by writing one chunk after another in a closure, reporting the progress to the specified callback.
+/// In the closure, you are provided a chunk writer that reports each chunk it writes.
+/// After all chunks and the offset tables have been written,
+/// the callback is invoked one final time with `WriteProgress::MetaDataCompleted`.
+/// Returns how many bytes have been written, in total and for each layer.
+/// Assumes the your write destination is buffered.
+pub fn write_chunks_with_progress<W: Write + Seek>(
+    buffered_write: W, headers: Headers, pedantic: bool,
+    mut on_progress: impl FnMut(WriteProgress),
+    write_chunks: impl FnOnce(MetaData, &mut OnBlockProgressChunkWriter<'_, ChunkWriter<W>, &mut dyn FnMut(ChunkWriteProgress)>) -> UnitResult
+) -> Result<WriteStatistics> {
+    let (meta, mut writer) = ChunkWriter::new_for_buffered(buffered_write, headers, pedantic)?;
+
+    {
+        let mut on_chunk_progress = |progress| on_progress(WriteProgress::ChunkWritten(progress));
+        let on_chunk_progress: &mut dyn FnMut(ChunkWriteProgress) = &mut on_chunk_progress;
+        write_chunks(meta, &mut writer.on_block_progress(on_chunk_progress))?;
+    }
+
+    let statistics = writer.statistics();
+    writer.complete_meta_data()?;
+
+    on_progress(WriteProgress::MetaDataCompleted(statistics.clone()));
+    Ok(statistics)
+}
+
+/// Write an exr file by writing one chunk after another in a closure, without ever leaving a corrupt file at the path.
+/// The file is written to a temporary file in the same directory, which replaces the file at the path
+/// only after all chunks and the offset tables have been written successfully.
+/// If an error occurs, the temporary file is deleted, and any previous file at the path is left untouched.
+pub fn write_chunks_to_file_atomically(
+    path: impl AsRef<std::path::Path>, headers: Headers, pedantic: bool,
+    write_chunks: impl FnOnce(MetaData, &mut ChunkWriter<std::io::BufWriter<&mut std::fs::File>>) -> UnitResult
+) -> UnitResult {
+    crate::io::write_file_atomically(path.as_ref(), move |file|
+        write_chunks_with(std::io::BufWriter::new(file), headers, pedantic, write_chunks)
+    )
+}
+
+/// Write a new exr file that contains all layers of an existing file, followed by additional layers.
+/// The chunks of the existing layers are copied without being decompressed or compressed again.
+/// In the closure, only the chunks of the new layers should be written,
+/// which have the layer indices following the existing layers.
+/// The existing file is read using the specified reader, which should be buffered.
+/// Assumes the your write destination is buffered.
+pub fn append_headers_with<R: std::io::Read + Seek, W: Write + Seek>(
+    existing: crate::block::reader::Reader<R>, buffered_write: W, new_headers: Headers, pedantic: bool,
+    write_new_chunks: impl FnOnce(MetaData, &mut ChunkWriter<W>) -> UnitResult
+) -> UnitResult {
+    let mut existing = existing;
+    let existing_headers = existing.headers().to_vec();
+
+    let mut headers = Headers::from_vec(existing_headers.clone());
+    headers.extend(new_headers);
+
+    let copier = ChunkCopier::new(&existing_headers, &headers)?;
+
+    write_chunks_with(buffered_write, headers, pedantic, move |meta, chunk_writer| {
+        copier.copy_all_chunks(&mut existing, chunk_writer)?;
+        write_new_chunks(meta, chunk_writer)
+    })
+}
+
+/// Write a new exr file with the same pixels as an existing file, but with modified headers.
+/// In the closure, the attributes of the headers can be changed, for example to add a comment.
+/// All chunks are copied from the existing file without being decompressed or compressed again.
+/// Returns an error if the closure changes anything that affects how the pixels are stored,
+/// such as the channels, the compression, the data window, or the tiles,
+/// or if the closure adds or removes headers. The line order may be changed.
+/// The existing file is read using the specified reader, which should be buffered.
+/// Assumes the your write destination is buffered.
+pub fn rewrite_with_modified_headers<R: std::io::Read + Seek, W: Write + Seek>(
+    existing: R, buffered_write: W, pedantic: bool,
+    edit: impl FnOnce(&mut Headers) -> UnitResult
+) -> UnitResult {
+    let mut existing = crate::block::read(existing, pedantic)?;
+    let mut headers = existing.meta_data().headers.clone();
+    edit(&mut headers)?;
+
+    if headers.len() != existing.headers().len() {
+        return Err(Error::unsupported("adding or removing headers while rewriting the headers"));
+    }
+
+    let copier = ChunkCopier::new(existing.headers(), &headers)?;
+
+    write_chunks_with(buffered_write, headers, pedantic, move |_, chunk_writer| {
+        copier.copy_all_chunks(&mut existing, chunk_writer)
+    })
+}
+
+/// Copies compressed chunks from one file to another, without decompressing or compressing them.
+/// Maps the tile coordinates of each chunk to the index of the chunk in its header,
+/// which is required by `ChunksWriter::write_chunk`.
+/// The layers of the source file are copied to the layers with the same index in the target file.
+/// The target file may contain additional layers after the copied layers.
+#[derive(Debug, Clone)]
+pub struct ChunkCopier {
+
+    /// The target headers of the copied layers, which decide in which order the chunks are written.
+    headers: Headers,
+
+    /// For each copied layer, the index of each chunk in increasing y order, by tile coordinates.
+    chunk_indices: Vec<HashMap<TileCoordinates, usize>>,
+}
+
+impl ChunkCopier {
+
+    /// Prepare copying the chunks of the source layers into the target layers.
+    /// Returns an error if any copied layer differs in how the pixels are stored,
+    /// such as the channels, the compression, the data window, or the tiles.
+    /// The line order and all other attributes may differ.
+    pub fn new(source_headers: &[Header], target_headers: &[Header]) -> Result<Self> {
+        if target_headers.len() < source_headers.len() {
+            return Err(Error::unsupported("copying chunks to fewer layers"));
+        }
+
+        let headers: Headers = target_headers[.. source_headers.len()].iter().cloned().collect();
+
+        for (target, source) in headers.iter().zip(source_headers) {
+            if let Some(difference) = pixel_layout_difference(target, source) {
+                return Err(Error::unsupported(format!("copying chunks between layers with different {}", difference)));
+            }
+        }
+
+        let chunk_indices = headers.iter()
+            .map(|header| header.blocks_increasing_y_order().enumerate()
+                .map(|(index_in_header_increasing_y, tile)| (tile.location, index_in_header_increasing_y))
+                .collect()
+            )
+            .collect();
+
+        Ok(ChunkCopier { headers, chunk_indices })
+    }
+
+    /// The index of the chunk in its header, in increasing y order.
+    /// Returns an error if the chunk does not belong to a copied layer.
+    pub fn index_in_header_increasing_y(&self, chunk: &Chunk) -> Result<usize> {
+        let header = self.headers.get(chunk.layer_index)
+            .ok_or(Error::invalid("chunk layer index"))?;
+
+        let tile = header.get_block_data_indices(&chunk.compressed_block)?;
+
+        self.chunk_indices[chunk.layer_index].get(&tile).copied()
+            .ok_or(Error::invalid("chunk tile coordinates"))
+    }
+
+    /// Write the chunk to the target file unchanged.
+    /// The chunks must be copied in the order required by the line order of the target headers.
+    pub fn copy_chunk(&self, chunk: Chunk, chunk_writer: &mut impl ChunksWriter) -> UnitResult {
+        let index_in_header_increasing_y = self.index_in_header_increasing_y(&chunk)?;
+        chunk_writer.write_chunk(index_in_header_increasing_y, chunk)
+    }
+
+    /// Copy all chunks of the copied layers from the source file, in the order required by the target headers.
+    /// Seeks to each chunk in the source file using its offset tables.
+    pub fn copy_all_chunks<R: std::io::Read + Seek>(
+        &self, source: &mut crate::block::reader::Reader<R>, chunk_writer: &mut impl ChunksWriter
+    ) -> UnitResult {
+        for (layer_index, header) in self.headers.iter().enumerate() {
+            for (index_in_header_increasing_y, tile) in header.enumerate_ordered_blocks() {
+                let chunk = source.read_chunk_at_index(layer_index, index_in_header_increasing_y, tile.location)?;
+                chunk_writer.write_chunk(index_in_header_increasing_y, chunk)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The name of the first property that affects how the pixels are stored in the file, if it differs between the headers.
+fn pixel_layout_difference(header: &Header, other: &Header) -> Option<&'static str> {
+    if header.channels != other.channels { Some("channels") }
+    else if header.compression != other.compression { Some("compression") }
+    else if header.blocks != other.blocks { Some("tiles") }
+    else if header.layer_size != other.layer_size
+        || header.own_attributes.layer_position != other.own_attributes.layer_position { Some("data window") }
+    else if header.deep != other.deep || header.deep_data_version != other.deep_data_version
+        || header.max_samples_per_pixel != other.max_samples_per_pixel { Some("deep data") }
+    else if header.chunk_count != other.chunk_count { Some("chunk count") }
+    else { None }
+}
+
+/// Write a new exr file with the same pixels as an existing file, but with a different compression method.
+/// The closure returns the new compression method for each header of the existing file.
+/// All layers, attributes, tiles, and the line order are preserved.
+/// Scan line blocks are split or merged where the new compression method uses a different number of lines per block.
+/// Only a few blocks are kept in memory at any time, instead of the whole image.
+/// The existing file is read using the specified reader, which should be buffered.
+/// Assumes the your write destination is buffered.
+pub fn transcode<R: std::io::Read + Seek, W: Write + Seek>(
+    existing: R, buffered_write: W, pedantic: bool, parallel: bool,
+    mut target_compression: impl FnMut(&Header) -> Compression
+) -> UnitResult {
+    let existing = crate::block::read(existing, pedantic)?;
+    let existing_headers = existing.headers().to_vec();
+
+    for header in &existing_headers {
+        if header.deep {
+            return Err(Error::unsupported("transcoding deep data"));
+        }
+
+        if header.channels.list.iter().any(|channel| channel.sampling != Vec2(1, 1)) {
+            return Err(Error::unsupported("transcoding sub-sampled channels"));
+        }
+    }
+
+    let headers: Headers = existing_headers.iter()
+        .map(|header| {
+            let compression = target_compression(header);
+            header.clone().with_encoding(compression, header.blocks, header.line_order)
+        })
+        .collect();
+
+    // each existing scan line block is needed by at most two consecutive new blocks, so two blocks are cached
+    let max_block_byte_size = existing_headers.iter()
+        .map(|header| header.max_block_byte_size())
+        .max().unwrap_or(0);
+
+    let mut existing_blocks = existing.cached_blocks(pedantic, 2 * max_block_byte_size)?;
+
+    write_chunks_with(buffered_write, headers, pedantic, move |meta, chunk_writer| {
+        let mut read_error = None;
+
+        let blocks = meta.headers.iter().enumerate()
+            .flat_map(|(layer_index, header)| header.enumerate_ordered_blocks()
+                .map(move |(index_in_header_increasing_y, tile)| (layer_index, index_in_header_increasing_y, tile.location))
+            )
+            .map_while(|(layer_index, index_in_header_increasing_y, tile)| {
+                match transcode_block(&mut existing_blocks, &meta.headers[layer_index], layer_index, tile) {
+                    Ok(block) => Some((index_in_header_increasing_y, block)),
+                    Err(error) => { read_error = Some(error); None },
+                }
+            });
+
+        let parallel_compressor = if parallel { chunk_writer.parallel_blocks_compressor(&meta) } else { None };
+
+        match parallel_compressor {
+            Some(compressor) => compressor.compress_all_blocks(blocks)?,
+
+            None => {
+                let mut compressor = chunk_writer.sequential_blocks_compressor(&meta);
+                for (index_in_header_increasing_y, block) in blocks {
+                    compressor.compress_block(index_in_header_increasing_y, block)?;
+                }
+            }
+        }
+
+        match read_error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    })
+}
+
+/// Assemble the block at the specified tile of the new header from the blocks of the existing file.
+/// Tiles are the same in both files, but scan line blocks may contain a different number of lines.
+fn transcode_block<R: std::io::Read + Seek>(
+    existing_blocks: &mut crate::block::reader::CachedBlockReader<R>,
+    header: &Header, layer_index: usize, tile: TileCoordinates
+) -> Result<UncompressedBlock> {
+    let pixel_bounds = header.get_absolute_block_pixel_coordinates(tile)?;
+    let index = BlockIndex {
+        layer: layer_index,
+        level: tile.level_index,
+        pixel_position: pixel_bounds.position.to_usize("data indices start")?,
+        pixel_size: pixel_bounds.size,
+    };
+
+    if header.blocks != BlockDescription::ScanLines {
+        let existing_block = existing_blocks.block_at(layer_index, tile.level_index, tile.tile_index)?;
+        return Ok(UncompressedBlock { index, data: existing_block.data.clone() });
+    }
+
+    // all samples of a line are stored consecutively for all channels, so consecutive lines can be copied at once
+    let existing_header = &existing_blocks.headers()[layer_index];
+    let existing_lines_per_block = existing_header.compression.scan_lines_per_block();
+    let bytes_per_line = index.pixel_size.width() * header.channels.bytes_per_pixel;
+
+    let lines = index.pixel_position.y() .. index.pixel_position.y() + index.pixel_size.height();
+    let mut data = Vec::with_capacity(lines.len() * bytes_per_line);
+
+    let first_block_y = lines.start / existing_lines_per_block;
+    let last_block_y = (lines.end - 1) / existing_lines_per_block;
+
+    for block_y in first_block_y ..= last_block_y {
+        let existing_block = existing_blocks.block_at(layer_index, Vec2(0, 0), Vec2(0, block_y))?;
+        let existing_lines = existing_block.index.pixel_position.y() .. existing_block.index.pixel_position.y() + existing_block.index.pixel_size.height();
+
+        let start_line = lines.start.max(existing_lines.start) - existing_lines.start;
+        let end_line = lines.end.min(existing_lines.end) - existing_lines.start;
+
+        let bytes = existing_block.data.get(start_line * bytes_per_line .. end_line * bytes_per_line)
+            .ok_or(Error::invalid("block byte size"))?;
+
+        data.extend_from_slice(bytes);
+    }
+
+    Ok(UncompressedBlock { index, data })
+}
+
+/// Write an exr file to a byte destination that cannot seek, such as a network stream or stdout.
+/// As the offset tables precede the pixels in the file, the closure is called twice:
+/// First, to measure the byte size of each compressed chunk, and then again to actually write the chunks.
+/// The closure must produce the same chunks in both calls, but may produce them in a different order.
+/// Only the byte sizes of the chunks are kept in memory, not the compressed pixels.
+/// Use `UnseekableChunkWriter::is_measuring_chunk_sizes` to find out which of the two calls is happening.
+pub fn write_chunks_with_unseekable<W: Write>(
+    write: W, headers: Headers, pedantic: bool,
+    mut write_chunks: impl FnMut(MetaData, &mut UnseekableChunkWriter<W>) -> UnitResult
+) -> UnitResult {
+    let mut headers = headers;
+    MetaData::complete_required_attributes(&mut headers);
+
+    // the meta data is validated and encoded before any byte is written to the destination
+    let mut meta_data_bytes = Vec::new();
+    let requirements = MetaData::write_validating_to_buffered(&mut meta_data_bytes, headers.as_slice(), pedantic)?;
+    let meta_data = MetaData { requirements, headers };
+
+    let mut writer = UnseekableChunkWriter::new(write, &meta_data.headers);
+    write_chunks(meta_data.clone(), &mut writer)?;
+
+    writer.write_meta_data(&meta_data_bytes)?;
+    write_chunks(meta_data, &mut writer)?;
+    writer.complete_chunks()
 }
 
 /// Can consume compressed pixel chunks, writing them a file.
@@ -41,9 +390,42 @@ pub fn write_chunks_with<W: Write + Seek>(
 pub struct ChunkWriter<W> {
     header_count: usize,
     byte_writer: Tracking<W>,
-    chunk_indices_byte_location: std::ops::Range<usize>,
+    chunk_indices_byte_location: std::ops::Range<u64>,
     chunk_indices_increasing_y: OffsetTables,
     chunk_count: usize, // TODO compose?
+    written_chunk_count: usize,
+    chunk_byte_counts_per_layer: Vec<u64>,
+    checkpoint_interval: Option<usize>,
+
+    /// For each layer, the line order and the number of chunks written so far.
+    line_orders: Vec<LineOrder>,
+    chunk_counts_per_layer: Vec<usize>,
+}
+
+/// How many bytes have been written to a file, in total and for each layer.
+/// Obtained from `ChunkWriter::statistics` or `write_chunks_with_statistics`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteStatistics {
+
+    /// The number of bytes that have been written so far, including the meta data and offset tables.
+    pub written_byte_count: u64,
+
+    /// For each layer, the number of bytes of all chunks of that layer that have been written so far.
+    pub chunk_byte_counts_per_layer: Vec<u64>,
+
+    /// The number of chunks that have been written so far.
+    pub written_chunk_count: usize,
+
+    /// The total number of chunks that the complete file will contain.
+    pub total_chunk_count: usize,
+}
+
+impl WriteStatistics {
+
+    /// The number of bytes of all chunks that have been written so far, excluding the meta data and offset tables.
+    pub fn chunk_byte_count(&self) -> u64 {
+        self.chunk_byte_counts_per_layer.iter().sum()
+    }
 }
 
 /// A new writer that triggers a callback
@@ -56,6 +438,58 @@ pub struct OnProgressChunkWriter<'w, W, F> {
     on_progress: F,
 }
 
+/// A new writer that triggers a callback with detailed progress information
+/// for each block written to the inner writer.
+#[derive(Debug)]
+#[must_use]
+pub struct OnBlockProgressChunkWriter<'w, W, F> {
+    chunk_writer: &'w mut W,
+    written_chunks: usize,
+    compressed_bytes: u64,
+    on_progress: F,
+}
+
+/// Detailed information about the writing progress,
+/// passed to the closure of `on_block_progress` after each chunk has been written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkWriteProgress {
+
+    /// The number of chunks that have been written so far, including this chunk.
+    pub written_chunk_count: usize,
+
+    /// The total number of chunks that the complete file will contain.
+    pub total_chunk_count: usize,
+
+    /// The index of the layer that the chunk that has just been written belongs to.
+    pub layer_index: usize,
+
+    /// The index of the chunk that has just been written, in increasing y order within its layer.
+    pub index_in_header_increasing_y: usize,
+
+    /// The number of compressed bytes that have been written so far, including this chunk.
+    pub compressed_byte_count: u64,
+
+    /// The number of compressed bytes of the chunk that has just been written.
+    /// Use this instead of the chunk count to compute a fraction if the chunks differ a lot in size.
+    pub chunk_byte_size: usize,
+
+    /// The number of bytes that have been written to the destination so far,
+    /// including the meta data and offset tables. `None` if the destination does not know its byte position.
+    pub written_byte_count: Option<u64>,
+}
+
+/// An event passed to the closure of `write_chunks_with_progress`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteProgress {
+
+    /// A chunk has just been written.
+    ChunkWritten(ChunkWriteProgress),
+
+    /// All chunks have been written, and the offset tables have been written and flushed.
+    /// This is always the last event.
+    MetaDataCompleted(WriteStatistics),
+}
+
 /// Write chunks to a byte destination.
 /// Then write each chunk with `writer.write_chunk(chunk)`.
 pub trait ChunksWriter: Sized {
@@ -69,11 +503,21 @@ pub trait ChunksWriter: Sized {
     /// Errors when the chunk at this index was already written.
     fn write_chunk(&mut self, index_in_header_increasing_y: usize, chunk: Chunk) -> UnitResult;
 
+    /// The number of bytes that have been written to the destination so far,
+    /// or `None` if this writer does not know its byte position.
+    fn byte_position(&self) -> Option<u64> { None }
+
     /// Obtain a new writer that calls the specified closure for each block that is written to this writer.
     fn on_progress<F>(&mut self, on_progress: F) -> OnProgressChunkWriter<'_, Self, F> where F: FnMut(f64) {
         OnProgressChunkWriter { chunk_writer: self, written_chunks: 0, on_progress }
     }
 
+    /// Obtain a new writer that calls the specified closure for each block that is written to this writer,
+    /// with detailed information about the chunk and the overall progress.
+    fn on_block_progress<F>(&mut self, on_progress: F) -> OnBlockProgressChunkWriter<'_, Self, F> where F: FnMut(ChunkWriteProgress) {
+        OnBlockProgressChunkWriter { chunk_writer: self, written_chunks: 0, compressed_bytes: 0, on_progress }
+    }
+
     /// Obtain a new writer that can compress blocks to chunks, which are then passed to this writer.
     fn sequential_blocks_compressor<'w>(&'w mut self, meta: &'w MetaData) -> SequentialBlocksCompressor<'w, Self> {
         SequentialBlocksCompressor::new(meta, self)
@@ -85,13 +529,19 @@ pub trait ChunksWriter: Sized {
         ParallelBlocksCompressor::new(meta, self)
     }
 
+    /// Obtain a new writer that can compress blocks to chunks using the threads of the specified pool,
+    /// which are then passed to this writer. The pool is only borrowed, so no additional threads are created.
+    /// Returns none if the sequential compressor should be used instead (too large performance overhead).
+    fn parallel_blocks_compressor_with_pool<'w>(&'w mut self, meta: &'w MetaData, pool: &'w ThreadPool) -> Option<ParallelBlocksCompressor<'w, Self>> {
+        ParallelBlocksCompressor::new_with_shared_thread_pool(meta, self, pool)
+    }
+
     /// Compresses all blocks to the file.
     /// The index of the block must be in increasing line order within the header.
     /// Obtain iterator with `MetaData::collect_ordered_blocks(...)` or similar methods.
     fn compress_all_blocks_sequential(mut self, meta: &MetaData, blocks: impl Iterator<Item=(usize, UncompressedBlock)>) -> UnitResult {
         let mut writer = self.sequential_blocks_compressor(meta);
 
-        // TODO check block order if line order is not unspecified!
         for (index_in_header_increasing_y, block) in blocks {
             writer.compress_block(index_in_header_increasing_y, block)?;
         }
@@ -105,18 +555,86 @@ pub trait ChunksWriter: Sized {
     /// Obtain iterator with `MetaData::collect_ordered_blocks(...)` or similar methods.
     /// Will fallback to sequential processing where threads are not available, or where it would not speed up the process.
     fn compress_all_blocks_parallel(mut self, meta: &MetaData, blocks: impl Iterator<Item=(usize, UncompressedBlock)>) -> UnitResult {
-        let mut parallel_writer = match self.parallel_blocks_compressor(meta) {
-            None => return self.compress_all_blocks_sequential(meta, blocks),
-            Some(writer) => writer,
-        };
-
-        // TODO check block order if line order is not unspecified!
-        for (index_in_header_increasing_y, block) in blocks {
-            parallel_writer.add_block_to_compression_queue(index_in_header_increasing_y, block)?;
+        match self.parallel_blocks_compressor(meta) {
+            None => self.compress_all_blocks_sequential(meta, blocks),
+            Some(parallel_writer) => parallel_writer.compress_all_blocks(blocks),
         }
+    }
 
-        // TODO debug_assert_eq!(self.is_complete());
-        Ok(())
+    /// Produces and compresses all blocks on the global thread pool, and writes them to the file in this thread.
+    /// Use this instead of `compress_all_blocks_parallel` if producing the blocks is expensive.
+    /// The parallel iterator must yield the blocks in the same order as `MetaData::collect_ordered_blocks(...)`,
+    /// that is, the index of the block must be in increasing line order within the header.
+    /// Chunks that are compressed before the chunks preceding them in the file are kept in memory until they can be written.
+    #[cfg(feature = "parallel-iterator")]
+    fn compress_all_blocks_from_par_iter(
+        &mut self, meta: &MetaData,
+        blocks: impl rayon::iter::IndexedParallelIterator<Item=(usize, UncompressedBlock)>
+    ) -> UnitResult
+    {
+        use rayon::iter::ParallelIterator;
+
+        let headers = meta.headers.as_slice();
+        let (sender, receiver) = flume::bounded(rayon_core::current_num_threads().max(1) * 2);
+
+        // the receiver is moved into the scope and dropped when an error is returned early,
+        // such that the blocked producer threads notice and stop producing blocks
+        rayon_core::in_place_scope(move |scope| {
+            scope.spawn(move |_| {
+                let _stopped = blocks.enumerate().try_for_each_with(sender, |sender, (index_in_file, (index_in_header_increasing_y, block))| {
+                    let compressed = block.compress_to_chunk(headers)
+                        .map(|chunk| (index_in_file, index_in_header_increasing_y, chunk));
+
+                    sender.send(compressed)
+                });
+            });
+
+            let mut sorted_writer = SortedBlocksWriter::new(meta, self);
+
+            loop {
+                let compressed = match receiver.try_recv() {
+                    Ok(compressed) => compressed,
+                    Err(flume::TryRecvError::Disconnected) => break,
+
+                    // when this is a thread of the pool, blocking it might leave no thread to produce blocks
+                    Err(flume::TryRecvError::Empty) => match rayon_core::yield_now() {
+                        Some(rayon_core::Yield::Executed) => continue,
+                        Some(rayon_core::Yield::Idle) => { std::thread::yield_now(); continue },
+
+                        None => match receiver.recv() {
+                            Ok(compressed) => compressed,
+                            Err(flume::RecvError::Disconnected) => break,
+                        },
+                    },
+                };
+
+                let (index_in_file, index_in_header_increasing_y, chunk) = compressed?;
+                sorted_writer.write_or_stash_chunk(index_in_file, index_in_header_increasing_y, chunk)?;
+            }
+
+            if sorted_writer.pending_chunk_count() != 0 {
+                return Err(Error::invalid("some chunks are not written yet"));
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Compresses all blocks to the file, using the threads of the specified pool.
+    /// The pool is only borrowed, so a single pool can be shared by many concurrent writes.
+    /// The pool may have any number of threads, and this function may also be called from within the pool.
+    /// The index of the block must be in increasing line order within the header.
+    /// Obtain iterator with `MetaData::collect_ordered_blocks(...)` or similar methods.
+    /// Will fallback to sequential processing where it would not speed up the process.
+    fn compress_all_blocks_parallel_with_pool(
+        mut self, meta: &MetaData, pool: &ThreadPool,
+        blocks: impl Iterator<Item=(usize, UncompressedBlock)>
+    ) -> UnitResult
+    {
+        match self.parallel_blocks_compressor_with_pool(meta, pool) {
+            None => self.compress_all_blocks_sequential(meta, blocks),
+            Some(parallel_writer) => parallel_writer.compress_all_blocks(blocks),
+        }
     }
 }
 
@@ -126,14 +644,20 @@ impl<W> ChunksWriter for ChunkWriter<W> where W: Write + Seek {
     /// The total number of chunks that the complete file will contain.
     fn total_chunks_count(&self) -> usize { self.chunk_count }
 
+    /// Includes the meta data and offset tables.
+    fn byte_position(&self) -> Option<u64> { Some(self.written_byte_count()) }
+
     /// Any more calls will result in an error and have no effect.
     /// If writing results in an error, the file and the writer
     /// may remain in an invalid state and should not be used further.
-    /// Errors when the chunk at this index was already written.
+    /// Errors when the chunk at this index was already written,
+    /// or when the chunk is not the next chunk according to the line order of its layer.
     fn write_chunk(&mut self, index_in_header_increasing_y: usize, chunk: Chunk) -> UnitResult {
-        let header_chunk_indices = &mut self.chunk_indices_increasing_y[chunk.layer_index];
+        let header_chunk_indices = self.chunk_indices_increasing_y
+            .get_mut(chunk.layer_index).ok_or(Error::invalid("chunk layer index"))?;
 
-        if index_in_header_increasing_y >= header_chunk_indices.len() {
+        let header_chunk_count = header_chunk_indices.len();
+        if index_in_header_increasing_y >= header_chunk_count {
             return Err(Error::invalid("too large chunk index"));
         }
 
@@ -142,8 +666,94 @@ impl<W> ChunksWriter for ChunkWriter<W> where W: Write + Seek {
             return Err(Error::invalid(format!("chunk at index {} is already written", index_in_header_increasing_y)));
         }
 
-        *chunk_index_slot = usize_to_u64(self.byte_writer.byte_position());
+        // all previous chunks of this layer have been written, so this cannot overflow
+        let layer_chunk_count = self.chunk_counts_per_layer[chunk.layer_index];
+        let expected_index_in_header_increasing_y = match self.line_orders[chunk.layer_index] {
+            LineOrder::Increasing => Some(layer_chunk_count),
+            LineOrder::Decreasing => Some(header_chunk_count - 1 - layer_chunk_count),
+            LineOrder::Unspecified => None,
+        };
+
+        if let Some(expected_index) = expected_index_in_header_increasing_y {
+            if expected_index != index_in_header_increasing_y {
+                return Err(Error::invalid(format!(
+                    "chunk at index {} does not match the line order, expected chunk at index {}",
+                    index_in_header_increasing_y, expected_index
+                )));
+            }
+        }
+
+        let chunk_start_byte = self.byte_writer.byte_position();
+        *chunk_index_slot = chunk_start_byte;
         chunk.write(&mut self.byte_writer, self.header_count)?;
+
+        self.written_chunk_count += 1;
+        self.chunk_counts_per_layer[chunk.layer_index] += 1;
+        self.chunk_byte_counts_per_layer[chunk.layer_index] += self.byte_writer.byte_position() - chunk_start_byte;
+
+        if let Some(interval) = self.checkpoint_interval {
+            if self.written_chunk_count % interval == 0 && self.written_chunk_count != self.chunk_count {
+                self.checkpoint()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<W> ChunkWriter<W> {
+
+    /// The number of bytes that have been written so far, including the meta data and offset tables.
+    pub fn written_byte_count(&self) -> u64 {
+        self.byte_writer.byte_position()
+    }
+
+    /// For each layer, the number of bytes of all chunks of that layer that have been written so far.
+    pub fn chunk_byte_counts_per_layer(&self) -> &[u64] {
+        &self.chunk_byte_counts_per_layer
+    }
+
+    /// The number of chunks that have been written so far. Compare to `total_chunks_count`.
+    pub fn written_chunk_count(&self) -> usize {
+        self.written_chunk_count
+    }
+
+    /// How many bytes have been written so far, in total and for each layer.
+    pub fn statistics(&self) -> WriteStatistics {
+        WriteStatistics {
+            written_byte_count: self.written_byte_count(),
+            chunk_byte_counts_per_layer: self.chunk_byte_counts_per_layer.clone(),
+            written_chunk_count: self.written_chunk_count,
+            total_chunk_count: self.chunk_count,
+        }
+    }
+
+    /// Automatically call `checkpoint` each time the specified number of chunks has been written.
+    /// Pass `None` to disable automatic checkpoints, which is the default.
+    /// Choose a large interval, as each checkpoint seeks back and rewrites the whole offset tables.
+    pub fn set_checkpoint_interval(&mut self, chunk_count: Option<usize>) {
+        self.checkpoint_interval = chunk_count.map(|count| count.max(1));
+    }
+}
+
+impl<W> ChunkWriter<W> where W: Write + Seek {
+
+    /// Write the offset tables of all chunks that have been written so far,
+    /// leaving the offsets of the remaining chunks zero, and flush the byte writer.
+    /// If writing is interrupted after a checkpoint, for example because the process crashes,
+    /// the incomplete file can still be read without being pedantic,
+    /// as the reader skips all chunks with a zero offset.
+    /// Afterwards, continues writing chunks at the end of the file.
+    pub fn checkpoint(&mut self) -> UnitResult {
+        let end_byte = self.byte_writer.byte_position();
+        self.byte_writer.seek_write_to_written(self.chunk_indices_byte_location.start)?;
+
+        for table in &self.chunk_indices_increasing_y {
+            u64::write_slice(&mut self.byte_writer, table.as_slice())?;
+        }
+
+        self.byte_writer.seek_write_to_written(end_byte)?;
+        self.byte_writer.flush()?;
         Ok(())
     }
 }
@@ -152,7 +762,9 @@ impl<W> ChunkWriter<W> where W: Write + Seek {
     // -- the following functions are private, because they must be called in a strict order --
 
     /// Writes the meta data and zeroed offset tables as a placeholder.
-    fn new_for_buffered(buffered_byte_writer: W, headers: Headers, pedantic: bool) -> Result<(MetaData, Self)> {
+    fn new_for_buffered(buffered_byte_writer: W, mut headers: Headers, pedantic: bool) -> Result<(MetaData, Self)> {
+        MetaData::complete_required_attributes(&mut headers);
+
         let mut write = Tracking::new(buffered_byte_writer);
         let requirements = MetaData::write_validating_to_buffered(&mut write, headers.as_slice(), pedantic)?;
 
@@ -169,7 +781,7 @@ impl<W> ChunkWriter<W> where W: Write + Seek {
         let offset_table_size: usize = headers.iter().map(|header| header.chunk_count).sum();
 
         let offset_table_start_byte = write.byte_position();
-        let offset_table_end_byte = write.byte_position() + offset_table_size * u64::BYTE_SIZE;
+        let offset_table_end_byte = write.byte_position() + usize_to_u64(offset_table_size * u64::BYTE_SIZE);
 
         // skip offset tables, filling with 0, will be updated after the last chunk has been written
         write.seek_write_to(offset_table_end_byte)?;
@@ -178,6 +790,7 @@ impl<W> ChunkWriter<W> where W: Write + Seek {
         let chunk_indices_increasing_y = headers.iter()
             .map(|header| vec![0_u64; header.chunk_count]).collect();
 
+        let line_orders = headers.iter().map(|header| header.line_order).collect();
         let meta_data = MetaData { requirements, headers };
 
         Ok((meta_data, ChunkWriter {
@@ -186,6 +799,11 @@ impl<W> ChunkWriter<W> where W: Write + Seek {
             chunk_count: offset_table_size,
             chunk_indices_byte_location: offset_table_start_byte .. offset_table_end_byte,
             chunk_indices_increasing_y,
+            written_chunk_count: 0,
+            chunk_byte_counts_per_layer: vec![0; header_count],
+            checkpoint_interval: None,
+            chunk_counts_per_layer: vec![0; header_count],
+            line_orders,
         }))
     }
 
@@ -210,12 +828,186 @@ impl<W> ChunkWriter<W> where W: Write + Seek {
 
 }
 
+/// Can consume compressed pixel chunks, writing them to a byte destination that cannot seek.
+/// Obtained from `write_chunks_with_unseekable`, which uses this writer twice:
+/// first to measure the byte size of each chunk, and then to write the chunks in the order of the file.
+#[derive(Debug)]
+#[must_use]
+pub struct UnseekableChunkWriter<W> {
+    header_count: usize,
+    byte_writer: W,
+
+    /// For each header, the position in the file of each chunk, in increasing y order.
+    chunk_file_positions: Vec<Vec<usize>>,
+
+    /// The byte size of each chunk, by position in the file.
+    chunk_byte_sizes: Vec<Option<u64>>,
+
+    /// Whether the chunk sizes have been measured, and the chunks are written now.
+    streaming: Option<StreamingChunks>,
+}
+
+/// The chunks that have not been written yet in the second pass of the unseekable writer.
+#[derive(Debug)]
+struct StreamingChunks {
+    unwritten_chunk_positions: Peekable<std::ops::Range<usize>>,
+    pending_chunks: BTreeMap<usize, Chunk>,
+}
+
+impl<W> ChunksWriter for UnseekableChunkWriter<W> where W: Write {
+
+    /// The total number of chunks that the complete file will contain.
+    fn total_chunks_count(&self) -> usize { self.chunk_byte_sizes.len() }
+
+    /// While measuring, only remembers the byte size of the chunk.
+    /// Afterwards, writes the chunk, or stashes it until all chunks before it in the file have been written.
+    /// Errors when the chunk at this index was already written,
+    /// or when the chunk does not have the same size as when it was measured.
+    fn write_chunk(&mut self, index_in_header_increasing_y: usize, chunk: Chunk) -> UnitResult {
+        let file_position = *self.chunk_file_positions.get(chunk.layer_index)
+            .and_then(|positions| positions.get(index_in_header_increasing_y))
+            .ok_or(Error::invalid("too large chunk index"))?;
+
+        let already_written = || Error::invalid(format!("chunk at index {} is already written", index_in_header_increasing_y));
+        let mut byte_size = Tracking::new(std::io::sink());
+        chunk.write(&mut byte_size, self.header_count)?;
+        let byte_size = byte_size.byte_position();
+
+        match &mut self.streaming {
+            None => {
+                let size_slot = &mut self.chunk_byte_sizes[file_position];
+                if size_slot.is_some() { return Err(already_written()); }
+                *size_slot = Some(byte_size);
+            },
+
+            Some(streaming) => {
+                if self.chunk_byte_sizes[file_position] != Some(byte_size) {
+                    return Err(Error::invalid("chunk size differs from the previously measured size"));
+                }
+
+                let is_written = streaming.unwritten_chunk_positions.peek().map_or(true, |&next| file_position < next);
+                if is_written || streaming.pending_chunks.contains_key(&file_position) {
+                    return Err(already_written());
+                }
+
+                streaming.pending_chunks.insert(file_position, chunk);
+
+                // write all pending chunks that are next up in the file
+                while let Some(next_chunk) = streaming.unwritten_chunk_positions.peek().cloned()
+                    .and_then(|position| streaming.pending_chunks.remove(&position))
+                {
+                    next_chunk.write(&mut self.byte_writer, self.header_count)?;
+                    streaming.unwritten_chunk_positions.next().expect("peeked chunk position is missing");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<W> UnseekableChunkWriter<W> where W: Write {
+    // -- the following functions are private, because they must be called in a strict order --
+
+    /// Prepare measuring the chunks. Does not write anything yet.
+    fn new(byte_writer: W, headers: &[Header]) -> Self {
+        let mut chunk_file_positions: Vec<Vec<usize>> = headers.iter()
+            .map(|header| vec![0; header.chunk_count]).collect();
+
+        // the chunks are written in the same order as the seeking writer would write sequentially compressed blocks
+        let mut chunk_count = 0;
+        for (index_in_header, block_index) in crate::block::enumerate_ordered_header_block_indices(headers) {
+            chunk_file_positions[block_index.layer][index_in_header] = chunk_count;
+            chunk_count += 1;
+        }
+
+        UnseekableChunkWriter {
+            header_count: headers.len(),
+            chunk_byte_sizes: vec![None; chunk_count],
+            chunk_file_positions,
+            streaming: None,
+            byte_writer,
+        }
+    }
+
+    /// Whether the chunks are currently only measured, instead of being written.
+    /// When measuring, the chunks will be requested a second time afterwards.
+    pub fn is_measuring_chunk_sizes(&self) -> bool {
+        self.streaming.is_none()
+    }
+
+    /// Write the meta data and the offset tables computed from the measured chunk sizes.
+    fn write_meta_data(&mut self, meta_data_bytes: &[u8]) -> UnitResult {
+        debug_assert!(self.is_measuring_chunk_sizes(), "meta data has already been written");
+
+        let chunk_byte_sizes = self.chunk_byte_sizes.iter()
+            .map(|size| size.ok_or(Error::invalid("some chunks are not written yet")))
+            .collect::<Result<Vec<u64>>>()?;
+
+        let offset_table_size = usize_to_u64(chunk_byte_sizes.len() * u64::BYTE_SIZE);
+        let mut chunk_start_byte = usize_to_u64(meta_data_bytes.len()) + offset_table_size;
+
+        let mut chunk_offsets_by_file_position = Vec::with_capacity(chunk_byte_sizes.len());
+        for chunk_byte_size in chunk_byte_sizes {
+            chunk_offsets_by_file_position.push(chunk_start_byte);
+            chunk_start_byte += chunk_byte_size;
+        }
+
+        self.byte_writer.write_all(meta_data_bytes)?;
+
+        for file_positions in &self.chunk_file_positions {
+            let table: Vec<u64> = file_positions.iter()
+                .map(|&position| chunk_offsets_by_file_position[position]).collect();
+
+            u64::write_slice(&mut self.byte_writer, table.as_slice())?;
+        }
+
+        self.streaming = Some(StreamingChunks {
+            unwritten_chunk_positions: (0 .. self.chunk_byte_sizes.len()).peekable(),
+            pending_chunks: BTreeMap::new(),
+        });
+
+        Ok(())
+    }
+
+    /// Check that all chunks have been written, and flush the byte writer.
+    fn complete_chunks(mut self) -> UnitResult {
+        let streaming = self.streaming.as_mut().expect("chunks are completed before measuring them");
+
+        if streaming.unwritten_chunk_positions.peek().is_some() {
+            return Err(Error::invalid("some chunks are not written yet"))
+        }
+
+        self.byte_writer.flush()?; // make sure we catch all (possibly delayed) io errors before returning
+        Ok(())
+    }
+}
+
+
+impl<'w, W, F> OnProgressChunkWriter<'w, W, F> {
+
+    /// Where the chunks will be written to. Use this to inspect the written byte count while writing.
+    pub fn inner_chunks_writer(&self) -> &W { self.chunk_writer }
+
+    /// The number of chunks that have been written through this writer so far.
+    pub fn written_chunk_count(&self) -> usize { self.written_chunks }
+}
+
+impl<'w, W, F> OnBlockProgressChunkWriter<'w, W, F> {
+
+    /// Where the chunks will be written to. Use this to inspect the written byte count while writing.
+    pub fn inner_chunks_writer(&self) -> &W { self.chunk_writer }
+}
 
 impl<'w, W, F> ChunksWriter for OnProgressChunkWriter<'w, W, F> where W: 'w + ChunksWriter, F: FnMut(f64) {
     fn total_chunks_count(&self) -> usize {
         self.chunk_writer.total_chunks_count()
     }
 
+    fn byte_position(&self) -> Option<u64> {
+        self.chunk_writer.byte_position()
+    }
+
     fn write_chunk(&mut self, index_in_header_increasing_y: usize, chunk: Chunk) -> UnitResult {
         let total_chunks = self.total_chunks_count();
         let on_progress = &mut self.on_progress;
@@ -237,15 +1029,67 @@ impl<'w, W, F> ChunksWriter for OnProgressChunkWriter<'w, W, F> where W: 'w + Ch
     }
 }
 
+impl<'w, W, F> ChunksWriter for OnBlockProgressChunkWriter<'w, W, F> where W: 'w + ChunksWriter, F: FnMut(ChunkWriteProgress) {
+    fn total_chunks_count(&self) -> usize {
+        self.chunk_writer.total_chunks_count()
+    }
+
+    fn byte_position(&self) -> Option<u64> {
+        self.chunk_writer.byte_position()
+    }
+
+    fn write_chunk(&mut self, index_in_header_increasing_y: usize, chunk: Chunk) -> UnitResult {
+        let layer_index = chunk.layer_index;
+        let compressed_byte_size = chunk.compressed_byte_size();
+
+        self.chunk_writer.write_chunk(index_in_header_increasing_y, chunk)?;
+
+        self.written_chunks += 1;
+        self.compressed_bytes += usize_to_u64(compressed_byte_size);
+
+        let progress = ChunkWriteProgress {
+            written_chunk_count: self.written_chunks,
+            total_chunk_count: self.total_chunks_count(),
+            compressed_byte_count: self.compressed_bytes,
+            chunk_byte_size: compressed_byte_size,
+            written_byte_count: self.chunk_writer.byte_position(),
+            layer_index, index_in_header_increasing_y,
+        };
+
+        let on_progress = &mut self.on_progress;
+        on_progress(progress);
+        Ok(())
+    }
+}
+
 
 /// Write blocks that appear in any order and reorder them before writing.
 #[derive(Debug)]
 #[must_use]
 pub struct SortedBlocksWriter<'w, W> {
     chunk_writer: &'w mut W,
-    pending_chunks: BTreeMap<usize, (usize, Chunk)>,
+    pending_chunks: BTreeMap<usize, (usize, PendingChunk)>,
     unwritten_chunk_indices: Peekable<std::ops::Range<usize>>,
     requires_sorting: bool, // using this instead of Option, because of borrowing
+    spill: Option<ChunkSpill>,
+}
+
+/// A chunk that waits for the chunks before it to be written.
+#[derive(Debug)]
+enum PendingChunk {
+    InMemory(Chunk),
+    Spilled { byte_offset: u64, byte_size: usize },
+}
+
+/// Moves pending chunks to a temporary file when they would occupy too much memory.
+#[derive(Debug)]
+struct ChunkSpill {
+    meta_data: MetaData, // required to read the chunks back
+    directory: PathBuf,
+    max_in_memory_bytes: usize,
+    in_memory_bytes: usize,
+    file: Option<TemporaryFile>, // only created when the first chunk is spilled
+    file_byte_size: u64,
 }
 
 
@@ -262,7 +1106,30 @@ impl<'w, W> SortedBlocksWriter<'w, W> where W: ChunksWriter {
             pending_chunks: BTreeMap::new(),
             unwritten_chunk_indices: (0 .. total_chunk_count).peekable(),
             requires_sorting,
-            chunk_writer
+            chunk_writer,
+            spill: None,
+        }
+    }
+
+    /// New sorting writer that moves pending chunks to a temporary file in the specified directory
+    /// as soon as the pending chunks in memory would exceed the specified number of compressed bytes.
+    /// Spilled chunks are read back when they can be written. The temporary file
+    /// is only created if a chunk is spilled, and is deleted when this writer is dropped.
+    pub fn with_spill_file(
+        meta_data: &MetaData, chunk_writer: &'w mut W,
+        spill_directory: impl Into<PathBuf>, max_pending_bytes_in_memory: usize
+    ) -> SortedBlocksWriter<'w, W> {
+        SortedBlocksWriter {
+            spill: Some(ChunkSpill {
+                meta_data: meta_data.clone(),
+                directory: spill_directory.into(),
+                max_in_memory_bytes: max_pending_bytes_in_memory,
+                in_memory_bytes: 0,
+                file: None,
+                file_byte_size: 0,
+            }),
+
+            .. Self::new(meta_data, chunk_writer)
         }
     }
 
@@ -282,6 +1149,14 @@ impl<'w, W> SortedBlocksWriter<'w, W> where W: ChunksWriter {
                 .unwritten_chunk_indices.peek().cloned()
                 .and_then(|id| self.pending_chunks.remove(&id))
             {
+                let next_chunk = match self.spill {
+                    Some(ref mut spill) => spill.restore(next_chunk)?,
+                    None => match next_chunk {
+                        PendingChunk::InMemory(chunk) => chunk,
+                        PendingChunk::Spilled { .. } => unreachable!("chunk spilled without spill file"),
+                    },
+                };
+
                 self.chunk_writer.write_chunk(next_chunk_y_index, next_chunk)?;
                 self.unwritten_chunk_indices.next().expect("peeked chunk index is missing");
             }
@@ -291,7 +1166,12 @@ impl<'w, W> SortedBlocksWriter<'w, W> where W: ChunksWriter {
             // the argument block is not to be written now,
             // and all the pending blocks are not next up either,
             // so just stash this block
-            self.pending_chunks.insert(chunk_index_in_file, (chunk_y_index, chunk));
+            let pending_chunk = match self.spill {
+                Some(ref mut spill) => spill.stash(chunk)?,
+                None => PendingChunk::InMemory(chunk),
+            };
+
+            self.pending_chunks.insert(chunk_index_in_file, (chunk_y_index, pending_chunk));
         }
 
         Ok(())
@@ -301,8 +1181,162 @@ impl<'w, W> SortedBlocksWriter<'w, W> where W: ChunksWriter {
     pub fn inner_chunks_writer(&self) -> &W {
         &self.chunk_writer
     }
+
+    /// The number of chunks that are stashed, waiting for the chunks before them to be written.
+    /// Includes the chunks that have been spilled to the temporary file.
+    pub fn pending_chunk_count(&self) -> usize {
+        self.pending_chunks.len()
+    }
+
+    /// The number of stashed chunks that currently reside in the temporary file instead of in memory.
+    pub fn spilled_chunk_count(&self) -> usize {
+        self.pending_chunks.values()
+            .filter(|(_, chunk)| matches!(chunk, PendingChunk::Spilled { .. }))
+            .count()
+    }
 }
 
+impl ChunkSpill {
+
+    /// Keep the chunk in memory if there is enough room, or append it to the temporary file otherwise.
+    fn stash(&mut self, chunk: Chunk) -> Result<PendingChunk> {
+        let byte_size = chunk.compressed_byte_size();
+
+        if self.in_memory_bytes + byte_size <= self.max_in_memory_bytes {
+            self.in_memory_bytes += byte_size;
+            return Ok(PendingChunk::InMemory(chunk));
+        }
+
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => self.file.insert(TemporaryFile::create_in(&self.directory, "exr-chunks")?),
+        };
+
+        let mut bytes = Vec::with_capacity(byte_size + 64);
+        chunk.write(&mut bytes, self.meta_data.headers.len())?;
+
+        // chunks are only appended, space of restored chunks is not reused
+        let file = file.file();
+        file.seek(SeekFrom::Start(self.file_byte_size))?;
+        file.write_all(&bytes)?;
+
+        let byte_offset = self.file_byte_size;
+        self.file_byte_size += usize_to_u64(bytes.len());
+        Ok(PendingChunk::Spilled { byte_offset, byte_size: bytes.len() })
+    }
+
+    /// Load the chunk from the temporary file, if it was spilled.
+    fn restore(&mut self, chunk: PendingChunk) -> Result<Chunk> {
+        match chunk {
+            PendingChunk::InMemory(chunk) => {
+                self.in_memory_bytes -= chunk.compressed_byte_size();
+                Ok(chunk)
+            },
+
+            PendingChunk::Spilled { byte_offset, byte_size } => {
+                let file = self.file.as_mut().expect("chunk spilled without spill file").file();
+                file.seek(SeekFrom::Start(byte_offset))?;
+
+                let mut bytes = vec![0_u8; byte_size];
+                file.read_exact(&mut bytes)?;
+
+                Chunk::read(&mut bytes.as_slice(), &self.meta_data)
+            },
+        }
+    }
+}
+
+
+
+/// Checks that the blocks are produced in the order of `MetaData::enumerate_ordered_header_block_indices`.
+/// Stops at the first block that is missing, duplicated, or unexpected.
+/// Obtained from `MetaData::validate_ordered_blocks`.
+#[derive(Debug)]
+#[must_use]
+pub struct ValidatedBlocks<'h, I> {
+    headers: &'h [Header],
+    blocks: I,
+    expected_blocks: Peekable<std::vec::IntoIter<(usize, BlockIndex)>>,
+    produced_blocks: HashSet<BlockIndex>,
+    error: Option<Error>,
+}
+
+impl<'h, I> ValidatedBlocks<'h, I> where I: Iterator<Item=(usize, UncompressedBlock)> {
+
+    /// Check the blocks against the blocks of the specified headers.
+    pub fn new(headers: &'h [Header], blocks: I) -> Self {
+        ValidatedBlocks {
+            expected_blocks: crate::block::enumerate_ordered_header_block_indices(headers)
+                .collect::<Vec<_>>().into_iter().peekable(),
+
+            produced_blocks: HashSet::new(),
+            error: None,
+            headers, blocks,
+        }
+    }
+
+    /// Returns an error if a block was missing, duplicated, or unexpected,
+    /// or if the iterator has not produced all blocks of the image.
+    pub fn finish(mut self) -> UnitResult {
+        if let Some(error) = self.error.take() { return Err(error); }
+
+        match self.expected_blocks.next() {
+            Some((_, missing)) => Err(self.block_error("missing", missing)),
+            None => Ok(()),
+        }
+    }
+
+    fn validate(&mut self, index_in_header_increasing_y: usize, block: BlockIndex) -> UnitResult {
+        let is_duplicate = self.produced_blocks.insert(block).not();
+        if is_duplicate { return Err(self.block_error("duplicate", block)); }
+
+        match self.expected_blocks.next() {
+            None => Err(self.block_error("unexpected", block)),
+            Some((_, expected)) if expected != block => Err(self.block_error("missing", expected)),
+
+            Some((expected_index, _)) if expected_index != index_in_header_increasing_y => Err(Error::invalid(format!(
+                "{} is at index {} instead of index {}",
+                self.describe_block(block), index_in_header_increasing_y, expected_index
+            ))),
+
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn block_error(&self, problem: &str, block: BlockIndex) -> Error {
+        Error::invalid(format!("{} {}", problem, self.describe_block(block)))
+    }
+
+    fn describe_block(&self, block: BlockIndex) -> String {
+        let tile = match self.headers.get(block.layer) {
+            Some(header) => block.pixel_position / header.max_block_pixel_size(),
+            None => return format!("block of non-existing layer {}", block.layer),
+        };
+
+        format!(
+            "block of layer {} at tile ({}, {}) of level ({}, {})",
+            block.layer, tile.x(), tile.y(), block.level.x(), block.level.y()
+        )
+    }
+}
+
+impl<'h, I> Iterator for ValidatedBlocks<'h, I> where I: Iterator<Item=(usize, UncompressedBlock)> {
+    type Item = (usize, UncompressedBlock);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.error.is_some() { return None; }
+
+        let (index_in_header_increasing_y, block) = self.blocks.next()?;
+
+        match self.validate(index_in_header_increasing_y, block.index) {
+            Ok(()) => Some((index_in_header_increasing_y, block)),
+            Err(error) => {
+                self.error = Some(error);
+                None
+            }
+        }
+    }
+}
 
 
 /// Compress blocks to a chunk writer in this thread.
@@ -311,22 +1345,28 @@ impl<'w, W> SortedBlocksWriter<'w, W> where W: ChunksWriter {
 pub struct SequentialBlocksCompressor<'w, W> {
     meta: &'w MetaData,
     chunks_writer: &'w mut W,
+    verify_chunks: bool,
 }
 
 impl<'w, W> SequentialBlocksCompressor<'w, W> where W: 'w + ChunksWriter {
 
     /// New blocks writer.
-    pub fn new(meta: &'w MetaData, chunks_writer: &'w mut W) -> Self { Self { meta, chunks_writer, } }
+    pub fn new(meta: &'w MetaData, chunks_writer: &'w mut W) -> Self { Self { meta, chunks_writer, verify_chunks: false } }
+
+    /// Decompress each chunk right after compressing it, and return an error
+    /// if the pixels differ from the original block. Compression methods that may lose data are not verified.
+    /// This roughly doubles the time spent compressing. Disabled by default.
+    pub fn verify_written_chunks(self, verify: bool) -> Self { Self { verify_chunks: verify, .. self } }
 
     /// This is where the compressed blocks are written to.
     pub fn inner_chunks_writer(&'w self) -> &'w W { self.chunks_writer }
 
     /// Compress a single block immediately. The index of the block must be in increasing line order.
     pub fn compress_block(&mut self, index_in_header_increasing_y: usize, block: UncompressedBlock) -> UnitResult {
-        self.chunks_writer.write_chunk(
-            index_in_header_increasing_y,
-            block.compress_to_chunk(&self.meta.headers)?
-        )
+        let chunk = if self.verify_chunks { block.compress_to_chunk_verified(&self.meta.headers)? }
+            else { block.compress_to_chunk(&self.meta.headers)? };
+
+        self.chunks_writer.write_chunk(index_in_header_increasing_y, chunk)
     }
 }
 
@@ -334,17 +1374,34 @@ impl<'w, W> SequentialBlocksCompressor<'w, W> where W: 'w + ChunksWriter {
 #[derive(Debug)]
 #[must_use]
 pub struct ParallelBlocksCompressor<'w, W> {
-    meta: &'w MetaData,
+    headers: Arc<Headers>, // shared with the compressor threads, instead of cloning the meta data for each block
     sorted_writer: SortedBlocksWriter<'w, W>,
 
     sender: flume::Sender<Result<(usize, usize, Chunk)>>,
     receiver: flume::Receiver<Result<(usize, usize, Chunk)>>,
-    pool: rayon_core::ThreadPool,
+    pool: CompressionPool<'w>,
 
     currently_compressing_count: usize,
     written_chunk_count: usize, // used to check for last chunk
-    max_threads: usize,
+    max_pending_chunks: usize,
     next_incoming_chunk_index: usize, // used to remember original chunk order
+    verify_chunks: bool,
+}
+
+/// The thread pool is either created by the compressor or supplied by the user.
+#[derive(Debug)]
+enum CompressionPool<'p> {
+    Owned(ThreadPool),
+    Borrowed(&'p ThreadPool),
+}
+
+impl CompressionPool<'_> {
+    fn get(&self) -> &ThreadPool {
+        match self {
+            CompressionPool::Owned(pool) => pool,
+            CompressionPool::Borrowed(pool) => pool,
+        }
+    }
 }
 
 impl<'w, W> ParallelBlocksCompressor<'w, W> where W: 'w + ChunksWriter {
@@ -365,33 +1422,79 @@ impl<'w, W> ParallelBlocksCompressor<'w, W> where W: 'w + ChunksWriter {
         -> Option<Self>
         where CreatePool: FnOnce() -> std::result::Result<ThreadPool, ThreadPoolBuildError>
     {
-        if meta.headers.iter().all(|head|head.compression == Compression::Uncompressed) {
+        if !Self::requires_compression(meta) {
             return None;
         }
 
         // in case thread pool creation fails (for example on WASM currently),
         // we revert to sequential compression
-        let pool = match try_create_thread_pool() {
-            Ok(pool) => pool,
+        match try_create_thread_pool() {
+            Ok(pool) => Some(Self::with_pool(meta, chunks_writer, CompressionPool::Owned(pool))),
 
             // TODO print warning?
-            Err(_) => return None,
-        };
+            Err(_) => None,
+        }
+    }
 
-        let max_threads = pool.current_num_threads().max(1).min(chunks_writer.total_chunks_count()) + 2; // ca one block for each thread at all times
-        let (send, recv) = flume::unbounded(); // TODO bounded channel simplifies logic?
+    /// New blocks writer that spawns its jobs on an existing thread pool.
+    /// The pool is only borrowed, so a single pool can serve many concurrent compressors.
+    /// Returns none if sequential compression should be used.
+    pub fn new_with_shared_thread_pool(meta: &'w MetaData, chunks_writer: &'w mut W, pool: &'w ThreadPool) -> Option<Self> {
+        if !Self::requires_compression(meta) {
+            return None;
+        }
+
+        Some(Self::with_pool(meta, chunks_writer, CompressionPool::Borrowed(pool)))
+    }
+
+    fn requires_compression(meta: &MetaData) -> bool {
+        meta.headers.iter().any(|head| head.compression != Compression::Uncompressed)
+    }
+
+    fn with_pool(meta: &'w MetaData, chunks_writer: &'w mut W, pool: CompressionPool<'w>) -> Self {
+        let max_pending_chunks = pool.get().current_num_threads().max(1).min(chunks_writer.total_chunks_count()) + 2; // ca one block for each thread at all times
+
+        // each compressing block sends exactly one message, and the number of compressing blocks is limited,
+        // so the channel does not need a bound, and the compressor threads will never wait for a free slot
+        let (send, recv) = flume::unbounded();
 
-        Some(Self {
+        Self {
             sorted_writer: SortedBlocksWriter::new(meta, chunks_writer),
             next_incoming_chunk_index: 0,
             currently_compressing_count: 0,
             written_chunk_count: 0,
             sender: send,
             receiver: recv,
-            max_threads,
+            headers: Arc::new(meta.headers.clone()),
+            max_pending_chunks,
             pool,
-            meta,
-        })
+            verify_chunks: false,
+        }
+    }
+
+    /// Decompress each chunk right after compressing it, on the compressor thread, and return an error
+    /// if the pixels differ from the original block. Compression methods that may lose data are not verified.
+    /// Disabled by default.
+    pub fn verify_written_chunks(self, verify: bool) -> Self { Self { verify_chunks: verify, .. self } }
+
+    /// Specify how many compressed chunks may wait to be written at the same time,
+    /// including the blocks that are still being compressed.
+    /// When this many chunks are pending, no more blocks are accepted until the next chunk can be written.
+    /// This limits the number of uncompressed blocks in memory, regardless of the line order.
+    /// By default, this is two more than the number of threads in the pool.
+    /// The value is clamped to at least one.
+    pub fn max_pending_chunks(mut self, max_pending_chunks: usize) -> Self {
+        self.max_pending_chunks = max_pending_chunks.max(1);
+        self
+    }
+
+    /// The number of chunks that are currently compressing or waiting to be written.
+    pub fn pending_chunk_count(&self) -> usize {
+        self.currently_compressing_count + self.sorted_writer.pending_chunk_count()
+    }
+
+    fn is_full(&self) -> bool {
+        self.pending_chunk_count() >= self.max_pending_chunks
     }
 
     /// This is where the compressed blocks are written to.
@@ -401,8 +1504,7 @@ impl<'w, W> ParallelBlocksCompressor<'w, W> where W: 'w + ChunksWriter {
     fn write_next_queued_chunk(&mut self) -> UnitResult {
         debug_assert!(self.currently_compressing_count > 0, "cannot wait for chunks as there are none left");
 
-        let some_compressed_chunk = self.receiver.recv()
-            .expect("cannot receive compressed block");
+        let some_compressed_chunk = self.receive_compressed_chunk();
 
         self.currently_compressing_count -= 1;
         let (chunk_file_index, chunk_y_index, chunk) = some_compressed_chunk?;
@@ -412,6 +1514,43 @@ impl<'w, W> ParallelBlocksCompressor<'w, W> where W: 'w + ChunksWriter {
         Ok(())
     }
 
+    /// Wait for the next compressed chunk.
+    fn receive_compressed_chunk(&self) -> Result<(usize, usize, Chunk)> {
+        let pool = self.pool.get();
+
+        // when called from a thread of the pool itself, blocking this thread might leave no thread to compress,
+        // so instead run the jobs of the pool in this thread until a chunk is ready
+        if pool.current_thread_index().is_some() {
+            loop {
+                if let Ok(compressed_chunk) = self.receiver.try_recv() {
+                    return compressed_chunk;
+                }
+
+                if pool.yield_now() != Some(rayon_core::Yield::Executed) {
+                    std::thread::yield_now();
+                }
+            }
+        }
+
+        // the compressor holds a sender itself, so the channel is never disconnected
+        self.receiver.recv().expect("cannot receive compressed block")
+    }
+
+    /// Compress all remaining blocks and wait until all of them have been written.
+    /// The index of the block must be in increasing line order within the header.
+    pub fn compress_all_blocks(mut self, blocks: impl Iterator<Item=(usize, UncompressedBlock)>) -> UnitResult {
+        // TODO check block order if line order is not unspecified!
+        for (index_in_header_increasing_y, block) in blocks {
+            self.add_block_to_compression_queue(index_in_header_increasing_y, block)?;
+        }
+
+        // the last block already waits for all chunks, but fewer blocks than expected may have been passed
+        self.write_all_queued_chunks()?;
+
+        // TODO debug_assert_eq!(self.is_complete());
+        Ok(())
+    }
+
     /// Wait until all currently compressing chunks in the compressor have been written.
     pub fn write_all_queued_chunks(&mut self) -> UnitResult {
         while self.currently_compressing_count > 0 {
@@ -428,18 +1567,23 @@ impl<'w, W> ParallelBlocksCompressor<'w, W> where W: 'w + ChunksWriter {
     /// Waits for a block from the queue to be written, if the queue already has enough items.
     pub fn add_block_to_compression_queue(&mut self, index_in_header_increasing_y: usize, block: UncompressedBlock) -> UnitResult {
 
-        // if pipe is full, block to wait for a slot to free up
-        if self.currently_compressing_count >= self.max_threads {
+        // if pipe is full, block to wait for a slot to free up.
+        // as blocks are added in file order, the next chunk to be written is always still compressing,
+        // so waiting for compressed chunks will eventually also write the stashed chunks
+        while self.is_full() && self.currently_compressing_count > 0 {
             self.write_next_queued_chunk()?;
         }
 
         // add the argument chunk to the compression queueue
         let index_in_file = self.next_incoming_chunk_index;
         let sender = self.sender.clone();
-        let meta = self.meta.clone();
+        let headers = self.headers.clone();
+        let verify_chunks = self.verify_chunks;
 
-        self.pool.spawn(move ||{
-            let compressed_or_err = block.compress_to_chunk(&meta.headers);
+        self.pool.get().spawn(move ||{
+            let compressed_or_err =
+                if verify_chunks { block.compress_to_chunk_verified(&headers) }
+                else { block.compress_to_chunk(&headers) };
 
             // by now, decompressing could have failed in another thread.
             // the error is then already handled, so we simply
@@ -466,3 +1610,155 @@ impl<'w, W> ParallelBlocksCompressor<'w, W> where W: 'w + ChunksWriter {
 
 
 
+
+
+
+/// Write scan line images one line after another, for example while rendering the image from top to bottom.
+/// Each block is compressed and written to the file as soon as all of its lines have been pushed,
+/// so that only one block of lines per layer is kept in memory.
+/// Call `finish` after all lines have been pushed, in order to complete the file.
+#[derive(Debug)]
+#[must_use]
+pub struct ScanLineWriter<W> {
+    meta_data: MetaData,
+    chunk_writer: ChunkWriter<W>,
+    layers: Vec<ScanLineLayer>,
+}
+
+/// The lines of the block that is currently being pushed to a layer.
+#[derive(Debug)]
+struct ScanLineLayer {
+    next_line_y: usize,
+    block_bytes: ByteVec,
+}
+
+impl<W> ScanLineWriter<W> where W: Write + Seek {
+
+    /// Write the meta data and prepare writing the scan lines.
+    /// All headers must contain scan lines in increasing or unspecified line order.
+    /// Assumes the your write destination is buffered.
+    pub fn new(buffered_write: W, headers: Headers, pedantic: bool) -> Result<Self> {
+        for header in &headers {
+            if header.blocks != BlockDescription::ScanLines {
+                return Err(Error::unsupported("scan line writer for tiled images"));
+            }
+
+            if header.line_order == LineOrder::Decreasing {
+                return Err(Error::unsupported("scan line writer with decreasing line order"));
+            }
+
+            if header.channels.list.iter().any(|channel| channel.sampling != Vec2(1, 1)) {
+                return Err(Error::unsupported("scan line writer for sub-sampled channels"));
+            }
+        }
+
+        let (meta_data, chunk_writer) = ChunkWriter::new_for_buffered(buffered_write, headers, pedantic)?;
+        let layers = meta_data.headers.iter()
+            .map(|_| ScanLineLayer { next_line_y: 0, block_bytes: ByteVec::new() })
+            .collect();
+
+        Ok(ScanLineWriter { meta_data, chunk_writer, layers })
+    }
+
+    /// The meta data that is written to the file.
+    pub fn meta_data(&self) -> &MetaData { &self.meta_data }
+
+    /// Add the next lines of a layer. The lines are relative to the data window of the layer,
+    /// and must be pushed in increasing order, without gaps and without repetition.
+    /// For each channel in the header, in the same order, a slice with all samples of these lines is required.
+    /// Each sample is converted to the sample type of its channel.
+    /// Compresses and writes every block that is completed by these lines.
+    pub fn push_lines(&mut self, layer_index: usize, y_range: std::ops::Range<usize>, samples_per_channel: &[&[f32]]) -> UnitResult {
+        let header = self.meta_data.headers.get(layer_index)
+            .ok_or(Error::invalid("layer index"))?;
+
+        let layer = &mut self.layers[layer_index];
+        let Vec2(width, height) = header.layer_size;
+
+        if y_range.start != layer.next_line_y {
+            return Err(Error::invalid(format!(
+                "scan line {} was pushed, but the next line must be {}",
+                y_range.start, layer.next_line_y
+            )));
+        }
+
+        if y_range.end > height {
+            return Err(Error::invalid("scan line range exceeds the layer"));
+        }
+
+        let line_count = y_range.len();
+        if samples_per_channel.len() != header.channels.list.len()
+            || samples_per_channel.iter().any(|samples| samples.len() != width * line_count)
+        {
+            return Err(Error::invalid("scan line sample count does not match the layer"));
+        }
+
+        let lines_per_block = header.compression.scan_lines_per_block();
+
+        for (line_index_in_range, y) in y_range.enumerate() {
+            let block_y_index = y / lines_per_block;
+            let block_bounds = header.get_absolute_block_pixel_coordinates(TileCoordinates {
+                tile_index: Vec2(0, block_y_index), level_index: Vec2(0, 0)
+            })?;
+
+            let block_position = block_bounds.position.to_usize("block position")?;
+            let block_end_y = block_position.y() + block_bounds.size.height();
+
+            if layer.block_bytes.is_empty() {
+                layer.block_bytes = vec![0; block_bounds.size.area() * header.channels.bytes_per_pixel];
+            }
+
+            // lines are stored interleaved: for each line, all channels of this line follow each other
+            let mut line_start_byte = (y - block_position.y()) * width * header.channels.bytes_per_pixel;
+            let channels = header.channels.list.iter().zip(samples_per_channel);
+
+            for (channel_index, (channel, samples)) in channels.enumerate() {
+                let line_byte_size = width * channel.sample_type.bytes_per_sample();
+
+                let line = LineRefMut {
+                    value: &mut layer.block_bytes[line_start_byte .. line_start_byte + line_byte_size],
+                    location: LineIndex {
+                        layer: layer_index, channel: channel_index, level: Vec2(0, 0),
+                        position: Vec2(0, y), sample_count: width,
+                    },
+                };
+
+                let line_samples = &samples[line_index_in_range * width .. (line_index_in_range + 1) * width];
+                line.write_samples_from_f32(channel.sample_type, line_samples)?;
+                line_start_byte += line_byte_size;
+            }
+
+            layer.next_line_y = y + 1;
+
+            // the block is complete, so compress and write it now
+            if layer.next_line_y == block_end_y {
+                let block = UncompressedBlock {
+                    index: BlockIndex {
+                        layer: layer_index, level: Vec2(0, 0),
+                        pixel_position: block_position, pixel_size: block_bounds.size,
+                    },
+
+                    data: std::mem::take(&mut layer.block_bytes),
+                };
+
+                let chunk = block.compress_to_chunk(&self.meta_data.headers)?;
+                self.chunk_writer.write_chunk(block_y_index, chunk)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the offset tables and flush the byte writer.
+    /// Returns an error if some lines have not been pushed yet.
+    pub fn finish(self) -> UnitResult {
+        let all_lines_pushed = self.layers.iter().zip(&self.meta_data.headers)
+            .all(|(layer, header)| layer.next_line_y == header.layer_size.height());
+
+        if !all_lines_pushed {
+            return Err(Error::invalid("some scan lines have not been pushed yet"));
+        }
+
+        self.chunk_writer.complete_meta_data()
+    }
+}
@@ -1,6 +1,6 @@
 mod table;
 
-use crate::compression::{mod_p, ByteVec};
+use crate::compression::{mod_p, ByteVec, Bytes};
 use crate::error::usize_to_i32;
 use crate::io::Data;
 use crate::meta::attribute::ChannelList;
@@ -234,7 +234,6 @@ struct ChannelData {
     y_sampling: usize,
     sample_type: SampleType,
     quantize_linearly: bool,
-    samples_per_pixel: usize,
 }
 
 // TODO: Unsafe seems to be required to efficiently copy whole slice of u16 ot u8. For now, we use
@@ -258,14 +257,14 @@ fn cpy_u8(src: &[u16], src_i: usize, dst: &mut [u8], dst_i: usize, n: usize) {
 
 pub fn decompress(
     channels: &ChannelList,
-    compressed: ByteVec,
+    compressed: Bytes<'_>,
     rectangle: IntegerBounds,
     expected_byte_size: usize,
     _pedantic: bool,
 ) -> Result<ByteVec> {
     debug_assert_eq!(
         expected_byte_size,
-        rectangle.size.area() * channels.bytes_per_pixel,
+        channels.byte_size_of_section(rectangle),
         "expected byte size does not match header" // TODO compute instead of passing argument?
     );
 
@@ -287,12 +286,9 @@ pub fn decompress(
             y_sampling: channel.sampling.y(),
             sample_type: channel.sample_type,
             quantize_linearly: channel.quantize_linearly,
-            samples_per_pixel: channel.sampling.area(),
         };
 
-        tmp_read_index += channel.resolution.area()
-            * channel.samples_per_pixel
-            * channel.sample_type.bytes_per_sample();
+        tmp_read_index += channel.resolution.area() * channel.sample_type.bytes_per_sample();
 
         channel_data.push(channel);
     }
@@ -311,7 +307,7 @@ pub fn decompress(
         debug_assert_eq!(remaining, compressed.len()-in_i);
 
         // Compute information for current channel.
-        let sample_count = channel.resolution.area() * channel.samples_per_pixel;
+        let sample_count = channel.resolution.area();
         let byte_count = sample_count * channel.sample_type.bytes_per_sample();
 
         // Sample types that does not support B44 compression (u32 and f32) are raw copied.
@@ -340,8 +336,8 @@ pub fn decompress(
         // Increase buffer to get new uncompressed datas.
         tmp.resize(tmp.len() + byte_count, 0);
 
-        let x_sample_count = channel.resolution.x() * channel.samples_per_pixel;
-        let y_sample_count = channel.resolution.y() * channel.samples_per_pixel;
+        let x_sample_count = channel.resolution.x();
+        let y_sample_count = channel.resolution.y();
 
         let bytes_per_sample = size_of::<u16>();
 
@@ -441,7 +437,7 @@ pub fn decompress(
             }
 
             // Find data location in temporary buffer.
-            let x_sample_count = channel.resolution.x() * channel.samples_per_pixel;
+            let x_sample_count = channel.resolution.x();
             let bytes_per_line = x_sample_count * channel.sample_type.bytes_per_sample();
             let next_tmp_end_index = channel.tmp_end_index + bytes_per_line;
             let channel_bytes = &tmp[channel.tmp_end_index..next_tmp_end_index];
@@ -514,7 +510,6 @@ pub fn compress(
             resolution: number_samples,
             sample_type: channel.sample_type,
             quantize_linearly: channel.quantize_linearly,
-            samples_per_pixel: channel.sampling.area(),
         };
 
         tmp_end_index += byte_count;
@@ -533,7 +528,7 @@ pub fn compress(
                 continue;
             }
 
-            let x_sample_count = channel.resolution.x() * channel.samples_per_pixel;
+            let x_sample_count = channel.resolution.x();
             let bytes_per_line = x_sample_count * channel.sample_type.bytes_per_sample();
             let next_tmp_end_index = channel.tmp_end_index + bytes_per_line;
             let target = &mut tmp[channel.tmp_end_index..next_tmp_end_index];
@@ -587,8 +582,8 @@ pub fn compress(
         debug_assert_eq!(channel.sample_type, SampleType::F16);
         debug_assert_eq!(channel.sample_type.bytes_per_sample(), size_of::<u16>());
 
-        let x_sample_count = channel.resolution.x() * channel.samples_per_pixel;
-        let y_sample_count = channel.resolution.y() * channel.samples_per_pixel;
+        let x_sample_count = channel.resolution.x();
+        let y_sample_count = channel.resolution.y();
 
         let x_byte_count = x_sample_count * size_of::<u16>();
         let cd_start = channel.tmp_start_index;
@@ -722,7 +717,7 @@ mod test {
         let compressed = b44::compress(&channels, pixel_bytes.clone(), rectangle, true).unwrap();
 
         let decompressed =
-            b44::decompress(&channels, compressed.clone(), rectangle, pixel_bytes.len(), true).unwrap();
+            b44::decompress(&channels, &compressed, rectangle, pixel_bytes.len(), true).unwrap();
 
         assert_eq!(decompressed.len(), pixel_bytes.len());
 
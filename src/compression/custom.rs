@@ -0,0 +1,94 @@
+//! Custom compression methods, which are __not part of the OpenEXR standard__.
+//! Files that use a custom compression can only be read by software that knows the codec.
+
+use super::{ByteVec, Bytes};
+use crate::meta::attribute::IntegerBounds;
+use crate::meta::header::Header;
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+
+/// The largest compression id used by the standard compression methods.
+/// Custom codecs must use an id above this.
+pub(crate) const MAX_RESERVED_ID: u8 = 9;
+
+/// Compresses and decompresses the pixels of a single block,
+/// for experimenting with compression methods that are not part of the OpenEXR standard.
+/// Register the codec in a `CodecRegistry` with an id above 9 and use `Compression::Custom(id)`.
+///
+/// __Files written with a custom codec are not valid OpenEXR files.__
+/// No other software will be able to read their pixels.
+pub trait BlockCodec: Send + Sync {
+
+    /// Compress the pixels of a block. The bytes are laid out as in an uncompressed file:
+    /// line by line, each line containing the samples of each channel, in little endian byte order.
+    /// If the result is not smaller than the uncompressed bytes, the uncompressed bytes are stored instead,
+    /// and will not be passed to `decompress` later.
+    fn compress(&self, header: &Header, uncompressed_le: ByteVec, pixel_section: IntegerBounds) -> Result<ByteVec>;
+
+    /// Decompress the pixels of a block, previously compressed with `compress`.
+    /// Must return exactly `expected_byte_size` bytes, in the same layout that was passed to `compress`.
+    fn decompress(
+        &self, header: &Header, compressed: Bytes<'_>, pixel_section: IntegerBounds,
+        expected_byte_size: usize, pedantic: bool
+    ) -> Result<ByteVec>;
+}
+
+/// The codecs used for `Compression::Custom`, looked up by the compression id stored in the file.
+/// Without a codec for the id of a layer, its pixels cannot be read or written.
+/// Cloning a registry does not clone the codecs.
+#[derive(Clone, Default)]
+pub struct CodecRegistry {
+    codecs: HashMap<u8, Arc<dyn BlockCodec>>,
+}
+
+impl CodecRegistry {
+
+    /// Create a registry that does not contain any codecs.
+    pub fn new() -> Self { Self::default() }
+
+    /// Add a codec for the specified compression id, replacing any previous codec with that id.
+    /// Ids from 0 to 9 are used by the standard compression methods and cannot be used for custom codecs,
+    /// so an error is returned for these ids.
+    pub fn with_codec(mut self, id: u8, codec: impl BlockCodec + 'static) -> Result<Self> {
+        if id <= MAX_RESERVED_ID {
+            return Err(Error::invalid(format!(
+                "compression id {} is reserved for standard compression methods, use an id above {}",
+                id, MAX_RESERVED_ID
+            )));
+        }
+
+        self.codecs.insert(id, Arc::new(codec));
+        Ok(self)
+    }
+
+    /// The codec for the specified compression id, if any.
+    pub fn get(&self, id: u8) -> Option<&dyn BlockCodec> {
+        self.codecs.get(&id).map(|codec| codec.as_ref())
+    }
+
+    /// Whether no codecs have been added.
+    pub fn is_empty(&self) -> bool { self.codecs.is_empty() }
+
+    fn sorted_ids(&self) -> Vec<u8> {
+        let mut ids: Vec<u8> = self.codecs.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+impl std::fmt::Debug for CodecRegistry {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.debug_tuple("CodecRegistry").field(&self.sorted_ids()).finish()
+    }
+}
+
+// codecs are compared by identity, as they cannot be compared by value
+impl PartialEq for CodecRegistry {
+    fn eq(&self, other: &Self) -> bool {
+        self.codecs.len() == other.codecs.len() && self.codecs.iter().all(|(id, codec)|
+            other.codecs.get(id).map_or(false, |other_codec| Arc::ptr_eq(codec, other_codec))
+        )
+    }
+}
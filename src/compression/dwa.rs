@@ -0,0 +1,1067 @@
+//! The DWAA and DWAB compression methods are lossy, DCT based compression methods,
+//! similar to JPEG.
+//! Color channels are converted to a perceptual space, transformed to the frequency domain
+//! in blocks of 8x8 pixels, and quantized. All other channels are compressed losslessly.
+// inspired by https://github.com/AcademySoftwareFoundation/openexr/blob/3bd93f85bcb74c77255f28cdbb913fdbfbb39dfe/OpenEXR/IlmImf/ImfDwaCompressor.cpp
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use half::f16;
+use crate::compression::{ByteVec, Bytes, mod_p};
+use crate::compression::piz::huffman;
+use crate::error::{Error, Result, UnitResult, usize_to_i32, usize_to_u64};
+use crate::io::Data;
+use crate::math::Vec2;
+use crate::meta::attribute::{ChannelList, IntegerBounds, SampleType};
+
+
+/// The number of `u64` values at the start of the compressed data.
+const SIZE_COUNT: usize = 11;
+
+/// The version of the compressed data that is written. Stores the rules in the compressed data.
+const VERSION: u64 = 2;
+
+/// The compression level that is used if the header does not specify a level, as in the reference implementation.
+const DEFAULT_COMPRESSION_LEVEL: f32 = 45.0;
+
+/// The maximum byte length of the channel name suffix in a rule, including the null terminator.
+const MAX_SUFFIX_BYTE_SIZE: usize = 256;
+
+/// How the AC coefficients of the lossy channels are compressed.
+const AC_STATIC_HUFFMAN: u64 = 0;
+const AC_DEFLATE: u64 = 1;
+
+/// The DCT blocks are 8 by 8 pixels.
+const BLOCK_SIZE: usize = 8;
+const BLOCK_SAMPLE_COUNT: usize = BLOCK_SIZE * BLOCK_SIZE;
+
+/// Contains the index in zig-zag order of each coefficient in the row-major 8x8 block.
+const ZIG_ZAG_INDICES: [usize; BLOCK_SAMPLE_COUNT] = [
+    0,  1,  5,  6,  14, 15, 27, 28,
+    2,  4,  7,  13, 16, 26, 29, 42,
+    3,  8,  12, 17, 25, 30, 41, 43,
+    9,  11, 18, 24, 31, 40, 44, 53,
+    10, 19, 23, 32, 39, 45, 52, 54,
+    20, 22, 33, 38, 46, 51, 55, 60,
+    21, 34, 37, 47, 50, 56, 59, 61,
+    35, 36, 48, 49, 57, 58, 62, 63,
+];
+
+/// The JPEG quantization tables, which describe how sensitive each coefficient
+/// of the row-major 8x8 block is to errors. Normalized by the smallest value of each table.
+const LUMA_QUANTIZATION: [u8; BLOCK_SAMPLE_COUNT] = [
+    16, 11, 10, 16, 24,  40,  51,  61,
+    12, 12, 14, 19, 26,  58,  60,  55,
+    14, 13, 16, 24, 40,  57,  69,  56,
+    14, 17, 22, 29, 51,  87,  80,  62,
+    18, 22, 37, 56, 68,  109, 103, 77,
+    24, 35, 55, 64, 81,  104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+const CHROMA_QUANTIZATION: [u8; BLOCK_SAMPLE_COUNT] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+
+/// How the samples of a channel are compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scheme {
+    /// Deflate compressed, without any transformation.
+    Unknown,
+
+    /// Quantized in the frequency domain.
+    LossyDct,
+
+    /// Run length encoded and then deflate compressed.
+    Rle,
+}
+
+/// Decides the compression scheme of all channels with the specified name suffix and sample type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    suffix: Vec<u8>,
+    scheme: Scheme,
+    sample_type: SampleType,
+
+    /// Which of the red, green, or blue channels this is, if it should be color converted.
+    color_index: Option<usize>,
+    case_insensitive: bool,
+}
+
+/// The start of each line in the decompressed bytes, and other properties of a channel.
+#[derive(Debug)]
+struct ChannelData {
+    scheme: Scheme,
+    sample_type: SampleType,
+    resolution: Vec2<usize>,
+    quantize_linearly: bool,
+    line_starts: Vec<usize>,
+}
+
+
+pub fn decompress(
+    channels: &ChannelList,
+    compressed: Bytes<'_>,
+    rectangle: IntegerBounds,
+    expected_byte_size: usize,
+    pedantic: bool
+) -> Result<ByteVec>
+{
+    let mut remaining = compressed;
+
+    let mut sizes = [0_u64; SIZE_COUNT];
+    u64::read_slice(&mut remaining, &mut sizes)?;
+
+    let [
+        version, unknown_uncompressed_size, unknown_compressed_size,
+        ac_compressed_size, dc_compressed_size, rle_compressed_size,
+        rle_uncompressed_size, rle_raw_size, ac_count, dc_count, ac_compression
+    ] = sizes;
+
+    let rules = match version {
+        2 => read_rules(&mut remaining)?,
+        0 | 1 => default_rules(),
+        _ => return Err(Error::unsupported(format!("dwa version {}", version))),
+    };
+
+    let unknown_compressed = take_bytes(&mut remaining, usize::try_from(unknown_compressed_size)?)?;
+    let ac_compressed = take_bytes(&mut remaining, usize::try_from(ac_compressed_size)?)?;
+    let dc_compressed = take_bytes(&mut remaining, usize::try_from(dc_compressed_size)?)?;
+    let rle_compressed = take_bytes(&mut remaining, usize::try_from(rle_compressed_size)?)?;
+
+    if pedantic && !remaining.is_empty() {
+        return Err(Error::invalid("data amount"));
+    }
+
+    let (mut channel_data, color_sets) = classify_channels(channels, rectangle, &rules);
+
+    // the byte sizes of the lossless data, and the number of lossy coefficients, are limited by the block size
+    let lossless_byte_size = |scheme: Scheme| channel_data.iter()
+        .filter(|channel| channel.scheme == scheme)
+        .map(|channel| channel.resolution.area() * channel.sample_type.bytes_per_sample())
+        .sum::<usize>();
+
+    let unknown_byte_size = lossless_byte_size(Scheme::Unknown);
+    let rle_byte_size = lossless_byte_size(Scheme::Rle);
+
+    let lossy_block_count = channel_data.iter()
+        .filter(|channel| channel.scheme == Scheme::LossyDct)
+        .map(|channel| block_count(channel.resolution).area())
+        .sum::<usize>();
+
+    // each byte of the raw data requires at most two bytes of runs, a count and the byte itself
+    let rle_raw_size = usize::try_from(rle_raw_size)?;
+    let rle_uncompressed_size = usize::try_from(rle_uncompressed_size)?;
+
+    if usize::try_from(unknown_uncompressed_size)? > unknown_byte_size
+        || rle_raw_size > rle_byte_size
+        || rle_uncompressed_size > rle_raw_size.saturating_mul(2)
+        || usize::try_from(ac_count)? > lossy_block_count * (BLOCK_SAMPLE_COUNT - 1)
+        || usize::try_from(dc_count)? > lossy_block_count
+    {
+        return Err(Error::invalid("dwa data size"));
+    }
+
+    let unknown = if unknown_compressed.is_empty() { Vec::new() }
+        else { super::zip::decompress_zlib(unknown_compressed, unknown_byte_size)? };
+
+    let ac_count = usize::try_from(ac_count)?;
+    let ac_values = if ac_compressed.is_empty() { Vec::new() } else {
+        match ac_compression {
+            AC_STATIC_HUFFMAN => huffman::decompress(ac_compressed, ac_count)?,
+            AC_DEFLATE => u16_from_little_endian(&super::zip::decompress_zlib(ac_compressed, ac_count * 2)?),
+            _ => return Err(Error::invalid("dwa ac compression method")),
+        }
+    };
+
+    let dc_count = usize::try_from(dc_count)?;
+    let dc_values = if dc_compressed.is_empty() { Vec::new() }
+        else { u16_from_little_endian(&super::zip::decompress_reordered_bytes(dc_compressed, dc_count * 2)?) };
+
+    if ac_values.len() != ac_count || dc_values.len() != dc_count {
+        return Err(Error::invalid("dwa coefficient count"));
+    }
+
+    let rle = if rle_raw_size == 0 { Vec::new() } else {
+        let runs = super::zip::decompress_zlib(rle_compressed, rle_uncompressed_size)?;
+        super::rle::decompress_runs(&runs, rle_raw_size, pedantic)?
+    };
+
+    // the decompressed lines contain all channels, in the same order as the uncompressed data
+    let mut decompressed = vec![0_u8; expected_byte_size];
+    if locate_lines(&mut channel_data, channels, rectangle) != expected_byte_size {
+        return Err(Error::invalid("dwa channel sizes"));
+    }
+
+    let mut remaining_ac = ac_values.as_slice();
+    let mut remaining_dc = dc_values.as_slice();
+    let mut is_decoded = vec![false; channel_data.len()];
+
+    // color channels are decoded together, as they need to be converted back to rgb
+    for color_set in color_sets {
+        let color_channels = color_set.iter().map(|&index| &channel_data[index]).collect::<Vec<_>>();
+
+        if color_channels.iter().any(|channel| channel.scheme != Scheme::LossyDct) {
+            return Err(Error::invalid("dwa color channel compression"));
+        }
+
+        decode_lossy_dct(&mut decompressed, &color_channels, true, &mut remaining_ac, &mut remaining_dc)?;
+        for index in color_set { is_decoded[index] = true; }
+    }
+
+    let mut remaining_unknown = unknown.as_slice();
+    let mut remaining_rle = rle.as_slice();
+
+    for (channel, _) in channel_data.iter().zip(is_decoded).filter(|(_, is_decoded)| !is_decoded) {
+        let line_byte_size = channel.resolution.x() * channel.sample_type.bytes_per_sample();
+
+        match channel.scheme {
+            Scheme::LossyDct => decode_lossy_dct(
+                &mut decompressed, &[channel], !channel.quantize_linearly,
+                &mut remaining_ac, &mut remaining_dc
+            )?,
+
+            Scheme::Unknown => for &line_start in &channel.line_starts {
+                let line = take_bytes(&mut remaining_unknown, line_byte_size)?;
+                decompressed[line_start .. line_start + line_byte_size].copy_from_slice(line);
+            },
+
+            // the bytes of each sample are separated into planes, one plane for each byte of a sample
+            Scheme::Rle => {
+                let bytes_per_sample = channel.sample_type.bytes_per_sample();
+                let plane_size = channel.resolution.area();
+                let planes = take_bytes(&mut remaining_rle, plane_size * bytes_per_sample)?;
+
+                for (line_index, &line_start) in channel.line_starts.iter().enumerate() {
+                    let line = &mut decompressed[line_start .. line_start + line_byte_size];
+
+                    for (sample_index, sample) in line.chunks_exact_mut(bytes_per_sample).enumerate() {
+                        let plane_index = line_index * channel.resolution.x() + sample_index;
+
+                        for (byte_index, byte) in sample.iter_mut().enumerate() {
+                            *byte = planes[byte_index * plane_size + plane_index];
+                        }
+                    }
+                }
+            },
+        }
+    }
+
+    if pedantic && !(remaining_ac.is_empty() && remaining_dc.is_empty()) {
+        return Err(Error::invalid("data amount"));
+    }
+
+    Ok(super::convert_little_endian_to_current(decompressed, channels, rectangle))
+}
+
+
+pub fn compress(
+    channels: &ChannelList,
+    uncompressed: ByteVec,
+    rectangle: IntegerBounds,
+    compression_level: Option<f32>,
+    deflate_ac_coefficients: bool,
+) -> Result<ByteVec>
+{
+    let uncompressed = super::convert_current_to_little_endian(uncompressed, channels, rectangle);
+
+    let rules = default_rules();
+    let (mut channel_data, color_sets) = classify_channels(channels, rectangle, &rules);
+
+    if locate_lines(&mut channel_data, channels, rectangle) != uncompressed.len() {
+        return Err(Error::invalid("dwa channel sizes"));
+    }
+
+    // the error that is acceptable for the coefficient which is the most sensitive to errors
+    let base_error = compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL) / 100_000.0;
+
+    let mut ac_values = Vec::new();
+    let mut dc_values = Vec::new();
+    let mut is_encoded = vec![false; channel_data.len()];
+
+    // color channels are encoded together, as they are converted from rgb first
+    for color_set in color_sets {
+        let color_channels = color_set.iter().map(|&index| &channel_data[index]).collect::<Vec<_>>();
+
+        if color_channels.iter().any(|channel| channel.scheme != Scheme::LossyDct) {
+            return Err(Error::invalid("dwa color channel compression"));
+        }
+
+        encode_lossy_dct(&uncompressed, &color_channels, true, base_error, &mut ac_values, &mut dc_values)?;
+        for index in color_set { is_encoded[index] = true; }
+    }
+
+    let mut unknown = Vec::new();
+    let mut rle_planes = Vec::new();
+
+    for (channel, _) in channel_data.iter().zip(is_encoded).filter(|(_, is_encoded)| !is_encoded) {
+        let line_byte_size = channel.resolution.x() * channel.sample_type.bytes_per_sample();
+
+        match channel.scheme {
+            Scheme::LossyDct => encode_lossy_dct(
+                &uncompressed, &[channel], !channel.quantize_linearly,
+                base_error, &mut ac_values, &mut dc_values
+            )?,
+
+            Scheme::Unknown => for &line_start in &channel.line_starts {
+                unknown.extend_from_slice(&uncompressed[line_start .. line_start + line_byte_size]);
+            },
+
+            // the bytes of each sample are separated into planes, one plane for each byte of a sample
+            Scheme::Rle => {
+                let bytes_per_sample = channel.sample_type.bytes_per_sample();
+                let plane_size = channel.resolution.area();
+
+                let planes_start = rle_planes.len();
+                rle_planes.resize(planes_start + plane_size * bytes_per_sample, 0);
+                let planes = &mut rle_planes[planes_start ..];
+
+                for (line_index, &line_start) in channel.line_starts.iter().enumerate() {
+                    let line = &uncompressed[line_start .. line_start + line_byte_size];
+
+                    for (sample_index, sample) in line.chunks_exact(bytes_per_sample).enumerate() {
+                        let plane_index = line_index * channel.resolution.x() + sample_index;
+
+                        for (byte_index, &byte) in sample.iter().enumerate() {
+                            planes[byte_index * plane_size + plane_index] = byte;
+                        }
+                    }
+                }
+            },
+        }
+    }
+
+    let unknown_compressed = if unknown.is_empty() { Vec::new() }
+        else { super::zip::compress_zlib(&unknown, super::zip::DEFAULT_COMPRESSION_LEVEL) };
+
+    let ac_compressed = if ac_values.is_empty() { Vec::new() }
+        else if deflate_ac_coefficients { super::zip::compress_zlib(&u16_to_little_endian(&ac_values), super::zip::DEFAULT_COMPRESSION_LEVEL) }
+        else { huffman::compress(&ac_values)? };
+
+    let dc_compressed = if dc_values.is_empty() { Vec::new() }
+        else { super::zip::compress_reordered_bytes(u16_to_little_endian(&dc_values), super::zip::DEFAULT_COMPRESSION_LEVEL) };
+
+    let rle_runs = super::rle::compress_runs(&rle_planes);
+    let rle_compressed = if rle_runs.is_empty() { Vec::new() }
+        else { super::zip::compress_zlib(&rle_runs, super::zip::DEFAULT_COMPRESSION_LEVEL) };
+
+    let sizes = [
+        VERSION, usize_to_u64(unknown.len()), usize_to_u64(unknown_compressed.len()),
+        usize_to_u64(ac_compressed.len()), usize_to_u64(dc_compressed.len()), usize_to_u64(rle_compressed.len()),
+        usize_to_u64(rle_runs.len()), usize_to_u64(rle_planes.len()),
+        usize_to_u64(ac_values.len()), usize_to_u64(dc_values.len()),
+        if deflate_ac_coefficients { AC_DEFLATE } else { AC_STATIC_HUFFMAN },
+    ];
+
+    let mut compressed = Vec::with_capacity(uncompressed.len() / 4);
+    u64::write_slice(&mut compressed, &sizes)?;
+    write_rules(&mut compressed, &rules)?;
+
+    compressed.extend_from_slice(&unknown_compressed);
+    compressed.extend_from_slice(&ac_compressed);
+    compressed.extend_from_slice(&dc_compressed);
+    compressed.extend_from_slice(&rle_compressed);
+
+    Ok(compressed)
+}
+
+/// Find the start of each line of each channel in the uncompressed bytes.
+/// Returns the total byte size of all lines.
+fn locate_lines(channel_data: &mut [ChannelData], channels: &ChannelList, rectangle: IntegerBounds) -> usize {
+    let mut byte_index = 0;
+
+    for y in rectangle.position.y() .. rectangle.end().y() {
+        for (channel, description) in channel_data.iter_mut().zip(&channels.list) {
+            if mod_p(y, usize_to_i32(description.sampling.y())) != 0 { continue; }
+
+            channel.line_starts.push(byte_index);
+            byte_index += channel.resolution.x() * channel.sample_type.bytes_per_sample();
+        }
+    }
+
+    byte_index
+}
+
+/// Read the rules that decide how each channel is compressed.
+fn read_rules(remaining: &mut Bytes<'_>) -> Result<Vec<Rule>> {
+    let rules_byte_size = usize::from(u16::read(remaining)?);
+    let rules_byte_size = rules_byte_size.checked_sub(u16::BYTE_SIZE)
+        .ok_or(Error::invalid("dwa rules size"))?;
+
+    let mut rule_bytes = take_bytes(remaining, rules_byte_size)?;
+    let mut rules = Vec::new();
+
+    while !rule_bytes.is_empty() {
+        let suffix_length = rule_bytes.iter().take(MAX_SUFFIX_BYTE_SIZE).position(|&byte| byte == 0)
+            .ok_or(Error::invalid("dwa rule suffix"))?;
+
+        let suffix = take_bytes(&mut rule_bytes, suffix_length + 1)?[.. suffix_length].to_vec();
+        let properties = take_bytes(&mut rule_bytes, 2)?;
+
+        let color_index = match properties[0] >> 4 {
+            0 => None,
+            index @ 1 ..= 3 => Some(usize::from(index) - 1),
+            _ => return Err(Error::invalid("dwa rule color index")),
+        };
+
+        let scheme = match (properties[0] >> 2) & 3 {
+            0 => Scheme::Unknown,
+            1 => Scheme::LossyDct,
+            2 => Scheme::Rle,
+            _ => return Err(Error::invalid("dwa rule compression scheme")),
+        };
+
+        let sample_type = match properties[1] {
+            0 => SampleType::U32,
+            1 => SampleType::F16,
+            2 => SampleType::F32,
+            _ => return Err(Error::invalid("dwa rule sample type")),
+        };
+
+        let case_insensitive = properties[0] & 1 != 0;
+        rules.push(Rule { suffix, scheme, sample_type, color_index, case_insensitive });
+    }
+
+    Ok(rules)
+}
+
+/// Write the rules that decide how each channel is compressed, preceded by their byte size.
+fn write_rules(write: &mut ByteVec, rules: &[Rule]) -> UnitResult {
+    let mut rule_bytes = Vec::new();
+
+    for rule in rules {
+        rule_bytes.extend_from_slice(&rule.suffix);
+        rule_bytes.push(0);
+
+        let color_index = rule.color_index.map_or(0, |index| index as u8 + 1);
+
+        let scheme = match rule.scheme {
+            Scheme::Unknown => 0,
+            Scheme::LossyDct => 1,
+            Scheme::Rle => 2,
+        };
+
+        let sample_type = match rule.sample_type {
+            SampleType::U32 => 0,
+            SampleType::F16 => 1,
+            SampleType::F32 => 2,
+        };
+
+        rule_bytes.push(color_index << 4 | scheme << 2 | u8::from(rule.case_insensitive));
+        rule_bytes.push(sample_type);
+    }
+
+    let rules_byte_size = u16::try_from(rule_bytes.len() + u16::BYTE_SIZE)
+        .map_err(|_| Error::invalid("dwa rules size"))?;
+
+    rules_byte_size.write(write)?;
+    write.extend_from_slice(&rule_bytes);
+    Ok(())
+}
+
+/// The rules that were used before the rules were stored in the file.
+/// These rules are also stored in each file written by this implementation.
+fn default_rules() -> Vec<Rule> {
+    let rule = |suffix: &str, scheme, sample_type, color_index| Rule {
+        suffix: suffix.as_bytes().to_vec(), scheme, sample_type, color_index, case_insensitive: false
+    };
+
+    let mut rules = Vec::new();
+
+    for (color_index, suffix) in ["R", "G", "B"].iter().enumerate() {
+        rules.push(rule(suffix, Scheme::LossyDct, SampleType::F16, Some(color_index)));
+        rules.push(rule(suffix, Scheme::LossyDct, SampleType::F32, Some(color_index)));
+    }
+
+    for suffix in ["Y", "BY", "RY"] {
+        rules.push(rule(suffix, Scheme::LossyDct, SampleType::F16, None));
+        rules.push(rule(suffix, Scheme::LossyDct, SampleType::F32, None));
+    }
+
+    for sample_type in [SampleType::U32, SampleType::F16, SampleType::F32] {
+        rules.push(rule("A", Scheme::Rle, sample_type, None));
+    }
+
+    rules
+}
+
+/// Decide the compression scheme of each channel, and find the sets of red, green, and blue channels.
+/// The sets are sorted by the common prefix of the channel names.
+fn classify_channels(channels: &ChannelList, rectangle: IntegerBounds, rules: &[Rule]) -> (Vec<ChannelData>, Vec<[usize; 3]>) {
+    let mut color_sets_by_prefix: BTreeMap<&[u8], [Option<usize>; 3]> = BTreeMap::new();
+
+    let channel_data = channels.list.iter().enumerate().map(|(channel_index, channel)| {
+        let name = channel.name.as_slice();
+
+        let (prefix, suffix) = match name.iter().rposition(|&byte| byte == b'.') {
+            Some(dot_index) => (&name[.. dot_index], &name[dot_index + 1 ..]),
+            None => (&name[.. 0], name),
+        };
+
+        let color_set = color_sets_by_prefix.entry(prefix).or_insert([None; 3]);
+        let mut scheme = Scheme::Unknown;
+
+        // the last matching rule wins
+        for rule in rules.iter().filter(|rule| rule.matches(suffix, channel.sample_type)) {
+            scheme = rule.scheme;
+
+            if let Some(color_index) = rule.color_index {
+                color_set[color_index] = Some(channel_index);
+            }
+        }
+
+        ChannelData {
+            scheme,
+            sample_type: channel.sample_type,
+            resolution: channel.subsampled_resolution(rectangle.size),
+            quantize_linearly: channel.quantize_linearly,
+            line_starts: Vec::new(),
+        }
+    }).collect::<Vec<_>>();
+
+    let color_sets = color_sets_by_prefix.values()
+        .filter_map(|&color_set| match color_set {
+            [Some(red), Some(green), Some(blue)] => Some([red, green, blue]),
+            _ => None,
+        })
+        .filter(|color_set| color_set.iter().all(|&index|
+            channels.list[index].sampling == channels.list[color_set[0]].sampling
+        ))
+        .collect();
+
+    (channel_data, color_sets)
+}
+
+impl Rule {
+    fn matches(&self, suffix: &[u8], sample_type: SampleType) -> bool {
+        self.sample_type == sample_type && (
+            if self.case_insensitive { self.suffix.eq_ignore_ascii_case(suffix) }
+            else { self.suffix == suffix }
+        )
+    }
+}
+
+
+/// Decode one channel, or three color channels, from the frequency domain,
+/// and write the samples to the lines of the channels.
+/// Consumes the coefficients of all blocks of the channels.
+fn decode_lossy_dct(
+    decompressed: &mut [u8], channels: &[&ChannelData], to_linear: bool,
+    remaining_ac: &mut &[u16], remaining_dc: &mut &[u16],
+) -> UnitResult
+{
+    let resolution = channels[0].resolution;
+    let block_count = block_count(resolution);
+
+    if channels.iter().any(|channel| channel.sample_type == SampleType::U32) {
+        return Err(Error::invalid("dwa lossy channel sample type"));
+    }
+
+    // the dc coefficients of all blocks of a channel are stored together, one channel after another
+    let dc_values = take_u16s(remaining_dc, block_count.area() * channels.len())?;
+    let mut dc_values_by_channel = dc_values.chunks_exact(block_count.area().max(1)).collect::<Vec<_>>();
+
+    // one line of blocks for each channel, containing the f16 bits in perceptual space
+    let mut block_line = vec![[0_u16; BLOCK_SAMPLE_COUNT]; block_count.x() * channels.len()];
+    let mut coefficients = vec![[0_f32; BLOCK_SAMPLE_COUNT]; channels.len()];
+
+    for block_y in 0 .. block_count.y() {
+        for block_x in 0 .. block_count.x() {
+            for (channel_index, block) in coefficients.iter_mut().enumerate() {
+                let mut zig_zag = [0_u16; BLOCK_SAMPLE_COUNT];
+
+                let (dc_value, rest) = dc_values_by_channel[channel_index].split_first()
+                    .ok_or(Error::invalid("dwa dc coefficient count"))?;
+
+                dc_values_by_channel[channel_index] = rest;
+                zig_zag[0] = *dc_value;
+
+                let last_non_zero_index = read_ac_coefficients(remaining_ac, &mut zig_zag)?;
+
+                if last_non_zero_index == 0 {
+                    inverse_dct_dc_only(block, f16::from_bits(zig_zag[0]).to_f32());
+                }
+                else {
+                    for (coefficient, &zig_zag_index) in block.iter_mut().zip(ZIG_ZAG_INDICES.iter()) {
+                        *coefficient = f16::from_bits(zig_zag[zig_zag_index]).to_f32();
+                    }
+
+                    inverse_dct(block, transformed_row_count(last_non_zero_index));
+                }
+            }
+
+            if let [luma, blue_difference, red_difference] = coefficients.as_mut_slice() {
+                for index in 0 .. BLOCK_SAMPLE_COUNT {
+                    let (red, green, blue) = ycbcr_to_rgb(luma[index], blue_difference[index], red_difference[index]);
+                    luma[index] = red;
+                    blue_difference[index] = green;
+                    red_difference[index] = blue;
+                }
+            }
+
+            for (channel_index, block) in coefficients.iter().enumerate() {
+                let block_bits = &mut block_line[channel_index * block_count.x() + block_x];
+
+                for (bits, &value) in block_bits.iter_mut().zip(block.iter()) {
+                    *bits = f16::from_f32(value).to_bits();
+                }
+            }
+        }
+
+        // copy the blocks to the lines, converting each sample to linear space
+        with_to_linear_table(|to_linear_table| {
+            for (channel_index, channel) in channels.iter().enumerate() {
+                let block_line = &block_line[channel_index * block_count.x() .. (channel_index + 1) * block_count.x()];
+                let lines = (block_y * BLOCK_SIZE .. resolution.y()).take(BLOCK_SIZE);
+
+                for y in lines {
+                    let line_start = channel.line_starts[y];
+                    let line = &mut decompressed[line_start .. line_start + resolution.x() * 2];
+
+                    for (x, sample) in line.chunks_exact_mut(2).enumerate() {
+                        let bits = block_line[x / BLOCK_SIZE][(y % BLOCK_SIZE) * BLOCK_SIZE + x % BLOCK_SIZE];
+                        let bits = if to_linear { to_linear_table[usize::from(bits)] } else { bits };
+                        sample.copy_from_slice(&bits.to_le_bytes());
+                    }
+                }
+            }
+        });
+    }
+
+    // the f16 samples are stored in the first half of each f32 line, and need to be expanded
+    for channel in channels.iter().filter(|channel| channel.sample_type == SampleType::F32) {
+        for &line_start in &channel.line_starts {
+            let line = &mut decompressed[line_start .. line_start + resolution.x() * 4];
+
+            // iterate backwards, so that no f16 value is overwritten before it is converted
+            for x in (0 .. resolution.x()).rev() {
+                let value = f16::from_bits(u16::from_le_bytes([line[x * 2], line[x * 2 + 1]]));
+                line[x * 4 .. x * 4 + 4].copy_from_slice(&value.to_f32().to_le_bytes());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Encode one channel, or three color channels, into the frequency domain.
+/// Appends the coefficients of all blocks of the channels.
+fn encode_lossy_dct(
+    uncompressed: Bytes<'_>, channels: &[&ChannelData], to_perceptual: bool, base_error: f32,
+    ac_values: &mut Vec<u16>, dc_values: &mut Vec<u16>,
+) -> UnitResult
+{
+    let resolution = channels[0].resolution;
+    let block_count = block_count(resolution);
+
+    if channels.iter().any(|channel| channel.sample_type == SampleType::U32) {
+        return Err(Error::invalid("dwa lossy channel sample type"));
+    }
+
+    // the dc coefficients of all blocks of a channel are stored together, one channel after another
+    let mut dc_values_by_channel = vec![Vec::new(); channels.len()];
+    let mut blocks = vec![[0_f32; BLOCK_SAMPLE_COUNT]; channels.len()];
+    let basis = dct_basis();
+
+    with_to_perceptual_table(|to_perceptual_table| {
+        for block_y in 0 .. block_count.y() {
+            for block_x in 0 .. block_count.x() {
+                for (channel, block) in channels.iter().zip(blocks.iter_mut()) {
+                    for (index, value) in block.iter_mut().enumerate() {
+                        // blocks at the border of the image repeat the last pixels
+                        let x = (block_x * BLOCK_SIZE + index % BLOCK_SIZE).min(resolution.x() - 1);
+                        let y = (block_y * BLOCK_SIZE + index / BLOCK_SIZE).min(resolution.y() - 1);
+
+                        let bits = f16_bits_at(uncompressed, channel, x, y);
+                        let bits = if to_perceptual { to_perceptual_table[usize::from(bits)] } else { bits };
+                        *value = f16::from_bits(bits).to_f32();
+                    }
+                }
+
+                if let [red, green, blue] = blocks.as_mut_slice() {
+                    for index in 0 .. BLOCK_SAMPLE_COUNT {
+                        let (luma, blue_difference, red_difference) = rgb_to_ycbcr(red[index], green[index], blue[index]);
+                        red[index] = luma;
+                        green[index] = blue_difference;
+                        blue[index] = red_difference;
+                    }
+                }
+
+                for (channel_index, block) in blocks.iter_mut().enumerate() {
+                    forward_dct(block, &basis);
+
+                    // only the difference channels of a color set use the chroma table
+                    let quantization = if channel_index == 0 { &LUMA_QUANTIZATION } else { &CHROMA_QUANTIZATION };
+                    let min_quantization = quantization.iter().copied().min().unwrap_or(1);
+
+                    let mut zig_zag = [0_u16; BLOCK_SAMPLE_COUNT];
+                    for ((&coefficient, &zig_zag_index), &quantization) in block.iter().zip(ZIG_ZAG_INDICES.iter()).zip(quantization.iter()) {
+                        let tolerance = base_error * f32::from(quantization) / f32::from(min_quantization);
+                        zig_zag[zig_zag_index] = quantize(coefficient, tolerance);
+                    }
+
+                    dc_values_by_channel[channel_index].push(zig_zag[0]);
+                    write_ac_coefficients(&zig_zag, ac_values);
+                }
+            }
+        }
+    });
+
+    for channel_dc_values in dc_values_by_channel {
+        dc_values.extend_from_slice(&channel_dc_values);
+    }
+
+    Ok(())
+}
+
+/// The bits of the sample of a lossy channel, converting f32 samples to f16.
+fn f16_bits_at(uncompressed: Bytes<'_>, channel: &ChannelData, x: usize, y: usize) -> u16 {
+    let sample_start = channel.line_starts[y] + x * channel.sample_type.bytes_per_sample();
+    let bytes = &uncompressed[sample_start ..];
+
+    match channel.sample_type {
+        SampleType::F32 => f16::from_f32(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).to_bits(),
+        _ => u16::from_le_bytes([bytes[0], bytes[1]]),
+    }
+}
+
+/// Find the f16 value with the fewest set bits that differs from the coefficient by less than the tolerance.
+/// Values with fewer set bits can be compressed further.
+fn quantize(coefficient: f32, tolerance: f32) -> u16 {
+    let bits = f16::from_f32(coefficient).to_bits();
+    if bits & 0x7c00 == 0x7c00 { return bits; }
+
+    let value = f16::from_bits(bits).to_f32();
+    let is_close = |candidate: u16| (f16::from_bits(candidate).to_f32() - value).abs() < tolerance;
+
+    if is_close(0) { return 0; }
+
+    let mut quantized = bits;
+
+    // clear the lowest mantissa bits, rounding towards zero and away from zero
+    for cleared_bit_count in 1 ..= 10 {
+        let rounded_down = bits & !((1 << cleared_bit_count) - 1);
+        let rounded_up = rounded_down + (1 << cleared_bit_count);
+
+        for candidate in [rounded_down, rounded_up] {
+            let is_finite = candidate & 0x7c00 != 0x7c00;
+
+            if is_finite && candidate.count_ones() < quantized.count_ones() && is_close(candidate) {
+                quantized = candidate;
+            }
+        }
+    }
+
+    quantized
+}
+
+/// Run length encode the ac coefficients of the zig-zag ordered block.
+fn write_ac_coefficients(zig_zag: &[u16; BLOCK_SAMPLE_COUNT], ac_values: &mut Vec<u16>) {
+    let mut index = 1;
+
+    while index < BLOCK_SAMPLE_COUNT {
+        if zig_zag[index] != 0 {
+            ac_values.push(zig_zag[index]);
+            index += 1;
+        }
+        else {
+            let run_length = zig_zag[index ..].iter().take_while(|&&value| value == 0).count();
+
+            // end of block, or a run of zero coefficients
+            if index + run_length == BLOCK_SAMPLE_COUNT { ac_values.push(0xff00); }
+            else { ac_values.push(0xff00 | run_length as u16); }
+
+            index += run_length;
+        }
+    }
+}
+
+/// Expand the run length encoded ac coefficients of a block into the zig-zag ordered block.
+/// Returns the zig-zag index of the last coefficient that is not zero, or zero if all ac coefficients are zero.
+fn read_ac_coefficients(remaining_ac: &mut &[u16], zig_zag: &mut [u16; BLOCK_SAMPLE_COUNT]) -> Result<usize> {
+    let mut index = 1;
+    let mut last_non_zero_index = 0;
+
+    while index < BLOCK_SAMPLE_COUNT {
+        let (&value, rest) = remaining_ac.split_first()
+            .ok_or(Error::invalid("dwa ac coefficient count"))?;
+
+        *remaining_ac = rest;
+
+        if value == 0xff00 { // end of block, all remaining coefficients are zero
+            index = BLOCK_SAMPLE_COUNT;
+        }
+        else if value >> 8 == 0xff { // a run of zero coefficients
+            index += usize::from(value & 0xff);
+        }
+        else {
+            zig_zag[index] = value;
+            last_non_zero_index = index;
+            index += 1;
+        }
+    }
+
+    if index > BLOCK_SAMPLE_COUNT {
+        return Err(Error::invalid("dwa ac coefficient run"));
+    }
+
+    Ok(last_non_zero_index)
+}
+
+/// Inverse discrete cosine transform of a block with only a dc coefficient.
+fn inverse_dct_dc_only(block: &mut [f32; BLOCK_SAMPLE_COUNT], dc_value: f32) {
+    let value = dc_value * 3.535536e-01 * 3.535536e-01;
+    *block = [value; BLOCK_SAMPLE_COUNT];
+}
+
+/// The number of rows that the reference implementation transforms in the first pass of the inverse
+/// cosine transform, assuming that the remaining rows only contain zeroes. It derives this number from
+/// the columns that the zig zag order has visited up to the last non-zero coefficient, not from the rows,
+/// so some rows with coefficients are not transformed. This is replicated to decode the same pixels.
+fn transformed_row_count(last_non_zero_index: usize) -> usize {
+    // the first row of the zig zag table contains the first index of each column
+    let first_indices_of_columns = &ZIG_ZAG_INDICES[1 .. BLOCK_SIZE];
+    1 + first_indices_of_columns.iter().take_while(|&&index| index <= last_non_zero_index).count()
+}
+
+/// Inverse discrete cosine transform of a row-major 8x8 block, first row-wise and then column-wise.
+/// Only the first rows are transformed in the first pass, the other rows are used as they are.
+fn inverse_dct(block: &mut [f32; BLOCK_SAMPLE_COUNT], transformed_row_count: usize) {
+    for row in block.chunks_exact_mut(BLOCK_SIZE).take(transformed_row_count) {
+        let values = inverse_dct_8([row[0], row[1], row[2], row[3], row[4], row[5], row[6], row[7]]);
+        row.copy_from_slice(&values);
+    }
+
+    for column in 0 .. BLOCK_SIZE {
+        let mut values = [0.0; BLOCK_SIZE];
+        for (row, value) in values.iter_mut().enumerate() { *value = block[row * BLOCK_SIZE + column]; }
+
+        let values = inverse_dct_8(values);
+        for (row, value) in values.iter().enumerate() { block[row * BLOCK_SIZE + column] = *value; }
+    }
+}
+
+/// One dimensional inverse discrete cosine transform, following Arai, Agui, and Nakajima.
+fn inverse_dct_8(x: [f32; BLOCK_SIZE]) -> [f32; BLOCK_SIZE] {
+    let pi = 3.14159_f32; // the same approximation as the reference implementation
+    let a = 0.5 * (pi / 4.0).cos();
+    let b = 0.5 * (pi / 16.0).cos();
+    let c = 0.5 * (pi / 8.0).cos();
+    let d = 0.5 * (3.0 * pi / 16.0).cos();
+    let e = 0.5 * (5.0 * pi / 16.0).cos();
+    let f = 0.5 * (3.0 * pi / 8.0).cos();
+    let g = 0.5 * (7.0 * pi / 16.0).cos();
+
+    let alpha = [c * x[2], f * x[2], c * x[6], f * x[6]];
+
+    let beta = [
+        b * x[1] + d * x[3] + e * x[5] + g * x[7],
+        d * x[1] - g * x[3] - b * x[5] - e * x[7],
+        e * x[1] - b * x[3] + g * x[5] + d * x[7],
+        g * x[1] - e * x[3] + d * x[5] - b * x[7],
+    ];
+
+    let theta = [
+        a * (x[0] + x[4]),
+        alpha[0] + alpha[3],
+        alpha[1] - alpha[2],
+        a * (x[0] - x[4]),
+    ];
+
+    let gamma = [
+        theta[0] + theta[1],
+        theta[3] + theta[2],
+        theta[3] - theta[2],
+        theta[0] - theta[1],
+    ];
+
+    [
+        gamma[0] + beta[0], gamma[1] + beta[1], gamma[2] + beta[2], gamma[3] + beta[3],
+        gamma[3] - beta[3], gamma[2] - beta[2], gamma[1] - beta[1], gamma[0] - beta[0],
+    ]
+}
+
+/// The cosine basis of the orthonormal discrete cosine transform, the inverse of `inverse_dct_8`.
+fn dct_basis() -> [[f32; BLOCK_SIZE]; BLOCK_SIZE] {
+    let mut basis = [[0.0; BLOCK_SIZE]; BLOCK_SIZE];
+
+    for (frequency, row) in basis.iter_mut().enumerate() {
+        let scale = if frequency == 0 { (1.0_f32 / 8.0).sqrt() } else { 0.5 };
+
+        for (index, value) in row.iter_mut().enumerate() {
+            let angle = (2 * index + 1) as f32 * frequency as f32 * std::f32::consts::PI / 16.0;
+            *value = scale * angle.cos();
+        }
+    }
+
+    basis
+}
+
+/// Discrete cosine transform of a row-major 8x8 block, first row-wise and then column-wise.
+fn forward_dct(block: &mut [f32; BLOCK_SAMPLE_COUNT], basis: &[[f32; BLOCK_SIZE]; BLOCK_SIZE]) {
+    let transform = |values: [f32; BLOCK_SIZE]| basis.map(|row|
+        row.iter().zip(values.iter()).map(|(basis, value)| basis * value).sum::<f32>()
+    );
+
+    for row in block.chunks_exact_mut(BLOCK_SIZE) {
+        let values = transform([row[0], row[1], row[2], row[3], row[4], row[5], row[6], row[7]]);
+        row.copy_from_slice(&values);
+    }
+
+    for column in 0 .. BLOCK_SIZE {
+        let mut values = [0.0; BLOCK_SIZE];
+        for (row, value) in values.iter_mut().enumerate() { *value = block[row * BLOCK_SIZE + column]; }
+
+        let values = transform(values);
+        for (row, value) in values.iter().enumerate() { block[row * BLOCK_SIZE + column] = *value; }
+    }
+}
+
+/// Convert from red, green, and blue to the color space of Rec. 709.
+fn rgb_to_ycbcr(red: f32, green: f32, blue: f32) -> (f32, f32, f32) {
+    (
+        0.2126 * red + 0.7152 * green + 0.0722 * blue,
+        -0.1146 * red - 0.3854 * green + 0.5 * blue,
+        0.5 * red - 0.4542 * green - 0.0458 * blue,
+    )
+}
+
+/// Convert from the color space of Rec. 709 back to red, green, and blue.
+fn ycbcr_to_rgb(luma: f32, blue_difference: f32, red_difference: f32) -> (f32, f32, f32) {
+    (
+        luma + 1.5747 * red_difference,
+        luma - 0.1873 * blue_difference - 0.4682 * red_difference,
+        luma + 1.8556 * blue_difference,
+    )
+}
+
+/// Call the closure with a table that maps each perceptual f16 value to a linear f16 value.
+/// The table is computed once for each thread.
+fn with_to_linear_table<T>(function: impl FnOnce(&[u16]) -> T) -> T {
+    thread_local! {
+        static TO_LINEAR: Vec<u16> = (0 ..= u16::MAX).map(perceptual_to_linear).collect();
+    }
+
+    TO_LINEAR.with(|table| function(table))
+}
+
+/// Call the closure with a table that maps each linear f16 value to a perceptual f16 value.
+/// The table is computed once for each thread.
+fn with_to_perceptual_table<T>(function: impl FnOnce(&[u16]) -> T) -> T {
+    thread_local! {
+        static TO_PERCEPTUAL: Vec<u16> = (0 ..= u16::MAX).map(linear_to_perceptual).collect();
+    }
+
+    TO_PERCEPTUAL.with(|table| function(table))
+}
+
+/// Convert a linear f16 value to a perceptual f16 value. Infinity and NaN are converted to zero.
+fn linear_to_perceptual(bits: u16) -> u16 {
+    if bits & 0x7c00 == 0x7c00 { return 0; }
+
+    let value = f16::from_bits(bits).to_f32();
+    let sign = if value < 0.0 { -1.0 } else { 1.0 };
+
+    let perceptual = if value.abs() <= 1.0 { sign * value.abs().powf(1.0 / 2.2) }
+        else { sign * (value.abs().ln() / 2.2 + 1.0) };
+
+    f16::from_f32(perceptual).to_bits()
+}
+
+/// Convert a perceptual f16 value back to a linear f16 value. Infinity and NaN are converted to zero.
+fn perceptual_to_linear(bits: u16) -> u16 {
+    if bits & 0x7c00 == 0x7c00 { return 0; }
+
+    let value = f16::from_bits(bits).to_f32();
+    let sign = if value < 0.0 { -1.0 } else { 1.0 };
+
+    let linear = if value.abs() <= 1.0 { sign * value.abs().powf(2.2) }
+        else { sign * (2.7182818_f64.powf(2.2) as f32).powf(value.abs() - 1.0) };
+
+    f16::from_f32(linear).to_bits()
+}
+
+
+/// The number of 8x8 blocks required to cover the resolution.
+fn block_count(resolution: Vec2<usize>) -> Vec2<usize> {
+    Vec2(
+        (resolution.x() + BLOCK_SIZE - 1) / BLOCK_SIZE,
+        (resolution.y() + BLOCK_SIZE - 1) / BLOCK_SIZE,
+    )
+}
+
+fn take_bytes<'s>(remaining: &mut Bytes<'s>, count: usize) -> Result<Bytes<'s>> {
+    if count > remaining.len() { return Err(Error::invalid("dwa data size")); }
+
+    let (taken, rest) = remaining.split_at(count);
+    *remaining = rest;
+    Ok(taken)
+}
+
+fn take_u16s<'s>(remaining: &mut &'s [u16], count: usize) -> Result<&'s [u16]> {
+    if count > remaining.len() { return Err(Error::invalid("dwa coefficient count")); }
+
+    let (taken, rest) = remaining.split_at(count);
+    *remaining = rest;
+    Ok(taken)
+}
+
+fn u16_to_little_endian(values: &[u16]) -> ByteVec {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn u16_from_little_endian(bytes: &[u8]) -> Vec<u16> {
+    bytes.chunks_exact(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]])).collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::meta::attribute::ChannelDescription;
+
+    #[test]
+    fn reject_corrupt_rle_size() {
+        let channels = ChannelList::new(smallvec::smallvec![
+            ChannelDescription::new("A", SampleType::F16, true),
+        ]);
+
+        let rectangle = IntegerBounds::new((0, 0), (19, 13));
+        let byte_size = rectangle.size.area() * SampleType::F16.bytes_per_sample();
+        let pixels = (0 .. byte_size).map(|index| (index / 7) as u8).collect::<ByteVec>();
+
+        let compressed = compress(&channels, pixels.clone(), rectangle, None, false).unwrap();
+        assert_eq!(decompress(&channels, &compressed, rectangle, byte_size, true).unwrap(), pixels);
+
+        // a corrupt size must not be allocated
+        let rle_uncompressed_size_index = 6 * u64::BYTE_SIZE;
+        let mut corrupt = compressed.clone();
+        corrupt[rle_uncompressed_size_index .. rle_uncompressed_size_index + u64::BYTE_SIZE]
+            .copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+
+        let result = decompress(&channels, &corrupt, rectangle, byte_size, true);
+        assert!(matches!(result, Err(Error::Invalid(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn transformed_rows_follow_visited_columns() {
+        // the last coefficient is in the first column of the row, which is not transformed
+        assert_eq!(transformed_row_count(ZIG_ZAG_INDICES[2 * BLOCK_SIZE]), 2);
+        assert_eq!(transformed_row_count(ZIG_ZAG_INDICES[4 * BLOCK_SIZE]), 4);
+        assert_eq!(transformed_row_count(ZIG_ZAG_INDICES[6 * BLOCK_SIZE]), 6);
+
+        assert_eq!(transformed_row_count(1), 2);
+        assert_eq!(transformed_row_count(ZIG_ZAG_INDICES[3 * BLOCK_SIZE]), 4);
+        assert_eq!(transformed_row_count(ZIG_ZAG_INDICES[7 * BLOCK_SIZE]), BLOCK_SIZE);
+        assert_eq!(transformed_row_count(BLOCK_SAMPLE_COUNT - 1), BLOCK_SIZE);
+    }
+}
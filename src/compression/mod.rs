@@ -4,19 +4,27 @@
 
 
 // private modules make non-breaking changes easier
-mod zip;
-mod rle;
-mod piz;
-mod pxr24;
-mod b44;
+// each standard compression method can be excluded with a cargo feature
+#[cfg(feature = "zip")] mod zip;
+#[cfg(feature = "rle")] mod rle;
+#[cfg(feature = "piz")] mod piz;
+#[cfg(feature = "pxr24")] mod pxr24;
+#[cfg(feature = "b44")] mod b44;
+#[cfg(feature = "dwa")] mod dwa;
+mod custom;
 
+pub use self::custom::{BlockCodec, CodecRegistry};
+pub(crate) use self::custom::MAX_RESERVED_ID;
 
+
+use std::borrow::Cow;
 use std::convert::TryInto;
 use std::mem::size_of;
 use half::f16;
-use crate::meta::attribute::{IntegerBounds, SampleType, ChannelList};
-use crate::error::{Result, Error, usize_to_i32};
+use crate::meta::attribute::{IntegerBounds, SampleType, ChannelDescription, ChannelList, Text};
+use crate::error::{Result, UnitResult, Error, usize_to_i32};
 use crate::meta::header::Header;
+use crate::block::pool::BlockBufferPool;
 
 
 /// A byte vector.
@@ -124,21 +132,36 @@ pub enum Compression {
     /// Only supported for flat images, not for deep data.
     B44A, // TODO collapse with B44
 
-    /// __This lossy compression is not yet supported by this implementation.__
+    /// Lossy DCT based compression, in blocks of 32 scanlines.
+    /// The optional value is the compression level, which is 45 if not specified.
+    /// Higher levels result in smaller files with lower quality.
     // lossy DCT based compression, in blocks
     // of 32 scanlines. More efficient for partial buffer access.
     DWAA(Option<f32>), // TODO does this have a default value? make this non optional? default Compression Level setting is 45.0
 
-    /// __This lossy compression is not yet supported by this implementation.__
+    /// Lossy DCT based compression, in blocks of 256 scanlines.
+    /// The optional value is the compression level, which is 45 if not specified.
+    /// Higher levels result in smaller files with lower quality.
     // lossy DCT based compression, in blocks
     // of 256 scanlines. More efficient space
     // wise and faster to decode full frames
     // than DWAA_COMPRESSION.
     DWAB(Option<f32>), // TODO collapse with B44. default Compression Level setting is 45.0
+
+    /// A compression method that is __not part of the OpenEXR standard__,
+    /// identified by an id above 9, which is stored in the file.
+    /// Delegates to the codec with this id in the `custom_codecs` of the header.
+    /// __Files with this compression are not valid OpenEXR files__ and cannot be read by other software.
+    /// Blocks contain 16 scan lines. Assumed to be lossy, and not supported for deep data.
+    Custom(u8),
 }
 
 impl std::fmt::Display for Compression {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Compression::Custom(id) = self {
+            return write!(formatter, "custom compression {}", id);
+        }
+
         write!(formatter, "{} compression", match self {
             Compression::Uncompressed => "no",
             Compression::RLE => "rle",
@@ -150,20 +173,76 @@ impl std::fmt::Display for Compression {
             Compression::DWAB(_) => "dwab",
             Compression::PIZ => "piz",
             Compression::PXR24 => "pxr24",
+            Compression::Custom(_) => "custom",
         })
     }
 }
 
+/// Describes why a compression method is not ideal for one of the channels of a layer.
+/// Returned by `Compression::validate_for_channels` and `Header::compression_warnings`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionWarning {
+
+    /// The compression method that was checked.
+    pub compression: Compression,
+
+    /// The name of the affected channel.
+    pub channel: Text,
+
+    /// The sample type of the affected channel.
+    pub sample_type: SampleType,
+
+    /// What happens to the samples of the channel.
+    pub reason: CompressionWarningReason,
+}
+
+/// What a compression method does to the samples of a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionWarningReason {
+
+    /// The samples are stored with reduced precision,
+    /// for example the mantissa of `f32` samples in `PXR24`.
+    Lossy,
+
+    /// The compression method does not compress samples of this type,
+    /// so they are stored without any compression, for example `f32` samples in `B44`.
+    NotCompressed,
+}
+
+impl std::fmt::Display for CompressionWarning {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let consequence = match self.reason {
+            CompressionWarningReason::Lossy => "samples lose precision",
+            CompressionWarningReason::NotCompressed => "samples are stored uncompressed",
+        };
+
+        write!(formatter, "{} of channel `{}` with {:?} samples: {}", self.compression, self.channel, self.sample_type, consequence)
+    }
+}
+
 
 
 impl Compression {
 
-    /// Compress the image section of bytes.
+    /// Compress the pixels of a single block, producing the bytes that are stored in a chunk of the file.
+    /// This can be used without any chunk reader or writer, for example to cache chunks in a different process.
+    ///
+    /// The `pixel_section` is the position and size of the block, relative to the data window of the layer,
+    /// as computed by `Header::get_absolute_block_pixel_coordinates`. It must not be larger than the blocks of the header.
+    /// The header is required for the channel list, and for the compression options
+    /// that are stored in the header, such as the zip level and the custom codecs.
+    ///
+    /// The uncompressed bytes are interleaved line by line:
+    /// for each line of the section, from top to bottom, the samples of each channel in the order of the channel list
+    /// are stored contiguously, each sample in the native byte order of the current machine.
+    /// Subsampled channels only contain the lines where `y % sampling.y() == 0`,
+    /// with one sample for every `sampling.x()` pixels.
+    ///
+    /// If compressing does not make the block smaller, the uncompressed little-endian bytes are returned instead,
+    /// which is also how they will be recognized when decompressing.
+    /// Returns an error for sections larger than a block, and for compression methods that are not available.
     pub fn compress_image_section(self, header: &Header, uncompressed_native_endian: ByteVec, pixel_section: IntegerBounds) -> Result<ByteVec> {
-        let max_tile_size = header.max_block_pixel_size();
-
-        assert!(pixel_section.validate(Some(max_tile_size)).is_ok(), "decompress tile coordinate bug");
-        if header.deep { assert!(self.supports_deep_data()) }
+        validate_image_section(self, header, pixel_section)?;
 
         use self::Compression::*;
         let compressed_little_endian = match self {
@@ -174,14 +253,30 @@ impl Compression {
             },
 
             // we need to clone here, because we might have to fallback to the uncompressed data later (when compressed data is larger than raw data)
-            ZIP16 => zip::compress_bytes(&header.channels, uncompressed_native_endian.clone(), pixel_section),
-            ZIP1 => zip::compress_bytes(&header.channels, uncompressed_native_endian.clone(), pixel_section),
+            #[cfg(feature = "zip")]
+            ZIP16 => zip::compress_bytes(&header.channels, uncompressed_native_endian.clone(), pixel_section, header.zip_compression_level),
+            #[cfg(feature = "zip")]
+            ZIP1 => zip::compress_bytes(&header.channels, uncompressed_native_endian.clone(), pixel_section, header.zip_compression_level),
+            #[cfg(feature = "rle")]
             RLE => rle::compress_bytes(&header.channels, uncompressed_native_endian.clone(), pixel_section),
+            #[cfg(feature = "piz")]
             PIZ => piz::compress(&header.channels, uncompressed_native_endian.clone(), pixel_section),
+            #[cfg(feature = "pxr24")]
             PXR24 => pxr24::compress(&header.channels, uncompressed_native_endian.clone(), pixel_section),
+            #[cfg(feature = "b44")]
             B44 => b44::compress(&header.channels, uncompressed_native_endian.clone(), pixel_section, false),
+            #[cfg(feature = "b44")]
             B44A => b44::compress(&header.channels, uncompressed_native_endian.clone(), pixel_section, true),
-            _ => return Err(Error::unsupported(format!("yet unimplemented compression method: {}", self)))
+            #[cfg(feature = "dwa")]
+            DWAA(level) => dwa::compress(&header.channels, uncompressed_native_endian.clone(), pixel_section, level.or(header.own_attributes.dwa_compression_level), false),
+            #[cfg(feature = "dwa")]
+            DWAB(level) => dwa::compress(&header.channels, uncompressed_native_endian.clone(), pixel_section, level.or(header.own_attributes.dwa_compression_level), true),
+            Custom(id) => custom_codec(header, id)?.compress(
+                header, convert_current_to_little_endian(uncompressed_native_endian.clone(), &header.channels, pixel_section), pixel_section
+            ),
+
+            #[allow(unreachable_patterns)]
+            disabled => return Err(disabled.disabled_at_compile_time()),
         };
 
         let compressed_little_endian = compressed_little_endian.map_err(|_|
@@ -198,31 +293,76 @@ impl Compression {
         }
     }
 
-    /// Decompress the image section of bytes.
+    /// Decompress the bytes of a single chunk, producing the pixels of the block.
+    /// This is the inverse of `compress_image_section`, see there for the meaning of the parameters,
+    /// and for the interleaved byte layout of the returned pixels.
+    /// The pixels are returned in the native byte order of the current machine.
+    ///
+    /// If `pedantic` is true, compressed data that contains superfluous bytes after
+    /// the expected pixels is rejected, instead of ignoring the superfluous bytes.
+    /// Returns an error if the data is malformed or decompresses to an unexpected number of bytes.
     pub fn decompress_image_section(self, header: &Header, compressed: ByteVec, pixel_section: IntegerBounds, pedantic: bool) -> Result<ByteVec> {
-        let max_tile_size = header.max_block_pixel_size();
+        self.decompress_image_section_from_cow(header, Cow::Owned(compressed), pixel_section, pedantic, None)
+    }
 
-        assert!(pixel_section.validate(Some(max_tile_size)).is_ok(), "decompress tile coordinate bug");
-        if header.deep { assert!(self.supports_deep_data()) }
+    /// Decompress the image section of bytes, reusing a buffer from the pool for the decompressed bytes where possible.
+    /// Currently, only ZIP compressed data is decompressed into a reused buffer.
+    pub fn decompress_image_section_with_buffer_pool(
+        self, header: &Header, compressed: ByteVec, pixel_section: IntegerBounds,
+        pedantic: bool, buffer_pool: &BlockBufferPool
+    ) -> Result<ByteVec>
+    {
+        self.decompress_image_section_from_cow(header, Cow::Owned(compressed), pixel_section, pedantic, Some(buffer_pool))
+    }
 
-        let expected_byte_size = pixel_section.size.area() * header.channels.bytes_per_pixel; // FIXME this needs to account for subsampling anywhere
+    /// Decompress the image section of bytes, borrowing the compressed bytes.
+    /// Useful when the compressed bytes are part of a larger buffer, such as a memory-mapped file.
+    /// Only copies the bytes if they are not compressed.
+    pub fn decompress_image_section_from_slice(self, header: &Header, compressed: Bytes<'_>, pixel_section: IntegerBounds, pedantic: bool) -> Result<ByteVec> {
+        self.decompress_image_section_from_cow(header, Cow::Borrowed(compressed), pixel_section, pedantic, None)
+    }
+
+    // owned uncompressed bytes are moved instead of copied
+    #[cfg_attr(not(feature = "zip"), allow(unused_variables))] // only zip uses the buffer pool
+    fn decompress_image_section_from_cow(
+        self, header: &Header, compressed: Cow<'_, [u8]>, pixel_section: IntegerBounds,
+        pedantic: bool, buffer_pool: Option<&BlockBufferPool>
+    ) -> Result<ByteVec>
+    {
+        validate_image_section(self, header, pixel_section)?;
+
+        let expected_byte_size = header.channels.byte_size_of_section(pixel_section);
 
         // note: always true where self == Uncompressed
         if compressed.len() == expected_byte_size {
             // the compressed data was larger than the raw data, so the small raw data has been written
-            Ok(convert_little_endian_to_current(compressed, &header.channels, pixel_section))
+            Ok(convert_little_endian_to_current(compressed.into_owned(), &header.channels, pixel_section))
         }
         else {
             use self::Compression::*;
             let bytes = match self {
-                Uncompressed => Ok(convert_little_endian_to_current(compressed, &header.channels, pixel_section)),
-                ZIP16 => zip::decompress_bytes(&header.channels, compressed, pixel_section, expected_byte_size, pedantic),
-                ZIP1 => zip::decompress_bytes(&header.channels, compressed, pixel_section, expected_byte_size, pedantic),
-                RLE => rle::decompress_bytes(&header.channels, compressed, pixel_section, expected_byte_size, pedantic),
-                PIZ => piz::decompress(&header.channels, compressed, pixel_section, expected_byte_size, pedantic),
-                PXR24 => pxr24::decompress(&header.channels, compressed, pixel_section, expected_byte_size, pedantic),
-                B44 | B44A => b44::decompress(&header.channels, compressed, pixel_section, expected_byte_size, pedantic),
-                _ => return Err(Error::unsupported(format!("yet unimplemented compression method: {}", self)))
+                Uncompressed => Ok(convert_little_endian_to_current(compressed.into_owned(), &header.channels, pixel_section)),
+                #[cfg(feature = "zip")]
+                ZIP16 | ZIP1 => match buffer_pool {
+                    Some(buffer_pool) => zip::decompress_bytes_into_pooled_buffer(&header.channels, &compressed, pixel_section, expected_byte_size, pedantic, buffer_pool),
+                    None => zip::decompress_bytes(&header.channels, &compressed, pixel_section, expected_byte_size, pedantic),
+                },
+                #[cfg(feature = "rle")]
+                RLE => rle::decompress_bytes(&header.channels, &compressed, pixel_section, expected_byte_size, pedantic),
+                #[cfg(feature = "piz")]
+                PIZ => piz::decompress(&header.channels, &compressed, pixel_section, expected_byte_size, pedantic),
+                #[cfg(feature = "pxr24")]
+                PXR24 => pxr24::decompress(&header.channels, &compressed, pixel_section, expected_byte_size, pedantic),
+                #[cfg(feature = "b44")]
+                B44 | B44A => b44::decompress(&header.channels, &compressed, pixel_section, expected_byte_size, pedantic),
+                #[cfg(feature = "dwa")]
+                DWAA(_) | DWAB(_) => dwa::decompress(&header.channels, &compressed, pixel_section, expected_byte_size, pedantic),
+                Custom(id) => custom_codec(header, id)?
+                    .decompress(header, &compressed, pixel_section, expected_byte_size, pedantic)
+                    .map(|bytes| convert_little_endian_to_current(bytes, &header.channels, pixel_section)),
+
+                #[allow(unreachable_patterns)]
+                disabled => return Err(disabled.disabled_at_compile_time()),
             };
 
             // map all errors to compression errors
@@ -245,6 +385,83 @@ impl Compression {
         }
     }
 
+    /// Compress either the sample count table or the samples of a deep chunk.
+    /// Deep data is not interpreted by the compression method, so the bytes must already be
+    /// in little-endian byte order, exactly as they would be stored without compression.
+    /// The header is only used for the compression options, such as the zip level.
+    ///
+    /// If compressing does not make the bytes smaller, the uncompressed bytes are returned instead,
+    /// which is also how they will be recognized when decompressing.
+    /// Only compression methods that support deep data can be used, see `supports_deep_data`.
+    #[cfg_attr(not(feature = "zip"), allow(unused_variables))] // only zip uses the header
+    #[cfg_attr(not(any(feature = "rle", feature = "zip")), allow(unreachable_code))] // every method returns early
+    pub fn compress_deep_bytes(self, header: &Header, uncompressed_little_endian: ByteVec) -> Result<ByteVec> {
+        use self::Compression::*;
+        let compressed: ByteVec = match self {
+            Uncompressed => return Ok(uncompressed_little_endian),
+
+            // we need to clone here, because we might have to fallback to the uncompressed data later
+            #[cfg(feature = "zip")]
+            ZIP16 | ZIP1 => zip::compress_reordered_bytes(
+                uncompressed_little_endian.clone(),
+                header.zip_compression_level.unwrap_or(zip::DEFAULT_COMPRESSION_LEVEL)
+            ),
+
+            #[cfg(feature = "rle")]
+            RLE => rle::compress_reordered_bytes(uncompressed_little_endian.clone()),
+
+            #[allow(unreachable_patterns)]
+            disabled @ (ZIP16 | ZIP1 | RLE) => return Err(disabled.disabled_at_compile_time()),
+            _ => return Err(Error::invalid("compression method does not support deep data")),
+        };
+
+        // compressed data of the same size would be mistaken for uncompressed data
+        if compressed.len() < uncompressed_little_endian.len() { Ok(compressed) }
+        else { Ok(uncompressed_little_endian) }
+    }
+
+    /// Decompress either the sample count table or the samples of a deep chunk.
+    /// Deep data is not interpreted by the compression method, so the returned bytes
+    /// are in little-endian byte order, exactly as they would be stored without compression.
+    /// Only compression methods that support deep data can be used, see `supports_deep_data`.
+    #[cfg_attr(not(feature = "rle"), allow(unused_variables))] // only rle checks for superfluous bytes
+    #[cfg_attr(not(any(feature = "rle", feature = "zip")), allow(unreachable_code))] // every method returns an error
+    pub fn decompress_deep_bytes(self, compressed: Bytes<'_>, expected_byte_size: usize, pedantic: bool) -> Result<ByteVec> {
+        // the compressed data was larger than the raw data, so the small raw data has been written
+        if compressed.len() == expected_byte_size {
+            return Ok(compressed.to_vec());
+        }
+
+        // neither deflate nor run length encoding can expand the data by more than this factor,
+        // which prevents allocating huge amounts of memory for corrupt byte sizes
+        if expected_byte_size / 1032 > compressed.len() {
+            return Err(Error::invalid("deep data byte size"));
+        }
+
+        use self::Compression::*;
+        let bytes: Result<ByteVec> = match self {
+            #[cfg(feature = "zip")]
+            ZIP16 | ZIP1 => zip::decompress_reordered_bytes(compressed, expected_byte_size),
+            #[cfg(feature = "rle")]
+            RLE => rle::decompress_reordered_bytes(compressed, expected_byte_size, pedantic),
+
+            #[allow(unreachable_patterns)]
+            disabled @ (ZIP16 | ZIP1 | RLE) => return Err(disabled.disabled_at_compile_time()),
+            Uncompressed => return Err(Error::invalid("uncompressed deep data byte size")),
+            _ => return Err(Error::invalid("compression method does not support deep data")),
+        };
+
+        let bytes = bytes.map_err(|error| Error::invalid(format!(
+            "compressed {:?} deep data ({})", self, error.to_string()
+        )))?;
+
+        if bytes.len() != expected_byte_size {
+            Err(Error::invalid("decompressed deep data"))
+        }
+
+        else { Ok(bytes) }
+    }
+
     /// For scan line images and deep scan line images, one or more scan lines may be
     /// stored together as a scan line block. The number of scan lines per block
     /// depends on how the pixel data are compressed.
@@ -252,7 +469,7 @@ impl Compression {
         use self::Compression::*;
         match self {
             Uncompressed | RLE   | ZIP1    => 1,
-            ZIP16 | PXR24                  => 16,
+            ZIP16 | PXR24 | Custom(_)      => 16,
             PIZ   | B44   | B44A | DWAA(_) => 32,
             DWAB(_)                        => 256,
         }
@@ -262,7 +479,7 @@ impl Compression {
     pub fn supports_deep_data(self) -> bool {
         use self::Compression::*;
         match self {
-            Uncompressed | RLE | ZIP1 => true,
+            Uncompressed | RLE | ZIP1 | ZIP16 => true,
             _ => false,
         }
     }
@@ -275,7 +492,7 @@ impl Compression {
             PXR24 => sample_type != SampleType::F32, // pxr reduces f32 to f24
             B44 | B44A => sample_type != SampleType::F16, // b44 only compresses f16 values, others are left uncompressed
             Uncompressed | RLE | ZIP1 | ZIP16 | PIZ => true,
-            DWAB(_) | DWAA(_) => false,
+            DWAB(_) | DWAA(_) | Custom(_) => false,
         }
     }
 
@@ -285,7 +502,7 @@ impl Compression {
         use self::Compression::*;
         match self {
             Uncompressed | RLE | ZIP1 | ZIP16 | PIZ => false,
-            PXR24 | B44 | B44A | DWAB(_) | DWAA(_)  => true,
+            PXR24 | B44 | B44A | DWAB(_) | DWAA(_) | Custom(_) => true,
         }
     }
 
@@ -299,6 +516,93 @@ impl Compression {
         }
     }
 
+    /// Check whether each channel is stored well with this compression method.
+    /// Returns a warning for the first channel that would lose precision or would not be compressed at all.
+    /// Use `warnings_for_channels` to obtain the warnings for all channels.
+    /// Custom compression methods are not checked, as their behaviour is unknown.
+    pub fn validate_for_channels(&self, channels: &ChannelList) -> std::result::Result<(), CompressionWarning> {
+        match self.warnings_for_channels(channels).into_iter().next() {
+            Some(warning) => Err(warning),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns a warning for each channel that would lose precision or would not be compressed at all
+    /// with this compression method, in the order of the channel list.
+    /// Custom compression methods are not checked, as their behaviour is unknown.
+    pub fn warnings_for_channels(&self, channels: &ChannelList) -> Vec<CompressionWarning> {
+        channels.list.iter()
+            .filter_map(|channel| self.effect_on_channel(channel).map(|reason| CompressionWarning {
+                compression: *self, channel: channel.name.clone(),
+                sample_type: channel.sample_type, reason,
+            }))
+            .collect()
+    }
+
+    /// What this compression does to the samples of the channel, if they are not simply compressed losslessly.
+    fn effect_on_channel(self, channel: &ChannelDescription) -> Option<CompressionWarningReason> {
+        use self::Compression::*;
+        use self::CompressionWarningReason::*;
+
+        match (self, channel.sample_type) {
+            (Uncompressed | RLE | ZIP1 | ZIP16 | PIZ | Custom(_), _) => None,
+
+            (PXR24, SampleType::F32) => Some(Lossy), // pxr reduces f32 to f24
+            (PXR24, _) => None,
+
+            (B44 | B44A, SampleType::F16) => Some(Lossy),
+            (B44 | B44A, _) => Some(NotCompressed), // b44 only compresses f16 values
+
+            // only f16 and f32 channels with color names are compressed lossy,
+            // all other channels, such as alpha, depth, and u32 channels, are compressed without loss
+            (DWAA(_) | DWAB(_), SampleType::U32) => None,
+            (DWAA(_) | DWAB(_), _) if is_lossy_dwa_channel_name(&channel.name) => Some(Lossy),
+            (DWAA(_) | DWAB(_), _) => None,
+        }
+    }
+
+}
+
+/// Whether the dwa compression methods store the channel using the lossy DCT, as described by their default rules.
+/// Only the part of the name after the last dot is considered, for example `R` in `diffuse.R`.
+fn is_lossy_dwa_channel_name(name: &Text) -> bool {
+    let name = name.as_slice();
+    let suffix = name.iter().rposition(|&byte| byte == b'.').map_or(name, |dot_index| &name[dot_index + 1 ..]);
+    [&b"R"[..], b"G", b"B", b"Y", b"BY", b"RY"].contains(&suffix)
+}
+
+/// Whether the section can be compressed or decompressed as a single block of the header.
+fn validate_image_section(compression: Compression, header: &Header, pixel_section: IntegerBounds) -> UnitResult {
+    if header.deep && !compression.supports_deep_data() {
+        return Err(Error::invalid("compression method does not support deep data"));
+    }
+
+    pixel_section.validate(Some(header.max_block_pixel_size()))
+        .map_err(|_| Error::invalid("image section bounds"))
+}
+
+fn custom_codec(header: &Header, id: u8) -> Result<&dyn BlockCodec> {
+    header.custom_codecs.get(id).ok_or(Error::unsupported("unknown compression method"))
+}
+
+impl Compression {
+
+    /// The error for a compression method whose cargo feature is not enabled.
+    #[allow(unused)] // unused when all compression features are enabled
+    fn disabled_at_compile_time(self) -> Error {
+        use self::Compression::*;
+        let feature = match self {
+            ZIP1 | ZIP16 => "zip",
+            RLE => "rle",
+            PIZ => "piz",
+            PXR24 => "pxr24",
+            B44 | B44A => "b44",
+            DWAA(_) | DWAB(_) => "dwa",
+            Uncompressed | Custom(_) => unreachable!("compression {} cannot be disabled", self),
+        };
+
+        Error::unsupported(format!("compression '{}' disabled at compile time", feature))
+    }
 }
 
 // see https://github.com/AcademySoftwareFoundation/openexr/blob/6a9f8af6e89547bcd370ae3cec2b12849eee0b54/OpenEXR/IlmImf/ImfMisc.cpp#L1456-L1541
@@ -384,11 +688,95 @@ fn mod_p(x: i32, y: i32) -> i32 {
     x - y * div_p(x, y)
 }
 
+/// Blocks with fewer bytes are always processed on a single thread,
+/// as splitting them into bands would cost more than it saves.
+#[cfg(any(feature = "zip", feature = "rle", feature = "piz"))]
+const MIN_BAND_BYTE_SIZE: usize = 512 * 1024;
+
+/// The byte size of the bands that a huge block can be split into, to be processed by multiple threads.
+/// Only splits the block if it is being processed on a thread pool, for example while
+/// decompressing in parallel, so that idle threads can help with a single huge block.
+/// The band size is always even.
+#[cfg(any(feature = "zip", feature = "rle", feature = "piz"))]
+fn parallel_band_size(byte_size: usize) -> Option<usize> {
+    let thread_count = rayon_core::current_num_threads();
+    let is_in_thread_pool = rayon_core::current_thread_index().is_some();
+
+    if !is_in_thread_pool || thread_count < 2 || byte_size < 2 * MIN_BAND_BYTE_SIZE {
+        return None;
+    }
+
+    let band_size = (byte_size / thread_count).max(MIN_BAND_BYTE_SIZE);
+    Some(band_size + band_size % 2)
+}
+
+/// Call the function for each item, on the threads of the current thread pool if `parallel` is true.
+#[cfg(any(feature = "zip", feature = "rle", feature = "piz"))]
+fn for_each_maybe_parallel<T: Send>(items: impl Iterator<Item=T>, parallel: bool, function: impl Fn(T) + Sync) {
+    if parallel {
+        let function = &function;
+        let items: Vec<T> = items.collect();
+
+        rayon_core::scope(|scope| for item in items {
+            scope.spawn(move |_| function(item));
+        });
+    }
+    else {
+        items.for_each(function);
+    }
+}
+
+/// Call the function for each item, on the threads of the current thread pool if `parallel` is true.
+/// Returns an error if the function failed for any item.
+#[cfg(feature = "piz")]
+fn try_for_each_maybe_parallel<T: Send>(items: impl Iterator<Item=T>, parallel: bool, function: impl Fn(T) -> Result<()> + Sync) -> Result<()> {
+    let any_error = std::sync::Mutex::new(None);
+
+    for_each_maybe_parallel(items, parallel, |item| {
+        if let Err(error) = function(item) {
+            any_error.lock().expect("poisoned error lock").get_or_insert(error);
+        }
+    });
+
+    match any_error.into_inner().expect("poisoned error lock") {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
 /// A collection of functions used to prepare data for compression.
+/// Huge buffers are split into bands, which are processed on multiple threads when called from a thread pool.
+#[cfg(any(feature = "zip", feature = "rle"))]
 mod optimize_bytes {
+    use super::{parallel_band_size, for_each_maybe_parallel};
 
     /// Integrate over all differences to the previous value in order to reconstruct sample values.
     pub fn differences_to_samples(buffer: &mut [u8]) {
+        match parallel_band_size(buffer.len()) {
+            None => differences_to_samples_in_band(buffer),
+            Some(band_size) => {
+                // integrate each band on its own, as if the first difference of the band was a sample
+                for_each_maybe_parallel(buffer.chunks_mut(band_size), true, differences_to_samples_in_band);
+
+                // the first value of each band was actually a difference to the last sample of the previous band,
+                // so all samples in the band are off by the same amount
+                let mut previous_last_sample = None;
+                let offsets: Vec<u8> = buffer.chunks(band_size).map(|band| {
+                    let offset = previous_last_sample.map_or(0, |sample: u8| sample.wrapping_sub(128));
+                    previous_last_sample = band.last().map(|&last| last.wrapping_add(offset));
+                    offset
+                }).collect();
+
+                let bands = buffer.chunks_mut(band_size).zip(offsets).filter(|&(_, offset)| offset != 0);
+                for_each_maybe_parallel(bands, true, |(band, offset)| {
+                    for sample in band { *sample = sample.wrapping_add(offset); }
+                });
+            }
+        }
+    }
+
+    fn differences_to_samples_in_band(buffer: &mut [u8]) {
+
         // The naive implementation is very simple:
         //
         // for index in 1..buffer.len() {
@@ -424,6 +812,26 @@ mod optimize_bytes {
 
     /// Derive over all values in order to produce differences to the previous value.
     pub fn samples_to_differences(buffer: &mut [u8]){
+        match parallel_band_size(buffer.len()) {
+            None => samples_to_differences_in_band(buffer),
+            Some(band_size) => {
+                // the first value of each band is a difference to the last sample of the previous band
+                let previous_last_samples = std::iter::once(None)
+                    .chain(buffer.chunks(band_size).map(|band| band.last().copied()))
+                    .collect::<Vec<_>>();
+
+                for_each_maybe_parallel(buffer.chunks_mut(band_size).zip(previous_last_samples), true, |(band, previous)| {
+                    samples_to_differences_in_band(band);
+
+                    if let Some(previous) = previous {
+                        band[0] = band[0].wrapping_sub(previous).wrapping_add(128);
+                    }
+                });
+            }
+        }
+    }
+
+    fn samples_to_differences_in_band(buffer: &mut [u8]){
         // naive version:
         // for index in (1..buffer.len()).rev() {
         //     buffer[index] = (buffer[index] as i32 - buffer[index - 1] as i32 + 128) as u8;
@@ -518,77 +926,128 @@ mod optimize_bytes {
 
     /// Interleave the bytes such that the second half of the array is every other byte.
     pub fn interleave_byte_blocks(separated: &mut [u8]) {
-        with_reused_buffer(separated.len(), |interleaved| {
-
-            // Split the two halves that we are going to interleave.
-            let (first_half, second_half) = separated.split_at((separated.len() + 1) / 2);
-            // The first half can be 1 byte longer than the second if the length of the input is odd,
-            // but the loop below only processes numbers in pairs.
-            // To handle it, preserve the last element of the first slice, to be handled after the loop.
-            let first_half_last = first_half.last();
-            // Truncate the first half to match the lenght of the second one; more optimizer-friendly
-            let first_half_iter = &first_half[..second_half.len()];
-
-            // Main loop that performs the interleaving
-            for ((first, second), interleaved) in first_half_iter.iter().zip(second_half.iter())
-                .zip(interleaved.chunks_exact_mut(2)) {
-                    // The length of each chunk is known to be 2 at compile time,
-                    // and each index is also a constant.
-                    // This allows the compiler to remove the bounds checks.
-                    interleaved[0] = *first;
-                    interleaved[1] = *second;
-            }
-
-            // If the length of the slice was odd, restore the last element of the first half that we saved
-            if interleaved.len() % 2 == 1 {
-                if let Some(value) = first_half_last {
-                    // we can unwrap() here because we just checked that the lenght is non-zero:
-                    // `% 2 == 1` will fail for zero
-                    *interleaved.last_mut().unwrap() = *value;
+        match parallel_band_size(separated.len()) {
+            None => with_reused_buffer(separated.len(), |interleaved| {
+                // Split the two halves that we are going to interleave.
+                let (first_half, second_half) = separated.split_at((separated.len() + 1) / 2);
+                interleave_band(first_half, second_half, interleaved);
+
+                // write out the results
+                separated.copy_from_slice(&interleaved);
+            }),
+
+            Some(band_size) => {
+                let mut interleaved = vec![0_u8; separated.len()];
+
+                {
+                    let (first_half, second_half) = separated.split_at((separated.len() + 1) / 2);
+
+                    // each band of the result consists of a band of each half
+                    let bands = interleaved.chunks_mut(band_size).enumerate().map(|(index, band)| {
+                        let start = index * band_size / 2;
+                        let first = &first_half[start .. start + (band.len() + 1) / 2];
+                        let second = &second_half[start .. start + band.len() / 2];
+                        (first, second, band)
+                    });
+
+                    for_each_maybe_parallel(bands, true, |(first, second, band)| interleave_band(first, second, band));
                 }
-            }
 
-            // write out the results
-            separated.copy_from_slice(&interleaved);
-        });
+                copy_in_bands(&interleaved, separated, band_size);
+            }
+        }
     }
 
-/// Separate the bytes such that the second half contains every other byte.
-/// This performs deinterleaving - the inverse of interleaving.
-pub fn separate_bytes_fragments(source: &mut [u8]) {
-    with_reused_buffer(source.len(), |separated| {
-
-        // Split the two halves that we are going to interleave.
-        let (first_half, second_half) = separated.split_at_mut((source.len() + 1) / 2);
+    /// Interleave the two halves into the target. The first half may be one byte longer than the second half.
+    fn interleave_band(first_half: &[u8], second_half: &[u8], interleaved: &mut [u8]) {
         // The first half can be 1 byte longer than the second if the length of the input is odd,
         // but the loop below only processes numbers in pairs.
-        // To handle it, preserve the last element of the input, to be handled after the loop.
-        let last = source.last();
-        let first_half_iter = &mut first_half[..second_half.len()];
-
-        // Main loop that performs the deinterleaving
-        for ((first, second), interleaved) in first_half_iter.iter_mut().zip(second_half.iter_mut())
-            .zip(source.chunks_exact(2)) {
+        // To handle it, preserve the last element of the first slice, to be handled after the loop.
+        let first_half_last = first_half.last();
+        // Truncate the first half to match the lenght of the second one; more optimizer-friendly
+        let first_half_iter = &first_half[..second_half.len()];
+
+        // Main loop that performs the interleaving
+        for ((first, second), interleaved) in first_half_iter.iter().zip(second_half.iter())
+            .zip(interleaved.chunks_exact_mut(2)) {
                 // The length of each chunk is known to be 2 at compile time,
                 // and each index is also a constant.
                 // This allows the compiler to remove the bounds checks.
-                *first = interleaved[0];
-                *second = interleaved[1];
+                interleaved[0] = *first;
+                interleaved[1] = *second;
         }
 
-        // If the length of the slice was odd, restore the last element of the input that we saved
-        if source.len() % 2 == 1 {
-            if let Some(value) = last {
+        // If the length of the slice was odd, restore the last element of the first half that we saved
+        if interleaved.len() % 2 == 1 {
+            if let Some(value) = first_half_last {
                 // we can unwrap() here because we just checked that the lenght is non-zero:
                 // `% 2 == 1` will fail for zero
-                *first_half.last_mut().unwrap() = *value;
+                *interleaved.last_mut().unwrap() = *value;
             }
         }
+    }
 
-        // write out the results
-        source.copy_from_slice(&separated);
-    });
-}
+    /// Separate the bytes such that the second half contains every other byte.
+    /// This performs deinterleaving - the inverse of interleaving.
+    pub fn separate_bytes_fragments(source: &mut [u8]) {
+        match parallel_band_size(source.len()) {
+            None => with_reused_buffer(source.len(), |separated| {
+                // Split the two halves that we are going to interleave.
+                let (first_half, second_half) = separated.split_at_mut((source.len() + 1) / 2);
+                separate_band(source, first_half, second_half);
+
+                // write out the results
+                source.copy_from_slice(&separated);
+            }),
+
+            Some(band_size) => {
+                let mut separated = vec![0_u8; source.len()];
+
+                {
+                    let (mut first_half, mut second_half) = separated.split_at_mut((source.len() + 1) / 2);
+
+                    // each band of the source is separated into a band of each half
+                    let bands = source.chunks(band_size).map(|band| {
+                        let (first, remaining_first) = std::mem::take(&mut first_half).split_at_mut((band.len() + 1) / 2);
+                        let (second, remaining_second) = std::mem::take(&mut second_half).split_at_mut(band.len() / 2);
+                        first_half = remaining_first;
+                        second_half = remaining_second;
+                        (band, first, second)
+                    });
+
+                    for_each_maybe_parallel(bands, true, |(band, first, second)| separate_band(band, first, second));
+                }
+
+                copy_in_bands(&separated, source, band_size);
+            }
+        }
+    }
+
+    /// Separate the bytes of the source into the two halves. The first half may be one byte longer than the second half.
+    fn separate_band(source: &[u8], first_half: &mut [u8], second_half: &mut [u8]) {
+        // The first half can be 1 byte longer than the second if the length of the input is odd,
+        // but the loop below only processes numbers in pairs.
+        // To handle it, split off the last element of the input, to be handled after the loop.
+        let (pairs, last) = source.split_at(second_half.len() * 2);
+
+        // Main loop that performs the deinterleaving.
+        // Indexing both halves with the same index lets the compiler vectorize this loop,
+        // which it does not do when zipping three iterators.
+        for (index, pair) in pairs.chunks_exact(2).enumerate() {
+            first_half[index] = pair[0];
+            second_half[index] = pair[1];
+        }
+
+        // If the length of the slice was odd, restore the last element of the input that we saved
+        if let Some(&value) = last.first() {
+            first_half[second_half.len()] = value;
+        }
+    }
+
+    fn copy_in_bands(source: &[u8], target: &mut [u8], band_size: usize) {
+        let bands = target.chunks_mut(band_size).zip(source.chunks(band_size));
+        for_each_maybe_parallel(bands, true, |(target, source)| target.copy_from_slice(source));
+    }
 
 
     #[cfg(test)]
@@ -616,6 +1075,23 @@ pub fn separate_bytes_fragments(source: &mut [u8]) {
             assert_eq!(source, modified);
         }
 
+        #[test]
+        fn huge_buffers_in_bands_match_single_band(){
+            // odd size, to test the last band being shorter and of odd length
+            let source: Vec<u8> = (0 .. 5 * super::super::MIN_BAND_BYTE_SIZE + 3).map(|_| rand::random()).collect();
+            let pool = rayon_core::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+
+            let single_band = |function: fn(&mut [u8])| { let mut bytes = source.clone(); function(&mut bytes); bytes };
+            let in_bands = |function: fn(&mut [u8])| pool.install(|| single_band(function));
+
+            for function in [
+                super::separate_bytes_fragments, super::interleave_byte_blocks,
+                super::samples_to_differences, super::differences_to_samples,
+            ] {
+                assert_eq!(single_band(function), in_bands(function));
+            }
+        }
+
     }
 }
 
@@ -650,6 +1126,132 @@ pub mod test {
         );
     }
 
+    #[test]
+    #[cfg(all(feature = "rle", feature = "zip"))]
+    fn decompress_deep_bytes_of_each_deep_compression() {
+        let little_endian: ByteVec = (0 .. 3000_u32).flat_map(|index| (index / 7).to_le_bytes()).collect();
+
+        let rle_compressed = rle::compress_reordered_bytes(little_endian.clone());
+        let zip_compressed = zip::compress_reordered_bytes(little_endian.clone(), 4);
+
+        let decompress = |compression: Compression, compressed: &[u8]|
+            compression.decompress_deep_bytes(compressed, little_endian.len(), true);
+
+        assert_eq!(decompress(Compression::Uncompressed, &little_endian).unwrap(), little_endian);
+        assert_eq!(decompress(Compression::RLE, &rle_compressed).unwrap(), little_endian);
+        assert_eq!(decompress(Compression::ZIP1, &zip_compressed).unwrap(), little_endian);
+        assert_eq!(decompress(Compression::ZIP16, &zip_compressed).unwrap(), little_endian);
+
+        assert!(decompress(Compression::PIZ, &zip_compressed).is_err());
+        assert!(decompress(Compression::ZIP1, &zip_compressed[.. 10]).is_err());
+        assert!(decompress(Compression::Uncompressed, &little_endian[1..]).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "rle", feature = "zip"))]
+    fn compress_deep_bytes_of_each_deep_compression() {
+        let header = Header::new(
+            Text::from("deep"), (4, 4),
+            smallvec![ ChannelDescription::new("Z", SampleType::F32, false) ]
+        );
+
+        let compressible: ByteVec = (0 .. 3000_u32).flat_map(|index| (index / 7).to_le_bytes()).collect();
+        let incompressible: ByteVec = vec![ 1, 2, 3 ];
+
+        for &compression in &[ Compression::Uncompressed, Compression::RLE, Compression::ZIP1, Compression::ZIP16 ] {
+            for bytes in &[ &compressible, &incompressible ] {
+                let compressed = compression.compress_deep_bytes(&header, (*bytes).clone()).unwrap();
+                assert!(compressed.len() <= bytes.len());
+
+                let decompressed = compression.decompress_deep_bytes(&compressed, bytes.len(), true).unwrap();
+                assert_eq!(&decompressed, *bytes);
+            }
+        }
+
+        assert_eq!(Compression::ZIP1.compress_deep_bytes(&header, incompressible.clone()).unwrap(), incompressible);
+        assert!(Compression::PIZ.compress_deep_bytes(&header, compressible).is_err());
+    }
+
+    #[test]
+    fn classify_compression_for_each_sample_type() {
+        use self::Compression::*;
+        use self::CompressionWarningReason::*;
+
+        let sample_types = [SampleType::F16, SampleType::F32, SampleType::U32];
+
+        // the expected reason for each sample type, in the order f16, f32, u32
+        let expected: [(Compression, [Option<CompressionWarningReason>; 3]); 11] = [
+            (Uncompressed, [None, None, None]),
+            (RLE, [None, None, None]),
+            (ZIP1, [None, None, None]),
+            (ZIP16, [None, None, None]),
+            (PIZ, [None, None, None]),
+            (PXR24, [None, Some(Lossy), None]),
+            (B44, [Some(Lossy), Some(NotCompressed), Some(NotCompressed)]),
+            (B44A, [Some(Lossy), Some(NotCompressed), Some(NotCompressed)]),
+            (DWAA(None), [None, None, None]),
+            (DWAB(Some(45.0)), [None, None, None]),
+            (Custom(42), [None, None, None]),
+        ];
+
+        for (compression, reasons) in expected {
+            for (sample_type, reason) in sample_types.iter().zip(reasons) {
+                let channels = ChannelList::new(smallvec![ ChannelDescription::new("X", *sample_type, true) ]);
+
+                let expected = reason.map(|reason| CompressionWarning {
+                    compression, channel: Text::from("X"), sample_type: *sample_type, reason
+                });
+
+                assert_eq!(compression.validate_for_channels(&channels).err(), expected, "{} with {:?}", compression, sample_type);
+            }
+        }
+
+        // dwa only compresses color channels lossy
+        let dwa_channels = [
+            ("R", SampleType::F16, true), ("diffuse.G", SampleType::F32, true), ("B", SampleType::U32, false),
+            ("Y", SampleType::F16, true), ("RY", SampleType::F16, true), ("BY", SampleType::F16, true),
+            ("A", SampleType::F16, false), ("Z", SampleType::F32, false), ("X", SampleType::F16, false),
+            ("r", SampleType::F16, false), ("R.Z", SampleType::F16, false), ("GR", SampleType::F16, false),
+        ];
+
+        for compression in [DWAA(None), DWAB(None)] {
+            for (name, sample_type, is_lossy) in dwa_channels {
+                let channels = ChannelList::new(smallvec![ ChannelDescription::new(name, sample_type, true) ]);
+                let reason = compression.validate_for_channels(&channels).err().map(|warning| warning.reason);
+                assert_eq!(reason, if is_lossy { Some(Lossy) } else { None }, "{} with channel {}", compression, name);
+            }
+        }
+    }
+
+    #[test]
+    fn validate_mixed_channels_reports_the_affected_channel() {
+        let channels = ChannelList::new(smallvec![
+            ChannelDescription::new("B", SampleType::F16, true),
+            ChannelDescription::new("ID", SampleType::U32, false),
+            ChannelDescription::new("Z", SampleType::F32, false),
+        ]);
+
+        assert_eq!(Compression::ZIP16.validate_for_channels(&channels), Ok(()));
+        assert_eq!(Compression::DWAA(None).validate_for_channels(&channels).unwrap_err().channel, Text::from("B"));
+
+        let pxr24 = Compression::PXR24.validate_for_channels(&channels).unwrap_err();
+        assert_eq!(pxr24.channel, Text::from("Z"));
+        assert_eq!(pxr24.reason, CompressionWarningReason::Lossy);
+
+        let b44 = Compression::B44.validate_for_channels(&channels).unwrap_err();
+        assert_eq!(b44.channel, Text::from("B"));
+        assert_eq!(b44.to_string(), "b44 compression of channel `B` with F16 samples: samples lose precision");
+
+        let b44_channels: Vec<(Text, CompressionWarningReason)> = Compression::B44.warnings_for_channels(&channels)
+            .into_iter().map(|warning| (warning.channel, warning.reason)).collect();
+
+        assert_eq!(b44_channels, vec![
+            (Text::from("B"), CompressionWarningReason::Lossy),
+            (Text::from("ID"), CompressionWarningReason::NotCompressed),
+            (Text::from("Z"), CompressionWarningReason::NotCompressed),
+        ]);
+    }
+
     fn roundtrip_convert_endianness(
         current_endian: ByteVec, channels: &ChannelList, rectangle: IntegerBounds
     ){
@@ -12,7 +12,6 @@ use std::{
     io::{Cursor, Read, Write},
 };
 use std::convert::TryFrom;
-use smallvec::SmallVec;
 
 
 pub fn decompress(compressed: &[u8], expected_size: usize) -> Result<Vec<u16>> {
@@ -39,7 +38,6 @@ pub fn decompress(compressed: &[u8], expected_size: usize) -> Result<Vec<u16>> {
     let decoding_table = build_decoding_table(&encoding_table, min_code_index, max_code_index)?;
 
     let result = decode_with_tables(
-        &encoding_table,
         &decoding_table,
         &remaining_compressed,
         i32::try_from(bit_count)?,
@@ -101,28 +99,64 @@ const LONG_ZEROCODE_RUN: u64 = 63;
 const SHORTEST_LONG_RUN: u64 = 2 + LONG_ZEROCODE_RUN - SHORT_ZEROCODE_RUN;
 const LONGEST_LONG_RUN: u64 = 255 + SHORTEST_LONG_RUN;
 
+const MAX_LONG_CODE_TABLE_BITS: u64 = 8; // additional bits looked up at once for long codes
+const MAX_LONG_CODE_TABLE_SIZE: usize = 1 << 16; // limits the memory used by corrupt code tables
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+
+/// An entry of the decoding table. Small enough to be copied,
+/// so that building the table for each block stays cheap.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Code {
     Empty,
     Short(ShortCode),
-    Long(SmallVec<[u32; 2]>), // often 2, sometimes 4, rarely 8
+    Long(u32), // index of the group of codes that share these first bits
+}
+
+/// Resolves short codes with a single lookup,
+/// and codes longer than `DECODE_BITS` with a second lookup in their group.
+#[derive(Debug)]
+struct DecodingTable {
+    codes: Vec<Code>,
+    long_code_groups: Vec<LongCodeGroup>,
+    long_codes: Vec<LongCode>,
+    long_code_table: Vec<ShortCode>, // the lookup tables of all groups, an entry with zero length is invalid
+}
+
+/// All long codes that start with the same `DECODE_BITS` bits.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct LongCodeGroup {
+    start: u32,
+    end: u32,
+
+    table_start: u32,
+    table_bits: u8, // zero if these codes can only be searched linearly
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 struct ShortCode {
     value: u32,
     len: u8,
 }
 
+/// A code longer than `DECODE_BITS`, which must be compared against all of its bits.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct LongCode {
+    code: u64,
+    value: u32,
+    len: u8,
+}
+
 impl ShortCode {
     #[inline] fn len(&self) -> u64 { u64::from(self.len) }
 }
 
+impl LongCode {
+    #[inline] fn len(&self) -> u64 { u64::from(self.len) }
+}
+
 /// Decode (uncompress) n bits based on encoding & decoding tables:
 fn decode_with_tables(
-    encoding_table: &[u64],
-    decoding_table: &[Code],
+    decoding_table: &DecodingTable,
     mut input: &[u8],
     input_bit_count: i32,
     run_length_code: u32,
@@ -133,13 +167,25 @@ fn decode_with_tables(
     let mut code_bits = 0_u64;
     let mut code_bit_count = 0_u64;
 
-    while input.len() > 0 {
-        read_byte(&mut code_bits, &mut code_bit_count, &mut input)?;
+    while !input.is_empty() {
+        // fill up the bit buffer with as many whole bytes as fit into it,
+        // instead of going through the table once per byte
+        while code_bit_count <= 64 - 8 {
+            match input.split_first() {
+                Some((&byte, remaining)) => {
+                    code_bits = (code_bits << 8) | u64::from(byte);
+                    code_bit_count += 8;
+                    input = remaining;
+                },
+
+                None => break,
+            }
+        }
 
         // Access decoding table
         while code_bit_count >= DECODE_BITS {
             let code_index = (code_bits >> (code_bit_count - DECODE_BITS)) & DECODE_MASK;
-            let code = &decoding_table[u64_to_usize(code_index)];
+            let code = decoding_table.codes[u64_to_usize(code_index)];
 
             // Get short code
             if let Code::Short(code) = code {
@@ -155,36 +201,13 @@ fn decode_with_tables(
                     expected_output_size,
                 )?;
             }
-            else if let Code::Long(ref long_codes) = code {
-                debug_assert_ne!(long_codes.len(), 0);
-
-                let long_code = long_codes.iter()
-                    .filter_map(|&long_code|{
-                        let encoded_long_code = encoding_table[u32_to_usize(long_code)];
-                        let length = length(encoded_long_code);
-
-                        while code_bit_count < length && input.len() > 0 {
-                            let err = read_byte(&mut code_bits, &mut code_bit_count, &mut input);
-                            if let Err(err) = err { return Some(Err(err)); }
-                        }
-
-                        if code_bit_count >= length {
-                            let required_code = (code_bits >> (code_bit_count - length)) & ((1 << length) - 1);
-
-                            if self::code(encoded_long_code) == required_code {
-                                code_bit_count -= length;
-                                return Some(Ok(long_code));
-                            }
-                        }
-
-                        None
-
-                    })
-                    .next()
-                    .ok_or(Error::invalid(INVALID_CODE))?;
+            else if let Code::Long(group_index) = code {
+                let long_code = decoding_table.read_long_code(
+                    group_index, &mut code_bits, &mut code_bit_count, &mut input
+                )?;
 
                 read_code_into_vec(
-                    long_code?,
+                    long_code,
                     run_length_code,
                     &mut code_bits,
                     &mut code_bit_count,
@@ -207,7 +230,7 @@ fn decode_with_tables(
 
     while code_bit_count > 0 {
         let index = (code_bits << (DECODE_BITS - code_bit_count)) & DECODE_MASK;
-        let code = &decoding_table[u64_to_usize(index)];
+        let code = decoding_table.codes[u64_to_usize(index)];
 
         if let Code::Short(short_code) = code {
             if short_code.len() > code_bit_count { return Err(Error::invalid("code")) }; // FIXME why does this happen??
@@ -237,16 +260,18 @@ fn decode_with_tables(
 
 /// Build a decoding hash table based on the encoding table code:
 ///	- short codes (<= HUF_DECBITS) are resolved with a single table access;
-///	- long code entry allocations are not optimized, because long codes are
-///	  unfrequent;
+///	- long codes are resolved with a second, smaller table per group of long codes,
+///	  because noisy images contain a lot of them;
 ///	- decoding tables are used by hufDecode();
 fn build_decoding_table(
     encoding_table: &[u64],
     min_code_index: usize,
     max_code_index: usize,
-) -> Result<Vec<Code>>
+) -> Result<DecodingTable>
 {
-    let mut decoding_table = vec![Code::Empty; DECODING_TABLE_SIZE]; // not an array because of code not being copy
+    let mut decoding_table = vec![Code::Empty; DECODING_TABLE_SIZE];
+    let mut long_code_groups = Vec::new();
+    let mut long_codes: Vec<(u32, LongCode)> = Vec::new();
 
     for (code_index, &encoded_code) in encoding_table[..= max_code_index].iter().enumerate().skip(min_code_index) {
         let code_index = u32::try_from(code_index).unwrap();
@@ -259,13 +284,21 @@ fn build_decoding_table(
         }
 
         if length > DECODE_BITS {
-            let long_code = &mut decoding_table[u64_to_usize(code >> (length - DECODE_BITS))];
+            let entry = &mut decoding_table[u64_to_usize(code >> (length - DECODE_BITS))];
 
-            match long_code {
-                Code::Empty => *long_code = Code::Long(smallvec![code_index]),
-                Code::Long(lits) => lits.push(code_index),
-                _ => { return Err(Error::invalid(INVALID_TABLE_ENTRY)); }
-            }
+            let group_index = match *entry {
+                Code::Long(group_index) => group_index,
+                Code::Short(_) => { return Err(Error::invalid(INVALID_TABLE_ENTRY)); },
+
+                Code::Empty => {
+                    let group_index = u32::try_from(long_code_groups.len())?;
+                    long_code_groups.push(LongCodeGroup::default());
+                    *entry = Code::Long(group_index);
+                    group_index
+                },
+            };
+
+            long_codes.push((group_index, LongCode { code, value: code_index, len: length as u8 }));
         }
         else if length != 0 {
             let default_value = Code::Short(ShortCode {
@@ -276,13 +309,107 @@ fn build_decoding_table(
             let start_index = u64_to_usize(code << (DECODE_BITS - length));
             let count = u64_to_usize(1 << (DECODE_BITS - length));
 
-            for value in &mut decoding_table[start_index .. start_index + count] {
-                *value = default_value.clone();
+            decoding_table[start_index .. start_index + count].fill(default_value);
+        }
+    }
+
+    // group the long codes, keeping their original order within each group
+    long_codes.sort_by_key(|&(group_index, _)| group_index);
+
+    let mut long_code_table = Vec::new();
+    let mut start = 0;
+
+    for (group_index, group) in long_code_groups.iter_mut().enumerate() {
+        let end = start + long_codes[start ..].iter()
+            .take_while(|&&(index, _)| u32_to_usize(index) == group_index)
+            .count();
+
+        let codes = long_codes[start .. end].iter().map(|&(_, long_code)| long_code);
+        group.start = u32::try_from(start)?;
+        group.end = u32::try_from(end)?;
+
+        // look up the remaining bits of all codes in this group at once, if the table is not too large
+        let table_bits = codes.clone().map(|long_code| long_code.len()).max().unwrap() - DECODE_BITS;
+        let table_size = 1_usize << table_bits;
+
+        if table_bits <= MAX_LONG_CODE_TABLE_BITS && long_code_table.len() + table_size <= MAX_LONG_CODE_TABLE_SIZE {
+            group.table_start = u32::try_from(long_code_table.len())?;
+            group.table_bits = table_bits as u8;
+            long_code_table.resize(long_code_table.len() + table_size, ShortCode { value: 0, len: 0 });
+
+            let table = &mut long_code_table[u32_to_usize(group.table_start) ..];
+
+            // the first matching code wins, like in the linear search
+            for long_code in codes.rev() {
+                let remaining_bits = long_code.len() - DECODE_BITS;
+                let remaining_code = long_code.code & ((1 << remaining_bits) - 1);
+
+                let start_index = u64_to_usize(remaining_code << (table_bits - remaining_bits));
+                let count = u64_to_usize(1 << (table_bits - remaining_bits));
+
+                table[start_index .. start_index + count]
+                    .fill(ShortCode { value: long_code.value, len: long_code.len });
             }
         }
+
+        start = end;
     }
 
-    Ok(decoding_table)
+    let long_codes = long_codes.into_iter().map(|(_, long_code)| long_code).collect();
+    Ok(DecodingTable { codes: decoding_table, long_code_groups, long_codes, long_code_table })
+}
+
+impl DecodingTable {
+
+    /// Find the long code at the start of the bits, reading more bytes if required.
+    #[inline]
+    fn read_long_code(
+        &self, group_index: u32,
+        code_bits: &mut u64, code_bit_count: &mut u64, input: &mut &[u8]
+    ) -> Result<u32>
+    {
+        let group = self.long_code_groups[u32_to_usize(group_index)];
+
+        if group.table_bits != 0 {
+            let length = DECODE_BITS + u64::from(group.table_bits);
+
+            while *code_bit_count < length && !input.is_empty() {
+                read_byte(code_bits, code_bit_count, input)?;
+            }
+
+            // near the end of the data, fall back to the linear search with fewer bits
+            if *code_bit_count >= length {
+                let table_index = (*code_bits >> (*code_bit_count - length)) & ((1 << group.table_bits) - 1);
+                let code = self.long_code_table[u32_to_usize(group.table_start) + u64_to_usize(table_index)];
+
+                if code.len == 0 {
+                    return Err(Error::invalid(INVALID_CODE));
+                }
+
+                *code_bit_count -= code.len();
+                return Ok(code.value);
+            }
+        }
+
+        for long_code in &self.long_codes[u32_to_usize(group.start) .. u32_to_usize(group.end)] {
+            let length = long_code.len();
+
+            while *code_bit_count < length && !input.is_empty() {
+                read_byte(code_bits, code_bit_count, input)?;
+            }
+
+            if *code_bit_count >= length {
+                let required_code = (*code_bits >> (*code_bit_count - length)) & ((1 << length) - 1);
+
+                if long_code.code == required_code {
+                    *code_bit_count -= length;
+                    return Ok(long_code.value);
+                }
+            }
+        }
+
+        Err(Error::invalid(INVALID_CODE))
+    }
 }
 
 /// Run-length-decompresses all zero runs from the packed table to the encoding table
@@ -4,7 +4,7 @@
 //! based on the PIZ image format, customized for OpenEXR.
 // inspired by  https://github.com/AcademySoftwareFoundation/openexr/blob/master/OpenEXR/IlmImf/ImfPizCompressor.cpp
 
-mod huffman;
+pub(super) mod huffman; // also used by dwa
 mod wavelet;
 
 use crate::prelude::*;
@@ -31,14 +31,14 @@ struct ChannelData {
 
 pub fn decompress(
     channels: &ChannelList,
-    compressed: ByteVec,
+    compressed: Bytes<'_>,
     rectangle: IntegerBounds,
-    expected_byte_size: usize, // TODO remove expected byte size as it can be computed with `rectangle.size.area() * channels.bytes_per_pixel`
+    expected_byte_size: usize, // TODO remove expected byte size as it can be computed with `channels.byte_size_of_section(rectangle)`
     pedantic: bool
 ) -> Result<ByteVec>
 {
     let expected_u16_count = expected_byte_size / 2;
-    debug_assert_eq!(expected_byte_size, rectangle.size.area() * channels.bytes_per_pixel);
+    debug_assert_eq!(expected_byte_size, channels.byte_size_of_section(rectangle));
     debug_assert!(!channels.list.is_empty());
 
     if compressed.is_empty() {
@@ -49,7 +49,7 @@ pub fn decompress(
 
     let mut bitmap = vec![0_u8; BITMAP_SIZE]; // FIXME use bit_vec!
 
-    let mut remaining_input = compressed.as_slice();
+    let mut remaining_input = compressed;
     let min_non_zero = u16::read(&mut remaining_input)? as usize;
     let max_non_zero = u16::read(&mut remaining_input)? as usize;
 
@@ -93,10 +93,18 @@ pub fn decompress(
         channel_data
     };
 
-    for channel in &channel_data {
+    // the channels are stored one after another, so huge blocks can transform all channels at once
+    let parallel = super::parallel_band_size(expected_byte_size).is_some();
+    let mut remaining_u16s = tmp_u16_buffer.as_mut_slice();
+
+    let channel_u16s = channel_data.iter().map(|channel| {
         let u16_count = channel.resolution.area() * channel.samples_per_pixel;
-        let u16s = &mut tmp_u16_buffer[channel.tmp_start_index .. channel.tmp_start_index + u16_count];
+        let (u16s, rest) = std::mem::take(&mut remaining_u16s).split_at_mut(u16_count);
+        remaining_u16s = rest;
+        (channel, u16s)
+    });
 
+    super::try_for_each_maybe_parallel(channel_u16s, parallel, |(channel, u16s)| {
         for offset in 0..channel.samples_per_pixel { // if channel is 32 bit, compress interleaved as two 16 bit values
             wavelet::decode(
                 &mut u16s[offset..],
@@ -105,7 +113,9 @@ pub fn decompress(
                 max_value
             )?;
         }
-    }
+
+        Ok(())
+    })?;
 
     // Expand the pixel data to their original range
     apply_lookup_table(&mut tmp_u16_buffer, &lookup_table);
@@ -219,16 +229,28 @@ pub fn compress(
         piz_compressed.extend_from_slice(&bitmap[min_non_zero ..= max_non_zero]);
     }
 
-    for channel in channel_data {
+    // the channels are stored one after another, so huge blocks can transform all channels at once
+    let parallel = super::parallel_band_size(uncompressed.len()).is_some();
+    let mut remaining_u16s = tmp.as_mut_slice();
+
+    let channel_u16s = channel_data.iter().map(|channel| {
+        let (u16s, rest) = std::mem::take(&mut remaining_u16s).split_at_mut(channel.tmp_end_index - channel.tmp_start_index);
+        remaining_u16s = rest;
+        (channel, u16s)
+    });
+
+    super::try_for_each_maybe_parallel(channel_u16s, parallel, |(channel, u16s)| {
         for offset in 0 .. channel.samples_per_pixel {
             wavelet::encode(
-                &mut tmp[channel.tmp_start_index + offset .. channel.tmp_end_index],
+                &mut u16s[offset ..],
                 channel.resolution,
                 Vec2(channel.samples_per_pixel, channel.resolution.x() * channel.samples_per_pixel),
                 max_value
             )?;
         }
-    }
+
+        Ok(())
+    })?;
 
     let huffman_compressed: Vec<u8> = huffman::compress(&tmp)?;
     u8::write_i32_sized_slice(&mut piz_compressed, &huffman_compressed).expect("in-memory write failed");
@@ -290,8 +312,15 @@ fn reverse_lookup_table_from_bitmap(bitmap: Bytes<'_>) -> (Vec<u16>, u16) {
 }
 
 fn apply_lookup_table(data: &mut [u16], table: &[u16]) {
-    for data in data {
-        *data = table[*data as usize];
+    let apply_to_band = |band: &mut [u16]| {
+        for data in band {
+            *data = table[*data as usize];
+        }
+    };
+
+    match super::parallel_band_size(data.len() * 2) {
+        Some(band_byte_size) => super::for_each_maybe_parallel(data.chunks_mut(band_byte_size / 2), true, apply_to_band),
+        None => apply_to_band(data),
     }
 }
 
@@ -308,7 +337,7 @@ mod test {
             .collect();
 
         let compressed = piz::compress(&channels, pixel_bytes.clone(), rectangle).unwrap();
-        let decompressed = piz::decompress(&channels, compressed, rectangle, pixel_bytes.len(), true).unwrap();
+        let decompressed = piz::decompress(&channels, &compressed, rectangle, pixel_bytes.len(), true).unwrap();
 
         assert_eq!(pixel_bytes, decompressed);
     }
@@ -434,4 +463,15 @@ mod test {
         test_roundtrip_noise_with(channels, rectangle);
     }
 
+    #[test]
+    fn roundtrip_huge_block_on_thread_pool(){
+        let channel = |sample_type| ChannelDescription::new("", sample_type, false);
+        let channels = ChannelList::new(smallvec![ channel(SampleType::F16), channel(SampleType::F32), channel(SampleType::U32) ]);
+        let rectangle = IntegerBounds::new(Vec2(0, 0), Vec2(1024, 256));
+
+        // within a thread pool, the channels of a huge block are transformed on multiple threads
+        let pool = rayon_core::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        pool.install(|| test_roundtrip_noise_with(channels, rectangle));
+    }
+
 }
\ No newline at end of file
@@ -113,14 +113,29 @@ pub fn decode(buffer: &mut [u16], count: Vec2<usize>, size: Vec2<usize>, max_val
 
 #[inline]
 pub fn decode_14_or_16_bit(
+    buffer: &mut [u16],
+    count: Vec2<usize>,
+    offset: Vec2<usize>,
+    is_14_bit: bool // true if maximum buffer[i] value < (1 << 14)
+) -> IoResult<()>
+{
+    // monomorphize the whole loop for each variant, instead of branching per value
+    if is_14_bit { decode_levels(buffer, count, offset, decode_14bit) }
+    else { decode_levels(buffer, count, offset, decode_16bit) }
+}
+
+/// Reconstructs all levels, from the coarsest to the finest.
+/// Each pair of rows is processed as two contiguous slices,
+/// so that the inner loop only touches those two rows.
+#[inline]
+fn decode_levels(
     buffer: &mut [u16],
     Vec2(count_x, count_y): Vec2<usize>,
     Vec2(offset_x, offset_y): Vec2<usize>,
-    is_14_bit: bool // true if maximum buffer[i] value < (1 << 14)
+    decode: impl Fn(u16, u16) -> (u16, u16),
 ) -> IoResult<()>
 {
     let count = count_x.min(count_y);
-    let decode = if is_14_bit { decode_14bit } else { decode_16bit }; // assume inlining and constant propagation
 
     let mut p: usize = 1; // TODO i32?
     let mut p2: usize; // TODO i32?
@@ -135,51 +150,46 @@ pub fn decode_14_or_16_bit(
     p >>= 1;
 
     while p >= 1 {
-
-        let mut position_y = 0;
-        let end_y = 0 + offset_y * (count_y - p2);
-
         let (offset1_x, offset1_y) = (offset_x * p, offset_y * p);
         let (offset2_x, offset2_y) = (offset_x * p2, offset_y * p2);
 
         debug_assert_ne!(offset_x, 0, "offset should not be zero");
         debug_assert_ne!(offset_y, 0, "offset should not be zero");
 
-        while position_y <= end_y {
-            let mut position_x = position_y;
-            let end_x = position_x + offset_x * (count_x - p2);
+        // the buffer may start at an interleaved sample, so the last row is not necessarily complete
+        let row_length = offset_x * (count_x - 1) + 1;
+        let pair_count_x = count_x / p2;
+        let has_odd_x = count_x & p != 0;
 
-            while position_x <= end_x {
-                let pos_right = position_x + offset1_x;
-                let pos_top = position_x + offset1_y;
-                let pos_top_right = pos_top + offset1_x;
+        let mut position_y = 0;
 
-                assert!(position_x < buffer.len());
-                assert!(pos_right < buffer.len());
-                assert!(pos_top < buffer.len());
-                assert!(pos_top_right < buffer.len());
+        for _ in 0 .. count_y / p2 {
+            let (bottom, top) = buffer[position_y ..].split_at_mut(offset1_y);
+            let (bottom, top) = (&mut bottom[.. row_length], &mut top[.. row_length]);
 
-                let (center, top) = decode(buffer[position_x], buffer[pos_top]);
-                let (right, top_right) = decode(buffer[pos_right], buffer[pos_top_right]);
+            for pair_x in 0 .. pair_count_x {
+                let left = pair_x * offset2_x;
+                let right = left + offset1_x;
 
-                let (center, right) = decode(center, right);
-                let (top, top_right) = decode(top, top_right);
+                let (center, top_left) = decode(bottom[left], top[left]);
+                let (right_value, top_right) = decode(bottom[right], top[right]);
 
-                buffer[position_x] = center; // TODO rustify
-                buffer[pos_top] = top;
-                buffer[pos_right] = right;
-                buffer[pos_top_right] = top_right;
+                let (center, right_value) = decode(center, right_value);
+                let (top_left, top_right) = decode(top_left, top_right);
 
-                position_x += offset2_x;
+                bottom[left] = center;
+                bottom[right] = right_value;
+                top[left] = top_left;
+                top[right] = top_right;
             }
 
             // decode last odd remaining x value
-            if count_x & p != 0 {
-                let pos_top = position_x + offset1_y;
-                let (center, top) = decode(buffer[position_x], buffer[pos_top]);
+            if has_odd_x {
+                let left = pair_count_x * offset2_x;
+                let (center, top_left) = decode(bottom[left], top[left]);
 
-                buffer[position_x] = center;
-                buffer[pos_top] = top;
+                bottom[left] = center;
+                top[left] = top_left;
             }
 
             position_y += offset2_y;
@@ -187,17 +197,15 @@ pub fn decode_14_or_16_bit(
 
         // decode remaining odd row
         if count_y & p != 0 {
-            let mut position_x = position_y;
-            let end_x = position_x + offset_x * (count_x - p2);
-
-            while position_x <= end_x {
-                let pos_right = position_x + offset1_x;
-                let (center, right) = decode(buffer[position_x], buffer[pos_right]);
+            let row = &mut buffer[position_y .. position_y + row_length];
 
-                buffer[position_x] = center;
-                buffer[pos_right] = right;
+            for pair_x in 0 .. pair_count_x {
+                let left = pair_x * offset2_x;
+                let right = left + offset1_x;
 
-                position_x += offset2_x;
+                let (center, right_value) = decode(row[left], row[right]);
+                row[left] = center;
+                row[right] = right_value;
             }
         }
 
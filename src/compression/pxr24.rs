@@ -53,13 +53,18 @@ pub fn compress(channels: &ChannelList, remaining_bytes: ByteVec, area: IntegerB
     let remaining_bytes = super::convert_current_to_little_endian(remaining_bytes, channels, area);
     let mut remaining_bytes = remaining_bytes.as_slice(); // TODO less allocation
 
-    let bytes_per_pixel: usize = channels.list.iter()
-        .map(|channel| match channel.sample_type {
-            SampleType::F16 => 2, SampleType::F32 => 3, SampleType::U32 => 4,
+    // each channel may be subsampled, and f32 samples are reduced to three bytes
+    let byte_count: usize = channels.list.iter()
+        .map(|channel| {
+            let bytes_per_sample = match channel.sample_type {
+                SampleType::F16 => 2, SampleType::F32 => 3, SampleType::U32 => 4,
+            };
+
+            bytes_per_sample * channel.subsampled_resolution(area.size).area()
         })
         .sum();
 
-    let mut raw = vec![0_u8; bytes_per_pixel * area.size.area()];
+    let mut raw = vec![0_u8; byte_count];
 
     {
         let mut write = raw.as_mut_slice();
@@ -138,7 +143,7 @@ pub fn compress(channels: &ChannelList, remaining_bytes: ByteVec, area: IntegerB
 }
 
 #[cfg_attr(target_endian = "big", allow(unused, unreachable_code))]
-pub fn decompress(channels: &ChannelList, bytes: ByteVec, area: IntegerBounds, expected_byte_size: usize, pedantic: bool) -> Result<ByteVec> {
+pub fn decompress(channels: &ChannelList, bytes: Bytes<'_>, area: IntegerBounds, expected_byte_size: usize, pedantic: bool) -> Result<ByteVec> {
     #[cfg(target_endian = "big")] {
         return Err(Error::unsupported(
             "PXR24 decompression method not supported yet on big endian processor architecture"
@@ -146,7 +151,7 @@ pub fn decompress(channels: &ChannelList, bytes: ByteVec, area: IntegerBounds, e
     }
 
     let options = zune_inflate::DeflateOptions::default().set_limit(expected_byte_size).set_size_hint(expected_byte_size);
-    let mut decoder = zune_inflate::DeflateDecoder::new_with_options(&bytes, options);
+    let mut decoder = zune_inflate::DeflateDecoder::new_with_options(bytes, options);
     let raw = decoder.decode_zlib()
         .map_err(|_| Error::invalid("zlib-compressed data malformed"))?; // TODO share code with zip?
 
@@ -258,4 +263,109 @@ pub fn f32_to_f24(float: f32) -> u32 {
     };
 
     return (sign >> 8) | result;
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+#[cfg(target_endian = "little")] // TODO big endian pxr24
+mod test {
+    use super::*;
+    use crate::meta::attribute::ChannelDescription;
+    use crate::math::Vec2;
+
+    /// A block of interleaved f16, f32, and u32 channels, in the layout of an uncompressed block,
+    /// and the same block after f32 samples have been reduced to 24 bits.
+    fn mixed_block(channels: &ChannelList, area: IntegerBounds) -> (ByteVec, ByteVec) {
+        let mut original = Vec::new();
+        let mut expected = Vec::new();
+
+        for y in area.position.y() .. area.end().y() {
+            for (channel_index, channel) in channels.list.iter().enumerate() {
+                if mod_p(y, usize_to_i32(channel.sampling.y())) != 0 { continue; }
+
+                for x in 0 .. channel.subsampled_resolution(area.size).width() {
+                    // alternate between rising and falling values, to produce negative differences
+                    let seed = (x * 7919 + y as usize * 104729 + channel_index * 31) as u32;
+                    let seed = if x % 2 == 0 { seed } else { u32::MAX - seed };
+
+                    match channel.sample_type {
+                        SampleType::F16 => {
+                            let sample = (seed as u16).to_ne_bytes();
+                            original.extend_from_slice(&sample);
+                            expected.extend_from_slice(&sample);
+                        },
+
+                        SampleType::U32 => {
+                            let sample = seed.wrapping_mul(2654435761).to_ne_bytes();
+                            original.extend_from_slice(&sample);
+                            expected.extend_from_slice(&sample);
+                        },
+
+                        SampleType::F32 => {
+                            let sample = (seed as f32 - 1.5e9) / 3.3;
+                            original.extend_from_slice(&sample.to_ne_bytes());
+                            expected.extend_from_slice(&(f32_to_f24(sample) << 8).to_ne_bytes());
+                        },
+                    }
+                }
+            }
+        }
+
+        (original, expected)
+    }
+
+    fn assert_roundtrip_mixed_channels(channels: ChannelList, area: IntegerBounds) {
+        let (original, expected) = mixed_block(&channels, area);
+
+        let compressed = compress(&channels, original.clone(), area).unwrap();
+        let decompressed = decompress(&channels, &compressed, area, original.len(), true).unwrap();
+
+        assert_eq!(decompressed.len(), original.len());
+        assert_eq!(decompressed, expected, "only f32 samples may be reduced to 24 bits");
+        assert_ne!(decompressed, original, "f32 samples should be reduced to 24 bits");
+    }
+
+    #[test]
+    fn roundtrip_interleaved_f16_f32_u32_channels() {
+        let channels = ChannelList::new(smallvec![
+            ChannelDescription::new("A", SampleType::F16, true),
+            ChannelDescription::new("B", SampleType::U32, false),
+            ChannelDescription::new("G", SampleType::F32, true),
+            ChannelDescription::new("ID", SampleType::U32, false),
+            ChannelDescription::new("R", SampleType::F16, true),
+            ChannelDescription::new("Z", SampleType::F32, false),
+        ]);
+
+        assert_roundtrip_mixed_channels(channels.clone(), IntegerBounds::new((0, 0), (64, 16)));
+        assert_roundtrip_mixed_channels(channels, IntegerBounds::new((3, 7), (17, 5)));
+    }
+
+    #[test]
+    fn roundtrip_mixed_subsampled_channels() {
+        let mut chroma = ChannelDescription::new("BY", SampleType::F16, true);
+        chroma.sampling = Vec2(2, 2);
+
+        let channels = ChannelList::new(smallvec![
+            chroma,
+            ChannelDescription::new("ID", SampleType::U32, false),
+            ChannelDescription::new("Y", SampleType::F32, true),
+        ]);
+
+        assert_roundtrip_mixed_channels(channels, IntegerBounds::new((0, 0), (32, 16)));
+    }
+
+    #[test]
+    fn u32_and_f16_samples_are_bit_exact() {
+        let channels = ChannelList::new(smallvec![
+            ChannelDescription::new("ID", SampleType::U32, false),
+            ChannelDescription::new("Y", SampleType::F16, true),
+        ]);
+
+        let area = IntegerBounds::new((0, 0), (40, 16));
+        let (original, expected) = mixed_block(&channels, area);
+        assert_eq!(original, expected);
+
+        let compressed = compress(&channels, original.clone(), area).unwrap();
+        assert_eq!(decompress(&channels, &compressed, area, original.len(), true).unwrap(), original);
+    }
+}
@@ -2,6 +2,7 @@ use super::*;
 use super::optimize_bytes::*;
 use super::Error;
 use super::Result;
+use std::convert::TryInto;
 
 // inspired by  https://github.com/openexr/openexr/blob/master/OpenEXR/IlmImf/ImfRle.cpp
 
@@ -11,13 +12,31 @@ const MAX_RUN_LENGTH : usize = 127;
 
 pub fn decompress_bytes(
     channels: &ChannelList,
-    compressed: ByteVec,
+    compressed: Bytes<'_>,
     rectangle: IntegerBounds,
     expected_byte_size: usize,
     pedantic: bool,
 ) -> Result<ByteVec> {
-    let mut remaining = compressed.as_slice();
-    let mut decompressed = Vec::with_capacity(expected_byte_size.min(8*2048));
+    let decompressed = decompress_reordered_bytes(compressed, expected_byte_size, pedantic)?;
+    Ok(super::convert_little_endian_to_current(decompressed, channels, rectangle))// TODO no alloc
+}
+
+/// Expand the runs of bytes and undo the byte reordering, without interpreting the bytes as samples.
+pub fn decompress_reordered_bytes(compressed: Bytes<'_>, expected_byte_size: usize, pedantic: bool) -> Result<ByteVec> {
+    let mut decompressed = decompress_runs(compressed, expected_byte_size, pedantic)?;
+
+    differences_to_samples(&mut decompressed);
+    interleave_byte_blocks(&mut decompressed);
+    Ok(decompressed)
+}
+
+/// Only expand the runs of bytes, without undoing the byte reordering.
+pub fn decompress_runs(compressed: Bytes<'_>, expected_byte_size: usize, pedantic: bool) -> Result<ByteVec> {
+    let mut remaining = compressed;
+
+    // a run of two bytes expands to at most 128 bytes, which limits the allocation for corrupt sizes
+    let max_byte_size = compressed.len().saturating_mul((MAX_RUN_LENGTH + 1) / 2);
+    let mut decompressed = Vec::with_capacity(expected_byte_size.min(max_byte_size));
 
     while !remaining.is_empty() && decompressed.len() != expected_byte_size {
         let count = take_1(&mut remaining)? as i8 as i32;
@@ -38,55 +57,112 @@ pub fn decompress_bytes(
         return Err(Error::invalid("data amount"));
     }
 
-    differences_to_samples(&mut decompressed);
-    interleave_byte_blocks(&mut decompressed);
-    Ok(super::convert_little_endian_to_current(decompressed, channels, rectangle))// TODO no alloc
+    Ok(decompressed)
 }
 
 pub fn compress_bytes(channels: &ChannelList, uncompressed: ByteVec, rectangle: IntegerBounds) -> Result<ByteVec> {
     // see https://github.com/AcademySoftwareFoundation/openexr/blob/3bd93f85bcb74c77255f28cdbb913fdbfbb39dfe/OpenEXR/IlmImf/ImfTiledOutputFile.cpp#L750-L842
-    let mut data = super::convert_current_to_little_endian(uncompressed, channels, rectangle);// TODO no alloc
-
-    separate_bytes_fragments(&mut data);
-    samples_to_differences(&mut data);
-
-    let mut compressed = Vec::with_capacity(data.len());
-    let mut run_start = 0;
-    let mut run_end = 1;
-
-    while run_start < data.len() {
-        while
-            run_end < data.len()
-                && data[run_start] == data[run_end]
-                && (run_end - run_start) as i32 - 1 < MAX_RUN_LENGTH as i32
-            {
-                run_end += 1;
-            }
-
-        if run_end - run_start >= MIN_RUN_LENGTH {
-            compressed.push(((run_end - run_start) as i32 - 1) as u8);
-            compressed.push(data[run_start]);
-            run_start = run_end;
-
-        } else {
-            while
-                run_end < data.len() && (
-                    (run_end + 1 >= data.len() || data[run_end] != data[run_end + 1])
-                        || (run_end + 2 >= data.len() || data[run_end + 1] != data[run_end + 2])
-                ) && run_end - run_start < MAX_RUN_LENGTH
-                {
-                    run_end += 1;
-                }
-
-            compressed.push((run_start as i32 - run_end as i32) as u8);
-            compressed.extend_from_slice(&data[run_start .. run_end]);
-
-            run_start = run_end;
-            run_end += 1;
+    let data = super::convert_current_to_little_endian(uncompressed, channels, rectangle);// TODO no alloc
+    Ok(compress_reordered_bytes(data))
+}
+
+/// Reorder the bytes and replace runs of equal bytes, without interpreting the bytes as samples.
+pub fn compress_reordered_bytes(mut bytes: ByteVec) -> ByteVec {
+    separate_bytes_fragments(&mut bytes);
+    samples_to_differences(&mut bytes);
+    compress_runs(&bytes)
+}
+
+/// Only replace runs of equal bytes, without reordering the bytes.
+pub fn compress_runs(data: Bytes<'_>) -> ByteVec {
+    // each literal span of up to 127 bytes adds one byte
+    let mut compressed = Vec::with_capacity(data.len() + data.len() / MAX_RUN_LENGTH + 1);
+    let mut remaining = data;
+
+    while let Some(&value) = remaining.first() {
+        let run_length = run_length(remaining, MAX_RUN_LENGTH + 1);
+
+        if run_length >= MIN_RUN_LENGTH {
+            compressed.push((run_length - 1) as u8);
+            compressed.push(value);
+            remaining = &remaining[run_length ..];
+        }
+        else {
+            let literal_length = literal_length(remaining, MAX_RUN_LENGTH);
+
+            compressed.push((-(literal_length as i32)) as u8);
+            compressed.extend_from_slice(&remaining[.. literal_length]);
+            remaining = &remaining[literal_length ..];
         }
     }
 
-    Ok(compressed)
+    compressed
+}
+
+/// How many bytes at the start are equal to the first byte, at most `max`.
+fn run_length(bytes: Bytes<'_>, max: usize) -> usize {
+    let value = bytes[0];
+    let end = bytes.len().min(max);
+    let mut length = 1;
+
+    // compare eight bytes at once
+    while length + 8 <= end {
+        let different_bytes = eight_bytes(&bytes[length ..]) ^ (u64::from(value) * LOWEST_BITS);
+
+        if different_bytes != 0 {
+            return length + different_bytes.trailing_zeros() as usize / 8;
+        }
+
+        length += 8;
+    }
+
+    length + bytes[length .. end].iter().take_while(|&&byte| byte == value).count()
+}
+
+/// How many bytes at the start can be copied as-is, before a run of at least three equal bytes starts.
+/// At least one and at most `max`.
+fn literal_length(bytes: Bytes<'_>, max: usize) -> usize {
+    let end = bytes.len().min(max);
+    let mut length = 1;
+
+    // look for the start of a run at eight positions at once
+    while length + 8 + 2 <= bytes.len() && length + 8 <= end {
+        let first = eight_bytes(&bytes[length ..]);
+        let second = eight_bytes(&bytes[length + 1 ..]);
+        let third = eight_bytes(&bytes[length + 2 ..]);
+
+        let run_start = first_zero_byte((first ^ second) | (second ^ third));
+        if run_start < 8 { return length + run_start; }
+
+        length += 8;
+    }
+
+    while length < end {
+        let starts_run = bytes.get(length + 2).map_or(false, |&third|
+            bytes[length] == bytes[length + 1] && bytes[length + 1] == third
+        );
+
+        if starts_run { break; }
+        length += 1;
+    }
+
+    length
+}
+
+const LOWEST_BITS: u64 = 0x0101_0101_0101_0101;
+const HIGHEST_BITS: u64 = 0x8080_8080_8080_8080;
+
+#[inline]
+fn eight_bytes(bytes: Bytes<'_>) -> u64 {
+    u64::from_le_bytes(bytes[.. 8].try_into().expect("slice size bug"))
+}
+
+/// The index of the first zero byte, or eight if no byte is zero.
+#[inline]
+fn first_zero_byte(value: u64) -> usize {
+    // only bytes after the first zero byte may be detected falsely
+    let zero_bytes = value.wrapping_sub(LOWEST_BITS) & !value & HIGHEST_BITS;
+    zero_bytes.trailing_zeros() as usize / 8
 }
 
 fn take_1(slice: &mut &[u8]) -> Result<u8> {
@@ -110,3 +186,30 @@ fn take_n<'s>(slice: &mut &'s [u8], n: usize) -> Result<&'s [u8]> {
         Err(Error::invalid("compressed data"))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip_runs_and_literals() {
+        for length in 0 .. 300 {
+            // alternate between noise and runs of different lengths, to hit all chunk boundaries
+            let data: Vec<u8> = (0 .. length)
+                .map(|index| if (index / 7) % 3 == 0 { rand::random::<u8>() } else { (index / 50) as u8 })
+                .collect();
+
+            let compressed = compress_runs(&data);
+            assert!(compressed.len() <= data.len() + data.len() / MAX_RUN_LENGTH + 1);
+
+            let decompressed = decompress_runs(&compressed, data.len(), true).unwrap();
+            assert_eq!(data, decompressed, "length {}", length);
+        }
+    }
+
+    #[test]
+    fn long_runs_are_split() {
+        let compressed = compress_runs(&[9; 300]);
+        assert_eq!(compressed, vec![127, 9, 127, 9, 43, 9]);
+    }
+}
@@ -14,28 +14,145 @@ use crate::error::Result;
 
 pub fn decompress_bytes(
     channels: &ChannelList,
-    data: ByteVec,
+    data: Bytes<'_>,
     rectangle: IntegerBounds,
     expected_byte_size: usize,
     _pedantic: bool,
 ) -> Result<ByteVec> {
+    let decompressed = decompress_reordered_bytes(data, expected_byte_size)?;
+    Ok(super::convert_little_endian_to_current(decompressed, channels, rectangle))// TODO no alloc
+}
+
+/// Inflate the bytes and undo the byte reordering, without interpreting the bytes as samples.
+pub fn decompress_reordered_bytes(data: Bytes<'_>, expected_byte_size: usize) -> Result<ByteVec> {
+    let mut decompressed = decompress_zlib(data, expected_byte_size)?;
+
+    differences_to_samples(&mut decompressed);
+    interleave_byte_blocks(&mut decompressed);
+    Ok(decompressed)
+}
+
+/// Only inflate the bytes, producing at most the expected number of bytes.
+#[cfg(not(feature = "fast-deflate"))]
+pub fn decompress_zlib(data: Bytes<'_>, expected_byte_size: usize) -> Result<ByteVec> {
     let options = zune_inflate::DeflateOptions::default().set_limit(expected_byte_size).set_size_hint(expected_byte_size);
-    let mut decoder = zune_inflate::DeflateDecoder::new_with_options(&data, options);
-    let mut decompressed = decoder.decode_zlib()
-        .map_err(|_| Error::invalid("zlib-compressed data malformed"))?;
+    let mut decoder = zune_inflate::DeflateDecoder::new_with_options(data, options);
+
+    decoder.decode_zlib()
+        .map_err(|_| Error::invalid("zlib-compressed data malformed"))
+}
+
+/// Only inflate the bytes, producing at most the expected number of bytes.
+#[cfg(feature = "fast-deflate")]
+pub fn decompress_zlib(data: Bytes<'_>, expected_byte_size: usize) -> Result<ByteVec> {
+    let mut decompressed = vec![0; expected_byte_size];
+    let byte_count = inflate_zlib_into(data, &mut decompressed)?;
+    decompressed.truncate(byte_count);
+    Ok(decompressed)
+}
+
+/// Inflate the bytes into the existing slice, returning the number of bytes written.
+#[cfg(not(feature = "fast-deflate"))]
+fn inflate_zlib_into(data: Bytes<'_>, decompressed: &mut [u8]) -> Result<usize> {
+    // the zune decoder always allocates its output, so we use miniz here, which writes into an existing slice
+    miniz_oxide::inflate::decompress_slice_iter_to_slice(decompressed, std::iter::once(data), true, false)
+        .map_err(|_| Error::invalid("zlib-compressed data malformed"))
+}
+
+/// Inflate the bytes into the existing slice, returning the number of bytes written.
+#[cfg(feature = "fast-deflate")]
+fn inflate_zlib_into(data: Bytes<'_>, decompressed: &mut [u8]) -> Result<usize> {
+    libdeflater::Decompressor::new().zlib_decompress(data, decompressed)
+        .map_err(|_| Error::invalid("zlib-compressed data malformed"))
+}
+
+/// Like `decompress_bytes`, but inflates into a buffer from the pool instead of allocating a new one.
+pub fn decompress_bytes_into_pooled_buffer(
+    channels: &ChannelList,
+    data: Bytes<'_>,
+    rectangle: IntegerBounds,
+    expected_byte_size: usize,
+    _pedantic: bool,
+    buffer_pool: &BlockBufferPool,
+) -> Result<ByteVec> {
+    let mut decompressed = buffer_pool.take(expected_byte_size);
+
+    match inflate_zlib_into(data, &mut decompressed) {
+        Ok(byte_count) if byte_count == expected_byte_size => {},
+        _ => {
+            buffer_pool.recycle_bytes(decompressed);
+            return Err(Error::invalid("zlib-compressed data malformed"));
+        }
+    }
 
     differences_to_samples(&mut decompressed);
     interleave_byte_blocks(&mut decompressed);
 
-    Ok(super::convert_little_endian_to_current(decompressed, channels, rectangle))// TODO no alloc
+    Ok(super::convert_little_endian_to_current(decompressed, channels, rectangle))
 }
 
-pub fn compress_bytes(channels: &ChannelList, uncompressed: ByteVec, rectangle: IntegerBounds) -> Result<ByteVec> {
+/// The deflate level that is used if no level is specified.
+pub const DEFAULT_COMPRESSION_LEVEL: u8 = 4;
+
+pub fn compress_bytes(channels: &ChannelList, uncompressed: ByteVec, rectangle: IntegerBounds, compression_level: Option<u8>) -> Result<ByteVec> {
     // see https://github.com/AcademySoftwareFoundation/openexr/blob/3bd93f85bcb74c77255f28cdbb913fdbfbb39dfe/OpenEXR/IlmImf/ImfTiledOutputFile.cpp#L750-L842
-    let mut packed = convert_current_to_little_endian(uncompressed, channels, rectangle);
+    let packed = convert_current_to_little_endian(uncompressed, channels, rectangle);
+    Ok(compress_reordered_bytes(packed, compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL)))
+}
+
+/// Reorder the bytes and deflate them, without interpreting the bytes as samples.
+pub fn compress_reordered_bytes(mut bytes: ByteVec, compression_level: u8) -> ByteVec {
+    separate_bytes_fragments(&mut bytes);
+    samples_to_differences(&mut bytes);
+    compress_zlib(&bytes, compression_level)
+}
+
+/// Only deflate the bytes.
+#[cfg(not(feature = "fast-deflate"))]
+pub fn compress_zlib(bytes: Bytes<'_>, compression_level: u8) -> ByteVec {
+    miniz_oxide::deflate::compress_to_vec_zlib(bytes, compression_level)
+}
+
+/// Only deflate the bytes.
+#[cfg(feature = "fast-deflate")]
+pub fn compress_zlib(bytes: Bytes<'_>, compression_level: u8) -> ByteVec {
+    let level = libdeflater::CompressionLvl::new(i32::from(compression_level)).unwrap_or_default();
+    let mut compressor = libdeflater::Compressor::new(level);
+
+    let mut compressed = vec![0; compressor.zlib_compress_bound(bytes.len())];
+    let byte_count = compressor.zlib_compress(bytes, &mut compressed).expect("zlib compression bound too small");
+    compressed.truncate(byte_count);
+    compressed
+}
+
+#[cfg(all(test, feature = "fast-deflate"))]
+mod test {
+    use super::*;
+
+    fn sample_bytes() -> ByteVec {
+        (0 .. 64 * 1024).map(|index: usize| (index / 7 % 251) as u8 ^ (index % 3) as u8).collect()
+    }
+
+    #[test]
+    fn fast_deflate_is_compatible_with_pure_rust_deflate() {
+        let bytes = sample_bytes();
+
+        for level in 0 ..= 9 {
+            let fast_compressed = compress_zlib(&bytes, level);
+            let rust_decompressed = miniz_oxide::inflate::decompress_to_vec_zlib(&fast_compressed).unwrap();
+            assert_eq!(rust_decompressed, bytes, "pure rust inflate of libdeflate level {}", level);
 
-    separate_bytes_fragments(&mut packed);
-    samples_to_differences(&mut packed);
+            let rust_compressed = miniz_oxide::deflate::compress_to_vec_zlib(&bytes, level);
+            let fast_decompressed = decompress_zlib(&rust_compressed, bytes.len()).unwrap();
+            assert_eq!(fast_decompressed, bytes, "libdeflate inflate of pure rust level {}", level);
+        }
+    }
 
-    Ok(miniz_oxide::deflate::compress_to_vec_zlib(packed.as_slice(), 4))
+    #[test]
+    fn fast_deflate_rejects_oversized_data() {
+        let bytes = sample_bytes();
+        let compressed = compress_zlib(&bytes, DEFAULT_COMPRESSION_LEVEL);
+        assert!(decompress_zlib(&compressed, bytes.len() - 1).is_err());
+        assert!(decompress_zlib(&compressed[.. compressed.len() / 2], bytes.len()).is_err());
+    }
 }
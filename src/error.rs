@@ -107,18 +107,21 @@ pub(crate) fn i32_to_usize(value: i32, error_message: &'static str) -> Result<us
 
 /// Return error on invalid range.
 #[inline]
+#[allow(unused)] // only used by some compression methods
 pub(crate) fn usize_to_u16(value: usize) -> Result<u16> {
     Ok(u16::try_from(value)?)
 }
 
 /// Panic on overflow.
 #[inline]
+#[cfg(feature = "piz")] // only used by the piz huffman coder
 pub(crate) fn u64_to_usize(value: u64) -> usize {
     usize::try_from(value).expect("(u64 as usize) overflowed")
 }
 
 /// Panic on overflow.
 #[inline]
+#[allow(unused)] // only used by some compression methods
 pub(crate) fn u32_to_usize(value: u32) -> usize {
     usize::try_from(value).expect("(u32 as usize) overflowed")
 }
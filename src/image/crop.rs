@@ -3,7 +3,8 @@
 
 use crate::meta::attribute::{IntegerBounds, LevelMode, ChannelList};
 use crate::math::{Vec2, RoundingMode};
-use crate::image::{Layer, FlatSamples, SpecificChannels, AnyChannels, FlatSamplesPixel, AnyChannel};
+use crate::image::{Image, Layer, Layers, FlatSamples, SpecificChannels, AnyChannels, FlatSamplesPixel, AnyChannel};
+use crate::block::samples::Sample;
 use crate::image::write::channels::{GetPixel, WritableChannels, ChannelsWriter};
 use crate::meta::header::{LayerAttributes, Header};
 use crate::block::BlockIndex;
@@ -290,6 +291,116 @@ impl ApplyCroppedView for Layer<CroppedChannels<AnyChannels<FlatSamples>>> {
 }
 
 
+impl Layer<AnyChannels<FlatSamples>> {
+
+    /// Lookup all channels of the pixel at the specified position.
+    /// The position is in the same coordinate system as the display window and the data window,
+    /// and is not relative to the data window of this layer.
+    /// Returns `None` if the position is outside the data window of this layer.
+    pub fn pixel_at_display_position(&self, position: Vec2<i32>) -> Option<FlatSamplesPixel> {
+        let local_position = (position - self.attributes.layer_position).to_usize("").ok()?;
+
+        if local_position.x() < self.size.width() && local_position.y() < self.size.height() {
+            Some(self.sample_vec_at(local_position))
+        }
+        else {
+            None
+        }
+    }
+
+    /// Copy the pixels of this layer into a new layer whose data window is exactly the specified bounds.
+    /// The bounds are in the same coordinate system as the data window of this layer.
+    /// Pixels of the bounds that are not inside the data window of this layer contain the default sample,
+    /// converted to the sample type of each channel, and pixels outside of the bounds are discarded.
+    /// The bounds may be larger, smaller, or completely disjoint from the data window of this layer.
+    /// Panics for subsampled channels.
+    pub fn expand_to(&self, bounds: IntegerBounds, default: impl Into<Sample>) -> Self {
+        let default = default.into();
+        let old_bounds = self.absolute_bounds();
+        let overlap = old_bounds.intersection(bounds).filter(|overlap| overlap.size.area() > 0);
+
+        fn copy_overlap<T: Copy>(samples: &[T], old_bounds: IntegerBounds, new_bounds: IntegerBounds, overlap: Option<IntegerBounds>, default: T) -> Vec<T> {
+            assert_eq!(samples.len(), old_bounds.size.area(), "subsampled channels are not supported");
+            let mut new_samples = vec![default; new_bounds.size.area()];
+
+            if let Some(overlap) = overlap {
+                // safe, because the overlap is inside both bounds
+                let old_start = (overlap.position - old_bounds.position).to_usize("overlap").unwrap();
+                let new_start = (overlap.position - new_bounds.position).to_usize("overlap").unwrap();
+                let width = overlap.size.width();
+
+                for y in 0 .. overlap.size.height() {
+                    let old_line = (old_start.y() + y) * old_bounds.size.width() + old_start.x();
+                    let new_line = (new_start.y() + y) * new_bounds.size.width() + new_start.x();
+                    new_samples[new_line .. new_line + width].copy_from_slice(&samples[old_line .. old_line + width]);
+                }
+            }
+
+            new_samples
+        }
+
+        let channels = self.channel_data.list.iter().map(|channel| {
+            let sample_data = match &channel.sample_data {
+                FlatSamples::F16(samples) => FlatSamples::F16(copy_overlap(samples, old_bounds, bounds, overlap, default.to_f16())),
+                FlatSamples::F32(samples) => FlatSamples::F32(copy_overlap(samples, old_bounds, bounds, overlap, default.to_f32())),
+                FlatSamples::U32(samples) => FlatSamples::U32(copy_overlap(samples, old_bounds, bounds, overlap, default.to_u32())),
+            };
+
+            AnyChannel {
+                name: channel.name.clone(),
+                sample_data,
+                quantize_linearly: channel.quantize_linearly,
+                sampling: channel.sampling,
+            }
+        }).collect();
+
+        Layer {
+            channel_data: AnyChannels { list: channels },
+            size: bounds.size,
+            attributes: LayerAttributes { layer_position: bounds.position, .. self.attributes.clone() },
+            encoding: self.encoding,
+        }
+    }
+
+    /// Copy the pixels of this layer into a new layer whose data window is exactly the display window.
+    /// Overscan pixels outside the display window are discarded,
+    /// and pixels of the display window that are not inside the data window contain the default sample.
+    /// Use `image.attributes.display_window` to obtain the display window of an image.
+    /// Panics for subsampled channels.
+    pub fn crop_to_display_window(&self, display_window: IntegerBounds, default: impl Into<Sample>) -> Self {
+        self.expand_to(display_window, default)
+    }
+}
+
+impl Image<Layers<AnyChannels<FlatSamples>>> {
+
+    /// Copy the pixels of each layer into a new layer whose data window is exactly the display window of this image.
+    /// See `Layer::crop_to_display_window` for details.
+    pub fn crop_to_display_window(&self, default: impl Into<Sample>) -> Self {
+        let default = default.into();
+
+        Image {
+            attributes: self.attributes.clone(),
+            layer_data: self.layer_data.iter()
+                .map(|layer| layer.crop_to_display_window(self.attributes.display_window, default))
+                .collect(),
+        }
+    }
+}
+
+impl Image<Layer<AnyChannels<FlatSamples>>> {
+
+    /// Copy the pixels of the layer into a new layer whose data window is exactly the display window of this image.
+    /// See `Layer::crop_to_display_window` for details.
+    pub fn crop_to_display_window(&self, default: impl Into<Sample>) -> Self {
+        Image {
+            attributes: self.attributes.clone(),
+            layer_data: self.layer_data.crop_to_display_window(self.attributes.display_window, default),
+        }
+    }
+}
+
+
 
 /// Return the smallest bounding rectangle including all pixels that satisfy the predicate.
 /// Worst case: Fully transparent image, visits each pixel once.
@@ -794,8 +905,66 @@ mod test {
         assert_eq!(bounds, None)
     }
 
-}
 
+    fn layer_with_values(position: Vec2<i32>, size: Vec2<usize>, values: Vec<f32>) -> Layer<AnyChannels<FlatSamples>> {
+        Layer::new(
+            size,
+            LayerAttributes { layer_position: position, .. LayerAttributes::default() },
+            crate::image::Encoding::UNCOMPRESSED,
+            AnyChannels::sort(smallvec::smallvec![ AnyChannel::new("Y", FlatSamples::F32(values)) ])
+        )
+    }
+
+    fn values_of(layer: &Layer<AnyChannels<FlatSamples>>) -> Vec<f32> {
+        layer.channel_data.list[0].sample_data.values_as_f32().collect()
+    }
 
+    #[test]
+    fn crop_overscanned_layer_to_display_window() {
+        // data window covers (-1,-1) to (2,2), display window covers (0,0) to (1,1)
+        let layer = layer_with_values(Vec2(-1, -1), Vec2(4, 4), (0 .. 16).map(|i| i as f32).collect());
+        let display_window = IntegerBounds::new((0, 0), (2, 2));
+
+        assert_eq!(layer.pixel_at_display_position(Vec2(-1, -1)).unwrap()[0].to_f32(), 0.0);
+        assert_eq!(layer.pixel_at_display_position(Vec2(0, 0)).unwrap()[0].to_f32(), 5.0);
+        assert_eq!(layer.pixel_at_display_position(Vec2(-2, 0)), None);
+        assert_eq!(layer.pixel_at_display_position(Vec2(0, 3)), None);
+
+        let cropped = layer.crop_to_display_window(display_window, 0.0_f32);
+        assert_eq!(cropped.absolute_bounds(), display_window);
+        assert_eq!(values_of(&cropped), vec![ 5.0, 6.0, 9.0, 10.0 ]);
+        assert_eq!(cropped.pixel_at_display_position(Vec2(1, 1)).unwrap()[0].to_f32(), 10.0);
+    }
 
+    #[test]
+    fn crop_auto_cropped_layer_to_display_window() {
+        let layer = layer_with_values(Vec2(0, 0), Vec2(4, 3), vec![
+            0.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 2.0, 0.0,
+            0.0, 0.0, 0.0, 0.0,
+        ]);
+
+        let display_window = layer.absolute_bounds();
+        let auto_cropped = layer.crop_where(|pixel: FlatSamplesPixel| pixel[0].to_f32() == 0.0).or_none_if_empty().unwrap().reallocate_cropped();
+        assert_eq!(auto_cropped.absolute_bounds(), IntegerBounds::new((1, 1), (2, 1)));
+
+        let restored = auto_cropped.crop_to_display_window(display_window, -1.0_f32);
+        assert_eq!(restored.absolute_bounds(), display_window);
+        assert_eq!(values_of(&restored), vec![
+            -1.0, -1.0, -1.0, -1.0,
+            -1.0,  1.0,  2.0, -1.0,
+            -1.0, -1.0, -1.0, -1.0,
+        ]);
+    }
 
+    #[test]
+    fn expand_to_disjoint_bounds() {
+        let layer = layer_with_values(Vec2(-5, -5), Vec2(2, 2), vec![ 1.0; 4 ]);
+        let bounds = IntegerBounds::new((3, 3), (2, 1));
+
+        let expanded = layer.expand_to(bounds, 7.0_f32);
+        assert_eq!(expanded.absolute_bounds(), bounds);
+        assert_eq!(values_of(&expanded), vec![ 7.0, 7.0 ]);
+    }
+
+}
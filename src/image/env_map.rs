@@ -0,0 +1,384 @@
+//! Map directions to the pixels of environment maps, as described by the `envmap` attribute.
+//! Follows the conventions of the `ImfEnvmap` functions of the reference implementation.
+//!
+//! Directions are `(x, y, z)` tuples which do not need to be normalized, where the `y` axis points up.
+//! Pixel positions are relative to the top left pixel of the data window,
+//! and the center of each pixel is at its integer coordinates.
+
+use std::f32::consts::PI;
+use crate::math::Vec2;
+use crate::meta::attribute::{IntegerBounds, EnvironmentMap};
+use crate::image::{Layer, AnyChannels, FlatSamples};
+use crate::error::{Result, Error, usize_to_i32};
+
+
+/// A direction in space, `(x, y, z)`, where the `y` axis points up.
+pub type Direction = (f32, f32, f32);
+
+/// One of the six faces of a cube environment map.
+/// The faces are stacked vertically in the image, in the order of this enum.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum CubeFace {
+
+    /// The face that the direction `(1, 0, 0)` points to.
+    PositiveX,
+
+    /// The face that the direction `(-1, 0, 0)` points to.
+    NegativeX,
+
+    /// The face that the direction `(0, 1, 0)` points to.
+    PositiveY,
+
+    /// The face that the direction `(0, -1, 0)` points to.
+    NegativeY,
+
+    /// The face that the direction `(0, 0, 1)` points to.
+    PositiveZ,
+
+    /// The face that the direction `(0, 0, -1)` points to.
+    NegativeZ,
+}
+
+impl CubeFace {
+
+    /// All faces, in the order in which they are stacked in the image, from top to bottom.
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX, CubeFace::NegativeX,
+        CubeFace::PositiveY, CubeFace::NegativeY,
+        CubeFace::PositiveZ, CubeFace::NegativeZ,
+    ];
+
+    /// The position of this face in the vertical stack of faces, where `0` is the topmost face.
+    pub fn index(self) -> usize { self as usize }
+}
+
+
+/// Compute where the direction points to in a latitude-longitude map.
+/// Returns coordinates in the range `0 ..= 1`, where `(0, 0)` is the center of the top left pixel,
+/// and `(1, 1)` is the center of the bottom right pixel.
+/// The direction `(0, 0, 1)` is in the center of the map, and `(0, 1, 0)` is at the top.
+/// The left and right edge of the map both contain the direction `(0, 0, -1)`.
+pub fn latlong_direction_to_uv(direction: Direction) -> Vec2<f32> {
+    let (x, y, z) = direction;
+    let length = (x*x + y*y + z*z).sqrt();
+    let radius = (x*x + z*z).sqrt();
+
+    // use the more precise function depending on the latitude
+    let latitude = {
+        if length == 0.0 { 0.0 }
+        else if radius < y.abs() { (radius / length).acos().copysign(y) }
+        else { (y / length).asin() }
+    };
+
+    let longitude = if x == 0.0 && z == 0.0 { 0.0 } else { x.atan2(z) };
+    Vec2(longitude / (-2.0 * PI) + 0.5, latitude / -PI + 0.5)
+}
+
+/// Compute the direction of a position in a latitude-longitude map.
+/// This is the inverse of `latlong_direction_to_uv`, and returns a normalized direction.
+pub fn latlong_uv_to_direction(uv: Vec2<f32>) -> Direction {
+    let latitude = -PI * (uv.y() - 0.5);
+    let longitude = -2.0 * PI * (uv.x() - 0.5);
+    (longitude.sin() * latitude.cos(), latitude.sin(), longitude.cos() * latitude.cos())
+}
+
+/// Locate a position of `latlong_direction_to_uv` in the pixels of a latitude-longitude map with the specified size.
+pub fn latlong_uv_to_pixel_position(uv: Vec2<f32>, size: Vec2<usize>) -> Vec2<f32> {
+    let max = Vec2(size.width().saturating_sub(1) as f32, size.height().saturating_sub(1) as f32);
+    Vec2(uv.x() * max.x(), uv.y() * max.y())
+}
+
+
+/// Find the cube face that the direction points to, and where on that face.
+/// The position on the face is in the range `0 ..= 1`, and is not yet oriented like the pixels in the image.
+/// Use `cube_face_uv_to_pixel_position` to locate the position in the pixels of the image.
+/// The zero direction points to the first corner of the positive x face.
+pub fn cube_direction_to_face_and_uv(direction: Direction) -> (CubeFace, Vec2<f32>) {
+    let (x, y, z) = direction;
+    let (abs_x, abs_y, abs_z) = (x.abs(), y.abs(), z.abs());
+    let face_uv = |u: f32, v: f32, major: f32| Vec2((u / major + 1.0) / 2.0, (v / major + 1.0) / 2.0);
+
+    if abs_x >= abs_y && abs_x >= abs_z {
+        if abs_x == 0.0 { return (CubeFace::PositiveX, Vec2(0.0, 0.0)); }
+        let face = if x > 0.0 { CubeFace::PositiveX } else { CubeFace::NegativeX };
+        (face, face_uv(y, z, abs_x))
+    }
+    else if abs_y >= abs_z {
+        let face = if y > 0.0 { CubeFace::PositiveY } else { CubeFace::NegativeY };
+        (face, face_uv(x, z, abs_y))
+    }
+    else {
+        let face = if z > 0.0 { CubeFace::PositiveZ } else { CubeFace::NegativeZ };
+        (face, face_uv(x, y, abs_z))
+    }
+}
+
+/// Compute the direction of a position on a cube face.
+/// This is the inverse of `cube_direction_to_face_and_uv`.
+/// The direction is not normalized, as its largest component is always one.
+pub fn cube_face_and_uv_to_direction(face: CubeFace, uv: Vec2<f32>) -> Direction {
+    let (u, v) = (uv.x() * 2.0 - 1.0, uv.y() * 2.0 - 1.0);
+
+    match face {
+        CubeFace::PositiveX => (1.0, u, v),
+        CubeFace::NegativeX => (-1.0, u, v),
+        CubeFace::PositiveY => (u, 1.0, v),
+        CubeFace::NegativeY => (u, -1.0, v),
+        CubeFace::PositiveZ => (u, v, 1.0),
+        CubeFace::NegativeZ => (u, v, -1.0),
+    }
+}
+
+/// The width and height of each face in a cube map with the specified size.
+/// Pixels which do not fit into six square faces are ignored.
+pub fn cube_face_size(size: Vec2<usize>) -> usize {
+    size.width().min(size.height() / 6)
+}
+
+/// The pixels of a face in a cube map with the specified size.
+/// The faces are stacked vertically, in the order of `CubeFace::ALL`.
+pub fn cube_face_pixel_rect(face: CubeFace, size: Vec2<usize>) -> IntegerBounds {
+    let face_size = cube_face_size(size);
+    IntegerBounds::new(Vec2(0, usize_to_i32(face.index() * face_size)), Vec2(face_size, face_size))
+}
+
+/// Locate a position of `cube_direction_to_face_and_uv` in the pixels of a cube map with the specified size.
+/// Each face is oriented as seen from the center of the cube, where the side faces are upright.
+pub fn cube_face_uv_to_pixel_position(face: CubeFace, uv: Vec2<f32>, size: Vec2<usize>) -> Vec2<f32> {
+    let face_size = cube_face_size(size);
+    let max = face_size.saturating_sub(1) as f32;
+    let (u, v) = (uv.x() * max, uv.y() * max);
+
+    let (left, top) = (0.0, (face.index() * face_size) as f32);
+    let (right, bottom) = (left + max, top + max);
+
+    match face {
+        CubeFace::PositiveX => Vec2(left + v, bottom - u),
+        CubeFace::NegativeX => Vec2(right - v, bottom - u),
+        CubeFace::PositiveY => Vec2(left + u, bottom - v),
+        CubeFace::NegativeY => Vec2(left + u, top + v),
+        CubeFace::PositiveZ => Vec2(right - u, bottom - v),
+        CubeFace::NegativeZ => Vec2(left + u, bottom - v),
+    }
+}
+
+
+impl Layer<AnyChannels<FlatSamples>> {
+
+    /// Look up the color of this environment map in the specified direction,
+    /// interpolating bilinearly between the four nearest pixels.
+    /// The projection is taken from the `environment_map` attribute of this layer.
+    /// On cube maps, the pixels of neighbouring faces are not used for interpolation.
+    /// Returns an error if the layer has no `environment_map` attribute,
+    /// or if it does not contain the channels `R`, `G`, and `B` without subsampling.
+    pub fn sample_environment(&self, direction: Direction) -> Result<(f32, f32, f32)> {
+        let channel = |name: &str| self.channel_data.list.iter()
+            .find(|channel| channel.name == *name)
+            .map(|channel| &channel.sample_data)
+            .ok_or(Error::invalid("environment map must contain rgb channels"));
+
+        let rgb = [ channel("R")?, channel("G")?, channel("B")? ];
+
+        if self.size.area() == 0 {
+            return Err(Error::invalid("environment map without pixels"));
+        }
+
+        if rgb.iter().any(|samples| samples.len() != self.size.area()) {
+            return Err(Error::unsupported("subsampled environment map"));
+        }
+
+        let (bounds, position) = match self.attributes.environment_map {
+            None => return Err(Error::invalid("layer is not an environment map")),
+
+            Some(EnvironmentMap::LatitudeLongitude) => (
+                IntegerBounds::from_dimensions(self.size),
+                latlong_uv_to_pixel_position(latlong_direction_to_uv(direction), self.size)
+            ),
+
+            Some(EnvironmentMap::Cube) => {
+                if cube_face_size(self.size) == 0 {
+                    return Err(Error::invalid("cube map is too small for six faces"));
+                }
+
+                let (face, uv) = cube_direction_to_face_and_uv(direction);
+                (cube_face_pixel_rect(face, self.size), cube_face_uv_to_pixel_position(face, uv, self.size))
+            },
+        };
+
+        let [red, green, blue] = rgb.map(|samples| sample_bilinear(samples, self.size.width(), bounds, position));
+        Ok((red, green, blue))
+    }
+}
+
+/// Interpolate between the four pixels nearest to the position, clamping the pixels to the bounds.
+fn sample_bilinear(samples: &FlatSamples, width: usize, bounds: IntegerBounds, position: Vec2<f32>) -> f32 {
+    let min = bounds.position;
+    let max = bounds.end() - Vec2(1, 1);
+
+    // also maps a nan position to the first pixel
+    let clamp = |value: f32, min: i32, max: i32| value.max(min as f32).min(max as f32) as usize;
+
+    let (left, top) = (position.x().floor(), position.y().floor());
+    let (x_weight, y_weight) = (position.x() - left, position.y() - top);
+
+    let (left_x, right_x) = (clamp(left, min.x(), max.x()), clamp(left + 1.0, min.x(), max.x()));
+    let (top_y, bottom_y) = (clamp(top, min.y(), max.y()), clamp(top + 1.0, min.y(), max.y()));
+
+    let sample = |x: usize, y: usize| samples.value_by_flat_index(y * width + x).to_f32();
+    let lerp = |a: f32, b: f32, weight: f32| if weight == 0.0 { a } else { a + (b - a) * weight };
+
+    let upper = lerp(sample(left_x, top_y), sample(right_x, top_y), x_weight);
+    let lower = lerp(sample(left_x, bottom_y), sample(right_x, bottom_y), x_weight);
+    lerp(upper, lower, y_weight)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image::AnyChannel;
+    use crate::meta::attribute::Text;
+
+    fn assert_close(actual: Vec2<f32>, expected: Vec2<f32>) {
+        assert!(
+            (actual.x() - expected.x()).abs() < 1e-4 && (actual.y() - expected.y()).abs() < 1e-4,
+            "expected {:?}, but was {:?}", expected, actual
+        );
+    }
+
+    fn assert_same_direction(actual: Direction, expected: Direction) {
+        let normalize = |(x, y, z): Direction| {
+            let length = (x*x + y*y + z*z).sqrt();
+            (x / length, y / length, z / length)
+        };
+
+        let (actual, expected) = (normalize(actual), normalize(expected));
+        let distance = (actual.0 - expected.0).abs() + (actual.1 - expected.1).abs() + (actual.2 - expected.2).abs();
+        assert!(distance < 1e-4, "expected {:?}, but was {:?}", expected, actual);
+    }
+
+    #[test]
+    fn latlong_axes() {
+        assert_close(latlong_direction_to_uv((0.0, 0.0, 1.0)), Vec2(0.5, 0.5));
+        assert_close(latlong_direction_to_uv((1.0, 0.0, 0.0)), Vec2(0.25, 0.5));
+        assert_close(latlong_direction_to_uv((-1.0, 0.0, 0.0)), Vec2(0.75, 0.5));
+        assert_close(latlong_direction_to_uv((0.0, 0.0, -1.0)), Vec2(0.0, 0.5));
+        assert_close(latlong_direction_to_uv((0.0, 5.0, 0.0)), Vec2(0.5, 0.0));
+        assert_close(latlong_direction_to_uv((0.0, -5.0, 0.0)), Vec2(0.5, 1.0));
+
+        // the zero direction does not produce nan values
+        assert_close(latlong_direction_to_uv((0.0, 0.0, 0.0)), Vec2(0.5, 0.5));
+
+        assert_close(latlong_uv_to_pixel_position(Vec2(0.25, 0.5), Vec2(9, 5)), Vec2(2.0, 2.0));
+    }
+
+    #[test]
+    fn latlong_round_trip() {
+        for &direction in &[(0.3, 0.2, 0.9), (-0.7, 0.1, -0.2), (0.1, -0.95, 0.05), (0.0, 0.99, 0.1)] {
+            assert_same_direction(latlong_uv_to_direction(latlong_direction_to_uv(direction)), direction);
+        }
+    }
+
+    #[test]
+    fn cube_axes_point_to_face_centers() {
+        let size = Vec2(8, 8 * 6);
+        let center = 3.5;
+
+        let axes = [
+            ((1.0, 0.0, 0.0), CubeFace::PositiveX),
+            ((-1.0, 0.0, 0.0), CubeFace::NegativeX),
+            ((0.0, 1.0, 0.0), CubeFace::PositiveY),
+            ((0.0, -1.0, 0.0), CubeFace::NegativeY),
+            ((0.0, 0.0, 1.0), CubeFace::PositiveZ),
+            ((0.0, 0.0, -1.0), CubeFace::NegativeZ),
+        ];
+
+        for &(direction, expected_face) in &axes {
+            let (face, uv) = cube_direction_to_face_and_uv(direction);
+            assert_eq!(face, expected_face);
+            assert_close(uv, Vec2(0.5, 0.5));
+
+            let face_top = (face.index() * 8) as f32;
+            assert_close(cube_face_uv_to_pixel_position(face, uv, size), Vec2(center, face_top + center));
+
+            assert_eq!(cube_face_pixel_rect(face, size), IntegerBounds::new(Vec2(0, face.index() as i32 * 8), Vec2(8, 8)));
+        }
+    }
+
+    #[test]
+    fn cube_side_faces_are_upright() {
+        let size = Vec2(8, 8 * 6);
+
+        // looking slightly upwards at the side faces points to the top edge of the face
+        for &(direction, face) in &[
+            ((1.0, 0.99, 0.0), CubeFace::PositiveX), ((-1.0, 0.99, 0.0), CubeFace::NegativeX),
+            ((0.0, 0.99, 1.0), CubeFace::PositiveZ), ((0.0, 0.99, -1.0), CubeFace::NegativeZ),
+        ] {
+            let (actual_face, uv) = cube_direction_to_face_and_uv(direction);
+            assert_eq!(actual_face, face);
+
+            let position = cube_face_uv_to_pixel_position(face, uv, size);
+            let face_top = (face.index() * 8) as f32;
+            assert!((position.y() - face_top).abs() < 0.1, "{:?} at {:?}", face, position);
+            assert!((position.x() - 3.5).abs() < 1e-4, "{:?} at {:?}", face, position);
+        }
+
+        // the positive z face shows the positive x axis on its left side
+        let (face, uv) = cube_direction_to_face_and_uv((0.99, 0.0, 1.0));
+        assert_eq!(face, CubeFace::PositiveZ);
+        assert!(cube_face_uv_to_pixel_position(face, uv, size).x() < 0.1);
+
+        // all faces are seen from inside the cube, so the top face shows the positive z axis at its top edge
+        let (face, uv) = cube_direction_to_face_and_uv((0.0, 1.0, 0.99));
+        assert_eq!(face, CubeFace::PositiveY);
+        assert!(cube_face_uv_to_pixel_position(face, uv, size).y() < 2.0 * 8.0 + 0.1);
+
+        let (face, uv) = cube_direction_to_face_and_uv((0.0, -1.0, 0.99));
+        assert_eq!(face, CubeFace::NegativeY);
+        assert!(cube_face_uv_to_pixel_position(face, uv, size).y() > 3.0 * 8.0 + 6.9);
+    }
+
+    #[test]
+    fn cube_round_trip() {
+        for &direction in &[(0.3, 0.2, 0.9), (-0.7, 0.1, -0.2), (0.1, -0.95, 0.05), (0.9, 0.99, 0.1)] {
+            let (face, uv) = cube_direction_to_face_and_uv(direction);
+            assert_same_direction(cube_face_and_uv_to_direction(face, uv), direction);
+        }
+    }
+
+    #[test]
+    fn sample_environment_bilinearly() {
+        let size = Vec2(5, 3);
+
+        // the red channel increases with x, the green channel with y
+        let layer = |environment_map: Option<EnvironmentMap>| {
+            let channel = |name: &str, value: fn(usize, usize) -> f32| AnyChannel::new(name, FlatSamples::F32(
+                (0 .. size.area()).map(|index| value(index % size.width(), index / size.width())).collect()
+            ));
+
+            let mut layer = Layer::new(
+                size, crate::meta::header::LayerAttributes::default(), crate::image::Encoding::default(),
+                AnyChannels::sort(smallvec::smallvec![
+                    channel("R", |x, _| x as f32), channel("G", |_, y| y as f32), channel("B", |_, _| 1.0),
+                ])
+            );
+
+            layer.attributes.environment_map = environment_map;
+            layer
+        };
+
+        assert!(layer(None).sample_environment((0.0, 0.0, 1.0)).is_err());
+
+        let latlong = layer(Some(EnvironmentMap::LatitudeLongitude));
+        assert_eq!(latlong.sample_environment((0.0, 0.0, 1.0)).unwrap(), (2.0, 1.0, 1.0));
+        assert_eq!(latlong.sample_environment((0.0, 1.0, 0.0)).unwrap(), (2.0, 0.0, 1.0));
+
+        // half way between the first and second column
+        let (red, green, _) = latlong.sample_environment(latlong_uv_to_direction(Vec2(0.125, 0.5))).unwrap();
+        assert!((red - 0.5).abs() < 1e-4 && (green - 1.0).abs() < 1e-4, "{} {}", red, green);
+
+        let mut missing_blue = latlong.clone();
+        missing_blue.channel_data.list.retain(|channel| channel.name != Text::from("B"));
+        assert!(missing_blue.sample_environment((0.0, 0.0, 1.0)).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! Composite deep pixels into flat pixels, as most applications will display deep images.
+//! The samples of each pixel are sorted by depth and combined front to back using the "over" operation.
+//!
+//! The channel named `A` contains the alpha of each sample, and all other floating point channels
+//! are assumed to be premultiplied by that alpha. The channels `Z` and `ZBack` contain the depth of each sample.
+//! Volumetric samples, where `ZBack` is larger than `Z`, are composited as if they were point samples at `Z`.
+//! Overlapping volumetric samples are not split.
+
+use crate::image::*;
+use smallvec::smallvec;
+use crate::block::samples::Sample;
+use crate::block::deep::DeepUncompressedBlock;
+use crate::meta::attribute::SampleType;
+use crate::error::{Error, UnitResult};
+use std::cmp::Ordering;
+
+
+impl Image<Layers<AnyChannels<DeepSamples>>> {
+
+    /// Composite the samples of each deep pixel into a single flat sample per channel.
+    /// The resulting flat image has the same layers, channels, attributes and encoding.
+    /// See [`Layer::flatten`] for details.
+    pub fn flatten(&self) -> FlatImage {
+        Image {
+            attributes: self.attributes.clone(),
+            layer_data: self.layer_data.iter().map(Layer::flatten).collect(),
+        }
+    }
+}
+
+impl Layer<AnyChannels<DeepSamples>> {
+
+    /// Composite the samples of each deep pixel into a single flat sample per channel.
+    ///
+    /// The samples of each pixel are sorted by `Z` and `ZBack`, and are combined front to back
+    /// using the channel `A` as alpha. Compositing stops as soon as the pixel is opaque.
+    /// Without an alpha channel, all samples are opaque, and only the front-most sample is visible.
+    /// The resulting `Z` is the depth of the front-most sample, and the resulting `ZBack`
+    /// is the largest `ZBack` of all visible samples. `u32` channels, such as object ids,
+    /// contain the value of the front-most sample.
+    ///
+    /// Pixels without samples are transparent black, with all channels set to zero.
+    /// Samples with a `NaN` alpha or depth are ignored, and alpha values are clamped to the range `0 ..= 1`.
+    /// Infinite or `NaN` values in other channels are composited like any other value,
+    /// but do not affect the pixel if an opaque sample is in front of them.
+    pub fn flatten(&self) -> Layer<AnyChannels<FlatSamples>> {
+        let channels = &self.channel_data.list;
+        let mut flat_layer = create_flat_layer(self);
+        let mut compositor = Compositor::new(channels.iter().map(|channel| (&channel.name, channel.sample_data.sample_type())));
+
+        for pixel_index in 0 .. self.size.area() {
+            let sample_count = channels.first()
+                .map_or(0, |channel| channel.sample_data.sample_count_by_flat_index(pixel_index));
+
+            let flat_pixel = compositor.composite(sample_count, |channel_index, sample_index| {
+                channels[channel_index].sample_data.value_by_flat_index(pixel_index, sample_index)
+            });
+
+            for (flat_channel, &sample) in flat_layer.channel_data.list.iter_mut().zip(flat_pixel) {
+                set_flat_sample(&mut flat_channel.sample_data, pixel_index, sample);
+            }
+        }
+
+        flat_layer
+    }
+}
+
+/// Create a layer with transparent black pixels, with the same channels as the deep layer.
+fn create_flat_layer(deep_layer: &Layer<AnyChannels<DeepSamples>>) -> Layer<AnyChannels<FlatSamples>> {
+    let pixel_count = deep_layer.size.area();
+
+    Layer {
+        size: deep_layer.size,
+        attributes: deep_layer.attributes.clone(),
+        encoding: deep_layer.encoding,
+
+        channel_data: AnyChannels { // not using `new()` as the channels are already sorted
+            list: deep_layer.channel_data.list.iter()
+                .map(|channel| AnyChannel {
+                    name: channel.name.clone(),
+                    quantize_linearly: channel.quantize_linearly,
+                    sampling: channel.sampling,
+
+                    sample_data: match channel.sample_data.sample_type() {
+                        SampleType::F16 => FlatSamples::F16(vec![f16::ZERO; pixel_count]),
+                        SampleType::F32 => FlatSamples::F32(vec![0.0; pixel_count]),
+                        SampleType::U32 => FlatSamples::U32(vec![0; pixel_count]),
+                    },
+                })
+                .collect()
+        },
+    }
+}
+
+/// Composite all pixels of the deep block into the flat layer.
+pub(crate) fn flatten_block_into(layer: &mut Layer<AnyChannels<FlatSamples>>, compositor: &mut Compositor, block: &DeepUncompressedBlock) -> UnitResult {
+    let layer_width = layer.size.width();
+    let block_width = block.index.pixel_size.width().max(1);
+
+    // the samples of a pixel in each channel start after the samples of all previous pixels
+    let mut sample_start = 0;
+
+    for (pixel_index_in_block, &sample_count) in block.sample_counts.iter().enumerate() {
+        let sample_count = sample_count as usize;
+        let position = block.index.pixel_position + Vec2(pixel_index_in_block % block_width, pixel_index_in_block / block_width);
+        let pixel_index = position.y() * layer_width + position.x();
+
+        if position.x() >= layer_width || pixel_index >= layer.size.area() {
+            return Err(Error::invalid("deep block position"));
+        }
+
+        if block.channels.iter().any(|samples| samples.len() < sample_start + sample_count) {
+            return Err(Error::invalid("deep block sample count"));
+        }
+
+        let flat_pixel = compositor.composite(sample_count, |channel_index, sample_index| {
+            block.channels[channel_index].value_by_index(sample_start + sample_index)
+        });
+
+        for (flat_channel, &sample) in layer.channel_data.list.iter_mut().zip(flat_pixel) {
+            set_flat_sample(&mut flat_channel.sample_data, pixel_index, sample);
+        }
+
+        sample_start += sample_count;
+    }
+
+    Ok(())
+}
+
+fn set_flat_sample(samples: &mut FlatSamples, index: usize, sample: Sample) {
+    match samples {
+        FlatSamples::F16(vec) => vec[index] = sample.to_f16(),
+        FlatSamples::F32(vec) => vec[index] = sample.to_f32(),
+        FlatSamples::U32(vec) => vec[index] = sample.to_u32(),
+    }
+}
+
+
+/// How a channel takes part in compositing.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ChannelRole {
+
+    /// A premultiplied floating point value, composited using the alpha of the sample.
+    Color,
+
+    /// The alpha of the sample.
+    Alpha,
+
+    /// The front depth of the sample.
+    Depth,
+
+    /// The back depth of the sample.
+    DepthBack,
+
+    /// An integer value, for example an object id, that cannot be blended.
+    Identifier,
+}
+
+/// Composites a single deep pixel after another, reusing its allocations.
+#[derive(Debug, Clone)]
+pub(crate) struct Compositor {
+    roles: SmallVec<[(ChannelRole, SampleType); 8]>,
+    alpha: Option<usize>,
+    depth: Option<usize>,
+    depth_back: Option<usize>,
+
+    sorted_samples: Vec<usize>,
+    sums: SmallVec<[f32; 8]>,
+    flat_pixel: SmallVec<[Sample; 8]>,
+}
+
+impl Compositor {
+
+    /// Determine the role of each channel by its name.
+    pub(crate) fn new<'c>(channels: impl Iterator<Item=(&'c Text, SampleType)>) -> Self {
+        let roles: SmallVec<[(ChannelRole, SampleType); 8]> = channels
+            .map(|(name, sample_type)| {
+                let role = if sample_type == SampleType::U32 { ChannelRole::Identifier }
+                    else if name.eq("A") { ChannelRole::Alpha }
+                    else if name.eq("Z") { ChannelRole::Depth }
+                    else if name.eq("ZBack") { ChannelRole::DepthBack }
+                    else { ChannelRole::Color };
+
+                (role, sample_type)
+            })
+            .collect();
+
+        let find = |role| roles.iter().position(|&(channel_role, _)| channel_role == role);
+
+        Compositor {
+            alpha: find(ChannelRole::Alpha),
+            depth: find(ChannelRole::Depth),
+            depth_back: find(ChannelRole::DepthBack),
+            sums: smallvec![0.0; roles.len()],
+            flat_pixel: SmallVec::with_capacity(roles.len()),
+            sorted_samples: Vec::new(),
+            roles,
+        }
+    }
+
+    /// Composite the samples of a pixel, returning one flat sample for each channel.
+    /// The closure returns a sample by channel index and sample index.
+    pub(crate) fn composite(&mut self, sample_count: usize, sample: impl Fn(usize, usize) -> Sample) -> &[Sample] {
+        let value = |channel: Option<usize>, sample_index: usize| channel.map(|channel| sample(channel, sample_index).to_f32());
+        let (alpha, depth, depth_back) = (self.alpha, self.depth, self.depth_back);
+
+        self.sorted_samples.clear();
+        self.sorted_samples.extend((0 .. sample_count).filter(|&sample_index| {
+            !value(alpha, sample_index).map_or(false, f32::is_nan)
+                && !value(depth, sample_index).map_or(false, f32::is_nan)
+        }));
+
+        // stable sort, so that samples at the same depth keep their order from the file
+        self.sorted_samples.sort_by(|&a, &b| {
+            let compare = |channel| value(channel, a).partial_cmp(&value(channel, b)).unwrap_or(Ordering::Equal);
+            compare(depth).then_with(|| compare(depth_back))
+        });
+
+        for sum in &mut self.sums { *sum = 0.0; }
+        let mut accumulated_alpha = 0.0;
+        let mut largest_depth_back = None;
+
+        for &sample_index in &self.sorted_samples {
+            let sample_alpha = value(alpha, sample_index).map_or(1.0, |alpha| alpha.max(0.0).min(1.0));
+            let transmission = 1.0 - accumulated_alpha;
+
+            for (channel_index, (sum, &(role, _))) in self.sums.iter_mut().zip(&self.roles).enumerate() {
+                if role == ChannelRole::Color {
+                    *sum += transmission * sample(channel_index, sample_index).to_f32();
+                }
+            }
+
+            accumulated_alpha += transmission * sample_alpha;
+
+            if let Some(sample_depth_back) = value(depth_back, sample_index) {
+                largest_depth_back = Some(largest_depth_back.map_or(sample_depth_back, |largest: f32| largest.max(sample_depth_back)));
+            }
+
+            if accumulated_alpha >= 1.0 { break; }
+        }
+
+        let front_sample = self.sorted_samples.first().copied();
+
+        self.flat_pixel.clear();
+        for (channel_index, (&sum, &(role, sample_type))) in self.sums.iter().zip(&self.roles).enumerate() {
+            let flat_value = match role {
+                ChannelRole::Color => sum,
+                ChannelRole::Alpha => accumulated_alpha,
+                ChannelRole::Depth => front_sample.map_or(0.0, |front| sample(channel_index, front).to_f32()),
+                ChannelRole::DepthBack => largest_depth_back.unwrap_or(0.0),
+
+                ChannelRole::Identifier => {
+                    self.flat_pixel.push(Sample::U32(front_sample.map_or(0, |front| sample(channel_index, front).to_u32())));
+                    continue;
+                },
+            };
+
+            self.flat_pixel.push(match sample_type {
+                SampleType::F16 => Sample::F16(f16::from_f32(flat_value)),
+                SampleType::F32 => Sample::F32(flat_value),
+                SampleType::U32 => Sample::U32(flat_value as u32),
+            });
+        }
+
+        &self.flat_pixel
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn composite(channels: &[(&str, SampleType)], samples: &[&[f32]]) -> Vec<f32> {
+        let names: Vec<Text> = channels.iter().map(|&(name, _)| Text::from(name)).collect();
+        let mut compositor = Compositor::new(names.iter().zip(channels.iter().map(|&(_, sample_type)| sample_type)));
+
+        compositor.composite(samples.len(), |channel, sample| Sample::F32(samples[sample][channel]))
+            .iter().map(|sample| sample.to_f32()).collect()
+    }
+
+    const RGBAZ: &[(&str, SampleType)] = &[
+        ("R", SampleType::F32), ("A", SampleType::F32), ("Z", SampleType::F32), ("ZBack", SampleType::F32)
+    ];
+
+    #[test]
+    fn empty_pixel_is_transparent_black() {
+        assert_eq!(composite(RGBAZ, &[]), vec![0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn composites_front_to_back_sorted_by_depth() {
+        // red, alpha, depth, depth back; the back sample is stored first
+        let back = [0.25, 0.5, 10.0, 12.0];
+        let front = [0.4, 0.5, 2.0, 2.0];
+
+        let flat = composite(RGBAZ, &[&back, &front]);
+        assert_eq!(flat, vec![0.4 + 0.5 * 0.25, 0.5 + 0.5 * 0.5, 2.0, 12.0]);
+    }
+
+    #[test]
+    fn hidden_samples_do_not_contribute() {
+        let opaque = [1.0, 1.0, 1.0, 1.0];
+        let hidden = [f32::INFINITY, 0.5, 3.0, 3.0];
+
+        assert_eq!(composite(RGBAZ, &[&hidden, &opaque]), vec![1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn ignores_nan_samples_and_clamps_alpha() {
+        let nan_depth = [0.7, 0.5, f32::NAN, 4.0];
+        let nan_alpha = [0.7, f32::NAN, 1.0, 1.0];
+        let overflowing_alpha = [0.6, 3.0, 2.0, 2.0];
+
+        assert_eq!(composite(RGBAZ, &[&nan_depth, &nan_alpha, &overflowing_alpha]), vec![0.6, 1.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn without_alpha_the_front_sample_is_opaque() {
+        let channels = &[("G", SampleType::F16), ("Z", SampleType::F32), ("id", SampleType::U32)];
+        let flat = composite(channels, &[&[0.5, 4.0, 7.0], &[0.75, 3.0, 9.0]]);
+        assert_eq!(flat, vec![0.75, 3.0, 9.0]);
+    }
+}
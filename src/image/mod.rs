@@ -28,6 +28,9 @@ pub mod write;
 pub mod crop;
 pub mod pixel_vec;
 pub mod recursive;
+pub mod flatten;
+pub mod env_map;
+pub mod tonemap;
 // pub mod channel_groups;
 
 
@@ -43,13 +46,17 @@ use crate::error::Error;
 pub(crate) fn ignore_progress(_progress: f64){}
 
 /// This image type contains all supported exr features and can represent almost any image.
-/// It currently does not support deep data yet.
+/// Does not contain deep data, see `DeepImage` instead.
 pub type AnyImage = Image<Layers<AnyChannels<Levels<FlatSamples>>>>;
 
 /// This image type contains the most common exr features and can represent almost any plain image.
 /// Does not contain resolution levels. Does not support deep data.
 pub type FlatImage = Image<Layers<AnyChannels<FlatSamples>>>;
 
+/// This image type contains the deep layers of an image, where each pixel can contain any number of samples.
+/// Does not contain resolution levels.
+pub type DeepImage = Image<Layers<AnyChannels<DeepSamples>>>;
+
 /// This image type contains multiple layers, with each layer containing a user-defined type of pixels.
 pub type PixelLayersImage<Storage, Channels> = Image<Layers<SpecificChannels<Storage, Channels>>>;
 
@@ -200,6 +207,23 @@ pub struct AnyChannel<Samples> {
     pub sampling: Vec2<usize>,
 }
 
+/// A list of channels, where each channel is stored in a separate vector of `f32` samples.
+/// In contrast to `SpecificChannels`, the samples of a pixel are not interleaved,
+/// which is convenient for processing each channel separately.
+/// Can be read using `specific_channels().required("R").planar()`.
+/// The channels are written as `f32` samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanarChannels {
+
+    /// The width and height of each channel.
+    pub resolution: Vec2<usize>,
+
+    /// The name and the samples of each channel.
+    /// The samples of a channel are stored row by row,
+    /// and must contain exactly `resolution.area()` samples.
+    pub channels: Vec<(Text, Vec<f32>)>,
+}
+
 /// One or multiple resolution levels of the same image.
 /// `Samples` can be `FlatSamples`.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -278,12 +302,51 @@ pub enum FlatSamples {
 }
 
 
-/*#[derive(Clone, PartialEq)]
+/// A vector of deep values (any number of values per pixel per channel).
+/// Stores row after row in a single vector, with a small vector of samples for each pixel.
+/// The precision of all values is either `f16`, `f32` or `u32`.
+#[derive(Clone, PartialEq)] // debug is implemented manually
 pub enum DeepSamples {
-    F16(Vec<Vec<f16>>),
-    F32(Vec<Vec<f32>>),
-    U32(Vec<Vec<u32>>),
-}*/
+
+    /// A vector of deep `f16` values.
+    F16(Vec<SmallVec<[f16; 4]>>),
+
+    /// A vector of deep `f32` values.
+    F32(Vec<SmallVec<[f32; 4]>>),
+
+    /// A vector of deep `u32` values.
+    U32(Vec<SmallVec<[u32; 4]>>),
+}
+
+/// All deep samples of a single channel in one contiguous vector, with the position of the samples of each pixel.
+/// The samples of the pixel with the flat index `i` are found at `sample_offsets[i] .. sample_offsets[i + 1]`.
+/// Stores row after row. Compared to `DeepSamples`, this needs fewer allocations,
+/// and allows processing all samples of a channel at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContiguousDeepSamples {
+
+    /// For each pixel, the index of its first sample, followed by the total number of samples.
+    /// Contains one more element than the number of pixels.
+    pub sample_offsets: Vec<usize>,
+
+    /// The samples of all pixels, row after row.
+    pub samples: DeepChannelSamples,
+}
+
+/// A single sample of a deep pixel, containing one value for each channel of the layer.
+pub type DeepSample = SmallVec<[Sample; 5]>;
+
+/// All channels of a deep layer, storing the samples of each pixel together.
+/// Each sample contains the values of all channels, in the order of the channel list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeepPixels {
+
+    /// The channels of the layer, in the same order as the values in each sample.
+    pub channels: ChannelList,
+
+    /// For each pixel, row after row, all samples of that pixel.
+    pub pixels: Vec<SmallVec<[DeepSample; 4]>>,
+}
 
 use crate::block::samples::*;
 use crate::meta::attribute::*;
@@ -291,13 +354,13 @@ use crate::error::Result;
 use crate::block::samples::Sample;
 use crate::image::write::channels::*;
 use crate::image::write::layers::WritableLayers;
-use crate::image::write::samples::{WritableSamples};
 use crate::meta::{mip_map_levels, rip_map_levels};
 use crate::io::Data;
 use crate::image::recursive::{NoneMore, Recursive, IntoRecursive};
 use std::marker::PhantomData;
 use std::ops::Not;
 use crate::image::validate_results::{ValidationOptions};
+use crate::block::deep::DeepChannelSamples;
 
 
 impl<Channels> Layer<Channels> {
@@ -305,6 +368,13 @@ impl<Channels> Layer<Channels> {
     pub fn absolute_bounds(&self) -> IntegerBounds {
         IntegerBounds::new(self.attributes.layer_position, self.size)
     }
+
+    /// Set the name of this layer. The layer name is written as a separate attribute,
+    /// so channel names like `R` need no adjustment. Channels that contain the old layer name,
+    /// like `beauty.R`, can be renamed using `AnyChannels::rename_prefix`.
+    pub fn rename(&mut self, new_name: impl Into<Text>) {
+        self.attributes.layer_name = Some(new_name.into());
+    }
 }
 
 
@@ -551,6 +621,23 @@ impl<SampleData> AnyChannels<SampleData>{
         list.sort_unstable_by_key(|channel| channel.name.clone()); // TODO no clone?
         Self { list }
     }
+
+    /// Replace the prefix of all channels whose name starts with `old_prefix`,
+    /// for example `"diffuse."` with `"albedo."`, returning the number of renamed channels.
+    /// Keeps the channels sorted alphabetically.
+    /// Returns an error and leaves the channels unchanged if two channels would end up with the same name.
+    pub fn rename_prefix(&mut self, old_prefix: &str, new_prefix: &str) -> Result<usize> {
+        let (old_prefix, new_prefix) = prefix_texts(old_prefix, new_prefix)?;
+
+        let (renamed_count, renamed) = rename_and_sort(self.list.iter().map(|channel| &channel.name), &old_prefix, &new_prefix)?;
+
+        let mut channels: Vec<Option<AnyChannel<SampleData>>> = self.list.drain(..).map(Some).collect();
+        self.list = renamed.into_iter().map(|(index, name)| AnyChannel {
+            name, .. channels[index].take().expect("channel renaming bug")
+        }).collect();
+
+        Ok(renamed_count)
+    }
 }
 
 // FIXME check content size of layer somewhere??? before writing?
@@ -664,6 +751,15 @@ impl FlatSamples {
         }
     }
 
+    /// The type of all samples in this channel.
+    pub fn sample_type(&self) -> SampleType {
+        match self {
+            FlatSamples::F16(_) => SampleType::F16,
+            FlatSamples::F32(_) => SampleType::F32,
+            FlatSamples::U32(_) => SampleType::U32,
+        }
+    }
+
     /// Views all samples in this storage as f32.
     /// Matches the underlying sample type again for every sample,
     /// match yourself if performance is critical! Does not allocate.
@@ -690,6 +786,93 @@ impl FlatSamples {
     }
 }
 
+impl DeepSamples {
+
+    /// The number of pixels in the image. Should be the width times the height.
+    pub fn len(&self) -> usize {
+        match self {
+            DeepSamples::F16(vec) => vec.len(),
+            DeepSamples::F32(vec) => vec.len(),
+            DeepSamples::U32(vec) => vec.len(),
+        }
+    }
+
+    /// The type of all samples in this channel.
+    pub fn sample_type(&self) -> SampleType {
+        match self {
+            DeepSamples::F16(_) => SampleType::F16,
+            DeepSamples::F32(_) => SampleType::F32,
+            DeepSamples::U32(_) => SampleType::U32,
+        }
+    }
+
+    /// The number of samples in a single pixel, by flat index.
+    /// The flat index of a pixel is `y * width + x`.
+    pub fn sample_count_by_flat_index(&self, index: usize) -> usize {
+        match self {
+            DeepSamples::F16(vec) => vec[index].len(),
+            DeepSamples::F32(vec) => vec[index].len(),
+            DeepSamples::U32(vec) => vec[index].len(),
+        }
+    }
+
+    /// The number of samples in all pixels of the image.
+    pub fn total_sample_count(&self) -> usize {
+        (0..self.len()).map(|index| self.sample_count_by_flat_index(index)).sum()
+    }
+
+    /// A single sample of a pixel, by flat index and sample index.
+    /// The flat index of a pixel is `y * width + x`.
+    pub fn value_by_flat_index(&self, index: usize, sample_index: usize) -> Sample {
+        match self {
+            DeepSamples::F16(vec) => Sample::F16(vec[index][sample_index]),
+            DeepSamples::F32(vec) => Sample::F32(vec[index][sample_index]),
+            DeepSamples::U32(vec) => Sample::U32(vec[index][sample_index]),
+        }
+    }
+
+    /// All samples of a single pixel, by flat index.
+    /// The flat index of a pixel is `y * width + x`.
+    /// Matches the underlying sample type again for every sample.
+    pub fn values_by_flat_index<'s>(&'s self, index: usize) -> impl 's + Iterator<Item = Sample> {
+        (0..self.sample_count_by_flat_index(index)).map(move |sample_index| self.value_by_flat_index(index, sample_index))
+    }
+}
+
+
+impl ContiguousDeepSamples {
+
+    /// The number of pixels in the image. Should be the width times the height.
+    pub fn len(&self) -> usize {
+        self.sample_offsets.len().saturating_sub(1)
+    }
+
+    /// The number of samples in a single pixel, by flat index.
+    /// The flat index of a pixel is `y * width + x`.
+    pub fn sample_count_by_flat_index(&self, index: usize) -> usize {
+        self.sample_offsets[index + 1] - self.sample_offsets[index]
+    }
+
+    /// The number of samples in all pixels of the image.
+    pub fn total_sample_count(&self) -> usize {
+        self.sample_offsets.last().copied().unwrap_or(0)
+    }
+
+    /// All samples of a single pixel, by flat index.
+    /// The flat index of a pixel is `y * width + x`.
+    pub fn values_by_flat_index<'s>(&'s self, index: usize) -> impl 's + Iterator<Item = Sample> {
+        (self.sample_offsets[index] .. self.sample_offsets[index + 1]).map(move |sample_index| self.samples.value_by_index(sample_index))
+    }
+}
+
+impl DeepPixels {
+
+    /// The number of samples in all pixels of the image.
+    pub fn total_sample_count(&self) -> usize {
+        self.pixels.iter().map(|pixel| pixel.len()).sum()
+    }
+}
+
 
 impl<'s, ChannelData:'s> Layer<ChannelData> {
 
@@ -706,6 +889,12 @@ impl<'s, ChannelData:'s> Layer<ChannelData> {
         Layer { channel_data: channels, attributes, size: dimensions.into(), encoding }
     }
 
+    /// Set the order in which the blocks of this layer will be written to the file.
+    /// Does not change any actual image orientation.
+    pub fn with_line_order(self, line_order: LineOrder) -> Self {
+        Layer { encoding: Encoding { line_order, .. self.encoding }, .. self }
+    }
+
     // TODO test pls wtf
     /// Panics for images with Scanline encoding.
     pub fn levels_with_resolution<'l, L>(&self, levels: &'l Levels<L>) -> Box<dyn 'l + Iterator<Item=(&'l L, Vec2<usize>)>> {
@@ -823,15 +1012,16 @@ impl<'s, InnerLayers: 's> Image<InnerLayers> where
 }
 
 
-impl<'s, SampleData: 's> AnyChannel<SampleData> {
+impl<SampleData> AnyChannel<SampleData> {
 
     /// Create a new channel without subsampling.
+    /// The sample data can be either `FlatSamples`, `Levels<FlatSamples>`, or `DeepSamples`.
     ///
     /// Automatically flags this channel for specialized compression
     /// if the name is "R", "G", "B", "Y", or "L",
     /// as they typically encode values that are perceived non-linearly.
     /// Construct the value yourself using `AnyChannel { .. }`, if you want to control this flag.
-    pub fn new(name: impl Into<Text>, sample_data: SampleData) -> Self where SampleData: WritableSamples<'s> {
+    pub fn new(name: impl Into<Text>, sample_data: SampleData) -> Self {
         let name: Text = name.into();
 
         AnyChannel {
@@ -869,6 +1059,16 @@ impl std::fmt::Debug for FlatSamples {
 }
 
 
+impl std::fmt::Debug for DeepSamples {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeepSamples::F16(vec) => write!(formatter, "[deep f16; {} pixels]", vec.len()),
+            DeepSamples::F32(vec) => write!(formatter, "[deep f32; {} pixels]", vec.len()),
+            DeepSamples::U32(vec) => write!(formatter, "[deep u32; {} pixels]", vec.len()),
+        }
+    }
+}
+
 
 /// Compare the result of a round trip test with the original method.
 /// Supports lossy compression methods.
@@ -1060,17 +1260,25 @@ pub mod validate_results {
         }
     }
 
-    impl<A,B,C,D> ValidateResult for (A, B, C, D) where A: Clone+ ValidateResult, B: Clone+ ValidateResult, C: Clone+ ValidateResult, D: Clone+ ValidateResult {
-        fn validate_result(&self, other: &Self, options: ValidationOptions, location: impl Fn()->String) -> ValidationResult {
-            self.clone().into_recursive().validate_result(&other.clone().into_recursive(), options, location)
-        }
+    /// Validate tuples by converting them to recursive types, one implementation for each tuple size.
+    macro_rules! validate_tuple_result {
+        ( $($name:ident),* ) => {
+            impl<$($name),*> ValidateResult for ($($name,)*) where $($name: Clone + ValidateResult),* {
+                fn validate_result(&self, other: &Self, options: ValidationOptions, location: impl Fn()->String) -> ValidationResult {
+                    self.clone().into_recursive().validate_result(&other.clone().into_recursive(), options, location)
+                }
+            }
+        };
     }
 
-    impl<A,B,C> ValidateResult for (A, B, C) where A: Clone+ ValidateResult, B: Clone+ ValidateResult, C: Clone+ ValidateResult {
-        fn validate_result(&self, other: &Self, options: ValidationOptions, location: impl Fn()->String) -> ValidationResult {
-            self.clone().into_recursive().validate_result(&other.clone().into_recursive(), options, location)
-        }
-    }
+    validate_tuple_result!(A);
+    validate_tuple_result!(A,B);
+    validate_tuple_result!(A,B,C);
+    validate_tuple_result!(A,B,C,D);
+    validate_tuple_result!(A,B,C,D,E);
+    validate_tuple_result!(A,B,C,D,E,F);
+    validate_tuple_result!(A,B,C,D,E,F,G);
+    validate_tuple_result!(A,B,C,D,E,F,G,H);
 
     // // (low priority because it is only used in the tests)
     /*TODO
@@ -2,17 +2,19 @@
 
 use crate::image::*;
 use crate::meta::header::{Header};
-use crate::error::{Result, UnitResult};
-use crate::block::UncompressedBlock;
+use crate::error::{Result, UnitResult, Error};
+use crate::block::{UncompressedBlock, BlockIndex};
+use crate::block::deep::{DeepUncompressedBlock, DeepChannelSamples};
 use crate::block::lines::{LineRef};
 use crate::math::Vec2;
 use crate::meta::attribute::{Text, ChannelDescription};
 use crate::image::read::layers::{ReadChannels, ChannelsReader};
 use crate::block::chunk::TileCoordinates;
+use std::ops::RangeInclusive;
 
 /// A template that creates an [AnyChannelsReader] for each layer in the image.
 /// This loads all channels for each layer.
-/// The `ReadSamples` can, for example, be [ReadFlatSamples] or [ReadAllLevels<ReadFlatSamples>].
+/// The `ReadSamples` can, for example, be [ReadFlatSamples], [ReadAllLevels<ReadFlatSamples>], or [ReadDeepSamples].
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ReadAnyChannels<ReadSamples> {
 
@@ -69,6 +71,21 @@ pub trait SamplesReader {
     /// Load a single pixel line, which has not been filtered, into the reader, accumulating the sample data
     fn read_line(&mut self, line: LineRef<'_>) -> UnitResult;
 
+    /// Load the samples of a single channel of a deep block, which has not been filtered, into the reader.
+    /// The samples of each pixel follow the samples of the previous pixel, as specified by the sample counts.
+    /// Returns an error, unless the sample storage supports deep data.
+    fn read_deep_samples(&mut self, _block: BlockIndex, _sample_counts: &[u32], _samples: DeepChannelSamples) -> UnitResult {
+        Err(Error::unsupported("deep data in a flat sample storage"))
+    }
+
+    /// The largest number of samples that a deep pixel may contain.
+    /// Deep blocks with more samples per pixel are rejected before their samples are decompressed.
+    fn max_samples_per_pixel(&self) -> Option<usize> { None }
+
+    /// The range of depths that deep samples must be inside of, as specified by `Z` and `ZBack`.
+    /// Deep samples outside of the range are removed before they are passed to the reader.
+    fn deep_depth_range(&self) -> Option<RangeInclusive<f32>> { None }
+
     /// Deliver the final accumulated sample storage for the image
     fn into_samples(self) -> Self::Samples;
 }
@@ -78,6 +95,10 @@ impl<'s, S: 's + ReadSamples> ReadChannels<'s> for ReadAnyChannels<S> {
     type Reader = AnyChannelsReader<S::Reader>;
 
     fn create_channels_reader(&self, header: &Header) -> Result<Self::Reader> {
+        if header.channels.is_subsampled() {
+            return Err(Error::unsupported("reading any channels does not support subsampled channels yet"));
+        }
+
         let samples: Result<_> = header.channels.list.iter()
             .map(|channel: &ChannelDescription| Ok(AnyChannelReader {
                 samples: self.read_samples.create_sample_reader(header, channel)?,
@@ -112,6 +133,30 @@ impl<S: SamplesReader> ChannelsReader for AnyChannelsReader<S> {
         Ok(())
     }
 
+    fn read_deep_block(&mut self, header: &Header, mut block: DeepUncompressedBlock) -> UnitResult {
+        if let Some(depth_range) = self.sample_channels_reader.first().and_then(|channel| channel.samples.deep_depth_range()) {
+            block.retain_samples_in_depth_range(&header.channels, depth_range)?;
+        }
+
+        let DeepUncompressedBlock { index, sample_counts, channels } = block;
+
+        if channels.len() != self.sample_channels_reader.len() {
+            return Err(Error::invalid("deep block channel count"));
+        }
+
+        for (channel, samples) in self.sample_channels_reader.iter_mut().zip(channels) {
+            channel.samples.read_deep_samples(index, &sample_counts, samples)?;
+        }
+
+        Ok(())
+    }
+
+    fn max_samples_per_pixel(&self) -> Option<usize> {
+        self.sample_channels_reader.iter()
+            .filter_map(|channel| channel.samples.max_samples_per_pixel())
+            .min()
+    }
+
     fn into_channels(self) -> Self::Channels {
         AnyChannels { // not using `new()` as the channels are already sorted
             list: self.sample_channels_reader.into_iter()
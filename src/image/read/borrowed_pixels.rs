@@ -0,0 +1,112 @@
+//! How to read a selection of channels into a sample buffer that is owned by the caller.
+
+use crate::image::*;
+use crate::meta::header::*;
+use crate::error::*;
+use crate::block::UncompressedBlock;
+use crate::image::read::layers::{ChannelsReader, ReadChannels};
+use crate::image::read::specific_channels::{DeclaredChannels, OptionalSampleReader};
+use crate::image::read::named_channels::create_sample_readers;
+use crate::block::chunk::TileCoordinates;
+
+use std::cell::RefCell;
+
+
+/// Specifies to read the declared channels into an existing buffer of `f32` samples,
+/// instead of allocating a new pixel storage.
+/// The samples of each pixel are interleaved, in the order in which the channels were declared.
+/// Create this by calling `collect_pixels_into(buffer)` after declaring the channels.
+///
+/// The buffer can only be used for a single layer,
+/// and must contain exactly one sample for each channel of each pixel.
+#[derive(Debug)]
+pub struct ReadIntoBuffer<'buffer, ReadChannels> {
+    read_channels: ReadChannels,
+    buffer: RefCell<Option<&'buffer mut [f32]>>,
+}
+
+impl<'buffer, ReadChannels> ReadIntoBuffer<'buffer, ReadChannels> {
+    pub(crate) fn new(read_channels: ReadChannels, buffer: &'buffer mut [f32]) -> Self {
+        ReadIntoBuffer { read_channels, buffer: RefCell::new(Some(buffer)) }
+    }
+}
+
+// the reader does not borrow from `self`, but only owns the buffer,
+// such that the buffer does not need to have a `'static` lifetime
+impl<'s, 'buffer, InnerChannels> ReadChannels<'s> for ReadIntoBuffer<'buffer, InnerChannels> where InnerChannels: DeclaredChannels {
+    type Reader = BufferReader<'buffer>;
+
+    fn create_channels_reader(&'s self, header: &Header) -> Result<Self::Reader> {
+        if header.deep { return Err(Error::unsupported("reading into a buffer does not support deep data yet")) }
+
+        let declared_channels = self.read_channels.declared_channels();
+        let channel_readers = create_sample_readers(header, &declared_channels)?;
+
+        let buffer = self.buffer.borrow_mut().take()
+            .ok_or_else(|| Error::invalid("the pixel buffer can only be used for a single layer"))?;
+
+        let expected_len = header.layer_size.area() * channel_readers.len();
+        if buffer.len() != expected_len {
+            let message = format!(
+                "pixel buffer contains {} samples, but the layer requires {} samples", buffer.len(), expected_len
+            );
+
+            *self.buffer.borrow_mut() = Some(buffer); // allow the buffer to be used for another layer
+            return Err(Error::invalid(message));
+        }
+
+        Ok(BufferReader {
+            channel_descriptions: channel_readers.iter().map(|reader| reader.channel().cloned()).collect(),
+            resolution: header.layer_size,
+            channel_line: Vec::new(),
+            channel_readers,
+            buffer,
+        })
+    }
+}
+
+/// The reader that writes the samples of some channels into a borrowed buffer.
+#[derive(Debug)]
+pub struct BufferReader<'buffer> {
+    buffer: &'buffer mut [f32],
+    resolution: Vec2<usize>,
+    channel_readers: Vec<OptionalSampleReader<f32>>,
+    channel_descriptions: Vec<Option<ChannelDescription>>,
+    channel_line: Vec<f32>,
+}
+
+impl<'buffer> ChannelsReader for BufferReader<'buffer> {
+    type Channels = SpecificChannels<&'buffer mut [f32], Vec<Option<ChannelDescription>>>;
+
+    fn filter_block(&self, tile: TileCoordinates) -> bool { tile.is_largest_resolution_level() }
+
+    fn read_block(&mut self, header: &Header, block: UncompressedBlock) -> UnitResult {
+        let width = block.index.pixel_size.width();
+        let channel_count = self.channel_readers.len();
+        self.channel_line.resize(width, 0.0);
+
+        let byte_lines = block.data.chunks_exact(header.channels.bytes_per_pixel * width);
+        debug_assert_eq!(byte_lines.len(), block.index.pixel_size.height(), "invalid block lines split");
+
+        for (y_offset, line_bytes) in byte_lines.enumerate() {
+            let position = block.index.pixel_position + Vec2(0, y_offset);
+            let start_index = (position.y() * self.resolution.width() + position.x()) * channel_count;
+            let pixels = &mut self.buffer[start_index .. start_index + width * channel_count];
+
+            // each channel is read into a separate line first, and then interleaved into the buffer
+            for (channel_index, channel_reader) in self.channel_readers.iter().enumerate() {
+                channel_reader.read_samples(line_bytes, &mut self.channel_line, |sample| sample);
+
+                for (pixel, &sample) in pixels.chunks_exact_mut(channel_count).zip(&self.channel_line) {
+                    pixel[channel_index] = sample;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn into_channels(self) -> Self::Channels {
+        SpecificChannels { channels: self.channel_descriptions, pixels: self.buffer }
+    }
+}
@@ -0,0 +1,574 @@
+//! How to read deep samples, where each pixel contains any number of samples.
+//! Contains the [`from_file(path)`] method that reads a complete deep image,
+//! or a flat image that contains the composited deep samples.
+
+use crate::image::*;
+use crate::meta::header::Header;
+use crate::meta::BlockDescription;
+use crate::meta::attribute::{SampleType, ChannelDescription};
+use crate::error::{Error, Result, UnitResult};
+use crate::block::{BlockIndex, UncompressedBlock};
+use crate::block::deep::{DeepUncompressedBlock, DeepChannelSamples};
+use crate::block::reader::ChunksReader;
+use crate::block::chunk::TileCoordinates;
+use crate::block::lines::LineRef;
+use crate::image::read::levels::ReadLargestLevel;
+use crate::image::read::any_channels::{ReadAnyChannels, ReadSamples, SamplesReader};
+use crate::image::read::layers::{ReadChannels, ChannelsReader};
+use crate::image::flatten::{Compositor, flatten_block_into};
+use std::path::Path;
+use std::ops::RangeInclusive;
+use std::io::{Read, Seek, BufReader};
+
+
+/// Specify to read deep samples, storing a small vector of samples for each pixel in each channel.
+/// Call `from_file` directly to read all layers that contain deep data, skipping all other layers.
+/// This reads only the largest resolution level of each layer, and decompresses all blocks sequentially.
+/// Alternatively, call `largest_resolution_level()` to continue configuring the reader like a flat reader.
+/// Use `contiguous_samples()` or `interleaved_samples()` to choose a different storage.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReadDeepSamples {
+    pedantic: bool,
+    max_samples_per_pixel: Option<usize>,
+    depth_range: Option<(f32, f32)>,
+}
+
+/// Specify to read deep samples, storing all samples of a channel in a single contiguous vector,
+/// along with the position of the samples of each pixel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReadContiguousDeepSamples {
+    max_samples_per_pixel: Option<usize>,
+    depth_range: Option<(f32, f32)>,
+}
+
+/// Specify to read deep samples, storing all samples of a pixel together,
+/// where each sample contains the values of all channels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReadInterleavedDeepSamples {
+    max_samples_per_pixel: Option<usize>,
+    depth_range: Option<(f32, f32)>,
+}
+
+impl ReadDeepSamples {
+
+    /// Create a reader that uses relaxed error handling.
+    pub fn new() -> Self { ReadDeepSamples { pedantic: false, max_samples_per_pixel: None, depth_range: None } }
+
+    /// Specify that any missing or unusual information should result in an error.
+    /// Otherwise, `exrs` will try to compute or ignore missing information.
+    pub fn pedantic(self) -> Self { ReadDeepSamples { pedantic: true, ..self } }
+
+    /// Specify the largest number of samples that a single pixel may contain, to guard against hostile files.
+    /// Reading fails before decompressing the samples of a block that contains a pixel with more samples.
+    pub fn max_samples_per_pixel(self, max: usize) -> Self { ReadDeepSamples { max_samples_per_pixel: Some(max), ..self } }
+
+    /// Specify to keep only the deep samples whose depth is inside the range, including its bounds.
+    /// A sample is removed while reading if its `Z` value, or its `ZBack` value if the layer has that channel,
+    /// is outside the range, and pixels without any remaining samples are empty.
+    /// Reading fails for layers without a `Z` channel.
+    pub fn deep_samples_in_range(self, near: f32, far: f32) -> Self { ReadDeepSamples { depth_range: Some((near, far)), ..self } }
+
+    /// Specify to store all samples of a channel in a single contiguous vector,
+    /// along with the position of the samples of each pixel. See `ContiguousDeepSamples`.
+    pub fn contiguous_samples(self) -> ReadContiguousDeepSamples {
+        ReadContiguousDeepSamples { max_samples_per_pixel: self.max_samples_per_pixel, depth_range: self.depth_range }
+    }
+
+    /// Specify to store all samples of a pixel together, where each sample contains the values of all channels.
+    /// Call `all_channels()` afterwards to obtain a layer of `DeepPixels`.
+    pub fn interleaved_samples(self) -> ReadInterleavedDeepSamples {
+        ReadInterleavedDeepSamples { max_samples_per_pixel: self.max_samples_per_pixel, depth_range: self.depth_range }
+    }
+
+    /// Specify to read only the highest resolution level, skipping all smaller variations.
+    /// Only layers that contain deep data can be read,
+    /// so use `first_valid_layer()` or `layers_matching(..)` for files that also contain flat layers.
+    pub fn largest_resolution_level(self) -> ReadLargestLevel<Self> { ReadLargestLevel { read_samples: self } }
+
+    /// Read the deep layers of the exr image from a file.
+    /// Use [`ReadDeepSamples::from_unbuffered`] instead, if you do not have a file.
+    #[inline]
+    #[must_use]
+    pub fn from_file(self, path: impl AsRef<Path>) -> Result<DeepImage> {
+        self.from_unbuffered(std::fs::File::open(path)?)
+    }
+
+    /// Buffer the reader and then read the deep layers of the exr image from it.
+    /// Use [`ReadDeepSamples::from_buffered`] instead, if your reader is an in-memory reader.
+    #[inline]
+    #[must_use]
+    pub fn from_unbuffered(self, unbuffered: impl Read + Seek) -> Result<DeepImage> {
+        self.from_buffered(BufReader::new(unbuffered))
+    }
+
+    /// Read the deep layers of the exr image from a buffered reader.
+    /// Use [`ReadDeepSamples::from_file`] instead, if you have a file path.
+    #[must_use]
+    pub fn from_buffered(self, buffered: impl Read + Seek) -> Result<DeepImage> {
+        read_deep_layers(
+            buffered, self.pedantic, self.max_samples_per_pixel, self.depth_range,
+            |header| create_layer(header, |sample_type, pixel_count| match sample_type {
+                SampleType::F16 => DeepSamples::F16(vec![SmallVec::new(); pixel_count]),
+                SampleType::F32 => DeepSamples::F32(vec![SmallVec::new(); pixel_count]),
+                SampleType::U32 => DeepSamples::U32(vec![SmallVec::new(); pixel_count]),
+            }),
+            insert_block
+        )
+    }
+}
+
+impl Default for ReadDeepSamples {
+    fn default() -> Self { Self::new() }
+}
+
+impl ReadContiguousDeepSamples {
+
+    /// Specify the largest number of samples that a single pixel may contain, to guard against hostile files.
+    /// Reading fails before decompressing the samples of a block that contains a pixel with more samples.
+    pub fn max_samples_per_pixel(self, max: usize) -> Self { ReadContiguousDeepSamples { max_samples_per_pixel: Some(max), ..self } }
+
+    /// Specify to keep only the deep samples whose depth is inside the range, including its bounds.
+    /// A sample is removed while reading if its `Z` value, or its `ZBack` value if the layer has that channel,
+    /// is outside the range, and pixels without any remaining samples are empty.
+    /// Reading fails for layers without a `Z` channel.
+    pub fn deep_samples_in_range(self, near: f32, far: f32) -> Self { ReadContiguousDeepSamples { depth_range: Some((near, far)), ..self } }
+
+    /// Specify to read only the highest resolution level, skipping all smaller variations.
+    /// Only layers that contain deep data can be read,
+    /// so use `first_valid_layer()` or `layers_matching(..)` for files that also contain flat layers.
+    pub fn largest_resolution_level(self) -> ReadLargestLevel<Self> { ReadLargestLevel { read_samples: self } }
+}
+
+impl ReadInterleavedDeepSamples {
+
+    /// Specify the largest number of samples that a single pixel may contain, to guard against hostile files.
+    /// Reading fails before decompressing the samples of a block that contains a pixel with more samples.
+    pub fn max_samples_per_pixel(self, max: usize) -> Self { ReadInterleavedDeepSamples { max_samples_per_pixel: Some(max), ..self } }
+
+    /// Specify to keep only the deep samples whose depth is inside the range, including its bounds.
+    /// A sample is removed while reading if its `Z` value, or its `ZBack` value if the layer has that channel,
+    /// is outside the range, and pixels without any remaining samples are empty.
+    /// Reading fails for layers without a `Z` channel.
+    pub fn deep_samples_in_range(self, near: f32, far: f32) -> Self { ReadInterleavedDeepSamples { depth_range: Some((near, far)), ..self } }
+
+    /// Specify to read only the highest resolution level, skipping all smaller variations.
+    /// Only layers that contain deep data can be read,
+    /// so use `first_valid_layer()` or `layers_matching(..)` for files that also contain flat layers.
+    pub fn largest_resolution_level(self) -> ReadLargestLevel<Self> { ReadLargestLevel { read_samples: self } }
+}
+
+
+/// Processes deep blocks from a file and accumulates them into a small vector of samples for each pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeepSamplesReader {
+    layer_width: usize,
+    max_samples_per_pixel: Option<usize>,
+    depth_range: Option<(f32, f32)>,
+    samples: DeepSamples,
+}
+
+/// Processes deep blocks from a file and accumulates them into a contiguous vector of samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContiguousDeepSamplesReader {
+    layer_size: Vec2<usize>,
+    max_samples_per_pixel: Option<usize>,
+    depth_range: Option<(f32, f32)>,
+    sample_type: SampleType,
+
+    /// The number of samples of each pixel, row after row.
+    sample_counts: Vec<u32>,
+
+    /// The samples of each block, as the position of the samples depends on all previous pixels.
+    blocks: Vec<(BlockIndex, DeepChannelSamples)>,
+}
+
+/// Processes deep blocks from a file and accumulates them into interleaved samples for each pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterleavedDeepSamplesReader {
+    layer_width: usize,
+    max_samples_per_pixel: Option<usize>,
+    depth_range: Option<(f32, f32)>,
+    pixels: DeepPixels,
+}
+
+impl ReadSamples for ReadDeepSamples {
+    type Reader = DeepSamplesReader;
+
+    fn create_sample_reader(&self, header: &Header, channel: &ChannelDescription) -> Result<Self::Reader> {
+        validate_deep_header(header, self.depth_range)?;
+        let pixel_count = header.layer_size.area();
+
+        Ok(DeepSamplesReader {
+            layer_width: header.layer_size.width(),
+            max_samples_per_pixel: self.max_samples_per_pixel,
+            depth_range: self.depth_range,
+            samples: match channel.sample_type {
+                SampleType::F16 => DeepSamples::F16(vec![SmallVec::new(); pixel_count]),
+                SampleType::F32 => DeepSamples::F32(vec![SmallVec::new(); pixel_count]),
+                SampleType::U32 => DeepSamples::U32(vec![SmallVec::new(); pixel_count]),
+            },
+        })
+    }
+}
+
+impl ReadSamples for ReadContiguousDeepSamples {
+    type Reader = ContiguousDeepSamplesReader;
+
+    fn create_sample_reader(&self, header: &Header, channel: &ChannelDescription) -> Result<Self::Reader> {
+        validate_deep_header(header, self.depth_range)?;
+
+        Ok(ContiguousDeepSamplesReader {
+            layer_size: header.layer_size,
+            max_samples_per_pixel: self.max_samples_per_pixel,
+            depth_range: self.depth_range,
+            sample_type: channel.sample_type,
+            sample_counts: vec![0; header.layer_size.area()],
+            blocks: Vec::new(),
+        })
+    }
+}
+
+impl<'s> ReadChannels<'s> for ReadAnyChannels<ReadInterleavedDeepSamples> {
+    type Reader = InterleavedDeepSamplesReader;
+
+    fn create_channels_reader(&self, header: &Header) -> Result<Self::Reader> {
+        validate_deep_header(header, self.read_samples.depth_range)?;
+
+        Ok(InterleavedDeepSamplesReader {
+            layer_width: header.layer_size.width(),
+            max_samples_per_pixel: self.read_samples.max_samples_per_pixel,
+            depth_range: self.read_samples.depth_range,
+            pixels: DeepPixels {
+                channels: header.channels.clone(),
+                pixels: vec![SmallVec::new(); header.layer_size.area()],
+            },
+        })
+    }
+}
+
+impl SamplesReader for DeepSamplesReader {
+    type Samples = DeepSamples;
+
+    fn filter_block(&self, tile: TileCoordinates) -> bool {
+        tile.level_index == Vec2(0, 0)
+    }
+
+    fn read_line(&mut self, _: LineRef<'_>) -> UnitResult {
+        Err(Error::invalid("flat data in a deep layer"))
+    }
+
+    fn read_deep_samples(&mut self, block: BlockIndex, sample_counts: &[u32], samples: DeepChannelSamples) -> UnitResult {
+        match (&mut self.samples, samples) {
+            (DeepSamples::F16(pixels), DeepChannelSamples::F16(samples)) => insert_samples(pixels, &samples, sample_counts, block, self.layer_width),
+            (DeepSamples::F32(pixels), DeepChannelSamples::F32(samples)) => insert_samples(pixels, &samples, sample_counts, block, self.layer_width),
+            (DeepSamples::U32(pixels), DeepChannelSamples::U32(samples)) => insert_samples(pixels, &samples, sample_counts, block, self.layer_width),
+            _ => Err(Error::invalid("deep block sample type")),
+        }
+    }
+
+    fn max_samples_per_pixel(&self) -> Option<usize> { self.max_samples_per_pixel }
+
+    fn deep_depth_range(&self) -> Option<RangeInclusive<f32>> { self.depth_range.map(|(near, far)| near ..= far) }
+
+    fn into_samples(self) -> DeepSamples { self.samples }
+}
+
+impl SamplesReader for ContiguousDeepSamplesReader {
+    type Samples = ContiguousDeepSamples;
+
+    fn filter_block(&self, tile: TileCoordinates) -> bool {
+        tile.level_index == Vec2(0, 0)
+    }
+
+    fn read_line(&mut self, _: LineRef<'_>) -> UnitResult {
+        Err(Error::invalid("flat data in a deep layer"))
+    }
+
+    fn read_deep_samples(&mut self, block: BlockIndex, sample_counts: &[u32], samples: DeepChannelSamples) -> UnitResult {
+        if samples.sample_type() != self.sample_type {
+            return Err(Error::invalid("deep block sample type"));
+        }
+
+        for (pixel_index, &count) in block_pixel_indices(block, self.layer_size.width()).zip(sample_counts) {
+            *self.sample_counts.get_mut(pixel_index).ok_or(Error::invalid("deep block position"))? = count;
+        }
+
+        self.blocks.push((block, samples));
+        Ok(())
+    }
+
+    fn max_samples_per_pixel(&self) -> Option<usize> { self.max_samples_per_pixel }
+
+    fn deep_depth_range(&self) -> Option<RangeInclusive<f32>> { self.depth_range.map(|(near, far)| near ..= far) }
+
+    fn into_samples(self) -> ContiguousDeepSamples {
+        let mut sample_offsets = Vec::with_capacity(self.sample_counts.len() + 1);
+        let mut total_sample_count = 0;
+
+        for &count in &self.sample_counts {
+            sample_offsets.push(total_sample_count);
+            total_sample_count += count as usize;
+        }
+
+        sample_offsets.push(total_sample_count);
+
+        let mut samples = match self.sample_type {
+            SampleType::F16 => DeepChannelSamples::F16(vec![f16::ZERO; total_sample_count]),
+            SampleType::F32 => DeepChannelSamples::F32(vec![0.0; total_sample_count]),
+            SampleType::U32 => DeepChannelSamples::U32(vec![0; total_sample_count]),
+        };
+
+        // the samples of each pixel in a block directly follow the samples of the previous pixel in that block
+        for (block, block_samples) in &self.blocks {
+            let mut block_sample_start = 0;
+
+            for pixel_index in block_pixel_indices(*block, self.layer_size.width()) {
+                let range = sample_offsets[pixel_index] .. sample_offsets[pixel_index + 1];
+                let block_range = block_sample_start .. block_sample_start + range.len();
+
+                match (&mut samples, block_samples) {
+                    (DeepChannelSamples::F16(samples), DeepChannelSamples::F16(block_samples)) => samples[range].copy_from_slice(&block_samples[block_range.clone()]),
+                    (DeepChannelSamples::F32(samples), DeepChannelSamples::F32(block_samples)) => samples[range].copy_from_slice(&block_samples[block_range.clone()]),
+                    (DeepChannelSamples::U32(samples), DeepChannelSamples::U32(block_samples)) => samples[range].copy_from_slice(&block_samples[block_range.clone()]),
+                    _ => unreachable!("deep block sample type was checked"),
+                }
+
+                block_sample_start = block_range.end;
+            }
+        }
+
+        ContiguousDeepSamples { sample_offsets, samples }
+    }
+}
+
+impl ChannelsReader for InterleavedDeepSamplesReader {
+    type Channels = DeepPixels;
+
+    fn filter_block(&self, tile: TileCoordinates) -> bool {
+        tile.level_index == Vec2(0, 0)
+    }
+
+    fn read_block(&mut self, _: &Header, _: UncompressedBlock) -> UnitResult {
+        Err(Error::invalid("flat data in a deep layer"))
+    }
+
+    fn read_deep_block(&mut self, header: &Header, mut block: DeepUncompressedBlock) -> UnitResult {
+        if let Some((near, far)) = self.depth_range {
+            block.retain_samples_in_depth_range(&header.channels, near ..= far)?;
+        }
+
+        let DeepUncompressedBlock { index, sample_counts, channels } = block;
+
+        if channels.len() != self.pixels.channels.list.len() {
+            return Err(Error::invalid("deep block channel count"));
+        }
+
+        let mut sample_index = 0;
+
+        for (pixel_index, &count) in block_pixel_indices(index, self.layer_width).zip(&sample_counts) {
+            let pixel = self.pixels.pixels.get_mut(pixel_index).ok_or(Error::invalid("deep block position"))?;
+            pixel.clear();
+
+            for _ in 0 .. count {
+                pixel.push(channels.iter().map(|samples| samples.value_by_index(sample_index)).collect());
+                sample_index += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn max_samples_per_pixel(&self) -> Option<usize> { self.max_samples_per_pixel }
+
+    fn into_channels(self) -> DeepPixels { self.pixels }
+}
+
+/// Check that the layer contains deep data, and can be filtered by depth if required.
+fn validate_deep_header(header: &Header, depth_range: Option<(f32, f32)>) -> UnitResult {
+    if !header.deep {
+        return Err(Error::invalid("layer does not contain deep data"));
+    }
+
+    if depth_range.is_some() && !header.channels.list.iter().any(|channel| channel.name.eq("Z")) {
+        return Err(Error::invalid("deep samples can only be filtered by depth if the layer contains a `Z` channel"));
+    }
+
+    Ok(())
+}
+
+/// The flat index of each pixel in the block, row after row.
+fn block_pixel_indices(block: BlockIndex, layer_width: usize) -> impl Iterator<Item = usize> {
+    (0 .. block.pixel_size.height()).flat_map(move |y| {
+        let line_start = (block.pixel_position.y() + y) * layer_width + block.pixel_position.x();
+        line_start .. line_start + block.pixel_size.width()
+    })
+}
+
+/// Specify to read deep samples and composite each pixel into a single flat sample per channel,
+/// skipping all layers that do not contain deep data.
+/// See [`Layer::flatten`] for how the samples are composited.
+/// Reads only the largest resolution level of each layer, and decompresses all blocks sequentially.
+/// Only a single block of deep samples is held in memory at once.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ReadFlattenedDeepSamples {
+    pedantic: bool,
+}
+
+impl ReadFlattenedDeepSamples {
+
+    /// Create a reader that uses relaxed error handling.
+    pub fn new() -> Self { ReadFlattenedDeepSamples { pedantic: false } }
+
+    /// Specify that any missing or unusual information should result in an error.
+    /// Otherwise, `exrs` will try to compute or ignore missing information.
+    pub fn pedantic(self) -> Self { ReadFlattenedDeepSamples { pedantic: true, ..self } }
+
+    /// Read and flatten the deep layers of the exr image from a file.
+    /// Use [`ReadFlattenedDeepSamples::from_unbuffered`] instead, if you do not have a file.
+    #[inline]
+    #[must_use]
+    pub fn from_file(self, path: impl AsRef<Path>) -> Result<FlatImage> {
+        self.from_unbuffered(std::fs::File::open(path)?)
+    }
+
+    /// Buffer the reader and then read and flatten the deep layers of the exr image from it.
+    /// Use [`ReadFlattenedDeepSamples::from_buffered`] instead, if your reader is an in-memory reader.
+    #[inline]
+    #[must_use]
+    pub fn from_unbuffered(self, unbuffered: impl Read + Seek) -> Result<FlatImage> {
+        self.from_buffered(BufReader::new(unbuffered))
+    }
+
+    /// Read and flatten the deep layers of the exr image from a buffered reader.
+    /// Use [`ReadFlattenedDeepSamples::from_file`] instead, if you have a file path.
+    #[must_use]
+    pub fn from_buffered(self, buffered: impl Read + Seek) -> Result<FlatImage> {
+        read_deep_layers(
+            buffered, self.pedantic, None, None,
+            |header| create_layer(header, |sample_type, pixel_count| match sample_type {
+                SampleType::F16 => FlatSamples::F16(vec![f16::ZERO; pixel_count]),
+                SampleType::F32 => FlatSamples::F32(vec![0.0; pixel_count]),
+                SampleType::U32 => FlatSamples::U32(vec![0; pixel_count]),
+            }),
+            |layer, block| {
+                let channels = layer.channel_data.list.iter()
+                    .map(|channel| (&channel.name, channel.sample_data.sample_type()));
+
+                let mut compositor = Compositor::new(channels);
+                flatten_block_into(layer, &mut compositor, &block)
+            }
+        )
+    }
+}
+
+impl Default for ReadFlattenedDeepSamples {
+    fn default() -> Self { Self::new() }
+}
+
+/// Read the largest level of all deep layers, inserting each decompressed block into its layer.
+fn read_deep_layers<Samples>(
+    buffered: impl Read + Seek, pedantic: bool, max_samples_per_pixel: Option<usize>, depth_range: Option<(f32, f32)>,
+    create_layer: impl Fn(&Header) -> Layer<AnyChannels<Samples>>,
+    mut insert_block: impl FnMut(&mut Layer<AnyChannels<Samples>>, DeepUncompressedBlock) -> UnitResult,
+) -> Result<Image<Layers<AnyChannels<Samples>>>> {
+    let chunks = crate::block::read(buffered, pedantic)?;
+
+    let mut layers: Layers<AnyChannels<Samples>> = chunks.headers().iter()
+        .filter(|header| header.deep)
+        .map(create_layer)
+        .collect();
+
+    let attributes = ImageAttributes::from_headers(chunks.headers())
+        .ok_or(Error::invalid("at least one layer is required"))?;
+
+    // the position of a header in this list is the index of its layer in the deep image
+    let deep_header_indices: Vec<usize> = chunks.headers().iter().enumerate()
+        .filter(|(_, header)| header.deep)
+        .map(|(header_index, _)| header_index)
+        .collect();
+
+    let mut chunks = chunks.filter_chunks(pedantic, |meta, _tile, block| {
+        meta.headers[block.layer].deep && block.level == Vec2(0, 0)
+    })?;
+
+    while let Some(chunk) = chunks.read_next_chunk() {
+        let mut block = DeepUncompressedBlock::decompress_chunk_with_sample_limit(chunk?, chunks.meta_data(), pedantic, max_samples_per_pixel)?;
+
+        if let Some((near, far)) = depth_range {
+            block.retain_samples_in_depth_range(&chunks.meta_data().headers[block.index.layer].channels, near ..= far)?;
+        }
+
+        let layer_index = deep_header_indices.iter().position(|&index| index == block.index.layer)
+            .expect("deep chunk filter bug");
+
+        insert_block(&mut layers[layer_index], block)?;
+    }
+
+    Ok(Image { attributes, layer_data: layers })
+}
+
+/// Create a layer for the header, with the samples of each channel created by the closure.
+fn create_layer<Samples>(header: &Header, create_samples: impl Fn(SampleType, usize) -> Samples) -> Layer<AnyChannels<Samples>> {
+    let pixel_count = header.layer_size.area();
+
+    Layer {
+        size: header.layer_size,
+        attributes: header.own_attributes.clone(),
+        encoding: Encoding {
+            compression: header.compression,
+            line_order: header.line_order,
+            blocks: match header.blocks {
+                BlockDescription::ScanLines => Blocks::ScanLines,
+                BlockDescription::Tiles(tiles) => Blocks::Tiles(tiles.tile_size),
+            },
+        },
+
+        channel_data: AnyChannels { // not using `new()` as the channels are already sorted
+            list: header.channels.list.iter()
+                .map(|channel| AnyChannel {
+                    name: channel.name.clone(),
+                    quantize_linearly: channel.quantize_linearly,
+                    sampling: channel.sampling,
+                    sample_data: create_samples(channel.sample_type, pixel_count),
+                })
+                .collect()
+        },
+    }
+}
+
+/// Distribute the samples of the block to the pixels of the layer.
+fn insert_block(layer: &mut Layer<AnyChannels<DeepSamples>>, block: DeepUncompressedBlock) -> UnitResult {
+    let layer_width = layer.size.width();
+    let DeepUncompressedBlock { index, sample_counts, channels } = block;
+
+    for (channel, samples) in layer.channel_data.list.iter_mut().zip(channels) {
+        match (&mut channel.sample_data, samples) {
+            (DeepSamples::F16(pixels), DeepChannelSamples::F16(samples)) => insert_samples(pixels, &samples, &sample_counts, index, layer_width)?,
+            (DeepSamples::F32(pixels), DeepChannelSamples::F32(samples)) => insert_samples(pixels, &samples, &sample_counts, index, layer_width)?,
+            (DeepSamples::U32(pixels), DeepChannelSamples::U32(samples)) => insert_samples(pixels, &samples, &sample_counts, index, layer_width)?,
+            _ => return Err(Error::invalid("deep block sample type")),
+        }
+    }
+
+    Ok(())
+}
+
+fn insert_samples<T: Copy>(pixels: &mut [SmallVec<[T; 4]>], samples: &[T], sample_counts: &[u32], index: BlockIndex, layer_width: usize) -> UnitResult {
+    let mut remaining_samples = samples;
+    let block_width = index.pixel_size.width().max(1);
+
+    for (y, line_sample_counts) in sample_counts.chunks(block_width).enumerate() {
+        let line_start = (index.pixel_position.y() + y) * layer_width + index.pixel_position.x();
+        let line_pixels = pixels.get_mut(line_start .. line_start + line_sample_counts.len())
+            .ok_or(Error::invalid("deep block position"))?;
+
+        for (pixel, &count) in line_pixels.iter_mut().zip(line_sample_counts) {
+            let (pixel_samples, rest) = remaining_samples.split_at(count as usize);
+            pixel.clear();
+            pixel.extend_from_slice(pixel_samples);
+            remaining_samples = rest;
+        }
+    }
+
+    Ok(())
+}
@@ -3,35 +3,79 @@
 
 use crate::image::*;
 use crate::meta::header::{Header, ImageAttributes};
-use crate::error::{Result, UnitResult};
+use crate::error::{Error, Result, UnitResult};
 use crate::block::{UncompressedBlock, BlockIndex};
+use crate::block::deep::DeepUncompressedBlock;
 use crate::block::chunk::TileCoordinates;
 use std::path::Path;
 use std::io::{Read, BufReader};
 use std::io::Seek;
 use crate::meta::MetaData;
+use crate::compression::CodecRegistry;
 use crate::block::reader::ChunksReader;
+use rayon_core::ThreadPool;
+use crate::meta::attribute::{IntegerBounds, LevelMode, AttributeParserRegistry};
+use crate::meta::{BlockDescription, ValidationOptions, compute_level_count, compute_level_size};
 
 /// Specify whether to read the image in parallel,
-/// whether to use pedantic error handling,
+/// which inconsistencies in the file should result in an error,
 /// and a callback for the reading progress.
+/// The lifetime refers to an optional borrowed thread pool.
 #[derive(Debug, Clone)]
-pub struct ReadImage<OnProgress, ReadLayers> {
+pub struct ReadImage<'p, OnProgress, ReadLayers> {
     on_progress: OnProgress,
     read_layers: ReadLayers,
-    pedantic: bool,
+    validation: ValidationOptions,
     parallel: bool,
+    thread_pool: Option<&'p ThreadPool>,
+    max_in_flight_blocks: Option<usize>,
+    region: Option<IntegerBounds>,
+    resolution_level: Option<Vec2<usize>>,
+    broken_block_fill: Option<f32>,
+    custom_codecs: Option<CodecRegistry>,
+    attribute_parsers: Option<AttributeParserRegistry>,
 }
 
-impl<F, L> ReadImage<F, L> where F: FnMut(f64)
+/// Specify to read an image, skipping the blocks that cannot be decompressed.
+/// Created by calling `skip_broken_blocks` on a `ReadImage`.
+#[derive(Debug, Clone)]
+pub struct ReadPartialImage<'p, OnProgress, ReadLayers> {
+    read_image: ReadImage<'p, OnProgress, ReadLayers>,
+}
+
+/// An image that was read while skipping broken blocks.
+/// The pixels of the broken blocks contain the fill value instead of the original pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialImage<Layers> {
+
+    /// The image, including the filled pixels of the broken blocks.
+    pub image: Image<Layers>,
+
+    /// The blocks which could not be decompressed.
+    /// The pixels in these regions are not trustworthy.
+    pub broken_blocks: Vec<BlockIndex>,
+}
+
+impl<F, L> ReadImage<'static, F, L> where F: FnMut(f64)
 {
     /// Uses relaxed error handling and parallel decompression.
     pub fn new(read_layers: L, on_progress: F) -> Self {
         Self {
             on_progress, read_layers,
-            pedantic: false, parallel: true,
+            validation: ValidationOptions::lenient(), parallel: true,
+            thread_pool: None,
+            max_in_flight_blocks: None,
+            region: None,
+            resolution_level: None,
+            broken_block_fill: None,
+            custom_codecs: None,
+            attribute_parsers: None,
         }
     }
+}
+
+impl<'p, F, L> ReadImage<'p, F, L> where F: FnMut(f64)
+{
 
     /// Specify that any missing or unusual information should result in an error.
     /// Otherwise, `exrs` will try to compute or ignore missing information.
@@ -45,22 +89,110 @@ impl<F, L> ReadImage<F, L> where F: FnMut(f64)
     /// an error is thrown, because this should not happen and something might be wrong with the file.
     /// Or if your application is a target of attacks, or if you want to emulate the original C++ library,
     /// you might want to switch to pedantic reading.
-    pub fn pedantic(self) -> Self { Self { pedantic: true, ..self } }
+    pub fn pedantic(self) -> Self { self.validation(ValidationOptions::strict()) }
+
+    /// Specify exactly which inconsistencies in the file should result in an error.
+    /// For example, corrupt offset tables can be rejected early, while malformed attributes are still skipped.
+    /// Calling `pedantic()` is equivalent to using `ValidationOptions::strict()`.
+    /// Replaces the options set by previous calls to `pedantic()`.
+    pub fn validation(self, options: impl Into<ValidationOptions>) -> Self { Self { validation: options.into(), ..self } }
 
     /// Specify that multiple pixel blocks should never be decompressed using multiple threads at once.
     /// This might be slower but uses less memory and less synchronization.
+    /// Files that contain deep data are always decompressed sequentially.
     pub fn non_parallel(self) -> Self { Self { parallel: false, ..self } }
 
+    /// Specify that multiple pixel blocks should be decompressed using the threads of an existing pool.
+    /// The pool is only borrowed, so a single pool can be shared by many concurrent reads,
+    /// and no additional threads will be created while reading.
+    /// Without this option, a new thread pool is created for each image.
+    pub fn parallel_with_pool<'q>(self, thread_pool: &'q ThreadPool) -> ReadImage<'q, F, L> {
+        ReadImage {
+            on_progress: self.on_progress,
+            read_layers: self.read_layers,
+            validation: self.validation,
+            parallel: true,
+            thread_pool: Some(thread_pool),
+            max_in_flight_blocks: self.max_in_flight_blocks,
+            region: self.region,
+            resolution_level: self.resolution_level,
+            broken_block_fill: self.broken_block_fill,
+            custom_codecs: self.custom_codecs,
+            attribute_parsers: self.attribute_parsers,
+        }
+    }
+
+    /// Specify how many pixel blocks may be decompressed at the same time when reading in parallel.
+    /// A larger number keeps more cores busy, while a smaller number reduces peak memory usage.
+    /// By default, this is two more than the number of threads.
+    /// The value is clamped to at least one.
+    pub fn max_in_flight_blocks(self, count: usize) -> Self {
+        Self { max_in_flight_blocks: Some(count), ..self }
+    }
+
+    /// Specify that only the pixels inside a rectangle should be loaded.
+    /// The rectangle is specified in the same coordinates as the data window of the layers.
+    /// Only the blocks that overlap the rectangle are read from the file.
+    /// The size of each loaded layer will be the part of its data window that is inside the rectangle,
+    /// and the position of the layer is adjusted accordingly.
+    /// A layer that is completely outside the rectangle will be empty.
+    /// Only the largest resolution level is loaded, as the rectangle does not apply to smaller levels.
+    /// Reading a region of a layer with deep data is not supported yet.
+    pub fn region(self, bounds: IntegerBounds) -> Self {
+        Self { region: Some(bounds), ..self }
+    }
+
+    /// Specify that a smaller resolution level should be loaded instead of the largest level,
+    /// for example `Vec2(2, 2)` for the third mip map level.
+    /// Use this together with `largest_resolution_level()`, which then loads the specified level.
+    /// The size of each loaded layer will be the size of that level,
+    /// as computed with the rounding mode of the layer.
+    /// If a region is specified, it is relative to the pixels of this level.
+    /// Returns an error when reading a layer that does not contain this level.
+    pub fn resolution_level(self, level: Vec2<usize>) -> Self {
+        Self { resolution_level: Some(level), ..self }
+    }
+
+    /// Specify that a chunk whose pixels cannot be decompressed should not abort the whole process.
+    /// Instead, the pixels of that block are set to the fill value, for example zero or NaN,
+    /// and the block is listed in the resulting `PartialImage`.
+    /// Corrupt meta data and chunks that cannot be read at all are still an error.
+    /// Broken blocks are not skipped in files that contain deep data.
+    pub fn skip_broken_blocks(self, fill_value: f32) -> ReadPartialImage<'p, F, L> {
+        ReadPartialImage { read_image: Self { broken_block_fill: Some(fill_value), ..self } }
+    }
+
+    /// Specify the codecs used for layers with `Compression::Custom`, as they are not stored in the file.
+    /// Without a codec for the compression id of a layer, its pixels cannot be decompressed.
+    /// __Custom compression methods are not part of the OpenEXR standard.__
+    pub fn custom_codecs(self, codecs: CodecRegistry) -> Self {
+        Self { custom_codecs: Some(codecs), ..self }
+    }
+
+    /// Specify the functions used to parse custom attribute types, as they are not part of the OpenEXR standard.
+    /// The attributes of these types will contain `AttributeValue::Parsed` instead of the raw bytes.
+    /// Attributes of other custom types are read as raw bytes.
+    pub fn attribute_parsers(self, parsers: AttributeParserRegistry) -> Self {
+        Self { attribute_parsers: Some(parsers), ..self }
+    }
+
     /// Specify a function to be called regularly throughout the loading process.
     /// Replaces all previously specified progress functions in this reader.
-    pub fn on_progress<OnProgress>(self, on_progress: OnProgress) -> ReadImage<OnProgress, L>
+    pub fn on_progress<OnProgress>(self, on_progress: OnProgress) -> ReadImage<'p, OnProgress, L>
         where OnProgress: FnMut(f64)
     {
         ReadImage {
             on_progress,
             read_layers: self.read_layers,
-            pedantic: self.pedantic,
-            parallel: self.parallel
+            validation: self.validation,
+            parallel: self.parallel,
+            thread_pool: self.thread_pool,
+            max_in_flight_blocks: self.max_in_flight_blocks,
+            region: self.region,
+            resolution_level: self.resolution_level,
+            broken_block_fill: self.broken_block_fill,
+            custom_codecs: self.custom_codecs,
+            attribute_parsers: self.attribute_parsers,
         }
     }
 
@@ -94,43 +226,397 @@ impl<F, L> ReadImage<F, L> where F: FnMut(f64)
     pub fn from_buffered<Layers>(self, buffered: impl Read + Seek) -> Result<Image<Layers>>
         where for<'s> L: ReadLayers<'s, Layers = Layers>
     {
-        let chunks = crate::block::read(buffered, self.pedantic)?;
+        let chunks = crate::block::read(buffered, self.validation)?;
         self.from_chunks(chunks)
     }
 
+    /// Buffer the byte stream and then read the exr image from it, without ever seeking.
+    /// This works with pipes, sockets, or the standard input.
+    /// As the stream cannot skip any bytes, all chunks are read,
+    /// but only the desired chunks are decompressed.
+    /// Use [`ReadImage::read_from_unbuffered`] instead, if your reader can seek.
+    #[must_use]
+    pub fn from_stream<Layers>(self, unbuffered_stream: impl Read) -> Result<Image<Layers>>
+        where for<'s> L: ReadLayers<'s, Layers = Layers>
+    {
+        let chunks = crate::block::reader::Reader::read_from_unseekable(BufReader::new(unbuffered_stream), self.validation)?;
+        self.from_unseekable_chunks(chunks)
+    }
+
     /// Read the exr image from an initialized chunks reader
     /// that has already extracted the meta data from the file.
     /// Use [`ReadImage::read_from_file`] instead, if you have a file path.
     /// Use [`ReadImage::read_from_buffered`] instead, if this is an in-memory reader.
     // TODO Use Parallel<> Wrapper to only require sendable byte source where parallel decompression is required
     #[must_use]
-    pub fn from_chunks<Layers>(mut self, chunks_reader: crate::block::reader::Reader<impl Read + Seek>) -> Result<Image<Layers>>
+    pub fn from_chunks<Layers>(self, chunks_reader: crate::block::reader::Reader<impl Read + Seek>) -> Result<Image<Layers>>
         where for<'s> L: ReadLayers<'s, Layers = Layers>
     {
-        let Self { pedantic, parallel, ref mut on_progress, ref mut read_layers } = self;
+        Ok(self.read_chunks(chunks_reader)?.image)
+    }
 
-        let layers_reader = read_layers.create_layers_reader(chunks_reader.headers())?;
-        let mut image_collector = ImageWithAttributesReader::new(chunks_reader.headers(), layers_reader)?;
+    /// Read the exr image from an initialized chunks reader that cannot seek,
+    /// and that has already extracted the meta data from the file.
+    /// Use [`ReadImage::from_chunks`] instead, if your reader can seek.
+    #[must_use]
+    pub fn from_unseekable_chunks<Layers>(self, chunks_reader: crate::block::reader::Reader<impl Read>) -> Result<Image<Layers>>
+        where for<'s> L: ReadLayers<'s, Layers = Layers>
+    {
+        Ok(self.read_unseekable_chunks(chunks_reader)?.image)
+    }
+
+    fn read_chunks<Layers>(mut self, chunks_reader: crate::block::reader::Reader<impl Read + Seek>) -> Result<PartialImage<Layers>>
+        where for<'s> L: ReadLayers<'s, Layers = Layers>
+    {
+        let Self { validation, parallel, thread_pool, max_in_flight_blocks, region, resolution_level, broken_block_fill, ref custom_codecs, ref attribute_parsers, ref mut on_progress, ref mut read_layers } = self;
+
+        let chunks_reader = match custom_codecs {
+            Some(codecs) => chunks_reader.with_custom_codecs(codecs),
+            None => chunks_reader,
+        };
+
+        let chunks_reader = match attribute_parsers {
+            Some(parsers) => chunks_reader.with_attribute_parsers(parsers)?,
+            None => chunks_reader,
+        };
+
+        let level = resolution_level.map(|level| ResolutionLevel::new(chunks_reader.headers(), level)).transpose()?;
+        let level_headers = level.as_ref().map_or(chunks_reader.headers(), |level| &level.headers);
+
+        let region = region.map(|region| RegionOfInterest::new(level_headers, region));
+        let headers = region.as_ref().map_or(level_headers, |region| &region.headers);
+
+        let layers_reader = read_layers.create_layers_reader(headers)?;
+        let mut image_collector = ImageWithAttributesReader::new(headers, layers_reader)?;
 
         let block_reader = chunks_reader
-            .filter_chunks(pedantic, |meta, tile, block| {
-                image_collector.filter_block(meta, tile, block)
+            .filter_chunks(validation, |meta, tile, block| {
+                let (tile, block) = match &level {
+                    Some(level) => match level.select_block(tile, block) {
+                        Some(selected) => selected,
+                        None => return false,
+                    },
+                    None => (tile, block),
+                };
+
+                region.as_ref().map_or(true, |region| region.overlaps_block(block))
+                    && image_collector.filter_block(meta, tile, block)
             })?
             .on_progress(on_progress);
 
-        // TODO propagate send requirement further upwards
-        if parallel {
-            block_reader.decompress_parallel(pedantic, |meta_data, block|{
-                image_collector.read_block(&meta_data.headers, block)
-            })?;
+        let broken_blocks = decompress_blocks_into(
+            block_reader, &mut image_collector, level.as_ref(), region.as_ref(),
+            validation, parallel, thread_pool, max_in_flight_blocks, broken_block_fill
+        )?;
+
+        Ok(PartialImage { image: image_collector.into_image(), broken_blocks })
+    }
+
+    fn read_unseekable_chunks<Layers>(mut self, chunks_reader: crate::block::reader::Reader<impl Read>) -> Result<PartialImage<Layers>>
+        where for<'s> L: ReadLayers<'s, Layers = Layers>
+    {
+        let Self { validation, parallel, thread_pool, max_in_flight_blocks, region, resolution_level, broken_block_fill, ref custom_codecs, ref attribute_parsers, ref mut on_progress, ref mut read_layers } = self;
+
+        let chunks_reader = match custom_codecs {
+            Some(codecs) => chunks_reader.with_custom_codecs(codecs),
+            None => chunks_reader,
+        };
+
+        let chunks_reader = match attribute_parsers {
+            Some(parsers) => chunks_reader.with_attribute_parsers(parsers)?,
+            None => chunks_reader,
+        };
+
+        let level = resolution_level.map(|level| ResolutionLevel::new(chunks_reader.headers(), level)).transpose()?;
+        let level_headers = level.as_ref().map_or(chunks_reader.headers(), |level| &level.headers);
+
+        let region = region.map(|region| RegionOfInterest::new(level_headers, region));
+        let headers = region.as_ref().map_or(level_headers, |region| &region.headers);
+
+        let layers_reader = read_layers.create_layers_reader(headers)?;
+        let mut image_collector = ImageWithAttributesReader::new(headers, layers_reader)?;
+
+        let block_reader = chunks_reader
+            .filter_chunks_sequentially(validation, |meta, tile, block| {
+                let (tile, block) = match &level {
+                    Some(level) => match level.select_block(tile, block) {
+                        Some(selected) => selected,
+                        None => return false,
+                    },
+                    None => (tile, block),
+                };
+
+                region.as_ref().map_or(true, |region| region.overlaps_block(block))
+                    && image_collector.filter_block(meta, tile, block)
+            })?
+            .on_progress(on_progress);
+
+        let broken_blocks = decompress_blocks_into(
+            block_reader, &mut image_collector, level.as_ref(), region.as_ref(),
+            validation, parallel, thread_pool, max_in_flight_blocks, broken_block_fill
+        )?;
+
+        Ok(PartialImage { image: image_collector.into_image(), broken_blocks })
+    }
+}
+
+impl<'p, F, L> ReadPartialImage<'p, F, L> where F: FnMut(f64)
+{
+    /// Read the exr image from a file, skipping broken blocks.
+    #[must_use]
+    pub fn from_file<Layers>(self, path: impl AsRef<Path>) -> Result<PartialImage<Layers>>
+        where for<'s> L: ReadLayers<'s, Layers = Layers>
+    {
+        self.from_unbuffered(std::fs::File::open(path)?)
+    }
+
+    /// Buffer the reader and then read the exr image from it, skipping broken blocks.
+    #[must_use]
+    pub fn from_unbuffered<Layers>(self, unbuffered: impl Read + Seek) -> Result<PartialImage<Layers>>
+        where for<'s> L: ReadLayers<'s, Layers = Layers>
+    {
+        self.from_buffered(BufReader::new(unbuffered))
+    }
+
+    /// Read the exr image from a buffered reader, skipping broken blocks.
+    #[must_use]
+    pub fn from_buffered<Layers>(self, buffered: impl Read + Seek) -> Result<PartialImage<Layers>>
+        where for<'s> L: ReadLayers<'s, Layers = Layers>
+    {
+        let chunks = crate::block::read(buffered, self.read_image.validation)?;
+        self.read_image.read_chunks(chunks)
+    }
+
+    /// Buffer the byte stream and then read the exr image from it, without ever seeking, skipping broken blocks.
+    #[must_use]
+    pub fn from_stream<Layers>(self, unbuffered_stream: impl Read) -> Result<PartialImage<Layers>>
+        where for<'s> L: ReadLayers<'s, Layers = Layers>
+    {
+        let chunks = crate::block::reader::Reader::read_from_unseekable(BufReader::new(unbuffered_stream), self.read_image.validation)?;
+        self.read_image.read_unseekable_chunks(chunks)
+    }
+}
+
+/// Decompress all chunks of the reader, and accumulate the blocks in the image collector.
+// TODO propagate send requirement further upwards
+fn decompress_blocks_into<L: LayersReader>(
+    block_reader: impl ChunksReader, image_collector: &mut ImageWithAttributesReader<L>,
+    level: Option<&ResolutionLevel>, region: Option<&RegionOfInterest>,
+    validation: ValidationOptions, parallel: bool, thread_pool: Option<&ThreadPool>, max_in_flight_blocks: Option<usize>,
+    broken_block_fill: Option<f32>,
+) -> Result<Vec<BlockIndex>>
+{
+    let insert_block = |image_collector: &mut ImageWithAttributesReader<L>, meta_data: &MetaData, mut block: UncompressedBlock| {
+        let headers: &[Header] = match level {
+            None => &meta_data.headers,
+            Some(level) => {
+                block.index.level = Vec2(0, 0);
+                &level.headers
+            },
+        };
+
+        match region {
+            None => image_collector.read_block(headers, block),
+            Some(region) => match region.crop_block(headers, block) {
+                Some(block) => image_collector.read_block(&region.headers, block),
+                None => Ok(()),
+            },
         }
-        else {
-            block_reader.decompress_sequential(pedantic, |meta_data, block|{
-                image_collector.read_block(&meta_data.headers, block)
-            })?;
+    };
+
+    // the block decompressors only handle flat blocks, so files with deep data are decompressed here, sequentially
+    if block_reader.meta_data().headers.iter().any(|header| header.deep) {
+        let mut block_reader = block_reader;
+
+        while let Some(chunk) = block_reader.read_next_chunk() {
+            let chunk = chunk?;
+            let meta_data = block_reader.meta_data();
+
+            if !meta_data.headers.get(chunk.layer_index).map_or(false, |header| header.deep) {
+                let block = UncompressedBlock::decompress_chunk(chunk, meta_data, validation)?;
+                insert_block(image_collector, meta_data, block)?;
+                continue;
+            }
+
+            if region.is_some() {
+                return Err(Error::unsupported("reading a region of deep data"));
+            }
+
+            let max_samples_per_pixel = image_collector.layers_reader.max_samples_per_pixel(chunk.layer_index);
+            let mut block = DeepUncompressedBlock::decompress_chunk_with_sample_limit(chunk, meta_data, validation, max_samples_per_pixel)?;
+
+            let headers: &[Header] = match level {
+                None => &meta_data.headers,
+                Some(level) => {
+                    block.index.level = Vec2(0, 0);
+                    &level.headers
+                },
+            };
+
+            image_collector.read_deep_block(headers, block)?;
         }
 
-        Ok(image_collector.into_image())
+        return Ok(Vec::new());
+    }
+
+    let decompressor = match (parallel, thread_pool) {
+        (true, Some(thread_pool)) => block_reader.parallel_decompressor_with_pool(validation, thread_pool),
+        (true, None) => block_reader.parallel_decompressor(validation),
+        (false, _) => Err(block_reader),
+    };
+
+    match decompressor {
+        Err(block_reader) => {
+            let mut decompressor = block_reader.sequential_decompressor(validation);
+            if let Some(fill_value) = broken_block_fill {
+                decompressor = decompressor.skip_broken_blocks(fill_value);
+            }
+
+            while let Some(block) = decompressor.next() {
+                insert_block(image_collector, decompressor.meta_data(), block?)?;
+            }
+
+            Ok(decompressor.broken_blocks().to_vec())
+        },
+
+        Ok(mut decompressor) => {
+            if let Some(count) = max_in_flight_blocks {
+                decompressor = decompressor.max_in_flight(count);
+            }
+
+            if let Some(fill_value) = broken_block_fill {
+                decompressor = decompressor.skip_broken_blocks(fill_value);
+            }
+
+            while let Some(block) = decompressor.next() {
+                insert_block(image_collector, decompressor.meta_data(), block?)?;
+            }
+
+            Ok(decompressor.broken_blocks().to_vec())
+        },
+    }
+}
+
+/// The part of each layer that should be loaded, and the headers describing the cropped layers.
+#[derive(Debug, Clone)]
+struct RegionOfInterest {
+    headers: Vec<Header>,
+
+    /// For each layer, the rectangle in pixel indices inside the data window.
+    layer_bounds: Vec<IntegerBounds>,
+}
+
+impl RegionOfInterest {
+    fn new(headers: &[Header], region: IntegerBounds) -> Self {
+        let (headers, layer_bounds) = headers.iter().map(|header| {
+            let data_window = header.data_window();
+            let cropped_window = data_window.intersection(region)
+                .unwrap_or(IntegerBounds::new(region.position, Vec2(0, 0)));
+
+            let mut cropped_header = header.clone();
+            cropped_header.layer_size = cropped_window.size;
+            cropped_header.own_attributes.layer_position = cropped_window.position;
+
+            // smaller resolution levels are not loaded
+            if let BlockDescription::Tiles(tiles) = &mut cropped_header.blocks {
+                tiles.level_mode = LevelMode::Singular;
+            }
+
+            (cropped_header, cropped_window.with_origin(-data_window.position))
+        }).unzip();
+
+        Self { headers, layer_bounds }
+    }
+
+    fn overlaps_block(&self, block: BlockIndex) -> bool {
+        let block_bounds = IntegerBounds::new(block.pixel_position.to_i32(), block.pixel_size);
+
+        block.level == Vec2(0, 0) && self.layer_bounds.get(block.layer)
+            .and_then(|bounds| bounds.intersection(block_bounds)).is_some()
+    }
+
+    /// Cut off the pixels outside of the region, and move the block to the cropped layer coordinates.
+    fn crop_block(&self, headers: &[Header], block: UncompressedBlock) -> Option<UncompressedBlock> {
+        let bounds = self.layer_bounds[block.index.layer];
+        let mut cropped_block = block.crop(&headers[block.index.layer].channels, bounds)?;
+
+        cropped_block.index.pixel_position = (cropped_block.index.pixel_position.to_i32() - bounds.position)
+            .to_usize("cropped block position").ok()?;
+
+        Some(cropped_block)
+    }
+}
+
+/// The smaller resolution level that should be loaded in place of the largest level,
+/// and the headers describing each layer at that level.
+#[derive(Debug, Clone)]
+struct ResolutionLevel {
+    level: Vec2<usize>,
+    headers: Vec<Header>,
+}
+
+impl ResolutionLevel {
+    fn new(headers: &[Header], level: Vec2<usize>) -> Result<Self> {
+        let headers = headers.iter().map(|header| {
+            let mut level_header = header.clone();
+            level_header.layer_size = Self::level_size(header, level)?;
+
+            if let BlockDescription::Tiles(tiles) = &mut level_header.blocks {
+                tiles.level_mode = LevelMode::Singular;
+            }
+
+            Ok(level_header)
+        }).collect::<Result<Vec<Header>>>()?;
+
+        Ok(Self { level, headers })
+    }
+
+    fn level_size(header: &Header, level: Vec2<usize>) -> Result<Vec2<usize>> {
+        let missing_level = |level_count: String| Error::invalid(format!(
+            "resolution level {:?} does not exist, as the layer only has {} levels", level, level_count
+        ));
+
+        let tiles = match header.blocks {
+            BlockDescription::Tiles(tiles) => tiles,
+            BlockDescription::ScanLines if level == Vec2(0, 0) => return Ok(header.layer_size),
+            BlockDescription::ScanLines => return Err(missing_level(String::from("1"))),
+        };
+
+        let round = tiles.rounding_mode;
+        let size = header.layer_size;
+
+        match tiles.level_mode {
+            LevelMode::Singular if level == Vec2(0, 0) => Ok(size),
+            LevelMode::Singular => Err(missing_level(String::from("1"))),
+
+            LevelMode::MipMap => {
+                let level_count = compute_level_count(round, size.width().max(size.height()));
+                if level.x() != level.y() || level.x() >= level_count {
+                    return Err(missing_level(level_count.to_string()));
+                }
+
+                Ok(Vec2(compute_level_size(round, size.width(), level.x()), compute_level_size(round, size.height(), level.y())))
+            },
+
+            LevelMode::RipMap => {
+                let level_count = Vec2(compute_level_count(round, size.width()), compute_level_count(round, size.height()));
+                if level.x() >= level_count.x() || level.y() >= level_count.y() {
+                    return Err(missing_level(format!("{}x{}", level_count.x(), level_count.y())));
+                }
+
+                Ok(Vec2(compute_level_size(round, size.width(), level.x()), compute_level_size(round, size.height(), level.y())))
+            },
+        }
+    }
+
+    /// If the block is part of the selected level, return it as if it were part of the largest level.
+    fn select_block(&self, tile: TileCoordinates, block: BlockIndex) -> Option<(TileCoordinates, BlockIndex)> {
+        if block.level != self.level { return None; }
+
+        Some((
+            TileCoordinates { level_index: Vec2(0, 0), ..tile },
+            BlockIndex { level: Vec2(0, 0), ..block }
+        ))
     }
 }
 
@@ -147,7 +633,7 @@ impl<L> ImageWithAttributesReader<L> where L: LayersReader {
     pub fn new(headers: &[Header], layers_reader: L) -> Result<Self>
     {
         Ok(ImageWithAttributesReader {
-            image_attributes: headers.first().as_ref().expect("invalid headers").shared_attributes.clone(),
+            image_attributes: ImageAttributes::from_headers(headers).expect("invalid headers"),
             layers_reader,
         })
     }
@@ -162,6 +648,11 @@ impl<L> ImageWithAttributesReader<L> where L: LayersReader {
         self.layers_reader.read_block(headers, block)
     }
 
+    /// Load a single deep block, which has not been filtered, into the reader, accumulating the image
+    fn read_deep_block(&mut self, headers: &[Header], block: DeepUncompressedBlock) -> UnitResult {
+        self.layers_reader.read_deep_block(headers, block)
+    }
+
     /// Deliver the complete accumulated image
     fn into_image(self) -> Image<L::Layers> {
         Image {
@@ -186,7 +677,7 @@ pub trait ReadLayers<'s> {
 
     /// Specify that all attributes should be read from an image.
     /// Use `from_file(path)` on the return value of this method to actually decode an image.
-    fn all_attributes(self) -> ReadImage<fn(f64), Self> where Self: Sized {
+    fn all_attributes(self) -> ReadImage<'static, fn(f64), Self> where Self: Sized {
         ReadImage::new(self, ignore_progress)
     }
 }
@@ -203,6 +694,16 @@ pub trait LayersReader {
     /// Load a single pixel block, which has not been filtered, into the reader, accumulating the layer
     fn read_block(&mut self, headers: &[Header], block: UncompressedBlock) -> UnitResult;
 
+    /// Load a single deep block, which has not been filtered, into the reader, accumulating the layer.
+    /// Returns an error, unless the layers support deep data.
+    fn read_deep_block(&mut self, _headers: &[Header], _block: DeepUncompressedBlock) -> UnitResult {
+        Err(Error::unsupported("deep data in flat layers"))
+    }
+
+    /// The largest number of samples that a deep pixel in the specified layer may contain.
+    /// Deep blocks with more samples per pixel are rejected before their samples are decompressed.
+    fn max_samples_per_pixel(&self, _layer_index: usize) -> Option<usize> { None }
+
     /// Deliver the final accumulated layers for the image
     fn into_layers(self) -> Self::Layers;
 }
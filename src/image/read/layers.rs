@@ -4,6 +4,7 @@ use crate::image::*;
 use crate::meta::header::{Header, LayerAttributes};
 use crate::error::{Result, UnitResult, Error};
 use crate::block::{UncompressedBlock, BlockIndex};
+use crate::block::deep::DeepUncompressedBlock;
 use crate::math::Vec2;
 use crate::image::read::image::{ReadLayers, LayersReader};
 use crate::block::chunk::TileCoordinates;
@@ -27,6 +28,55 @@ pub struct ReadFirstValidLayer<ReadChannels> {
     pub read_channels: ReadChannels,
 }
 
+/// Specify to read only the layers that pass a filter, aborting if any one of them is invalid.
+/// Aborts if no layer passes the filter.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ReadMatchingLayers<ReadChannels, LayerFilter> {
+
+    /// The channel reading specification
+    pub read_channels: ReadChannels,
+
+    /// Decides whether a layer should be read, based on its header
+    pub layer_filter: LayerFilter,
+}
+
+/// Specify to read exactly one layer, selected by name or by a filter.
+/// Aborts if no layer or more than one layer is selected, or if the selected layer is invalid.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ReadSelectedLayer<ReadChannels, LayerSelection> {
+
+    /// The channel reading specification
+    pub read_channels: ReadChannels,
+
+    /// Decides which layer should be read, based on its header
+    pub layer_selection: LayerSelection,
+}
+
+/// Decides whether a layer should be selected, based on its header.
+/// Implemented for closures `Fn(&Header) -> bool` and for [`LayerNamed`].
+pub trait SelectLayer {
+
+    /// Whether the layer with this header should be selected.
+    fn is_selected(&self, header: &Header) -> bool;
+
+    /// Describes the selection in an error message, for example when no layer was selected.
+    fn describe(&self) -> String { "your layer filter".to_string() }
+}
+
+/// Selects the layer that has the specified name,
+/// either as the layer name attribute or as a prefix of the channel names, as in `beauty.R`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LayerNamed(pub Text);
+
+impl<F> SelectLayer for F where F: Fn(&Header) -> bool {
+    fn is_selected(&self, header: &Header) -> bool { self(header) }
+}
+
+impl SelectLayer for LayerNamed {
+    fn is_selected(&self, header: &Header) -> bool { header.has_layer_name(&self.0.to_string()) }
+    fn describe(&self) -> String { format!("the layer name `{}`", self.0) }
+}
+
 /// A template that creates a [`ChannelsReader`] once for all channels per layer.
 pub trait ReadChannels<'s> {
 
@@ -50,6 +100,32 @@ pub trait ReadChannels<'s> {
     /// even if only one of the layers contains unexpected data.
     fn all_layers(self) -> ReadAllLayers<Self> where Self:Sized { ReadAllLayers { read_channels: self } }
 
+    /// Reads only the layers that pass the filter, for example
+    /// `layers_matching(|header| header.has_layer_name("diffuse"))`.
+    /// Aborts if no layer passes the filter, or if any of the matching layers is invalid.
+    fn layers_matching<LayerFilter>(self, layer_filter: LayerFilter) -> ReadMatchingLayers<Self, LayerFilter>
+        where Self:Sized, LayerFilter: Fn(&Header) -> bool
+    {
+        ReadMatchingLayers { read_channels: self, layer_filter }
+    }
+
+    /// Reads exactly the one layer that has the specified name, for example `layer_named("beauty")`.
+    /// The name is found either in the layer name attribute, or as a prefix of the channel names, as in `beauty.R`.
+    /// In the latter case, all channels of that header are read, including their prefixes.
+    /// Aborts if no layer or more than one layer has this name, listing the layer names in the image.
+    fn layer_named(self, layer_name: impl Into<Text>) -> ReadSelectedLayer<Self, LayerNamed> where Self:Sized {
+        ReadSelectedLayer { read_channels: self, layer_selection: LayerNamed(layer_name.into()) }
+    }
+
+    /// Reads exactly the one layer that passes the filter, for example
+    /// `layer_matching(|header| header.channels.find_index_of_channel(&Text::from("Z")).is_some())`.
+    /// Aborts if no layer or more than one layer passes the filter, listing the layer names in the image.
+    fn layer_matching<LayerFilter>(self, layer_filter: LayerFilter) -> ReadSelectedLayer<Self, LayerFilter>
+        where Self:Sized, LayerFilter: Fn(&Header) -> bool
+    {
+        ReadSelectedLayer { read_channels: self, layer_selection: layer_filter }
+    }
+
     // TODO pub fn all_valid_layers(self) -> ReadAllValidLayers<Self> { ReadAllValidLayers { read_channels: self } }
 }
 
@@ -62,6 +138,13 @@ pub struct AllLayersReader<ChannelsReader> {
     layer_readers: SmallVec<[LayerReader<ChannelsReader>; 2]>, // TODO unpack struct?
 }
 
+/// Processes pixel blocks from a file and accumulates them into a list of layers,
+/// using only the layers that passed a filter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchingLayersReader<ChannelsReader> {
+    layer_readers: SmallVec<[(usize, LayerReader<ChannelsReader>); 2]>,
+}
+
 /// Processes pixel blocks from a file and accumulates them into a single layers, using only the first.
 /// For example, `ChannelsReader` can be
 /// `SpecificChannelsReader` or `AnyChannelsReader<FlatSamplesReader>`.
@@ -94,6 +177,16 @@ pub trait ChannelsReader {
     /// Load a single pixel block, which has not been filtered, into the reader, accumulating the channel data
     fn read_block(&mut self, header: &Header, block: UncompressedBlock) -> UnitResult;
 
+    /// Load a single deep block, which has not been filtered, into the reader, accumulating the channel data.
+    /// Returns an error, unless the channels support deep data.
+    fn read_deep_block(&mut self, _header: &Header, _block: DeepUncompressedBlock) -> UnitResult {
+        Err(Error::unsupported("deep data in flat channels"))
+    }
+
+    /// The largest number of samples that a deep pixel may contain.
+    /// Deep blocks with more samples per pixel are rejected before their samples are decompressed.
+    fn max_samples_per_pixel(&self) -> Option<usize> { None }
+
     /// Deliver the final accumulated channel collection for the image
     fn into_channels(self) -> Self::Channels;
 }
@@ -146,6 +239,16 @@ impl<C> LayersReader for AllLayersReader<C> where C: ChannelsReader {
             .channels_reader.read_block(headers.get(block.index.layer).expect("invalid header index in block"), block)
     }
 
+    fn read_deep_block(&mut self, headers: &[Header], block: DeepUncompressedBlock) -> UnitResult {
+        self.layer_readers
+            .get_mut(block.index.layer).expect("invalid layer index argument")
+            .channels_reader.read_deep_block(headers.get(block.index.layer).expect("invalid header index in block"), block)
+    }
+
+    fn max_samples_per_pixel(&self, layer_index: usize) -> Option<usize> {
+        self.layer_readers.get(layer_index)?.channels_reader.max_samples_per_pixel()
+    }
+
     fn into_layers(self) -> Self::Layers {
         self.layer_readers
             .into_iter()
@@ -160,6 +263,76 @@ impl<C> LayersReader for AllLayersReader<C> where C: ChannelsReader {
 }
 
 
+impl<'s, C, F> ReadLayers<'s> for ReadMatchingLayers<C, F> where C: ReadChannels<'s>, F: Fn(&Header) -> bool {
+    type Layers = Layers<<C::Reader as ChannelsReader>::Channels>;
+    type Reader = MatchingLayersReader<C::Reader>;
+
+    fn create_layers_reader(&'s self, headers: &[Header]) -> Result<Self::Reader> {
+        let readers: Result<SmallVec<_>> = headers.iter().enumerate()
+            .filter(|(_, header)| (self.layer_filter)(header))
+            .map(|(index, header)| Ok((index, LayerReader::new(header, self.read_channels.create_channels_reader(header)?)?)))
+            .collect();
+
+        let readers = readers?;
+        if readers.is_empty() {
+            return Err(Error::invalid("no layer in the image matched your layer filter"));
+        }
+
+        Ok(MatchingLayersReader { layer_readers: readers })
+    }
+}
+
+impl<C> MatchingLayersReader<C> {
+    fn layer_reader_mut(&mut self, layer_index: usize) -> Option<&mut LayerReader<C>> {
+        self.layer_readers.iter_mut()
+            .find(|(index, _)| *index == layer_index)
+            .map(|(_, reader)| reader)
+    }
+}
+
+impl<C> LayersReader for MatchingLayersReader<C> where C: ChannelsReader {
+    type Layers = Layers<C::Channels>;
+
+    fn filter_block(&self, _: &MetaData, tile: TileCoordinates, block: BlockIndex) -> bool {
+        self.layer_readers.iter()
+            .find(|(index, _)| *index == block.layer)
+            .map_or(false, |(_, layer)| layer.channels_reader.filter_block(tile))
+    }
+
+    fn read_block(&mut self, headers: &[Header], block: UncompressedBlock) -> UnitResult {
+        let header = headers.get(block.index.layer).expect("invalid header index in block");
+
+        self.layer_reader_mut(block.index.layer).expect("block should have been filtered out")
+            .channels_reader.read_block(header, block)
+    }
+
+    fn read_deep_block(&mut self, headers: &[Header], block: DeepUncompressedBlock) -> UnitResult {
+        let header = headers.get(block.index.layer).expect("invalid header index in block");
+
+        self.layer_reader_mut(block.index.layer).expect("block should have been filtered out")
+            .channels_reader.read_deep_block(header, block)
+    }
+
+    fn max_samples_per_pixel(&self, layer_index: usize) -> Option<usize> {
+        self.layer_readers.iter()
+            .find(|(index, _)| *index == layer_index)
+            .and_then(|(_, layer)| layer.channels_reader.max_samples_per_pixel())
+    }
+
+    fn into_layers(self) -> Self::Layers {
+        self.layer_readers
+            .into_iter()
+            .map(|(_, layer)| Layer {
+                channel_data: layer.channels_reader.into_channels(),
+                attributes: layer.attributes,
+                size: layer.size,
+                encoding: layer.encoding
+            })
+            .collect()
+    }
+}
+
+
 impl<'s, C> ReadLayers<'s> for ReadFirstValidLayer<C> where C: ReadChannels<'s> {
     type Layers = Layer<<C::Reader as ChannelsReader>::Channels>;
     type Reader = FirstValidLayerReader<C::Reader>;
@@ -180,6 +353,44 @@ impl<'s, C> ReadLayers<'s> for ReadFirstValidLayer<C> where C: ReadChannels<'s>
 }
 
 
+impl<'s, C, S> ReadLayers<'s> for ReadSelectedLayer<C, S> where C: ReadChannels<'s>, S: SelectLayer {
+    type Layers = Layer<<C::Reader as ChannelsReader>::Channels>;
+    type Reader = FirstValidLayerReader<C::Reader>;
+
+    fn create_layers_reader(&'s self, headers: &[Header]) -> Result<Self::Reader> {
+        let mut selected = headers.iter().enumerate()
+            .filter(|(_, header)| self.layer_selection.is_selected(header));
+
+        let (layer_index, header) = selected.next().ok_or_else(|| Error::invalid(format!(
+            "no layer matched {} (available layers: {})",
+            self.layer_selection.describe(), list_layer_names(headers)
+        )))?;
+
+        if selected.next().is_some() {
+            return Err(Error::invalid(format!(
+                "more than one layer matched {} (available layers: {})",
+                self.layer_selection.describe(), list_layer_names(headers)
+            )));
+        }
+
+        Ok(FirstValidLayerReader {
+            layer_reader: LayerReader::new(header, self.read_channels.create_channels_reader(header)?)?,
+            layer_index
+        })
+    }
+}
+
+/// Lists the names of all layers in the headers for an error message, for example `` `beauty`, `diffuse` ``.
+fn list_layer_names(headers: &[Header]) -> String {
+    let names: Vec<String> = headers.iter()
+        .flat_map(|header| header.layer_names())
+        .map(|name| format!("`{}`", name))
+        .collect();
+
+    if names.is_empty() { "none".to_string() } else { names.join(", ") }
+}
+
+
 impl<C> LayersReader for FirstValidLayerReader<C> where C: ChannelsReader {
     type Layers = Layer<C::Channels>;
 
@@ -192,6 +403,16 @@ impl<C> LayersReader for FirstValidLayerReader<C> where C: ChannelsReader {
         self.layer_reader.channels_reader.read_block(&headers[self.layer_index], block)
     }
 
+    fn read_deep_block(&mut self, headers: &[Header], block: DeepUncompressedBlock) -> UnitResult {
+        debug_assert_eq!(block.index.layer, self.layer_index, "block should have been filtered out");
+        self.layer_reader.channels_reader.read_deep_block(&headers[self.layer_index], block)
+    }
+
+    fn max_samples_per_pixel(&self, layer_index: usize) -> Option<usize> {
+        if layer_index == self.layer_index { self.layer_reader.channels_reader.max_samples_per_pixel() }
+        else { None }
+    }
+
     fn into_layers(self) -> Self::Layers {
         Layer {
             channel_data: self.layer_reader.channels_reader.into_channels(),
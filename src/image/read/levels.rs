@@ -7,6 +7,9 @@ use crate::meta::attribute::*;
 use crate::image::read::any_channels::*;
 use crate::block::chunk::TileCoordinates;
 use crate::image::read::specific_channels::*;
+use crate::image::read::named_channels::ReadNamedChannels;
+use crate::image::read::borrowed_pixels::ReadIntoBuffer;
+use crate::image::read::luminance_chroma::ReadRgbaOrLuminanceChroma;
 use crate::image::recursive::*;
 use crate::math::Vec2;
 use crate::block::lines::LineRef;
@@ -61,6 +64,45 @@ impl<DeepOrFlatSamples> ReadLargestLevel<DeepOrFlatSamples> {
             .collect_pixels(create_pixels, set_pixel)
     }
 
+    /// Read only layers that contain either rgba channels, or luminance and chroma channels (`Y`, `RY`, `BY` and optionally `A`).
+    /// Luminance and chroma are converted to rgb using the chromaticities of the image, just like the OpenEXR `RgbaYca` functions.
+    /// Layers that only contain a `Y` channel are gray, such that red, green and blue are all set to the luminance.
+    /// The alpha channel will contain the value `1.0` if no alpha channel can be found in the image.
+    ///
+    /// The closures are the same as for `rgba_channels`. For luminance-chroma layers,
+    /// the channel descriptions passed to the first closure describe the converted rgb channels,
+    /// which use the sample type of the luminance channel.
+    ///
+    /// Subsampled chroma channels are upsampled bilinearly, which requires all chroma samples of the layer,
+    /// so the pixels are only converted to rgba after all blocks of the layer have been read.
+    /// Throws an error for images with deep data, and for subsampled rgba, luminance or alpha channels.
+    pub fn rgba_channels_or_luminance_chroma<R,G,B,A, Create, Set, Pixels>(
+        self, create_pixels: Create, set_pixel: Set
+    ) -> ReadRgbaOrLuminanceChroma<R, G, B, A, Pixels, Create, Set>
+        where
+            R: FromNativeSample, G: FromNativeSample, B: FromNativeSample, A: FromNativeSample,
+            Create: Fn(Vec2<usize>, &RgbaChannels) -> Pixels,
+            Set: Fn(&mut Pixels, Vec2<usize>, (R,G,B,A)),
+    {
+        ReadRgbaOrLuminanceChroma::new(self.rgba_channels(create_pixels, set_pixel))
+    }
+
+    /// Read only layers that contain rgba channels, writing the samples into the specified buffer
+    /// instead of allocating a new pixel storage. Skips any other channels in the layer.
+    /// The alpha channel will contain the value `1.0` if no alpha channel can be found in the image.
+    ///
+    /// The buffer will contain four interleaved `f32` samples per pixel,
+    /// and must have a length of exactly `resolution.area() * 4`.
+    /// As the buffer can only be used for a single layer, combine this with `first_valid_layer`.
+    ///
+    /// Throws an error for images with deep data or subsampling.
+    pub fn rgba_channels_into(self, buffer: &mut [f32]) -> ReadIntoBuffer<'_, RgbaChannelsF32> {
+        self.specific_channels()
+            .required("R").required("G").required("B")
+            .optional("A", 1.0)
+            .collect_pixels_into(buffer)
+    }
+
     /// Read only layers that contain rgb channels. Skips any other channels in the layer.
     ///
     /// Using two closures, define how to store the pixels.
@@ -86,6 +128,30 @@ impl<DeepOrFlatSamples> ReadLargestLevel<DeepOrFlatSamples> {
             .collect_pixels(create_pixels, set_pixel)
     }
 
+    /// Read only layers that contain the channels with the specified names, skipping any other channels in the layer.
+    /// In contrast to `specific_channels`, the channel names can be chosen at runtime, for example by the user.
+    /// Call `default_sample` on the result to also read layers that do not contain some of the channels.
+    ///
+    /// Using two closures, define how to store the pixels.
+    /// The first closure creates an image, receiving the description of each channel,
+    /// which is `None` for missing channels that use the default sample.
+    /// The second closure inserts a single pixel, which is a slice containing one sample for each channel,
+    /// in the order of the channel names. All samples are of the same type,
+    /// being either `f16`, `f32`, `u32` or `Sample`.
+    ///
+    /// Throws an error for images with deep data or subsampling.
+    /// Also throws an error if no channel name is specified, or if a channel name is empty or occurs more than once.
+    pub fn channels_by_name<Sample, Create, Set, Pixels>(
+        self, channel_names: impl IntoIterator<Item=impl Into<Text>>, create_pixels: Create, set_pixel: Set
+    ) -> ReadNamedChannels<Sample, Pixels, Create, Set>
+        where
+            Sample: FromNativeSample,
+            Create: Fn(Vec2<usize>, &[Option<ChannelDescription>]) -> Pixels,
+            Set: Fn(&mut Pixels, Vec2<usize>, &[Sample]),
+    {
+        ReadNamedChannels::new(channel_names, create_pixels, set_pixel)
+    }
+
     /// Read only layers that contain the specified channels, skipping any other channels in the layer.
     /// Further specify which channels should be included by calling `.required("ChannelName")`
     /// or `.optional("ChannelName", default_value)` on the result of this function.
@@ -97,6 +163,9 @@ impl<DeepOrFlatSamples> ReadLargestLevel<DeepOrFlatSamples> {
     }
 }
 
+/// Declares the rgba channels with `f32` samples, used by `rgba_channels_into`.
+pub type RgbaChannelsF32 = ReadOptionalChannel<ReadRequiredChannel<ReadRequiredChannel<ReadRequiredChannel<NoneMore, f32>, f32>, f32>, f32>;
+
 /// Specify to read all contained resolution levels from the image, if any.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ReadAllLevels<DeepOrFlatSamples> {
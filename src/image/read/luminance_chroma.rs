@@ -0,0 +1,294 @@
+//! How to read rgba pixels from layers that contain either rgba channels
+//! or luminance and chroma channels (`Y`, `RY` and `BY`), as produced by the OpenEXR `RgbaYca` functions.
+
+use crate::block::samples::*;
+use crate::image::*;
+use crate::image::recursive::*;
+use crate::math::*;
+use crate::meta::header::*;
+use crate::meta::attribute::*;
+use crate::error::*;
+use crate::block::UncompressedBlock;
+use crate::image::read::layers::{ChannelsReader, ReadChannels};
+use crate::image::read::specific_channels::*;
+use crate::block::chunk::TileCoordinates;
+
+use std::marker::PhantomData;
+
+
+type ReadRgba<R, G, B, A> = ReadOptionalChannel<ReadRequiredChannel<ReadRequiredChannel<ReadRequiredChannel<NoneMore, R>, G>, B>, A>;
+
+/// Specifies to read rgba pixels from layers with rgba channels,
+/// and to convert the pixels of layers with luminance and chroma channels to rgba.
+/// Create this by calling `rgba_channels_or_luminance_chroma`.
+#[derive(Clone, Debug)]
+pub struct ReadRgbaOrLuminanceChroma<R, G, B, A, Pixels, CreatePixels, SetPixel> {
+    rgba: CollectPixels<ReadRgba<R, G, B, A>, (R, G, B, A), Pixels, CreatePixels, SetPixel>,
+}
+
+impl<R, G, B, A, Pixels, CreatePixels, SetPixel> ReadRgbaOrLuminanceChroma<R, G, B, A, Pixels, CreatePixels, SetPixel> {
+    pub(crate) fn new(rgba: CollectPixels<ReadRgba<R, G, B, A>, (R, G, B, A), Pixels, CreatePixels, SetPixel>) -> Self {
+        ReadRgbaOrLuminanceChroma { rgba }
+    }
+}
+
+impl<'s, R, G, B, A, Pixels, CreatePixels, SetPixel: 's>
+ReadChannels<'s> for ReadRgbaOrLuminanceChroma<R, G, B, A, Pixels, CreatePixels, SetPixel>
+    where
+        R: FromNativeSample, G: FromNativeSample, B: FromNativeSample, A: FromNativeSample,
+        CreatePixels: Fn(Vec2<usize>, &RgbaChannels) -> Pixels,
+        SetPixel: Fn(&mut Pixels, Vec2<usize>, (R, G, B, A)),
+{
+    type Reader = RgbaOrLuminanceChromaReader<
+        <CollectPixels<ReadRgba<R, G, B, A>, (R, G, B, A), Pixels, CreatePixels, SetPixel> as ReadChannels<'s>>::Reader,
+        LuminanceChromaReader<Pixels, &'s SetPixel, (R, G, B, A)>,
+    >;
+
+    fn create_channels_reader(&'s self, header: &Header) -> Result<Self::Reader> {
+        let contains_channel = |name: &str| header.channels.find_index_of_channel(&Text::from(name)).is_some();
+
+        // layers that contain both rgb and luminance are read as rgb
+        if !contains_channel("Y") || (contains_channel("R") && contains_channel("G") && contains_channel("B")) {
+            return self.rgba.create_channels_reader(header).map(RgbaOrLuminanceChromaReader::Rgba);
+        }
+
+        if header.deep { return Err(Error::unsupported("luminance chroma does not support deep data yet")) }
+
+        let channel = |name: &str| header.channels.find_index_of_channel(&Text::from(name))
+            .map(|index| ChannelPlane::new(index, &header.channels.list[index], header.layer_size));
+
+        let luminance = channel("Y").expect("luminance channel bug");
+        let alpha = channel("A");
+
+        // only the chroma channels are upsampled
+        if luminance.sampling != Vec2(1, 1) || alpha.as_ref().map_or(false, |alpha| alpha.sampling != Vec2(1, 1)) {
+            return Err(Error::unsupported("luminance chroma does not support subsampled luminance or alpha channels"));
+        }
+
+        // the converted rgb channels use the sample type of the luminance channel
+        let luminance_type = header.channels.list[luminance.channel_index].sample_type;
+        let rgb_channel = |name: &str| ChannelDescription::named(name, luminance_type);
+        let alpha_channel = alpha.as_ref().map(|alpha| header.channels.list[alpha.channel_index].clone());
+        let channel_descriptions = (rgb_channel("R"), rgb_channel("G"), rgb_channel("B"), alpha_channel);
+
+        let create = &self.rgba.create_pixels;
+        let pixel_storage = create(header.layer_size, &channel_descriptions);
+
+        let chromaticities = header.shared_attributes.chromaticities.unwrap_or(Chromaticities::REC_709);
+
+        Ok(RgbaOrLuminanceChromaReader::LuminanceChroma(LuminanceChromaReader {
+            set_pixel: &self.rgba.set_pixel,
+            luminance_weights: chromaticities.luminance_weights(),
+            planes: [Some(luminance), channel("RY"), channel("BY"), alpha],
+            layer_size: header.layer_size,
+            pixel_storage, channel_descriptions,
+            px: PhantomData::default(),
+        }))
+    }
+}
+
+/// Reads either a layer with rgba channels, or a layer with luminance and chroma channels.
+#[derive(Clone, Debug)]
+pub enum RgbaOrLuminanceChromaReader<RgbaReader, LuminanceChromaReader> {
+
+    /// The layer contains rgb channels.
+    Rgba(RgbaReader),
+
+    /// The layer contains a luminance channel, and possibly chroma channels, but no rgb channels.
+    LuminanceChroma(LuminanceChromaReader),
+}
+
+impl<Channels, RgbaReader, LuminanceChromaReader> ChannelsReader for RgbaOrLuminanceChromaReader<RgbaReader, LuminanceChromaReader>
+    where
+        RgbaReader: ChannelsReader<Channels = Channels>,
+        LuminanceChromaReader: ChannelsReader<Channels = Channels>,
+{
+    type Channels = Channels;
+
+    fn filter_block(&self, tile: TileCoordinates) -> bool {
+        match self {
+            RgbaOrLuminanceChromaReader::Rgba(reader) => reader.filter_block(tile),
+            RgbaOrLuminanceChromaReader::LuminanceChroma(reader) => reader.filter_block(tile),
+        }
+    }
+
+    fn read_block(&mut self, header: &Header, block: UncompressedBlock) -> UnitResult {
+        match self {
+            RgbaOrLuminanceChromaReader::Rgba(reader) => reader.read_block(header, block),
+            RgbaOrLuminanceChromaReader::LuminanceChroma(reader) => reader.read_block(header, block),
+        }
+    }
+
+    fn into_channels(self) -> Self::Channels {
+        match self {
+            RgbaOrLuminanceChromaReader::Rgba(reader) => reader.into_channels(),
+            RgbaOrLuminanceChromaReader::LuminanceChroma(reader) => reader.into_channels(),
+        }
+    }
+}
+
+/// The reader that converts luminance and chroma samples to rgba pixels.
+/// The pixels are converted after all blocks have been read,
+/// because upsampling the chroma of a line requires the chroma of the following lines, which may be in another block.
+#[derive(Clone, Debug)]
+pub struct LuminanceChromaReader<Pixels, SetPixel, Pixel> {
+    set_pixel: SetPixel,
+    pixel_storage: Pixels,
+
+    /// The samples of the `Y`, `RY`, `BY` and `A` channels, if contained in the layer.
+    planes: [Option<ChannelPlane>; 4],
+
+    layer_size: Vec2<usize>,
+    channel_descriptions: RgbaChannels,
+    luminance_weights: [f32; 3],
+    px: PhantomData<Pixel>,
+}
+
+/// All samples of a channel, at the resolution of the channel.
+#[derive(Clone, Debug)]
+struct ChannelPlane {
+    channel_index: usize,
+    reader: SampleReader<f32>,
+    sampling: Vec2<usize>,
+    resolution: Vec2<usize>,
+    samples: Vec<f32>,
+}
+
+impl<R, G, B, A, Pixels, SetPixel> ChannelsReader for LuminanceChromaReader<Pixels, SetPixel, (R, G, B, A)>
+    where
+        R: FromNativeSample, G: FromNativeSample, B: FromNativeSample, A: FromNativeSample,
+        SetPixel: Fn(&mut Pixels, Vec2<usize>, (R, G, B, A)),
+{
+    type Channels = SpecificChannels<Pixels, RgbaChannels>;
+
+    fn filter_block(&self, tile: TileCoordinates) -> bool { tile.is_largest_resolution_level() }
+
+    fn read_block(&mut self, header: &Header, block: UncompressedBlock) -> UnitResult {
+        let position = block.index.pixel_position;
+        let mut remaining_bytes = block.data.as_slice();
+
+        // a line only contains the channels that are sampled in that line,
+        // and each of those channels only contains the samples of the sampled columns
+        for y in position.y() .. position.y() + block.index.pixel_size.height() {
+            for (channel_index, channel) in header.channels.list.iter().enumerate() {
+                if y % channel.sampling.y() != 0 { continue; }
+
+                let sample_count = block.index.pixel_size.width() / channel.sampling.x();
+                let byte_count = sample_count * channel.sample_type.bytes_per_sample();
+
+                if remaining_bytes.len() < byte_count { return Err(Error::invalid("block data size")); }
+                let (line_bytes, rest) = remaining_bytes.split_at(byte_count);
+                remaining_bytes = rest;
+
+                let plane = self.planes.iter_mut().flatten()
+                    .find(|plane| plane.channel_index == channel_index);
+
+                if let Some(plane) = plane {
+                    let start = (y / plane.sampling.y()) * plane.resolution.x() + position.x() / plane.sampling.x();
+                    let samples = &mut plane.samples[start .. start + sample_count];
+                    plane.reader.read_own_samples(line_bytes, samples, |sample| sample);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn into_channels(mut self) -> Self::Channels {
+        let [luminance, red_chroma, blue_chroma, alpha] = &self.planes;
+        let luminance = luminance.as_ref().expect("luminance channel bug");
+        let sample = |plane: &Option<ChannelPlane>, position: Vec2<usize>, default: f32|
+            plane.as_ref().map_or(default, |plane| plane.interpolated_sample(position));
+
+        for y in 0 .. self.layer_size.height() {
+            for x in 0 .. self.layer_size.width() {
+                let position = Vec2(x, y);
+
+                let (r, g, b) = luminance_chroma_to_rgb(
+                    luminance.interpolated_sample(position),
+                    sample(red_chroma, position, 0.0),
+                    sample(blue_chroma, position, 0.0),
+                    self.luminance_weights
+                );
+
+                let pixel = (R::from_f32(r), G::from_f32(g), B::from_f32(b), A::from_f32(sample(alpha, position, 1.0)));
+                let set_pixel = &self.set_pixel;
+                set_pixel(&mut self.pixel_storage, position, pixel);
+            }
+        }
+
+        SpecificChannels { channels: self.channel_descriptions, pixels: self.pixel_storage }
+    }
+}
+
+impl ChannelPlane {
+    fn new(channel_index: usize, channel: &ChannelDescription, layer_size: Vec2<usize>) -> Self {
+        let resolution = channel.subsampled_resolution(layer_size);
+
+        ChannelPlane {
+            channel_index, resolution,
+            reader: SampleReader::new(0, channel.clone()),
+            sampling: channel.sampling,
+            samples: vec![0.0; resolution.area()],
+        }
+    }
+
+    /// The sample at the pixel position. For subsampled channels,
+    /// interpolates bilinearly between the samples that surround the pixel.
+    /// Pixels after the last sample use the last sample.
+    fn interpolated_sample(&self, position: Vec2<usize>) -> f32 {
+        let Vec2(x, y) = position;
+        let (left, right, x_weight) = interpolation_indices(x, self.sampling.x(), self.resolution.x());
+        let (top, bottom, y_weight) = interpolation_indices(y, self.sampling.y(), self.resolution.y());
+
+        let sample = |x: usize, y: usize| self.samples[y * self.resolution.x() + x];
+        let top_sample = interpolate(sample(left, top), sample(right, top), x_weight);
+        let bottom_sample = interpolate(sample(left, bottom), sample(right, bottom), x_weight);
+        interpolate(top_sample, bottom_sample, y_weight)
+    }
+}
+
+/// The indices of the samples before and after the pixel, and the weight of the sample after the pixel.
+fn interpolation_indices(pixel: usize, sampling: usize, resolution: usize) -> (usize, usize, f32) {
+    let before = (pixel / sampling).min(resolution - 1);
+    let after = (before + 1).min(resolution - 1);
+    (before, after, (pixel % sampling) as f32 / sampling as f32)
+}
+
+/// Pixels on a sample use that sample exactly.
+fn interpolate(before: f32, after: f32, weight: f32) -> f32 {
+    if weight == 0.0 { before } else { before * (1.0 - weight) + after * weight }
+}
+
+/// Convert a pixel with luminance and chroma to rgb, just like `YCAtoRGB` in the OpenEXR library.
+/// Pixels without chroma are gray, such that the luminance is used for all three rgb values.
+pub fn luminance_chroma_to_rgb(luminance: f32, red_chroma: f32, blue_chroma: f32, luminance_weights: [f32; 3]) -> (f32, f32, f32) {
+    if red_chroma == 0.0 && blue_chroma == 0.0 {
+        return (luminance, luminance, luminance);
+    }
+
+    let [red_weight, green_weight, blue_weight] = luminance_weights;
+    let red = (red_chroma + 1.0) * luminance;
+    let blue = (blue_chroma + 1.0) * luminance;
+    let green = (luminance - red * red_weight - blue * blue_weight) / green_weight;
+
+    (red, green, blue)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn convert_luminance_chroma_to_rgb() {
+        let weights = Chromaticities::REC_709.luminance_weights();
+        assert_eq!(luminance_chroma_to_rgb(0.5, 0.0, 0.0, weights), (0.5, 0.5, 0.5));
+
+        let (red, green, blue) = (0.8_f32, 0.3_f32, 0.1_f32);
+        let luminance = red * weights[0] + green * weights[1] + blue * weights[2];
+        let (r, g, b) = luminance_chroma_to_rgb(luminance, red / luminance - 1.0, blue / luminance - 1.0, weights);
+
+        assert!((r - red).abs() < 1e-5 && (g - green).abs() < 1e-5 && (b - blue).abs() < 1e-5, "{:?}", (r, g, b));
+    }
+}
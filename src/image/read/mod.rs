@@ -28,9 +28,11 @@
 //! 1. `read_all_data_from_file(path)`:
 //!     All layers with arbitrary channels and all resolution levels are extracted from the file.
 //!
-//!     Note: Currently does not support deep data, and currently fails
+//!     Note: Does not read deep data, and currently fails
 //!     if any layer in the image contains deep data.
 //!
+//! Use `read().deep_data()` to read the layers that contain deep data instead.
+//!
 
 // The following three stages are internally used to read an image.
 // 1. `ReadImage` - The specification. Contains everything the user wants to tell us about loading an image.
@@ -48,9 +50,15 @@ pub mod any_channels;
 pub mod levels;
 pub mod samples;
 pub mod specific_channels;
+pub mod named_channels;
+pub mod planar_channels;
+pub mod borrowed_pixels;
+pub mod luminance_chroma;
+pub mod deep;
 
 use crate::error::{Result};
 use crate::image::read::samples::{ReadFlatSamples};
+use crate::image::read::deep::{ReadDeepSamples, ReadFlattenedDeepSamples};
 use std::path::Path;
 use crate::image::{AnyImage, AnyChannels, FlatSamples, Image, Layer, FlatImage, PixelLayersImage, RgbaChannels};
 use crate::image::read::image::ReadLayers;
@@ -111,8 +119,8 @@ pub fn read_first_flat_layer_from_file(path: impl AsRef<Path>) -> Result<Image<L
 /// The first closure creates an image, and the second closure inserts a single pixel.
 /// The type of the pixel can be defined by the second closure;
 /// it must be a tuple containing four values, each being either `f16`, `f32`, `u32` or `Sample`.
-// FIXME Set and Create should not need to be static
-pub fn read_all_rgba_layers_from_file<R,G,B,A, Set:'static, Create:'static, Pixels: 'static>(
+// FIXME Set should not need to be static
+pub fn read_all_rgba_layers_from_file<R,G,B,A, Set:'static, Create, Pixels>(
     path: impl AsRef<Path>, create: Create, set_pixel: Set
 )
     -> Result<PixelLayersImage<Pixels, RgbaChannels>>
@@ -140,8 +148,8 @@ pub fn read_all_rgba_layers_from_file<R,G,B,A, Set:'static, Create:'static, Pixe
 /// The first closure creates an image, and the second closure inserts a single pixel.
 /// The type of the pixel can be defined by the second closure;
 /// it must be a tuple containing four values, each being either `f16`, `f32`, `u32` or `Sample`.
-// FIXME Set and Create should not need to be static
-pub fn read_first_rgba_layer_from_file<R,G,B,A, Set:'static, Create:'static, Pixels: 'static>(
+// FIXME Set should not need to be static
+pub fn read_first_rgba_layer_from_file<R,G,B,A, Set:'static, Create, Pixels>(
     path: impl AsRef<Path>, create: Create, set_pixel: Set
 )
     -> Result<PixelImage<Pixels, RgbaChannels>>
@@ -196,6 +204,17 @@ impl ReadBuilder {
     // TODO not panic but skip deep layers!
     pub fn no_deep_data(self) -> ReadFlatSamples { ReadFlatSamples }
 
+    /// Specify to handle any number of samples per pixel, reading only the layers that contain "deep data".
+    /// Reading the file directly skips the layers without deep data, and reads only the largest resolution level.
+    /// Alternatively, choose a different sample storage, or continue with `largest_resolution_level()`
+    /// to select the layers and attributes just like with flat data.
+    pub fn deep_data(self) -> ReadDeepSamples { ReadDeepSamples::new() }
+
+    /// Specify to read only the layers that contain "deep data", compositing the samples of each pixel
+    /// into a single flat sample per channel. The result is a flat image, which can be processed like any other.
+    /// Layers without deep data are skipped, and only the largest resolution level is read.
+    pub fn flatten_deep_data(self) -> ReadFlattenedDeepSamples { ReadFlattenedDeepSamples::new() }
+
     // pub fn any_resolution_levels() -> ReadBuilder<> {}
 
     // TODO
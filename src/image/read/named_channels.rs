@@ -0,0 +1,192 @@
+//! How to read a selection of channels whose names are only known at runtime.
+//! In contrast to `specific_channels`, all samples of a pixel have the same type.
+
+use crate::block::samples::*;
+use crate::image::*;
+use crate::math::*;
+use crate::meta::header::*;
+use crate::error::*;
+use crate::block::UncompressedBlock;
+use crate::image::read::layers::{ChannelsReader, ReadChannels};
+use crate::image::read::specific_channels::{SampleReader, OptionalSampleReader};
+use crate::block::chunk::TileCoordinates;
+
+use std::marker::PhantomData;
+
+
+/// Specifies which channels to read, using a list of channel names,
+/// and how to collect the samples into a number of individual pixels.
+/// Each pixel is a slice that contains one sample for each channel, in the order of the channel names.
+/// Call `default_sample` on this object to also read layers that do not contain some of the channels.
+/// The names are only validated when reading, as they may come from user input.
+#[derive(Clone, Debug)]
+pub struct ReadNamedChannels<Sample, PixelStorage, CreatePixels, SetPixel> {
+    channel_names: Vec<Text>,
+    default_samples: Vec<(Text, Sample)>,
+    create_pixels: CreatePixels,
+    set_pixel: SetPixel,
+    px: PhantomData<PixelStorage>,
+}
+
+impl<Sample, PixelStorage, CreatePixels, SetPixel> ReadNamedChannels<Sample, PixelStorage, CreatePixels, SetPixel> {
+
+    pub(crate) fn new(channel_names: impl IntoIterator<Item=impl Into<Text>>, create_pixels: CreatePixels, set_pixel: SetPixel) -> Self {
+        ReadNamedChannels {
+            channel_names: channel_names.into_iter().map(Into::into).collect(),
+            default_samples: Vec::new(),
+            create_pixels, set_pixel, px: Default::default()
+        }
+    }
+
+    /// If a layer does not contain the channel with the specified name,
+    /// use the specified sample for all of its pixels, instead of skipping the layer.
+    /// The description of such a channel will be `None`.
+    /// Reading returns an error if the channel name is not in the list of channels that should be read.
+    pub fn default_sample(mut self, channel_name: impl Into<Text>, default_sample: Sample) -> Self {
+        let channel_name = channel_name.into();
+        self.default_samples.retain(|(name, _)| name != &channel_name);
+        self.default_samples.push((channel_name, default_sample));
+        self
+    }
+
+    /// Pair each channel name with its default sample, if any.
+    /// Returns an error if the list of names is empty, contains an empty or duplicate name,
+    /// or if a default sample was specified for a channel that is not in the list.
+    fn channels_with_defaults(&self) -> Result<Vec<(Text, Option<Sample>)>> where Sample: Copy {
+        if self.channel_names.is_empty() {
+            return Err(Error::invalid("at least one channel name is required"));
+        }
+
+        for (index, channel_name) in self.channel_names.iter().enumerate() {
+            if channel_name.bytes().is_empty() {
+                return Err(Error::invalid("channel names must not be empty"));
+            }
+
+            if self.channel_names[.. index].contains(channel_name) {
+                return Err(Error::invalid(format!("the channel name `{}` is specified more than once", channel_name)));
+            }
+        }
+
+        if let Some((unknown_name, _)) = self.default_samples.iter().find(|(name, _)| !self.channel_names.contains(name)) {
+            return Err(Error::invalid(format!(
+                "a default sample is specified for the channel `{}`, which is not in the list of channel names",
+                unknown_name
+            )));
+        }
+
+        Ok(self.channel_names.iter().map(|channel_name| {
+            let default_sample = self.default_samples.iter()
+                .find(|(name, _)| name == channel_name)
+                .map(|&(_, sample)| sample);
+
+            (channel_name.clone(), default_sample)
+        }).collect())
+    }
+}
+
+impl<'s, Sample, PixelStorage, CreatePixels, SetPixel: 's>
+ReadChannels<'s> for ReadNamedChannels<Sample, PixelStorage, CreatePixels, SetPixel>
+    where
+        Sample: FromNativeSample,
+        CreatePixels: Fn(Vec2<usize>, &[Option<ChannelDescription>]) -> PixelStorage,
+        SetPixel: Fn(&mut PixelStorage, Vec2<usize>, &[Sample]),
+{
+    type Reader = NamedChannelsReader<Sample, PixelStorage, &'s SetPixel>;
+
+    fn create_channels_reader(&'s self, header: &Header) -> Result<Self::Reader> {
+        let channels = self.channels_with_defaults()?;
+        if header.deep { return Err(Error::unsupported("`channels_by_name` does not support deep data yet")) }
+
+        let channel_readers = create_sample_readers(header, &channels)?;
+        let channel_descriptions: Vec<Option<ChannelDescription>> = channel_readers.iter()
+            .map(|reader| reader.channel().cloned()).collect();
+
+        let create = &self.create_pixels;
+        let pixel_storage = create(header.layer_size, &channel_descriptions);
+
+        Ok(NamedChannelsReader {
+            set_pixel: &self.set_pixel,
+            pixel_storage,
+            channel_readers,
+            channel_descriptions,
+        })
+    }
+}
+
+/// Create a reader for each of the named channels, in the order of the list.
+/// Channels that are not contained in the layer use their default sample, if any.
+/// The readers assume that each line contains a sample of each channel for each pixel,
+/// so layers with subsampled channels are rejected, even if the subsampled channels are not requested.
+pub(crate) fn create_sample_readers<Sample: FromNativeSample>(header: &Header, channels: &[(Text, Option<Sample>)])
+    -> Result<Vec<OptionalSampleReader<Sample>>>
+{
+    if header.channels.is_subsampled() {
+        return Err(Error::unsupported("reading channels by name does not support subsampled channels yet"));
+    }
+
+    channels.iter().map(|(channel_name, default_sample)| {
+        let reader = header.channels.channels_with_byte_offset()
+            .find(|(_, channel)| &channel.name == channel_name)
+            .map(|(channel_byte_offset, channel)| SampleReader::new(channel_byte_offset, channel.clone()));
+
+        match (reader, default_sample) {
+            (Some(reader), _) => Ok(OptionalSampleReader::new(Some(reader), Sample::default())),
+            (None, &Some(default_sample)) => Ok(OptionalSampleReader::new(None, default_sample)),
+
+            (None, None) => Err(Error::invalid(format!(
+                "layer does not contain all of your specified channels (`{}` is missing)",
+                channel_name
+            ))),
+        }
+    }).collect()
+}
+
+/// The reader that holds the temporary data that is required to read some channels by name.
+#[derive(Clone, Debug)]
+pub struct NamedChannelsReader<Sample, PixelStorage, SetPixel> {
+    set_pixel: SetPixel,
+    pixel_storage: PixelStorage,
+    channel_readers: Vec<OptionalSampleReader<Sample>>,
+    channel_descriptions: Vec<Option<ChannelDescription>>,
+}
+
+impl<Sample, PixelStorage, SetPixel> ChannelsReader for NamedChannelsReader<Sample, PixelStorage, SetPixel>
+    where Sample: FromNativeSample, SetPixel: Fn(&mut PixelStorage, Vec2<usize>, &[Sample]),
+{
+    type Channels = SpecificChannels<PixelStorage, Vec<Option<ChannelDescription>>>;
+
+    fn filter_block(&self, tile: TileCoordinates) -> bool { tile.is_largest_resolution_level() }
+
+    fn read_block(&mut self, header: &Header, block: UncompressedBlock) -> UnitResult {
+        let width = block.index.pixel_size.width();
+        let channel_count = self.channel_readers.len();
+
+        // each channel is read into a separate line first, and then interleaved into the pixels
+        let mut channel_line = vec![Sample::default(); width];
+        let mut pixel_line = vec![Sample::default(); width * channel_count];
+
+        let byte_lines = block.data.chunks_exact(header.channels.bytes_per_pixel * width);
+        debug_assert_eq!(byte_lines.len(), block.index.pixel_size.height(), "invalid block lines split");
+
+        for (y_offset, line_bytes) in byte_lines.enumerate() {
+            for (channel_index, channel_reader) in self.channel_readers.iter().enumerate() {
+                channel_reader.read_samples(line_bytes, &mut channel_line, |sample| sample);
+
+                for (pixel, &sample) in pixel_line.chunks_exact_mut(channel_count).zip(&channel_line) {
+                    pixel[channel_index] = sample;
+                }
+            }
+
+            for (x_offset, pixel) in pixel_line.chunks_exact(channel_count).enumerate() {
+                let set_pixel = &self.set_pixel;
+                set_pixel(&mut self.pixel_storage, block.index.pixel_position + Vec2(x_offset, y_offset), pixel);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn into_channels(self) -> Self::Channels {
+        SpecificChannels { channels: self.channel_descriptions, pixels: self.pixel_storage }
+    }
+}
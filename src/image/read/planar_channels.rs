@@ -0,0 +1,77 @@
+//! How to read a selection of channels into one separate vector per channel.
+
+use crate::image::*;
+use crate::meta::header::*;
+use crate::error::*;
+use crate::block::UncompressedBlock;
+use crate::image::read::layers::{ChannelsReader, ReadChannels};
+use crate::image::read::specific_channels::{DeclaredChannels, OptionalSampleReader};
+use crate::image::read::named_channels::create_sample_readers;
+use crate::block::chunk::TileCoordinates;
+
+
+/// Specifies to read the declared channels into a `PlanarChannels` struct,
+/// storing each channel in a separate vector of `f32` samples.
+/// Create this by calling `planar()` after declaring the channels with `required` and `optional`.
+#[derive(Clone, Debug)]
+pub struct ReadPlanarChannels<ReadChannels> {
+    pub(crate) read_channels: ReadChannels,
+}
+
+impl<'s, InnerChannels> ReadChannels<'s> for ReadPlanarChannels<InnerChannels> where InnerChannels: DeclaredChannels {
+    type Reader = PlanarChannelsReader;
+
+    fn create_channels_reader(&'s self, header: &Header) -> Result<Self::Reader> {
+        if header.deep { return Err(Error::unsupported("planar channels do not support deep data yet")) }
+
+        let declared_channels = self.read_channels.declared_channels();
+        let channel_readers = create_sample_readers(header, &declared_channels)?;
+
+        let sample_count = header.layer_size.area();
+        let channels = declared_channels.into_iter()
+            .map(|(name, _)| (name, vec![0.0; sample_count]))
+            .collect();
+
+        Ok(PlanarChannelsReader {
+            channel_readers,
+            channels: PlanarChannels { resolution: header.layer_size, channels }
+        })
+    }
+}
+
+/// The reader that holds the temporary data that is required to read channels into separate vectors.
+#[derive(Clone, Debug)]
+pub struct PlanarChannelsReader {
+    channel_readers: Vec<OptionalSampleReader<f32>>,
+    channels: PlanarChannels,
+}
+
+impl ChannelsReader for PlanarChannelsReader {
+    type Channels = PlanarChannels;
+
+    fn filter_block(&self, tile: TileCoordinates) -> bool { tile.is_largest_resolution_level() }
+
+    fn read_block(&mut self, header: &Header, block: UncompressedBlock) -> UnitResult {
+        let width = block.index.pixel_size.width();
+        let image_width = self.channels.resolution.width();
+
+        let byte_lines = block.data.chunks_exact(header.channels.bytes_per_pixel * width);
+        debug_assert_eq!(byte_lines.len(), block.index.pixel_size.height(), "invalid block lines split");
+
+        for (y_offset, line_bytes) in byte_lines.enumerate() {
+            let position = block.index.pixel_position + Vec2(0, y_offset);
+            let start_index = position.y() * image_width + position.x();
+
+            // the samples of each line are converted directly into the vector of the channel
+            for (channel_reader, (_, samples)) in self.channel_readers.iter().zip(&mut self.channels.channels) {
+                channel_reader.read_samples(line_bytes, &mut samples[start_index .. start_index + width], |sample| sample);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn into_channels(self) -> Self::Channels {
+        self.channels
+    }
+}
@@ -9,6 +9,8 @@ use crate::meta::header::*;
 use crate::error::*;
 use crate::block::UncompressedBlock;
 use crate::image::read::layers::{ChannelsReader, ReadChannels};
+use crate::image::read::planar_channels::ReadPlanarChannels;
+use crate::image::read::borrowed_pixels::ReadIntoBuffer;
 use crate::block::chunk::TileCoordinates;
 
 use std::marker::PhantomData;
@@ -25,7 +27,9 @@ pub trait ReadSpecificChannel: Sized + CheckDuplicates {
     type RecursivePixelReader: RecursivePixelReader;
 
     /// Create a separate internal reader for the pixels of the specific channel combination.
-    fn create_recursive_reader(&self, channels: &ChannelList) -> Result<Self::RecursivePixelReader>;
+    /// The channels are the channels of the layer, or of the selected view,
+    /// each with the byte offset of the channel within all channels of a pixel.
+    fn create_recursive_reader(&self, channels: &[(usize, ChannelDescription)]) -> Result<Self::RecursivePixelReader>;
 
     /// Plan to read an additional channel from the image, with the specified name.
     /// If the channel cannot be found in the image when the image is read, the image will not be loaded.
@@ -66,7 +70,7 @@ pub trait ReadSpecificChannel: Sized + CheckDuplicates {
             ) -> PixelStorage,
             SetPixel: Fn(&mut PixelStorage, Vec2<usize>, Pixel),
     {
-        CollectPixels { read_channels: self, set_pixel, create_pixels, px: Default::default() }
+        CollectPixels { read_channels: self, set_pixel, create_pixels, view: None, px: Default::default() }
     }
 }
 
@@ -111,14 +115,26 @@ pub struct ReadRequiredChannel<ReadChannels, Sample> {
 }
 
 /// Specifies how to collect all the specified channels into a number of individual pixels.
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct CollectPixels<ReadChannels, Pixel, PixelStorage, CreatePixels, SetPixel> {
     read_channels: ReadChannels,
-    create_pixels: CreatePixels,
-    set_pixel: SetPixel,
+    pub(crate) create_pixels: CreatePixels,
+    pub(crate) set_pixel: SetPixel,
+    view: Option<Text>,
     px: PhantomData<(Pixel, PixelStorage)>,
 }
 
+impl<ReadChannels, Pixel, PixelStorage, CreatePixels, SetPixel> CollectPixels<ReadChannels, Pixel, PixelStorage, CreatePixels, SetPixel> {
+
+    /// Read the channels of the specified view of a multi-view layer, for example `"right"`.
+    /// The view is removed from the channel names, such that the channel `right.R` is read as `R`.
+    /// The channels without a view prefix belong to the default view, which is the first view in the `multiView` attribute.
+    /// Layers without a `multiView` attribute, or without the specified view, cannot be read.
+    pub fn view(self, view_name: impl Into<Text>) -> Self {
+        Self { view: Some(view_name.into()), ..self }
+    }
+}
+
 impl<Inner: CheckDuplicates, Sample> CheckDuplicates for ReadRequiredChannel<Inner, Sample> {
     fn already_contains(&self, name: &Text) -> bool {
         &self.channel_name == name || self.previous_channels.already_contains(name)
@@ -147,9 +163,26 @@ ReadChannels<'s> for CollectPixels<InnerChannels, Pixel, PixelStorage, CreatePix
     >;
 
     fn create_channels_reader(&'s self, header: &Header) -> Result<Self::Reader> {
-        if header.deep { return Err(Error::invalid("`SpecificChannels` does not support deep data yet")) }
+        if header.deep { return Err(Error::unsupported("`SpecificChannels` does not support deep data yet")) }
+        if header.channels.is_subsampled() { return Err(Error::unsupported("`SpecificChannels` does not support subsampled channels yet")) }
 
-        let pixel_reader = self.read_channels.create_recursive_reader(&header.channels)?;
+        let channels: Vec<(usize, ChannelDescription)> = match self.view {
+            None => header.channels.channels_with_byte_offset()
+                .map(|(byte_offset, channel)| (byte_offset, channel.clone())).collect(),
+
+            Some(ref view) => {
+                let view_names = header.own_attributes.multi_view_names.as_deref()
+                    .ok_or_else(|| Error::invalid("layer does not contain multiple views"))?;
+
+                if !view_names.contains(view) {
+                    return Err(Error::invalid(format!("layer does not contain the view `{}`", view)));
+                }
+
+                header.channels.channels_of_view(view_names, view).collect()
+            },
+        };
+
+        let pixel_reader = self.read_channels.create_recursive_reader(&channels)?;
         let channel_descriptions = pixel_reader.get_descriptions().into_non_recursive();// TODO not call this twice
 
         let create = &self.create_pixels;
@@ -215,7 +248,7 @@ pub type ReadZeroChannels = NoneMore;
 
 impl ReadSpecificChannel for NoneMore {
     type RecursivePixelReader = NoneMore;
-    fn create_recursive_reader(&self, _: &ChannelList) -> Result<Self::RecursivePixelReader> { Ok(NoneMore) }
+    fn create_recursive_reader(&self, _: &[(usize, ChannelDescription)]) -> Result<Self::RecursivePixelReader> { Ok(NoneMore) }
 }
 
 impl<DefaultSample, ReadChannels> ReadSpecificChannel for ReadOptionalChannel<ReadChannels, DefaultSample>
@@ -223,13 +256,13 @@ impl<DefaultSample, ReadChannels> ReadSpecificChannel for ReadOptionalChannel<Re
 {
     type RecursivePixelReader = Recursive<ReadChannels::RecursivePixelReader, OptionalSampleReader<DefaultSample>>;
 
-    fn create_recursive_reader(&self, channels: &ChannelList) -> Result<Self::RecursivePixelReader> {
+    fn create_recursive_reader(&self, channels: &[(usize, ChannelDescription)]) -> Result<Self::RecursivePixelReader> {
         debug_assert!(self.previous_channels.already_contains(&self.channel_name).not(), "duplicate channel name: {}", self.channel_name);
 
         let inner_samples_reader = self.previous_channels.create_recursive_reader(channels)?;
-        let reader = channels.channels_with_byte_offset()
+        let reader = channels.iter()
             .find(|(_, channel)| channel.name == self.channel_name)
-            .map(|(channel_byte_offset, channel)| SampleReader {
+            .map(|&(channel_byte_offset, ref channel)| SampleReader {
                 channel_byte_offset, channel: channel.clone(),
                 px: Default::default()
             });
@@ -245,9 +278,9 @@ impl<Sample, ReadChannels> ReadSpecificChannel for ReadRequiredChannel<ReadChann
 {
     type RecursivePixelReader = Recursive<ReadChannels::RecursivePixelReader, SampleReader<Sample>>;
 
-    fn create_recursive_reader(&self, channels: &ChannelList) -> Result<Self::RecursivePixelReader> {
+    fn create_recursive_reader(&self, channels: &[(usize, ChannelDescription)]) -> Result<Self::RecursivePixelReader> {
         let previous_samples_reader = self.previous_channels.create_recursive_reader(channels)?;
-        let (channel_byte_offset, channel) = channels.channels_with_byte_offset()
+        let &(channel_byte_offset, ref channel) = channels.iter()
                 .find(|(_, channel)| channel.name == self.channel_name)
                 .ok_or_else(|| Error::invalid(format!(
                     "layer does not contain all of your specified channels (`{}` is missing)",
@@ -258,6 +291,53 @@ impl<Sample, ReadChannels> ReadSpecificChannel for ReadRequiredChannel<ReadChann
     }
 }
 
+/// Lists the channels that have been declared using `required` and `optional`,
+/// such that they can be read into separate vectors instead of pixels.
+/// Only implemented where all channels are declared with `f32` samples.
+pub trait DeclaredChannels: Sized {
+
+    /// The names of all channels declared so far, in the order of declaration.
+    /// Optional channels also contain their default sample.
+    fn declared_channels(&self) -> Vec<(Text, Option<f32>)>;
+
+    /// Instead of collecting pixels, store each channel in a separate vector of `f32` samples.
+    /// The resulting channels will be a `PlanarChannels` struct,
+    /// containing the channels in the order in which they were declared.
+    /// Optional channels that are not contained in the file are filled with the default sample.
+    fn planar(self) -> ReadPlanarChannels<Self> {
+        ReadPlanarChannels { read_channels: self }
+    }
+
+    /// Instead of allocating a pixel storage, write the samples into the specified buffer.
+    /// The samples of each pixel are interleaved, in the order in which the channels were declared.
+    /// The buffer must contain exactly `resolution.area() * channel_count` samples,
+    /// and can only be used for a single layer. The buffer is returned as the pixels of the resulting channels.
+    /// Optional channels that are not contained in the file are filled with the default sample.
+    fn collect_pixels_into(self, buffer: &mut [f32]) -> ReadIntoBuffer<'_, Self> {
+        ReadIntoBuffer::new(self, buffer)
+    }
+}
+
+impl DeclaredChannels for NoneMore {
+    fn declared_channels(&self) -> Vec<(Text, Option<f32>)> { Vec::new() }
+}
+
+impl<Inner: DeclaredChannels> DeclaredChannels for ReadRequiredChannel<Inner, f32> {
+    fn declared_channels(&self) -> Vec<(Text, Option<f32>)> {
+        let mut channels = self.previous_channels.declared_channels();
+        channels.push((self.channel_name.clone(), None));
+        channels
+    }
+}
+
+impl<Inner: DeclaredChannels> DeclaredChannels for ReadOptionalChannel<Inner, f32> {
+    fn declared_channels(&self) -> Vec<(Text, Option<f32>)> {
+        let mut channels = self.previous_channels.declared_channels();
+        channels.push((self.channel_name.clone(), Some(self.default_sample)));
+        channels
+    }
+}
+
 /// Reader for a single channel. Generic over the concrete sample type (f16, f32, u32).
 #[derive(Clone, Debug)]
 pub struct SampleReader<Sample> {
@@ -278,7 +358,17 @@ pub struct OptionalSampleReader<DefaultSample> {
 }
 
 impl<Sample: FromNativeSample> SampleReader<Sample> {
-    fn read_own_samples<'s, FullPixel>(
+
+    /// Read the channel that starts at the specified byte offset within all channels of a pixel.
+    pub(crate) fn new(channel_byte_offset: usize, channel: ChannelDescription) -> Self {
+        SampleReader { channel_byte_offset, channel, px: Default::default() }
+    }
+
+    /// The description of the channel in the file.
+    pub(crate) fn channel(&self) -> &ChannelDescription { &self.channel }
+
+    /// Read the samples of this channel for one line of pixels, which contains the samples of all channels.
+    pub(crate) fn read_own_samples<'s, FullPixel>(
         &self, bytes: &'s[u8], pixels: &mut [FullPixel],
         get_sample: impl Fn(&mut FullPixel) -> &mut Sample
     ){
@@ -311,6 +401,36 @@ impl<Sample: FromNativeSample> SampleReader<Sample> {
 }
 
 
+impl<Sample: FromNativeSample> OptionalSampleReader<Sample> {
+
+    /// Read the channel if it was found in the file, or otherwise return the default sample for each pixel.
+    pub(crate) fn new(reader: Option<SampleReader<Sample>>, default_sample: Sample) -> Self {
+        OptionalSampleReader { reader, default_sample }
+    }
+
+    /// The description of the channel in the file, if the channel was found.
+    pub(crate) fn channel(&self) -> Option<&ChannelDescription> {
+        self.reader.as_ref().map(SampleReader::channel)
+    }
+
+    /// Read the samples of this channel for one line of pixels.
+    pub(crate) fn read_samples<'s, FullPixel>(
+        &self, bytes: &'s[u8], pixels: &mut [FullPixel],
+        get_sample: impl Fn(&mut FullPixel) -> &mut Sample
+    ){
+        if let Some(reader) = &self.reader {
+            reader.read_own_samples(bytes, pixels, get_sample);
+        }
+        else {
+            // if this channel is optional and was not found in the file, fill the default sample
+            for pixel in pixels.iter_mut() {
+                *get_sample(pixel) = self.default_sample;
+            }
+        }
+    }
+}
+
+
 /// Does the same as `convert_batch(in_bytes.chunks().map(From::from_bytes))`, but vectorized.
 /// Reads the samples for one line, using the sample type specified in the file,
 /// and then converts those to the desired sample types.
@@ -437,7 +557,7 @@ for Recursive<InnerReader, OptionalSampleReader<Sample>>
 {
     type RecursiveChannelDescriptions = Recursive<InnerReader::RecursiveChannelDescriptions, Option<ChannelDescription>>;
     fn get_descriptions(&self) -> Self::RecursiveChannelDescriptions { Recursive::new(
-        self.inner.get_descriptions(), self.value.reader.as_ref().map(|reader| reader.channel.clone())
+        self.inner.get_descriptions(), self.value.channel().cloned()
     ) }
 
     type RecursivePixel = Recursive<InnerReader::RecursivePixel, Sample>;
@@ -446,16 +566,7 @@ for Recursive<InnerReader, OptionalSampleReader<Sample>>
         &self, bytes: &'s[u8], pixels: &mut [FullPixel],
         get_pixel: impl Fn(&mut FullPixel) -> &mut Self::RecursivePixel
     ) {
-        if let Some(reader) = &self.value.reader {
-            reader.read_own_samples(bytes, pixels, |px| &mut get_pixel(px).value);
-        }
-        else {
-            // if this channel is optional and was not found in the file, fill the default sample
-            for pixel in pixels.iter_mut() {
-                get_pixel(pixel).value = self.value.default_sample;
-            }
-        }
-
+        self.value.read_samples(bytes, pixels, |px| &mut get_pixel(px).value);
         self.inner.read_pixels(bytes, pixels, |px| &mut get_pixel(px).inner);
     }
 }
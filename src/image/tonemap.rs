@@ -0,0 +1,160 @@
+//! Convert linear rgba pixels to 8-bit sRGB values, for example to display a preview of an image.
+//! Follows the steps of the `exrdisplay` program of the reference implementation:
+//! apply the exposure, optionally compress the highlights with a logarithmic knee,
+//! and then encode the result for display, using the sRGB transfer function instead of a plain gamma.
+//!
+//! Values that cannot be displayed are clamped, such that negative values become `0`,
+//! values larger than one become `255`, and `NaN` becomes `0`. Alpha is not encoded, but only clamped.
+
+use crate::image::{Image, Layer, SpecificChannels};
+use crate::image::pixel_vec::PixelVec;
+
+
+/// A linear pixel with red, green, blue, and alpha values.
+pub type LinearRgba = (f32, f32, f32, f32);
+
+/// Compresses the highlights of an image, instead of clipping them,
+/// like the knee of the `exrdisplay` program.
+/// Values below `low` are not changed. Values between `low` and `high`
+/// are compressed logarithmically into the range from `low` to `1.0`.
+/// Values above `high` will still be clipped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Knee {
+    low: f32,
+    factor: f32,
+}
+
+impl Knee {
+
+    /// Compress the linear values from `low` to `high` into the range from `low` to `1.0`.
+    /// Panics if `low` is not between zero and one, or if `high` is not larger than one.
+    pub fn new(low: f32, high: f32) -> Self {
+        assert!(low >= 0.0 && low < 1.0, "knee low value must be at least zero and less than one");
+        assert!(high > 1.0, "knee high value must be larger than one");
+
+        // search the factor which maps `high` to one, using bisection, like `findKneeF` in `exrdisplay`
+        let (x, y) = (high - low, 1.0 - low);
+        let (mut f0, mut f1) = (0.0_f32, 1.0_f32);
+
+        while knee(x, f1) > y {
+            f0 = f1;
+            f1 *= 2.0;
+        }
+
+        for _ in 0 .. 30 {
+            let f2 = (f0 + f1) * 0.5;
+            if knee(x, f2) < y { f1 = f2 } else { f0 = f2 }
+        }
+
+        Knee { low, factor: (f0 + f1) * 0.5 }
+    }
+
+    /// Compress a single linear value.
+    pub fn apply(&self, value: f32) -> f32 {
+        if value > self.low { self.low + knee(value - self.low, self.factor) }
+        else { value }
+    }
+}
+
+/// The logarithmic curve of the knee.
+fn knee(value: f32, factor: f32) -> f32 {
+    (value * factor).ln_1p() / factor
+}
+
+/// Convert all pixels to sRGB bytes, in the same order as the pixels.
+/// The exposure is specified in stops, such that `1.0` doubles the brightness.
+pub fn to_srgb_u8(pixels: &PixelVec<LinearRgba>, exposure: f32, knee: Option<Knee>) -> Vec<[u8; 4]> {
+    let mut bytes = vec![[0; 4]; pixels.pixels.len()];
+    line_to_srgb_u8(&pixels.pixels, exposure, knee, &mut bytes);
+    bytes
+}
+
+/// Convert a number of pixels to sRGB bytes, for example a single line.
+/// This allows converting an image while reading it, without storing all linear pixels.
+/// The exposure is specified in stops, such that `1.0` doubles the brightness.
+/// Panics if the output does not have the same length as the pixels.
+pub fn line_to_srgb_u8(pixels: &[LinearRgba], exposure: f32, knee: Option<Knee>, output: &mut [[u8; 4]]) {
+    assert_eq!(pixels.len(), output.len(), "output length must match the pixel count");
+
+    let exposure = exposure.exp2();
+    let color_to_byte = |value: f32| {
+        let value = value * exposure;
+        let value = knee.map_or(value, |knee| knee.apply(value));
+        unit_to_byte(encode_srgb(value))
+    };
+
+    for (&(r, g, b, a), output) in pixels.iter().zip(output.iter_mut()) {
+        *output = [color_to_byte(r), color_to_byte(g), color_to_byte(b), unit_to_byte(a)];
+    }
+}
+
+/// Apply the sRGB transfer function to a linear value.
+/// Values outside of the displayable range are not clamped.
+fn encode_srgb(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 { linear * 12.92 }
+    else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// Clamp the value to the range from zero to one, and round it to a byte. `NaN` becomes zero.
+fn unit_to_byte(value: f32) -> u8 {
+    // `max` returns the other value if one of the values is `NaN`
+    (value.max(0.0).min(1.0) * 255.0 + 0.5) as u8
+}
+
+impl<Channels> Image<Layer<SpecificChannels<PixelVec<LinearRgba>, Channels>>> {
+
+    /// Convert all pixels of this image to sRGB bytes, for example to display a preview.
+    /// The exposure is specified in stops, such that `1.0` doubles the brightness.
+    /// See `tonemap::to_srgb_u8` for more information.
+    pub fn tonemap_to_u8(&self, exposure: f32, knee: Option<Knee>) -> Vec<[u8; 4]> {
+        to_srgb_u8(&self.layer_data.channel_data.pixels, exposure, knee)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::math::Vec2;
+
+    fn convert(pixel: LinearRgba, exposure: f32, knee: Option<Knee>) -> [u8; 4] {
+        let mut output = [[0; 4]];
+        line_to_srgb_u8(&[pixel], exposure, knee, &mut output);
+        output[0]
+    }
+
+    #[test]
+    fn pinned_srgb_values() {
+        assert_eq!(convert((0.0, 1.0, 0.5, 0.5), 0.0, None), [0, 255, 188, 128]);
+        assert_eq!(convert((0.18, 0.002, 0.214, 1.0), 0.0, None), [118, 7, 127, 255]);
+
+        // negative, not a number, and too bright
+        assert_eq!(convert((-0.5, f32::NAN, 2.0, f32::NAN), 0.0, None), [0, 0, 255, 0]);
+        assert_eq!(convert((f32::INFINITY, f32::NEG_INFINITY, 1.5, 2.0), 0.0, None), [255, 0, 255, 255]);
+
+        // exposure does not change the alpha
+        assert_eq!(convert((0.25, 0.09, 1.0, 0.5), 1.0, None), [188, 118, 255, 128]);
+        assert_eq!(convert((1.0, 0.72, 0.36, 0.5), -2.0, None), [137, 118, 85, 128]);
+    }
+
+    #[test]
+    fn knee_compresses_highlights() {
+        let knee = Knee::new(0.5, 4.0);
+
+        assert_eq!(knee.apply(0.25), 0.25);
+        assert!((knee.apply(4.0) - 1.0).abs() < 1e-5);
+        assert!(knee.apply(0.6) < 0.6 && knee.apply(2.0) < knee.apply(3.0));
+
+        assert_eq!(convert((0.18, 4.0, 8.0, 1.0), 0.0, Some(knee)), [118, 255, 255, 255]);
+        assert_eq!(convert((2.0, 1.0, f32::NAN, 1.0), 0.0, Some(knee)), [240, 221, 0, 255]);
+    }
+
+    #[test]
+    fn image_to_srgb_u8() {
+        let pixels = PixelVec::new(Vec2(2, 1), vec![(0.5, 0.5, 0.5, 1.0), (0.0, 0.0, 0.0, 0.0)]);
+        let image = Image::from_channels(Vec2(2, 1), SpecificChannels::rgba(pixels.clone()));
+
+        assert_eq!(image.tonemap_to_u8(0.0, None), vec![[188, 188, 188, 255], [0, 0, 0, 0]]);
+        assert_eq!(image.tonemap_to_u8(0.0, None), to_srgb_u8(&pixels, 0.0, None));
+    }
+}
@@ -110,6 +110,61 @@ impl<Samples> ChannelsWriter for AnyChannelsWriter<Samples> where Samples: Sampl
 
 
 
+impl<'channels> WritableChannels<'channels> for PlanarChannels {
+    fn infer_channel_list(&self) -> ChannelList {
+        let mut vec: SmallVec<[ChannelDescription; 5]> = self.channels.iter()
+            .map(|(name, _)| ChannelDescription::named(name.clone(), SampleType::F32))
+            .collect();
+
+        vec.sort_unstable_by_key(|channel| channel.name.clone());
+
+        debug_assert!(
+            vec.iter().zip(vec.iter().skip(1)).all(|(prev, next)| prev.name != next.name),
+            "planar channels contain duplicate channel names"
+        );
+
+        ChannelList::new(vec)
+    }
+
+    fn infer_level_modes(&self) -> (LevelMode, RoundingMode) {
+        (LevelMode::Singular, RoundingMode::Down) // TODO levels?
+    }
+
+    type Writer = PlanarChannelsWriter<'channels>;
+    fn create_writer(&'channels self, header: &Header) -> Self::Writer {
+        let sample_count = header.layer_size.area();
+
+        // find the samples for each channel in the order of the file
+        let channels = header.channels.list.iter().map(|channel| {
+            let (_, samples) = self.channels.iter().find(|(name, _)| name == &channel.name)
+                .expect("planar channels do not match the channel list of the header");
+
+            assert_eq!(samples.len(), sample_count, "the channel `{}` does not contain one sample per pixel", channel.name);
+            samples.as_slice()
+        }).collect();
+
+        PlanarChannelsWriter { channels }
+    }
+}
+
+/// A temporary writer for a list of planar channels
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanarChannelsWriter<'channels> {
+    channels: SmallVec<[&'channels [f32]; 4]>,
+}
+
+impl ChannelsWriter for PlanarChannelsWriter<'_> {
+    fn extract_uncompressed_block(&self, header: &Header, block_index: BlockIndex) -> Vec<u8> {
+        let image_width = header.layer_size.width();
+
+        UncompressedBlock::collect_block_data_from_lines(&header.channels, block_index, |line| {
+            let start_index = line.location.position.y() * image_width + line.location.position.x();
+            let samples = &self.channels[line.location.channel][start_index .. start_index + line.location.sample_count];
+            line.write_samples_from_slice(samples).expect("writing line bytes failed");
+        })
+    }
+}
+
 impl<'c, Channels, Storage>
 WritableChannels<'c> for SpecificChannels<Storage, Channels>
 where
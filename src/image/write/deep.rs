@@ -0,0 +1,187 @@
+//! How to write deep samples, where each pixel contains any number of samples.
+//! Call `my_deep_image.write()` to obtain the writer.
+
+use crate::image::*;
+use crate::meta::{Headers, BlockDescription, compute_chunk_count};
+use crate::meta::header::Header;
+use crate::meta::attribute::{ChannelDescription, ChannelList, TileDescription, LevelMode};
+use crate::math::RoundingMode;
+use crate::error::{Error, Result, UnitResult};
+use crate::block::BlockIndex;
+use crate::block::deep::{DeepUncompressedBlock, DeepChannelSamples};
+use crate::block::writer::ChunksWriter;
+use std::io::{Write, Seek, BufWriter};
+
+
+impl Image<Layers<AnyChannels<DeepSamples>>> {
+
+    /// Create a temporary writer which can be configured and used to write the deep image to a file.
+    /// Each layer is written as deep scan lines or deep tiles, depending on the blocks of its encoding.
+    pub fn write(&self) -> WriteDeepImageWithOptions<'_> {
+        WriteDeepImageWithOptions {
+            image: self,
+            check_compatibility: true,
+            zip_compression_level: None,
+        }
+    }
+}
+
+/// A temporary writer which can be configured and used to write a deep image to a file.
+/// Compresses all blocks sequentially. Each layer must be named,
+/// and must be compressed using `RLE`, `ZIP1` or `ZIP16`, or not at all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteDeepImageWithOptions<'img> {
+    image: &'img DeepImage,
+    check_compatibility: bool,
+    zip_compression_level: Option<u8>,
+}
+
+impl<'img> WriteDeepImageWithOptions<'img> {
+
+    /// Generate file meta data for this image. The meta data structure is close to the data in the file.
+    /// The maximum number of samples per pixel is computed from the samples of each layer.
+    pub fn infer_meta_data(&self) -> Headers {
+        self.image.layer_data.iter()
+            .map(|layer| {
+                let mut header = infer_header(layer, &self.image.attributes);
+                header.zip_compression_level = self.zip_compression_level;
+                header
+            })
+            .collect()
+    }
+
+    /// Set the deflate level used when writing `ZIP1` or `ZIP16` compressed layers,
+    /// from 0 (fastest) to 9 (smallest). Does not affect how the samples are read.
+    pub fn zip_compression_level(self, level: u8) -> Self {
+        Self { zip_compression_level: Some(level), ..self }
+    }
+
+    /// Do not check whether other exr readers would be able to read the file.
+    pub fn skip_compatibility_checks(self) -> Self { Self { check_compatibility: false, ..self } }
+
+    /// Write the deep exr image to a file.
+    /// Use `to_unbuffered` instead, if you do not have a file.
+    /// If an error occurs, attempts to delete the partially written file.
+    #[inline]
+    #[must_use]
+    pub fn to_file(self, path: impl AsRef<std::path::Path>) -> UnitResult {
+        crate::io::attempt_delete_file_on_write_error(path.as_ref(), move |write|
+            self.to_unbuffered(write)
+        )
+    }
+
+    /// Buffer the writer and then write the deep exr image to it.
+    /// Use `to_buffered` instead, if your writer is an in-memory buffer.
+    /// Use `to_file` instead, if you have a file path.
+    #[inline]
+    #[must_use]
+    pub fn to_unbuffered(self, unbuffered: impl Write + Seek) -> UnitResult {
+        self.to_buffered(BufWriter::new(unbuffered))
+    }
+
+    /// Write the deep exr image to a writer.
+    /// Use `to_file` instead, if you have a file path.
+    /// Use `to_unbuffered` instead, if this is not an in-memory writer.
+    #[must_use]
+    pub fn to_buffered(self, write: impl Write + Seek) -> UnitResult {
+        let headers = self.infer_meta_data();
+        let layers = &self.image.layer_data;
+
+        crate::block::write(
+            write, headers, self.check_compatibility,
+            move |meta, chunk_writer| {
+                for (index_in_header_increasing_y, block_index) in meta.enumerate_ordered_header_block_indices() {
+                    let block = extract_block(&layers[block_index.layer], block_index)?;
+                    chunk_writer.write_chunk(index_in_header_increasing_y, block.compress_to_chunk(&meta.headers)?)?;
+                }
+
+                Ok(())
+            }
+        )
+    }
+}
+
+/// Create the header of a single deep layer, with a single resolution level.
+fn infer_header(layer: &Layer<AnyChannels<DeepSamples>>, image_attributes: &ImageAttributes) -> Header {
+    let blocks = match layer.encoding.blocks {
+        Blocks::ScanLines => BlockDescription::ScanLines,
+        Blocks::Tiles(tile_size) => BlockDescription::Tiles(TileDescription {
+            tile_size, level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down
+        }),
+    };
+
+    let channels = ChannelList::new(
+        layer.channel_data.list.iter()
+            .map(|channel| ChannelDescription {
+                name: channel.name.clone(),
+                sample_type: channel.sample_data.sample_type(),
+                quantize_linearly: channel.quantize_linearly,
+                sampling: channel.sampling,
+            })
+            .collect()
+    );
+
+    let max_samples_per_pixel = layer.channel_data.list.iter()
+        .flat_map(|channel| (0 .. channel.sample_data.len()).map(move |index| channel.sample_data.sample_count_by_flat_index(index)))
+        .max().unwrap_or(0);
+
+    Header {
+        channels,
+        compression: layer.encoding.compression,
+        zip_compression_level: None,
+        custom_codecs: Default::default(),
+
+        blocks,
+        chunk_count: compute_chunk_count(layer.encoding.compression, layer.size, blocks),
+
+        line_order: layer.encoding.line_order,
+        layer_size: layer.size,
+        shared_attributes: image_attributes.clone(),
+        own_attributes: layer.attributes.clone(),
+
+        deep: true,
+        deep_data_version: Some(1),
+        max_samples_per_pixel: Some(max_samples_per_pixel),
+    }
+}
+
+/// Collect the samples of all pixels in the block.
+/// All channels of a pixel must contain the same number of samples.
+fn extract_block(layer: &Layer<AnyChannels<DeepSamples>>, index: BlockIndex) -> Result<DeepUncompressedBlock> {
+    let layer_width = layer.size.width();
+    let pixel_indices = (0 .. index.pixel_size.height()).flat_map(|y| {
+        let line_start = (index.pixel_position.y() + y) * layer_width + index.pixel_position.x();
+        line_start .. line_start + index.pixel_size.width()
+    });
+
+    let pixel_count = layer.size.area();
+    if layer.channel_data.list.iter().any(|channel| channel.sample_data.len() != pixel_count) {
+        return Err(Error::invalid("deep channel pixel count does not match the layer size"));
+    }
+
+    let sample_counts = pixel_indices.clone()
+        .map(|pixel_index| {
+            let mut channel_counts = layer.channel_data.list.iter()
+                .map(|channel| channel.sample_data.sample_count_by_flat_index(pixel_index));
+
+            let count = channel_counts.next().unwrap_or(0);
+
+            if channel_counts.all(|channel_count| channel_count == count) { Ok(count as u32) }
+            else { Err(Error::invalid("all deep channels of a pixel must contain the same number of samples")) }
+        })
+        .collect::<Result<Vec<u32>>>()?;
+
+    let channels = layer.channel_data.list.iter()
+        .map(|channel| {
+            let pixels = pixel_indices.clone();
+
+            match &channel.sample_data {
+                DeepSamples::F16(samples) => DeepChannelSamples::F16(pixels.flat_map(|pixel| samples[pixel].iter().copied()).collect()),
+                DeepSamples::F32(samples) => DeepChannelSamples::F32(pixels.flat_map(|pixel| samples[pixel].iter().copied()).collect()),
+                DeepSamples::U32(samples) => DeepChannelSamples::U32(pixels.flat_map(|pixel| samples[pixel].iter().copied()).collect()),
+            }
+        })
+        .collect();
+
+    Ok(DeepUncompressedBlock { index, sample_counts, channels })
+}
@@ -89,6 +89,8 @@ impl<'slf, Channels: WritableChannels<'slf>> WritableLayers<'slf> for Layer<Chan
         let header = Header {
             channels: self.channel_data.infer_channel_list(),
             compression: self.encoding.compression,
+            zip_compression_level: None,
+            custom_codecs: Default::default(),
 
             blocks,
             chunk_count,
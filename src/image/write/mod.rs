@@ -15,17 +15,23 @@
 pub mod layers;
 pub mod samples;
 pub mod channels;
+pub mod deep;
 
 
 
-use crate::meta::Headers;
-use crate::error::UnitResult;
+use crate::meta::{Headers, MetaData};
+use crate::meta::header::Header;
+use crate::meta::attribute::{Preview, Text};
+use crate::compression::{Compression, CodecRegistry, CompressionWarning};
+use crate::error::{Error, Result, UnitResult};
 use std::io::{Seek, BufWriter};
-use crate::io::Write;
+use crate::io::{Read, Write};
 use crate::image::{Image, ignore_progress, SpecificChannels, IntoSample};
 use crate::image::write::layers::{WritableLayers, LayersWriter};
 use crate::math::Vec2;
 use crate::block::writer::ChunksWriter;
+use crate::block::{BlockIndex, UncompressedBlock};
+use rayon_core::ThreadPool;
 
 /// An oversimplified function for "just write the damn file already" use cases.
 /// Have a look at the examples to see how you can write an image with more flexibility (it's not that hard).
@@ -74,19 +80,58 @@ impl<'img, WritableLayers> WritableImage<'img, WritableLayers> for &'img Image<W
             image: self,
             check_compatibility: true,
             parallel: true,
+            thread_pool: None,
+            max_pending_chunks: None,
+            verify_written_chunks: false,
+            zip_compression_level: None,
+            dwa_compression_level: None,
+            custom_codecs: None,
+            preview_size: None,
             on_progress: ignore_progress
         }
     }
 }
 
 /// A temporary writer which can be configured and used to write an image to a file.
+/// The lifetime refers to the image and an optional borrowed thread pool.
 // temporary writer with options
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct WriteImageWithOptions<'img, Layers, OnProgress> {
     image: &'img Image<Layers>,
     on_progress: OnProgress,
     check_compatibility: bool,
     parallel: bool,
+    thread_pool: Option<&'img ThreadPool>,
+    max_pending_chunks: Option<usize>,
+    verify_written_chunks: bool,
+    zip_compression_level: Option<u8>,
+    dwa_compression_level: Option<f32>,
+    custom_codecs: Option<CodecRegistry>,
+    preview_size: Option<Vec2<usize>>,
+}
+
+// thread pools are compared by identity, as they cannot be compared by value
+impl<Layers, OnProgress> PartialEq for WriteImageWithOptions<'_, Layers, OnProgress>
+    where Layers: PartialEq, OnProgress: PartialEq
+{
+    fn eq(&self, other: &Self) -> bool {
+        let same_thread_pool = match (self.thread_pool, other.thread_pool) {
+            (Some(pool), Some(other_pool)) => std::ptr::eq(pool, other_pool),
+            (pool, other_pool) => pool.is_none() && other_pool.is_none(),
+        };
+
+        self.image == other.image
+            && self.on_progress == other.on_progress
+            && self.check_compatibility == other.check_compatibility
+            && self.parallel == other.parallel
+            && self.max_pending_chunks == other.max_pending_chunks
+            && self.verify_written_chunks == other.verify_written_chunks
+            && self.zip_compression_level == other.zip_compression_level
+            && self.dwa_compression_level == other.dwa_compression_level
+            && self.custom_codecs == other.custom_codecs
+            && self.preview_size == other.preview_size
+            && same_thread_pool
+    }
 }
 
 
@@ -95,13 +140,162 @@ impl<'img, L, F> WriteImageWithOptions<'img, L, F>
 {
     /// Generate file meta data for this image. The meta data structure is close to the data in the file.
     pub fn infer_meta_data(&self) -> Headers { // TODO this should perform all validity checks? and none after that?
-        self.image.layer_data.infer_headers(&self.image.attributes)
+        let mut headers = self.infer_meta_data_without_preview();
+
+        if let Some(max_size) = self.preview_size {
+            let layers = self.image.layer_data.create_writer(&headers);
+
+            // the reason for skipping the preview can be retrieved using `preview_warning`
+            if let Some(preview) = generate_preview(&headers, &layers, max_size) {
+                headers[0].own_attributes.preview = Some(preview);
+            }
+        }
+
+        headers
+    }
+
+    /// Generate file meta data for this image, but skip generating the preview, which requires all pixels.
+    fn infer_meta_data_without_preview(&self) -> Headers {
+        let mut headers = self.image.layer_data.infer_headers(&self.image.attributes);
+
+        if let Some(level) = self.zip_compression_level {
+            for header in &mut headers { header.zip_compression_level = Some(level); }
+        }
+
+        if let Some(level) = self.dwa_compression_level {
+            for header in &mut headers {
+                header.own_attributes.dwa_compression_level = Some(level);
+
+                header.compression = match header.compression {
+                    Compression::DWAA(_) => Compression::DWAA(Some(level)),
+                    Compression::DWAB(_) => Compression::DWAB(Some(level)),
+                    other => other,
+                };
+            }
+        }
+
+        if let Some(codecs) = &self.custom_codecs {
+            for header in &mut headers { header.custom_codecs = codecs.clone(); }
+        }
+
+        headers
+    }
+
+    /// Returns an upper bound for the number of bytes that the pixels of this image
+    /// occupy when decompressed, as reported by `MetaData::estimated_decoded_bytes` after reading the file.
+    /// Does not look at the pixels.
+    pub fn estimated_decoded_bytes(&self) -> usize {
+        self.infer_meta_data_without_preview().iter()
+            .fold(0, |sum, header| sum.saturating_add(header.total_uncompressed_pixel_bytes()))
+    }
+
+    /// Returns an upper bound for the byte size of the file that will be written, including the meta data.
+    /// Use this to preallocate an in-memory buffer before writing.
+    /// Does not look at the pixels, not even to generate the preview.
+    /// Returns an error if the meta data of the image is invalid, just like writing the image would.
+    pub fn worst_case_file_bytes(&self) -> Result<usize> {
+        let mut headers = self.infer_meta_data_without_preview();
+
+        // a blank preview occupies as many bytes as the generated preview
+        if let (Some(max_size), Some(header)) = (self.preview_size, headers.first_mut()) {
+            if let Ok(size) = preview_size(header, max_size) {
+                header.own_attributes.preview = Some(Preview { size, pixel_data: vec![0; size.area() * 4] });
+            }
+        }
+
+        MetaData::complete_required_attributes(&mut headers);
+
+        let mut meta_data_bytes = Vec::new();
+        MetaData::write_validating_to_buffered(&mut meta_data_bytes, headers.as_slice(), self.check_compatibility)?;
+
+        Ok(headers.iter().fold(meta_data_bytes.len(), |sum, header| sum.saturating_add(header.worst_case_compressed_bytes())))
+    }
+
+    /// Returns a warning for each channel that would lose precision
+    /// or would not be compressed at all with the compression method of its layer,
+    /// for example an `f32` channel with `PXR24`. The image can still be written.
+    /// Does not look at the pixels.
+    pub fn compression_warnings(&self) -> Vec<CompressionWarning> {
+        self.infer_meta_data_without_preview().iter()
+            .flat_map(|header| header.compression_warnings())
+            .collect()
+    }
+
+    /// Describes why no preview will be written, if `generate_preview` was specified
+    /// but the first layer cannot be displayed as a preview,
+    /// for example because it has no red, green, and blue channels.
+    /// Returns none if the preview will be generated, or if no preview was requested.
+    /// Does not look at the pixels.
+    pub fn preview_warning(&self) -> Option<&'static str> {
+        let max_size = self.preview_size?;
+
+        match self.infer_meta_data_without_preview().first() {
+            None => Some("the image has no layers"),
+            Some(header) => preview_size(header, max_size).err(),
+        }
+    }
+
+    /// Specify the deflate level used for layers with `ZIP1` or `ZIP16` compression,
+    /// from 0 (fastest) to 9 (smallest). The level does not change how the file is read.
+    /// If not specified, level 4 is used.
+    pub fn zip_compression_level(self, level: u8) -> Self {
+        Self { zip_compression_level: Some(level), ..self }
+    }
+
+    /// Specify the quality level used for layers with `DWAA` or `DWAB` compression,
+    /// where larger values produce smaller files with less precision.
+    /// Overrides the level of the layer encoding and attributes. If not specified anywhere, level 45 is used.
+    /// The level is stored in every header, even for layers with other compression methods.
+    pub fn dwa_compression_level(self, level: f32) -> Self {
+        Self { dwa_compression_level: Some(level), ..self }
+    }
+
+    /// Specify the codecs used for layers with `Compression::Custom`.
+    /// __Custom compression methods are not part of the OpenEXR standard__,
+    /// so no other software will be able to read the pixels of these layers.
+    pub fn custom_codecs(self, codecs: CodecRegistry) -> Self {
+        Self { custom_codecs: Some(codecs), ..self }
+    }
+
+    /// Generate the `preview` attribute of the first layer from its pixels, replacing any existing preview.
+    /// The preview is a downsampled 8-bit rgba thumbnail that fits into the specified maximum size,
+    /// which file browsers and render managers can display without reading the pixels.
+    /// If the first layer has no red, green, and blue channels at full resolution,
+    /// or contains deep data, no preview is generated, and `preview_warning` returns the reason.
+    /// Extracts the pixels of the first layer once more before writing.
+    pub fn generate_preview(self, max_size: impl Into<Vec2<usize>>) -> Self {
+        Self { preview_size: Some(max_size.into()), ..self }
     }
 
     /// Do not compress multiple pixel blocks on multiple threads at once.
     /// Might use less memory and synchronization, but will be slower in most situations.
     pub fn non_parallel(self) -> Self { Self { parallel: false, ..self } }
 
+    /// Specify that multiple pixel blocks should be compressed using the threads of an existing pool.
+    /// The pool is only borrowed, so a single pool can be shared by many concurrent writes,
+    /// and no additional threads will be created while writing.
+    /// Without this option, a new thread pool is created for each image.
+    pub fn parallel_with_pool(self, thread_pool: &'img ThreadPool) -> Self {
+        Self { parallel: true, thread_pool: Some(thread_pool), ..self }
+    }
+
+    /// Specify how many compressed pixel blocks may wait to be written at the same time when writing in parallel.
+    /// When this many blocks are pending, no more blocks are compressed until the next block has been written.
+    /// A smaller number reduces peak memory usage, while a larger number keeps more cores busy.
+    /// By default, this is two more than the number of threads.
+    /// The value is clamped to at least one.
+    pub fn max_pending_chunks(self, count: usize) -> Self {
+        Self { max_pending_chunks: Some(count), ..self }
+    }
+
+    /// Decompress each pixel block right after compressing it, and fail if the pixels differ from the original pixels.
+    /// Proves that the written file contains your pixels, without reading the whole file again afterwards.
+    /// Compression methods that may lose data, such as `B44` or `PXR24`, are not verified.
+    /// Compressing takes roughly twice as long with this option. Disabled by default.
+    pub fn verify_written_chunks(self, verify: bool) -> Self {
+        Self { verify_written_chunks: verify, ..self }
+    }
+
     /// Skip some checks that ensure a file can be opened by other exr software.
     /// For example, it is no longer checked that no two headers or two attributes have the same name,
     /// which might be an expensive check for images with an exorbitant number of headers.
@@ -121,7 +315,14 @@ impl<'img, L, F> WriteImageWithOptions<'img, L, F>
             on_progress,
             image: self.image,
             check_compatibility: self.check_compatibility,
-            parallel: self.parallel
+            parallel: self.parallel,
+            thread_pool: self.thread_pool,
+            max_pending_chunks: self.max_pending_chunks,
+            verify_written_chunks: self.verify_written_chunks,
+            zip_compression_level: self.zip_compression_level,
+            dwa_compression_level: self.dwa_compression_level,
+            custom_codecs: self.custom_codecs,
+            preview_size: self.preview_size,
         }
     }
 
@@ -136,10 +337,21 @@ impl<'img, L, F> WriteImageWithOptions<'img, L, F>
         )
     }
 
+    /// Write the exr image to a file, without ever leaving a corrupt file at the path.
+    /// Writes to a temporary file in the same directory first,
+    /// which then replaces the file at the path only if writing succeeded.
+    /// If an error occurs, any previous file at the path is left untouched.
+    #[must_use]
+    pub fn to_file_atomic(self, path: impl AsRef<std::path::Path>) -> UnitResult {
+        crate::io::write_file_atomically(path.as_ref(), move |file|
+            self.to_unbuffered(file)
+        )
+    }
+
     /// Buffer the writer and then write the exr image to it.
     /// Use `to_buffered` instead, if your writer is an in-memory buffer.
     /// Use `to_file` instead, if you have a file path.
-    /// If your writer cannot seek, you can write to an in-memory vector of bytes first, using `to_buffered`.
+    /// If your writer cannot seek, use `to_stream` instead.
     #[inline]
     #[must_use]
     pub fn to_unbuffered(self, unbuffered: impl Write + Seek) -> UnitResult {
@@ -149,10 +361,11 @@ impl<'img, L, F> WriteImageWithOptions<'img, L, F>
     /// Write the exr image to a writer.
     /// Use `to_file` instead, if you have a file path.
     /// Use `to_unbuffered` instead, if this is not an in-memory writer.
-    /// If your writer cannot seek, you can write to an in-memory vector of bytes first.
+    /// If your writer cannot seek, use `to_stream` instead.
     #[must_use]
     pub fn to_buffered(self, write: impl Write + Seek) -> UnitResult {
         let headers = self.infer_meta_data();
+        validate_full_resolution(&headers)?;
         let layers = self.image.layer_data.create_writer(&headers);
 
         crate::block::write(
@@ -164,8 +377,7 @@ impl<'img, L, F> WriteImageWithOptions<'img, L, F>
                 );
 
                 let chunk_writer = chunk_writer.on_progress(self.on_progress);
-                if self.parallel { chunk_writer.compress_all_blocks_parallel(&meta, blocks)?; }
-                else { chunk_writer.compress_all_blocks_sequential(&meta, blocks)?; }
+                compress_all_blocks(chunk_writer, &meta, blocks, self.parallel, self.thread_pool, self.max_pending_chunks, self.verify_written_chunks)?;
                 /*let blocks_writer = chunk_writer.as_blocks_writer(&meta);
 
                 // TODO propagate send requirement further upwards
@@ -180,5 +392,235 @@ impl<'img, L, F> WriteImageWithOptions<'img, L, F>
             }
         )
     }
+
+    /// Write the exr image to a byte destination that cannot seek, such as a network stream or stdout.
+    /// Compresses all pixels twice: once to compute the offset tables
+    /// that precede the pixels in the file, and once to actually write the pixels.
+    /// Only the byte sizes of the compressed blocks are kept in memory, not the whole compressed image.
+    /// Use `to_buffered` instead, if your writer can seek, as it compresses the pixels only once.
+    /// The writer should be buffered.
+    #[must_use]
+    pub fn to_stream(mut self, write: impl Write) -> UnitResult {
+        let headers = self.infer_meta_data();
+        validate_full_resolution(&headers)?;
+        let layers = self.image.layer_data.create_writer(&headers);
+        let (parallel, thread_pool, max_pending_chunks) = (self.parallel, self.thread_pool, self.max_pending_chunks);
+        let verify_written_chunks = self.verify_written_chunks;
+        let on_progress = &mut self.on_progress;
+
+        crate::block::writer::write_chunks_with_unseekable(
+            write, headers, self.check_compatibility,
+            |meta, chunk_writer|{
+
+                let blocks = meta.collect_ordered_block_data(|block_index|
+                     layers.extract_uncompressed_block(&meta.headers, block_index)
+                );
+
+                // only report progress while the chunks are written, not while their sizes are measured
+                let mut ignore_measuring_progress = ignore_progress;
+                let on_progress: &mut dyn FnMut(f64) =
+                    if chunk_writer.is_measuring_chunk_sizes() { &mut ignore_measuring_progress }
+                    else { &mut *on_progress };
+
+                let chunk_writer = chunk_writer.on_progress(on_progress);
+                compress_all_blocks(chunk_writer, &meta, blocks, parallel, thread_pool, max_pending_chunks, verify_written_chunks)?;
+
+                Ok(())
+            }
+        )
+    }
+
+    /// Add the layers of this image to an existing exr file, writing a new file that contains all layers.
+    /// The pixels of the existing layers are copied without being decompressed and compressed again.
+    /// The image attributes of this image are ignored, the new layers use the image attributes of the existing file.
+    /// As the meta data precedes the pixels in a file, the existing file cannot be modified in place.
+    /// Use `append_to_file` instead, if you have a file path.
+    /// The existing bytes and the writer should both be buffered.
+    #[must_use]
+    pub fn append_to_buffered(self, existing: impl Read + Seek, write: impl Write + Seek) -> UnitResult {
+        let existing = crate::block::read(existing, self.check_compatibility)?;
+        let existing_layer_count = existing.headers().len();
+
+        let shared_attributes = existing.headers().first()
+            .expect("validated meta data without headers").shared_attributes.clone();
+
+        let new_headers: Headers = self.infer_meta_data().into_iter()
+            .map(|header| header.with_shared_attributes(shared_attributes.clone()))
+            .collect();
+
+        validate_full_resolution(&new_headers)?;
+        let layers = self.image.layer_data.create_writer(&new_headers);
+
+        crate::block::writer::append_headers_with(
+            existing, write, new_headers, self.check_compatibility,
+            move |meta, chunk_writer|{
+                let new_headers = &meta.headers[existing_layer_count ..];
+
+                // the new layers follow the existing layers in the file
+                let blocks = crate::block::enumerate_ordered_header_block_indices(new_headers)
+                    .map(|(index_in_header_increasing_y, block_index)| {
+                        let data = layers.extract_uncompressed_block(new_headers, block_index);
+                        let index = BlockIndex { layer: block_index.layer + existing_layer_count, .. block_index };
+                        (index_in_header_increasing_y, UncompressedBlock { index, data })
+                    });
+
+                let chunk_writer = chunk_writer.on_progress(self.on_progress);
+                compress_all_blocks(chunk_writer, &meta, blocks, self.parallel, self.thread_pool, self.max_pending_chunks, self.verify_written_chunks)
+            }
+        )
+    }
+
+    /// Add the layers of this image to the existing exr file at the path, replacing that file.
+    /// The pixels of the existing layers are copied without being decompressed and compressed again.
+    /// The image attributes of this image are ignored, the new layers use the image attributes of the existing file.
+    /// The file is replaced only after the new file has been written successfully, see `to_file_atomic`.
+    #[must_use]
+    pub fn append_to_file(self, path: impl AsRef<std::path::Path>) -> UnitResult {
+        let existing = std::io::BufReader::new(std::fs::File::open(path.as_ref())?);
+
+        crate::io::write_file_atomically(path.as_ref(), move |file|
+            self.append_to_buffered(existing, BufWriter::new(file))
+        )
+    }
+}
+
+/// The size of the preview that is generated for the first layer, which fits into the maximum size.
+/// Returns the reason if no preview can be generated for the header.
+fn preview_size(header: &Header, max_size: Vec2<usize>) -> std::result::Result<Vec2<usize>, &'static str> {
+    let source_size = header.layer_size;
+
+    let has_channel = |name: &str| header.channels.find_index_of_channel(&Text::from(name))
+        .map_or(false, |index| header.channels.list[index].sampling == Vec2(1, 1));
+
+    if !(has_channel("R") && has_channel("G") && has_channel("B")) {
+        return Err("the first layer has no red, green, and blue channels at full resolution");
+    }
+
+    if header.deep {
+        return Err("the first layer contains deep data");
+    }
+
+    if source_size.area() == 0 || max_size.area() == 0 {
+        return Err("the first layer or the maximum preview size is empty");
+    }
+
+    // shrink the image to fit into the maximum size, but never enlarge it
+    let scale = (max_size.width() as f64 / source_size.width() as f64)
+        .min(max_size.height() as f64 / source_size.height() as f64)
+        .min(1.0);
+
+    Ok(Vec2(
+        ((source_size.width() as f64 * scale).round() as usize).max(1),
+        ((source_size.height() as f64 * scale).round() as usize).max(1),
+    ))
+}
+
+/// Downsample the rgba channels of the first layer to a preview that fits into the maximum size, using a box filter.
+/// Returns none if the first layer has no rgb channels at full resolution.
+fn generate_preview(headers: &[Header], layers: &impl LayersWriter, max_size: Vec2<usize>) -> Option<Preview> {
+    let header = headers.first()?;
+    let source_size = header.layer_size;
+    let preview_size = preview_size(header, max_size).ok()?;
+
+    let channel_index = |name: &str| header.channels.find_index_of_channel(&Text::from(name))
+        .filter(|&index| header.channels.list[index].sampling == Vec2(1, 1));
+
+    let rgb_indices = [ channel_index("R")?, channel_index("G")?, channel_index("B")? ];
+    let alpha_index = channel_index("A");
+
+    // the sum of all source samples that fall into each preview pixel, for each channel
+    let mut sums = vec![[0.0_f64; 4]; preview_size.area()];
+    let mut counts = vec![[0_usize; 4]; preview_size.area()];
+    let mut samples = Vec::new();
+
+    let first_layer_blocks = crate::block::enumerate_ordered_header_block_indices(&headers[.. 1])
+        .map(|(_, block_index)| block_index)
+        .filter(|block_index| block_index.level == Vec2(0, 0));
+
+    for block_index in first_layer_blocks {
+        let block = UncompressedBlock { index: block_index, data: layers.extract_uncompressed_block(headers, block_index) };
+
+        for line in block.lines(&header.channels) {
+            let channel = line.location.channel;
+
+            let target_channel =
+                if let Some(rgb_channel) = rgb_indices.iter().position(|&index| index == channel) { rgb_channel }
+                else if alpha_index == Some(channel) { 3 }
+                else { continue };
+
+            samples.resize(line.location.sample_count, 0.0);
+            line.read_samples_into_f32(header.channels.list[channel].sample_type, &mut samples).ok()?;
+
+            let preview_y = line.location.position.y() * preview_size.height() / source_size.height();
+            for (x, &sample) in samples.iter().enumerate() {
+                let preview_x = (line.location.position.x() + x) * preview_size.width() / source_size.width();
+                let preview_index = preview_y * preview_size.width() + preview_x;
+
+                sums[preview_index][target_channel] += sample as f64;
+                counts[preview_index][target_channel] += 1;
+            }
+        }
+    }
+
+    let average = |sums: &[f64; 4], counts: &[usize; 4], channel: usize|
+        if counts[channel] == 0 { 0.0 } else { (sums[channel] / counts[channel] as f64) as f32 };
+
+    let pixels = sums.iter().zip(&counts).map(|(sums, counts)| (
+        average(sums, counts, 0), average(sums, counts, 1), average(sums, counts, 2),
+        if alpha_index.is_some() { average(sums, counts, 3) } else { 1.0 },
+    ));
+
+    Preview::from_linear_rgba(preview_size, pixels).ok()
 }
 
+/// The layer writers extract each block at full resolution, so subsampled channels cannot be written yet.
+fn validate_full_resolution(headers: &[Header]) -> UnitResult {
+    if headers.iter().any(|header| header.channels.is_subsampled()) {
+        return Err(Error::unsupported("writing subsampled channels is not supported yet"));
+    }
+
+    Ok(())
+}
+
+/// Compress all blocks, either in this thread, on a new thread pool, or on the specified thread pool.
+fn compress_all_blocks(
+    mut chunk_writer: impl ChunksWriter, meta: &MetaData, blocks: impl Iterator<Item=(usize, UncompressedBlock)>,
+    parallel: bool, thread_pool: Option<&ThreadPool>, max_pending_chunks: Option<usize>, verify_chunks: bool
+) -> UnitResult
+{
+    if !parallel {
+        return compress_all_blocks_sequential(&mut chunk_writer, meta, blocks, verify_chunks);
+    }
+
+    let compressor = match thread_pool {
+        Some(thread_pool) => chunk_writer.parallel_blocks_compressor_with_pool(meta, thread_pool),
+        None => chunk_writer.parallel_blocks_compressor(meta),
+    };
+
+    match compressor {
+        None => compress_all_blocks_sequential(&mut chunk_writer, meta, blocks, verify_chunks),
+
+        Some(mut compressor) => {
+            if let Some(count) = max_pending_chunks {
+                compressor = compressor.max_pending_chunks(count);
+            }
+
+            compressor.verify_written_chunks(verify_chunks).compress_all_blocks(blocks)
+        }
+    }
+}
+
+/// Compress all blocks in this thread.
+fn compress_all_blocks_sequential(
+    chunk_writer: &mut impl ChunksWriter, meta: &MetaData,
+    blocks: impl Iterator<Item=(usize, UncompressedBlock)>, verify_chunks: bool
+) -> UnitResult
+{
+    let mut compressor = chunk_writer.sequential_blocks_compressor(meta).verify_written_chunks(verify_chunks);
+
+    for (index_in_header_increasing_y, block) in blocks {
+        compressor.compress_block(index_in_header_increasing_y, block)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+//! Convert rgba pixels to and from the `image` crate.
+//! Requires the `image-interop` feature.
+//!
+//! Both `PixelVec` and `image::ImageBuffer` store the rows from top to bottom,
+//! regardless of the line order in the exr file, so the pixels are converted without reordering the rows.
+
+use std::convert::TryFrom;
+use std::path::Path;
+
+use image::{ImageBuffer, Rgba, Rgba32FImage};
+
+use crate::prelude::*;
+use crate::image::pixel_vec::PixelVec;
+
+
+/// Read the first layer that contains rgba channels, from the largest resolution level,
+/// converting all samples to `f32`. The alpha channel will contain the value `1.0`
+/// if no alpha channel can be found in the image. Uses parallel decompression and relaxed error handling.
+/// Inspect the source code of this function if you need customization.
+pub fn read_rgba32f_image(path: impl AsRef<Path>) -> Result<Rgba32FImage> {
+    let image = read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .rgba_channels(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+        .first_valid_layer()
+        .all_attributes()
+        .from_file(path)?;
+
+    Rgba32FImage::try_from(image.layer_data.channel_data.pixels)
+}
+
+/// Fails if the resolution does not fit into an `u32`.
+fn image_buffer_size(resolution: Vec2<usize>) -> Result<(u32, u32)> {
+    let too_large = || Error::unsupported("image buffer larger than 4294967295 pixels in one dimension");
+    Ok((u32::try_from(resolution.width()).map_err(|_| too_large())?, u32::try_from(resolution.height()).map_err(|_| too_large())?))
+}
+
+impl TryFrom<PixelVec<(f32, f32, f32, f32)>> for ImageBuffer<Rgba<f32>, Vec<f32>> {
+    type Error = Error;
+
+    fn try_from(pixels: PixelVec<(f32, f32, f32, f32)>) -> Result<Self> {
+        let (width, height) = image_buffer_size(pixels.resolution)?;
+        let samples = pixels.pixels.into_iter().flat_map(|(r, g, b, a)| [r, g, b, a]).collect();
+        Ok(ImageBuffer::from_raw(width, height, samples).expect("pixel vec length bug"))
+    }
+}
+
+/// The alpha channel will contain the value `1.0`.
+impl TryFrom<PixelVec<(f32, f32, f32)>> for ImageBuffer<Rgba<f32>, Vec<f32>> {
+    type Error = Error;
+
+    fn try_from(pixels: PixelVec<(f32, f32, f32)>) -> Result<Self> {
+        let (width, height) = image_buffer_size(pixels.resolution)?;
+        let samples = pixels.pixels.into_iter().flat_map(|(r, g, b)| [r, g, b, 1.0]).collect();
+        Ok(ImageBuffer::from_raw(width, height, samples).expect("pixel vec length bug"))
+    }
+}
+
+impl From<ImageBuffer<Rgba<f32>, Vec<f32>>> for PixelVec<(f32, f32, f32, f32)> {
+    fn from(image: ImageBuffer<Rgba<f32>, Vec<f32>>) -> Self {
+        let resolution = Vec2(image.width() as usize, image.height() as usize);
+        let pixels = image.pixels().map(|&Rgba([r, g, b, a])| (r, g, b, a)).collect();
+        PixelVec::new(resolution, pixels)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn roundtrip_pixels_through_image_buffer() {
+        let size = Vec2(7, 3);
+        let pixels = PixelVec::new(size, (0 .. size.area())
+            .map(|index| (index as f32, index as f32 * 0.5, -(index as f32), 1.0 / (index as f32 + 1.0)))
+            .collect());
+
+        let buffer = Rgba32FImage::try_from(pixels.clone()).unwrap();
+        assert_eq!((buffer.width(), buffer.height()), (7, 3));
+
+        // the second row of the exr pixels is the second row of the buffer
+        let Rgba([r, g, b, a]) = *buffer.get_pixel(2, 1);
+        assert_eq!((r, g, b, a), pixels.pixels[7 + 2]);
+
+        assert!(PixelVec::from(buffer) == pixels);
+    }
+
+    #[test]
+    fn rgb_pixels_are_opaque() {
+        let pixels = PixelVec::new(Vec2(2, 2), vec![(0.25_f32, 0.5_f32, 0.75_f32); 4]);
+        let buffer = Rgba32FImage::try_from(pixels).unwrap();
+        assert!(buffer.pixels().all(|pixel| pixel.0 == [0.25, 0.5, 0.75, 1.0]));
+    }
+
+    #[test]
+    fn read_file_without_alpha_into_image_buffer() {
+        let size = Vec2(9, 5);
+        let pixels = PixelVec::new(size, (0 .. size.area())
+            .map(|index| (index as f32, f16::from_f32(index as f32 * 0.25), index as u32))
+            .collect::<Vec<_>>());
+
+        let encoding = Encoding { line_order: LineOrder::Decreasing, .. Encoding::FAST_LOSSLESS };
+        let image = Image::from_layer(Layer::new(size, LayerAttributes::default(), encoding, SpecificChannels::rgb(pixels.clone())));
+
+        let mut bytes = Vec::new();
+        image.write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+        let path = std::env::temp_dir().join("exr_interop_read_file_without_alpha.exr");
+        std::fs::write(&path, bytes).unwrap();
+        let buffer = read_rgba32f_image(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for (&(r, g, b), &Rgba(rgba)) in pixels.pixels.iter().zip(buffer.pixels()) {
+            assert_eq!(rgba, [r, g.to_f32(), b as f32, 1.0]);
+        }
+    }
+}
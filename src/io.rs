@@ -51,6 +51,131 @@ pub fn attempt_delete_file_on_write_error<'p>(path: &'p Path, write: impl FnOnce
     }
 }
 
+/// Write a file without ever leaving a partially written file at the path.
+/// Writes to a temporary file in the same directory, and then replaces the file at the path
+/// with the temporary file, only if writing succeeded. The temporary file is deleted if an error occurs,
+/// or if the write function panics.
+/// Any file that previously existed at the path is left untouched if an error occurs.
+pub fn write_file_atomically(path: &Path, write: impl FnOnce(&mut File) -> UnitResult) -> UnitResult {
+    let file_name = path.file_name().ok_or(Error::invalid("file path"))?;
+
+    // the same directory, such that renaming stays on the same file system
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut temporary = TemporaryFile::create_in(directory, file_name)?;
+
+    write(temporary.file())?;
+    temporary.file().sync_all()?; // make sure the contents are on the disk before the file becomes visible
+    temporary.persist(path)
+}
+
+/// A hidden file name that is unique within this process.
+/// Starts with a dot to hide it from most directory listings.
+fn temporary_file_name(name: &std::ffi::OsStr) -> std::ffi::OsString {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static TEMPORARY_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let count = TEMPORARY_FILE_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    let mut temporary_name = std::ffi::OsString::from(".");
+    temporary_name.push(name);
+    temporary_name.push(format!(".{}.{}.tmp", std::process::id(), count));
+    temporary_name
+}
+
+/// A file that is deleted when this value is dropped, for example after an error occurred
+/// or while a panic unwinds, unless the file has been persisted.
+#[derive(Debug)]
+pub struct TemporaryFile {
+    path: std::path::PathBuf,
+    file: Option<File>, // only none while dropping or persisting, as windows cannot delete or rename open files
+    persisted: bool,
+}
+
+impl TemporaryFile {
+
+    /// Create a new file with a unique name in the specified directory.
+    pub fn create_in(directory: &Path, name: impl AsRef<std::ffi::OsStr>) -> Result<Self> {
+        let path = directory.join(temporary_file_name(name.as_ref()));
+
+        let file = std::fs::OpenOptions::new()
+            .read(true).write(true).create_new(true)
+            .open(&path)?;
+
+        Ok(TemporaryFile { path, file: Some(file), persisted: false })
+    }
+
+    /// Where the file is located.
+    pub fn path(&self) -> &Path { &self.path }
+
+    /// The opened file, which can be read and written.
+    pub fn file(&mut self) -> &mut File {
+        self.file.as_mut().expect("temporary file is only closed when dropped")
+    }
+
+    /// Close the file and move it to the specified path, replacing any file at that path.
+    /// If the file cannot be moved, it is deleted, and any file at the path is left untouched.
+    pub fn persist(mut self, target: &Path) -> UnitResult {
+        drop(self.file.take()); // close the file before renaming it
+        replace_file(&self.path, target)?;
+
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            drop(self.file.take()); // close the file before deleting it
+            let _deleted = std::fs::remove_file(&self.path); // ignore deletion errors
+        }
+    }
+}
+
+/// Move the source file to the target path, replacing any file at the target path.
+/// If an error occurs, the file at the target path is left untouched.
+fn replace_file(source: &Path, target: &Path) -> UnitResult {
+    match std::fs::rename(source, target) {
+        Ok(()) => Ok(()),
+
+        // on windows, replacing fails for some existing files, for example read-only files
+        #[cfg(windows)]
+        Err(_) if target.is_file() => replace_file_using_backup(source, target),
+
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Move the existing target file to a backup path first, then move the source file to the target path.
+/// Moves the backup back to the target path if the source file cannot be moved.
+#[cfg(any(windows, test))]
+fn replace_file_using_backup(source: &Path, target: &Path) -> UnitResult {
+    let target_name = target.file_name().ok_or(Error::invalid("file path"))?;
+    let backup = target.with_file_name(temporary_file_name(target_name));
+
+    std::fs::rename(target, &backup)?;
+
+    if let Err(error) = std::fs::rename(source, target) {
+        let _restored = std::fs::rename(&backup, target); // nothing else can be done if restoring fails
+        return Err(error.into());
+    }
+
+    // read-only files cannot be deleted on windows
+    if std::fs::remove_file(&backup).is_err() {
+        if let Ok(metadata) = std::fs::metadata(&backup) {
+            let mut permissions = metadata.permissions();
+
+            #[allow(clippy::permissions_set_readonly_false)] // only affects the backup, which is deleted right away
+            permissions.set_readonly(false);
+
+            let _writable = std::fs::set_permissions(&backup, permissions);
+            let _deleted = std::fs::remove_file(&backup); // ignore deletion errors
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct LateFile<'p> {
     path: &'p Path,
@@ -156,17 +281,22 @@ impl<T: Read + Seek> PeekRead<Tracking<T>> {
 
     /// Seek this read to the specified byte position.
     /// Discards any previously peeked value.
-    pub fn skip_to(&mut self, position: usize) -> std::io::Result<()> {
+    pub fn skip_to(&mut self, position: u64) -> std::io::Result<()> {
         self.inner.seek_read_to(position)?;
         self.peeked = None;
         Ok(())
     }
+
+    /// The total number of bytes in the byte source. Does not change the current position.
+    pub fn total_byte_size(&mut self) -> std::io::Result<u64> {
+        self.inner.total_byte_size()
+    }
 }
 
 impl<T: Read> PeekRead<Tracking<T>> {
 
     /// Current number of bytes read.
-    pub fn byte_position(&self) -> usize {
+    pub fn byte_position(&self) -> u64 {
         self.inner.byte_position()
     }
 }
@@ -179,13 +309,13 @@ pub struct Tracking<T> {
     /// Do not expose to prevent seeking without updating position
     inner: T,
 
-    position: usize,
+    position: u64,
 }
 
 impl<T: Read> Read for Tracking<T> {
     fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
         let count = self.inner.read(buffer)?;
-        self.position += count;
+        self.position += count as u64;
         Ok(count)
     }
 }
@@ -193,7 +323,7 @@ impl<T: Read> Read for Tracking<T> {
 impl<T: Write> Write for Tracking<T> {
     fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
         let count = self.inner.write(buffer)?;
-        self.position += count;
+        self.position += count as u64;
         Ok(count)
     }
 
@@ -211,7 +341,8 @@ impl<T> Tracking<T> {
     }
 
     /// Current number of bytes written or read.
-    pub fn byte_position(&self) -> usize {
+    /// Is a `u64` even on 32-bit platforms, as files may be larger than the addressable memory.
+    pub fn byte_position(&self) -> u64 {
         self.position
     }
 }
@@ -220,34 +351,39 @@ impl<T: Read + Seek> Tracking<T> {
 
     /// Set the reader to the specified byte position.
     /// If it is only a couple of bytes, no seek system call is performed.
-    pub fn seek_read_to(&mut self, target_position: usize) -> std::io::Result<()> {
-        let delta = target_position as i128 - self.position as i128; // FIXME  panicked at 'attempt to subtract with overflow'
-        debug_assert!(delta.abs() < usize::MAX as i128);
+    pub fn seek_read_to(&mut self, target_position: u64) -> std::io::Result<()> {
+        let delta = i128::from(target_position) - i128::from(self.position);
 
         if delta > 0 && delta < 16 { // TODO profile that this is indeed faster than a syscall! (should be because of bufread buffer discard)
-            skip_bytes(self, delta as usize)?;
-            self.position += delta as usize;
+            skip_bytes(self, delta as usize)?; // also advances the position
         }
         else if delta != 0 {
-            self.inner.seek(SeekFrom::Start(u64::try_from(target_position).unwrap()))?;
+            self.inner.seek(SeekFrom::Start(target_position))?;
             self.position = target_position;
         }
 
         Ok(())
     }
+
+    /// The total number of bytes in the byte source. Does not change the current position.
+    pub fn total_byte_size(&mut self) -> std::io::Result<u64> {
+        let byte_size = self.inner.seek(SeekFrom::End(0))?;
+        self.inner.seek(SeekFrom::Start(self.position))?;
+        Ok(byte_size)
+    }
 }
 
 impl<T: Write + Seek> Tracking<T> {
 
     /// Move the writing cursor to the specified target byte index.
     /// If seeking forward, this will write zeroes.
-    pub fn seek_write_to(&mut self, target_position: usize) -> std::io::Result<()> {
+    pub fn seek_write_to(&mut self, target_position: u64) -> std::io::Result<()> {
         if target_position < self.position {
-            self.inner.seek(SeekFrom::Start(u64::try_from(target_position).unwrap()))?;
+            self.inner.seek(SeekFrom::Start(target_position))?;
         }
         else if target_position > self.position {
             std::io::copy(
-                &mut std::io::repeat(0).take(u64::try_from(target_position - self.position).unwrap()),
+                &mut std::io::repeat(0).take(target_position - self.position),
                 self
             )?;
         }
@@ -255,6 +391,17 @@ impl<T: Write + Seek> Tracking<T> {
         self.position = target_position;
         Ok(())
     }
+
+    /// Move the writing cursor to the specified target byte index,
+    /// which must have already been written before. Never writes any bytes.
+    pub fn seek_write_to_written(&mut self, target_position: u64) -> std::io::Result<()> {
+        if target_position != self.position {
+            self.inner.seek(SeekFrom::Start(target_position))?;
+            self.position = target_position;
+        }
+
+        Ok(())
+    }
 }
 
 
@@ -310,10 +457,10 @@ pub trait Data: Sized + Default + Clone {
         // (most of the time, this loop will run only once)
         while data.len() < end {
             let chunk_start = data.len();
-            let chunk_end = (chunk_start + soft_max).min(data_size);
+            let chunk_end = (chunk_start + soft_max).min(end);
 
             data.resize(chunk_end, Self::default());
-            Self::read_slice(read, &mut data[chunk_start .. chunk_end])?; // safe because of `min(data_size)``
+            Self::read_slice(read, &mut data[chunk_start .. chunk_end])?; // safe because of `min(end)`
         }
 
         Ok(())
@@ -442,6 +589,47 @@ mod test {
 
         assert!(u8::read_from_little_endian(&mut peek).is_err());
     }
-}
 
+    #[test]
+    fn read_into_non_empty_vec(){
+        use crate::io::Data;
+        let buffer: &[u8] = &[1,2,3,4,5,6,7];
+        let mut remaining = buffer;
+        let mut vec = vec![9_u8];
+
+        u8::read_into_vec(&mut remaining, &mut vec, 4, 2, None, "test").unwrap();
+        u8::read_into_vec(&mut remaining, &mut vec, 3, 2, None, "test").unwrap();
+        assert_eq!(vec, vec![9,1,2,3,4,5,6,7]);
+    }
 
+    #[test]
+    fn replace_file_keeps_original_if_moving_fails(){
+        use crate::io::{replace_file_using_backup, write_file_atomically};
+
+        let directory = std::env::temp_dir().join(format!("exrs_replace_file_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let target = directory.join("target.exr");
+        let source = directory.join("source.exr");
+        let directory_entries = || std::fs::read_dir(&directory).unwrap().count();
+
+        std::fs::write(&target, b"original").unwrap();
+        std::fs::write(&source, b"replacement").unwrap();
+        replace_file_using_backup(&source, &target).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"replacement");
+        assert_eq!(directory_entries(), 1, "backup must be deleted");
+
+        // the source does not exist anymore, so the backup is moved back
+        assert!(replace_file_using_backup(&source, &target).is_err());
+        assert_eq!(std::fs::read(&target).unwrap(), b"replacement");
+        assert_eq!(directory_entries(), 1, "backup must be restored");
+
+        // the temporary file is deleted while unwinding
+        let panicked = std::panic::catch_unwind(|| write_file_atomically(&target, |_| panic!("writing failed")));
+        assert!(panicked.is_err());
+        assert_eq!(std::fs::read(&target).unwrap(), b"replacement");
+        assert_eq!(directory_entries(), 1, "temporary file must be deleted");
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -46,6 +46,9 @@ pub mod image;
 pub mod error;
 pub mod block;
 
+#[cfg(feature = "image-interop")]
+pub mod interop;
+
 #[macro_use]
 extern crate smallvec;
 
@@ -59,7 +62,7 @@ pub mod prelude {
         pub use crate::image::read::{
             read, any_channels::ReadSamples, image::ReadLayers,
             image::ReadImage, layers::ReadChannels,
-            specific_channels::{ReadSpecificChannel}
+            specific_channels::{ReadSpecificChannel, DeclaredChannels}
         };
 
         pub use crate::image::crop::{Crop, CropWhere, CropResult, InspectSample, CroppedChannels, ApplyCroppedView};
@@ -93,11 +93,17 @@ pub enum AttributeValue {
         /// Use the `exr::io::Data` trait to extract binary values from this vector.
         bytes: Vec<u8>
     },
+
+    /// A custom attribute whose type has been registered in an `AttributeParserRegistry`.
+    /// Contains the parsed value, as well as the bytes that are written to the file.
+    Parsed(ParsedAttribute),
 }
 
-/// A byte array with each byte being a char.
-/// This is not UTF an must be constructed from a standard string.
-// TODO is this ascii? use a rust ascii crate?
+/// A string of arbitrary bytes, as stored in an exr file.
+/// The file format does not specify an encoding, so the bytes are never validated or converted,
+/// and writing a text always reproduces the exact bytes that were read.
+/// Most software writes UTF-8, which `to_string_lossy()` decodes,
+/// whereas `Display` and `chars()` interpret each byte as a single char (Latin-1).
 #[derive(Clone, PartialEq, Ord, PartialOrd, Default)] // hash implemented manually
 pub struct Text {
     bytes: TextBytes,
@@ -185,7 +191,11 @@ pub type DataWindow = IntegerBounds;
 pub type DisplayWindow = IntegerBounds;
 
 /// An integer dividend and divisor, together forming a ratio.
-pub type Rational = (i32, u32);
+/// Two ratios are equal if they describe the same number, so `24/1` equals `48/2`.
+/// Ratios with a zero divisor are considered infinite, or undefined for `0/0`,
+/// which compares greater than all other ratios and equal to itself.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rational(pub i32, pub u32);
 
 /// A float matrix with four rows and four columns.
 pub type Matrix4x4 = [f32; 4*4];
@@ -235,6 +245,46 @@ pub struct ChannelList {
     pub uniform_sample_type: Option<SampleType>,
 }
 
+/// A single problem of a channel list, found by `ChannelList::validate_names`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChannelListProblem {
+
+    /// The list does not contain any channels.
+    NoChannels,
+
+    /// The channel at this index has an empty name.
+    EmptyName(usize),
+
+    /// The name of the channel at this index contains a null byte or another control character.
+    ReservedCharacter(usize, Text),
+
+    /// The channel at this index has the same name as a previous channel.
+    Duplicate(usize, Text),
+
+    /// The channel at this index should be sorted before the previous channel.
+    Unsorted(usize, Text),
+}
+
+/// All problems of a channel list, found by `ChannelList::validate_names`.
+/// Converts into `Error::Invalid`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChannelListError {
+
+    /// The problems, in the order of the channels. Never empty.
+    pub problems: Vec<ChannelListProblem>,
+}
+
+/// Describes what `ChannelList::normalized` changed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ChannelListNormalization {
+
+    /// Whether the channels had to be sorted alphabetically.
+    pub sorted: bool,
+
+    /// The channels that were removed because a previous channel had the same name.
+    pub removed_duplicates: Vec<ChannelDescription>,
+}
+
 /// A single channel in an layer.
 /// Does not contain the actual pixel data,
 /// but instead merely describes it.
@@ -309,28 +359,30 @@ pub enum EnvironmentMap {
 }
 
 /// Uniquely identifies a motion picture film frame.
+/// Formats as the conventional edge code, for example `01 13 123456 7890+12`,
+/// which contains the manufacturer, the film type, the roll prefix, the count, and the perforation offset.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct KeyCode {
 
-    /// Identifies a film manufacturer.
+    /// Identifies a film manufacturer. Values 0 - 99 are valid.
     pub film_manufacturer_code: i32,
 
-    /// Identifies a film type.
+    /// Identifies a film type. Values 0 - 99 are valid.
     pub film_type: i32,
 
-    /// Specifies the film roll prefix.
+    /// Specifies the film roll prefix. Values 0 - 999999 are valid.
     pub film_roll_prefix: i32,
 
-    /// Specifies the film count.
+    /// Specifies the film count. Values 0 - 9999 are valid.
     pub count: i32,
 
-    /// Specifies the perforation offset.
+    /// Specifies the perforation offset. Values 0 - 119 are valid.
     pub perforation_offset: i32,
 
-    /// Specifies the perforation count of each single frame.
+    /// Specifies the perforation count of each single frame. Values 1 - 15 are valid.
     pub perforations_per_frame: i32,
 
-    /// Specifies the perforation count of each single film.
+    /// Specifies the perforation count between two edge code counts. Values 20 - 120 are valid.
     pub perforations_per_count: i32,
 }
 
@@ -408,14 +460,41 @@ pub type TextBytes = SmallVec<[u8; 24]>;
 pub type TextSlice = [u8];
 
 
+/// The value of a custom attribute type, parsed by a function registered in an `AttributeParserRegistry`.
+/// Use `AttributeParserRegistry::to_attribute_value` to create a new value that can be written to a file.
+/// Two values are equal if their type names and bytes are equal.
+#[derive(Clone)]
+pub struct ParsedAttribute {
+    kind: Text,
+    value: Arc<dyn Any + Send + Sync>,
+    bytes: Vec<u8>,
+}
+
+/// The functions used to parse and serialize custom attribute types, looked up by the type name stored in the file.
+/// Attributes of types without functions are read as `AttributeValue::Custom`, containing the raw bytes.
+/// Cloning a registry does not clone the functions.
+#[derive(Clone, Default)]
+pub struct AttributeParserRegistry {
+    types: HashMap<Text, Arc<AttributeType>>,
+}
+
+/// The parse and serialize functions of a single custom attribute type.
+struct AttributeType {
+    parse: Box<dyn Fn(&[u8]) -> Result<Arc<dyn Any + Send + Sync>> + Send + Sync>,
+    serialize: Box<dyn Fn(&(dyn Any + Send + Sync)) -> Result<Vec<u8>> + Send + Sync>,
+}
+
 use crate::io::*;
-use crate::meta::{sequence_end};
+use crate::meta::{sequence_end, ReadLimits};
 use crate::error::*;
 use crate::math::{RoundingMode, Vec2};
 use half::f16;
 use std::convert::{TryFrom};
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
 use std::hash::{Hash, Hasher};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use bit_field::BitField;
 
 
@@ -454,11 +533,21 @@ impl Text {
         Text { bytes }
     }
 
-    /// The internal ASCII bytes this text is made of.
+    /// The internal bytes this text is made of.
     pub fn as_slice(&self) -> &TextSlice {
         self.bytes.as_slice()
     }
 
+    /// If this text starts with the specified prefix,
+    /// returns a copy with the prefix replaced by the new prefix.
+    pub fn replace_prefix(&self, old_prefix: &TextSlice, new_prefix: &TextSlice) -> Option<Self> {
+        let rest = self.as_slice().strip_prefix(old_prefix)?;
+
+        let mut bytes = TextBytes::from_slice(new_prefix);
+        bytes.extend_from_slice(rest);
+        Some(Self::from_bytes_unchecked(bytes))
+    }
+
     /// Check whether this string is valid, adjusting `long_names` if required.
     /// If `long_names` is not provided, text length will be entirely unchecked.
     pub fn validate(&self, null_terminated: bool, long_names: Option<&mut bool>) -> UnitResult {
@@ -603,6 +692,18 @@ impl Text {
         self.bytes.as_slice()
     }
 
+    /// The underlying bytes that represent this text, exactly as they are stored in the file.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_slice()
+    }
+
+    /// Decode the bytes as UTF-8, replacing invalid sequences with `�`.
+    /// Does not allocate if the bytes are valid UTF-8.
+    /// Use `to_string()` instead for texts that were written with one byte per char, like Latin-1.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.as_bytes())
+    }
+
     /// Iterate over the individual chars in this text, similar to `String::chars()`.
     /// Does not do any heap-allocation but borrows from this instance instead.
     pub fn chars(&self) -> impl '_ + Iterator<Item = char> {
@@ -720,6 +821,36 @@ impl ChannelList {
         }
     }
 
+    /// Whether any channel contains fewer samples than the layer contains pixels.
+    pub fn is_subsampled(&self) -> bool {
+        self.list.iter().any(|channel| channel.sampling != Vec2(1, 1))
+    }
+
+    /// The number of bytes that the samples of all channels occupy inside the section, without compression.
+    /// Unlike `bytes_per_pixel * area`, this respects subsampled channels.
+    pub fn byte_size_of_section(&self, section: IntegerBounds) -> usize {
+        self.list.iter()
+            .map(|channel| channel.subsampled_section_size(section).area()
+                .saturating_mul(channel.sample_type.bytes_per_sample()))
+            .fold(0, usize::saturating_add)
+    }
+
+    /// Replace the prefix of all channels whose name starts with `old_prefix`,
+    /// for example `"diffuse."` with `"albedo."`, returning the number of renamed channels.
+    /// Sorts the channels afterwards, because the file format requires alphabetical order.
+    /// Returns an error and leaves the channels unchanged if two channels would end up with the same name.
+    pub fn rename_prefix(&mut self, old_prefix: &str, new_prefix: &str) -> Result<usize> {
+        let (old_prefix, new_prefix) = prefix_texts(old_prefix, new_prefix)?;
+
+        let (renamed_count, renamed) = rename_and_sort(self.list.iter().map(|channel| &channel.name), &old_prefix, &new_prefix)?;
+
+        self.list = renamed.into_iter()
+            .map(|(index, name)| ChannelDescription { name, .. self.list[index].clone() })
+            .collect();
+
+        Ok(renamed_count)
+    }
+
     /// Iterate over the channels, and adds to each channel the byte offset of the channels sample type.
     /// Assumes the internal channel list is properly sorted.
     pub fn channels_with_byte_offset(&self) -> impl Iterator<Item=(usize, &ChannelDescription)> {
@@ -736,6 +867,72 @@ impl ChannelList {
         self.list.binary_search_by_key(&exact_name.bytes(), |chan| chan.name.bytes()).ok()
     }
 
+    /// Return the view that the channel with the specified name belongs to,
+    /// given the view names of the `multiView` attribute, or none.
+    /// A channel name without a period belongs to the default view, which is the first view name.
+    /// Otherwise, the view is the second to last section of the name, for example `right` in `right.R`.
+    /// If that section is not a view name, the channel does not belong to any view.
+    pub fn view_of_channel<'v>(channel_name: &Text, view_names: &'v [Text]) -> Option<&'v Text> {
+        let mut sections = channel_name.as_slice().rsplit(|&byte| byte == b'.');
+        sections.next(); // skip the channel name itself
+
+        match sections.next() {
+            None => view_names.first(),
+            Some(view) => view_names.iter().find(|name| name.as_slice() == view),
+        }
+    }
+
+    /// Create the name of a channel in the specified view, given the view names of the `multiView` attribute.
+    /// The channels of the default view, which is the first view name, keep their name.
+    /// For other views, the view name is inserted before the last section of the name,
+    /// such that `R` in view `right` becomes `right.R`, and `diffuse.R` becomes `diffuse.right.R`.
+    pub fn channel_name_in_view(channel_name: &Text, view_names: &[Text], view: &Text) -> Text {
+        if view_names.first() == Some(view) { return channel_name.clone(); }
+
+        let name = channel_name.as_slice();
+        let last_section_start = name.iter().rposition(|&byte| byte == b'.').map_or(0, |period| period + 1);
+
+        let mut bytes = TextBytes::from_slice(&name[.. last_section_start]);
+        bytes.extend_from_slice(view.as_slice());
+        bytes.push(b'.');
+        bytes.extend_from_slice(&name[last_section_start ..]);
+        Text::from_bytes_unchecked(bytes)
+    }
+
+    /// Iterate over the views that contain at least one channel,
+    /// given the view names of the `multiView` attribute, in the order of the view names.
+    pub fn views<'v>(&'v self, view_names: &'v [Text]) -> impl 'v + Iterator<Item=&'v Text> {
+        view_names.iter().filter(move |&view| self.list.iter().any(|channel|
+            Self::view_of_channel(&channel.name, view_names) == Some(view)
+        ))
+    }
+
+    /// Iterate over the channels that belong to the specified view,
+    /// given the view names of the `multiView` attribute.
+    /// The view is removed from the name of each channel, such that `right.R` becomes `R`.
+    /// Adds to each channel the byte offset of the channel within all channels of a pixel,
+    /// just like `channels_with_byte_offset`.
+    pub fn channels_of_view<'v>(&'v self, view_names: &'v [Text], view: &'v Text)
+        -> impl 'v + Iterator<Item=(usize, ChannelDescription)>
+    {
+        self.channels_with_byte_offset()
+            .filter(move |(_, channel)| Self::view_of_channel(&channel.name, view_names) == Some(view))
+            .map(|(byte_offset, channel)| {
+                let name = channel.name.as_slice();
+                let name = match name.iter().rposition(|&byte| byte == b'.') {
+                    None => Text::from_slice_unchecked(name),
+                    Some(last_period) => {
+                        let view_start = name[.. last_period].iter().rposition(|&byte| byte == b'.').map_or(0, |period| period + 1);
+                        let mut bytes = TextBytes::from_slice(&name[.. view_start]);
+                        bytes.extend_from_slice(&name[last_period + 1 ..]);
+                        Text::from_bytes_unchecked(bytes)
+                    }
+                };
+
+                (byte_offset, ChannelDescription { name, .. channel.clone() })
+            })
+    }
+
     // TODO use this in compression methods
     /*pub fn pixel_section_indices(&self, bounds: IntegerBounds) -> impl '_ + Iterator<Item=(&Channel, usize, usize)> {
         (bounds.position.y() .. bounds.end().y()).flat_map(|y| {
@@ -904,6 +1101,16 @@ impl IntegerBounds {
         && subset.end().x() <= self.end().x()
         && subset.end().y() <= self.end().y()
     }
+
+    /// Returns the rectangle that is inside both this and the other rectangle.
+    /// Returns `None` if the rectangles do not overlap.
+    pub fn intersection(self, other: Self) -> Option<Self> {
+        let start = self.position.max(other.position);
+        let end = self.end().min(other.end());
+
+        if end.x() <= start.x() || end.y() <= start.y() { None }
+        else { Some(IntegerBounds::new(start, (end - start).to_usize("intersection size").ok()?)) }
+    }
 }
 
 
@@ -1016,6 +1223,21 @@ impl ChannelDescription {
         dimensions / self.sampling
     }
 
+    /// The number of samples of this channel inside the section, respecting subsampling.
+    /// A subsampled channel only contains samples in the lines and columns
+    /// whose coordinate is a multiple of the sampling rate.
+    pub fn subsampled_section_size(&self, section: IntegerBounds) -> Vec2<usize> {
+        let sample_count = |start: i32, size: usize, sampling: usize| {
+            let (start, end, sampling) = (i64::from(start), i64::from(start) + size as i64, sampling as i64);
+            ((end - 1).div_euclid(sampling) - (start - 1).div_euclid(sampling)) as usize
+        };
+
+        Vec2(
+            sample_count(section.position.x(), section.size.width(), self.sampling.x()),
+            sample_count(section.position.y(), section.size.height(), self.sampling.y()),
+        )
+    }
+
     /// Number of bytes this would consume in an exr file.
     pub fn byte_size(&self) -> usize {
         self.name.null_terminated_byte_size()
@@ -1085,11 +1307,8 @@ impl ChannelDescription {
             return Err(Error::invalid("channel sampling factor not dividing data window size"));
         }
 
-        if self.sampling != Vec2(1,1) {
-            // TODO this must only be implemented in the crate::image module and child modules,
-            //      should not be too difficult
-
-            return Err(Error::unsupported("channel subsampling not supported yet"));
+        if !allow_sampling && self.sampling != Vec2(1,1) {
+            return Err(Error::unsupported("channel subsampling in tiled or deep images"));
         }
 
         Ok(())
@@ -1116,8 +1335,14 @@ impl ChannelList {
 
     /// Read the value without validating.
     pub fn read(read: &mut PeekRead<impl Read>) -> Result<Self> {
+        Self::read_limited(read, usize::MAX)
+    }
+
+    /// Read the value without validating, aborting before the list exceeds the maximum channel count.
+    pub(crate) fn read_limited(read: &mut PeekRead<impl Read>, max_channel_count: usize) -> Result<Self> {
         let mut channels = SmallVec::new();
         while !sequence_end::has_come(read)? {
+            ReadLimits::check(channels.len() + 1, max_channel_count, "max_channel_count")?;
             channels.push(ChannelDescription::read(read)?);
         }
 
@@ -1138,6 +1363,226 @@ impl ChannelList {
 
         Ok(())
     }
+
+    /// Check the channel names only, collecting all problems instead of stopping at the first one.
+    /// Reports duplicate names regardless of the `strict` flag of `validate`.
+    pub fn validate_names(&self) -> std::result::Result<(), ChannelListError> {
+        let mut problems = Vec::new();
+        let mut previous_names = HashSet::with_capacity(self.list.len());
+
+        if self.list.is_empty() {
+            problems.push(ChannelListProblem::NoChannels);
+        }
+
+        for (index, channel) in self.list.iter().enumerate() {
+            let name = &channel.name;
+
+            if name.as_slice().is_empty() {
+                problems.push(ChannelListProblem::EmptyName(index));
+            }
+
+            if name.as_slice().iter().any(|&byte| byte < 0x20 || byte == 0x7f) {
+                problems.push(ChannelListProblem::ReservedCharacter(index, name.clone()));
+            }
+
+            if !previous_names.insert(name) {
+                problems.push(ChannelListProblem::Duplicate(index, name.clone()));
+            }
+
+            else if index > 0 && self.list[index - 1].name > *name {
+                problems.push(ChannelListProblem::Unsorted(index, name.clone()));
+            }
+        }
+
+        if problems.is_empty() { Ok(()) }
+        else { Err(ChannelListError { problems }) }
+    }
+
+    /// Sort the channels alphabetically and, if desired, remove channels whose name has already occurred,
+    /// keeping the first channel of each name. Returns the new list and a description of what was changed.
+    /// Does not fix empty names or reserved characters.
+    pub fn normalized(&self, remove_duplicates: bool) -> (Self, ChannelListNormalization) {
+        let mut list = self.list.clone();
+        list.sort_by(|left, right| left.name.cmp(&right.name)); // stable, keeping the first duplicate first
+
+        let mut normalization = ChannelListNormalization {
+            sorted: list != self.list,
+            removed_duplicates: Vec::new(),
+        };
+
+        if remove_duplicates {
+            let mut deduplicated = SmallVec::with_capacity(list.len());
+
+            for channel in list {
+                let is_duplicate = deduplicated.last()
+                    .map_or(false, |previous: &ChannelDescription| previous.name == channel.name);
+
+                if is_duplicate { normalization.removed_duplicates.push(channel); }
+                else { deduplicated.push(channel); }
+            }
+
+            list = deduplicated;
+        }
+
+        (ChannelList::new(list), normalization)
+    }
+}
+
+impl ChannelListNormalization {
+
+    /// Whether the normalized list differs from the original list.
+    pub fn changed_anything(&self) -> bool {
+        self.sorted || !self.removed_duplicates.is_empty()
+    }
+}
+
+impl std::fmt::Display for ChannelListProblem {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelListProblem::NoChannels => write!(formatter, "at least one channel is required"),
+            ChannelListProblem::EmptyName(index) => write!(formatter, "channel {} has an empty name", index),
+            ChannelListProblem::ReservedCharacter(index, name) => write!(formatter, "channel {} name `{}` contains reserved characters", index, name),
+            ChannelListProblem::Duplicate(index, name) => write!(formatter, "channel {} name `{}` is not unique", index, name),
+            ChannelListProblem::Unsorted(index, name) => write!(formatter, "channel {} name `{}` is not sorted alphabetically", index, name),
+        }
+    }
+}
+
+impl std::fmt::Display for ChannelListError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "invalid channel list: ")?;
+
+        for (index, problem) in self.problems.iter().enumerate() {
+            if index > 0 { write!(formatter, ", ")?; }
+            write!(formatter, "{}", problem)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ChannelListError {}
+
+impl From<ChannelListError> for Error {
+    fn from(error: ChannelListError) -> Self {
+        Error::invalid(error.to_string())
+    }
+}
+
+impl Rational {
+
+    /// Frame rate of NTSC film, `24000/1001`, about 23.976 frames per second.
+    pub const FPS_23_976: Self = Rational(24000, 1001);
+
+    /// Frame rate of film, 24 frames per second.
+    pub const FPS_24: Self = Rational(24, 1);
+
+    /// Frame rate of PAL video, 25 frames per second.
+    pub const FPS_25: Self = Rational(25, 1);
+
+    /// Frame rate of NTSC video, `30000/1001`, about 29.97 frames per second.
+    pub const FPS_29_97: Self = Rational(30000, 1001);
+
+    /// Frame rate of 30 frames per second.
+    pub const FPS_30: Self = Rational(30, 1);
+
+    /// Frame rate of high frame rate film, 48 frames per second.
+    pub const FPS_48: Self = Rational(48, 1);
+
+    /// Frame rate of progressive PAL video, 50 frames per second.
+    pub const FPS_50: Self = Rational(50, 1);
+
+    /// Frame rate of progressive NTSC video, `60000/1001`, about 59.94 frames per second.
+    pub const FPS_59_94: Self = Rational(60000, 1001);
+
+    /// Frame rate of 60 frames per second.
+    pub const FPS_60: Self = Rational(60, 1);
+
+    /// Divide the numerator by the denominator.
+    /// Returns an infinite number for a zero denominator, or NaN for `0/0`.
+    pub fn as_f64(self) -> f64 {
+        f64::from(self.0) / f64::from(self.1)
+    }
+
+    /// Reduce the fraction by the greatest common divisor of numerator and denominator,
+    /// such that `48/2` becomes `24/1`. A zero denominator results in `1/0`, `-1/0`, or `0/0`.
+    pub fn normalized(self) -> Self {
+        let Rational(numerator, denominator) = self;
+
+        let mut divisor = numerator.unsigned_abs();
+        let mut remainder = denominator;
+
+        while remainder != 0 {
+            let next = divisor % remainder;
+            divisor = remainder;
+            remainder = next;
+        }
+
+        if divisor == 0 { return self; } // `0/0` cannot be reduced
+
+        // computed in i64, as the divisor of `i32::MIN` may not fit into an i32
+        let numerator = i64::from(numerator) / i64::from(divisor);
+        Rational(numerator as i32, denominator / divisor)
+    }
+
+    /// Ratios with a zero denominator are ordered by the sign of their numerator.
+    fn ordering_class(self) -> u8 {
+        match (self.0.signum(), self.1) {
+            (-1, 0) => 0, // negative infinity
+            (0, 0) => 3, // undefined
+            (_, 0) => 2, // positive infinity
+            _ => 1, // finite
+        }
+    }
+}
+
+impl From<(i32, u32)> for Rational {
+    fn from((numerator, denominator): (i32, u32)) -> Self {
+        Rational(numerator, denominator)
+    }
+}
+
+impl From<Rational> for (i32, u32) {
+    fn from(Rational(numerator, denominator): Rational) -> Self {
+        (numerator, denominator)
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let class = self.ordering_class();
+
+        if class != 1 || other.ordering_class() != 1 {
+            return class.cmp(&other.ordering_class());
+        }
+
+        // i128 cannot overflow, as each product has at most 32 + 32 bits
+        let left = i128::from(self.0) * i128::from(other.1);
+        let right = i128::from(other.0) * i128::from(self.1);
+        left.cmp(&right)
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Rational {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Rational {}
+
+impl Hash for Rational {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let Rational(numerator, denominator) = self.normalized();
+        numerator.hash(state);
+        denominator.hash(state);
+    }
 }
 
 fn u8_to_decimal32(binary: u8) -> u32 {
@@ -1151,6 +1596,9 @@ fn u8_from_decimal32(coded: u32) -> u8 {
     ((coded & 0x0f) + 10 * ((coded >> 4) & 0x0f)) as u8
 }
 
+/// The number of frame labels that drop frame time codes skip at the start of most minutes.
+const DROPPED_FRAME_LABELS: u64 = 2;
+
 // https://github.com/AcademySoftwareFoundation/openexr/blob/master/src/lib/OpenEXR/ImfTimeCode.cpp
 impl TimeCode {
 
@@ -1291,6 +1739,105 @@ impl TimeCode {
     }
 
 
+    /// Create a time code from the time of day and the frame within the second, without any flags or user data.
+    /// Returns an `Error::Invalid` if the fields are out of the allowed range.
+    pub fn from_time(hours: u8, minutes: u8, seconds: u8, frame: u8) -> Result<Self> {
+        let time_code = Self { hours, minutes, seconds, frame, .. Self::default() };
+        time_code.validate(true)?;
+        Ok(time_code)
+    }
+
+    /// Compute the time code of the frame with the specified index, counting from `00:00:00:00`.
+    /// The frame rate, for example `Rational::FPS_29_97` for NTSC video, defines the number of frames per time code second,
+    /// which is the frame rate rounded to the nearest integer. Time codes wrap around after 24 hours.
+    ///
+    /// Drop frame time codes skip the frame labels `00` and `01` at the start of each minute,
+    /// except every tenth minute, so that the time code stays close to the wall clock time.
+    /// Drop frame time codes are only defined for frame rates of `Rational::FPS_29_97`.
+    pub fn from_frame_number(frame_number: u64, frames_per_second: Rational, drop_frame: bool) -> Result<Self> {
+        let nominal_rate = Self::nominal_frame_rate(frames_per_second, drop_frame)?;
+        let frames_per_day = nominal_rate * 60 * 60 * 24;
+        let mut frame_label = frame_number;
+
+        if drop_frame {
+            let frames_per_minute = nominal_rate * 60 - DROPPED_FRAME_LABELS;
+            let frames_per_ten_minutes = nominal_rate * 60 * 10 - 9 * DROPPED_FRAME_LABELS;
+
+            let frame_number = frame_number % (frames_per_ten_minutes * 6 * 24);
+            let ten_minutes = frame_number / frames_per_ten_minutes;
+            let frame_in_ten_minutes = frame_number % frames_per_ten_minutes;
+
+            // the first minute of every ten minutes does not drop any labels
+            let dropped_minutes = frame_in_ten_minutes.saturating_sub(DROPPED_FRAME_LABELS) / frames_per_minute;
+            frame_label = frame_number + DROPPED_FRAME_LABELS * (9 * ten_minutes + dropped_minutes);
+        }
+
+        let frame_label = frame_label % frames_per_day;
+        let total_seconds = frame_label / nominal_rate;
+
+        let time_code = Self {
+            hours: (total_seconds / 3600) as u8,
+            minutes: (total_seconds / 60 % 60) as u8,
+            seconds: (total_seconds % 60) as u8,
+            frame: (frame_label % nominal_rate) as u8,
+            drop_frame,
+            .. Self::default()
+        };
+
+        debug_assert!(time_code.validate(true).is_ok(), "time code frame number conversion bug");
+        Ok(time_code)
+    }
+
+    /// Compute the index of the frame that this time code labels, counting from `00:00:00:00`.
+    /// The `drop_frame` flag of this time code defines whether frame labels have been skipped.
+    /// See `TimeCode::from_frame_number` for details about the frame rate and drop frame time codes.
+    /// Returns an error for frame labels that are skipped in drop frame time codes, such as `00:01:00:00`.
+    pub fn to_frame_number(&self, frames_per_second: Rational) -> Result<u64> {
+        self.validate(true)?;
+
+        let nominal_rate = Self::nominal_frame_rate(frames_per_second, self.drop_frame)?;
+        if u64::from(self.frame) >= nominal_rate {
+            return Err(Error::invalid("time code frame exceeds frame rate"));
+        }
+
+        let total_minutes = u64::from(self.hours) * 60 + u64::from(self.minutes);
+        let total_seconds = total_minutes * 60 + u64::from(self.seconds);
+        let frame_label = total_seconds * nominal_rate + u64::from(self.frame);
+
+        if !self.drop_frame { return Ok(frame_label) }
+
+        if self.seconds == 0 && self.minutes % 10 != 0 && u64::from(self.frame) < DROPPED_FRAME_LABELS {
+            return Err(Error::invalid("time code frame label is dropped"));
+        }
+
+        let dropped_minutes = total_minutes - total_minutes / 10;
+        Ok(frame_label - DROPPED_FRAME_LABELS * dropped_minutes)
+    }
+
+    /// The number of frames per time code second, which is the frame rate rounded to the nearest integer.
+    fn nominal_frame_rate(Rational(numerator, denominator): Rational, drop_frame: bool) -> Result<u64> {
+        if numerator <= 0 || denominator == 0 {
+            return Err(Error::invalid("time code frame rate"));
+        }
+
+        let (numerator, denominator) = (numerator as u64, u64::from(denominator));
+        let nominal_rate = (numerator + denominator / 2) / denominator;
+
+        if nominal_rate == 0 {
+            return Err(Error::invalid("time code frame rate"));
+        }
+
+        if nominal_rate > 30 {
+            return Err(Error::unsupported("time code frame rates above 30 frames per second"));
+        }
+
+        if drop_frame && (numerator * 1001 != 30000 * denominator) {
+            return Err(Error::invalid("drop frame time codes require a frame rate of 30000/1001"));
+        }
+
+        Ok(nominal_rate)
+    }
+
     /// Write this time code to the byte stream, encoded as TV60 integers.
     /// Returns an `Error::Invalid` if the fields are out of the allowed range.
     pub fn write<W: Write>(&self, write: &mut W) -> UnitResult {
@@ -1341,6 +1888,224 @@ impl Chromaticities {
     }
 }
 
+/// The Bradford cone response matrix, used to adapt colors to a different white point.
+const BRADFORD: [[f64; 3]; 3] = [
+    [ 0.8951,  0.2664, -0.1614],
+    [-0.7502,  1.7135,  0.0367],
+    [ 0.0389, -0.0685,  1.0296],
+];
+
+impl Chromaticities {
+
+    /// The primaries and white point of `Rec. ITU-R BT.709-3`,
+    /// which should be assumed if a file does not contain a chromaticities attribute.
+    pub const REC_709: Self = Chromaticities {
+        red: Vec2(0.64, 0.33),
+        green: Vec2(0.30, 0.60),
+        blue: Vec2(0.15, 0.06),
+        white: Vec2(0.3127, 0.3290),
+    };
+
+    /// The primaries and white point of sRGB, which are the same as in `Rec. 709`.
+    pub const SRGB: Self = Self::REC_709;
+
+    /// The primaries and white point of `Rec. ITU-R BT.2020`.
+    pub const REC_2020: Self = Chromaticities {
+        red: Vec2(0.708, 0.292),
+        green: Vec2(0.170, 0.797),
+        blue: Vec2(0.131, 0.046),
+        white: Vec2(0.3127, 0.3290),
+    };
+
+    /// The primaries and white point of ACES 2065-1 (AP0), the ACES interchange color space.
+    pub const ACES_AP0: Self = Chromaticities {
+        red: Vec2(0.7347, 0.2653),
+        green: Vec2(0.0, 1.0),
+        blue: Vec2(0.0001, -0.0770),
+        white: Vec2(0.32168, 0.33767),
+    };
+
+    /// The primaries and white point of ACEScg (AP1), the ACES working color space.
+    pub const ACES_AP1: Self = Chromaticities {
+        red: Vec2(0.713, 0.293),
+        green: Vec2(0.165, 0.830),
+        blue: Vec2(0.128, 0.044),
+        white: Vec2(0.32168, 0.33767),
+    };
+
+    /// Compute the matrix that converts linear rgb values in this color space to CIE XYZ tristimulus values.
+    /// The white point `(1, 1, 1)` is converted to a luminance `Y` of `1`.
+    /// Multiply the `whiteLuminance` attribute with the result to obtain absolute luminance.
+    /// The rows of the matrix produce `X`, `Y` and `Z`, such that `xyz = matrix * rgb`.
+    pub fn to_xyz_matrix(&self) -> [[f32; 3]; 3] {
+        to_f32_matrix(self.to_xyz_matrix_f64())
+    }
+
+    /// The contribution of red, green and blue to the luminance of a color in this color space.
+    /// This is the `Y` row of `to_xyz_matrix`, normalized such that the three weights add up to one.
+    /// Used to convert luminance-chroma images to rgb, just like `Yw` in the OpenEXR `RgbaYca` functions.
+    pub fn luminance_weights(&self) -> [f32; 3] {
+        let [red, green, blue] = self.to_xyz_matrix_f64()[1];
+        let sum = red + green + blue;
+        [ (red / sum) as f32, (green / sum) as f32, (blue / sum) as f32 ]
+    }
+
+    /// Compute the matrix that converts CIE XYZ tristimulus values to linear rgb values in this color space.
+    /// This is the inverse of `to_xyz_matrix`.
+    pub fn from_xyz_matrix(&self) -> [[f32; 3]; 3] {
+        to_f32_matrix(invert_matrix(self.to_xyz_matrix_f64()))
+    }
+
+    /// Compute the matrix that converts linear rgb values in this color space to linear rgb values in the target color space.
+    /// If the white points differ, the colors are adapted to the target white point using the Bradford transform,
+    /// such that white in this color space is white in the target color space.
+    /// The rows of the matrix produce the target red, green and blue values, such that `target_rgb = matrix * rgb`.
+    pub fn conversion_matrix_to(&self, target: &Chromaticities) -> [[f32; 3]; 3] {
+        let to_xyz = self.to_xyz_matrix_f64();
+        let from_target_xyz = invert_matrix(target.to_xyz_matrix_f64());
+
+        let source_cone = multiply_matrix_vector(BRADFORD, xy_to_xyz(self.white));
+        let target_cone = multiply_matrix_vector(BRADFORD, xy_to_xyz(target.white));
+
+        let mut cone_scale = [[0.0; 3]; 3];
+        for index in 0..3 { cone_scale[index][index] = target_cone[index] / source_cone[index]; }
+
+        let adaptation = multiply_matrices(invert_matrix(BRADFORD), multiply_matrices(cone_scale, BRADFORD));
+        to_f32_matrix(multiply_matrices(from_target_xyz, multiply_matrices(adaptation, to_xyz)))
+    }
+
+    /// Compute the rgb to xyz matrix with double precision, to avoid accumulating errors when combining matrices.
+    fn to_xyz_matrix_f64(&self) -> [[f64; 3]; 3] {
+        let primaries = [ xy_to_xyz(self.red), xy_to_xyz(self.green), xy_to_xyz(self.blue) ];
+
+        // the columns of the matrix are the primaries, scaled such that their sum is the white point
+        let unscaled = [
+            [ primaries[0][0], primaries[1][0], primaries[2][0] ],
+            [ primaries[0][1], primaries[1][1], primaries[2][1] ],
+            [ primaries[0][2], primaries[1][2], primaries[2][2] ],
+        ];
+
+        let scale = multiply_matrix_vector(invert_matrix(unscaled), xy_to_xyz(self.white));
+
+        let mut matrix = unscaled;
+        for row in &mut matrix {
+            for (value, scale) in row.iter_mut().zip(scale.iter()) { *value *= scale; }
+        }
+
+        matrix
+    }
+}
+
+/// Convert the prefixes of a channel renaming, failing for unsupported characters.
+pub(crate) fn prefix_texts(old_prefix: &str, new_prefix: &str) -> Result<(Text, Text)> {
+    let text = |prefix: &str| Text::new_or_none(prefix)
+        .ok_or_else(|| Error::invalid("channel name prefix contains unsupported characters"));
+
+    Ok((text(old_prefix)?, text(new_prefix)?))
+}
+
+/// Rename all channels with the old prefix, then sort them by name and check for duplicates.
+/// Returns the number of renamed channels, and for each channel in the new order,
+/// its index in the original order and its new name.
+pub(crate) fn rename_and_sort<'t>(
+    names: impl Iterator<Item=&'t Text>, old_prefix: &Text, new_prefix: &Text
+) -> Result<(usize, Vec<(usize, Text)>)>
+{
+    let mut renamed_count = 0;
+
+    let mut renamed: Vec<(usize, Text)> = names.enumerate().map(|(index, name)| {
+        match name.replace_prefix(old_prefix.as_slice(), new_prefix.as_slice()) {
+            Some(renamed) => { renamed_count += 1; (index, renamed) },
+            None => (index, name.clone()),
+        }
+    }).collect();
+
+    renamed.sort_unstable_by(|(_, left), (_, right)| left.cmp(right));
+
+    if let Some(pair) = renamed.windows(2).find(|pair| pair[0].1 == pair[1].1) {
+        return Err(Error::invalid(format!("duplicate channel name after renaming: `{}`", pair[0].1)));
+    }
+
+    Ok((renamed_count, renamed))
+}
+
+/// Convert CIE xy chromaticity coordinates to XYZ tristimulus values with a luminance `Y` of `1`.
+fn xy_to_xyz(xy: Vec2<f32>) -> [f64; 3] {
+    let (x, y) = (f64::from(xy.x()), f64::from(xy.y()));
+    [ x / y, 1.0, (1.0 - x - y) / y ]
+}
+
+fn multiply_matrices(left: [[f64; 3]; 3], right: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut result = [[0.0; 3]; 3];
+
+    for row in 0..3 {
+        for column in 0..3 {
+            result[row][column] = (0..3).map(|index| left[row][index] * right[index][column]).sum();
+        }
+    }
+
+    result
+}
+
+fn multiply_matrix_vector(matrix: [[f64; 3]; 3], vector: [f64; 3]) -> [f64; 3] {
+    let row = |row: [f64; 3]| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2];
+    [ row(matrix[0]), row(matrix[1]), row(matrix[2]) ]
+}
+
+fn invert_matrix(matrix: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let [[a, b, c], [d, e, f], [g, h, i]] = matrix;
+
+    let determinant = a * (e * i - f * h) - b * (d * i - f * g) + c * (d * h - e * g);
+    let inverse = 1.0 / determinant;
+
+    [
+        [ (e * i - f * h) * inverse, (c * h - b * i) * inverse, (b * f - c * e) * inverse ],
+        [ (f * g - d * i) * inverse, (a * i - c * g) * inverse, (c * d - a * f) * inverse ],
+        [ (d * h - e * g) * inverse, (b * g - a * h) * inverse, (a * e - b * d) * inverse ],
+    ]
+}
+
+fn to_f32_matrix(matrix: [[f64; 3]; 3]) -> [[f32; 3]; 3] {
+    matrix.map(|row| row.map(|value| value as f32))
+}
+
+/// Split a matrix attribute into its four rows, such that `rows[row][column]` is the element at that position.
+/// The file stores matrices row by row, in the row-vector convention of OpenEXR:
+/// a point is transformed by `point * matrix`, and the translation is in the last row.
+/// Transpose the result when using a library that multiplies `matrix * point` instead.
+pub fn matrix4x4_to_rows(matrix: Matrix4x4) -> [[f32; 4]; 4] {
+    let mut rows = [[0.0; 4]; 4];
+
+    for (row, values) in rows.iter_mut().zip(matrix.chunks_exact(4)) {
+        row.copy_from_slice(values);
+    }
+
+    rows
+}
+
+/// Combine four rows into a matrix attribute, such that `rows[row][column]` is the element at that position.
+/// This is the inverse of `matrix4x4_to_rows`, see there for the convention.
+pub fn matrix4x4_from_rows(rows: [[f32; 4]; 4]) -> Matrix4x4 {
+    let mut matrix = [0.0; 4*4];
+
+    for (values, row) in matrix.chunks_exact_mut(4).zip(rows.iter()) {
+        values.copy_from_slice(row);
+    }
+
+    matrix
+}
+
+/// Transform a 3D point by a matrix attribute, for example `worldToCamera`, as OpenEXR does.
+/// The point is treated as the row vector `[x, y, z, 1]`, multiplied with the matrix,
+/// and then divided by the resulting `w` component, which is relevant for projections like `worldToNDC`.
+pub fn transform_point_by_matrix4x4(matrix: Matrix4x4, point: [f32; 3]) -> [f32; 3] {
+    let rows = matrix4x4_to_rows(matrix);
+    let column = |column: usize| point[0] * rows[0][column] + point[1] * rows[1][column] + point[2] * rows[2][column] + rows[3][column];
+
+    let w = column(3);
+    [ column(0) / w, column(1) / w, column(2) / w ]
+}
+
 impl Compression {
 
     /// Number of bytes this would consume in an exr file.
@@ -1360,6 +2125,7 @@ impl Compression {
             B44A => 7_u8,
             DWAA(_) => 8_u8,
             DWAB(_) => 9_u8,
+            Custom(id) => id,
         }.write(write)?;
         Ok(())
     }
@@ -1378,7 +2144,7 @@ impl Compression {
             7 => B44A,
             8 => DWAA(None),
             9 => DWAB(None),
-            _ => return Err(Error::unsupported("unknown compression method")),
+            id => Custom(id),
         })
     }
 }
@@ -1416,7 +2182,31 @@ impl KeyCode {
 
     /// Number of bytes this would consume in an exr file.
     pub fn byte_size() -> usize {
-        6 * i32::BYTE_SIZE
+        7 * i32::BYTE_SIZE
+    }
+
+    /// Create a key code from the fields of an edge code, with four perforations per frame
+    /// and 64 perforations per count, which is the layout of 35mm film with four perforations per frame.
+    /// Returns an `Error::Invalid` if the fields are out of the allowed range.
+    pub fn from_edge_code(film_manufacturer_code: i32, film_type: i32, film_roll_prefix: i32, count: i32, perforation_offset: i32) -> Result<Self> {
+        let key_code = KeyCode { film_manufacturer_code, film_type, film_roll_prefix, count, perforation_offset, .. Self::default() };
+        key_code.validate(true)?;
+        Ok(key_code)
+    }
+
+    /// Returns an error if this key code is considered invalid.
+    pub fn validate(&self, strict: bool) -> UnitResult {
+        if strict {
+            if !(0 ..= 99).contains(&self.film_manufacturer_code) { Err(Error::invalid("key code film manufacturer code not within 0 - 99")) }
+            else if !(0 ..= 99).contains(&self.film_type) { Err(Error::invalid("key code film type not within 0 - 99")) }
+            else if !(0 ..= 999_999).contains(&self.film_roll_prefix) { Err(Error::invalid("key code film roll prefix not within 0 - 999999")) }
+            else if !(0 ..= 9999).contains(&self.count) { Err(Error::invalid("key code count not within 0 - 9999")) }
+            else if !(0 ..= 119).contains(&self.perforation_offset) { Err(Error::invalid("key code perforation offset not within 0 - 119")) }
+            else if !(1 ..= 15).contains(&self.perforations_per_frame) { Err(Error::invalid("key code perforations per frame not within 1 - 15")) }
+            else if !(20 ..= 120).contains(&self.perforations_per_count) { Err(Error::invalid("key code perforations per count not within 20 - 120")) }
+            else { Ok(()) }
+        }
+        else { Ok(()) }
     }
 
     /// Without validation, write this instance to the byte stream.
@@ -1426,6 +2216,7 @@ impl KeyCode {
         self.film_roll_prefix.write(write)?;
         self.count.write(write)?;
         self.perforation_offset.write(write)?;
+        self.perforations_per_frame.write(write)?;
         self.perforations_per_count.write(write)?;
         Ok(())
     }
@@ -1444,6 +2235,52 @@ impl KeyCode {
     }
 }
 
+impl Default for KeyCode {
+    fn default() -> Self {
+        KeyCode {
+            film_manufacturer_code: 0,
+            film_type: 0,
+            film_roll_prefix: 0,
+            count: 0,
+            perforation_offset: 0,
+            perforations_per_frame: 4,
+            perforations_per_count: 64,
+        }
+    }
+}
+
+impl std::fmt::Display for KeyCode {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            formatter, "{:02} {:02} {:06} {:04}+{:02}",
+            self.film_manufacturer_code, self.film_type, self.film_roll_prefix,
+            self.count, self.perforation_offset
+        )
+    }
+}
+
+impl std::str::FromStr for KeyCode {
+    type Err = Error;
+
+    /// Parse an edge code, for example `01 13 123456 7890+12`, using `KeyCode::from_edge_code`.
+    /// The perforation offset may be omitted, for example `01 13 123456 7890`.
+    fn from_str(edge_code: &str) -> Result<Self> {
+        let invalid = || Error::invalid("key code edge code format");
+        let parse = |number: &str| number.parse::<i32>().map_err(|_| invalid());
+
+        let (numbers, perforation_offset) = match edge_code.trim().split_once('+') {
+            Some((numbers, offset)) => (numbers, parse(offset.trim())?),
+            None => (edge_code, 0),
+        };
+
+        let numbers = numbers.split_whitespace().map(parse).collect::<Result<SmallVec<[i32; 4]>>>()?;
+        match numbers.as_slice() {
+            &[manufacturer, film_type, prefix, count] => Self::from_edge_code(manufacturer, film_type, prefix, count, perforation_offset),
+            _ => Err(invalid()),
+        }
+    }
+}
+
 impl LineOrder {
 
     /// Number of bytes this would consume in an exr file.
@@ -1480,6 +2317,29 @@ impl LineOrder {
 
 impl Preview {
 
+    /// Create a preview from linear rgba pixels, stored row by row from the top left pixel.
+    /// Maps the linear colors to 8-bit values using the same exposure,
+    /// soft clipping and gamma as the `makePreview` function of the OpenEXR utilities.
+    /// Alpha is not gamma corrected.
+    /// Returns an error if the number of pixels does not match the size.
+    pub fn from_linear_rgba(size: Vec2<usize>, pixels: impl IntoIterator<Item=(f32, f32, f32, f32)>) -> Result<Self> {
+        let mut pixels = pixels.into_iter();
+
+        let pixel_data: Vec<i8> = pixels.by_ref().take(size.area())
+            .flat_map(|(r, g, b, a)| [
+                linear_to_preview_color(r), linear_to_preview_color(g),
+                linear_to_preview_color(b), linear_to_preview_alpha(a),
+            ])
+            .map(|byte| byte as i8)
+            .collect();
+
+        if pixel_data.len() != size.area() * 4 || pixels.next().is_some() {
+            return Err(Error::invalid("preview pixel count does not match preview size"));
+        }
+
+        Ok(Preview { size, pixel_data })
+    }
+
     /// Number of bytes this would consume in an exr file.
     pub fn byte_size(&self) -> usize {
         2 * u32::BYTE_SIZE + self.pixel_data.len()
@@ -1536,6 +2396,23 @@ impl Preview {
     }
 }
 
+/// Map a linear color sample to 8 bits, like the OpenEXR `makePreview` function with an exposure of zero.
+fn linear_to_preview_color(linear: f32) -> u8 {
+    // the exposure multiplier of `makePreview`, which is 2 to the power of 2.47393
+    let exposed = (linear * 2.0_f32.powf(2.47393)).max(0.0);
+
+    // compress values above one instead of clipping them
+    let knee = 0.184874_f32;
+    let exposed = if exposed > 1.0 { 1.0 + (((exposed - 1.0) * knee) + 1.0).ln() / knee } else { exposed };
+
+    (exposed.powf(0.4545) * 84.66).clamp(0.0, 255.0) as u8
+}
+
+/// Map a linear alpha sample to 8 bits, like the OpenEXR `makePreview` function.
+fn linear_to_preview_alpha(linear: f32) -> u8 {
+    ((linear * 255.0).clamp(0.0, 255.0) + 0.5) as u8
+}
+
 impl ::std::fmt::Debug for Preview {
     fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
         write!(f, "Preview ({}x{} px)", self.size.width(), self.size.height())
@@ -1612,6 +2489,117 @@ impl TileDescription {
     }
 }
 
+impl ParsedAttribute {
+
+    /// The name of the custom type of this attribute.
+    pub fn kind(&self) -> &Text { &self.kind }
+
+    /// The parsed value, if it has the specified type.
+    pub fn value<T: Any>(&self) -> Option<&T> { self.value.downcast_ref() }
+
+    /// The serialized value, which is written to the file.
+    pub fn bytes(&self) -> &[u8] { &self.bytes }
+}
+
+impl ::std::fmt::Debug for ParsedAttribute {
+    fn fmt(&self, formatter: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        write!(formatter, "ParsedAttribute ({}, {} bytes)", self.kind, self.bytes.len())
+    }
+}
+
+impl PartialEq for ParsedAttribute {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.bytes == other.bytes
+    }
+}
+
+impl AttributeParserRegistry {
+
+    /// Create a registry that does not contain any types.
+    pub fn new() -> Self { Self::default() }
+
+    /// Add the functions for the specified custom type name, replacing any previous functions for that type.
+    /// The `parse` function receives the bytes of an attribute value in the file,
+    /// and the `serialize` function must produce these bytes from the parsed value.
+    pub fn with_type<T: Any + Send + Sync>(
+        mut self, kind: impl Into<Text>,
+        parse: impl Fn(&[u8]) -> Result<T> + Send + Sync + 'static,
+        serialize: impl Fn(&T) -> Result<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self
+    {
+        let attribute_type = AttributeType {
+            parse: Box::new(move |bytes| Ok(Arc::new(parse(bytes)?) as Arc<dyn Any + Send + Sync>)),
+
+            serialize: Box::new(move |value| serialize(
+                value.downcast_ref::<T>().ok_or_else(invalid_type)?
+            )),
+        };
+
+        self.types.insert(kind.into(), Arc::new(attribute_type));
+        self
+    }
+
+    /// Whether functions have been added for the specified type name.
+    pub fn contains(&self, kind: &Text) -> bool { self.types.contains_key(kind) }
+
+    /// Whether no types have been added.
+    pub fn is_empty(&self) -> bool { self.types.is_empty() }
+
+    /// Serialize the value using the functions of the specified type name, returning an attribute that can be written to a file.
+    /// Returns an error if the type has not been added, or if the value does not have the type of the registered functions.
+    pub fn to_attribute_value<T: Any + Send + Sync>(&self, kind: impl Into<Text>, value: T) -> Result<AttributeValue> {
+        let kind = kind.into();
+        let attribute_type = self.types.get(&kind)
+            .ok_or_else(|| Error::unsupported(format!("custom attribute type `{}`", kind)))?;
+
+        let bytes = (attribute_type.serialize)(&value)?;
+        Ok(AttributeValue::Parsed(ParsedAttribute { kind, value: Arc::new(value), bytes }))
+    }
+
+    /// Parse all custom attributes of a registered type in the map.
+    /// If a value cannot be parsed, returns the error if pedantic, or otherwise keeps the raw bytes.
+    pub fn parse_all(&self, attributes: &mut HashMap<Text, AttributeValue>, pedantic: bool) -> UnitResult {
+        if self.is_empty() { return Ok(()); }
+
+        for value in attributes.values_mut() {
+            if let AttributeValue::Custom { kind, bytes } = value {
+                if let Some(attribute_type) = self.types.get(kind) {
+                    match (attribute_type.parse)(bytes) {
+                        Ok(parsed) => *value = AttributeValue::Parsed(ParsedAttribute {
+                            kind: kind.clone(), value: parsed, bytes: std::mem::take(bytes)
+                        }),
+
+                        Err(error) => if pedantic { return Err(error) },
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn sorted_kinds(&self) -> Vec<&Text> {
+        let mut kinds: Vec<&Text> = self.types.keys().collect();
+        kinds.sort_unstable_by_key(|kind| kind.as_slice());
+        kinds
+    }
+}
+
+impl ::std::fmt::Debug for AttributeParserRegistry {
+    fn fmt(&self, formatter: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        formatter.debug_tuple("AttributeParserRegistry").field(&self.sorted_kinds()).finish()
+    }
+}
+
+// functions are compared by identity, as they cannot be compared by value
+impl PartialEq for AttributeParserRegistry {
+    fn eq(&self, other: &Self) -> bool {
+        self.types.len() == other.types.len() && self.types.iter().all(|(kind, attribute_type)|
+            other.types.get(kind).map_or(false, |other_type| Arc::ptr_eq(attribute_type, other_type))
+        )
+    }
+}
+
 
 /// Number of bytes this attribute would consume in an exr file.
 // TODO instead of pre calculating byte size, write to a tmp buffer whose length is inspected before actually writing?
@@ -1632,10 +2620,28 @@ pub fn write<W: Write>(name: &TextSlice, value: &AttributeValue, write: &mut W)
 
 /// Read the attribute without validating. The result may be `Ok` even if this single attribute is invalid.
 pub fn read(read: &mut PeekRead<impl Read>, max_size: usize) -> Result<(Text, Result<AttributeValue>)> {
+    let mut remaining_header_bytes = usize::MAX;
+    read_limited(read, max_size, &ReadLimits::unlimited(), &mut remaining_header_bytes)
+}
+
+/// Read the attribute without validating, but check its declared size against the limits before reading the value.
+/// Subtracts the byte size of the whole attribute from the remaining byte size of the headers.
+pub(crate) fn read_limited(
+    read: &mut PeekRead<impl Read>, max_size: usize,
+    limits: &ReadLimits, remaining_header_bytes: &mut usize
+) -> Result<(Text, Result<AttributeValue>)>
+{
     let name = Text::read_null_terminated(read, max_size)?;
     let kind = Text::read_null_terminated(read, max_size)?;
     let size = i32_to_usize(i32::read(read)?, "attribute size")?;
-    let value = AttributeValue::read(read, kind, size)?;
+    ReadLimits::check(size, limits.max_attribute_byte_size, "max_attribute_byte_size")?;
+
+    let attribute_byte_size = name.bytes().len() + kind.bytes().len() + 2 + i32::BYTE_SIZE + size;
+    *remaining_header_bytes = remaining_header_bytes.checked_sub(attribute_byte_size).ok_or_else(|| Error::invalid(format!(
+        "header byte size exceeds the read limit `max_header_byte_size` of {}", limits.max_header_byte_size
+    )))?;
+
+    let value = AttributeValue::read_limited(read, kind, size, limits)?;
     Ok((name, value))
 }
 
@@ -1688,6 +2694,7 @@ impl AttributeValue {
             TextVector(ref value) => value.iter().map(self::Text::i32_sized_byte_size).sum(),
             TileDescription(_) => self::TileDescription::byte_size(),
             Custom { ref bytes, .. } => bytes.len(),
+            Parsed(ref value) => value.bytes.len(),
             BlockType(ref kind) => kind.byte_size()
         }
     }
@@ -1723,6 +2730,7 @@ impl AttributeValue {
             TileDescription(_) =>  ty::TILES,
             BlockType(_) => super::BlockType::TYPE_NAME,
             Custom { ref kind, .. } => kind.as_slice(),
+            Parsed(ref value) => value.kind.as_slice(),
         }
     }
 
@@ -1737,7 +2745,7 @@ impl AttributeValue {
             F32(value) => value.write(write)?,
             F64(value) => value.write(write)?,
 
-            Rational((a, b)) => { a.write(write)?; b.write(write)?; },
+            Rational(self::Rational(a, b)) => { a.write(write)?; b.write(write)?; },
             TimeCode(codes) => { codes.write(write)?; },
 
             IntVec2(Vec2(x, y)) => { x.write(write)?; y.write(write)?; },
@@ -1765,6 +2773,7 @@ impl AttributeValue {
             TextVector(ref value) => self::Text::write_vec_of_i32_sized_texts(write, value)?,
             TileDescription(ref value) => value.write(write)?,
             Custom { ref bytes, .. } => u8::write_slice(write, &bytes)?, // write.write(&bytes).map(|_| ()),
+            Parsed(ref value) => u8::write_slice(write, &value.bytes)?,
             BlockType(kind) => kind.write(write)?
         };
 
@@ -1776,6 +2785,11 @@ impl AttributeValue {
     /// Returns `Ok(Err(Error))` for invalid attributes from a valid byte source.
     /// Returns `Err(Error)` for invalid byte sources, for example for invalid files.
     pub fn read(read: &mut PeekRead<impl Read>, kind: Text, byte_size: usize) -> Result<Result<Self>> {
+        Self::read_limited(read, kind, byte_size, &ReadLimits::unlimited())
+    }
+
+    /// Read the value without validating, aborting as soon as the value exceeds the limits.
+    pub(crate) fn read_limited(read: &mut PeekRead<impl Read>, kind: Text, byte_size: usize, limits: &ReadLimits) -> Result<Result<Self>> {
         use self::AttributeValue::*;
         use self::type_names as ty;
 
@@ -1797,7 +2811,7 @@ impl AttributeValue {
                 ty::RATIONAL => Rational({
                     let a = i32::read(reader)?;
                     let b = u32::read(reader)?;
-                    (a, b)
+                    self::Rational(a, b)
                 }),
 
                 ty::TIME_CODE => TimeCode(self::TimeCode::read(reader)?),
@@ -1828,7 +2842,10 @@ impl AttributeValue {
                     (a, b, c)
                 }),
 
-                ty::CHANNEL_LIST    => ChannelList(self::ChannelList::read(&mut PeekRead::new(attribute_bytes.as_slice()))?),
+                ty::CHANNEL_LIST    => ChannelList(self::ChannelList::read_limited(
+                    &mut PeekRead::new(attribute_bytes.as_slice()),
+                    limits.max_channel_count
+                )?),
                 ty::CHROMATICITIES  => Chromaticities(self::Chromaticities::read(reader)?),
                 ty::COMPRESSION     => Compression(self::Compression::read(reader)?),
                 ty::ENVIRONMENT_MAP => EnvironmentMap(self::EnvironmentMap::read(reader)?),
@@ -1875,6 +2892,7 @@ impl AttributeValue {
             TileDescription(ref value) => value.validate()?,
             Preview(ref value) => value.validate(strict)?,
             TimeCode(ref time_code) => time_code.validate(strict)?,
+            KeyCode(ref key_code) => key_code.validate(strict)?,
 
             TextVector(ref vec) => if strict && vec.is_empty() {
                 return Err(Error::invalid("text vector may not be empty"))
@@ -2069,6 +3087,13 @@ mod test {
                     max: Vec2(68623.0, 3.12425926538),
                 }),
             ),
+            (
+                Text::from("film frame"),
+                AttributeValue::KeyCode(KeyCode {
+                    film_manufacturer_code: 1, film_type: 13, film_roll_prefix: 123456,
+                    count: 7890, perforation_offset: 12, perforations_per_frame: 3, perforations_per_count: 48,
+                }),
+            ),
             (
                 Text::from("rabbit area int"),
                 AttributeValue::IntegerBounds(IntegerBounds {
@@ -2223,4 +3248,390 @@ mod test {
         }
     }
 
+    #[test]
+    fn time_code_packing_test_vectors(){
+        let code = |hours, minutes, seconds, frame| TimeCode::from_time(hours, minutes, seconds, frame).unwrap();
+
+        // (time code, packed time and flags, packed user data), as packed by the reference implementation
+        let vectors = [
+            (code(0, 0, 0, 0), 0x00000000_u32, 0x00000000_u32),
+            (code(1, 2, 3, 4), 0x01020304, 0x00000000),
+            (code(23, 59, 59, 29), 0x23595929, 0x00000000),
+            (code(12, 34, 56, 17), 0x12345617, 0x00000000),
+            (TimeCode { drop_frame: true, .. code(10, 0, 0, 0) }, 0x10000040, 0x00000000),
+            (TimeCode { color_frame: true, .. code(0, 0, 0, 0) }, 0x00000080, 0x00000000),
+            (TimeCode { field_phase: true, .. code(0, 0, 0, 0) }, 0x00008000, 0x00000000),
+            (TimeCode { binary_group_flags: [true, false, false], .. code(0, 0, 0, 0) }, 0x00800000, 0x00000000),
+            (TimeCode { binary_group_flags: [false, true, false], .. code(0, 0, 0, 0) }, 0x40000000, 0x00000000),
+            (TimeCode { binary_group_flags: [false, false, true], .. code(0, 0, 0, 0) }, 0x80000000, 0x00000000),
+            (TimeCode { binary_groups: [1, 2, 3, 4, 5, 6, 7, 8], .. code(0, 0, 0, 0) }, 0x00000000, 0x87654321),
+            (TimeCode { binary_groups: [15, 0, 0, 0, 0, 0, 0, 15], .. code(0, 0, 0, 0) }, 0x00000000, 0xF000000F),
+        ];
+
+        for &(time_code, time_and_flags, user_data) in &vectors {
+            let mut bytes = Vec::new();
+            time_code.write(&mut bytes).unwrap();
+
+            let mut expected = Vec::new();
+            expected.extend_from_slice(&time_and_flags.to_le_bytes());
+            expected.extend_from_slice(&user_data.to_le_bytes());
+
+            assert_eq!(bytes, expected, "{:?}", time_code);
+            assert_eq!(TimeCode::read(&mut bytes.as_slice()).unwrap(), time_code);
+        }
+
+        assert!(TimeCode::from_time(24, 0, 0, 0).is_err());
+        assert!(TimeCode::from_time(0, 0, 0, 30).is_err());
+    }
+
+    #[test]
+    fn rational_frame_rates(){
+        assert_eq!(Rational(48, 2), Rational::FPS_24);
+        assert_eq!(Rational(48, 2).normalized(), Rational::FPS_24);
+        assert_eq!(Rational(48, 2).normalized().1, 1);
+        assert_eq!(Rational(60000, 2002).normalized().1, 1001);
+        assert_eq!(Rational(-10, 4).normalized().0, -5);
+        assert_eq!(Rational(0, 7).normalized().1, 1);
+        assert_eq!(Rational(-7, 0).normalized().0, -1);
+        assert_eq!(Rational(i32::MIN, 2).normalized().0, i32::MIN / 2);
+        assert_eq!(Rational(i32::MIN, 1).normalized().0, i32::MIN);
+
+        let ascending = [
+            Rational::FPS_23_976, Rational::FPS_24, Rational::FPS_25, Rational::FPS_29_97, Rational::FPS_30,
+            Rational::FPS_48, Rational::FPS_50, Rational::FPS_59_94, Rational::FPS_60,
+        ];
+
+        for pair in ascending.windows(2) {
+            assert!(pair[0] < pair[1], "{:?}", pair);
+            assert!(pair[0].as_f64() < pair[1].as_f64(), "{:?}", pair);
+        }
+
+        assert!((Rational::FPS_29_97.as_f64() - 29.97002997).abs() < 1e-8);
+        assert_eq!(Rational::FPS_59_94, Rational(120000, 2002));
+        assert_ne!(Rational::FPS_29_97, Rational::FPS_30);
+
+        // products of these values exceed the range of an i64
+        assert!(Rational(i32::MAX, u32::MAX) > Rational(i32::MAX - 1, u32::MAX));
+        assert!(Rational(i32::MIN, 1) < Rational(i32::MIN + 1, 1));
+        assert!(Rational(i32::MIN, u32::MAX) < Rational(-1, u32::MAX));
+        assert_eq!(Rational(i32::MAX, u32::MAX), Rational(i32::MAX, u32::MAX));
+        assert!(Rational(1, u32::MAX) > Rational(0, 1));
+        assert!(Rational(i32::MAX, 1) < Rational(1, 0));
+        assert!(Rational(i32::MIN, 1) > Rational(-1, 0));
+        assert_eq!(Rational(3, 0), Rational(1, 0));
+        assert_eq!(Rational(0, 0), Rational(0, 0));
+        assert!(Rational(0, 0) > Rational(1, 0));
+
+        let mut hashes = std::collections::HashSet::new();
+        hashes.insert(Rational(24, 1));
+        assert!(hashes.contains(&Rational(48, 2)));
+        assert!(!hashes.contains(&Rational::FPS_23_976));
+
+        let mut layer = crate::meta::header::LayerAttributes::default();
+        let mut image = crate::meta::header::ImageAttributes::new(IntegerBounds::from_dimensions((1, 1)));
+        assert_eq!(layer.time_code_frame_number(&image).unwrap(), None);
+
+        image.time_code = Some(TimeCode { drop_frame: true, .. TimeCode::from_time(0, 10, 0, 0).unwrap() });
+        layer.frames_per_second = Some(Rational(60000, 2002));
+        assert_eq!(layer.time_code_frame_number(&image).unwrap(), Some(17982));
+
+        layer.frames_per_second = Some(Rational::FPS_25);
+        assert!(layer.time_code_frame_number(&image).is_err());
+    }
+
+    #[test]
+    fn time_code_frame_numbers(){
+        let ntsc = Rational::FPS_29_97;
+        let code = |hours, minutes, seconds, frame, drop_frame| TimeCode {
+            drop_frame, .. TimeCode::from_time(hours, minutes, seconds, frame).unwrap()
+        };
+
+        // (frame number, drop frame time code)
+        let drop_frame_codes = [
+            (0, code(0, 0, 0, 0, true)),
+            (1799, code(0, 0, 59, 29, true)),
+            (1800, code(0, 1, 0, 2, true)),
+            (3597, code(0, 1, 59, 29, true)),
+            (3598, code(0, 2, 0, 2, true)),
+            (16183, code(0, 8, 59, 29, true)),
+            (16184, code(0, 9, 0, 2, true)),
+            (17981, code(0, 9, 59, 29, true)),
+            (17982, code(0, 10, 0, 0, true)),
+            (17983, code(0, 10, 0, 1, true)),
+            (19781, code(0, 10, 59, 29, true)),
+            (19782, code(0, 11, 0, 2, true)),
+            (107892, code(1, 0, 0, 0, true)),
+            (2589407, code(23, 59, 59, 29, true)),
+            (2589408, code(0, 0, 0, 0, true)),
+        ];
+
+        for &(frame_number, time_code) in &drop_frame_codes {
+            assert_eq!(TimeCode::from_frame_number(frame_number, ntsc, true).unwrap(), time_code, "frame {}", frame_number);
+            assert_eq!(time_code.to_frame_number(ntsc).unwrap(), frame_number % 2589408, "{:?}", time_code);
+        }
+
+        // these labels are skipped in drop frame time codes
+        assert!(code(0, 1, 0, 0, true).to_frame_number(ntsc).is_err());
+        assert!(code(0, 9, 0, 1, true).to_frame_number(ntsc).is_err());
+        assert!(code(0, 10, 0, 0, true).to_frame_number(ntsc).is_ok());
+
+        // non drop frame time codes count frames at the rounded frame rate
+        assert_eq!(TimeCode::from_frame_number(1800, ntsc, false).unwrap(), code(0, 1, 0, 0, false));
+        assert_eq!(TimeCode::from_frame_number(86399, Rational::FPS_24, false).unwrap(), code(0, 59, 59, 23, false));
+        assert_eq!(TimeCode::from_frame_number(86400 * 24, Rational::FPS_23_976, false).unwrap(), code(0, 0, 0, 0, false));
+        assert_eq!(code(1, 0, 0, 1, false).to_frame_number(Rational::FPS_25).unwrap(), 90001);
+
+        for frame_number in (0 .. 2 * 17982 + 100).chain(2589408 - 100 .. 2589408) {
+            let drop_frame_code = TimeCode::from_frame_number(frame_number, ntsc, true).unwrap();
+            assert_eq!(drop_frame_code.to_frame_number(ntsc).unwrap(), frame_number);
+
+            let time_code = TimeCode::from_frame_number(frame_number, ntsc, false).unwrap();
+            assert_eq!(time_code.to_frame_number(ntsc).unwrap(), frame_number);
+        }
+
+        assert!(TimeCode::from_frame_number(0, Rational::FPS_24, true).is_err());
+        assert!(TimeCode::from_frame_number(0, Rational::FPS_60, false).is_err());
+        assert!(TimeCode::from_frame_number(0, Rational(0, 1), false).is_err());
+        assert!(code(0, 0, 0, 25, false).to_frame_number(Rational::FPS_25).is_err());
+    }
+
+    #[test]
+    fn key_code_edge_codes(){
+        let key_code = KeyCode::from_edge_code(1, 13, 123456, 7890, 12).unwrap();
+        assert_eq!(key_code.perforations_per_frame, 4);
+        assert_eq!(key_code.perforations_per_count, 64);
+
+        assert_eq!(key_code.to_string(), "01 13 123456 7890+12");
+        assert_eq!("01 13 123456 7890+12".parse::<KeyCode>().unwrap(), key_code);
+        assert_eq!(" 1  13 123456 7890 + 12 ".parse::<KeyCode>().unwrap(), key_code);
+        assert_eq!("01 13 123456 7890".parse::<KeyCode>().unwrap(), KeyCode { perforation_offset: 0, .. key_code });
+        assert_eq!(KeyCode::default().to_string(), "00 00 000000 0000+00");
+
+        for invalid in &[ "", "01 13 123456", "01 13 123456 7890 1+12", "01 13 123456 7890+", "a1 13 123456 7890+12", "100 13 123456 7890+12", "01 13 123456 7890+120" ] {
+            assert!(invalid.parse::<KeyCode>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn key_code_ranges(){
+        let valid = KeyCode::from_edge_code(99, 99, 999_999, 9999, 119).unwrap();
+        let valid_extremes = [
+            valid,
+            KeyCode { perforations_per_frame: 1, perforations_per_count: 20, .. valid },
+            KeyCode { perforations_per_frame: 15, perforations_per_count: 120, .. KeyCode::default() },
+        ];
+
+        for key_code in &valid_extremes {
+            key_code.validate(true).unwrap();
+        }
+
+        let invalid = [
+            KeyCode { film_manufacturer_code: 100, .. valid },
+            KeyCode { film_manufacturer_code: -1, .. valid },
+            KeyCode { film_type: 100, .. valid },
+            KeyCode { film_roll_prefix: 1_000_000, .. valid },
+            KeyCode { count: 10_000, .. valid },
+            KeyCode { perforation_offset: 120, .. valid },
+            KeyCode { perforations_per_frame: 0, .. valid },
+            KeyCode { perforations_per_frame: 16, .. valid },
+            KeyCode { perforations_per_count: 19, .. valid },
+            KeyCode { perforations_per_count: 121, .. valid },
+        ];
+
+        for key_code in &invalid {
+            assert!(key_code.validate(true).is_err(), "{:?}", key_code);
+            key_code.validate(false).unwrap();
+
+            let value = AttributeValue::KeyCode(*key_code);
+            assert!(value.validate(false, IntegerBounds::zero(), true).is_err());
+            value.validate(false, IntegerBounds::zero(), false).unwrap();
+        }
+    }
+
+    #[test]
+    fn multi_view_channel_names(){
+        let views = [ Text::from("left"), Text::from("right"), Text::from("center") ];
+
+        assert_eq!(ChannelList::view_of_channel(&Text::from("R"), &views), Some(&views[0]));
+        assert_eq!(ChannelList::view_of_channel(&Text::from("left.R"), &views), Some(&views[0]));
+        assert_eq!(ChannelList::view_of_channel(&Text::from("right.R"), &views), Some(&views[1]));
+        assert_eq!(ChannelList::view_of_channel(&Text::from("diffuse.center.R"), &views), Some(&views[2]));
+        assert_eq!(ChannelList::view_of_channel(&Text::from("diffuse.R"), &views), None);
+        assert_eq!(ChannelList::view_of_channel(&Text::from("R"), &[]), None);
+
+        assert_eq!(ChannelList::channel_name_in_view(&Text::from("R"), &views, &views[0]), Text::from("R"));
+        assert_eq!(ChannelList::channel_name_in_view(&Text::from("R"), &views, &views[1]), Text::from("right.R"));
+        assert_eq!(ChannelList::channel_name_in_view(&Text::from("diffuse.R"), &views, &views[2]), Text::from("diffuse.center.R"));
+
+        let channels = ChannelList::new(smallvec![
+            ChannelDescription::named("B", SampleType::F16),
+            ChannelDescription::named("R", SampleType::F16),
+            ChannelDescription::named("diffuse.R", SampleType::F32),
+            ChannelDescription::named("diffuse.right.R", SampleType::U32),
+            ChannelDescription::named("right.R", SampleType::F32),
+        ]);
+
+        assert_eq!(channels.views(&views).collect::<Vec<_>>(), vec![ &views[0], &views[1] ]);
+
+        let right: Vec<(usize, Text)> = channels.channels_of_view(&views, &views[1])
+            .map(|(byte_offset, channel)| (byte_offset, channel.name)).collect();
+
+        assert_eq!(right, vec![ (8, Text::from("diffuse.R")), (12, Text::from("R")) ]);
+
+        let left: Vec<(usize, Text)> = channels.channels_of_view(&views, &views[0])
+            .map(|(byte_offset, channel)| (byte_offset, channel.name)).collect();
+
+        assert_eq!(left, vec![ (0, Text::from("B")), (2, Text::from("R")) ]);
+    }
+
+    #[test]
+    fn chromaticities_conversion_matrices(){
+        fn assert_matrix_eq(actual: [[f32; 3]; 3], expected: [[f64; 3]; 3], tolerance: f64) {
+            for (actual_row, expected_row) in actual.iter().zip(expected.iter()) {
+                for (&actual, &expected) in actual_row.iter().zip(expected_row.iter()) {
+                    assert!((f64::from(actual) - expected).abs() < tolerance, "{:?} is not {:?}", actual_row, expected_row);
+                }
+            }
+        }
+
+        // from the ACES specification, TB-2014-004
+        assert_matrix_eq(Chromaticities::ACES_AP0.to_xyz_matrix(), [
+            [ 0.9525523959, 0.0000000000,  0.0000936786 ],
+            [ 0.3439664498, 0.7281660966, -0.0721325464 ],
+            [ 0.0000000000, 0.0000000000,  1.0088251844 ],
+        ], 1e-6);
+
+        assert_matrix_eq(Chromaticities::ACES_AP0.from_xyz_matrix(), [
+            [  1.0498110175, 0.0000000000, -0.0000974845 ],
+            [ -0.4959030231, 1.3733130458,  0.0982400361 ],
+            [  0.0000000000, 0.0000000000,  0.9912520182 ],
+        ], 1e-6);
+
+        // same white point, no adaptation
+        assert_matrix_eq(Chromaticities::ACES_AP1.conversion_matrix_to(&Chromaticities::ACES_AP0), [
+            [  0.6954522414, 0.1406786965, 0.1638690622 ],
+            [  0.0447945634, 0.8596711185, 0.0955343182 ],
+            [ -0.0055258826, 0.0040252103, 1.0015006723 ],
+        ], 1e-6);
+
+        let [red, green, blue] = Chromaticities::REC_709.luminance_weights();
+        assert!((red - 0.2126).abs() < 1e-4 && (green - 0.7152).abs() < 1e-4 && (blue - 0.0722).abs() < 1e-4);
+
+        // from IEC 61966-2-1, rounded to four digits
+        assert_matrix_eq(Chromaticities::SRGB.to_xyz_matrix(), [
+            [ 0.4124, 0.3576, 0.1805 ],
+            [ 0.2126, 0.7152, 0.0722 ],
+            [ 0.0193, 0.1192, 0.9505 ],
+        ], 1e-4);
+
+        // bradford adaptation from the ACES white point to D65, as in the OpenColorIO ACES configuration
+        assert_matrix_eq(Chromaticities::ACES_AP0.conversion_matrix_to(&Chromaticities::SRGB), [
+            [  2.52168618674388, -1.13413098823972, -0.38755519850416 ],
+            [ -0.27647991422992,  1.37271908766826, -0.09623917343834 ],
+            [ -0.01537806377976, -0.15297534297025,  1.16835340675001 ],
+        ], 1e-5);
+
+        // converting to the same color space does nothing
+        assert_matrix_eq(Chromaticities::REC_2020.conversion_matrix_to(&Chromaticities::REC_2020), [
+            [ 1.0, 0.0, 0.0 ],
+            [ 0.0, 1.0, 0.0 ],
+            [ 0.0, 0.0, 1.0 ],
+        ], 1e-6);
+
+        // white stays white
+        let rec_2020_to_ap1 = Chromaticities::REC_2020.conversion_matrix_to(&Chromaticities::ACES_AP1);
+        for row in &rec_2020_to_ap1 {
+            assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn matrix4x4_rows_and_point_transform() {
+        // translates by (1,2,3) in the row-vector convention, where the translation is in the last row
+        let translation: Matrix4x4 = [
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            1.0, 2.0, 3.0, 1.0,
+        ];
+
+        let rows = matrix4x4_to_rows(translation);
+        assert_eq!(rows[3], [ 1.0, 2.0, 3.0, 1.0 ]);
+        assert_eq!(rows[0][3], 0.0);
+        assert_eq!(matrix4x4_from_rows(rows), translation);
+
+        assert_eq!(transform_point_by_matrix4x4(translation, [ 1.0, 1.0, 1.0 ]), [ 2.0, 3.0, 4.0 ]);
+
+        // projection that divides by z
+        let projection = matrix4x4_from_rows([
+            [ 1.0, 0.0, 0.0, 0.0 ],
+            [ 0.0, 1.0, 0.0, 0.0 ],
+            [ 0.0, 0.0, 1.0, 1.0 ],
+            [ 0.0, 0.0, 0.0, 0.0 ],
+        ]);
+
+        assert_eq!(transform_point_by_matrix4x4(projection, [ 2.0, 4.0, 2.0 ]), [ 1.0, 2.0, 1.0 ]);
+    }
+
+    #[test]
+    fn channel_list_problems_and_normalization() {
+        let channel = |name: &[u8], sample_type| ChannelDescription::named(Text::from_slice_unchecked(name), sample_type);
+
+        let malformed = ChannelList::new(smallvec::smallvec![
+            channel(b"G", SampleType::F16),
+            channel(b"B", SampleType::F16),
+            channel(b"", SampleType::F32),
+            channel(b"R", SampleType::F16),
+            channel(b"G", SampleType::U32),
+            channel(b"Y\0", SampleType::F16),
+        ]);
+
+        let problems = malformed.validate_names().unwrap_err().problems;
+        assert_eq!(problems, vec![
+            ChannelListProblem::Unsorted(1, Text::from("B")),
+            ChannelListProblem::EmptyName(2),
+            ChannelListProblem::Unsorted(2, Text::from("")),
+            ChannelListProblem::Duplicate(4, Text::from("G")),
+            ChannelListProblem::ReservedCharacter(5, Text::from_slice_unchecked(b"Y\0")),
+        ]);
+
+        assert!(ChannelList::new(smallvec::smallvec![]).validate_names().unwrap_err().problems == vec![ ChannelListProblem::NoChannels ]);
+        assert!(Error::from(ChannelListError { problems }).to_string().contains("not unique"));
+
+        let (sorted, normalization) = malformed.normalized(false);
+        assert!(normalization.sorted && normalization.removed_duplicates.is_empty());
+        assert_eq!(sorted.list.len(), malformed.list.len());
+
+        let (normalized, normalization) = malformed.normalized(true);
+        let names: Vec<&[u8]> = normalized.list.iter().map(|channel| channel.name.as_slice()).collect();
+        assert_eq!(names, vec![ &b""[..], b"B", b"G", b"R", b"Y\0" ]);
+
+        // the first of the duplicate channels is kept
+        assert_eq!(normalized.list[2].sample_type, SampleType::F16);
+        assert_eq!(normalization.removed_duplicates, vec![ channel(b"G", SampleType::U32) ]);
+        assert_eq!(normalized.bytes_per_pixel, 4 * 2 + 4);
+
+        let problems = normalized.validate_names().unwrap_err().problems;
+        assert_eq!(problems, vec![
+            ChannelListProblem::EmptyName(0),
+            ChannelListProblem::ReservedCharacter(4, Text::from_slice_unchecked(b"Y\0")),
+        ]);
+
+        let (unchanged, normalization) = sorted.normalized(false);
+        assert!(!normalization.changed_anything());
+        assert_eq!(unchanged, sorted);
+    }
+
+    #[test]
+    fn preview_pixel_count_must_match_size() {
+        let pixels = |count: usize| vec![(0.18, 0.18, 0.18, 1.0); count];
+
+        let preview = Preview::from_linear_rgba(Vec2(3, 2), pixels(6)).unwrap();
+        assert_eq!(preview.size, Vec2(3, 2));
+        assert_eq!(preview.pixel_data.len(), 3 * 2 * 4);
+
+        assert!(matches!(Preview::from_linear_rgba(Vec2(3, 2), pixels(5)), Err(Error::Invalid(_))));
+        assert!(matches!(Preview::from_linear_rgba(Vec2(3, 2), pixels(7)), Err(Error::Invalid(_))));
+    }
+
 }
@@ -6,6 +6,7 @@ use std::collections::HashMap;
 use crate::meta::attribute::*; // FIXME shouldn't this need some more imports????
 use crate::meta::*;
 use crate::math::Vec2;
+use crate::compression::{CodecRegistry, CompressionWarning};
 
 // TODO rename header to LayerDescription!
 
@@ -21,6 +22,15 @@ pub struct Header {
     /// How the pixel data of all channels in this layer is compressed. May be `Compression::Uncompressed`.
     pub compression: Compression,
 
+    // the deflate level used when writing `ZIP1` or `ZIP16` compressed pixels, not stored in the file.
+    // private, so that further options can be added without breaking code in other crates
+    pub(crate) zip_compression_level: Option<u8>,
+
+    /// The codecs used if the compression of this layer is `Compression::Custom`.
+    /// __Custom compression methods are not part of the OpenEXR standard.__
+    /// This is not stored in the file, and must be specified again before reading the pixels.
+    pub custom_codecs: CodecRegistry,
+
     /// Describes how the pixels of this layer are divided into smaller blocks.
     /// A single block can be loaded without processing all bytes of a file.
     ///
@@ -204,10 +214,12 @@ pub struct LayerAttributes {
 
     /// The matrix that transforms 3D points from the world to the camera coordinate space.
     /// Left-handed coordinate system, y up, z forward.
+    /// Use `attribute::matrix4x4_to_rows` to access the elements without getting the transposition wrong.
     pub world_to_camera: Option<Matrix4x4>,
 
     /// The matrix that transforms 3D points from the world to the "Normalized Device Coordinate" space.
     /// Left-handed coordinate system, y up, z forward.
+    /// Use `attribute::matrix4x4_to_rows` to access the elements without getting the transposition wrong.
     pub world_to_normalized_device: Option<Matrix4x4>,
 
     /// Specifies whether the pixels in a deep image are sorted and non-overlapping.
@@ -237,12 +249,118 @@ pub struct LayerAttributes {
     /// The field of view angle, along the horizontal axis, in degrees.
     pub vertical_field_of_view: Option<f32>,
 
+    /// The quality level used when writing `DWAA` or `DWAB` compressed pixels,
+    /// where larger values produce smaller files with less precision. The reference default is `45.0`.
+    /// A level inside the `Compression` value takes precedence over this attribute.
+    /// It is kept in the file even if the layer uses another compression.
+    pub dwa_compression_level: Option<f32>,
+
+    /// The name of the reel this image was captured on.
+    pub reel_name: Option<Text>,
+
+    /// The sequential number of this image, for example the frame number,
+    /// as counted by the device that captured it.
+    pub image_counter: Option<i32>,
+
+    /// The JSON representation of an ASC Framing Decision List,
+    /// describing how the image should be framed for display.
+    pub asc_framing_decision_list: Option<Text>,
+
+    /// The manufacturer of the camera.
+    pub camera_make: Option<Text>,
+
+    /// The model name of the camera.
+    pub camera_model: Option<Text>,
+
+    /// The serial number of the camera.
+    pub camera_serial_number: Option<Text>,
+
+    /// The firmware version of the camera.
+    pub camera_firmware_version: Option<Text>,
+
+    /// A unique identifier of the camera, for example a UUID.
+    pub camera_uuid: Option<Text>,
+
+    /// A user-defined name of the camera, for example `"A"` or `"B"` in a multi-camera setup.
+    pub camera_label: Option<Text>,
+
+    /// The correlated color temperature setting of the camera, in Kelvin.
+    pub camera_cct_setting: Option<f32>,
+
+    /// The green/magenta tint setting of the camera.
+    pub camera_tint_setting: Option<f32>,
+
+    /// The chromaticity coordinates of the white balance of the camera, in CIE 1931 (x,y).
+    pub camera_color_balance: Option<Vec2<f32>>,
+
+    /// The manufacturer of the lens.
+    pub lens_make: Option<Text>,
+
+    /// The model name of the lens.
+    pub lens_model: Option<Text>,
+
+    /// The serial number of the lens.
+    pub lens_serial_number: Option<Text>,
+
+    /// The firmware version of the lens.
+    pub lens_firmware_version: Option<Text>,
+
+    /// The focal length of the lens, as written on the lens, in millimeters.
+    pub nominal_focal_length: Option<f32>,
+
+    /// The focal length of the pinhole camera model that describes the lens, in millimeters.
+    pub pinhole_focal_length: Option<f32>,
+
+    /// The focal length of the lens when focused at the current distance, in millimeters.
+    pub effective_focal_length: Option<f32>,
+
+    /// The distance from the image plane to the entrance pupil of the lens, in millimeters.
+    pub entrance_pupil_offset: Option<f32>,
+
+    /// The transmission-corrected aperture of the lens, measured in t-stops.
+    pub t_stop: Option<f32>,
+
+    /// The shutter angle of the camera, in degrees.
+    pub shutter_angle: Option<f32>,
+
+    /// The offset of the center of the sensor from the optical axis of the lens, in millimeters.
+    pub sensor_center_offset: Option<Vec2<f32>>,
+
+    /// The total size of the sensor, in millimeters.
+    pub sensor_overall_dimensions: Option<Vec2<f32>>,
+
+    /// The distance between the centers of two neighbouring photosites of the sensor, in micrometers.
+    pub sensor_photosite_pitch: Option<f32>,
+
+    /// The rectangle of photosites of the sensor that produced the pixels of this image.
+    pub sensor_acquisition_rectangle: Option<IntegerBounds>,
+
+    /// The distance between the two cameras of a stereoscopic rig, in millimeters.
+    pub interocular_distance: Option<f32>,
+
     /// Contains custom attributes.
     /// Does not contain the attributes already present in the `Header` or `LayerAttributes` struct.
     /// Does not contain attributes that are standardized to be the same for all layers: no chromaticities and no time codes.
     pub other: HashMap<Text, AttributeValue>,
 }
 
+/// Creates a consistent `Header`, computing the chunk count and the block description.
+/// Create a builder using `Header::builder(size)`, and call `build()` to obtain the validated header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderBuilder {
+    layer_size: Vec2<usize>,
+    channels: SmallVec<[ChannelDescription; 5]>,
+    compression: Compression,
+    zip_compression_level: Option<u8>,
+    custom_codecs: CodecRegistry,
+    tile_size: Option<Vec2<usize>>,
+    levels: Option<(LevelMode, RoundingMode)>,
+    line_order: LineOrder,
+    max_samples_per_pixel: Option<usize>,
+    shared_attributes: ImageAttributes,
+    own_attributes: LayerAttributes,
+}
+
 
 impl LayerAttributes {
 
@@ -280,6 +398,55 @@ impl LayerAttributes {
             ..self
         }
     }
+
+    /// The height of the screen window, which is not stored in the file.
+    /// The screen window has the same aspect ratio as the display window of the image,
+    /// where the pixel aspect ratio has already been accounted for.
+    pub fn screen_window_height(&self, image: &ImageAttributes) -> f32 {
+        self.screen_window_width / image.display_aspect_ratio()
+    }
+
+    /// The position of the center of a pixel on the screen window of the perspective projection.
+    /// The display window of the image covers the whole screen window,
+    /// such that the left edge of the display window is at `screen_window_center.x - screen_window_width / 2`.
+    /// The y axis of the screen window points upwards, so the top edge of the display window has the largest y value.
+    /// The pixel position is in the same global coordinate system as the display window.
+    pub fn screen_space_of_pixel(&self, image: &ImageAttributes, pixel: Vec2<i32>) -> Vec2<f32> {
+        let (top_left, pixel_size) = self.screen_window_pixel_grid(image);
+        let pixel_center = Vec2(pixel.x() as f32 + 0.5, pixel.y() as f32 + 0.5);
+        let display_position = Vec2(image.display_window.position.x() as f32, image.display_window.position.y() as f32);
+        top_left + (pixel_center - display_position) * pixel_size
+    }
+
+    /// The inverse of `screen_space_of_pixel`: the pixel at a position on the screen window.
+    /// Returns fractional pixel coordinates, where the centers of the pixels are whole numbers.
+    /// Round the result to obtain the pixel that contains the screen position.
+    pub fn pixel_of_screen_space(&self, image: &ImageAttributes, screen_position: Vec2<f32>) -> Vec2<f32> {
+        let (top_left, pixel_size) = self.screen_window_pixel_grid(image);
+        let display_position = Vec2(image.display_window.position.x() as f32, image.display_window.position.y() as f32);
+        (screen_position - top_left) / pixel_size + display_position - Vec2(0.5, 0.5)
+    }
+
+    /// The index of the frame labeled by the time code of the image, counting from `00:00:00:00`,
+    /// at the frame rate of this layer. Returns `None` if either attribute is missing.
+    /// See `TimeCode::to_frame_number` for details.
+    pub fn time_code_frame_number(&self, image: &ImageAttributes) -> Result<Option<u64>> {
+        match (image.time_code, self.frames_per_second) {
+            (Some(time_code), Some(frames_per_second)) => time_code.to_frame_number(frames_per_second).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The screen position of the top left corner of the display window,
+    /// and the signed size of a single pixel on the screen window.
+    fn screen_window_pixel_grid(&self, image: &ImageAttributes) -> (Vec2<f32>, Vec2<f32>) {
+        let size = image.display_window.size;
+        let screen_size = Vec2(self.screen_window_width, self.screen_window_height(image));
+
+        let top_left = self.screen_window_center + Vec2(-screen_size.width(), screen_size.height()) / Vec2(2.0, 2.0);
+        let pixel_size = Vec2(screen_size.width() / size.width() as f32, -screen_size.height() / size.height() as f32);
+        (top_left, pixel_size)
+    }
 }
 
 impl ImageAttributes {
@@ -299,6 +466,34 @@ impl ImageAttributes {
     pub fn with_size(size: impl Into<Vec2<usize>>) -> Self {
         Self::new(IntegerBounds::from_dimensions(size))
     }
+
+    /// The ratio of the width to the height of the image when it is displayed,
+    /// which takes non-square pixels into account.
+    /// For example, an anamorphic plate of 1024×858 pixels with a pixel aspect of 2 is displayed at roughly 2.39:1.
+    pub fn display_aspect_ratio(&self) -> f32 {
+        let size = self.display_window.size;
+        self.pixel_aspect * size.width() as f32 / size.height() as f32
+    }
+
+    /// Combine the image attributes of all headers, as some files store them in only some of the headers.
+    /// Uses the attributes of the first header, adding the attributes that only the other headers contain.
+    /// Returns none if there are no headers.
+    pub fn from_headers(headers: &[Header]) -> Option<Self> {
+        let (first, others) = headers.split_first()?;
+        let mut attributes = first.shared_attributes.clone();
+
+        for header in others {
+            let shared = &header.shared_attributes;
+            attributes.chromaticities = attributes.chromaticities.or(shared.chromaticities);
+            attributes.time_code = attributes.time_code.or(shared.time_code);
+
+            for (name, value) in &shared.other {
+                attributes.other.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+
+        Some(attributes)
+    }
 }
 
 
@@ -328,6 +523,8 @@ impl Header {
         Self {
             layer_size: data_size,
             compression,
+            zip_compression_level: None,
+            custom_codecs: CodecRegistry::default(),
             blocks,
 
             channels: ChannelList::new(channels),
@@ -344,6 +541,28 @@ impl Header {
         }
     }
 
+    /// Start creating a header for a layer of the specified size, computing the derived properties automatically.
+    /// The other settings default to uncompressed scan lines in increasing line order,
+    /// with the display window equal to the data window, and no custom attributes.
+    /// Call `build()` to obtain the validated header.
+    pub fn builder(layer_size: impl Into<Vec2<usize>>) -> HeaderBuilder {
+        let layer_size = layer_size.into();
+
+        HeaderBuilder {
+            layer_size,
+            channels: SmallVec::new(),
+            compression: Compression::Uncompressed,
+            zip_compression_level: None,
+            custom_codecs: CodecRegistry::default(),
+            tile_size: None,
+            levels: None,
+            line_order: LineOrder::Increasing,
+            max_samples_per_pixel: None,
+            shared_attributes: ImageAttributes::with_size(layer_size),
+            own_attributes: LayerAttributes::default(),
+        }
+    }
+
     /// Set the display window, that is, the global clipping rectangle.
     /// __Must be the same for all headers of a file.__
     pub fn with_display_window(mut self, display_window: IntegerBounds) -> Self {
@@ -357,6 +576,44 @@ impl Header {
         self
     }
 
+    /// Set the name of this layer, and rename the channels prefixed with the previous layer name,
+    /// as in `beauty.R` becoming `rgba.R`, keeping the channels sorted.
+    /// Returns an error and leaves the header unchanged if two channels would end up with the same name.
+    pub fn set_layer_name(&mut self, new_name: impl Into<Text>) -> UnitResult {
+        let new_name = new_name.into();
+
+        if let Some(old_name) = &self.own_attributes.layer_name {
+            let with_separator = |name: &Text| {
+                let mut bytes = TextBytes::from_slice(name.as_slice());
+                bytes.push(b'.');
+                Text::from_bytes_unchecked(bytes)
+            };
+
+            let (old_prefix, new_prefix) = (with_separator(old_name), with_separator(&new_name));
+
+            let (_, renamed) = rename_and_sort(self.channels.list.iter().map(|channel| &channel.name), &old_prefix, &new_prefix)?;
+
+            self.channels.list = renamed.into_iter()
+                .map(|(index, name)| ChannelDescription { name, .. self.channels.list[index].clone() })
+                .collect();
+        }
+
+        self.own_attributes.layer_name = Some(new_name);
+        Ok(())
+    }
+
+    /// Update all fields that are computed from other fields, after the header has been modified directly.
+    /// Computes the chunk count from the compression, the blocks, and the layer size,
+    /// which also accounts for the scan lines per block and the resolution levels.
+    /// Also computes the byte size and the uniform sample type of the channel list.
+    /// Called automatically before writing a file.
+    pub fn recompute_derived_fields(&mut self) {
+        self.chunk_count = compute_chunk_count(self.compression, self.layer_size, self.blocks);
+
+        let channels = std::mem::take(&mut self.channels.list);
+        self.channels = ChannelList::new(channels);
+    }
+
     /// Set compression, tiling, and line order. Automatically computes chunk count.
     pub fn with_encoding(self, compression: Compression, blocks: BlockDescription, line_order: LineOrder) -> Self {
         Self {
@@ -366,6 +623,37 @@ impl Header {
         }
     }
 
+    /// Set the deflate level used when writing `ZIP1` or `ZIP16` compressed pixels,
+    /// from 0 (fastest) to 9 (smallest). Does not affect how the pixels are read.
+    pub fn with_zip_compression_level(self, level: u8) -> Self {
+        Self { zip_compression_level: Some(level), .. self }
+    }
+
+    /// The deflate level used when writing `ZIP1` or `ZIP16` compressed pixels,
+    /// from 0 (fastest) to 9 (smallest). If not specified, level 4 is used.
+    /// This is not stored in the file, as decompressing does not depend on it.
+    pub fn zip_compression_level(&self) -> Option<u8> {
+        self.zip_compression_level
+    }
+
+    /// Set the codecs used if the compression of this layer is `Compression::Custom`.
+    /// __Custom compression methods are not part of the OpenEXR standard.__
+    pub fn with_custom_codecs(self, custom_codecs: CodecRegistry) -> Self {
+        Self { custom_codecs, .. self }
+    }
+
+    /// Store deep data in this layer, where each pixel contains any number of samples,
+    /// but not more than the specified maximum. Deep data can only be compressed
+    /// using `RLE`, `ZIP1` or `ZIP16`, or not at all, and cannot have subsampled channels.
+    pub fn with_deep_data(self, max_samples_per_pixel: usize) -> Self {
+        Self {
+            deep: true,
+            deep_data_version: Some(1),
+            max_samples_per_pixel: Some(max_samples_per_pixel),
+            .. self
+        }
+    }
+
     /// Set **all** attributes of the header that are not shared with all other headers in the image.
     pub fn with_attributes(self, own_attributes: LayerAttributes) -> Self {
         Self { own_attributes, .. self }
@@ -474,6 +762,38 @@ impl Header {
         })
     }*/
 
+    /// Whether this header contains the layer with the specified name.
+    /// This is the case if the header has this layer name attribute,
+    /// or if any channel name is prefixed with the layer name, as in `diffuse.R`.
+    pub fn has_layer_name(&self, layer_name: &str) -> bool {
+        let is_header_name = self.own_attributes.layer_name.as_ref()
+            .map_or(false, |name| name.eq(layer_name));
+
+        is_header_name || self.channels.list.iter().any(|channel|{
+            let channel_name = channel.name.bytes();
+            channel_name.len() > layer_name.len() + 1
+                && channel_name.starts_with(layer_name.as_bytes())
+                && channel_name[layer_name.len()] == b'.'
+        })
+    }
+
+    /// The names of the layers in this header, which are accepted by `has_layer_name`.
+    /// Contains the layer name attribute, followed by each distinct channel name prefix, such as `diffuse` in `diffuse.R`.
+    pub fn layer_names(&self) -> Vec<Text> {
+        let mut names: Vec<Text> = self.own_attributes.layer_name.iter().cloned().collect();
+
+        for channel in &self.channels.list {
+            let channel_name = channel.name.bytes();
+
+            if let Some(separator) = channel_name.iter().rposition(|&byte| byte == b'.') {
+                let prefix = Text::from_slice_unchecked(&channel_name[.. separator]);
+                if separator > 0 && !names.contains(&prefix) { names.push(prefix); }
+            }
+        }
+
+        names
+    }
+
     // TODO reuse this function everywhere
     /// The default pixel resolution of a single block (tile or scan line block).
     /// Not all blocks have this size, because they may be cutoff at the end of the image.
@@ -547,7 +867,13 @@ impl Header {
                 }
             },
 
-            _ => return Err(Error::unsupported("deep data not supported yet"))
+            CompressedBlock::DeepTile(ref tile) => {
+                tile.coordinates
+            },
+
+            CompressedBlock::DeepScanLine(ref block) => {
+                self.get_scan_line_block_tile_coordinates(block.y_coordinate)?
+            },
         })
     }
 
@@ -577,43 +903,91 @@ impl Header {
         }
     }
 
+    /// Maximum byte length of the sample data or of the sample count table in a deep block.
+    /// Depends on the maximum number of samples per pixel, and is not limited if that number is unknown.
+    pub fn max_deep_block_byte_size(&self) -> usize {
+        let max_samples_per_pixel = self.max_samples_per_pixel.unwrap_or(usize::MAX);
+        let bytes_per_pixel = self.channels.bytes_per_pixel.max(std::mem::size_of::<i32>());
+        (bytes_per_pixel * self.max_block_pixel_size().area()).saturating_mul(max_samples_per_pixel)
+    }
+
     /// Returns the number of bytes that the pixels of this header will require
     /// when stored without compression. Respects multi-resolution levels and subsampling.
     pub fn total_pixel_bytes(&self) -> usize {
         assert!(!self.deep);
 
-        let pixel_count_of_levels = |size: Vec2<usize>| -> usize {
-            match self.blocks {
-                BlockDescription::ScanLines => size.area(),
-                BlockDescription::Tiles(tile_description) => match tile_description.level_mode {
-                    LevelMode::Singular => size.area(),
-
-                    LevelMode::MipMap => mip_map_levels(tile_description.rounding_mode, size)
-                        .map(|(_, size)| size.area()).sum(),
-
-                    LevelMode::RipMap => rip_map_levels(tile_description.rounding_mode, size)
-                        .map(|(_, size)| size.area()).sum(),
-                }
-            }
-        };
-
         self.channels.list.iter()
             .map(|channel: &ChannelDescription|
-                pixel_count_of_levels(channel.subsampled_resolution(self.layer_size)) * channel.sample_type.bytes_per_sample()
+                self.pixel_count_of_all_levels(channel.subsampled_resolution(self.layer_size)) * channel.sample_type.bytes_per_sample()
             )
             .sum()
 
     }
 
+    /// Returns an upper bound for the number of bytes that the pixels of this header
+    /// will require in memory after decompressing, summing all resolution levels and respecting subsampling.
+    /// For flat images, this is exactly `total_pixel_bytes`.
+    /// Deep images also contain a sample count for each pixel and may contain any number of samples per pixel,
+    /// so the bound saturates at `usize::MAX` unless the maximum number of samples per pixel is known.
+    pub fn total_uncompressed_pixel_bytes(&self) -> usize {
+        if !self.deep { return self.total_pixel_bytes(); }
+
+        let pixel_count = self.pixel_count_of_all_levels(self.layer_size);
+        let sample_count_table_bytes = pixel_count.saturating_mul(std::mem::size_of::<i32>());
+        let max_sample_count = pixel_count.saturating_mul(self.max_samples_per_pixel.unwrap_or(usize::MAX));
+        max_sample_count.saturating_mul(self.channels.bytes_per_pixel).saturating_add(sample_count_table_bytes)
+    }
+
+    /// Returns an upper bound for the number of bytes that the pixels of this header will occupy in a file,
+    /// including the offset table and the header of each chunk.
+    /// Any compression method stores a chunk without compression if compressing does not make it smaller,
+    /// so this bound holds for the compression method of this header, and also for any other compression method.
+    /// Saturates at `usize::MAX` for deep data, unless the maximum number of samples per pixel is known.
+    pub fn worst_case_compressed_bytes(&self) -> usize {
+        // at most 64 bytes for the offset table entry, layer index, block coordinates, and byte sizes of each chunk
+        self.chunk_count.saturating_mul(64)
+            .saturating_add(self.total_uncompressed_pixel_bytes())
+    }
+
+    /// The number of pixels in all resolution levels of a channel with the specified full resolution.
+    fn pixel_count_of_all_levels(&self, size: Vec2<usize>) -> usize {
+        match self.blocks {
+            BlockDescription::ScanLines => size.area(),
+            BlockDescription::Tiles(tile_description) => match tile_description.level_mode {
+                LevelMode::Singular => size.area(),
+
+                LevelMode::MipMap => mip_map_levels(tile_description.rounding_mode, size)
+                    .map(|(_, size)| size.area()).sum(),
+
+                LevelMode::RipMap => rip_map_levels(tile_description.rounding_mode, size)
+                    .map(|(_, size)| size.area()).sum(),
+            }
+        }
+    }
+
     /// Approximates the maximum number of bytes that the pixels of this header will consume in a file.
     /// Due to compression, the actual byte size may be smaller.
+    /// Deep pixels may contain any number of samples, so this is not limited for deep data
+    /// unless the maximum number of samples per pixel is known.
     pub fn max_pixel_file_bytes(&self) -> usize {
-        assert!(!self.deep);
+        if self.deep {
+            // each deep chunk contains both the sample count table and the samples
+            return self.max_deep_block_byte_size()
+                .saturating_mul(2 * self.chunk_count)
+                .saturating_add(self.chunk_count * 64);
+        }
 
         self.chunk_count * 64 // at most 64 bytes overhead for each chunk (header index, tile description, chunk size, and more)
             + self.total_pixel_bytes()
     }
 
+    /// Returns a warning for each channel that would lose precision
+    /// or would not be compressed at all with the compression method of this header,
+    /// for example an `f32` channel with `PXR24`. These channels can still be written.
+    pub fn compression_warnings(&self) -> Vec<CompressionWarning> {
+        self.compression.warnings_for_channels(&self.channels)
+    }
+
     /// Validate this instance.
     pub fn validate(&self, is_multilayer: bool, long_names: &mut bool, strict: bool) -> UnitResult {
 
@@ -651,6 +1025,14 @@ impl Header {
         let allow_subsampling = !self.deep && self.blocks == BlockDescription::ScanLines;
         self.channels.validate(allow_subsampling, self.data_window(), strict)?;
 
+        if let Some(time_code) = self.shared_attributes.time_code {
+            time_code.validate(strict)?;
+        }
+
+        if let Some(key_code) = self.own_attributes.film_key_code {
+            key_code.validate(strict)?;
+        }
+
         for (name, value) in &self.shared_attributes.other {
             attribute::validate(name, value, long_names, allow_subsampling, self.data_window(), strict)?;
         }
@@ -660,8 +1042,20 @@ impl Header {
         }
 
         // this is only to check whether someone tampered with our precious values, to avoid writing an invalid file
-        if self.chunk_count != compute_chunk_count(self.compression, self.layer_size, self.blocks) {
-            return Err(Error::invalid("chunk count attribute")); // TODO this may be an expensive check?
+        let expected_chunk_count = compute_chunk_count(self.compression, self.layer_size, self.blocks);
+        if self.chunk_count != expected_chunk_count { // TODO this may be an expensive check?
+            return Err(Error::invalid(format!(
+                "chunk count attribute is {} instead of {}, use `Header::recompute_derived_fields` after modifying the header",
+                self.chunk_count, expected_chunk_count
+            )));
+        }
+
+        if let Compression::Custom(id) = self.compression {
+            if id <= crate::compression::MAX_RESERVED_ID { return Err(Error::invalid("custom compression id")); }
+        }
+
+        if self.zip_compression_level.map_or(false, |level| level > 9) {
+            return Err(Error::invalid("zip compression level"));
         }
 
         // check if attribute names appear twice
@@ -672,12 +1066,13 @@ impl Header {
                 }
             }
 
-            for &reserved in header::standard_names::ALL.iter() {
+            // a reserved name may only be custom if the header does not write that attribute itself,
+            // for example an attribute of an unusual type that was read from another file
+            for (reserved, _) in self.typed_named_attributes() {
                 let name  = Text::from_bytes_unchecked(SmallVec::from_slice(reserved));
                 if self.own_attributes.other.contains_key(&name) || self.shared_attributes.other.contains_key(&name) {
                     return Err(Error::invalid(format!(
-                        "attribute name `{}` is reserved and cannot be custom",
-                        Text::from_bytes_unchecked(reserved.into())
+                        "attribute name `{}` is reserved and cannot be custom", name
                     )));
                 }
             }
@@ -709,15 +1104,19 @@ impl Header {
     }
 
     /// Read the headers without validating them.
-    pub fn read_all(read: &mut PeekRead<impl Read>, version: &Requirements, pedantic: bool) -> Result<Headers> {
+    pub fn read_all(read: &mut PeekRead<impl Read>, version: &Requirements, validation: impl Into<ValidationOptions>) -> Result<Headers> {
+        let validation = validation.into();
+
         if !version.is_multilayer() {
-            Ok(smallvec![ Header::read(read, version, pedantic)? ])
+            Ok(smallvec![ Header::read(read, version, validation)? ])
         }
         else {
             let mut headers = SmallVec::new();
 
+            // the byte size limit applies to all headers together
+            let mut remaining_header_bytes = validation.limits.max_header_byte_size;
             while !sequence_end::has_come(read)? {
-                headers.push(Header::read(read, version, pedantic)?);
+                headers.push(Header::read_with_byte_limit(read, version, validation, &mut remaining_header_bytes)?);
             }
 
             Ok(headers)
@@ -745,6 +1144,17 @@ impl Header {
     // This function is used for writing the attributes to files.
     #[inline]
     pub fn all_named_attributes(&self) -> impl '_ + Iterator<Item=(&TextSlice, AttributeValue)> {
+        let other = self.own_attributes.other.iter()
+            .chain(self.shared_attributes.other.iter())
+            .map(|(name, val)| (name.as_slice(), val.clone())); // TODO no clone
+
+        self.typed_named_attributes().chain(other)
+    }
+
+    /// Iterate over the `(name, attribute_value)` pairs in this header that would be written to a file,
+    /// excluding the custom attributes in the `other` maps.
+    #[inline]
+    fn typed_named_attributes(&self) -> impl '_ + Iterator<Item=(&TextSlice, AttributeValue)> {
         use std::iter::{once, once_with, empty};
         use crate::meta::header::standard_names::*;
         use AttributeValue::*;
@@ -797,6 +1207,8 @@ impl Header {
 
         let block_type_and_tiles = expect_is_iter(once_with(move ||{
             let (block_type, tiles) = match self.blocks {
+                BlockDescription::ScanLines if self.deep => (attribute::BlockType::DeepScanLine, None),
+                BlockDescription::Tiles(tiles) if self.deep => (attribute::BlockType::DeepTile, Some(tiles)),
                 BlockDescription::ScanLines => (attribute::BlockType::ScanLine, None),
                 BlockDescription::Tiles(tiles) => (attribute::BlockType::Tile, Some(tiles))
             };
@@ -812,13 +1224,13 @@ impl Header {
         // dwa writes compression parameters as attribute.
         let dwa_compr_level = expect_is_iter(
             once_with(move ||{
-                match self.compression {
+                let level = match self.compression {
                     attribute::Compression::DWAA(Some(level)) |
-                    attribute::Compression::DWAB(Some(level)) =>
-                        Some((DWA_COMPRESSION_LEVEL, F32(level))),
+                    attribute::Compression::DWAB(Some(level)) => Some(level),
+                    _ => self.own_attributes.dwa_compression_level
+                };
 
-                    _ => None
-                }
+                level.map(|level| (DWA_COMPRESSION_LEVEL, F32(level)))
             }).flatten()
         );
 
@@ -877,21 +1289,55 @@ impl Header {
             FAR: F32 = &self.own_attributes.far_clip_plane,
             FOV_X: F32 = &self.own_attributes.horizontal_field_of_view,
             FOV_Y: F32 = &self.own_attributes.vertical_field_of_view,
-            SOFTWARE: Text = &self.own_attributes.software_name
+            SOFTWARE: Text = &self.own_attributes.software_name,
+
+            REEL_NAME: Text = &self.own_attributes.reel_name,
+            IMAGE_COUNTER: I32 = &self.own_attributes.image_counter,
+            ASC_FRAMING_DECISION_LIST: Text = &self.own_attributes.asc_framing_decision_list,
+            CAMERA_MAKE: Text = &self.own_attributes.camera_make,
+            CAMERA_MODEL: Text = &self.own_attributes.camera_model,
+            CAMERA_SERIAL_NUMBER: Text = &self.own_attributes.camera_serial_number,
+            CAMERA_FIRMWARE_VERSION: Text = &self.own_attributes.camera_firmware_version,
+            CAMERA_UUID: Text = &self.own_attributes.camera_uuid,
+            CAMERA_LABEL: Text = &self.own_attributes.camera_label,
+            CAMERA_CCT_SETTING: F32 = &self.own_attributes.camera_cct_setting,
+            CAMERA_TINT_SETTING: F32 = &self.own_attributes.camera_tint_setting,
+            CAMERA_COLOR_BALANCE: FloatVec2 = &self.own_attributes.camera_color_balance,
+            LENS_MAKE: Text = &self.own_attributes.lens_make,
+            LENS_MODEL: Text = &self.own_attributes.lens_model,
+            LENS_SERIAL_NUMBER: Text = &self.own_attributes.lens_serial_number,
+            LENS_FIRMWARE_VERSION: Text = &self.own_attributes.lens_firmware_version,
+            NOMINAL_FOCAL_LENGTH: F32 = &self.own_attributes.nominal_focal_length,
+            PINHOLE_FOCAL_LENGTH: F32 = &self.own_attributes.pinhole_focal_length,
+            EFFECTIVE_FOCAL_LENGTH: F32 = &self.own_attributes.effective_focal_length,
+            ENTRANCE_PUPIL_OFFSET: F32 = &self.own_attributes.entrance_pupil_offset,
+            T_STOP: F32 = &self.own_attributes.t_stop,
+            SHUTTER_ANGLE: F32 = &self.own_attributes.shutter_angle,
+            SENSOR_CENTER_OFFSET: FloatVec2 = &self.own_attributes.sensor_center_offset,
+            SENSOR_OVERALL_DIMENSIONS: FloatVec2 = &self.own_attributes.sensor_overall_dimensions,
+            SENSOR_PHOTOSITE_PITCH: F32 = &self.own_attributes.sensor_photosite_pitch,
+            SENSOR_ACQUISITION_RECTANGLE: IntegerBounds = &self.own_attributes.sensor_acquisition_rectangle,
+            INTEROCULAR_DISTANCE: F32 = &self.own_attributes.interocular_distance
         );
 
-        let other = self.own_attributes.other.iter()
-            .chain(self.shared_attributes.other.iter())
-            .map(|(name, val)| (name.as_slice(), val.clone())); // TODO no clone
-
         req_core_attrs
             .chain(opt_core_attrs)
             .chain(opt_attr)
-            .chain(other)
     }
 
     /// Read the value without validating.
-    pub fn read(read: &mut PeekRead<impl Read>, requirements: &Requirements, pedantic: bool) -> Result<Self> {
+    pub fn read(read: &mut PeekRead<impl Read>, requirements: &Requirements, validation: impl Into<ValidationOptions>) -> Result<Self> {
+        let validation = validation.into();
+        Self::read_with_byte_limit(read, requirements, validation, &mut validation.limits.max_header_byte_size.clone())
+    }
+
+    /// Read the value without validating, subtracting the byte size of each attribute from the remaining header bytes.
+    fn read_with_byte_limit(
+        read: &mut PeekRead<impl Read>, requirements: &Requirements,
+        validation: ValidationOptions, remaining_header_bytes: &mut usize
+    ) -> Result<Self>
+    {
+        let limits = validation.limits;
         let max_string_len = if requirements.has_long_names { 256 } else { 32 }; // TODO DRY this information
 
         // these required attributes will be filled when encountered while parsing
@@ -909,10 +1355,15 @@ impl Header {
 
         let mut layer_attributes = LayerAttributes::default();
         let mut image_attributes = ImageAttributes::new(IntegerBounds::zero());
+        let mut attribute_names = HashSet::new();
 
         // read each attribute in this header
         while !sequence_end::has_come(read)? {
-            let (attribute_name, value) = attribute::read(read, max_string_len)?;
+            let (attribute_name, value) = attribute::read_limited(read, max_string_len, &limits, remaining_header_bytes)?;
+
+            if !validation.allow_duplicate_attributes && !attribute_names.insert(attribute_name.clone()) {
+                return Err(Error::invalid(format!("duplicate attribute name: `{}`", attribute_name)));
+            }
 
             // if the attribute value itself is ok, record it
             match value {
@@ -972,7 +1423,10 @@ impl Header {
                         (name::WORLD_TO_NDC, Matrix4x4(value)) => layer_attributes.world_to_normalized_device = Some(value),
                         (name::DEEP_IMAGE_STATE, Rational(value)) => layer_attributes.deep_image_state = Some(value),
                         (name::ORIGINAL_DATA_WINDOW, IntegerBounds(value)) => layer_attributes.original_data_window = Some(value),
-                        (name::DWA_COMPRESSION_LEVEL, F32(value)) => dwa_compression_level = Some(value),
+                        (name::DWA_COMPRESSION_LEVEL, F32(value)) => {
+                            dwa_compression_level = Some(value);
+                            layer_attributes.dwa_compression_level = Some(value);
+                        },
                         (name::PREVIEW, Preview(value)) => layer_attributes.preview = Some(value),
                         (name::VIEW, Text(value)) => layer_attributes.view_name = Some(value),
 
@@ -982,6 +1436,34 @@ impl Header {
                         (name::FOV_Y, F32(value)) => layer_attributes.vertical_field_of_view = Some(value),
                         (name::SOFTWARE, Text(value)) => layer_attributes.software_name = Some(value),
 
+                        (name::REEL_NAME, Text(value)) => layer_attributes.reel_name = Some(value),
+                        (name::IMAGE_COUNTER, I32(value)) => layer_attributes.image_counter = Some(value),
+                        (name::ASC_FRAMING_DECISION_LIST, Text(value)) => layer_attributes.asc_framing_decision_list = Some(value),
+                        (name::CAMERA_MAKE, Text(value)) => layer_attributes.camera_make = Some(value),
+                        (name::CAMERA_MODEL, Text(value)) => layer_attributes.camera_model = Some(value),
+                        (name::CAMERA_SERIAL_NUMBER, Text(value)) => layer_attributes.camera_serial_number = Some(value),
+                        (name::CAMERA_FIRMWARE_VERSION, Text(value)) => layer_attributes.camera_firmware_version = Some(value),
+                        (name::CAMERA_UUID, Text(value)) => layer_attributes.camera_uuid = Some(value),
+                        (name::CAMERA_LABEL, Text(value)) => layer_attributes.camera_label = Some(value),
+                        (name::CAMERA_CCT_SETTING, F32(value)) => layer_attributes.camera_cct_setting = Some(value),
+                        (name::CAMERA_TINT_SETTING, F32(value)) => layer_attributes.camera_tint_setting = Some(value),
+                        (name::CAMERA_COLOR_BALANCE, FloatVec2(value)) => layer_attributes.camera_color_balance = Some(value),
+                        (name::LENS_MAKE, Text(value)) => layer_attributes.lens_make = Some(value),
+                        (name::LENS_MODEL, Text(value)) => layer_attributes.lens_model = Some(value),
+                        (name::LENS_SERIAL_NUMBER, Text(value)) => layer_attributes.lens_serial_number = Some(value),
+                        (name::LENS_FIRMWARE_VERSION, Text(value)) => layer_attributes.lens_firmware_version = Some(value),
+                        (name::NOMINAL_FOCAL_LENGTH, F32(value)) => layer_attributes.nominal_focal_length = Some(value),
+                        (name::PINHOLE_FOCAL_LENGTH, F32(value)) => layer_attributes.pinhole_focal_length = Some(value),
+                        (name::EFFECTIVE_FOCAL_LENGTH, F32(value)) => layer_attributes.effective_focal_length = Some(value),
+                        (name::ENTRANCE_PUPIL_OFFSET, F32(value)) => layer_attributes.entrance_pupil_offset = Some(value),
+                        (name::T_STOP, F32(value)) => layer_attributes.t_stop = Some(value),
+                        (name::SHUTTER_ANGLE, F32(value)) => layer_attributes.shutter_angle = Some(value),
+                        (name::SENSOR_CENTER_OFFSET, FloatVec2(value)) => layer_attributes.sensor_center_offset = Some(value),
+                        (name::SENSOR_OVERALL_DIMENSIONS, FloatVec2(value)) => layer_attributes.sensor_overall_dimensions = Some(value),
+                        (name::SENSOR_PHOTOSITE_PITCH, F32(value)) => layer_attributes.sensor_photosite_pitch = Some(value),
+                        (name::SENSOR_ACQUISITION_RECTANGLE, IntegerBounds(value)) => layer_attributes.sensor_acquisition_rectangle = Some(value),
+                        (name::INTEROCULAR_DISTANCE, F32(value)) => layer_attributes.interocular_distance = Some(value),
+
                         (name::PIXEL_ASPECT, F32(value)) => image_attributes.pixel_aspect = value,
                         (name::TIME_CODE, TimeCode(value)) => image_attributes.time_code = Some(value),
                         (name::CHROMATICITIES, Chromaticities(value)) => image_attributes.chromaticities = Some(value),
@@ -1004,7 +1486,9 @@ impl Header {
                 // in case the attribute value itself is not ok, but the rest of the image is
                 // only abort reading the image if desired
                 Err(error) => {
-                    if pedantic { return Err(error); }
+                    // a layer cannot be read without its channels anyway,
+                    // and this also reports exceeding the channel count limit
+                    if validation.strict_attributes || attribute_name.as_slice() == standard_names::CHANNELS { return Err(error); }
                 }
             }
         }
@@ -1014,7 +1498,6 @@ impl Header {
             (Some(level), Some(Compression::DWAA(_))) => Some(Compression::DWAA(Some(level))),
             (Some(level), Some(Compression::DWAB(_))) => Some(Compression::DWAB(Some(level))),
             (_, other) => other,
-            // FIXME dwa compression level gets lost if any other compression is used later in the process
         };
 
         let compression = compression.ok_or(missing_attribute("compression"))?;
@@ -1022,6 +1505,9 @@ impl Header {
 
         let data_window = data_window.ok_or(missing_attribute("data window"))?;
         data_window.validate(None)?; // validate now to avoid errors when computing the chunk_count
+
+        let image_area = data_window.size.width().checked_mul(data_window.size.height()).unwrap_or(usize::MAX);
+        ReadLimits::check(image_area, limits.max_image_area, "max_image_area")?;
         layer_attributes.layer_position = data_window.position;
 
 
@@ -1039,12 +1525,17 @@ impl Header {
         };
 
         let computed_chunk_count = compute_chunk_count(compression, data_window.size, blocks);
-        if chunk_count.is_some() && pedantic && chunk_count != Some(computed_chunk_count) {
+        if chunk_count.is_some() && validation.strict_attributes && chunk_count != Some(computed_chunk_count) {
             return Err(Error::invalid("chunk count not matching data size"));
         }
 
+        let channels = channels.ok_or(missing_attribute("channels"))?;
+        let channels = if validation.normalize_channel_lists { channels.normalized(true).0 } else { channels };
+
         let header = Header {
             compression,
+            zip_compression_level: None,
+            custom_codecs: CodecRegistry::default(),
 
             // always compute ourselves, because we cannot trust anyone out there 😱
             chunk_count: computed_chunk_count,
@@ -1054,7 +1545,7 @@ impl Header {
             shared_attributes: image_attributes,
             own_attributes: layer_attributes,
 
-            channels: channels.ok_or(missing_attribute("channels"))?,
+            channels,
             line_order: line_order.unwrap_or(LineOrder::Unspecified),
 
             blocks,
@@ -1083,6 +1574,138 @@ impl Header {
     }
 }
 
+impl HeaderBuilder {
+
+    /// Set the channels of the layer. The channels are sorted by name.
+    pub fn channels(self, channels: impl IntoIterator<Item=ChannelDescription>) -> Self {
+        let mut channels: SmallVec<[ChannelDescription; 5]> = channels.into_iter().collect();
+        channels.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        Self { channels, .. self }
+    }
+
+    /// Set how the pixels of the layer are compressed. Default is `Compression::Uncompressed`.
+    pub fn compression(self, compression: Compression) -> Self {
+        Self { compression, .. self }
+    }
+
+    /// Set the deflate level used when writing `ZIP1` or `ZIP16` compressed pixels,
+    /// from 0 (fastest) to 9 (smallest). Does not affect how the pixels are read.
+    pub fn zip_compression_level(self, level: u8) -> Self {
+        Self { zip_compression_level: Some(level), .. self }
+    }
+
+    /// Set the codecs used if the compression of this layer is `Compression::Custom`.
+    /// __Custom compression methods are not part of the OpenEXR standard.__
+    pub fn custom_codecs(self, custom_codecs: CodecRegistry) -> Self {
+        Self { custom_codecs, .. self }
+    }
+
+    /// Divide the layer into scan line blocks. This is the default.
+    pub fn scan_lines(self) -> Self {
+        Self { tile_size: None, .. self }
+    }
+
+    /// Divide the layer into tiles of the specified size.
+    pub fn tiles(self, tile_size: impl Into<Vec2<usize>>) -> Self {
+        Self { tile_size: Some(tile_size.into()), .. self }
+    }
+
+    /// Store multiple resolution levels, such as mip maps or rip maps, in the tiles of this layer.
+    /// Only tiled layers can contain multiple resolution levels, see `tiles`.
+    /// By default, only a single resolution level is stored.
+    pub fn levels(self, level_mode: LevelMode, rounding_mode: RoundingMode) -> Self {
+        Self { levels: Some((level_mode, rounding_mode)), .. self }
+    }
+
+    /// Set the order of the blocks in the file. Default is `LineOrder::Increasing`.
+    pub fn line_order(self, line_order: LineOrder) -> Self {
+        Self { line_order, .. self }
+    }
+
+    /// Store deep data in this layer, where each pixel contains any number of samples,
+    /// but not more than the specified maximum.
+    pub fn deep_data(self, max_samples_per_pixel: usize) -> Self {
+        Self { max_samples_per_pixel: Some(max_samples_per_pixel), .. self }
+    }
+
+    /// Set **all** attributes of the layer, including its name and position.
+    /// Call this before setting the name or position separately.
+    pub fn attributes(self, own_attributes: LayerAttributes) -> Self {
+        Self { own_attributes, .. self }
+    }
+
+    /// Set **all** attributes that are shared with all other headers in the image, including the display window.
+    /// Call this before setting the display window separately.
+    pub fn shared_attributes(self, shared_attributes: ImageAttributes) -> Self {
+        Self { shared_attributes, .. self }
+    }
+
+    /// Set the name of the layer, which is required if the image contains multiple layers.
+    pub fn layer_name(mut self, name: impl Into<Text>) -> Self {
+        self.own_attributes.layer_name = Some(name.into());
+        self
+    }
+
+    /// Set the offset of this layer.
+    pub fn position(mut self, position: impl Into<Vec2<i32>>) -> Self {
+        self.own_attributes.layer_position = position.into();
+        self
+    }
+
+    /// Set the display window, that is, the global clipping rectangle.
+    /// __Must be the same for all headers of a file.__
+    /// By default, the display window starts at zero and has the size of the layer.
+    pub fn display_window(mut self, display_window: IntegerBounds) -> Self {
+        self.shared_attributes.display_window = display_window;
+        self
+    }
+
+    /// Compute the block description and the chunk count, and validate the header.
+    /// Returns an error if the header could not be written to a file,
+    /// for example if it has no channels, or if scan lines are combined with multiple resolution levels.
+    pub fn build(self) -> Result<Header> {
+        let blocks = match (self.tile_size, self.levels) {
+            (None, None) => BlockDescription::ScanLines,
+
+            (None, Some(_)) => return Err(Error::invalid(
+                "multiple resolution levels require tiles instead of scan lines"
+            )),
+
+            (Some(tile_size), levels) => {
+                let (level_mode, rounding_mode) = levels.unwrap_or((LevelMode::Singular, RoundingMode::Down));
+                BlockDescription::Tiles(TileDescription { tile_size, level_mode, rounding_mode })
+            },
+        };
+
+        if self.channels.is_empty() {
+            return Err(missing_attribute("channels"));
+        }
+
+        let header = Header {
+            channels: ChannelList::new(self.channels),
+            compression: self.compression,
+            zip_compression_level: self.zip_compression_level,
+            custom_codecs: self.custom_codecs,
+
+            chunk_count: compute_chunk_count(self.compression, self.layer_size, blocks),
+            blocks,
+
+            line_order: self.line_order,
+            layer_size: self.layer_size,
+
+            shared_attributes: self.shared_attributes,
+            own_attributes: self.own_attributes,
+
+            deep: self.max_samples_per_pixel.is_some(),
+            deep_data_version: self.max_samples_per_pixel.map(|_| 1),
+            max_samples_per_pixel: self.max_samples_per_pixel,
+        };
+
+        header.validate(false, &mut false, true)?;
+        Ok(header)
+    }
+}
+
 
 
 /// Collection of required attribute names.
@@ -1151,7 +1774,34 @@ pub mod standard_names {
         FAR: b"far",
         FOV_X: b"fieldOfViewHorizontal",
         FOV_Y: b"fieldOfViewVertical",
-        SOFTWARE: b"software"
+        SOFTWARE: b"software",
+        REEL_NAME: b"reelName",
+        IMAGE_COUNTER: b"imageCounter",
+        ASC_FRAMING_DECISION_LIST: b"ascFramingDecisionList",
+        CAMERA_MAKE: b"cameraMake",
+        CAMERA_MODEL: b"cameraModel",
+        CAMERA_SERIAL_NUMBER: b"cameraSerialNumber",
+        CAMERA_FIRMWARE_VERSION: b"cameraFirmwareVersion",
+        CAMERA_UUID: b"cameraUuid",
+        CAMERA_LABEL: b"cameraLabel",
+        CAMERA_CCT_SETTING: b"cameraCCTSetting",
+        CAMERA_TINT_SETTING: b"cameraTintSetting",
+        CAMERA_COLOR_BALANCE: b"cameraColorBalance",
+        LENS_MAKE: b"lensMake",
+        LENS_MODEL: b"lensModel",
+        LENS_SERIAL_NUMBER: b"lensSerialNumber",
+        LENS_FIRMWARE_VERSION: b"lensFirmwareVersion",
+        NOMINAL_FOCAL_LENGTH: b"nominalFocalLength",
+        PINHOLE_FOCAL_LENGTH: b"pinholeFocalLength",
+        EFFECTIVE_FOCAL_LENGTH: b"effectiveFocalLength",
+        ENTRANCE_PUPIL_OFFSET: b"entrancePupilOffset",
+        T_STOP: b"tStop",
+        SHUTTER_ANGLE: b"shutterAngle",
+        SENSOR_CENTER_OFFSET: b"sensorCenterOffset",
+        SENSOR_OVERALL_DIMENSIONS: b"sensorOverallDimensions",
+        SENSOR_PHOTOSITE_PITCH: b"sensorPhotositePitch",
+        SENSOR_ACQUISITION_RECTANGLE: b"sensorAcquisitionRectangle",
+        INTEROCULAR_DISTANCE: b"interocularDistance"
     }
 }
 
@@ -1195,6 +1845,34 @@ impl Default for LayerAttributes {
             far_clip_plane: None,
             horizontal_field_of_view: None,
             vertical_field_of_view: None,
+            dwa_compression_level: None,
+            reel_name: None,
+            image_counter: None,
+            asc_framing_decision_list: None,
+            camera_make: None,
+            camera_model: None,
+            camera_serial_number: None,
+            camera_firmware_version: None,
+            camera_uuid: None,
+            camera_label: None,
+            camera_cct_setting: None,
+            camera_tint_setting: None,
+            camera_color_balance: None,
+            lens_make: None,
+            lens_model: None,
+            lens_serial_number: None,
+            lens_firmware_version: None,
+            nominal_focal_length: None,
+            pinhole_focal_length: None,
+            effective_focal_length: None,
+            entrance_pupil_offset: None,
+            t_stop: None,
+            shutter_angle: None,
+            sensor_center_offset: None,
+            sensor_overall_dimensions: None,
+            sensor_photosite_pitch: None,
+            sensor_acquisition_rectangle: None,
+            interocular_distance: None,
             other: Default::default()
         }
     }
@@ -1234,7 +1912,18 @@ impl std::fmt::Debug for LayerAttributes {
             deep_image_state, original_data_window,
             preview, view_name,
             vertical_field_of_view, horizontal_field_of_view,
-            near_clip_plane, far_clip_plane, software_name
+            near_clip_plane, far_clip_plane, software_name,
+            dwa_compression_level,
+            reel_name, image_counter, asc_framing_decision_list,
+            camera_make, camera_model, camera_serial_number,
+            camera_firmware_version, camera_uuid, camera_label,
+            camera_cct_setting, camera_tint_setting, camera_color_balance,
+            lens_make, lens_model, lens_serial_number, lens_firmware_version,
+            nominal_focal_length, pinhole_focal_length, effective_focal_length,
+            entrance_pupil_offset, t_stop, shutter_angle,
+            sensor_center_offset, sensor_overall_dimensions,
+            sensor_photosite_pitch, sensor_acquisition_rectangle,
+            interocular_distance
         }
 
         for (name, value) in &self.other {
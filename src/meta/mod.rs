@@ -87,6 +87,69 @@ pub struct Requirements {
     pub has_multiple_layers: bool,
 }
 
+/// Controls which inconsistencies in a file are rejected while reading it.
+/// Use `ValidationOptions::strict()` or `ValidationOptions::lenient()` to start,
+/// and adjust single fields as desired.
+/// A `bool` converts into these options, where `true` means strict and `false` means lenient,
+/// so that every function accepting these options also accepts the old `pedantic` flag.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+pub struct ValidationOptions {
+
+    /// Return an error if the offset tables are out of bounds or contain duplicate offsets.
+    /// If false, corrupt offset tables are reconstructed by reading all chunks,
+    /// and zero offsets of partially written files are skipped.
+    pub strict_offset_tables: bool,
+
+    /// Return an error if an attribute value cannot be parsed, for example because of a wrong byte size,
+    /// or if the attributes of the headers contradict each other.
+    /// If false, malformed attributes are skipped.
+    pub strict_attributes: bool,
+
+    /// Return an error if there are bytes left in the file after the last chunk.
+    /// Only checked when reading all chunks sequentially.
+    pub require_end_of_file: bool,
+
+    /// Return an error if a decompressed block contains superfluous bytes
+    /// after the expected number of bytes.
+    pub verify_decompressed_sizes: bool,
+
+    /// Whether a header may contain the same attribute name multiple times.
+    /// If true, the last attribute with that name is used.
+    pub allow_duplicate_attributes: bool,
+
+    /// Sort the channel list of each header and remove channels with a previously occurring name,
+    /// instead of rejecting such files. See `ChannelList::normalized`.
+    /// Disabled in both `strict()` and `lenient()`, because the pixels of such files may be interpreted differently by other software.
+    pub normalize_channel_lists: bool,
+
+    /// The maximum sizes that a file may declare before anything is allocated for it.
+    /// Both `strict()` and `lenient()` use the default limits.
+    pub limits: ReadLimits,
+}
+
+/// Upper bounds for the sizes declared in a file, which are checked before any memory is allocated.
+/// Protects against malicious or corrupt files that would otherwise exhaust the memory of this machine.
+/// Exceeding a limit returns an `Error::Invalid` that names the limit.
+/// The default limits are generous enough for any reasonable file.
+/// Use `ReadLimits::unlimited()` to disable all checks.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+pub struct ReadLimits {
+
+    /// The maximum number of pixels in the data window of a single layer.
+    pub max_image_area: usize,
+
+    /// The maximum number of channels in a single layer.
+    pub max_channel_count: usize,
+
+    /// The maximum byte size of a single attribute value in a header.
+    pub max_attribute_byte_size: usize,
+
+    /// The maximum byte size of all headers together, excluding the offset tables.
+    pub max_header_byte_size: usize,
+
+    /// The maximum byte size of the pixels of a single chunk after decompression.
+    pub max_chunk_byte_size: usize,
+}
 
 /// Locates a rectangular section of pixels in an image.
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
@@ -209,6 +272,20 @@ fn missing_attribute(name: &str) -> Error {
     Error::invalid(format!("missing or invalid {} attribute", name))
 }
 
+/// Check that no two headers share the same layer name.
+/// Missing names are ignored, as they are checked when validating each header.
+fn validate_unique_layer_names(headers: &[Header]) -> UnitResult {
+    let mut header_names = HashSet::with_capacity(headers.len());
+
+    for name in headers.iter().filter_map(|header| header.own_attributes.layer_name.as_ref()) {
+        if !header_names.insert(name) {
+            return Err(Error::invalid(format!("duplicate layer name: `{}`", name)));
+        }
+    }
+
+    Ok(())
+}
+
 
 /// Compute the number of tiles required to contain all values.
 pub fn compute_block_count(full_res: usize, tile_size: usize) -> usize {
@@ -354,8 +431,8 @@ impl MetaData {
     /// Use `read_from_unbuffered` instead if you do not have a file.
     /// Does not validate the meta data.
     #[must_use]
-    pub fn read_from_file(path: impl AsRef<::std::path::Path>, pedantic: bool) -> Result<Self> {
-        Self::read_from_unbuffered(File::open(path)?, pedantic)
+    pub fn read_from_file(path: impl AsRef<::std::path::Path>, validation: impl Into<ValidationOptions>) -> Result<Self> {
+        Self::read_from_unbuffered(File::open(path)?, validation)
     }
 
     /// Buffer the reader and then read the exr meta data from it.
@@ -363,23 +440,62 @@ impl MetaData {
     /// Use `read_from_file` if you have a file path.
     /// Does not validate the meta data.
     #[must_use]
-    pub fn read_from_unbuffered(unbuffered: impl Read, pedantic: bool) -> Result<Self> {
-        Self::read_from_buffered(BufReader::new(unbuffered), pedantic)
+    pub fn read_from_unbuffered(unbuffered: impl Read, validation: impl Into<ValidationOptions>) -> Result<Self> {
+        Self::read_from_buffered(BufReader::new(unbuffered), validation)
     }
 
     /// Read the exr meta data from a reader.
     /// Use `read_from_file` if you have a file path.
     /// Use `read_from_unbuffered` if this is not an in-memory reader.
+    /// Stops reading after the headers, so the reader may also contain only the meta data
+    /// without any offset tables or chunks, as written by `write_to_buffered`.
     /// Does not validate the meta data.
     #[must_use]
-    pub fn read_from_buffered(buffered: impl Read, pedantic: bool) -> Result<Self> {
+    pub fn read_from_buffered(buffered: impl Read, validation: impl Into<ValidationOptions>) -> Result<Self> {
         let mut read = PeekRead::new(buffered);
-        MetaData::read_unvalidated_from_buffered_peekable(&mut read, pedantic)
+        MetaData::read_unvalidated_from_buffered_peekable(&mut read, validation.into())
+    }
+
+    /// Write the exr meta data to a writer, exactly as it appears at the start of a file:
+    /// the magic number, the version and feature flags, and all headers.
+    /// Does not write any offset tables or chunks, so the result is not a valid exr file,
+    /// but it can be read again with `read_from_buffered`, for example to store the headers of a file separately.
+    /// Writes the requirements and attributes as they are, without validating or completing them.
+    /// The writer should be buffered.
+    pub fn write_to_buffered(&self, write: &mut impl Write) -> UnitResult {
+        magic_number::write(write)?;
+        self.requirements.write(write)?;
+        Header::write_all(self.headers.as_slice(), write, self.requirements.has_multiple_layers)
+    }
+
+    /// Read the exr meta data from a reader, parsing the custom attributes whose types are in the registry.
+    /// Attributes of other custom types are read as raw bytes.
+    /// Use `read_from_buffered` if you do not have any custom attribute types.
+    /// Does not validate the meta data.
+    #[must_use]
+    pub fn read_with_attribute_parsers(buffered: impl Read, validation: impl Into<ValidationOptions>, parsers: &AttributeParserRegistry) -> Result<Self> {
+        let validation = validation.into();
+        let mut meta_data = Self::read_from_buffered(buffered, validation)?;
+        meta_data.parse_custom_attributes(parsers, validation)?;
+        Ok(meta_data)
+    }
+
+    /// Parse the custom attributes of all headers whose types are in the registry.
+    /// If a value cannot be parsed, returns the error if attributes are strict, or otherwise keeps the raw bytes.
+    pub fn parse_custom_attributes(&mut self, parsers: &AttributeParserRegistry, validation: impl Into<ValidationOptions>) -> UnitResult {
+        let strict = validation.into().strict_attributes;
+
+        for header in &mut self.headers {
+            parsers.parse_all(&mut header.shared_attributes.other, strict)?;
+            parsers.parse_all(&mut header.own_attributes.other, strict)?;
+        }
+
+        Ok(())
     }
 
     /// Does __not validate__ the meta data completely.
     #[must_use]
-    pub(crate) fn read_unvalidated_from_buffered_peekable(read: &mut PeekRead<impl Read>, pedantic: bool) -> Result<Self> {
+    pub(crate) fn read_unvalidated_from_buffered_peekable(read: &mut PeekRead<impl Read>, validation: ValidationOptions) -> Result<Self> {
         magic_number::validate_exr(read)?;
 
         let requirements = Requirements::read(read)?;
@@ -387,7 +503,7 @@ impl MetaData {
         // do this check now in order to fast-fail for newer versions and features than version 2
         requirements.validate()?;
 
-        let headers = Header::read_all(read, &requirements, pedantic)?;
+        let headers = Header::read_all(read, &requirements, validation)?;
 
         // TODO check if supporting requirements 2 always implies supporting requirements 1
         Ok(MetaData { requirements, headers })
@@ -396,21 +512,59 @@ impl MetaData {
     /// Validates the meta data.
     #[must_use]
     pub(crate) fn read_validated_from_buffered_peekable(
-        read: &mut PeekRead<impl Read>, pedantic: bool
+        read: &mut PeekRead<impl Read>, validation: impl Into<ValidationOptions>
     ) -> Result<Self> {
-        let meta_data = Self::read_unvalidated_from_buffered_peekable(read, !pedantic)?;
-        MetaData::validate(meta_data.headers.as_slice(), pedantic)?;
+        let validation = validation.into();
+        let meta_data = Self::read_unvalidated_from_buffered_peekable(read, validation)?;
+        MetaData::validate(meta_data.headers.as_slice(), validation.strict_attributes)?;
         Ok(meta_data)
     }
 
+    /// Recompute the derived fields of each header, such as the chunk count,
+    /// and add the attributes that the specification requires in multi-part and deep files.
+    /// Each unnamed part of such a file is named after its index, like `layer0` or `layer1`,
+    /// skipping names that are already used by another part.
+    /// Called automatically before writing a file.
+    pub fn complete_required_attributes(headers: &mut [Header]) {
+        for header in headers.iter_mut() {
+            header.recompute_derived_fields();
+        }
+
+        // the block type and the chunk count are always written, but the name is optional in single-part files
+        let requires_names = headers.len() > 1 || headers.iter().any(|header| header.deep);
+        if !requires_names { return; }
+
+        let mut used_names: HashSet<Text> = headers.iter()
+            .filter_map(|header| header.own_attributes.layer_name.clone())
+            .collect();
+
+        for (index, header) in headers.iter_mut().enumerate() {
+            if header.own_attributes.layer_name.is_none() {
+                let name = (index ..)
+                    .map(|number| Text::from(format!("layer{}", number).as_str()))
+                    .find(|name| !used_names.contains(name))
+                    .expect("all layer names are used");
+
+                used_names.insert(name.clone());
+                header.own_attributes.layer_name = Some(name);
+            }
+        }
+    }
+
     /// Validates the meta data and writes it to the stream.
     /// If pedantic, throws errors for files that may produce errors in other exr readers.
+    /// Part names must be unique in multi-part files, even if not pedantic.
     /// Returns the automatically detected minimum requirement flags.
     pub(crate) fn write_validating_to_buffered(write: &mut impl Write, headers: &[Header], pedantic: bool) -> Result<Requirements> {
         // pedantic validation to not allow slightly invalid files
         // that still could be read correctly in theory
         let minimal_requirements = Self::validate(headers, pedantic)?;
 
+        // other readers locate the parts by name, so duplicates would produce unreadable files
+        if minimal_requirements.has_multiple_layers {
+            validate_unique_layer_names(headers)?;
+        }
+
         magic_number::write(write)?;
         minimal_requirements.write(write)?;
         Header::write_all(headers, write, minimal_requirements.has_multiple_layers)?;
@@ -464,13 +618,48 @@ impl MetaData {
         )
     }
 
+    /// Check that the iterator will produce exactly as many blocks as the image contains,
+    /// before spending any time compressing the blocks.
+    /// Use `validate_ordered_blocks` to also check the index of each block.
+    pub fn validate_block_count(&self, blocks: &impl ExactSizeIterator) -> UnitResult {
+        let chunk_count: usize = self.headers.iter().map(|header| header.chunk_count).sum();
+
+        if blocks.len() != chunk_count {
+            return Err(Error::invalid(format!(
+                "the image contains {} blocks, but {} blocks were produced",
+                chunk_count, blocks.len()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Returns an upper bound for the number of bytes that the pixels of all layers
+    /// will require in memory after decompressing, including all resolution levels.
+    /// Use this to decide whether an image is too large before reading any pixels.
+    /// See `Header::total_uncompressed_pixel_bytes` for details.
+    pub fn estimated_decoded_bytes(&self) -> usize {
+        self.headers.iter().fold(0, |sum, header| sum.saturating_add(header.total_uncompressed_pixel_bytes()))
+    }
+
+    /// Check each block that the iterator produces against `enumerate_ordered_header_block_indices`.
+    /// The returned iterator stops at the first block that does not match,
+    /// which allows you to detect a missing or duplicated block before compressing all other blocks.
+    /// Pass a mutable reference of the returned iterator to a compressor,
+    /// and then call `ValidatedBlocks::finish` to find out whether all blocks were correct.
+    pub fn validate_ordered_blocks<I>(&self, blocks: I) -> crate::block::writer::ValidatedBlocks<'_, I>
+        where I: Iterator<Item=(usize, UncompressedBlock)>
+    {
+        crate::block::writer::ValidatedBlocks::new(&self.headers, blocks)
+    }
+
     /// Validates this meta data. Returns the minimal possible requirements.
     pub fn validate(headers: &[Header], pedantic: bool) -> Result<Requirements> {
         if headers.len() == 0 {
             return Err(Error::invalid("at least one layer is required"));
         }
 
-        let deep = false; // TODO deep data
+        let deep = headers.iter().any(|header| header.deep);
         let is_multilayer = headers.len() > 1;
         let first_header_has_tiles = headers.iter().next()
             .map_or(false, |header| header.blocks.has_tiles());
@@ -483,16 +672,13 @@ impl MetaData {
             // start as low as possible, later increasing if required
             has_long_names: false,
 
-            is_single_layer_and_tiled: !is_multilayer && first_header_has_tiles,
+            // a single deep layer sets the deep bit instead of the tile bit, even if it contains tiles
+            is_single_layer_and_tiled: !is_multilayer && !deep && first_header_has_tiles,
             has_multiple_layers: is_multilayer,
             has_deep_data: deep,
         };
 
         for header in headers {
-            if header.deep { // TODO deep data (and then remove this check)
-                return Err(Error::unsupported("deep data not supported yet"));
-            }
-
             header.validate(is_multilayer, &mut minimal_requirements.has_long_names, pedantic)?;
         }
 
@@ -508,15 +694,7 @@ impl MetaData {
         }*/
 
         if pedantic { // check for duplicate header names
-            let mut header_names = HashSet::with_capacity(headers.len());
-            for header in headers {
-                if !header_names.insert(&header.own_attributes.layer_name) {
-                    return Err(Error::invalid(format!(
-                        "duplicate layer name: `{}`",
-                        header.own_attributes.layer_name.as_ref().expect("header validation bug")
-                    )));
-                }
-            }
+            validate_unique_layer_names(headers)?;
         }
 
         if pedantic {
@@ -643,6 +821,78 @@ impl Requirements {
     }
 }
 
+impl ValidationOptions {
+
+    /// Return an error as soon as anything is missing in the file,
+    /// or two values in the file contradict each other.
+    pub fn strict() -> Self {
+        Self {
+            strict_offset_tables: true,
+            strict_attributes: true,
+            require_end_of_file: true,
+            verify_decompressed_sizes: true,
+            allow_duplicate_attributes: false,
+            normalize_channel_lists: false,
+            limits: ReadLimits::default(),
+        }
+    }
+
+    /// Only return an error if the file cannot be decoded at all.
+    pub fn lenient() -> Self {
+        Self {
+            strict_offset_tables: false,
+            strict_attributes: false,
+            require_end_of_file: false,
+            verify_decompressed_sizes: false,
+            allow_duplicate_attributes: true,
+            normalize_channel_lists: false,
+            limits: ReadLimits::default(),
+        }
+    }
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self { Self::lenient() }
+}
+
+impl From<bool> for ValidationOptions {
+    fn from(pedantic: bool) -> Self {
+        if pedantic { Self::strict() } else { Self::lenient() }
+    }
+}
+
+impl ReadLimits {
+
+    /// Do not limit any declared size. Only use this for trusted files.
+    pub fn unlimited() -> Self {
+        Self {
+            max_image_area: usize::MAX,
+            max_channel_count: usize::MAX,
+            max_attribute_byte_size: usize::MAX,
+            max_header_byte_size: usize::MAX,
+            max_chunk_byte_size: usize::MAX,
+        }
+    }
+
+    /// Return an error naming the limit if the value exceeds it.
+    pub(crate) fn check(value: usize, limit: usize, limit_name: &'static str) -> UnitResult {
+        if value <= limit { Ok(()) }
+        else { Err(Error::invalid(format!("{} exceeds the read limit `{}` of {}", value, limit_name, limit))) }
+    }
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self {
+            max_image_area: usize::try_from(1_u64 << 34).unwrap_or(usize::MAX), // for example, 128k × 128k pixels
+            max_channel_count: 1 << 12,
+            max_attribute_byte_size: 1 << 29,
+            max_header_byte_size: 1 << 30,
+            max_chunk_byte_size: 1 << 31,
+        }
+    }
+}
+
 
 #[cfg(test)]
 mod test {
@@ -678,6 +928,8 @@ mod test {
                 ],
             ),
             compression: Compression::Uncompressed,
+            zip_compression_level: None,
+            custom_codecs: Default::default(),
             line_order: LineOrder::Increasing,
             deep_data_version: Some(1),
             chunk_count: compute_chunk_count(Compression::Uncompressed, Vec2(2000, 333), BlockDescription::ScanLines),
@@ -721,6 +973,203 @@ mod test {
         assert_eq!(meta, meta2);
     }
 
+    #[test]
+    fn round_trip_deep_headers(){
+        let channels = smallvec![ ChannelDescription::new("Z", SampleType::F32, true) ];
+
+        let scan_lines = Header::new(Text::from("deep scan lines"), (20, 10), channels.clone())
+            .with_encoding(Compression::ZIP1, BlockDescription::ScanLines, LineOrder::Increasing)
+            .with_deep_data(7);
+
+        let tiles = Header::new(Text::from("deep tiles"), (20, 10), channels)
+            .with_encoding(Compression::RLE, BlockDescription::Tiles(TileDescription {
+                tile_size: Vec2(8, 8), level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down
+            }), LineOrder::Increasing)
+            .with_deep_data(3);
+
+        for headers in [ vec![ tiles.clone() ], vec![ scan_lines.clone(), tiles.clone() ] ] {
+            let mut data: Vec<u8> = Vec::new();
+            let requirements = MetaData::write_validating_to_buffered(&mut data, &headers, true).unwrap();
+            assert!(requirements.has_deep_data && !requirements.is_single_layer_and_tiled);
+
+            let meta = MetaData::read_from_buffered(data.as_slice(), true).unwrap();
+            assert_eq!(meta.requirements, requirements);
+            assert_eq!(meta.headers.as_slice(), headers.as_slice());
+        }
+
+        let lossy = scan_lines.with_encoding(Compression::PIZ, BlockDescription::ScanLines, LineOrder::Increasing);
+        assert!(MetaData::write_validating_to_buffered(&mut Vec::new(), &[ lossy ], true).is_err());
+    }
+
+    #[test]
+    fn round_trip_standard_camera_attributes(){
+        let header = Header::new(Text::from("camera"), (3, 2), smallvec![
+            ChannelDescription::new("Y", SampleType::F16, true)
+        ]);
+
+        let write_and_read = |header: Header| {
+            let mut data: Vec<u8> = Vec::new();
+            MetaData::write_validating_to_buffered(&mut data, &[ header ], false).unwrap();
+            MetaData::read_from_buffered(data.as_slice(), true).unwrap().headers.remove(0)
+        };
+
+        macro_rules! test_attributes {
+            ( $( $field: ident : $name: literal = $variant: ident ( $value: expr ) ),* ) => { $(
+                // the typed field is written with the standard name and type
+                let mut typed = header.clone();
+                typed.own_attributes.$field = Some($value);
+
+                let read = write_and_read(typed.clone());
+                assert_eq!(read, typed, "{}", $name);
+                assert!(read.own_attributes.other.is_empty(), "{}", $name);
+
+                // an attribute with the standard name and type is read into the typed field
+                let mut custom = header.clone();
+                custom.own_attributes.other.insert(Text::from($name), AttributeValue::$variant($value));
+                assert_eq!(write_and_read(custom).own_attributes.$field, Some($value), "{}", $name);
+
+                // an attribute with the standard name but an unexpected type stays custom
+                let mut unexpected = header.clone();
+                unexpected.own_attributes.other.insert(Text::from($name), AttributeValue::F64(1.0));
+
+                let read = write_and_read(unexpected.clone());
+                assert_eq!(read.own_attributes.$field, None, "{}", $name);
+                assert_eq!(read.own_attributes.other, unexpected.own_attributes.other, "{}", $name);
+            )* };
+        }
+
+        test_attributes! {
+            reel_name: "reelName" = Text(Text::from("A001")),
+            image_counter: "imageCounter" = I32(-81),
+            asc_framing_decision_list: "ascFramingDecisionList" = Text(Text::from("{}")),
+            camera_make: "cameraMake" = Text(Text::from("make")),
+            camera_model: "cameraModel" = Text(Text::from("model")),
+            camera_serial_number: "cameraSerialNumber" = Text(Text::from("0815")),
+            camera_firmware_version: "cameraFirmwareVersion" = Text(Text::from("1.2.3")),
+            camera_uuid: "cameraUuid" = Text(Text::from("c3a8d2b8-5c8f-4e2a-9c59-3b1c9a5f0e11")),
+            camera_label: "cameraLabel" = Text(Text::from("B")),
+            camera_cct_setting: "cameraCCTSetting" = F32(5600.0),
+            camera_tint_setting: "cameraTintSetting" = F32(-3.5),
+            camera_color_balance: "cameraColorBalance" = FloatVec2(Vec2(0.3127, 0.329)),
+            lens_make: "lensMake" = Text(Text::from("lens make")),
+            lens_model: "lensModel" = Text(Text::from("lens model")),
+            lens_serial_number: "lensSerialNumber" = Text(Text::from("4711")),
+            lens_firmware_version: "lensFirmwareVersion" = Text(Text::from("2.0")),
+            nominal_focal_length: "nominalFocalLength" = F32(35.0),
+            pinhole_focal_length: "pinholeFocalLength" = F32(35.4),
+            effective_focal_length: "effectiveFocalLength" = F32(36.1),
+            entrance_pupil_offset: "entrancePupilOffset" = F32(-92.5),
+            t_stop: "tStop" = F32(2.8),
+            shutter_angle: "shutterAngle" = F32(172.8),
+            sensor_center_offset: "sensorCenterOffset" = FloatVec2(Vec2(0.1, -0.2)),
+            sensor_overall_dimensions: "sensorOverallDimensions" = FloatVec2(Vec2(36.7, 25.54)),
+            sensor_photosite_pitch: "sensorPhotositePitch" = F32(8.25),
+            sensor_acquisition_rectangle: "sensorAcquisitionRectangle" = IntegerBounds(IntegerBounds::new(Vec2(-4, 8), Vec2(4448, 3096))),
+            interocular_distance: "interocularDistance" = F32(63.0)
+        }
+    }
+
+    #[test]
+    fn round_trip_key_code(){
+        let mut header = Header::new(Text::from("film scan"), (3, 2), smallvec![
+            ChannelDescription::new("Y", SampleType::F16, true)
+        ]);
+
+        header.own_attributes.film_key_code = Some("22 50 123456 2041+07".parse().unwrap());
+
+        let mut data: Vec<u8> = Vec::new();
+        MetaData::write_validating_to_buffered(&mut data, &[ header.clone() ], true).unwrap();
+        let meta = MetaData::read_from_buffered(data.as_slice(), true).unwrap();
+        assert_eq!(meta.headers.as_slice(), &[ header.clone() ]);
+        assert!(meta.headers[0].own_attributes.other.is_empty());
+
+        // a three-digit manufacturer code is rejected when pedantic, but can still be read otherwise
+        let key_code = header.own_attributes.film_key_code.as_mut().unwrap();
+        key_code.film_manufacturer_code = 220;
+
+        assert!(MetaData::write_validating_to_buffered(&mut Vec::new(), &[ header.clone() ], true).is_err());
+
+        let mut data: Vec<u8> = Vec::new();
+        MetaData::write_validating_to_buffered(&mut data, &[ header.clone() ], false).unwrap();
+        assert!(MetaData::read_validated_from_buffered_peekable(&mut PeekRead::new(data.as_slice()), true).is_err());
+
+        let meta = MetaData::read_validated_from_buffered_peekable(&mut PeekRead::new(data.as_slice()), false).unwrap();
+        assert_eq!(meta.headers[0].own_attributes.film_key_code, header.own_attributes.film_key_code);
+    }
+
+    #[test]
+    fn build_headers(){
+        use crate::image::*;
+        use crate::image::write::WritableImage;
+
+        let size = Vec2(16, 8);
+        let rgb = || vec![
+            ChannelDescription::named("R", SampleType::F32),
+            ChannelDescription::named("G", SampleType::F32),
+            ChannelDescription::named("B", SampleType::F32),
+        ];
+
+        let flat_channels = || AnyChannels::sort(smallvec![
+            AnyChannel::new("R", FlatSamples::F32(vec![ 0.0; size.area() ])),
+            AnyChannel::new("G", FlatSamples::F32(vec![ 0.0; size.area() ])),
+            AnyChannel::new("B", FlatSamples::F32(vec![ 0.0; size.area() ])),
+        ]);
+
+        macro_rules! inferred_header {
+            ($channels: expr, $encoding: expr) => {{
+                let layer = Layer::new(size, LayerAttributes::named("main"), $encoding, $channels);
+                Image::from_layer(layer).write().infer_meta_data().remove(0)
+            }};
+        }
+
+        let scan_lines = Header::builder(size).channels(rgb()).compression(Compression::ZIP16)
+            .layer_name("main").build().unwrap();
+
+        assert_eq!(scan_lines, inferred_header!(flat_channels(), Encoding {
+            compression: Compression::ZIP16, blocks: Blocks::ScanLines, line_order: LineOrder::Increasing
+        }));
+
+        let tiles = Header::builder(size).channels(rgb()).compression(Compression::PIZ)
+            .tiles((8, 8)).line_order(LineOrder::Decreasing).layer_name("main").build().unwrap();
+
+        assert_eq!(tiles, inferred_header!(flat_channels(), Encoding {
+            compression: Compression::PIZ, blocks: Blocks::Tiles(Vec2(8, 8)), line_order: LineOrder::Decreasing
+        }));
+
+        let mip_maps = Header::builder(size).channels(rgb()).compression(Compression::RLE)
+            .tiles((4, 4)).levels(LevelMode::MipMap, RoundingMode::Up).layer_name("main").build().unwrap();
+
+        let mip_level = || Levels::Mip {
+            rounding_mode: RoundingMode::Up,
+            level_data: mip_map_levels(RoundingMode::Up, size)
+                .map(|(_, level_size)| FlatSamples::F32(vec![ 0.0; level_size.area() ]))
+                .collect()
+        };
+
+        let mip_channels = AnyChannels::sort(smallvec![
+            AnyChannel::new("R", mip_level()), AnyChannel::new("G", mip_level()), AnyChannel::new("B", mip_level()),
+        ]);
+
+        assert_eq!(mip_maps, inferred_header!(mip_channels, Encoding {
+            compression: Compression::RLE, blocks: Blocks::Tiles(Vec2(4, 4)), line_order: LineOrder::Increasing
+        }));
+
+        let rip_maps = Header::builder(size).channels(rgb()).tiles((4, 4))
+            .levels(LevelMode::RipMap, RoundingMode::Down).position((-3, 5)).build().unwrap();
+
+        assert_eq!(rip_maps.chunk_count, compute_chunk_count(Compression::Uncompressed, size, rip_maps.blocks));
+        assert_eq!(rip_maps.own_attributes.layer_position, Vec2(-3, 5));
+
+        for header in [ scan_lines, tiles, mip_maps, rip_maps ] {
+            MetaData::write_validating_to_buffered(&mut Vec::new(), &[ header ], true).unwrap();
+        }
+
+        // inconsistent headers are rejected
+        assert!(Header::builder(size).build().is_err(), "no channels");
+        assert!(Header::builder(size).channels(rgb()).levels(LevelMode::MipMap, RoundingMode::Down).build().is_err(), "scan line levels");
+        assert!(Header::builder(size).channels(rgb()).compression(Compression::PXR24).deep_data(4).build().is_err(), "deep pxr24");
+    }
+
     #[test]
     fn infer_low_requirements() {
         let header_version_1_short_names = Header {
@@ -734,6 +1183,8 @@ mod test {
                 ],
             ),
             compression: Compression::Uncompressed,
+            zip_compression_level: None,
+            custom_codecs: Default::default(),
             line_order: LineOrder::Increasing,
             deep_data_version: Some(1),
             chunk_count: compute_chunk_count(Compression::Uncompressed, Vec2(2000, 333), BlockDescription::ScanLines),
@@ -781,6 +1232,8 @@ mod test {
                 ],
             ),
             compression: Compression::Uncompressed,
+            zip_compression_level: None,
+            custom_codecs: Default::default(),
             line_order: LineOrder::Increasing,
             deep_data_version: Some(1),
             chunk_count: compute_chunk_count(Compression::Uncompressed, Vec2(2000, 333), BlockDescription::ScanLines),
@@ -817,5 +1270,179 @@ mod test {
         assert_eq!(low_requirements.has_deep_data, false);
         assert_eq!(low_requirements.has_multiple_layers, true);
     }
-}
 
+    #[test]
+    fn level_sizes_for_each_rounding_mode() {
+        let size = Vec2(2049, 1025);
+        let tiles = |rounding_mode| TileDescription { tile_size: Vec2(64, 64), level_mode: LevelMode::MipMap, rounding_mode };
+
+        let expected_down = [
+            (2049, 1025), (1024, 512), (512, 256), (256, 128), (128, 64), (64, 32),
+            (32, 16), (16, 8), (8, 4), (4, 2), (2, 1), (1, 1),
+        ];
+
+        let expected_up = [
+            (2049, 1025), (1025, 513), (513, 257), (257, 129), (129, 65), (65, 33),
+            (33, 17), (17, 9), (9, 5), (5, 3), (3, 2), (2, 1), (1, 1),
+        ];
+
+        for &(rounding_mode, expected) in &[ (RoundingMode::Down, &expected_down[..]), (RoundingMode::Up, &expected_up[..]) ] {
+            assert_eq!(compute_level_count(rounding_mode, size.width()), expected.len(), "{:?}", rounding_mode);
+
+            let levels: Vec<(usize, usize)> = mip_map_levels(rounding_mode, size)
+                .map(|(_, level_size)| (level_size.width(), level_size.height())).collect();
+
+            assert_eq!(levels, expected, "{:?}", rounding_mode);
+
+            let rip_levels: Vec<Vec2<usize>> = rip_map_levels(rounding_mode, size).map(|(_, size)| size).collect();
+            assert_eq!(rip_levels.len(), expected.len() * compute_level_count(rounding_mode, size.height()));
+            assert_eq!(rip_levels[1], Vec2(expected[1].0, size.height()), "{:?}", rounding_mode);
+
+            let expected_chunks: usize = expected.iter()
+                .map(|&(width, height)| compute_block_count(width, 64) * compute_block_count(height, 64))
+                .sum();
+
+            let header = Header::new(Text::from("main"), size, smallvec![ ChannelDescription::named("Y", SampleType::F16) ])
+                .with_encoding(Compression::Uncompressed, BlockDescription::Tiles(tiles(rounding_mode)), LineOrder::Increasing);
+
+            assert_eq!(header.chunk_count, expected_chunks, "{:?}", rounding_mode);
+            assert_eq!(header.blocks_increasing_y_order().len(), expected_chunks, "{:?}", rounding_mode);
+        }
+
+        assert_eq!(compute_level_count(RoundingMode::Up, 1025), 12);
+        assert_eq!(compute_level_count(RoundingMode::Down, 1025), 11);
+
+        // the last tile of the second level is only a single pixel wide when rounding up
+        let round_up = Header::new(Text::from("main"), size, smallvec![ ChannelDescription::named("Y", SampleType::F16) ])
+            .with_encoding(Compression::Uncompressed, BlockDescription::Tiles(tiles(RoundingMode::Up)), LineOrder::Increasing);
+
+        let edge_tile = TileCoordinates { tile_index: Vec2(16, 8), level_index: Vec2(1, 1) };
+        assert_eq!(round_up.get_absolute_block_pixel_coordinates(edge_tile).unwrap(), IntegerBounds::new((1024, 512), (1, 1)));
+
+        // when rounding down, the second level is evenly divided and has no such tile
+        let round_down = Header { blocks: BlockDescription::Tiles(tiles(RoundingMode::Down)), .. round_up.clone() };
+        assert!(round_down.get_absolute_block_pixel_coordinates(edge_tile).is_err());
+
+        let last_tile = TileCoordinates { tile_index: Vec2(15, 7), level_index: Vec2(1, 1) };
+        assert_eq!(round_down.get_absolute_block_pixel_coordinates(last_tile).unwrap(), IntegerBounds::new((960, 448), (64, 64)));
+    }
+
+    #[test]
+    fn write_version_flags_and_required_part_attributes() {
+        fn string_attribute(name: &str, value: &str) -> Vec<u8> {
+            let mut bytes = [name.as_bytes(), b"\0string\0"].concat();
+            bytes.extend_from_slice(&(value.len() as i32).to_le_bytes());
+            bytes.extend_from_slice(value.as_bytes());
+            bytes
+        }
+
+        fn chunk_count_attribute(count: usize) -> Vec<u8> {
+            [&b"chunkCount\0int\0"[..], &4_i32.to_le_bytes(), &(count as i32).to_le_bytes()].concat()
+        }
+
+        fn occurrences(bytes: &[u8], pattern: &[u8]) -> usize {
+            bytes.windows(pattern.len()).filter(|window| *window == pattern).count()
+        }
+
+        fn write(headers: &mut [Header]) -> Vec<u8> {
+            MetaData::complete_required_attributes(headers);
+            let mut bytes = Vec::new();
+            MetaData::write_validating_to_buffered(&mut bytes, headers, false).unwrap();
+            bytes
+        }
+
+        let unnamed = |blocks: BlockDescription| {
+            let mut header = Header::new(Text::from("unused"), (20, 10), smallvec![ ChannelDescription::named("Y", SampleType::F16) ])
+                .with_encoding(Compression::Uncompressed, blocks, LineOrder::Increasing);
+
+            header.own_attributes.layer_name = None;
+            header
+        };
+
+        let tiles = BlockDescription::Tiles(TileDescription {
+            tile_size: Vec2(8, 8), level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down
+        });
+
+        // a single part keeps its missing name, and only sets the tile bit if tiled
+        let single_scan_lines = write(&mut [ unnamed(BlockDescription::ScanLines) ]);
+        assert_eq!(&single_scan_lines[4 .. 8], &[2, 0, 0, 0]);
+        assert_eq!(occurrences(&single_scan_lines, &string_attribute("type", "scanlineimage")), 1);
+        assert_eq!(occurrences(&single_scan_lines, &chunk_count_attribute(10)), 1);
+        assert_eq!(occurrences(&single_scan_lines, b"name\0string\0"), 0);
+
+        let single_tiles = write(&mut [ unnamed(tiles) ]);
+        assert_eq!(&single_tiles[4 .. 8], &[2, 0b10, 0, 0]);
+        assert_eq!(occurrences(&single_tiles, &string_attribute("type", "tiledimage")), 1);
+        assert_eq!(occurrences(&single_tiles, &chunk_count_attribute(6)), 1);
+
+        // multiple parts set the multi-part bit instead of the tile bit, and each part has a unique name
+        let mut named = unnamed(BlockDescription::ScanLines);
+        named.own_attributes.layer_name = Some(Text::from("layer0"));
+
+        let mut headers = [ unnamed(tiles), named, unnamed(BlockDescription::ScanLines) ];
+        let multi_part = write(&mut headers);
+        assert_eq!(&multi_part[4 .. 8], &[2, 0b1_0000, 0, 0]);
+
+        let names: Vec<Option<Text>> = headers.iter().map(|header| header.own_attributes.layer_name.clone()).collect();
+        assert_eq!(names, vec![ Some(Text::from("layer1")), Some(Text::from("layer0")), Some(Text::from("layer2")) ]);
+
+        for name in &["layer0", "layer1", "layer2"] {
+            assert_eq!(occurrences(&multi_part, &string_attribute("name", name)), 1);
+        }
+
+        assert_eq!(occurrences(&multi_part, &string_attribute("type", "tiledimage")), 1);
+        assert_eq!(occurrences(&multi_part, &string_attribute("type", "scanlineimage")), 2);
+        assert_eq!(occurrences(&multi_part, &chunk_count_attribute(6)), 1);
+        assert_eq!(occurrences(&multi_part, &chunk_count_attribute(10)), 2);
+
+        let meta = MetaData::read_from_buffered(multi_part.as_slice(), true).unwrap();
+        assert_eq!(meta.headers.as_slice(), &headers);
+
+        // duplicate part names are rejected, even if not pedantic
+        let duplicate = [ headers[0].clone(), headers[0].clone() ];
+        assert!(MetaData::write_validating_to_buffered(&mut Vec::new(), &duplicate, false).is_err());
+    }
+
+    #[test]
+    fn screen_window_of_display_pixels() {
+        fn assert_close(actual: Vec2<f32>, expected: Vec2<f32>) {
+            assert!(
+                (actual.x() - expected.x()).abs() < 1e-4 && (actual.y() - expected.y()).abs() < 1e-4,
+                "{:?} is not {:?}", actual, expected
+            );
+        }
+
+        // square pixels with the default screen window, which is as wide as the display window
+        let full_hd = ImageAttributes::with_size((1920, 1080));
+        let layer = LayerAttributes::default();
+        assert!((full_hd.display_aspect_ratio() - 16.0 / 9.0).abs() < 1e-6);
+        assert!((layer.screen_window_height(&full_hd) - 0.5625).abs() < 1e-6);
+
+        assert_close(layer.screen_space_of_pixel(&full_hd, Vec2(0, 0)), Vec2(-0.5 + 0.5 / 1920.0, 0.28125 - 0.5 / 1920.0));
+        assert_close(layer.screen_space_of_pixel(&full_hd, Vec2(1919, 1079)), Vec2(0.5 - 0.5 / 1920.0, -0.28125 + 0.5 / 1920.0));
+        assert_close(layer.pixel_of_screen_space(&full_hd, Vec2(-0.5, 0.28125)), Vec2(-0.5, -0.5));
+        assert_close(layer.pixel_of_screen_space(&full_hd, Vec2(0.0, 0.0)), Vec2(959.5, 539.5));
+
+        // an anamorphic plate, where each pixel is displayed twice as wide as it is high
+        let anamorphic = ImageAttributes { pixel_aspect: 2.0, .. ImageAttributes::with_size((1024, 858)) };
+        let layer = LayerAttributes { screen_window_width: 2.0, .. LayerAttributes::default() };
+        assert!((anamorphic.display_aspect_ratio() - 2048.0 / 858.0).abs() < 1e-5);
+
+        let origin = layer.screen_space_of_pixel(&anamorphic, Vec2(0, 0));
+        let pixel_width = layer.screen_space_of_pixel(&anamorphic, Vec2(1, 0)).x() - origin.x();
+        let pixel_height = origin.y() - layer.screen_space_of_pixel(&anamorphic, Vec2(0, 1)).y();
+        assert!((pixel_width - 2.0 / 1024.0).abs() < 1e-6);
+        assert!((pixel_width / pixel_height - 2.0).abs() < 1e-3);
+
+        // a shifted display window and screen window
+        let shifted = ImageAttributes::new(IntegerBounds::new((-100, 50), (200, 100)));
+        let layer = LayerAttributes { screen_window_center: Vec2(1.0, -1.0), screen_window_width: 4.0, .. LayerAttributes::default() };
+        assert_close(layer.screen_space_of_pixel(&shifted, Vec2(-100, 50)), Vec2(-1.0 + 0.01, 0.0 - 0.01));
+        assert_close(layer.pixel_of_screen_space(&shifted, Vec2(1.0, -1.0)), Vec2(-0.5, 99.5));
+
+        for &pixel in &[ Vec2(-100, 50), Vec2(0, 0), Vec2(99, 149), Vec2(-37, 80) ] {
+            let screen_position = layer.screen_space_of_pixel(&shifted, pixel);
+            assert_close(layer.pixel_of_screen_space(&shifted, screen_position), Vec2(pixel.x() as f32, pixel.y() as f32));
+        }
+    }
+}
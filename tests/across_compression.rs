@@ -86,6 +86,48 @@ fn expect_eq_png(image_name: &str) {
     }
 }
 
+/// Compare a dwa file from the OpenEXR test images to the pixels that OpenEXR decoded from it.
+/// OpenEXR does not transform all rows of some blocks. The reference was decoded by an optimized build,
+/// which replaces such a row with zeroes, while this crate keeps the row as it is, like the portable build.
+/// As these rows only contain a coefficient in the first column, the first column of each block is not compared.
+/// The other samples may differ by one f16 step in the perceptual space of dwa, due to rounding.
+fn expect_dwa_like_openexr(image_name: &str, expected: &str) {
+    let openexr_dir = Path::new("tests/images/valid/openexr/IlmfmlmflmTest");
+
+    let decompressed = read_first_flat_layer_from_file(openexr_dir.join(image_name))
+        .expect("dwa image could not be loaded");
+
+    let reference = read_first_flat_layer_from_file(openexr_dir.join(expected))
+        .expect("reference image could not be loaded");
+
+    assert_eq!(decompressed.layer_data.size, reference.layer_data.size, "size should not be affected by compression");
+    let width = decompressed.layer_data.size.width();
+
+    // the f16 steps of the value after converting it to the perceptual space of dwa
+    fn perceptual_steps(value: f32) -> i32 {
+        let perceptual = if value.abs() <= 1.0 { value.abs().powf(1.0 / 2.2) } else { value.abs().ln() / 2.2 + 1.0 };
+        i32::from(f16::from_f32(perceptual).to_bits()) * if value < 0.0 { -1 } else { 1 }
+    }
+
+    let channels = decompressed.layer_data.channel_data.list.iter().zip(&reference.layer_data.channel_data.list);
+    for (channel, reference_channel) in channels {
+        assert_eq!(channel.name, reference_channel.name);
+
+        let samples = channel.sample_data.values_as_f32();
+        let reference_samples = reference_channel.sample_data.values_as_f32();
+
+        for (index, (sample, reference_sample)) in samples.zip(reference_samples).enumerate() {
+            let (x, y) = (index % width, index / width);
+            if x % 8 == 0 { continue; }
+
+            assert!(
+                (perceptual_steps(sample) - perceptual_steps(reference_sample)).abs() <= 1,
+                "channel {} at ({}, {}): expected {}, found {}", channel.name, x, y, reference_sample, sample
+            );
+        }
+    }
+}
+
 fn expect_eq_uncompressed(sub_dir: &str, image_name: &str) {
     expect_eq_other(sub_dir, image_name, "uncompressed.exr")
 }
@@ -151,6 +193,18 @@ fn compare_compression_contents_dwab_f16() {
     expect_eq_other("f16", "dwab.exr", "decompressed_dwab.exr");
 }
 
+#[test]
+fn compare_openexr_dwaa_to_reference() {
+    expect_dwa_like_openexr("comp_dwaa_v1.exr", "comp_dwaa_piz.exr");
+    expect_dwa_like_openexr("comp_dwaa_v2.exr", "comp_dwaa_piz.exr");
+}
+
+#[test]
+fn compare_openexr_dwab_to_reference() {
+    expect_dwa_like_openexr("comp_dwab_v1.exr", "comp_dwab_piz.exr");
+    expect_dwa_like_openexr("comp_dwab_v2.exr", "comp_dwab_piz.exr");
+}
+
 #[test]
 fn compare_compression_contents_piz_f32() {
     expect_eq_uncompressed("f32", "piz.exr");
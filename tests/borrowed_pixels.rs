@@ -0,0 +1,106 @@
+//! Check that reading into a caller-provided buffer does not allocate another pixel storage.
+//! This test has its own binary, because it observes the allocations of the whole process.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use exr::prelude::*;
+use exr::prelude::pixel_vec::PixelVec;
+
+struct LargestAllocationTracker;
+static LARGEST_ALLOCATION: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for LargestAllocationTracker {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LARGEST_ALLOCATION.fetch_max(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LARGEST_ALLOCATION.fetch_max(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: LargestAllocationTracker = LargestAllocationTracker;
+
+
+fn rgba_file_bytes(size: Vec2<usize>) -> Vec<u8> {
+    let pixels = SpecificChannels::rgba(|Vec2(x, y): Vec2<usize>| (
+        (x as f32 * 0.1).sin(), f16::from_f32((y as f32 * 0.2).cos()), (x * y) as f32 / 1000.0, 0.5_f32
+    ));
+
+    let encoding = Encoding { compression: Compression::ZIP16, .. Encoding::default() };
+    let image = Image::from_layer(Layer::new(size, LayerAttributes::named("test"), encoding, pixels));
+
+    let mut bytes = Vec::new();
+    image.write().non_parallel().to_buffered(Cursor::new(&mut bytes)).unwrap();
+    bytes
+}
+
+#[test]
+fn read_rgba_into_borrowed_buffer_without_allocating_pixels() {
+    let size = Vec2(128, 128);
+    let bytes = rgba_file_bytes(size);
+
+    let expected = read().no_deep_data().largest_resolution_level()
+        .rgba_channels(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+        .first_valid_layer().all_attributes().non_parallel()
+        .from_buffered(Cursor::new(&bytes)).unwrap();
+
+    let mut buffer = vec![0.0_f32; size.area() * 4];
+    let buffer_address = buffer.as_ptr();
+    let buffer_bytes = buffer.len() * std::mem::size_of::<f32>();
+
+    LARGEST_ALLOCATION.store(0, Ordering::Relaxed);
+
+    let image = read().no_deep_data().largest_resolution_level()
+        .rgba_channels_into(&mut buffer)
+        .first_valid_layer().all_attributes().non_parallel()
+        .from_buffered(Cursor::new(&bytes)).unwrap();
+
+    let largest_allocation = LARGEST_ALLOCATION.load(Ordering::Relaxed);
+    assert!(
+        largest_allocation < buffer_bytes / 2,
+        "reading allocated {} bytes at once, while the pixels only require {} bytes",
+        largest_allocation, buffer_bytes
+    );
+
+    assert_eq!(image.layer_data.channel_data.pixels.as_ptr(), buffer_address, "the pixels are not the borrowed buffer");
+    assert!(image.layer_data.channel_data.channels.iter().all(Option::is_some));
+
+    let expected_samples = expected.layer_data.channel_data.pixels.pixels.iter()
+        .flat_map(|&(r, g, b, a)| vec![r, g, b, a]);
+
+    assert!(buffer.iter().copied().eq(expected_samples), "the buffer contains different samples");
+}
+
+#[test]
+fn reject_borrowed_buffer_with_wrong_length() {
+    let size = Vec2(16, 9);
+    let bytes = rgba_file_bytes(size);
+
+    let mut too_small = vec![0.0_f32; size.area() * 3];
+    let result = read().no_deep_data().largest_resolution_level()
+        .specific_channels().required("R").required("G").required("B").required("A")
+        .collect_pixels_into(&mut too_small)
+        .all_layers().all_attributes()
+        .from_buffered(Cursor::new(&bytes));
+
+    assert!(matches!(result, Err(Error::Invalid(_))), "buffer size has not been validated");
+
+    // the missing channel uses the default sample
+    let mut buffer = [0.0_f32; 16 * 9 * 2];
+    read().no_deep_data().largest_resolution_level()
+        .specific_channels().required("G").optional("Z", -1.0)
+        .collect_pixels_into(&mut buffer)
+        .first_valid_layer().all_attributes()
+        .from_buffered(Cursor::new(&bytes)).unwrap();
+
+    assert!(buffer.chunks_exact(2).all(|pixel| pixel[1] == -1.0 && pixel[0] != -1.0));
+}
@@ -0,0 +1,74 @@
+//! Check that reusing block buffers actually reduces the number of allocations.
+//! This test has its own binary, because it counts the allocations of the whole process.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use exr::prelude::*;
+use exr::block::pool::BlockBufferPool;
+use exr::block::reader::ChunksReader;
+
+struct CountingAllocator;
+static ALLOCATION_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATION_COUNT.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+
+#[test]
+fn reuse_block_buffers_while_decompressing_tiled_zip() {
+    let size = Vec2(512, 512);
+    let pixels = SpecificChannels::rgba(|Vec2(x, y)| (
+        (x as f32 * 0.1).sin(), (y as f32 * 0.2).cos(), (x * y) as f32 / 1000.0, 1.0_f32
+    ));
+
+    let encoding = Encoding {
+        compression: Compression::ZIP16,
+        blocks: Blocks::Tiles(Vec2(64, 64)),
+        line_order: LineOrder::Increasing,
+    };
+
+    let image = Image::from_layer(Layer::new(size, LayerAttributes::named("test"), encoding, pixels));
+    let mut bytes = Vec::new();
+    image.write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+    let read_all_blocks = |buffer_pool: Option<BlockBufferPool>| -> (usize, Vec<u8>) {
+        let chunks = exr::block::read(Cursor::new(&bytes), true).unwrap().all_chunks(true).unwrap();
+        let mut decompressor = chunks.sequential_decompressor(true);
+        if let Some(buffer_pool) = &buffer_pool { decompressor = decompressor.buffer_pool(buffer_pool.clone()); }
+
+        let mut checksum = Vec::new();
+        let allocations_before = ALLOCATION_COUNT.load(Ordering::Relaxed);
+
+        for block in decompressor {
+            let block = block.unwrap();
+            checksum.push(block.data.iter().fold(0_u8, |sum, &byte| sum.wrapping_add(byte)));
+            if let Some(buffer_pool) = &buffer_pool { buffer_pool.recycle(block); }
+        }
+
+        (ALLOCATION_COUNT.load(Ordering::Relaxed) - allocations_before, checksum)
+    };
+
+    let (unpooled_allocations, unpooled_checksum) = read_all_blocks(None);
+    let (pooled_allocations, pooled_checksum) = read_all_blocks(Some(BlockBufferPool::new()));
+
+    assert_eq!(pooled_checksum, unpooled_checksum, "pooled decompression produced different pixels");
+    assert_eq!(pooled_checksum.len(), 64, "unexpected number of blocks");
+
+    assert!(
+        pooled_allocations * 2 < unpooled_allocations,
+        "pooling did not reduce allocations: {} with pool, {} without pool",
+        pooled_allocations, unpooled_allocations
+    );
+}
@@ -15,6 +15,9 @@ use exr::image::validate_results::ValidateResult;
 use rayon::prelude::IntoParallelIterator;
 use rayon::iter::ParallelIterator;
 use exr::block::samples::IntoNativeSample;
+use exr::block::reader::ChunksReader;
+use exr::compression::{BlockCodec, CodecRegistry};
+use exr::meta::header::Header;
 
 #[test]
 fn roundtrip_all_files_in_repository_x4(){
@@ -64,12 +67,8 @@ fn round_trip_simple(file: &[u8]) -> Result<()> {
 }
 
 fn round_trip_rgba_file(path: &Path, file: &[u8]) -> Result<()> {
-    // these files are known to be invalid, because they do not contain any rgb channels
+    // these files are known to be invalid, because they contain neither rgb nor luminance channels
     let blacklist = [
-        Path::new("tests/images/valid/openexr/LuminanceChroma/Garden.exr"),
-        Path::new("tests/images/valid/openexr/MultiView/Fog.exr"),
-        Path::new("tests/images/valid/openexr/TestImages/GrayRampsDiagonal.exr"),
-        Path::new("tests/images/valid/openexr/TestImages/GrayRampsHorizontal.exr"),
         Path::new("tests/images/valid/openexr/TestImages/WideFloatRange.exr"),
         Path::new("tests/images/valid/openexr/IlmfmlmflmTest/v1.7.test.tiled.exr")
     ];
@@ -79,7 +78,7 @@ fn round_trip_rgba_file(path: &Path, file: &[u8]) -> Result<()> {
     let image_reader = read()
         .no_deep_data()
         .largest_resolution_level() // TODO all levels
-        .rgba_channels(PixelVec::<(f32,f32,f32,f32)>::constructor, PixelVec::set_pixel)
+        .rgba_channels_or_luminance_chroma(PixelVec::<(f32,f32,f32,f32)>::constructor, PixelVec::set_pixel)
         .first_valid_layer()
         .all_attributes()
         .non_parallel();
@@ -303,45 +302,444 @@ fn pxr24_expect_error_on_big_endian(){
 
 #[test]
 #[cfg(target_endian = "little")] // TODO big endian pxr24
+#[cfg(feature = "pxr24")]
 fn roundtrip_pxr24() {
     test_mixed_roundtrip_with_compression(Compression::PXR24)
 }
 
 #[test]
+#[cfg(feature = "rle")]
 fn roundtrip_rle() {
     test_mixed_roundtrip_with_compression(Compression::RLE)
 }
 
 #[test]
+#[cfg(feature = "zip")]
 fn roundtrip_zip1() {
     test_mixed_roundtrip_with_compression(Compression::ZIP1)
 }
 
 #[test]
+#[cfg(feature = "zip")]
 fn roundtrip_zip16() {
     test_mixed_roundtrip_with_compression(Compression::ZIP16)
 }
 
 #[test]
+#[cfg(feature = "b44")]
 fn roundtrip_b44() {
     test_mixed_roundtrip_with_compression(Compression::B44)
 }
 
 #[test]
+#[cfg(feature = "b44")]
 fn roundtrip_b44a() {
     test_mixed_roundtrip_with_compression(Compression::B44A)
 }
 
 #[test]
+#[cfg(feature = "b44")]
+fn b44_preserves_flat_blocks_and_other_sample_types() {
+    // the resolution is not divisible by the block size, such that the border blocks are incomplete
+    let size = Vec2(18, 11);
+
+    // each 4x4 block has a single color, and the color changes from block to block
+    let flat = (0 .. size.area())
+        .map(|index| f16::from_f32(((index % size.width()) / 4 + 5 * ((index / size.width()) / 4)) as f32 * 0.37))
+        .collect::<Vec<_>>();
+
+    let image = |compression: Compression| Image::from_layer(Layer::new(
+        size, LayerAttributes::named("flat"),
+        Encoding { compression, .. Encoding::default() },
+        AnyChannels::sort(smallvec::smallvec![
+            AnyChannel::new("Y", FlatSamples::F16(flat.clone())),
+            AnyChannel::new("Z", FlatSamples::F32((0 .. size.area()).map(|index| index as f32 / 3.0).collect())),
+            AnyChannel::new("id", FlatSamples::U32((0 .. size.area()).map(|index| index as u32 * 7).collect())),
+        ])
+    ));
+
+    let mut byte_sizes = Vec::new();
+
+    for compression in [Compression::B44, Compression::B44A] {
+        let original = image(compression);
+
+        let mut bytes = Vec::new();
+        original.write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+        byte_sizes.push(bytes.len());
+
+        let decompressed = read().no_deep_data().largest_resolution_level()
+            .all_channels().first_valid_layer().all_attributes()
+            .from_buffered(Cursor::new(&bytes)).unwrap();
+
+        // flat blocks and samples other than f16 are not altered
+        assert_eq!(decompressed.layer_data.channel_data, original.layer_data.channel_data, "{} altered the samples", compression);
+    }
+
+    assert!(byte_sizes[1] < byte_sizes[0], "b44a should store flat blocks in fewer bytes");
+}
+
+#[test]
+#[cfg(feature = "dwa")]
+fn roundtrip_dwaa() {
+    test_mixed_roundtrip_with_compression(Compression::DWAA(None))
+}
+
+#[test]
+#[cfg(feature = "dwa")]
+fn roundtrip_dwab() {
+    test_mixed_roundtrip_with_compression(Compression::DWAB(Some(100.0)))
+}
+
+#[test]
+#[cfg(feature = "dwa")]
+fn dwa_compression_preserves_gradients() {
+    // more than 256 lines, such that dwab produces multiple blocks
+    let size = Vec2(203, 301);
+
+    let pixels = SpecificChannels::rgba(|Vec2(x, y)| (
+        (x as f32 / size.width() as f32).to_f16(),
+        (y as f32 / size.height() as f32).to_f16(),
+        ((x + y) as f32 * 0.05).sin().abs().to_f16(),
+        (x % 7) as f32 / 7.0,
+    ));
+
+    let write = |compression: Compression| {
+        let encoding = Encoding { compression, .. Encoding::default() };
+        let image = Image::from_layer(Layer::new(size, LayerAttributes::named("gradient"), encoding, pixels.clone()));
+
+        let mut bytes = Vec::new();
+        image.write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+        bytes
+    };
+
+    let uncompressed_byte_size = write(Compression::Uncompressed).len();
+    let mut default_level_byte_size = None;
+
+    for compression in [Compression::DWAA(None), Compression::DWAB(None), Compression::DWAB(Some(300.0))] {
+        let bytes = write(compression);
+        assert!(bytes.len() < uncompressed_byte_size / 2, "{} does not compress the gradient", compression);
+
+        let image = read().no_deep_data().largest_resolution_level()
+            .rgba_channels(PixelVec::<(f16, f16, f16, f32)>::constructor, PixelVec::set_pixel)
+            .first_valid_layer().all_attributes().from_buffered(Cursor::new(&bytes)).unwrap();
+
+        assert_eq!(image.layer_data.encoding.compression, compression);
+
+        let mut squared_error_sum = 0.0;
+        for (index, &(red, green, blue, alpha)) in image.layer_data.channel_data.pixels.pixels.iter().enumerate() {
+            let (expected_red, expected_green, expected_blue, expected_alpha) = pixels.pixels.get_pixel(Vec2(index % size.width(), index / size.width()));
+
+            // the alpha channel is compressed without loss
+            assert_eq!(alpha, expected_alpha);
+
+            for (value, expected) in [(red, expected_red), (green, expected_green), (blue, expected_blue)] {
+                squared_error_sum += (value.to_f32() as f64 - expected.to_f32() as f64).powi(2);
+            }
+        }
+
+        let mean_squared_error = squared_error_sum / (size.area() * 3) as f64;
+        let peak_signal_to_noise_ratio = 10.0 * (1.0 / mean_squared_error).log10();
+        assert!(peak_signal_to_noise_ratio > 40.0, "{} has a psnr of {} db", compression, peak_signal_to_noise_ratio);
+
+        match compression {
+            Compression::DWAB(None) => default_level_byte_size = Some(bytes.len()),
+            Compression::DWAB(Some(_)) => assert!(Some(bytes.len()) < default_level_byte_size, "higher level should compress more"),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "zip")]
+fn zip_compression_level_does_not_change_the_pixels() {
+    let size = Vec2(157, 93);
+
+    // a smooth image with some noise, similar to a photograph
+    let pixels = SpecificChannels::rgb(|Vec2(x, y)| (
+        ((x as f32 * 0.05).sin() + ((x * 7919 + y * 104729) % 13) as f32 * 0.001).to_f16(),
+        (y as f32 / 93.0).to_f16(),
+        ((x + y) as f32 * 0.02).cos().to_f16(),
+    ));
+
+    for compression in [Compression::ZIP1, Compression::ZIP16] {
+        let image = Image::from_layer(Layer::new(
+            size, LayerAttributes::named("zip"),
+            Encoding { compression, .. Encoding::default() },
+            pixels.clone()
+        ));
+
+        let write = |level: u8| {
+            let mut bytes = Vec::new();
+            image.write().zip_compression_level(level).to_buffered(Cursor::new(&mut bytes)).unwrap();
+            bytes
+        };
+
+        let read_pixels = |bytes: &[u8]| read().no_deep_data().largest_resolution_level()
+            .rgb_channels(PixelVec::<(f16, f16, f16)>::constructor, PixelVec::set_pixel)
+            .first_valid_layer().all_attributes().from_buffered(Cursor::new(bytes)).unwrap()
+            .layer_data.channel_data.pixels;
+
+        let fastest = write(1);
+        let smallest = write(9);
+
+        assert!(smallest.len() <= fastest.len(), "level 9 is larger than level 1 with {}", compression);
+        assert_eq!(read_pixels(&fastest), read_pixels(&smallest));
+
+        let header = MetaData::read_from_buffered(Cursor::new(&fastest), false).unwrap().headers[0].clone();
+        assert_eq!(header.zip_compression_level(), None, "the level is not stored in the file");
+        assert_eq!(header.with_zip_compression_level(9).zip_compression_level(), Some(9));
+
+        let mut bytes = Vec::new();
+        let invalid_level = image.write().zip_compression_level(10).to_buffered(Cursor::new(&mut bytes));
+        assert!(matches!(invalid_level, Err(Error::Invalid(_))));
+    }
+}
+
+#[test]
+#[cfg(feature = "dwa")]
+fn dwa_compression_level_survives_rewriting() {
+    let path = "tests/images/valid/openexr/IlmfmlmflmTest/comp_dwaa_v1.exr";
+    let image = read_first_flat_layer_from_file(path).unwrap();
+
+    assert_eq!(image.layer_data.attributes.dwa_compression_level, Some(85.0));
+    assert_eq!(image.layer_data.encoding.compression, Compression::DWAA(Some(85.0)));
+
+    let rewrite = |image: &Image<Layer<AnyChannels<FlatSamples>>>, level: Option<f32>| {
+        let mut bytes = Vec::new();
+
+        match level {
+            Some(level) => image.write().dwa_compression_level(level).to_buffered(Cursor::new(&mut bytes)).unwrap(),
+            None => image.write().to_buffered(Cursor::new(&mut bytes)).unwrap(),
+        }
+
+        read().no_deep_data().largest_resolution_level().all_channels()
+            .first_valid_layer().all_attributes().from_buffered(Cursor::new(bytes)).unwrap()
+    };
+
+    let same_compression = rewrite(&image, None);
+    assert_eq!(same_compression.layer_data.attributes.dwa_compression_level, Some(85.0));
+    assert_eq!(same_compression.layer_data.encoding.compression, Compression::DWAA(Some(85.0)));
+
+    // the level is kept as metadata, even though zip does not use it
+    let mut zip_image = image.clone();
+    zip_image.layer_data.encoding.compression = Compression::ZIP16;
+
+    let other_compression = rewrite(&zip_image, None);
+    assert_eq!(other_compression.layer_data.attributes.dwa_compression_level, Some(85.0));
+    assert_eq!(other_compression.layer_data.encoding.compression, Compression::ZIP16);
+
+    let other_level = rewrite(&image, Some(45.0));
+    assert_eq!(other_level.layer_data.attributes.dwa_compression_level, Some(45.0));
+    assert_eq!(other_level.layer_data.encoding.compression, Compression::DWAA(Some(45.0)));
+}
+
+/// Flips all bits and omits trailing zero bytes. Not useful, but lossless and non-standard.
+struct XorCodec;
+
+impl BlockCodec for XorCodec {
+    fn compress(&self, _: &Header, uncompressed: Vec<u8>, _: IntegerBounds) -> Result<Vec<u8>> {
+        let length = uncompressed.iter().rposition(|&byte| byte != 0).map_or(0, |index| index + 1);
+        Ok(uncompressed[.. length].iter().map(|byte| !byte).collect())
+    }
+
+    fn decompress(&self, _: &Header, compressed: &[u8], _: IntegerBounds, expected_byte_size: usize, _: bool) -> Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = compressed.iter().map(|byte| !byte).collect();
+        bytes.resize(expected_byte_size, 0);
+        Ok(bytes)
+    }
+}
+
+#[test]
+fn roundtrip_custom_codec() {
+    let size = Vec2(64, 40);
+
+    // the green and red channels are stored last, so the zeroes of the last line of each block are trimmed
+    let pixels = SpecificChannels::rgba(|Vec2(x, y)| (
+        0.0_f32, 0.0_f32, (x as f32 * 0.3).cos() + y as f32 / 7.0, ((x * y) as f32).to_f16()
+    ));
+
+    let image = Image::from_layer(Layer::new(
+        size, LayerAttributes::named("custom"),
+        Encoding { compression: Compression::Custom(42), .. Encoding::default() },
+        pixels
+    ));
+
+    let codecs = CodecRegistry::new().with_codec(42, XorCodec).unwrap();
+
+    let mut bytes = Vec::new();
+    image.write().custom_codecs(codecs.clone()).to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+    let uncompressed_size = size.area() * (3 * 4 + 2);
+    assert!(bytes.len() < uncompressed_size, "custom codec was not used");
+
+    let reader = || read().no_deep_data().largest_resolution_level()
+        .rgba_channels(PixelVec::<(f32, f32, f32, f16)>::constructor, PixelVec::set_pixel)
+        .first_valid_layer().all_attributes();
+
+    let read_image = reader().custom_codecs(codecs).from_buffered(Cursor::new(&bytes)).unwrap();
+    assert_eq!(read_image.layer_data.encoding.compression, Compression::Custom(42));
+
+    let original_pixels = image.layer_data.channel_data.pixels.clone();
+    let read_pixels = read_image.layer_data.channel_data.pixels;
+    assert_eq!(read_pixels.pixels.len(), size.area());
+
+    for (index, read_pixel) in read_pixels.pixels.iter().enumerate() {
+        let original = original_pixels.get_pixel(Vec2(index % size.width(), index / size.width()));
+        assert_eq!(*read_pixel, (original.0, original.1, original.2, original.3));
+    }
+
+    let without_codec = reader().from_buffered(Cursor::new(&bytes));
+    assert!(matches!(without_codec, Err(Error::NotSupported(_))), "{:?}", without_codec.err());
+
+    let write_without_codec = image.write().to_buffered(Cursor::new(Vec::new()));
+    assert!(matches!(write_without_codec, Err(Error::NotSupported(_))));
+
+    // the ids of the standard compression methods cannot be used
+    for reserved_id in 0 ..= 9 {
+        let standard_id = CodecRegistry::new().with_codec(reserved_id, XorCodec);
+        assert!(matches!(standard_id, Err(Error::Invalid(_))), "id {} was accepted", reserved_id);
+    }
+
+    let mut standard_id = image.clone();
+    standard_id.layer_data.encoding.compression = Compression::Custom(3);
+    let write_standard_id = standard_id.write().custom_codecs(CodecRegistry::new()).to_buffered(Cursor::new(Vec::new()));
+    assert!(matches!(write_standard_id, Err(Error::Invalid(_))));
+}
+
+#[test]
+fn report_channels_that_are_compressed_lossy() {
+    use exr::compression::CompressionWarningReason;
+
+    let size = Vec2(16, 9);
+    let channels = AnyChannels::sort(smallvec::smallvec![
+        AnyChannel::new("R", FlatSamples::F32(vec![0.5; size.area()])),
+        AnyChannel::new("Z", FlatSamples::F32(vec![2.0; size.area()])),
+        AnyChannel::new("id", FlatSamples::U32(vec![7; size.area()])),
+    ]);
+
+    let image_with = |compression| Image::from_layer(Layer::new(
+        size, LayerAttributes::named("warnings"),
+        Encoding { compression, .. Encoding::default() },
+        channels.clone()
+    ));
+
+    let warnings = |compression| image_with(compression).write().compression_warnings().into_iter()
+        .map(|warning| (warning.channel.to_string(), warning.reason))
+        .collect::<Vec<_>>();
+
+    assert_eq!(warnings(Compression::ZIP16), vec![]);
+
+    assert_eq!(warnings(Compression::PXR24), vec![
+        ("R".to_string(), CompressionWarningReason::Lossy),
+        ("Z".to_string(), CompressionWarningReason::Lossy),
+    ]);
+
+    // depth and ids are stored without loss
+    assert_eq!(warnings(Compression::DWAA(None)), vec![
+        ("R".to_string(), CompressionWarningReason::Lossy),
+    ]);
+
+    assert_eq!(warnings(Compression::B44), vec![
+        ("R".to_string(), CompressionWarningReason::NotCompressed),
+        ("Z".to_string(), CompressionWarningReason::NotCompressed),
+        ("id".to_string(), CompressionWarningReason::NotCompressed),
+    ]);
+}
+
+#[test]
+#[cfg(feature = "piz")]
 fn roundtrip_piz() {
     test_mixed_roundtrip_with_compression(Compression::PIZ)
 }
 
+#[test]
+#[cfg(not(feature = "piz"))]
+fn report_compression_disabled_at_compile_time() {
+    let path = "tests/images/valid/custom/compression_methods/f16/piz.exr";
+
+    // the meta data can still be inspected
+    let meta = exr::meta::MetaData::read_from_file(path, false).unwrap();
+    assert_eq!(meta.headers[0].compression, Compression::PIZ);
+
+    match read_all_data_from_file(path) {
+        Err(Error::NotSupported(message)) => assert_eq!(message, "compression 'piz' disabled at compile time"),
+        _ => panic!("reading piz pixels should fail without the piz feature"),
+    }
+}
+
 #[test]
 fn roundtrip_uncompressed() {
     test_mixed_roundtrip_with_compression(Compression::Uncompressed)
 }
 
+#[test]
+fn compress_and_decompress_image_sections_without_chunks() {
+    let header = Header::new(
+        Text::from("sections"), (70, 50),
+        smallvec::smallvec![
+            ChannelDescription::new("B", SampleType::F16, true),
+            ChannelDescription::new("ID", SampleType::U32, false),
+            ChannelDescription::new("Z", SampleType::F32, false),
+        ],
+    );
+
+    let compressions = [
+        Compression::Uncompressed, Compression::RLE, Compression::ZIP1, Compression::ZIP16, Compression::PIZ,
+        Compression::PXR24, Compression::B44, Compression::B44A, Compression::DWAA(None), Compression::DWAB(None),
+    ];
+
+    for compression in compressions {
+        if compression == Compression::PXR24 && cfg!(target_endian = "big") { continue; } // TODO big endian pxr24
+
+        let header = header.clone().with_encoding(compression, exr::meta::BlockDescription::ScanLines, LineOrder::Increasing);
+        let last_block = header.chunk_count - 1;
+        let pixel_section = header.get_absolute_block_pixel_coordinates(exr::block::chunk::TileCoordinates {
+            tile_index: Vec2(0, last_block), level_index: Vec2(0, 0)
+        }).unwrap();
+
+        let expected_size = Vec2(70, 50 - last_block * compression.scan_lines_per_block());
+        assert_eq!(pixel_section.size, expected_size, "last block of {}", compression);
+
+        // interleaved line by line, each channel in the order of the channel list
+        let mut pixels = Vec::new();
+        for y in pixel_section.position.y() .. pixel_section.end().y() {
+            for x in 0 .. pixel_section.size.width() {
+                pixels.extend_from_slice(&f16::from_f32(x as f32 * 0.25).to_ne_bytes());
+            }
+
+            for x in 0 .. pixel_section.size.width() {
+                pixels.extend_from_slice(&(x as u32 / 7 + y as u32 * 1_000_000).to_ne_bytes());
+            }
+
+            for x in 0 .. pixel_section.size.width() {
+                pixels.extend_from_slice(&(y as f32 - x as f32).to_ne_bytes());
+            }
+        }
+
+        let compressed = compression.compress_image_section(&header, pixels.clone(), pixel_section).unwrap();
+        let decompressed = compression.decompress_image_section(&header, compressed.clone(), pixel_section, true).unwrap();
+        assert_eq!(decompressed.len(), pixels.len(), "{}", compression);
+
+        if !compression.may_loose_data() {
+            assert_eq!(decompressed, pixels, "{}", compression);
+        }
+
+        // the u32 ids are always stored without loss
+        let line_byte_size = pixel_section.size.width() * (2 + 4 + 4);
+        let ids = |bytes: &[u8]| -> Vec<u8> { bytes.chunks(line_byte_size)
+            .flat_map(|line| line[pixel_section.size.width() * 2 .. pixel_section.size.width() * 6].to_vec())
+            .collect() };
+
+        assert_eq!(ids(&decompressed), ids(&pixels), "ids in {}", compression);
+
+        let borrowed = compression.decompress_image_section_from_slice(&header, &compressed, pixel_section, true).unwrap();
+        assert_eq!(borrowed, decompressed, "{}", compression);
+
+        let too_large = IntegerBounds::new(pixel_section.position, Vec2(70, 300));
+        assert!(compression.compress_image_section(&header, pixels, too_large).is_err(), "{}", compression);
+    }
+}
+
 fn test_mixed_roundtrip_with_compression(compression: Compression) {
 
     let original_pixels: [(f16,f32,f32); 4] = [
@@ -352,7 +750,7 @@ fn test_mixed_roundtrip_with_compression(compression: Compression) {
     ];
 
     let mut file_bytes = Vec::new();
-    let original_image = Image::from_encoded_channels(
+    let mut original_image = Image::from_encoded_channels(
         (2,2),
         Encoding {
             compression,
@@ -363,6 +761,11 @@ fn test_mixed_roundtrip_with_compression(compression: Compression) {
         )
     );
 
+    // the level of dwa compression is also read as a layer attribute
+    if let Compression::DWAA(level) | Compression::DWAB(level) = compression {
+        original_image.layer_data.attributes.dwa_compression_level = level;
+    }
+
     original_image.write().to_buffered(Cursor::new(&mut file_bytes)).unwrap();
 
     let lossy_image = read().no_deep_data().largest_resolution_level()
@@ -374,3 +777,3837 @@ fn test_mixed_roundtrip_with_compression(compression: Compression) {
     lossy_image.assert_equals_result(&lossy_image);
     original_image.assert_equals_result(&lossy_image);
 }
+
+#[test]
+fn parallel_and_sequential_writing_produce_the_same_image() {
+    let size = Vec2(157, 93);
+    let pixels = SpecificChannels::rgba(|Vec2(x, y)| (
+        ((x * y) as f32 * 0.01).sin().to_f16(), (x as f32 * 0.3).cos(), y as f32 / 7.0, 1.0_f32
+    ));
+
+    let mut compression_methods = vec![
+        Compression::Uncompressed, Compression::RLE, Compression::ZIP1, Compression::ZIP16,
+        Compression::PIZ, Compression::B44, Compression::B44A,
+    ];
+
+    if cfg!(target_endian = "little") { // TODO big endian pxr24
+        compression_methods.push(Compression::PXR24);
+    }
+
+    for compression in compression_methods {
+        for line_order in [LineOrder::Increasing, LineOrder::Decreasing, LineOrder::Unspecified] {
+            for blocks in [Blocks::ScanLines, Blocks::Tiles(Vec2(32, 16))] {
+                if line_order == LineOrder::Unspecified && blocks == Blocks::ScanLines { continue; } // not allowed for scan lines
+
+                let encoding = Encoding { compression, blocks, line_order };
+                let image = Image::from_layer(Layer::new(size, LayerAttributes::named("test"), encoding, pixels.clone()));
+
+                let mut sequential_bytes = Vec::new();
+                image.write().non_parallel().to_buffered(Cursor::new(&mut sequential_bytes)).unwrap();
+
+                let mut parallel_bytes = Vec::new();
+                image.write().to_buffered(Cursor::new(&mut parallel_bytes)).unwrap();
+
+                // with a specified line order, the chunks are sorted before writing them to the file
+                if line_order != LineOrder::Unspecified {
+                    assert!(parallel_bytes == sequential_bytes, "files differ for {:?}, {:?}, {:?}", compression, line_order, blocks);
+                }
+
+                let read_image = |bytes: &[u8]| read()
+                    .no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+                    .non_parallel().from_buffered(Cursor::new(bytes)).unwrap();
+
+                let sequential_image = read_image(&sequential_bytes);
+                let parallel_image = read_image(&parallel_bytes);
+                sequential_image.assert_equals_result(&parallel_image);
+            }
+        }
+    }
+}
+
+#[test]
+fn write_to_unseekable_stream() {
+    /// Can only be written to, not seeked.
+    struct WriteOnly(Vec<u8>);
+    impl std::io::Write for WriteOnly {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> { self.0.write(bytes) }
+        fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+    }
+
+    let size = Vec2(97, 61);
+    let layer = |name: &str, compression: Compression, blocks: Blocks, line_order: LineOrder| Layer::new(
+        size, LayerAttributes::named(name),
+        Encoding { compression, blocks, line_order },
+        SpecificChannels::rgb(|Vec2(x, y)| ((x as f32 * 0.1).sin(), (y as f32 * 0.3).cos(), (x * y) as f32))
+    );
+
+    let image = Image::from_layers(
+        ImageAttributes::new(IntegerBounds::from_dimensions(size)),
+        vec![
+            layer("scan lines", Compression::ZIP16, Blocks::ScanLines, LineOrder::Decreasing),
+            layer("tiles", Compression::PIZ, Blocks::Tiles(Vec2(16, 32)), LineOrder::Unspecified),
+            layer("raw", Compression::Uncompressed, Blocks::ScanLines, LineOrder::Increasing),
+        ]
+    );
+
+    let mut seeking_bytes = Vec::new();
+    image.write().non_parallel().to_buffered(Cursor::new(&mut seeking_bytes)).unwrap();
+
+    let mut sequential_stream = WriteOnly(Vec::new());
+    image.write().non_parallel().to_stream(&mut sequential_stream).unwrap();
+    assert!(sequential_stream.0 == seeking_bytes, "streamed file differs from seeked file");
+
+    // parallel compression may produce the unspecified line order chunks in any order
+    let mut progress = Vec::new();
+    let mut parallel_stream = WriteOnly(Vec::new());
+    image.write().on_progress(|value| progress.push(value)).to_stream(&mut parallel_stream).unwrap();
+
+    assert_eq!(progress.last(), Some(&1.0), "progress must end at one");
+    assert!(progress.windows(2).all(|pair| pair[0] <= pair[1]), "progress must only be reported while writing");
+
+    let read_image = |bytes: &[u8]| read()
+        .no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+        .non_parallel().pedantic().from_buffered(Cursor::new(bytes)).unwrap();
+
+    read_image(&seeking_bytes).assert_equals_result(&read_image(&parallel_stream.0));
+}
+
+#[test]
+fn push_scan_lines_one_at_a_time() {
+    let size = Vec2(41, 533);
+    let color = |Vec2(x, y): Vec2<usize>| ((x as f32 * 0.1).sin().to_f16(), (y as f32 * 0.01).cos(), (x * y) as u32);
+
+    let encoding = Encoding { compression: Compression::ZIP16, blocks: Blocks::ScanLines, line_order: LineOrder::Increasing };
+    let channels = SpecificChannels::build()
+        .with_channel("H").with_channel("F").with_channel("U")
+        .with_pixel_fn(color);
+
+    let image = Image::from_layer(Layer::new(size, LayerAttributes::named("render"), encoding, channels));
+
+    let mut expected_bytes = Vec::new();
+    image.write().non_parallel().to_buffered(Cursor::new(&mut expected_bytes)).unwrap();
+
+    let mut bytes = Vec::new();
+    let mut writer = exr::block::writer::ScanLineWriter::new(
+        Cursor::new(&mut bytes), image.write().infer_meta_data(), true
+    ).unwrap();
+
+    // the channels in the header are sorted by name
+    let channel_names: Vec<String> = writer.meta_data().headers[0].channels.list.iter()
+        .map(|channel| channel.name.to_string()).collect();
+
+    for y in 0 .. size.height() {
+        let samples: Vec<Vec<f32>> = channel_names.iter().map(|name| (0 .. size.width()).map(|x| {
+            let (h, f, u) = color(Vec2(x, y));
+            match name.as_str() { "H" => h.to_f32(), "F" => f, _ => u as f32 }
+        }).collect()).collect();
+
+        let samples: Vec<&[f32]> = samples.iter().map(Vec::as_slice).collect();
+        writer.push_lines(0, y .. y + 1, &samples).unwrap();
+
+        if y == 100 {
+            assert!(writer.push_lines(0, y .. y + 1, &samples).is_err(), "duplicate line must be rejected");
+            assert!(writer.push_lines(0, y + 2 .. y + 3, &samples).is_err(), "skipped line must be rejected");
+        }
+    }
+
+    writer.finish().unwrap();
+    assert!(bytes == expected_bytes, "pushed lines produced a different file");
+}
+
+#[test]
+fn write_with_shared_thread_pools_of_any_size() {
+    let size = Vec2(200, 150);
+    let encoding = Encoding { compression: Compression::ZIP16, blocks: Blocks::Tiles(Vec2(32, 32)), line_order: LineOrder::Unspecified };
+    let pixels = SpecificChannels::rgba(|Vec2(x, y)| ((x as f32).sin(), (y as f32).cos(), (x + y) as f32, 1.0_f32));
+    let image = Image::from_layer(Layer::new(size, LayerAttributes::named("pooled"), encoding, pixels));
+
+    let read_image = |bytes: &[u8]| read()
+        .no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+        .non_parallel().from_buffered(Cursor::new(bytes)).unwrap();
+
+    let mut expected_bytes = Vec::new();
+    image.write().non_parallel().to_buffered(Cursor::new(&mut expected_bytes)).unwrap();
+    let expected_image = read_image(&expected_bytes);
+
+    for thread_count in [1, 16] {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(thread_count).build().unwrap();
+
+        let mut bytes = Vec::new();
+        image.write().parallel_with_pool(&pool).to_buffered(Cursor::new(&mut bytes)).unwrap();
+        expected_image.assert_equals_result(&read_image(&bytes));
+
+        // writing from within the pool must not wait for a thread that is blocked by the writer itself
+        let mut bytes = Vec::new();
+        pool.install(|| image.write().parallel_with_pool(&pool).to_buffered(Cursor::new(&mut bytes))).unwrap();
+        expected_image.assert_equals_result(&read_image(&bytes));
+    }
+}
+
+#[test]
+fn limit_pending_chunks_while_compressing_in_parallel() {
+    use exr::block::writer::ChunksWriter;
+    use exr::image::write::layers::{WritableLayers, LayersWriter};
+
+    let size = Vec2(256, 192);
+    let encoding = Encoding { compression: Compression::PIZ, blocks: Blocks::Tiles(Vec2(16, 16)), line_order: LineOrder::Unspecified };
+    let pixels = SpecificChannels::rgb(|Vec2(x, y)| ((x as f32 * 0.2).sin(), (y * x) as f32, (x + y) as f32));
+    let image = Image::from_layer(Layer::new(size, LayerAttributes::named("backwards"), encoding, pixels));
+
+    let max_pending_chunks = 3;
+    let headers = image.write().infer_meta_data();
+    let layers = image.layer_data.create_writer(&headers);
+    let mut peak_pending_chunk_count = 0;
+
+    let mut bytes = Vec::new();
+    exr::block::write(Cursor::new(&mut bytes), headers, true, |meta, chunk_writer| {
+        let mut blocks: Vec<_> = meta.collect_ordered_block_data(|block_index|
+            layers.extract_uncompressed_block(&meta.headers, block_index)
+        ).collect();
+
+        blocks.reverse();
+
+        let mut compressor = chunk_writer.parallel_blocks_compressor(&meta)
+            .expect("compressed image should be compressed in parallel")
+            .max_pending_chunks(max_pending_chunks);
+
+        for (index_in_header_increasing_y, block) in blocks {
+            compressor.add_block_to_compression_queue(index_in_header_increasing_y, block)?;
+            peak_pending_chunk_count = peak_pending_chunk_count.max(compressor.pending_chunk_count());
+        }
+
+        compressor.write_all_queued_chunks()
+    }).unwrap();
+
+    assert!(peak_pending_chunk_count <= max_pending_chunks, "{} chunks were pending", peak_pending_chunk_count);
+
+    let read_image = |bytes: &[u8]| read()
+        .no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+        .non_parallel().from_buffered(Cursor::new(bytes)).unwrap();
+
+    let mut expected_bytes = Vec::new();
+    image.write().non_parallel().to_buffered(Cursor::new(&mut expected_bytes)).unwrap();
+    read_image(&expected_bytes).assert_equals_result(&read_image(&bytes));
+
+    let mut limited_bytes = Vec::new();
+    image.write().max_pending_chunks(1).to_buffered(Cursor::new(&mut limited_bytes)).unwrap();
+    assert!(limited_bytes == expected_bytes, "a single pending chunk must result in sequential order");
+}
+
+#[test]
+#[cfg(feature = "parallel-iterator")]
+fn compress_blocks_from_parallel_iterator() {
+    use exr::block::writer::ChunksWriter;
+    use exr::image::write::layers::{WritableLayers, LayersWriter};
+    use rayon::iter::IntoParallelIterator;
+
+    let size = Vec2(301, 207);
+    let pixels = SpecificChannels::rgb(|Vec2(x, y)| ((x as f32 * 0.2).sin(), (y * x) as f32, (x + y) as f32));
+
+    for (blocks, line_order) in [
+        (Blocks::ScanLines, LineOrder::Increasing),
+        (Blocks::ScanLines, LineOrder::Decreasing),
+        (Blocks::Tiles(Vec2(32, 16)), LineOrder::Unspecified),
+    ] {
+        let encoding = Encoding { compression: Compression::ZIP16, blocks, line_order };
+        let image = Image::from_layer(Layer::new(size, LayerAttributes::named("parallel"), encoding, pixels.clone()));
+
+        let headers = image.write().infer_meta_data();
+        let layers = image.layer_data.create_writer(&headers);
+
+        let mut bytes = Vec::new();
+        exr::block::write(Cursor::new(&mut bytes), headers, true, |meta, chunk_writer| {
+            let block_indices: Vec<_> = meta.enumerate_ordered_header_block_indices().collect();
+            let blocks = block_indices.into_par_iter().map(|(index_in_header, block_index)| (
+                index_in_header,
+                exr::block::UncompressedBlock {
+                    index: block_index,
+                    data: layers.extract_uncompressed_block(&meta.headers, block_index)
+                }
+            ));
+
+            chunk_writer.compress_all_blocks_from_par_iter(&meta, blocks)
+        }).unwrap();
+
+        let mut expected_bytes = Vec::new();
+        image.write().non_parallel().to_buffered(Cursor::new(&mut expected_bytes)).unwrap();
+        assert!(bytes == expected_bytes, "parallel iterator produced a different file for {:?}", line_order);
+    }
+}
+
+#[test]
+fn write_statistics_count_bytes_per_layer() {
+    use exr::block::writer::ChunksWriter;
+    use exr::image::write::layers::{WritableLayers, LayersWriter};
+
+    let size = Vec2(120, 80);
+    let layer = |name: &str, compression: Compression| Layer::new(
+        size, LayerAttributes::named(name),
+        Encoding { compression, .. Encoding::default() },
+        SpecificChannels::rgb(|Vec2(x, y)| ((x as f32 * 0.2).sin(), (y * x) as f32, 0.5_f32))
+    );
+
+    let image = Image::from_layers(
+        ImageAttributes::new(IntegerBounds::from_dimensions(size)),
+        vec![ layer("raw", Compression::Uncompressed), layer("zip", Compression::ZIP16) ]
+    );
+
+    let headers = image.write().infer_meta_data();
+    let layers = image.layer_data.create_writer(&headers);
+    let mut written_byte_counts = Vec::new();
+
+    let mut bytes = Vec::new();
+    let statistics = exr::block::writer::write_chunks_with_statistics(Cursor::new(&mut bytes), headers, true, |meta, chunk_writer| {
+        let blocks = meta.collect_ordered_block_data(|block_index|
+            layers.extract_uncompressed_block(&meta.headers, block_index)
+        );
+
+        let mut compressor = chunk_writer.sequential_blocks_compressor(&meta);
+        for (index_in_header_increasing_y, block) in blocks {
+            compressor.compress_block(index_in_header_increasing_y, block)?;
+            written_byte_counts.push(compressor.inner_chunks_writer().written_byte_count());
+        }
+
+        Ok(())
+    }).unwrap();
+
+    assert_eq!(statistics.written_byte_count, bytes.len() as u64);
+    assert_eq!(written_byte_counts.last(), Some(&(bytes.len() as u64)));
+    assert!(written_byte_counts.windows(2).all(|pair| pair[0] < pair[1]), "written byte count must increase with each chunk");
+
+    assert_eq!(statistics.written_chunk_count, statistics.total_chunk_count);
+    assert_eq!(statistics.written_chunk_count, written_byte_counts.len());
+    assert_eq!(statistics.chunk_byte_counts_per_layer.len(), 2);
+
+    // the uncompressed layer contains the raw pixels and a small header for each chunk
+    let [raw_byte_count, zip_byte_count] = [statistics.chunk_byte_counts_per_layer[0], statistics.chunk_byte_counts_per_layer[1]];
+    assert!(raw_byte_count > (size.area() * 3 * 4) as u64);
+    assert!(zip_byte_count < raw_byte_count);
+    assert!(statistics.chunk_byte_count() < statistics.written_byte_count);
+}
+
+#[test]
+fn read_concurrently_with_shared_thread_pool() {
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    let path = "tests/images/valid/custom/compression_methods/f32/zip.exr";
+
+    let expected = read()
+        .no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+        .non_parallel().from_file(path).unwrap();
+
+    // many concurrent reads, all sharing the same two decompression threads
+    (0 .. 8).into_par_iter().for_each(|_| {
+        let image = read()
+            .no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+            .parallel_with_pool(&pool).from_file(path).unwrap();
+
+        expected.assert_equals_result(&image);
+    });
+}
+
+#[test]
+fn read_with_limited_and_excessive_in_flight_blocks() {
+    let path = "tests/images/valid/custom/compression_methods/f32/piz.exr";
+
+    let expected = read()
+        .no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+        .non_parallel().from_file(path).unwrap();
+
+    for max_in_flight_blocks in [0, 1, 10_000] {
+        let image = read()
+            .no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+            .max_in_flight_blocks(max_in_flight_blocks).from_file(path).unwrap();
+
+        expected.assert_equals_result(&image);
+    }
+
+    let chunks = exr::block::read(std::io::BufReader::new(std::fs::File::open(path).unwrap()), false).unwrap()
+        .all_chunks(false).unwrap();
+
+    let chunk_count = chunks.len();
+    let decompressor = chunks.parallel_decompressor(false).ok().unwrap().max_in_flight(1);
+    assert_eq!(decompressor.map(|block| block.unwrap()).count(), chunk_count);
+}
+
+#[test]
+fn read_from_unseekable_stream() {
+    /// Hides the `Seek` implementation of the inner reader.
+    struct Stream<R>(R);
+    impl<R: std::io::Read> std::io::Read for Stream<R> {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> { self.0.read(buffer) }
+    }
+
+    for path in [
+        "tests/images/valid/custom/compression_methods/f32/zip.exr",
+        "tests/images/valid/openexr/MultiResolution/Kapaa.exr",
+    ] {
+        let read_image = read()
+            .no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+            .pedantic();
+
+        let expected = read_image.clone().from_file(path).unwrap();
+        let streamed = read_image.from_stream(Stream(std::fs::File::open(path).unwrap())).unwrap();
+        expected.assert_equals_result(&streamed);
+    }
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn read_blocks_asynchronously() {
+    use futures::StreamExt;
+    use exr::block::read_async::AsyncReader;
+
+    for path in [
+        "tests/images/valid/custom/compression_methods/f32/zip.exr",
+        "tests/images/valid/openexr/MultiResolution/Kapaa.exr",
+    ] {
+        let bytes = std::fs::read(path).unwrap();
+
+        let mut expected_blocks = Vec::new();
+        exr::block::read(Cursor::new(&bytes), true).unwrap()
+            .all_chunks(true).unwrap()
+            .decompress_sequential(true, |_, block| { expected_blocks.push(block); Ok(()) })
+            .unwrap();
+
+        let mut blocks: Vec<_> = futures::executor::block_on(async {
+            let reader = AsyncReader::read_from_buffered(futures::io::Cursor::new(&bytes), true).await.unwrap();
+            reader.blocks_with_thread_pool(None, 1).map(|block| block.unwrap()).collect::<Vec<_>>().await
+        });
+
+        let sort_key = |block: &exr::block::UncompressedBlock| {
+            let index = block.index;
+            (index.layer, index.level.x(), index.level.y(), index.pixel_position.y(), index.pixel_position.x())
+        };
+        expected_blocks.sort_by_key(sort_key);
+        blocks.sort_by_key(sort_key);
+        assert_eq!(blocks, expected_blocks);
+
+        let parallel_blocks = futures::executor::block_on(async {
+            let reader = AsyncReader::read_from_buffered(futures::io::Cursor::new(&bytes), true).await.unwrap();
+            reader.blocks().map(|block| block.unwrap()).collect::<Vec<_>>().await
+        });
+
+        assert_eq!(parallel_blocks.len(), expected_blocks.len());
+    }
+}
+
+#[test]
+fn read_borrowed_chunks_from_slice() {
+    for path in [
+        "tests/images/valid/custom/compression_methods/f32/uncompressed.exr",
+        "tests/images/valid/custom/compression_methods/f32/piz.exr",
+        "tests/images/valid/openexr/MultiResolution/Kapaa.exr",
+    ] {
+        let bytes = std::fs::read(path).unwrap();
+
+        let mut expected_blocks = Vec::new();
+        exr::block::read(Cursor::new(&bytes), true).unwrap()
+            .all_chunks(true).unwrap()
+            .decompress_sequential(true, |_, block| { expected_blocks.push(block); Ok(()) })
+            .unwrap();
+
+        let mut blocks = Vec::new();
+        exr::block::reader::SliceReader::read_from_slice(&bytes, true).unwrap()
+            .all_chunks(true).unwrap()
+            .decompress_sequential(true, |_, block| { blocks.push(block); Ok(()) })
+            .unwrap();
+
+        let sort_key = |block: &exr::block::UncompressedBlock| {
+            let index = block.index;
+            (index.layer, index.level.x(), index.level.y(), index.pixel_position.y(), index.pixel_position.x())
+        };
+
+        expected_blocks.sort_by_key(sort_key);
+        blocks.sort_by_key(sort_key);
+        assert_eq!(blocks, expected_blocks);
+    }
+}
+
+#[test]
+fn decompress_chunks_from_slice_in_parallel() {
+    for path in [
+        "tests/images/valid/custom/crowskull/crow_zips.exr",
+        "tests/images/valid/custom/compression_methods/f32/piz.exr",
+        "tests/images/valid/openexr/MultiResolution/Kapaa.exr",
+    ] {
+        let bytes = std::fs::read(path).unwrap();
+
+        let mut expected_blocks = Vec::new();
+        exr::block::reader::SliceReader::read_from_slice(&bytes, true).unwrap()
+            .all_chunks(true).unwrap()
+            .decompress_sequential(true, |_, block| { expected_blocks.push(block); Ok(()) })
+            .unwrap();
+
+        let caller_thread = std::thread::current().id();
+        let mut blocks = Vec::new();
+        exr::block::reader::SliceReader::read_from_slice(&bytes, true).unwrap()
+            .all_chunks(true).unwrap()
+            .decompress_parallel(true, |_, block| {
+                assert_eq!(std::thread::current().id(), caller_thread, "blocks must be inserted on the calling thread");
+                blocks.push(block);
+                Ok(())
+            })
+            .unwrap();
+
+        let sort_key = |block: &exr::block::UncompressedBlock| {
+            let index = block.index;
+            (index.layer, index.level.x(), index.level.y(), index.pixel_position.y(), index.pixel_position.x())
+        };
+
+        expected_blocks.sort_by_key(sort_key);
+        blocks.sort_by_key(sort_key);
+        assert_eq!(blocks, expected_blocks);
+
+        // an error in the closure aborts decompression without waiting for the remaining blocks to be consumed
+        let mut inserted_blocks = 0;
+        let aborted = exr::block::reader::SliceReader::read_from_slice(&bytes, true).unwrap()
+            .all_chunks(true).unwrap()
+            .decompress_parallel(true, |_, _| {
+                inserted_blocks += 1;
+                Err(exr::error::Error::Aborted)
+            });
+
+        assert!(aborted.is_err());
+        assert_eq!(inserted_blocks, 1);
+    }
+}
+
+#[test]
+fn read_cached_blocks_in_any_order() {
+    use std::rc::Rc;
+    use std::cell::Cell;
+    use std::io::{Read, Seek, SeekFrom};
+
+    /// Counts the bytes that are read from the inner reader.
+    struct CountingReader<R> { inner: R, read_bytes: Rc<Cell<usize>> }
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            let count = self.inner.read(buffer)?;
+            self.read_bytes.set(self.read_bytes.get() + count);
+            Ok(count)
+        }
+    }
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> { self.inner.seek(position) }
+    }
+
+    let path = "tests/images/valid/openexr/MultiResolution/Kapaa.exr";
+    let bytes = std::fs::read(path).unwrap();
+
+    let mut expected_blocks = Vec::new();
+    exr::block::read(Cursor::new(&bytes), true).unwrap()
+        .all_chunks(true).unwrap()
+        .decompress_sequential(true, |_, block| { expected_blocks.push(block); Ok(()) })
+        .unwrap();
+
+    let first = expected_blocks[0].clone();
+    let second = expected_blocks.iter()
+        .find(|block| block.index.level == first.index.level && block.index.pixel_position != first.index.pixel_position)
+        .unwrap().clone();
+
+    let tile_of = |block: &exr::block::UncompressedBlock| {
+        let tile_size = block.index.pixel_size; // the first tiles of a level have the full tile size
+        Vec2(block.index.pixel_position.x() / tile_size.width(), block.index.pixel_position.y() / tile_size.height())
+    };
+
+    let read_bytes = Rc::new(Cell::new(0));
+    let counting = CountingReader { inner: Cursor::new(&bytes), read_bytes: read_bytes.clone() };
+
+    // enough space for exactly one block
+    let mut reader = exr::block::read(counting, true).unwrap()
+        .cached_blocks(true, first.data.len()).unwrap();
+
+    let block = reader.block_at(first.index.layer, first.index.level, tile_of(&first)).unwrap();
+    assert_eq!(*block, first);
+
+    // a cache hit does not read any bytes
+    let bytes_before_hit = read_bytes.get();
+    let block = reader.block_at(first.index.layer, first.index.level, tile_of(&first)).unwrap();
+    assert_eq!(*block, first);
+    assert_eq!(read_bytes.get(), bytes_before_hit);
+    assert_eq!(reader.cached_byte_size(), first.data.len());
+
+    // reading another block evicts the first block
+    let block = reader.block_at(second.index.layer, second.index.level, tile_of(&second)).unwrap();
+    assert_eq!(*block, second);
+
+    let bytes_before_miss = read_bytes.get();
+    let block = reader.block_at(first.index.layer, first.index.level, tile_of(&first)).unwrap();
+    assert_eq!(*block, first);
+    assert!(read_bytes.get() > bytes_before_miss);
+
+    assert!(reader.block_at(1, Vec2(0, 0), Vec2(0, 0)).is_err());
+    assert!(reader.block_at(0, Vec2(0, 0), Vec2(10_000, 0)).is_err());
+}
+
+#[test]
+fn read_single_blocks_by_tile_coordinates() {
+    use exr::block::chunk::TileCoordinates;
+
+    for path in [
+        "tests/images/valid/custom/compression_methods/f32/zip.exr",
+        "tests/images/valid/openexr/MultiResolution/Kapaa.exr",
+    ] {
+        let bytes = std::fs::read(path).unwrap();
+        let mut reader = exr::block::read(Cursor::new(&bytes), true).unwrap();
+
+        let tiles: Vec<TileCoordinates> = reader.headers()[0].blocks_increasing_y_order()
+            .map(|tile| tile.location).collect();
+
+        // read in reverse order, to require seeking backwards
+        let blocks: Vec<_> = tiles.iter().rev()
+            .map(|&tile| reader.read_block(0, tile).unwrap())
+            .collect();
+
+        assert!(reader.read_block(1, tiles[0]).is_err());
+        assert!(reader.read_block(0, TileCoordinates { tile_index: Vec2(10_000, 0), level_index: Vec2(0, 0) }).is_err());
+
+        // reading single blocks does not disturb reading all blocks afterwards
+        let mut expected_blocks = Vec::new();
+        reader.all_chunks(true).unwrap()
+            .decompress_sequential(true, |_, block| { expected_blocks.push(block); Ok(()) })
+            .unwrap();
+
+        assert_eq!(blocks.len(), expected_blocks.len());
+        for block in blocks {
+            assert!(expected_blocks.contains(&block));
+        }
+    }
+}
+
+#[test]
+fn read_layers_by_name() {
+    let path = "tests/images/valid/openexr/Beachball/multipart.0001.exr";
+    let bytes = std::fs::read(path).unwrap();
+
+    let reader = exr::block::read(Cursor::new(&bytes), true).unwrap();
+    let header = reader.headers()[1].clone();
+    let layer_name = header.own_attributes.layer_name.clone().unwrap().to_string();
+
+    let chunks = reader.filter_chunks_by_layer_names(true, &[&layer_name]).unwrap();
+    assert_eq!(chunks.len(), header.chunk_count);
+
+    let all_chunks_of_layer = chunks
+        .map(|chunk| chunk.unwrap())
+        .all(|chunk| chunk.layer_index == 1);
+
+    assert!(all_chunks_of_layer);
+
+    let typo = exr::block::read(Cursor::new(&bytes), true).unwrap()
+        .filter_chunks_by_layer_names(true, &[&layer_name, "no such layer"]);
+
+    assert!(typo.is_err());
+
+    let image = read().no_deep_data().largest_resolution_level().all_channels()
+        .layers_matching(|header| header.has_layer_name(&layer_name))
+        .all_attributes().from_buffered(Cursor::new(&bytes)).unwrap();
+
+    assert_eq!(image.layer_data.len(), 1);
+    assert_eq!(image.layer_data[0].attributes.layer_name, header.own_attributes.layer_name);
+
+    let no_match = read().no_deep_data().largest_resolution_level().all_channels()
+        .layers_matching(|header| header.has_layer_name("no such layer"))
+        .all_attributes().from_buffered(Cursor::new(&bytes));
+
+    assert!(no_match.is_err());
+
+    // layer names encoded in channel name prefixes
+    let path = "tests/images/valid/openexr/Beachball/singlepart.0001.exr";
+    let reader = exr::block::read(std::fs::File::open(path).unwrap(), true).unwrap();
+    let prefixed_channel = reader.headers()[0].channels.list.iter()
+        .map(|channel| channel.name.to_string())
+        .find(|name| name.contains('.')).unwrap();
+
+    let prefix = prefixed_channel.split('.').next().unwrap();
+    assert!(reader.headers()[0].has_layer_name(prefix));
+
+    let chunks = reader.filter_chunks_by_layer_names(true, &[prefix]).unwrap();
+    assert_eq!(chunks.len(), chunks.meta_data().headers[0].chunk_count);
+}
+
+#[test]
+fn select_single_layer_by_name_or_filter() {
+    let path = "tests/images/valid/openexr/Beachball/multipart.0001.exr";
+    let bytes = std::fs::read(path).unwrap();
+
+    let all_layers = read().no_deep_data().largest_resolution_level().all_channels()
+        .all_layers().all_attributes().from_buffered(Cursor::new(&bytes)).unwrap();
+
+    let second_layer_name = all_layers.layer_data[1].attributes.layer_name.clone().unwrap();
+    assert_eq!(second_layer_name, Text::from("depth_left"));
+
+    let second_layer = read().no_deep_data().largest_resolution_level().all_channels()
+        .layer_named(second_layer_name.clone()).all_attributes().from_buffered(Cursor::new(&bytes)).unwrap();
+
+    assert_eq!(second_layer.layer_data.attributes.layer_name, Some(second_layer_name));
+    assert_eq!(second_layer.layer_data, all_layers.layer_data[1]);
+    assert_ne!(second_layer.layer_data.channel_data, all_layers.layer_data[0].channel_data);
+
+    // two layers with the same channels but different pixels
+    let right = read().no_deep_data().largest_resolution_level()
+        .rgba_channels(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+        .layer_named("rgba_right").all_attributes().from_buffered(Cursor::new(&bytes)).unwrap();
+
+    let left = read().no_deep_data().largest_resolution_level()
+        .rgba_channels(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+        .layer_matching(|header: &Header| header.own_attributes.layer_name == Some(Text::from("rgba_left")))
+        .all_attributes().from_buffered(Cursor::new(&bytes)).unwrap();
+
+    assert_eq!(left.layer_data.attributes.layer_name, Some(Text::from("rgba_left")));
+    assert_eq!(left.layer_data.size, right.layer_data.size);
+    assert_ne!(left.layer_data.channel_data.pixels, right.layer_data.channel_data.pixels);
+
+    let depth = read().no_deep_data().largest_resolution_level()
+        .specific_channels().required("Z")
+        .collect_pixels(PixelVec::<(f32,)>::constructor, PixelVec::set_pixel)
+        .layer_named("depth_right").all_attributes().from_buffered(Cursor::new(&bytes)).unwrap();
+
+    assert_eq!(depth.layer_data.attributes.layer_name, Some(Text::from("depth_right")));
+
+    // layer names derived from channel name prefixes
+    let disparity = read().no_deep_data().largest_resolution_level().all_channels()
+        .layer_named("disparityR").all_attributes().from_buffered(Cursor::new(&bytes)).unwrap();
+
+    assert_eq!(disparity.layer_data.channel_data.list[0].name, Text::from("disparityR.x"));
+
+    let assert_invalid = |result: Result<Image<Layer<AnyChannels<FlatSamples>>>>, expected: &[&str]| match result {
+        Err(Error::Invalid(message)) => for expected in expected {
+            assert!(message.contains(expected), "`{}` does not contain `{}`", message, expected);
+        },
+        other => panic!("expected an error, but got {:?}", other.map(|_| ())),
+    };
+
+    // the error lists the available layers
+    assert_invalid(
+        read().no_deep_data().largest_resolution_level().all_channels()
+            .layer_named("beauty").all_attributes().from_buffered(Cursor::new(&bytes)),
+        &["`beauty`", "`rgba_right`", "`depth_left`", "`forward`", "`whitebarmask_right`"]
+    );
+
+    assert_invalid(
+        read().no_deep_data().largest_resolution_level().all_channels()
+            .layer_matching(|header: &Header| header.channels.list.len() > 4)
+            .all_attributes().from_buffered(Cursor::new(&bytes)),
+        &["no layer", "`rgba_left`"]
+    );
+
+    // the prefix `forward` is contained in two layers
+    assert_invalid(
+        read().no_deep_data().largest_resolution_level().all_channels()
+            .layer_named("forward").all_attributes().from_buffered(Cursor::new(&bytes)),
+        &["more than one layer", "`forward`"]
+    );
+}
+
+#[test]
+fn read_region_of_interest() {
+    use exr::meta::attribute::IntegerBounds;
+
+    fn read_image(path: &str, region: Option<IntegerBounds>) -> FlatImage {
+        let read_image = read().no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes();
+        let read_image = match region { Some(region) => read_image.region(region), None => read_image };
+        read_image.from_file(path).unwrap()
+    }
+
+    for path in [
+        "tests/images/valid/openexr/ScanLines/Blobbies.exr",
+        "tests/images/valid/openexr/Tiles/Spirals.exr",
+        "tests/images/valid/openexr/MultiResolution/Kapaa.exr",
+    ] {
+        let full = read_image(path, None);
+        let data_window = full.layer_data[0].absolute_bounds();
+
+        let regions = [
+            // inside the data window, not aligned to blocks
+            IntegerBounds::new(data_window.position + Vec2(17, 33), Vec2(61, 45)),
+
+            // partially outside the data window
+            IntegerBounds::new(data_window.position - Vec2(10, 20), Vec2(50, 70)),
+            IntegerBounds::new(data_window.end() - Vec2(30, 30), Vec2(100, 100)),
+
+            // containing the whole data window
+            IntegerBounds::new(data_window.position - Vec2(1, 1), data_window.size + Vec2(2, 2)),
+        ];
+
+        for region in regions {
+            let cropped = read_image(path, Some(region));
+            let expected_bounds = data_window.intersection(region).unwrap();
+
+            for (full_layer, cropped_layer) in full.layer_data.iter().zip(&cropped.layer_data) {
+                assert_eq!(cropped_layer.absolute_bounds(), expected_bounds);
+
+                let offset = (expected_bounds.position - data_window.position).to_usize("offset").unwrap();
+                let full_width = full_layer.size.width();
+
+                for (full_channel, cropped_channel) in full_layer.channel_data.list.iter().zip(&cropped_layer.channel_data.list) {
+                    assert_eq!(full_channel.name, cropped_channel.name);
+                    let full_samples = &full_channel.sample_data;
+                    let cropped_samples = &cropped_channel.sample_data;
+
+                    for y in 0 .. expected_bounds.size.height() {
+                        for x in 0 .. expected_bounds.size.width() {
+                            let full_index = (y + offset.y()) * full_width + x + offset.x();
+                            let cropped_index = y * expected_bounds.size.width() + x;
+
+                            assert_eq!(
+                                full_samples.value_by_flat_index(full_index).to_f32().to_bits(),
+                                cropped_samples.value_by_flat_index(cropped_index).to_f32().to_bits()
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        // completely outside the data window
+        let outside = read_image(path, Some(IntegerBounds::new(data_window.end() + Vec2(5, 5), Vec2(10, 10))));
+        assert!(outside.layer_data.iter().all(|layer| layer.size == Vec2(0, 0)));
+    }
+}
+
+#[test]
+fn cancel_parallel_decompression_after_first_block() {
+    use std::ops::ControlFlow;
+
+    let path = "tests/images/valid/custom/compression_methods/f32/piz.exr";
+    let open_chunks = || exr::block::read(std::io::BufReader::new(std::fs::File::open(path).unwrap()), false).unwrap()
+        .all_chunks(false).unwrap();
+
+    assert!(open_chunks().len() > 1);
+
+    let mut inserted_blocks = 0;
+    let result = open_chunks().decompress_parallel_cancellable(false, |_, _| {
+        inserted_blocks += 1;
+        Ok(ControlFlow::Break(()))
+    });
+
+    assert_eq!(result.unwrap(), ControlFlow::Break(()));
+    assert_eq!(inserted_blocks, 1);
+
+    // not cancelling decompresses all blocks
+    let chunk_count = open_chunks().len();
+    let mut inserted_blocks = 0;
+    let result = open_chunks().decompress_parallel_cancellable(false, |_, _| {
+        inserted_blocks += 1;
+        Ok(ControlFlow::Continue(()))
+    });
+
+    assert_eq!(result.unwrap(), ControlFlow::Continue(()));
+    assert_eq!(inserted_blocks, chunk_count);
+
+    // dropping the decompressor mid-iteration does not block or panic
+    let mut decompressor = open_chunks().parallel_decompressor(false).ok().unwrap();
+    decompressor.next().unwrap().unwrap();
+    drop(decompressor);
+}
+
+#[test]
+fn report_block_progress_while_reading_and_writing() {
+    use exr::block::reader::BlockProgress;
+    use exr::block::writer::{ChunksWriter, ChunkWriteProgress};
+
+    let path = "tests/images/valid/openexr/MultiResolution/Kapaa.exr";
+    let bytes = std::fs::read(path).unwrap();
+
+    let mut read_progress: Vec<BlockProgress> = Vec::new();
+    let reader = exr::block::read(Cursor::new(&bytes), true).unwrap();
+    let headers = reader.headers().to_vec();
+
+    let chunks: Vec<_> = reader.all_chunks(true).unwrap()
+        .on_block_progress(|progress| read_progress.push(progress))
+        .map(|chunk| chunk.unwrap())
+        .collect();
+
+    assert_eq!(read_progress.len(), chunks.len());
+    assert_eq!(read_progress.last().unwrap().decoded_chunk_count, chunks.len());
+    assert!(read_progress.iter().all(|progress| progress.expected_chunk_count == chunks.len()));
+
+    let total_bytes: u64 = chunks.iter().map(|chunk| chunk.compressed_byte_size() as u64).sum();
+    assert_eq!(read_progress.last().unwrap().compressed_byte_count, total_bytes);
+
+    for (progress, chunk) in read_progress.iter().zip(&chunks) {
+        let tile = headers[chunk.layer_index].get_block_data_indices(&chunk.compressed_block).unwrap();
+        assert_eq!(progress.tile, tile);
+        assert_eq!(progress.block.level, tile.level_index);
+    }
+
+    // filtered chunks report the filtered count
+    let mut filtered_progress = Vec::new();
+    let filtered_count = exr::block::read(Cursor::new(&bytes), true).unwrap()
+        .filter_chunks(true, |_, tile, _| tile.is_largest_resolution_level()).unwrap()
+        .on_block_progress(|progress| filtered_progress.push(progress))
+        .map(|chunk| chunk.unwrap()).count();
+
+    assert_eq!(filtered_progress.len(), filtered_count);
+    assert!(filtered_progress.iter().all(|progress| progress.expected_chunk_count == filtered_count && progress.block.level == Vec2(0, 0)));
+
+    let mut write_progress: Vec<ChunkWriteProgress> = Vec::new();
+    exr::block::write(Cursor::new(Vec::new()), headers.clone().into(), true, |_, writer| {
+        let mut writer = writer.on_block_progress(|progress| write_progress.push(progress));
+
+        for chunk in chunks.clone() {
+            let header = &headers[chunk.layer_index];
+            let tile = header.get_block_data_indices(&chunk.compressed_block)?;
+            let index = header.blocks_increasing_y_order().position(|block| block.location == tile).unwrap();
+            writer.write_chunk(index, chunk)?;
+        }
+
+        Ok(())
+    }).unwrap();
+
+    assert_eq!(write_progress.len(), chunks.len());
+    assert_eq!(write_progress.last().unwrap().written_chunk_count, chunks.len());
+    assert_eq!(write_progress.last().unwrap().total_chunk_count, chunks.len());
+    assert_eq!(write_progress.last().unwrap().compressed_byte_count, total_bytes);
+}
+
+#[test]
+fn recover_from_zeroed_offset_tables() {
+    let path = "tests/images/valid/custom/compression_methods/f32/zip.exr";
+    let mut bytes = std::fs::read(path).unwrap();
+
+    let read_image = read().no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes();
+    let expected = read_image.clone().from_buffered(Cursor::new(&bytes)).unwrap();
+
+    let chunk_count = exr::block::read(Cursor::new(&bytes), true).unwrap()
+        .all_chunks(true).unwrap().len();
+
+    // in this file, the first offset points to the byte right after the offset table
+    let table_start = (0 .. bytes.len() - 8)
+        .find(|&position| {
+            let offset = u64::from_le_bytes(std::convert::TryInto::try_into(&bytes[position .. position + 8]).unwrap());
+            offset == (position + chunk_count * 8) as u64
+        })
+        .unwrap();
+
+    for byte in &mut bytes[table_start .. table_start + chunk_count * 8] {
+        *byte = 0;
+    }
+
+    let recovered = read_image.clone().from_buffered(Cursor::new(&bytes)).unwrap();
+    expected.assert_equals_result(&recovered);
+
+    assert!(read_image.clone().pedantic().from_buffered(Cursor::new(&bytes)).is_err());
+
+    let mut recovered_blocks = 0;
+    exr::block::read(Cursor::new(&bytes), true).unwrap()
+        .all_chunks_ignoring_offsets(true).unwrap()
+        .decompress_sequential(true, |_, _| { recovered_blocks += 1; Ok(()) })
+        .unwrap();
+
+    assert_eq!(recovered_blocks, chunk_count);
+}
+
+#[test]
+fn skip_broken_blocks_and_read_partial_image() {
+    let path = "tests/images/valid/custom/compression_methods/f32/zip.exr";
+    let mut bytes = std::fs::read(path).unwrap();
+
+    let read_image = read().no_deep_data().largest_resolution_level().all_channels().first_valid_layer().all_attributes();
+    let expected = read_image.clone().from_buffered(Cursor::new(&bytes)).unwrap();
+
+    let mut reader = exr::block::read(Cursor::new(&bytes), true).unwrap();
+    let broken_tile = reader.headers()[0].blocks_increasing_y_order().nth(1).unwrap().location;
+    let broken_block_size = reader.read_block(0, broken_tile).unwrap().index.pixel_size;
+
+    let chunk_count = reader.all_chunks(true).unwrap().len();
+    assert!(chunk_count > 2);
+
+    // in this file, the first offset points to the byte right after the offset table
+    let table_start = (0 .. bytes.len() - 8)
+        .find(|&position| {
+            let offset = u64::from_le_bytes(std::convert::TryInto::try_into(&bytes[position .. position + 8]).unwrap());
+            offset == (position + chunk_count * 8) as u64
+        })
+        .unwrap();
+
+    // overwrite the compressed pixels of the second chunk, keeping the y coordinate and byte size intact
+    let second_chunk_start = u64::from_le_bytes(std::convert::TryInto::try_into(&bytes[table_start + 8 .. table_start + 16]).unwrap()) as usize;
+    let second_chunk_size = u32::from_le_bytes(std::convert::TryInto::try_into(&bytes[second_chunk_start + 4 .. second_chunk_start + 8]).unwrap()) as usize;
+    for byte in &mut bytes[second_chunk_start + 8 .. second_chunk_start + 8 + second_chunk_size] {
+        *byte = 0xff;
+    }
+
+    assert!(read_image.clone().from_buffered(Cursor::new(&bytes)).is_err());
+
+    for parallel in [true, false] {
+        let read_image = if parallel { read_image.clone() } else { read_image.clone().non_parallel() };
+        let partial = read_image.skip_broken_blocks(f32::NAN).from_buffered(Cursor::new(&bytes)).unwrap();
+
+        assert_eq!(partial.broken_blocks.len(), 1);
+        let broken = partial.broken_blocks[0];
+        assert_eq!(broken.pixel_size, broken_block_size);
+
+        let width = expected.layer_data.size.width();
+        let is_broken_pixel = |index: usize| {
+            let (x, y) = (index % width, index / width);
+            y >= broken.pixel_position.y() && y < broken.pixel_position.y() + broken.pixel_size.height()
+                && x >= broken.pixel_position.x() && x < broken.pixel_position.x() + broken.pixel_size.width()
+        };
+
+        for (expected_channel, channel) in expected.layer_data.channel_data.list.iter().zip(&partial.image.layer_data.channel_data.list) {
+            for index in 0 .. expected_channel.sample_data.len() {
+                let sample = channel.sample_data.value_by_flat_index(index).to_f32();
+                let expected_sample = expected_channel.sample_data.value_by_flat_index(index).to_f32();
+
+                if is_broken_pixel(index) { assert!(sample.is_nan()); }
+                else { assert_eq!(sample.to_bits(), expected_sample.to_bits()); }
+            }
+        }
+    }
+}
+
+#[test]
+fn read_offset_tables_and_chunk_byte_ranges() {
+    for path in [
+        "tests/images/valid/custom/compression_methods/f32/zip.exr",
+        "tests/images/valid/openexr/MultiResolution/Kapaa.exr",
+        "tests/images/valid/openexr/Beachball/multipart.0001.exr",
+    ] {
+        let bytes = std::fs::read(path).unwrap();
+        let mut reader = exr::block::read(Cursor::new(&bytes), true).unwrap();
+
+        let offset_tables = reader.offset_tables().unwrap();
+        let chunk_ranges = reader.chunk_byte_ranges().unwrap();
+
+        let total_chunk_count: usize = offset_tables.iter().map(|table| table.len()).sum();
+        assert_eq!(chunk_ranges.len(), total_chunk_count);
+        assert!(chunk_ranges.windows(2).all(|pair| pair[0].byte_offset + pair[0].byte_size == pair[1].byte_offset));
+
+        let last = chunk_ranges.last().unwrap();
+        assert_eq!(last.byte_offset + last.byte_size, bytes.len() as u64);
+
+        for range in &chunk_ranges {
+            assert!(offset_tables[range.block.layer].contains(&range.byte_offset));
+
+            let start = range.byte_offset as usize;
+            let mut chunk_bytes = &bytes[start .. start + range.byte_size as usize];
+            let chunk = exr::block::chunk::Chunk::read(&mut chunk_bytes, reader.meta_data()).unwrap();
+
+            assert!(chunk_bytes.is_empty(), "chunk byte range too large");
+            assert_eq!(chunk.layer_index, range.block.layer);
+            assert_eq!(reader.headers()[chunk.layer_index].get_block_data_indices(&chunk.compressed_block).unwrap(), range.tile);
+        }
+
+        for range in &chunk_ranges {
+            assert_eq!(range.layer, range.block.layer);
+        }
+
+        // the reader is still positioned at the offset tables
+        let chunk_count = reader.all_chunks(true).unwrap()
+            .map(|chunk| chunk.unwrap()).count();
+
+        assert_eq!(chunk_count, total_chunk_count);
+
+        // chunks can also be filtered after looking up the byte ranges
+        let mut reader = exr::block::read(Cursor::new(&bytes), true).unwrap();
+        let first_layer_chunks = reader.chunk_byte_ranges().unwrap().iter()
+            .filter(|range| range.block.layer == 0).count();
+
+        let filtered_count = reader.filter_chunks(true, |_, _, block| block.layer == 0).unwrap()
+            .map(|chunk| chunk.unwrap()).count();
+
+        assert_eq!(filtered_count, first_layer_chunks);
+    }
+}
+
+#[test]
+fn sort_chunk_byte_ranges_by_block_index() {
+    use exr::block::reader::ChunkByteRange;
+    use exr::block::BlockIndex;
+    use exr::block::chunk::TileCoordinates;
+
+    // (layer, level, pixel position), in the order they might appear in a file
+    let file_order = [
+        (1, Vec2(0, 0), Vec2(0, 16)),
+        (0, Vec2(1, 1), Vec2(0, 0)),
+        (1, Vec2(0, 0), Vec2(0, 0)),
+        (0, Vec2(0, 0), Vec2(16, 0)),
+        (0, Vec2(0, 0), Vec2(0, 16)),
+        (2, Vec2(0, 0), Vec2(0, 0)),
+        (0, Vec2(0, 0), Vec2(0, 0)),
+        (0, Vec2(1, 0), Vec2(0, 0)),
+        (0, Vec2(0, 1), Vec2(0, 0)),
+        (0, Vec2(0, 0), Vec2(16, 16)),
+    ];
+
+    let mut ranges: Vec<ChunkByteRange> = file_order.iter().enumerate()
+        .map(|(index, &(layer, level, pixel_position))| ChunkByteRange {
+            layer,
+            byte_offset: 100 + index as u64 * 10,
+            byte_size: 10,
+            tile: TileCoordinates { tile_index: pixel_position / Vec2(16, 16), level_index: level },
+            block: BlockIndex { layer, level, pixel_position, pixel_size: Vec2(16, 16) },
+        })
+        .collect();
+
+    ChunkByteRange::sort_by_block_index(&mut ranges);
+
+    let sorted: Vec<(usize, Vec2<usize>, Vec2<usize>)> = ranges.iter()
+        .map(|range| (range.layer, range.block.level, range.block.pixel_position))
+        .collect();
+
+    assert_eq!(sorted, vec![
+        (0, Vec2(0, 0), Vec2(0, 0)),
+        (0, Vec2(0, 0), Vec2(16, 0)),
+        (0, Vec2(0, 0), Vec2(0, 16)),
+        (0, Vec2(0, 0), Vec2(16, 16)),
+        (0, Vec2(1, 0), Vec2(0, 0)),
+        (0, Vec2(0, 1), Vec2(0, 0)),
+        (0, Vec2(1, 1), Vec2(0, 0)),
+        (1, Vec2(0, 0), Vec2(0, 0)),
+        (1, Vec2(0, 0), Vec2(0, 16)),
+        (2, Vec2(0, 0), Vec2(0, 0)),
+    ]);
+
+    // the byte ranges are moved together with the block
+    assert_eq!(ranges[0].byte_offset, 160);
+    assert_eq!(ranges[9].byte_offset, 150);
+}
+
+#[test]
+fn parallel_decompression_preserves_chunk_order() {
+    let path = "tests/images/valid/custom/compression_methods/f32/zip.exr";
+    let open_chunks = || exr::block::read(std::io::BufReader::new(std::fs::File::open(path).unwrap()), true).unwrap()
+        .all_chunks(true).unwrap();
+
+    // the chunks of this file are stored in increasing y order
+    let expected_positions: Vec<_> = open_chunks().sequential_decompressor(true)
+        .map(|block| block.unwrap().index.pixel_position)
+        .collect();
+
+    assert!(expected_positions.len() > 2);
+    assert!(expected_positions.windows(2).all(|pair| pair[0].y() < pair[1].y()));
+
+    for max_in_flight in [1, 3, 10_000] {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+
+        let positions: Vec<_> = open_chunks().parallel_decompressor_with_pool(true, &pool).ok().unwrap()
+            .max_in_flight(max_in_flight).preserve_order()
+            .map(|block| block.unwrap().index.pixel_position)
+            .collect();
+
+        assert_eq!(positions, expected_positions);
+    }
+}
+
+#[test]
+fn chunk_readers_share_meta_data_without_cloning() {
+    let path = "tests/images/valid/openexr/Beachball/multipart.0001.exr";
+    let reader = exr::block::read(std::io::BufReader::new(std::fs::File::open(path).unwrap()), true).unwrap();
+    let meta_data = reader.shared_meta_data();
+
+    let chunks = reader.all_chunks(true).unwrap().on_progress(|_| {});
+    assert!(std::sync::Arc::ptr_eq(&chunks.shared_meta_data(), &meta_data));
+
+    let decompressor = chunks.parallel_decompressor(true).ok().unwrap();
+    assert!(std::sync::Arc::strong_count(&meta_data) > 2, "the decompressor should share the meta data instead of cloning it");
+    assert_eq!(decompressor.meta_data().headers.len(), meta_data.headers.len());
+}
+
+#[test]
+fn convert_block_sample_types() {
+    use exr::block::{UncompressedBlock, BlockIndex};
+    use exr::meta::attribute::{ChannelList, ChannelDescription, SampleType};
+
+    let channels = ChannelList::new(smallvec::smallvec![
+        ChannelDescription::named("A", SampleType::F16),
+        ChannelDescription::named("B", SampleType::F32),
+        ChannelDescription::named("Z", SampleType::U32),
+    ]);
+
+    let index = BlockIndex { layer: 0, level: Vec2(0, 0), pixel_position: Vec2(3, 5), pixel_size: Vec2(4, 3) };
+
+    let block = UncompressedBlock::from_lines(&channels, index, |line| {
+        let x = line.location.position.x() as f32;
+        let y = line.location.position.y() as f32;
+
+        match line.location.channel {
+            0 => line.write_samples(|sample| f16::from_f32(x * 0.25 + y + sample as f32 * -1.5)),
+            1 => line.write_samples(|sample| x * 1000.0 - y + sample as f32),
+            _ => line.write_samples(|sample| if sample == 0 { u32::MAX } else { sample as u32 * 7 }),
+        }.unwrap();
+    });
+
+    let (f32_channels, f32_block) = block.convert_samples(&channels, SampleType::F32);
+    assert_eq!(f32_channels.uniform_sample_type, Some(SampleType::F32));
+    assert_eq!(f32_block.data.len(), index.pixel_size.area() * 3 * 4);
+
+    // f16 values are representable in f32, so converting back is bit-exact
+    let (f16_channels, f16_block) = f32_block.convert_samples(&f32_channels, SampleType::F16);
+
+    for (original, converted) in block.lines(&channels).zip(f16_block.lines(&f16_channels)) {
+        assert_eq!(original.location, converted.location);
+
+        if original.location.channel == 0 {
+            assert_eq!(original.value, converted.value, "f16 samples changed after converting to f32 and back");
+        }
+    }
+
+    // large integers saturate instead of becoming infinite
+    let (f16_channels, f16_block) = block.convert_samples(&channels, SampleType::F16);
+    let saturated = f16_block.lines(&f16_channels)
+        .filter(|line| line.location.channel == 2)
+        .flat_map(|line| line.samples_as_f32(SampleType::F16).collect::<Vec<f32>>())
+        .fold(0.0_f32, f32::max);
+
+    assert_eq!(saturated, f16::MAX.to_f32());
+
+    // floats saturate when converted to integers
+    let (u32_channels, u32_block) = block.convert_samples(&channels, SampleType::U32);
+    let first_b_line = u32_block.lines(&u32_channels).find(|line| line.location.channel == 1).unwrap();
+    let first_a_line = u32_block.lines(&u32_channels).find(|line| line.location.channel == 0).unwrap();
+    assert_eq!(first_b_line.samples(SampleType::U32).next().unwrap().to_u32(), 2995);
+    assert!(first_a_line.samples(SampleType::U32).all(|sample| sample.to_u32() <= 6));
+}
+
+#[test]
+fn read_single_resolution_level() {
+    for (path, level) in [
+        ("tests/images/valid/openexr/MultiResolution/ColorCodedLevels.exr", Vec2(3, 3)), // mip map
+        ("tests/images/valid/openexr/MultiResolution/Kapaa.exr", Vec2(2, 1)), // rip map
+    ] {
+        let all_levels = read().no_deep_data().all_resolution_levels().all_channels().first_valid_layer().all_attributes()
+            .from_file(path).unwrap();
+
+        let single_level = read().no_deep_data().largest_resolution_level().all_channels().first_valid_layer().all_attributes()
+            .resolution_level(level).non_parallel()
+            .from_file(path).unwrap();
+
+        let meta_data = exr::meta::MetaData::read_from_file(path, false).unwrap();
+        let header = &meta_data.headers[0];
+        let rounding_mode = match header.blocks { exr::meta::BlockDescription::Tiles(tiles) => tiles.rounding_mode, _ => unreachable!() };
+
+        let expected_size = Vec2(
+            exr::meta::compute_level_size(rounding_mode, header.layer_size.width(), level.x()),
+            exr::meta::compute_level_size(rounding_mode, header.layer_size.height(), level.y()),
+        );
+        assert_eq!(single_level.layer_data.size, expected_size);
+        assert_eq!(single_level.layer_data.channel_data.list.len(), all_levels.layer_data.channel_data.list.len());
+
+        for (level_channel, all_levels_channel) in single_level.layer_data.channel_data.list.iter().zip(&all_levels.layer_data.channel_data.list) {
+            assert_eq!(level_channel.name, all_levels_channel.name);
+            assert_eq!(&level_channel.sample_data, all_levels_channel.sample_data.get_level(level).unwrap());
+        }
+
+        // the level also works with specific channels
+        let rgba_level = read().no_deep_data().largest_resolution_level()
+            .rgba_channels(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+            .first_valid_layer().all_attributes().resolution_level(level)
+            .from_file(path).unwrap();
+
+        assert_eq!(rgba_level.layer_data.size, expected_size);
+        assert_eq!(rgba_level.layer_data.channel_data.pixels.pixels.len(), expected_size.area());
+    }
+
+    let missing_level = read().no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+        .resolution_level(Vec2(40, 40))
+        .from_file("tests/images/valid/openexr/MultiResolution/ColorCodedLevels.exr");
+
+    match missing_level {
+        Err(Error::Invalid(message)) => assert!(message.contains("levels"), "unexpected message: {}", message),
+        other => panic!("expected an invalid level error, but got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn read_chunk_sizes_without_pixels() {
+    for path in [
+        "tests/images/valid/custom/compression_methods/f32/zip.exr",
+        "tests/images/valid/openexr/MultiResolution/Kapaa.exr",
+        "tests/images/valid/openexr/Beachball/multipart.0001.exr",
+    ] {
+        let bytes = std::fs::read(path).unwrap();
+
+        let reader = exr::block::read(Cursor::new(&bytes), true).unwrap();
+        let headers = reader.headers().to_vec();
+
+        let expected: Vec<_> = reader.all_chunks(true).unwrap().map(|chunk| {
+            let chunk = chunk.unwrap();
+            let header = &headers[chunk.layer_index];
+            let tile = header.get_block_data_indices(&chunk.compressed_block).unwrap();
+            let data_indices = header.get_absolute_block_pixel_coordinates(tile).unwrap();
+            (chunk.layer_index, tile.level_index, data_indices, chunk.compressed_byte_size())
+        }).collect();
+
+        let as_expected = |sizes: Vec<(exr::block::BlockIndex, usize)>| -> Vec<_> {
+            sizes.into_iter().map(|(block, size)| (
+                block.layer, block.level,
+                IntegerBounds::new(block.pixel_position.to_i32(), block.pixel_size), size
+            )).collect()
+        };
+
+        let sequential = exr::block::read(Cursor::new(&bytes), true).unwrap().chunk_sizes().unwrap();
+        assert_eq!(as_expected(sequential), expected, "{}", path);
+
+        let mut reader = exr::block::read(Cursor::new(&bytes), true).unwrap();
+        let from_offsets = reader.chunk_sizes_from_offset_tables().unwrap();
+        assert_eq!(as_expected(from_offsets), expected, "{}", path);
+
+        // the reader can still be used afterwards
+        assert_eq!(reader.all_chunks(true).unwrap().count(), expected.len());
+    }
+}
+
+#[test]
+fn read_partially_written_file_after_checkpoint() {
+    use exr::block::writer::ChunksWriter;
+    use exr::image::write::layers::{WritableLayers, LayersWriter};
+
+    let size = Vec2(40, 64);
+    let pixel = |Vec2(x, y): Vec2<usize>| ((x as f32 * 0.3).sin(), y as f32, (x * y) as f32);
+
+    let image = Image::from_layer(Layer::new(
+        size, LayerAttributes::named("partial"),
+        Encoding { compression: Compression::ZIP1, blocks: Blocks::ScanLines, line_order: LineOrder::Increasing },
+        SpecificChannels::rgb(pixel)
+    ));
+
+    let headers = image.write().infer_meta_data();
+    let layers = image.layer_data.create_writer(&headers);
+    let written_chunk_count = size.height() / 2;
+
+    // simulate a crash by aborting halfway, such that the meta data is never completed
+    let mut bytes = Vec::new();
+    let aborted = exr::block::writer::write_chunks_with(Cursor::new(&mut bytes), headers, true, |meta, chunk_writer| {
+        chunk_writer.set_checkpoint_interval(Some(written_chunk_count));
+
+        let blocks = meta.collect_ordered_block_data(|block_index|
+            layers.extract_uncompressed_block(&meta.headers, block_index)
+        );
+
+        let mut compressor = chunk_writer.sequential_blocks_compressor(&meta);
+        for (index_in_header_increasing_y, block) in blocks.take(written_chunk_count) {
+            compressor.compress_block(index_in_header_increasing_y, block)?;
+        }
+
+        Err(Error::Aborted)
+    });
+
+    assert!(aborted.is_err());
+
+    let read_rgb = read().no_deep_data().largest_resolution_level()
+        .rgb_channels(PixelVec::<(f32, f32, f32)>::constructor, PixelVec::set_pixel)
+        .first_valid_layer().all_attributes();
+
+    assert!(read_rgb.clone().pedantic().from_buffered(Cursor::new(&bytes)).is_err(), "pedantic reading must reject the incomplete file");
+
+    let partial = read_rgb.from_buffered(Cursor::new(&bytes)).unwrap();
+    let pixels = &partial.layer_data.channel_data.pixels;
+
+    for y in 0 .. written_chunk_count {
+        for x in 0 .. size.width() {
+            assert_eq!(pixels.get_pixel(Vec2(x, y)), &pixel(Vec2(x, y)));
+        }
+    }
+
+    // the blocks that were not written keep their default value
+    assert_eq!(pixels.get_pixel(Vec2(0, size.height() - 1)), &(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn atomic_file_writing_leaves_destination_untouched_on_error() {
+    use std::io::{Seek, SeekFrom, Write};
+
+    /// Fails all writes after the specified number of bytes.
+    struct FailingWrite<W> { inner: W, remaining_bytes: usize }
+
+    impl<W: Write> Write for FailingWrite<W> {
+        fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+            if buffer.len() > self.remaining_bytes {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"));
+            }
+
+            self.remaining_bytes -= buffer.len();
+            self.inner.write(buffer)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> { self.inner.flush() }
+    }
+
+    impl<W: Seek> Seek for FailingWrite<W> {
+        fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> { self.inner.seek(position) }
+    }
+
+    let directory = std::env::temp_dir().join(format!("exrs_atomic_write_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("image.exr");
+
+    let image = Image::from_channels(
+        (64, 64), SpecificChannels::rgb(|Vec2(x, y)| (x as f32, y as f32, 0.5_f32))
+    );
+
+    let previous_contents = b"previous contents".to_vec();
+    std::fs::write(&path, &previous_contents).unwrap();
+
+    let failed = exr::io::write_file_atomically(&path, |file| {
+        image.write().to_unbuffered(FailingWrite { inner: file, remaining_bytes: 2000 })
+    });
+
+    assert!(failed.is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), previous_contents, "destination must not be modified");
+
+    let directory_entries = || std::fs::read_dir(&directory).unwrap().count();
+    assert_eq!(directory_entries(), 1, "temporary file must be deleted");
+
+    image.write().to_file_atomic(&path).unwrap();
+    assert_eq!(directory_entries(), 1, "temporary file must be renamed");
+
+    let written = read_first_rgba_layer_from_file(
+        &path, PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel
+    ).unwrap();
+
+    assert_eq!(written.layer_data.channel_data.pixels.get_pixel(Vec2(3, 5)), &(3.0, 5.0, 0.5, 1.0));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn write_decreasing_line_order() {
+    let size = Vec2(70, 90);
+    let pixels = SpecificChannels::rgb(|Vec2(x, y)| ((x as f32 * 0.1).sin(), y as f32, (x * y) as f32));
+
+    for blocks in [Blocks::ScanLines, Blocks::Tiles(Vec2(16, 32))] {
+        let layer = |line_order| Layer::new(
+            size, LayerAttributes::named("ordered"),
+            Encoding { compression: Compression::ZIP1, blocks, line_order: LineOrder::Increasing },
+            pixels.clone()
+        ).with_line_order(line_order);
+
+        let write = |line_order, parallel: bool| {
+            let image = Image::from_layer(layer(line_order));
+            let mut bytes = Vec::new();
+
+            let writer = image.write();
+            let writer = if parallel { writer } else { writer.non_parallel() };
+            writer.to_buffered(Cursor::new(&mut bytes)).unwrap();
+            bytes
+        };
+
+        let read_image = read().no_deep_data().largest_resolution_level()
+            .rgb_channels(PixelVec::<(f32, f32, f32)>::constructor, PixelVec::set_pixel)
+            .first_valid_layer().all_attributes();
+
+        let increasing_bytes = write(LineOrder::Increasing, false);
+        let decreasing_bytes = write(LineOrder::Decreasing, false);
+        assert!(decreasing_bytes == write(LineOrder::Decreasing, true), "parallel writing changed the file for {:?}", blocks);
+
+        let increasing = read_image.clone().from_buffered(Cursor::new(&increasing_bytes)).unwrap();
+        let decreasing = read_image.from_buffered(Cursor::new(&decreasing_bytes)).unwrap();
+        assert_eq!(decreasing.layer_data.encoding.line_order, LineOrder::Decreasing);
+        assert_eq!(decreasing.layer_data.channel_data, increasing.layer_data.channel_data, "{:?}", blocks);
+
+        // the offset tables must point to the blocks in reversed physical order
+        let blocks_in_file_order = |bytes: &[u8]| -> Vec<Vec2<usize>> {
+            exr::block::read(Cursor::new(bytes), true).unwrap()
+                .chunk_sizes_from_offset_tables().unwrap()
+                .into_iter().map(|(block, _)| block.pixel_position)
+                .collect()
+        };
+
+        let mut reversed_increasing = blocks_in_file_order(&increasing_bytes);
+        reversed_increasing.reverse();
+
+        let decreasing_blocks = blocks_in_file_order(&decreasing_bytes);
+        assert_eq!(decreasing_blocks, reversed_increasing, "{:?}", blocks);
+        assert!(decreasing_blocks.first().unwrap().y() > decreasing_blocks.last().unwrap().y());
+    }
+
+    // writing the blocks in increasing order is rejected for a decreasing layer
+    {
+        use exr::block::writer::ChunksWriter;
+        use exr::image::write::layers::{WritableLayers, LayersWriter};
+
+        let image = Image::from_layer(Layer::new(size, LayerAttributes::named("wrong order"), Encoding::SMALL_LOSSLESS, pixels)
+            .with_line_order(LineOrder::Decreasing));
+
+        let headers = image.write().infer_meta_data();
+        let layers = image.layer_data.create_writer(&headers);
+
+        let result = exr::block::write(Cursor::new(Vec::new()), headers, true, |meta, chunk_writer| {
+            let blocks = exr::block::enumerate_ordered_header_block_indices(&meta.headers)
+                .collect::<Vec<_>>().into_iter().rev() // increasing order
+                .map(|(index_in_header_increasing_y, block_index)| (
+                    index_in_header_increasing_y,
+                    exr::block::UncompressedBlock { index: block_index, data: layers.extract_uncompressed_block(&meta.headers, block_index) }
+                ));
+
+            let mut compressor = chunk_writer.sequential_blocks_compressor(&meta);
+            for (index_in_header_increasing_y, block) in blocks {
+                compressor.compress_block(index_in_header_increasing_y, block)?;
+            }
+
+            Ok(())
+        });
+
+        assert!(result.is_err(), "blocks written in the wrong order must be rejected");
+    }
+}
+
+#[test]
+fn append_layer_without_recompressing_existing_layers() {
+    let size = Vec2(60, 40);
+
+    let beauty = Layer::new(
+        size, LayerAttributes::named("beauty"), Encoding::SMALL_LOSSLESS,
+        SpecificChannels::rgb(|Vec2(x, y)| ((x as f32 * 0.1).sin(), y as f32, 0.25_f32))
+    );
+
+    let depth = Layer::new(
+        size, LayerAttributes::named("depth"), Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(smallvec::smallvec![ AnyChannel::new(
+            "Z", FlatSamples::F32((0 .. size.area()).map(|index| index as f32).collect())
+        ) ])
+    );
+
+    let mut existing_bytes = Vec::new();
+    Image::from_layer(beauty).write().to_buffered(Cursor::new(&mut existing_bytes)).unwrap();
+
+    let mut appended_bytes = Vec::new();
+    Image::from_layer(depth.clone()).write()
+        .append_to_buffered(Cursor::new(&existing_bytes), Cursor::new(&mut appended_bytes))
+        .unwrap();
+
+    let read_all = |bytes: &[u8]| read().no_deep_data().largest_resolution_level()
+        .all_channels().all_layers().all_attributes()
+        .from_buffered(Cursor::new(bytes)).unwrap();
+
+    let existing = read_all(&existing_bytes);
+    let appended = read_all(&appended_bytes);
+
+    assert_eq!(appended.layer_data.len(), 2);
+    assert_eq!(appended.layer_data[0], existing.layer_data[0]);
+    assert_eq!(appended.layer_data[1].channel_data, depth.channel_data);
+    assert_eq!(appended.layer_data[1].attributes.layer_name, depth.attributes.layer_name);
+
+    // the compressed bytes of the existing layer are copied unchanged
+    let compressed_blocks = |bytes: &[u8], layer_index: usize| -> Vec<_> {
+        exr::block::read(Cursor::new(bytes), true).unwrap()
+            .all_chunks(true).unwrap()
+            .map(|chunk| chunk.unwrap())
+            .filter(|chunk| chunk.layer_index == layer_index)
+            .map(|chunk| format!("{:?}", chunk.compressed_block))
+            .collect()
+    };
+
+    assert_eq!(compressed_blocks(&appended_bytes, 0), compressed_blocks(&existing_bytes, 0));
+}
+
+#[test]
+fn rewrite_headers_without_recompressing_chunks() {
+    use exr::block::writer::rewrite_with_modified_headers;
+
+    let path = "tests/images/valid/openexr/Beachball/multipart.0001.exr";
+    let bytes = std::fs::read(path).unwrap();
+
+    let chunk_payload_hashes = |bytes: &[u8]| -> Vec<u64> {
+        use std::hash::{Hash, Hasher};
+
+        let mut hashes: Vec<u64> = exr::block::read(Cursor::new(bytes), true).unwrap()
+            .all_chunks(true).unwrap()
+            .map(|chunk| {
+                let chunk = chunk.unwrap();
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                chunk.layer_index.hash(&mut hasher);
+                format!("{:?}", chunk.compressed_block).hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect();
+
+        hashes.sort_unstable();
+        hashes
+    };
+
+    let mut rewritten = Vec::new();
+    rewrite_with_modified_headers(Cursor::new(&bytes), Cursor::new(&mut rewritten), true, |headers| {
+        for header in headers.iter_mut() {
+            header.own_attributes.comments = Some(Text::from("rewritten"));
+            header.shared_attributes.pixel_aspect = 2.0;
+        }
+
+        Ok(())
+    }).unwrap();
+
+    assert_eq!(chunk_payload_hashes(&rewritten), chunk_payload_hashes(&bytes));
+
+    let meta = MetaData::read_from_buffered(Cursor::new(&rewritten), true).unwrap();
+    assert!(meta.headers.iter().all(|header| header.own_attributes.comments == Some(Text::from("rewritten"))));
+    assert!(meta.headers.iter().all(|header| header.shared_attributes.pixel_aspect == 2.0));
+
+    // the pixels are decoded exactly as before
+    let read_all = |bytes: &[u8]| read().no_deep_data().largest_resolution_level()
+        .all_channels().all_layers().all_attributes()
+        .from_buffered(Cursor::new(bytes)).unwrap();
+
+    let original_layers = read_all(&bytes).layer_data;
+    let rewritten_layers = read_all(&rewritten).layer_data;
+    for (original, rewritten) in original_layers.iter().zip(&rewritten_layers) {
+        assert_eq!(original.channel_data, rewritten.channel_data);
+    }
+
+    // changing the pixel layout is rejected
+    let changed_compression = rewrite_with_modified_headers(Cursor::new(&bytes), Cursor::new(Vec::new()), true, |headers| {
+        headers[0].compression = Compression::Uncompressed;
+        Ok(())
+    });
+
+    assert!(matches!(changed_compression, Err(Error::NotSupported(_))));
+}
+
+#[test]
+fn transcode_between_all_compression_methods() {
+    let size = Vec2(71, 83);
+
+    let layer = |name: &str, blocks: Blocks, line_order: LineOrder| Layer::new(
+        size, LayerAttributes::named(name),
+        Encoding { compression: Compression::Uncompressed, blocks, line_order },
+        AnyChannels::sort(smallvec::smallvec![
+            AnyChannel::new("Y", FlatSamples::F16((0 .. size.area()).map(|index| f16::from_f32((index as f32 * 0.01).sin())).collect())),
+            AnyChannel::new("Z", FlatSamples::F32((0 .. size.area()).map(|index| index as f32 / 3.0).collect())),
+            AnyChannel::new("id", FlatSamples::U32((0 .. size.area()).map(|index| index as u32 * 7).collect())),
+        ])
+    );
+
+    let mut compression_methods = vec![
+        Compression::Uncompressed, Compression::RLE, Compression::ZIP1, Compression::ZIP16,
+        Compression::PIZ, Compression::B44, Compression::B44A,
+    ];
+
+    if cfg!(target_endian = "little") { // TODO big endian pxr24
+        compression_methods.push(Compression::PXR24);
+    }
+
+    let read_all = |bytes: &[u8]| read().no_deep_data().largest_resolution_level()
+        .all_channels().all_layers().all_attributes()
+        .from_buffered(Cursor::new(bytes)).unwrap();
+
+    let write = |image: &Image<Layers<AnyChannels<FlatSamples>>>, compression: Compression| {
+        let mut image = image.clone();
+        for layer in &mut image.layer_data { layer.encoding.compression = compression; }
+
+        let mut bytes = Vec::new();
+        image.write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+        bytes
+    };
+
+    let image = Image::from_layers(ImageAttributes::new(IntegerBounds::from_dimensions(size)), vec![
+        layer("lines", Blocks::ScanLines, LineOrder::Increasing),
+        layer("reversed", Blocks::ScanLines, LineOrder::Decreasing),
+        layer("tiles", Blocks::Tiles(Vec2(16, 32)), LineOrder::Increasing),
+    ]);
+
+    for &source_compression in &compression_methods {
+        let source_bytes = write(&image, source_compression);
+        let source_image = read_all(&source_bytes);
+
+        for &target_compression in &compression_methods {
+            for parallel in [false, true] {
+                let mut transcoded_bytes = Vec::new();
+
+                exr::block::writer::transcode(
+                    Cursor::new(&source_bytes), Cursor::new(&mut transcoded_bytes),
+                    true, parallel, |_| target_compression
+                ).unwrap();
+
+                let transcoded = read_all(&transcoded_bytes);
+                let expected = read_all(&write(&source_image, target_compression));
+
+                for (transcoded_layer, expected_layer) in transcoded.layer_data.iter().zip(&expected.layer_data) {
+                    assert_eq!(transcoded_layer.encoding, expected_layer.encoding);
+                    assert_eq!(transcoded_layer.attributes, expected_layer.attributes);
+                    assert!(
+                        transcoded_layer.channel_data == expected_layer.channel_data,
+                        "pixels differ when transcoding {:?} to {:?}", source_compression, target_compression
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn verify_written_chunks_while_writing() {
+    let size = Vec2(97, 61);
+    let pixels = SpecificChannels::rgba(|Vec2(x, y)| (
+        ((x * y) as f32 * 0.01).sin().to_f16(), (x as f32 * 0.3).cos(), y as f32 / 7.0, (x + y) as u32
+    ));
+
+    for compression in [Compression::ZIP16, Compression::ZIP1, Compression::RLE, Compression::PIZ, Compression::B44] {
+        for blocks in [Blocks::ScanLines, Blocks::Tiles(Vec2(32, 16))] {
+            let image = Image::from_encoded_channels(
+                size, Encoding { compression, blocks, line_order: LineOrder::Increasing }, pixels.clone()
+            );
+
+            let mut expected = Vec::new();
+            image.write().non_parallel().to_buffered(Cursor::new(&mut expected)).unwrap();
+
+            for parallel in [false, true] {
+                let writer = image.write().verify_written_chunks(true);
+                let writer = if parallel { writer } else { writer.non_parallel() };
+
+                let mut verified = Vec::new();
+                writer.to_buffered(Cursor::new(&mut verified)).unwrap();
+                assert!(verified == expected, "verifying changed the file for {:?}, {:?}", compression, blocks);
+            }
+        }
+    }
+}
+
+#[test]
+fn compressing_block_with_wrong_byte_size_returns_error() {
+    let image = Image::from_encoded_channels(
+        (16, 16), Encoding::SMALL_LOSSLESS,
+        SpecificChannels::rgb(|Vec2(x, y)| (x as f32, y as f32, 0.0_f32))
+    );
+
+    let headers = image.write().infer_meta_data();
+    let block = exr::block::UncompressedBlock {
+        index: exr::block::BlockIndex { layer: 0, level: Vec2(0, 0), pixel_position: Vec2(0, 0), pixel_size: Vec2(16, 16) },
+        data: vec![0; 17],
+    };
+
+    assert!(matches!(block.compress_to_chunk(&headers), Err(Error::Invalid(_))));
+}
+
+#[test]
+fn copy_all_chunks_of_file_verbatim() {
+    use exr::block::writer::ChunkCopier;
+
+    for path in [
+        "tests/images/valid/openexr/Beachball/multipart.0001.exr",
+        "tests/images/valid/openexr/MultiResolution/Kapaa.exr",
+    ] {
+        let bytes = std::fs::read(path).unwrap();
+        let chunks = exr::block::read(Cursor::new(&bytes), true).unwrap().all_chunks(true).unwrap();
+        let headers = chunks.meta_data().headers.clone();
+        let copier = ChunkCopier::new(&headers, &headers).unwrap();
+
+        let mut copied = Vec::new();
+        exr::block::write(Cursor::new(&mut copied), headers, true, |_, chunk_writer| {
+            for chunk in chunks {
+                copier.copy_chunk(chunk?, chunk_writer)?;
+            }
+
+            Ok(())
+        }).unwrap();
+
+        let all_chunks = |bytes: &[u8]| -> Vec<String> {
+            exr::block::read(Cursor::new(bytes), true).unwrap()
+                .all_chunks(true).unwrap()
+                .map(|chunk| format!("{:?}", chunk.unwrap()))
+                .collect()
+        };
+
+        assert_eq!(all_chunks(&copied), all_chunks(&bytes), "{}", path);
+
+        let read_all = |bytes: &[u8]| read().no_deep_data().all_resolution_levels()
+            .all_channels().all_layers().all_attributes()
+            .from_buffered(Cursor::new(bytes)).unwrap();
+
+        assert!(read_all(&copied) == read_all(&bytes), "{}", path);
+    }
+
+    // layers that store their pixels differently cannot be copied
+    let headers = exr::meta::MetaData::read_from_file("tests/images/valid/openexr/Beachball/multipart.0001.exr", true).unwrap().headers;
+    let mut changed = headers.clone();
+    changed[1].compression = Compression::Uncompressed;
+    assert!(ChunkCopier::new(&headers, &changed).is_err());
+}
+
+#[test]
+fn parallel_compression_limits_uncompressed_blocks_in_memory() {
+    use std::cell::Cell;
+    use exr::block::writer::ChunksWriter;
+    use exr::image::write::layers::{WritableLayers, LayersWriter};
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(3).build().unwrap();
+    let pixels = SpecificChannels::rgb(|Vec2(x, y)| ((x as f32 * 0.2).sin(), (y * x) as f32, (x + y) as f32));
+
+    for (blocks, line_order) in [(Blocks::ScanLines, LineOrder::Increasing), (Blocks::Tiles(Vec2(16, 16)), LineOrder::Unspecified)] {
+        for max_pending_chunks in [None, Some(1), Some(4)] {
+            let encoding = Encoding { compression: Compression::ZIP1, blocks, line_order };
+            let image = Image::from_layer(Layer::new(Vec2(128, 96), LayerAttributes::named("limited"), encoding, pixels.clone()));
+
+            let headers = image.write().infer_meta_data();
+            let layers = image.layer_data.create_writer(&headers);
+
+            let written_blocks = Cell::new(0);
+            let produced_blocks = Cell::new(0);
+            let peak_alive_blocks = Cell::new(0);
+
+            let mut bytes = Vec::new();
+            exr::block::write(Cursor::new(&mut bytes), headers, true, |meta, chunk_writer| {
+                let blocks = meta.collect_ordered_block_data(|block_index| {
+                    produced_blocks.set(produced_blocks.get() + 1);
+
+                    let alive_blocks = produced_blocks.get() - written_blocks.get();
+                    peak_alive_blocks.set(peak_alive_blocks.get().max(alive_blocks));
+
+                    layers.extract_uncompressed_block(&meta.headers, block_index)
+                });
+
+                let mut chunk_writer = chunk_writer.on_block_progress(|progress| written_blocks.set(progress.written_chunk_count));
+                let mut compressor = chunk_writer.parallel_blocks_compressor_with_pool(&meta, &pool)
+                    .expect("compressed image should be compressed in parallel");
+
+                if let Some(max_pending_chunks) = max_pending_chunks {
+                    compressor = compressor.max_pending_chunks(max_pending_chunks);
+                }
+
+                compressor.compress_all_blocks(blocks)
+            }).unwrap();
+
+            // the compressor holds at most the limit, and the iterator has produced one more block to hand over
+            let limit = max_pending_chunks.unwrap_or(pool.current_num_threads() + 2);
+            assert!(
+                peak_alive_blocks.get() <= limit + 1,
+                "{} blocks were alive with a limit of {} for {:?}", peak_alive_blocks.get(), limit, line_order
+            );
+        }
+    }
+}
+
+#[test]
+fn sorted_blocks_writer_spills_reversed_chunks_to_disk() {
+    use exr::block::writer::SortedBlocksWriter;
+    use exr::image::write::layers::{WritableLayers, LayersWriter};
+
+    let directory = std::env::temp_dir().join(format!("exrs_chunk_spill_{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let pixels = SpecificChannels::rgb(|Vec2(x, y)| ((x as f32 * 0.3).cos(), (x + y) as f32, y as f32));
+    let encoding = Encoding { compression: Compression::RLE, blocks: Blocks::ScanLines, line_order: LineOrder::Increasing };
+    let image = Image::from_layer(Layer::new(Vec2(40, 150), LayerAttributes::named("spilled"), encoding, pixels));
+
+    let headers = image.write().infer_meta_data();
+    let layers = image.layer_data.create_writer(&headers);
+
+    let mut bytes = Vec::new();
+    exr::block::write(Cursor::new(&mut bytes), headers, true, |meta, chunk_writer| {
+        let blocks: Vec<_> = meta.collect_ordered_block_data(|block_index| {
+            layers.extract_uncompressed_block(&meta.headers, block_index)
+        }).enumerate().collect();
+
+        assert!(blocks.len() > 3);
+
+        let mut sorted_writer = SortedBlocksWriter::with_spill_file(&meta, chunk_writer, &directory, 100);
+        let mut max_spilled_chunks = 0;
+
+        // the last chunk arrives first, so no chunk can be written until all chunks have been stashed
+        for (index_in_file, (index_in_header_increasing_y, block)) in blocks.into_iter().rev() {
+            let chunk = block.compress_to_chunk(&meta.headers)?;
+            sorted_writer.write_or_stash_chunk(index_in_file, index_in_header_increasing_y, chunk)?;
+            max_spilled_chunks = max_spilled_chunks.max(sorted_writer.spilled_chunk_count());
+        }
+
+        assert_eq!(sorted_writer.pending_chunk_count(), 0);
+        assert!(max_spilled_chunks > 1, "chunks should have been spilled");
+        Ok(())
+    }).unwrap();
+
+    assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 0, "spill file must be deleted");
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    let read = read().no_deep_data().largest_resolution_level().all_channels().first_valid_layer().all_attributes()
+        .pedantic().from_buffered(Cursor::new(bytes)).unwrap();
+
+    let read_pixels = &read.layer_data.channel_data.list[1].sample_data;
+    assert_eq!(read_pixels.value_by_flat_index(40 * 100 + 7), Sample::F32(107.0));
+}
+
+#[test]
+fn report_chunk_sizes_and_completion_while_writing() {
+    use exr::block::writer::{ChunksWriter, WriteProgress};
+    use exr::image::write::layers::{WritableLayers, LayersWriter};
+
+    let layer = |name: &str, size: Vec2<usize>, tiles: Blocks| Layer::new(
+        size, LayerAttributes::named(name),
+        Encoding { compression: Compression::RLE, blocks: tiles, line_order: LineOrder::Increasing },
+        SpecificChannels::rgb(|Vec2(x, y)| (x as f32, (x * y) as f32, y as f32))
+    );
+
+    let image = Image::empty(ImageAttributes::new(IntegerBounds::from_dimensions((40, 30))))
+        .with_layer(layer("small", Vec2(40, 30), Blocks::ScanLines))
+        .with_layer(layer("large", Vec2(20, 70), Blocks::Tiles(Vec2(16, 16))));
+
+    let headers = image.write().infer_meta_data();
+    let layers = image.layer_data.create_writer(&headers);
+
+    let mut events = Vec::new();
+    let mut written_chunks = Vec::new();
+    let mut bytes = Cursor::new(Vec::new());
+
+    let statistics = exr::block::writer::write_chunks_with_progress(
+        &mut bytes, headers, true,
+        |event| events.push(event),
+
+        |meta, chunk_writer| {
+            let blocks = meta.collect_ordered_block_data(|block_index|
+                layers.extract_uncompressed_block(&meta.headers, block_index)
+            );
+
+            for (index_in_header_increasing_y, block) in blocks {
+                let chunk = block.compress_to_chunk(&meta.headers)?;
+                written_chunks.push((chunk.layer_index, index_in_header_increasing_y, chunk.compressed_byte_size()));
+                chunk_writer.write_chunk(index_in_header_increasing_y, chunk)?;
+                assert_eq!(chunk_writer.byte_position(), chunk_writer.inner_chunks_writer().byte_position());
+            }
+
+            Ok(())
+        }
+    ).unwrap();
+
+    let file_size = bytes.into_inner().len() as u64;
+    assert_eq!(statistics.written_byte_count, file_size);
+    assert!(written_chunks.iter().any(|&(layer, _, _)| layer == 1));
+    assert_eq!(events.len(), written_chunks.len() + 1);
+
+    let (last, chunk_events) = events.split_last().unwrap();
+    assert_eq!(last, &WriteProgress::MetaDataCompleted(statistics));
+
+    let mut previous_byte_count = 0;
+    for (index, (event, &(layer_index, index_in_header_increasing_y, byte_size))) in chunk_events.iter().zip(&written_chunks).enumerate() {
+        let progress = match event {
+            WriteProgress::ChunkWritten(progress) => progress,
+            other => panic!("unexpected event {:?}", other),
+        };
+
+        assert_eq!(progress.layer_index, layer_index);
+        assert_eq!(progress.index_in_header_increasing_y, index_in_header_increasing_y);
+        assert_eq!(progress.chunk_byte_size, byte_size);
+        assert_eq!(progress.written_chunk_count, index + 1);
+
+        let written_byte_count = progress.written_byte_count.unwrap();
+        assert!(written_byte_count > previous_byte_count + byte_size as u64);
+        previous_byte_count = written_byte_count;
+    }
+
+    assert_eq!(previous_byte_count, file_size);
+}
+
+#[test]
+fn missing_block_is_reported_before_compressing_all_blocks() {
+    use exr::block::writer::ChunksWriter;
+    use exr::image::write::layers::{WritableLayers, LayersWriter};
+
+    let image = Image::from_layer(Layer::new(
+        Vec2(64, 48), LayerAttributes::named("tiles"),
+        Encoding { compression: Compression::ZIP16, blocks: Blocks::Tiles(Vec2(16, 16)), line_order: LineOrder::Increasing },
+        SpecificChannels::rgb(|Vec2(x, y)| (x as f32, y as f32, 0.5_f32))
+    ));
+
+    let headers = image.write().infer_meta_data();
+    let layers = image.layer_data.create_writer(&headers);
+
+    let write_blocks = |skip_index: Option<usize>, duplicate_index: Option<usize>| {
+        exr::block::write(Cursor::new(Vec::new()), headers.clone(), true, |meta, chunk_writer| {
+            let mut blocks: Vec<_> = meta.collect_ordered_block_data(|block_index|
+                layers.extract_uncompressed_block(&meta.headers, block_index)
+            ).collect();
+
+            if let Some(index) = duplicate_index { blocks.insert(index, blocks[index].clone()); }
+            if let Some(index) = skip_index { blocks.remove(index); }
+
+            let mut compressed_count = 0;
+            let mut validated = meta.validate_ordered_blocks(blocks.into_iter().inspect(|_| compressed_count += 1));
+            let mut compressor = chunk_writer.sequential_blocks_compressor(&meta);
+            for (index_in_header_increasing_y, block) in &mut validated {
+                compressor.compress_block(index_in_header_increasing_y, block)?;
+            }
+
+            validated.finish()?;
+
+            assert_eq!(compressed_count, meta.headers[0].chunk_count);
+            Ok(())
+        })
+    };
+
+    write_blocks(None, None).unwrap();
+
+    match write_blocks(Some(5), None) {
+        Err(Error::Invalid(message)) => assert_eq!(message, "missing block of layer 0 at tile (1, 1) of level (0, 0)"),
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    match write_blocks(None, Some(2)) {
+        Err(Error::Invalid(message)) => assert_eq!(message, "duplicate block of layer 0 at tile (2, 0) of level (0, 0)"),
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    // the last block is missing, which is only noticed after all other blocks
+    match write_blocks(Some(11), None) {
+        Err(Error::Invalid(message)) => assert_eq!(message, "missing block of layer 0 at tile (3, 2) of level (0, 0)"),
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    // the count can be checked before producing any block
+    exr::block::write(Cursor::new(Vec::new()), headers.clone(), true, |meta, _| {
+        let block_indices: Vec<_> = meta.enumerate_ordered_header_block_indices().collect();
+        assert!(meta.validate_block_count(&block_indices.iter()).is_ok());
+        assert!(meta.validate_block_count(&block_indices[1..].iter()).is_err());
+        Err(Error::Aborted)
+    }).unwrap_err();
+}
+
+#[test]
+fn read_deep_scan_lines() {
+    use exr::image::DeepSamples;
+    use exr::block::deep::DeepUncompressedBlock;
+
+    let deep_files = all_exr_files_in_repo()
+        .filter(|path| path.to_string_lossy().contains("v2"));
+
+    let mut deep_layer_count = 0;
+
+    for path in deep_files {
+        let meta = MetaData::read_from_file(&path, false).unwrap();
+        let deep_headers: Vec<&Header> = meta.headers.iter().filter(|header| header.deep).collect();
+
+        let image = read().deep_data().from_file(&path).unwrap();
+        assert_eq!(image.layer_data.len(), deep_headers.len(), "{:?}", path);
+
+        for (layer, header) in image.layer_data.iter().zip(&deep_headers) {
+            assert_eq!(layer.size, header.layer_size);
+            assert_eq!(layer.channel_data.list.len(), header.channels.list.len());
+
+            // all channels of a pixel contain the same number of samples
+            let first_channel = &layer.channel_data.list[0].sample_data;
+            assert_eq!(first_channel.len(), layer.size.area());
+
+            for channel in &layer.channel_data.list[1..] {
+                assert!((0 .. first_channel.len()).all(|index|
+                    channel.sample_data.sample_count_by_flat_index(index) == first_channel.sample_count_by_flat_index(index)
+                ));
+            }
+
+            // the depth samples of each pixel are sorted from front to back
+            let depth = layer.channel_data.list.iter().find(|channel| channel.name.eq("Z")).unwrap();
+            match &depth.sample_data {
+                DeepSamples::F32(pixels) => assert!(pixels.iter().all(|samples| samples.windows(2).all(|pair| pair[0] <= pair[1]))),
+                other => panic!("unexpected depth samples {:?}", other),
+            }
+
+            deep_layer_count += 1;
+        }
+    }
+
+    assert_eq!(deep_layer_count, 16);
+
+    // the block interface produces the same number of samples as the image interface
+    let path = "tests/images/valid/openexr/v2/LowResLeftView/Balls.exr";
+    let image = read().deep_data().from_file(path).unwrap();
+    let image_sample_count = image.layer_data[0].channel_data.list[0].sample_data.total_sample_count();
+    assert_eq!(image_sample_count, 162877);
+
+    let meta = MetaData::read_from_file(path, false).unwrap();
+    let block_sample_count: usize = exr::block::read(std::fs::File::open(path).unwrap(), false).unwrap()
+        .all_chunks(false).unwrap()
+        .map(|chunk| {
+            let block = DeepUncompressedBlock::decompress_chunk(chunk.unwrap(), &meta, true).unwrap();
+            assert!(block.channels.iter().all(|channel| channel.len() == block.total_sample_count()));
+            block.total_sample_count()
+        })
+        .sum();
+
+    assert_eq!(block_sample_count, image_sample_count);
+
+    // the stereo file contains the same left view
+    let stereo = read().deep_data().from_file("tests/images/valid/openexr/v2/Stereo/Balls.exr").unwrap();
+    assert_eq!(stereo.layer_data.len(), 2);
+    let left = read().deep_data().from_file("tests/images/valid/openexr/v2/LeftView/Balls.exr").unwrap();
+    assert_eq!(stereo.layer_data[0].channel_data, left.layer_data[0].channel_data);
+}
+
+#[test]
+fn read_deep_data_as_flat_data_is_unsupported() {
+    let path = "tests/images/valid/openexr/v2/LowResLeftView/Balls.exr";
+
+    let flat = read().no_deep_data().largest_resolution_level().all_channels()
+        .all_layers().all_attributes().from_file(path);
+
+    assert!(matches!(flat, Err(Error::NotSupported(_))), "{:?}", flat.err());
+
+    // flat layers are skipped when reading deep data
+    let composited = read().deep_data()
+        .from_file("tests/images/valid/openexr/v2/LowResLeftView/composited.exr").unwrap();
+
+    assert!(composited.layer_data.is_empty());
+}
+
+#[test]
+fn read_deep_tiles() {
+    use exr::block::deep::{DeepUncompressedBlock, DeepChannelSamples};
+    use exr::block::chunk::TileCoordinates;
+    use exr::image::DeepSamples;
+    use std::collections::HashMap;
+
+    // contains a mip mapped zip compressed layer with 37x23 pixels in 16x16 tiles,
+    // and an uncompressed layer with 20x12 pixels in 8x8 tiles
+    let path = "tests/images/valid/custom/deep_tiles.exr";
+
+    fn sample_count(layer: usize, level: Vec2<usize>, x: usize, y: usize) -> usize {
+        if layer == 0 { (x * 7 + y * 3 + level.x() * 5 + level.y()) % 4 } else { (x + y) % 2 }
+    }
+
+    fn id(x: usize, y: usize, sample: usize) -> u32 {
+        ((x + y * 100) * 10 + sample) as u32
+    }
+
+    let reader = exr::block::read(std::fs::File::open(path).unwrap(), true).unwrap();
+    let meta = reader.meta_data().clone();
+    let mut sample_counts_by_level = HashMap::new();
+    let mut has_partial_tiles = false;
+
+    for chunk in reader.all_chunks(true).unwrap() {
+        let block = DeepUncompressedBlock::decompress_chunk(chunk.unwrap(), &meta, true).unwrap();
+        let index = block.index;
+
+        let tile_size = meta.headers[index.layer].max_block_pixel_size();
+        has_partial_tiles |= index.pixel_size != tile_size;
+
+        *sample_counts_by_level.entry((index.layer, index.level)).or_insert(0) += block.total_sample_count();
+
+        let pixels = (0 .. index.pixel_size.area())
+            .map(|pixel| index.pixel_position + Vec2(pixel % index.pixel_size.width(), pixel / index.pixel_size.width()));
+
+        let expected_ids: Vec<u32> = pixels.clone()
+            .flat_map(|Vec2(x, y)| (0 .. sample_count(index.layer, index.level, x, y)).map(move |sample| id(x, y, sample)))
+            .collect();
+
+        let expected_counts: Vec<u32> = pixels
+            .map(|Vec2(x, y)| sample_count(index.layer, index.level, x, y) as u32)
+            .collect();
+
+        assert_eq!(block.sample_counts, expected_counts);
+        assert_eq!(block.channels.last(), Some(&DeepChannelSamples::U32(expected_ids)));
+    }
+
+    assert!(has_partial_tiles);
+
+    let expected_sample_counts = [
+        ((0, Vec2(0, 0)), 1277), ((0, Vec2(1, 1)), 295), ((0, Vec2(2, 2)), 66),
+        ((0, Vec2(3, 3)), 12), ((0, Vec2(4, 4)), 3), ((0, Vec2(5, 5)), 2),
+        ((1, Vec2(0, 0)), 120),
+    ];
+
+    assert_eq!(sample_counts_by_level, expected_sample_counts.iter().copied().collect());
+
+    // read a single partial tile of a smaller resolution level
+    let mut reader = exr::block::read(std::fs::File::open(path).unwrap(), true).unwrap();
+    let tile = TileCoordinates { tile_index: Vec2(1, 0), level_index: Vec2(1, 1) };
+    let block = reader.read_deep_block(0, tile).unwrap();
+    assert_eq!(block.index.pixel_position, Vec2(16, 0));
+    assert_eq!(block.index.pixel_size, Vec2(2, 11));
+    assert!(reader.read_block(0, tile).is_err(), "deep tiles are not flat blocks");
+
+    // filter the deep chunks by their resolution level
+    let level_chunks = exr::block::read(std::fs::File::open(path).unwrap(), true).unwrap()
+        .filter_chunks(true, |_, tile, _| tile.level_index == Vec2(2, 2)).unwrap();
+
+    assert_eq!(level_chunks.expected_chunk_count(), 1);
+
+    // the image contains the largest level of both layers
+    let image = read().deep_data().pedantic().from_file(path).unwrap();
+    assert_eq!(image.layer_data.len(), 2);
+
+    let volume = &image.layer_data[0];
+    assert_eq!(volume.size, Vec2(37, 23));
+    assert!(volume.channel_data.list.iter().all(|channel| channel.sample_data.total_sample_count() == 1277));
+
+    let mask = &image.layer_data[1];
+    assert_eq!(mask.channel_data.list[0].sample_data.total_sample_count(), 120);
+
+    let pixel_index = 22 * volume.size.width() + 36;
+    assert_eq!(volume.channel_data.list[0].sample_data.sample_count_by_flat_index(pixel_index), sample_count(0, Vec2(0, 0), 36, 22));
+
+    match &volume.channel_data.list[1].sample_data {
+        DeepSamples::F32(depths) => assert_eq!(depths[pixel_index].as_slice(), &[22.36, 32.36, 42.36][.. depths[pixel_index].len()]),
+        other => panic!("unexpected depth samples {:?}", other),
+    }
+}
+
+#[test]
+fn roundtrip_deep_image() {
+    use exr::image::DeepSamples;
+    use smallvec::SmallVec;
+
+    fn layer(name: &str, size: Vec2<usize>, encoding: Encoding) -> Layer<AnyChannels<DeepSamples>> {
+        // some pixels are empty, and no pixel contains more than three samples
+        let sample_count = |index: usize| (index * 7 + index / size.width()) % 4;
+
+        let f16_samples = (0 .. size.area())
+            .map(|index| (0 .. sample_count(index)).map(|sample| f16::from_f32(1.0 / (sample as f32 + 1.0))).collect())
+            .collect();
+
+        let f32_samples = (0 .. size.area())
+            .map(|index| (0 .. sample_count(index)).map(|sample| index as f32 + sample as f32 * 0.25).collect())
+            .collect();
+
+        let u32_samples = (0 .. size.area())
+            .map(|index| (0 .. sample_count(index)).map(|sample| (index * 10 + sample) as u32).collect())
+            .collect();
+
+        Layer {
+            size,
+            attributes: LayerAttributes::named(name),
+            encoding,
+            channel_data: AnyChannels::sort(smallvec::smallvec![
+                AnyChannel::new("A", DeepSamples::F16(f16_samples)),
+                AnyChannel::new("Z", DeepSamples::F32(f32_samples)),
+                AnyChannel::new("id", DeepSamples::U32(u32_samples)),
+            ]),
+        }
+    }
+
+    let size = Vec2(37, 23);
+    let layers = smallvec::smallvec![
+        layer("scan lines", size, Encoding { compression: Compression::RLE, blocks: Blocks::ScanLines, line_order: LineOrder::Increasing }),
+        layer("tiles", size, Encoding { compression: Compression::ZIP1, blocks: Blocks::Tiles(Vec2(16, 16)), line_order: LineOrder::Decreasing }),
+        layer("blocks", size, Encoding { compression: Compression::ZIP16, blocks: Blocks::ScanLines, line_order: LineOrder::Increasing }),
+        layer("raw", size, Encoding { compression: Compression::Uncompressed, blocks: Blocks::Tiles(Vec2(8, 8)), line_order: LineOrder::Increasing }),
+    ];
+
+    let image = Image { attributes: ImageAttributes::new(IntegerBounds::from_dimensions(size)), layer_data: layers };
+
+    let mut file = Cursor::new(Vec::new());
+    image.write().to_buffered(&mut file).unwrap();
+
+    let meta = MetaData::read_from_buffered(Cursor::new(file.get_ref()), true).unwrap();
+    assert!(meta.requirements.has_deep_data);
+    assert!(meta.headers.iter().all(|header| header.deep && header.max_samples_per_pixel == Some(3)));
+
+    let read_image = read().deep_data().pedantic().from_buffered(Cursor::new(file.into_inner())).unwrap();
+    assert_eq!(read_image, image);
+
+    // a single deep tiled layer does not set the flag for a single tiled layer
+    let single = Image { attributes: image.attributes.clone(), layer_data: smallvec::smallvec![ image.layer_data[1].clone() ] };
+    let mut file = Cursor::new(Vec::new());
+    single.write().to_buffered(&mut file).unwrap();
+
+    let meta = MetaData::read_from_buffered(Cursor::new(file.get_ref()), true).unwrap();
+    assert!(meta.requirements.has_deep_data && !meta.requirements.is_single_layer_and_tiled);
+    assert_eq!(read().deep_data().pedantic().from_buffered(Cursor::new(file.into_inner())).unwrap(), single);
+
+    // the channels of a pixel must contain the same number of samples
+    let mut invalid = single.clone();
+    if let DeepSamples::U32(pixels) = &mut invalid.layer_data[0].channel_data.list[2].sample_data {
+        pixels[5] = SmallVec::new();
+    }
+
+    assert!(invalid.write().to_buffered(Cursor::new(Vec::new())).is_err());
+
+    // deep data cannot be compressed using lossy methods
+    let mut invalid = single;
+    invalid.layer_data[0].encoding.compression = Compression::PIZ;
+    assert!(invalid.write().to_buffered(Cursor::new(Vec::new())).is_err());
+}
+
+#[test]
+fn rewrite_deep_files() {
+    let deep_files = all_exr_files_in_repo()
+        .filter(|path| path.to_string_lossy().contains("v2") || path.ends_with("deep_tiles.exr"));
+
+    for path in deep_files {
+        let image = read().deep_data().from_file(&path).unwrap();
+        if image.layer_data.is_empty() { continue; } // some of the files only contain flat layers
+
+        let mut file = Cursor::new(Vec::new());
+        image.write().to_buffered(&mut file).unwrap();
+
+        let rewritten = read().deep_data().pedantic().from_buffered(Cursor::new(file.into_inner())).unwrap();
+        assert_eq!(rewritten.layer_data.len(), image.layer_data.len(), "{:?}", path);
+
+        for (rewritten, layer) in rewritten.layer_data.iter().zip(&image.layer_data) {
+            assert_eq!(rewritten.channel_data, layer.channel_data, "{:?}", path);
+        }
+    }
+}
+
+#[test]
+fn rewrite_deep_chunks_of_all_levels() {
+    use exr::block::deep::DeepUncompressedBlock;
+    use exr::block::writer::ChunksWriter;
+
+    let path = "tests/images/valid/custom/deep_tiles.exr";
+    let reader = exr::block::read(std::fs::File::open(path).unwrap(), true).unwrap();
+    let meta = reader.meta_data().clone();
+
+    let blocks: Vec<DeepUncompressedBlock> = reader.all_chunks(true).unwrap()
+        .map(|chunk| DeepUncompressedBlock::decompress_chunk(chunk.unwrap(), &meta, true).unwrap())
+        .collect();
+
+    let mut file = Cursor::new(Vec::new());
+    exr::block::write(&mut file, meta.headers.clone(), true, |meta, chunk_writer| {
+        for (index_in_header_increasing_y, block_index) in meta.enumerate_ordered_header_block_indices() {
+            let block = blocks.iter().find(|block| block.index == block_index).unwrap().clone();
+            chunk_writer.write_chunk(index_in_header_increasing_y, block.compress_to_chunk(&meta.headers)?)?;
+        }
+
+        Ok(())
+    }).unwrap();
+
+    let reader = exr::block::read(Cursor::new(file.into_inner()), true).unwrap();
+    let rewritten_meta = reader.meta_data().clone();
+    assert_eq!(rewritten_meta.headers, meta.headers);
+
+    let rewritten_blocks: Vec<DeepUncompressedBlock> = reader.all_chunks(true).unwrap()
+        .map(|chunk| DeepUncompressedBlock::decompress_chunk(chunk.unwrap(), &rewritten_meta, true).unwrap())
+        .collect();
+
+    assert_eq!(rewritten_blocks.len(), blocks.len());
+    assert!(blocks.iter().all(|block| rewritten_blocks.contains(block)));
+
+    // a block that contains too many samples per pixel is rejected
+    let mut invalid = blocks[0].clone();
+    invalid.sample_counts[0] += 4;
+    assert!(invalid.compress_to_chunk(&meta.headers).is_err());
+}
+
+#[test]
+fn read_deep_sample_counts_without_samples() {
+    use exr::block::deep::DeepUncompressedBlock;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Counts all bytes that are read from the byte source.
+    struct CountingRead { inner: Cursor<Vec<u8>>, read_bytes: Rc<Cell<usize>> }
+
+    impl Read for CountingRead {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            let count = self.inner.read(buffer)?;
+            self.read_bytes.set(self.read_bytes.get() + count);
+            Ok(count)
+        }
+    }
+
+    impl Seek for CountingRead {
+        fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> { self.inner.seek(position) }
+    }
+
+    // the balls do not specify the maximum number of samples per pixel, so they cannot be read pedantically
+    for path in &["tests/images/valid/openexr/v2/LowResLeftView/Balls.exr", "tests/images/valid/custom/deep_tiles.exr"] {
+        let bytes = std::fs::read(path).unwrap();
+
+        let read_bytes = Rc::new(Cell::new(0));
+        let counting = CountingRead { inner: Cursor::new(bytes.clone()), read_bytes: read_bytes.clone() };
+        let mut reader = exr::block::read(counting, false).unwrap();
+
+        let meta_data_bytes = read_bytes.get();
+        let sample_counts = reader.deep_sample_counts(|_, _, _| true).unwrap();
+        let sample_count_bytes = read_bytes.get() - meta_data_bytes;
+
+        let read_bytes = Rc::new(Cell::new(0));
+        let counting = CountingRead { inner: Cursor::new(bytes), read_bytes: read_bytes.clone() };
+        let reader = exr::block::read(counting, false).unwrap();
+        let meta = reader.meta_data().clone();
+
+        let meta_data_bytes = read_bytes.get();
+        let blocks: Vec<DeepUncompressedBlock> = reader.all_chunks(false).unwrap()
+            .map(|chunk| DeepUncompressedBlock::decompress_chunk(chunk.unwrap(), &meta, false).unwrap())
+            .collect();
+
+        let block_bytes = read_bytes.get() - meta_data_bytes;
+
+        assert_eq!(sample_counts.len(), blocks.len(), "{}", path);
+        for block in &blocks {
+            assert_eq!(sample_counts.get(&block.index), Some(&block.sample_counts), "{}", path);
+        }
+
+        assert!(sample_count_bytes * 4 < block_bytes, "{}: {} sample count bytes, {} block bytes", path, sample_count_bytes, block_bytes);
+    }
+
+    // a single resolution level can be selected
+    let mut reader = exr::block::read(std::fs::File::open("tests/images/valid/custom/deep_tiles.exr").unwrap(), true).unwrap();
+    let level_sample_counts = reader.deep_sample_counts(|_, tile, _| tile.level_index == Vec2(1, 1)).unwrap();
+    let level_sample_count: u32 = level_sample_counts.values().flatten().sum();
+    assert_eq!(level_sample_count, 295);
+
+    // the chunks can still be read afterwards
+    assert_eq!(reader.all_chunks(true).unwrap().count(), 18);
+}
+
+#[test]
+fn flatten_deep_image() {
+    use exr::image::DeepSamples;
+
+    // each pixel contains the samples as (red, alpha, depth, id), not sorted by depth
+    let pixels: Vec<Vec<(f32, f32, f32, u32)>> = vec![
+        vec![],
+        vec![(0.5, 0.5, 2.0, 1)],
+        vec![(0.2, 0.25, 8.0, 2), (0.3, 0.5, 1.0, 3)],
+        vec![(0.9, 1.0, 5.0, 4), (0.6, 0.75, 3.0, 5), (f32::INFINITY, 0.5, 9.0, 6)],
+        vec![(0.1, f32::NAN, 1.0, 7), (0.4, 0.5, 4.0, 8)],
+        vec![(0.0, 0.0, 6.0, 9), (0.8, 2.0, 7.0, 10)],
+    ];
+
+    // the composited (red, alpha, depth, id) of each pixel, computed by hand
+    let expected: Vec<(f32, f32, f32, u32)> = vec![
+        (0.0, 0.0, 0.0, 0),
+        (0.5, 0.5, 2.0, 1),
+        (0.3 + 0.5 * 0.2, 0.5 + 0.5 * 0.25, 1.0, 3),
+        (0.6 + 0.25 * 0.9, 1.0, 3.0, 5),
+        (0.4, 0.5, 4.0, 8),
+        (0.8, 1.0, 6.0, 9),
+    ];
+
+    let channel = |get: fn(&(f32, f32, f32, u32)) -> f32| DeepSamples::F32(
+        pixels.iter().map(|samples| samples.iter().map(get).collect()).collect()
+    );
+
+    let size = Vec2(3, 2);
+    let layer = Layer {
+        size,
+        attributes: LayerAttributes::named("deep"),
+        encoding: Encoding { compression: Compression::ZIP1, blocks: Blocks::Tiles(Vec2(2, 2)), line_order: LineOrder::Increasing },
+        channel_data: AnyChannels::sort(smallvec::smallvec![
+            AnyChannel::new("R", channel(|sample| sample.0)),
+            AnyChannel::new("A", channel(|sample| sample.1)),
+            AnyChannel::new("Z", channel(|sample| sample.2)),
+            AnyChannel::new("id", DeepSamples::U32(pixels.iter().map(|samples| samples.iter().map(|sample| sample.3).collect()).collect())),
+        ]),
+    };
+
+    let image = Image { attributes: ImageAttributes::new(IntegerBounds::from_dimensions(size)), layer_data: smallvec::smallvec![ layer ] };
+    let flat = image.flatten();
+
+    let flat_channels = &flat.layer_data[0].channel_data.list;
+    let flat_channel = |name: &str| flat_channels.iter().find(|channel| channel.name.eq(name)).unwrap();
+
+    for (index, &(red, alpha, depth, id)) in expected.iter().enumerate() {
+        assert_eq!(flat_channel("R").sample_data.value_by_flat_index(index).to_f32(), red, "red of pixel {}", index);
+        assert_eq!(flat_channel("A").sample_data.value_by_flat_index(index).to_f32(), alpha, "alpha of pixel {}", index);
+        assert_eq!(flat_channel("Z").sample_data.value_by_flat_index(index).to_f32(), depth, "depth of pixel {}", index);
+        assert_eq!(flat_channel("id").sample_data.value_by_flat_index(index).to_u32(), id, "id of pixel {}", index);
+    }
+
+    assert_eq!(flat.layer_data[0].encoding, image.layer_data[0].encoding);
+    assert_eq!(flat.layer_data[0].attributes, image.layer_data[0].attributes);
+
+    // flattening while reading composites each block into the same flat image
+    let mut file = Cursor::new(Vec::new());
+    image.write().to_buffered(&mut file).unwrap();
+
+    let read_flat = read().flatten_deep_data().pedantic().from_buffered(Cursor::new(file.into_inner())).unwrap();
+    assert_eq!(read_flat, flat);
+
+    // the flat image can be written like any other flat image
+    let mut flat_file = Cursor::new(Vec::new());
+    read_flat.write().to_buffered(&mut flat_file).unwrap();
+}
+
+#[test]
+fn flatten_deep_files_while_reading() {
+    for path in &["tests/images/valid/openexr/v2/LowResLeftView/Balls.exr", "tests/images/valid/custom/deep_tiles.exr"] {
+        let flattened = read().flatten_deep_data().from_file(path).unwrap();
+        let deep = read().deep_data().from_file(path).unwrap();
+        assert_eq!(flattened, deep.flatten(), "{:?}", path);
+
+        for (flat_layer, deep_layer) in flattened.layer_data.iter().zip(&deep.layer_data) {
+            let alpha = match flat_layer.channel_data.list.iter().find(|channel| channel.name.eq("A")) {
+                Some(alpha) => alpha,
+                None => continue,
+            };
+
+            let deep_alpha = deep_layer.channel_data.list.iter().find(|channel| channel.name.eq("A")).unwrap();
+
+            for (index, flat_alpha) in alpha.sample_data.values_as_f32().enumerate() {
+                assert!((0.0 ..= 1.0).contains(&flat_alpha), "{:?}", path);
+
+                // empty pixels are transparent
+                if deep_alpha.sample_data.sample_count_by_flat_index(index) == 0 {
+                    assert_eq!(flat_alpha, 0.0, "{:?}", path);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn read_deep_data_into_each_storage() {
+    use exr::image::{DeepSamples, ContiguousDeepSamples, DeepPixels};
+
+    let path = "tests/images/valid/openexr/v2/LowResLeftView/Balls.exr";
+    let image = read().deep_data().from_file(path).unwrap();
+    let layer = &image.layer_data[0];
+
+    let per_pixel: Image<Layers<AnyChannels<DeepSamples>>> = read().deep_data()
+        .largest_resolution_level().all_channels().all_layers().all_attributes()
+        .from_file(path).unwrap();
+
+    assert_eq!(per_pixel, image);
+
+    let contiguous: Image<Layer<AnyChannels<ContiguousDeepSamples>>> = read().deep_data().contiguous_samples()
+        .largest_resolution_level().all_channels().first_valid_layer().all_attributes()
+        .from_file(path).unwrap();
+
+    let interleaved: Image<Layers<DeepPixels>> = read().deep_data().interleaved_samples()
+        .largest_resolution_level().all_channels().all_layers().all_attributes()
+        .non_parallel().from_file(path).unwrap();
+
+    let interleaved = &interleaved.layer_data[0].channel_data;
+    assert_eq!(interleaved.channels.list.len(), layer.channel_data.list.len());
+    assert_eq!(interleaved.pixels.len(), layer.size.area());
+
+    for (channel_index, (contiguous_channel, channel)) in contiguous.layer_data.channel_data.list.iter().zip(&layer.channel_data.list).enumerate() {
+        assert_eq!(contiguous_channel.name, channel.name);
+        assert_eq!(contiguous_channel.sample_data.len(), channel.sample_data.len());
+        assert_eq!(contiguous_channel.sample_data.total_sample_count(), channel.sample_data.total_sample_count());
+        assert_eq!(interleaved.total_sample_count(), channel.sample_data.total_sample_count());
+
+        for pixel_index in 0 .. channel.sample_data.len() {
+            let samples: Vec<Sample> = channel.sample_data.values_by_flat_index(pixel_index).collect();
+            let contiguous_samples: Vec<Sample> = contiguous_channel.sample_data.values_by_flat_index(pixel_index).collect();
+            let interleaved_samples: Vec<Sample> = interleaved.pixels[pixel_index].iter().map(|sample| sample[channel_index]).collect();
+
+            assert_eq!(contiguous_samples, samples);
+            assert_eq!(interleaved_samples, samples);
+        }
+    }
+}
+
+#[test]
+fn limit_deep_samples_per_pixel() {
+    let path = "tests/images/valid/openexr/v2/LowResLeftView/Balls.exr";
+
+    let image = read().deep_data().from_file(path).unwrap();
+    let samples = &image.layer_data[0].channel_data.list[0].sample_data;
+    let max_samples = (0 .. samples.len()).map(|index| samples.sample_count_by_flat_index(index)).max().unwrap();
+    assert!(max_samples > 1);
+
+    assert_eq!(read().deep_data().max_samples_per_pixel(max_samples).from_file(path).unwrap(), image);
+
+    let limited = read().deep_data().max_samples_per_pixel(max_samples - 1).from_file(path);
+    assert!(matches!(limited, Err(Error::Invalid(_))));
+
+    let limited = read().deep_data().interleaved_samples().max_samples_per_pixel(max_samples - 1)
+        .largest_resolution_level().all_channels().all_layers().all_attributes().from_file(path);
+
+    assert!(matches!(limited, Err(Error::Invalid(_))));
+
+    let limited = read().deep_data().max_samples_per_pixel(max_samples - 1).contiguous_samples()
+        .largest_resolution_level().all_channels().all_layers().all_attributes().from_file(path);
+
+    assert!(matches!(limited, Err(Error::Invalid(_))));
+}
+
+#[test]
+fn read_deep_samples_in_depth_range() {
+    use exr::image::{DeepSamples, DeepPixels};
+
+    // each pixel contains the samples as (depth, depth back)
+    let pixels: Vec<Vec<(f32, f32)>> = vec![
+        vec![],
+        vec![(1.0, 1.5), (2.0, 2.5), (5.0, 5.5)],
+        vec![(0.5, 1.5), (3.0, 4.0)],
+        vec![(2.0, 3.0), (4.0, 6.0), (f32::NAN, 3.0)],
+        vec![(6.0, 7.0)],
+        vec![(2.0, 4.0), (4.0, 4.0)],
+    ];
+
+    // the samples inside the range from 1 to 4, including the bounds
+    let expected: Vec<Vec<(f32, f32)>> = vec![
+        vec![],
+        vec![(1.0, 1.5), (2.0, 2.5)],
+        vec![(3.0, 4.0)],
+        vec![(2.0, 3.0)],
+        vec![],
+        vec![(2.0, 4.0), (4.0, 4.0)],
+    ];
+
+    let channel = |pixels: &[Vec<(f32, f32)>], get: fn(&(f32, f32)) -> f32| DeepSamples::F32(
+        pixels.iter().map(|samples| samples.iter().map(get).collect()).collect()
+    );
+
+    let size = Vec2(3, 2);
+    let create_layer = |pixels: &[Vec<(f32, f32)>]| Layer {
+        size,
+        attributes: LayerAttributes::named("deep"),
+        encoding: Encoding { compression: Compression::ZIP1, blocks: Blocks::ScanLines, line_order: LineOrder::Increasing },
+        channel_data: AnyChannels::sort(smallvec::smallvec![
+            AnyChannel::new("Z", channel(pixels, |sample| sample.0)),
+            AnyChannel::new("ZBack", channel(pixels, |sample| sample.1)),
+        ]),
+    };
+
+    let image = Image { attributes: ImageAttributes::new(IntegerBounds::from_dimensions(size)), layer_data: smallvec::smallvec![ create_layer(&pixels) ] };
+    let expected_layer = create_layer(&expected);
+
+    let mut file = Cursor::new(Vec::new());
+    image.write().to_buffered(&mut file).unwrap();
+    let file = file.into_inner();
+
+    let filtered = read().deep_data().deep_samples_in_range(1.0, 4.0).pedantic()
+        .from_buffered(Cursor::new(file.as_slice())).unwrap();
+
+    assert_eq!(filtered.layer_data[0].channel_data, expected_layer.channel_data);
+
+    let interleaved: Image<Layer<DeepPixels>> = read().deep_data().interleaved_samples().deep_samples_in_range(1.0, 4.0)
+        .largest_resolution_level().all_channels().first_valid_layer().all_attributes()
+        .from_buffered(Cursor::new(file.as_slice())).unwrap();
+
+    let interleaved_pixels: Vec<Vec<(f32, f32)>> = interleaved.layer_data.channel_data.pixels.iter()
+        .map(|samples| samples.iter().map(|sample| (sample[0].to_f32(), sample[1].to_f32())).collect())
+        .collect();
+
+    assert_eq!(interleaved_pixels, expected);
+
+    // layers without depth cannot be filtered
+    let without_depth = read().deep_data().deep_samples_in_range(1.0, 4.0)
+        .from_file("tests/images/valid/custom/deep_tiles.exr");
+
+    assert!(matches!(without_depth, Err(Error::Invalid(_))));
+}
+
+#[test]
+fn rewrite_deep_chunks_byte_for_byte() {
+    use exr::block::chunk::CompressedBlock;
+
+    for path in &["tests/images/valid/openexr/v2/LowResLeftView/Balls.exr", "tests/images/valid/custom/deep_tiles.exr"] {
+        let bytes = std::fs::read(path).unwrap();
+        let reader = exr::block::read(Cursor::new(bytes.as_slice()), false).unwrap();
+        let header_count = reader.headers().len();
+
+        let mut rewritten = Vec::new();
+        for chunk in reader.all_chunks(false).unwrap() {
+            let chunk = chunk.unwrap();
+
+            match &chunk.compressed_block {
+                CompressedBlock::DeepScanLine(block) => {
+                    assert!(!block.compressed_sample_counts.is_empty(), "{}", path)
+                },
+
+                CompressedBlock::DeepTile(block) => {
+                    assert!(!block.compressed_sample_counts.is_empty(), "{}", path)
+                },
+
+                _ => panic!("{}: expected only deep chunks", path),
+            }
+
+            chunk.write(&mut rewritten, header_count).unwrap();
+        }
+
+        // the chunks are stored back to back after the offset tables
+        assert!(rewritten.len() < bytes.len(), "{}", path);
+        assert!(bytes.ends_with(&rewritten), "{}: rewritten chunks differ from the original bytes", path);
+    }
+}
+
+#[test]
+fn read_each_view_of_multi_view_files() {
+    for file_name in &["Balls.exr", "LosPadres.exr", "Adjuster.exr", "Impact.exr"] {
+        let path = format!("tests/images/valid/openexr/MultiView/{}", file_name);
+
+        let meta = exr::meta::MetaData::read_from_file(&path, false).unwrap();
+        let header = &meta.headers[0];
+        let view_names = header.own_attributes.multi_view_names.as_ref().expect("multi view attribute");
+
+        let views: Vec<&Text> = header.channels.views(view_names).collect();
+        assert!(views.len() >= 2, "{}", path);
+
+        let read_view = |view: &Text| read()
+            .no_deep_data().largest_resolution_level()
+            .rgba_channels(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+            .view(view.clone())
+            .first_valid_layer().all_attributes()
+            .from_file(&path).unwrap();
+
+        let left = read_view(views[0]);
+        let right = read_view(views[1]);
+
+        assert_eq!(left.layer_data.channel_data.channels.0.name, Text::from("R"), "{}", path);
+        assert_eq!(right.layer_data.channel_data.channels.0.name, Text::from("R"), "{}", path);
+        assert_ne!(left.layer_data.channel_data.pixels.pixels, right.layer_data.channel_data.pixels.pixels, "{}", path);
+    }
+
+    // a view that is not in the file cannot be read
+    let missing_view = read()
+        .no_deep_data().largest_resolution_level()
+        .rgba_channels(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+        .view("top")
+        .first_valid_layer().all_attributes()
+        .from_file("tests/images/valid/openexr/MultiView/Balls.exr");
+
+    assert!(missing_view.is_err());
+}
+
+#[test]
+fn write_and_read_multi_view_channels() {
+    use exr::meta::attribute::ChannelList;
+
+    let view_names = vec![ Text::from("left"), Text::from("right") ];
+    let size = Vec2(8, 4);
+
+    let view_channel = |view: &Text, name: &str, value: f32| AnyChannel::new(
+        ChannelList::channel_name_in_view(&Text::from(name), &view_names, view),
+        FlatSamples::F32(vec![ value; size.area() ])
+    );
+
+    let channels = AnyChannels::sort(smallvec::smallvec![
+        view_channel(&view_names[0], "R", 0.1), view_channel(&view_names[0], "G", 0.2), view_channel(&view_names[0], "B", 0.3),
+        view_channel(&view_names[1], "R", 0.4), view_channel(&view_names[1], "G", 0.5), view_channel(&view_names[1], "B", 0.6),
+    ]);
+
+    let names: Vec<&Text> = channels.list.iter().map(|channel| &channel.name).collect();
+    assert_eq!(names, vec![ "B", "G", "R", "right.B", "right.G", "right.R" ]);
+
+    let mut layer = Layer::new(size, LayerAttributes::named("stereo"), Encoding::FAST_LOSSLESS, channels);
+    layer.attributes.multi_view_names = Some(view_names.clone());
+
+    let mut bytes = Vec::new();
+    Image::from_layer(layer).write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+    for (view, expected) in view_names.iter().zip(&[ (0.1, 0.2, 0.3, 1.0), (0.4, 0.5, 0.6, 1.0) ]) {
+        let image = read()
+            .no_deep_data().largest_resolution_level()
+            .rgba_channels(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+            .view(view.clone())
+            .first_valid_layer().all_attributes()
+            .from_buffered(Cursor::new(&bytes)).unwrap();
+
+        assert_eq!(image.layer_data.attributes.multi_view_names.as_ref(), Some(&view_names));
+        assert!(image.layer_data.channel_data.pixels.pixels.iter().all(|pixel| pixel == expected));
+    }
+}
+
+#[test]
+fn write_generated_preview() {
+    let size = Vec2(64, 32);
+
+    let image = Image::from_channels(size, SpecificChannels::rgba(|Vec2(x, y)| (
+        x as f32 / size.width() as f32, y as f32 / size.height() as f32, 0.18_f32, f16::ONE
+    )));
+
+    assert_eq!(image.write().generate_preview((16, 16)).preview_warning(), None);
+    assert_eq!(image.write().preview_warning(), None);
+
+    let mut bytes = Vec::new();
+    image.write().generate_preview((16, 16)).to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+    let meta = MetaData::read_from_buffered(Cursor::new(&bytes), true).unwrap();
+    let preview = meta.headers[0].own_attributes.preview.as_ref().expect("no preview generated");
+    assert_eq!(preview.size, Vec2(16, 8));
+
+    let pixels: Vec<&[i8]> = preview.pixel_data.chunks_exact(4).collect();
+    assert_eq!(pixels.len(), 16 * 8);
+
+    // red increases to the right, green increases downwards, and blue is a constant middle gray
+    assert!((pixels[15][0] as u8) > (pixels[0][0] as u8));
+    assert!((pixels[7 * 16][1] as u8) > (pixels[0][1] as u8));
+    assert!(pixels.iter().all(|pixel| pixel[2] as u8 == 84 && pixel[3] as u8 == 255));
+
+    // layers without rgb channels are written without a preview
+    let depth = Image::from_channels(size, SpecificChannels::build()
+        .with_channel("Z").with_pixel_fn(|_| (1.0_f32,)));
+
+    let warning = depth.write().generate_preview((16, 16)).preview_warning().expect("no warning for skipped preview");
+    assert!(warning.contains("red, green, and blue"), "{}", warning);
+
+    let mut bytes = Vec::new();
+    depth.write().generate_preview((16, 16)).to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+    let meta = MetaData::read_from_buffered(Cursor::new(&bytes), true).unwrap();
+    assert!(meta.headers[0].own_attributes.preview.is_none());
+}
+
+#[test]
+fn roundtrip_rounded_up_mip_maps() {
+    use exr::math::RoundingMode;
+
+    let size = Vec2(133, 67);
+
+    for &rounding_mode in &[ RoundingMode::Up, RoundingMode::Down ] {
+        let levels = || Levels::Mip {
+            rounding_mode,
+            level_data: exr::meta::mip_map_levels(rounding_mode, size)
+                .map(|(index, level_size)| FlatSamples::F32(
+                    (0 .. level_size.area()).map(|pixel| (pixel * (index + 1)) as f32).collect()
+                ))
+                .collect()
+        };
+
+        let channels = AnyChannels::sort(smallvec::smallvec![ AnyChannel::new("Y", levels()) ]);
+        let encoding = Encoding { blocks: Blocks::Tiles(Vec2(16, 16)), .. Encoding::FAST_LOSSLESS };
+        let image = Image::from_layer(Layer::new(size, LayerAttributes::named("mips"), encoding, channels));
+
+        let mut bytes = Vec::new();
+        image.write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+        let meta = MetaData::read_from_buffered(Cursor::new(&bytes), true).unwrap();
+        match meta.headers[0].blocks {
+            exr::meta::BlockDescription::Tiles(tiles) => assert_eq!(tiles.rounding_mode, rounding_mode),
+            _ => panic!("expected tiles"),
+        }
+
+        let read_image = read().no_deep_data().all_resolution_levels().all_channels().all_layers().all_attributes()
+            .from_buffered(Cursor::new(&bytes)).unwrap();
+
+        let read_levels = &read_image.layer_data[0].channel_data.list[0].sample_data;
+        let level_sizes: Vec<Vec2<usize>> = read_image.layer_data[0].levels_with_resolution(read_levels)
+            .map(|(_, level_size)| level_size).collect();
+
+        let expected_level_count = if rounding_mode == RoundingMode::Up { 9 } else { 8 };
+        assert_eq!(level_sizes.len(), expected_level_count, "{:?}", rounding_mode);
+        assert_eq!(level_sizes[1], if rounding_mode == RoundingMode::Up { Vec2(67, 34) } else { Vec2(66, 33) });
+
+        assert!(read_levels == &levels(), "{:?}", rounding_mode);
+    }
+}
+
+#[test]
+fn write_and_read_camera_matrices() {
+    use exr::meta::attribute::{matrix4x4_from_rows, matrix4x4_to_rows};
+
+    let world_to_camera = matrix4x4_from_rows([
+        [ 0.0, 0.0, -1.0, 0.0 ],
+        [ 0.0, 1.0,  0.0, 0.0 ],
+        [ 1.0, 0.0,  0.0, 0.0 ],
+        [ 4.0, 5.0,  6.0, 1.0 ],
+    ]);
+
+    let world_to_ndc: Vec<f32> = (0 .. 16).map(|index| index as f32 * 0.5).collect();
+    let world_to_ndc: [f32; 16] = std::convert::TryFrom::try_from(world_to_ndc.as_slice()).unwrap();
+
+    let attributes = LayerAttributes::named("camera")
+        .with_camera_frustum(world_to_camera, world_to_ndc, (0.8, 0.6), 0.1 .. 100.0);
+
+    let layer = Layer::new(
+        (8, 8), attributes, Encoding::UNCOMPRESSED,
+        SpecificChannels::build().with_channel("Z").with_pixel_fn(|_| (1.0_f32,))
+    );
+
+    let mut bytes = Vec::new();
+    Image::from_layer(layer).write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+    let meta = MetaData::read_from_buffered(Cursor::new(&bytes), true).unwrap();
+    let read_attributes = &meta.headers[0].own_attributes;
+
+    assert_eq!(read_attributes.world_to_camera, Some(world_to_camera));
+    assert_eq!(read_attributes.world_to_normalized_device, Some(world_to_ndc));
+    assert!(read_attributes.other.is_empty(), "matrices should not end up in the custom attributes");
+
+    // the translation is in the last row, and the element order is preserved
+    let rows = matrix4x4_to_rows(read_attributes.world_to_camera.unwrap());
+    assert_eq!(rows[3], [ 4.0, 5.0, 6.0, 1.0 ]);
+    assert_eq!(rows[0][2], -1.0);
+    assert_eq!(matrix4x4_to_rows(world_to_ndc)[1][0], 2.0);
+}
+
+#[test]
+fn rename_layers_and_channels() {
+    use exr::meta::attribute::{ChannelDescription, SampleType};
+
+    let size = Vec2(8, 4);
+    let flat = |value: f32| FlatSamples::F32(vec![ value; size.area() ]);
+
+    let mut channels = AnyChannels::sort(smallvec::smallvec![
+        AnyChannel::new("diffuse.R", flat(0.1)), AnyChannel::new("diffuse.G", flat(0.2)),
+        AnyChannel::new("diffuse.B", flat(0.3)), AnyChannel::new("Z", flat(4.0)),
+    ]);
+
+    assert_eq!(channels.rename_prefix("diffuse.", "albedo.").unwrap(), 3);
+    assert_eq!(channels.rename_prefix("specular.", "gloss.").unwrap(), 0);
+
+    let names: Vec<String> = channels.list.iter().map(|channel| channel.name.to_string()).collect();
+    assert_eq!(names, vec![ "Z", "albedo.B", "albedo.G", "albedo.R" ]);
+    assert_eq!(channels.list[3].sample_data, flat(0.1), "samples must move together with their names");
+
+    // renaming must not produce duplicate names, and fails without changing anything
+    let mut colliding = channels.clone();
+    colliding.list.push(AnyChannel::new("diffuse.R", flat(1.0)));
+    colliding = AnyChannels::sort(colliding.list);
+    let before = colliding.clone();
+    assert!(colliding.rename_prefix("diffuse.", "albedo.").is_err());
+    assert_eq!(colliding, before);
+
+    let mut layer = Layer::new(size, LayerAttributes::named("beauty"), Encoding::FAST_LOSSLESS, channels);
+    layer.rename("rgba");
+
+    let mut bytes = Vec::new();
+    Image::from_layer(layer.clone()).write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+    let image = read().no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes()
+        .from_buffered(Cursor::new(&bytes)).unwrap();
+
+    assert_eq!(image.layer_data[0].attributes.layer_name, Some(Text::from("rgba")));
+    assert_eq!(image.layer_data[0].channel_data, layer.channel_data);
+
+    // renaming the header also renames the channels prefixed with the layer name
+    let mut header = Header::new(Text::from("beauty"), size, smallvec::smallvec![
+        ChannelDescription::named("Z", SampleType::F32),
+        ChannelDescription::named("beauty.B", SampleType::F16),
+        ChannelDescription::named("beauty.G", SampleType::F16),
+        ChannelDescription::named("beauty.R", SampleType::F16),
+    ]);
+
+    header.set_layer_name("Zeta").unwrap();
+    assert_eq!(header.own_attributes.layer_name, Some(Text::from("Zeta")));
+
+    let names: Vec<String> = header.channels.list.iter().map(|channel| channel.name.to_string()).collect();
+    assert_eq!(names, vec![ "Z", "Zeta.B", "Zeta.G", "Zeta.R" ]);
+
+    assert_eq!(header.channels.rename_prefix("Zeta.", "").unwrap(), 3);
+    let names: Vec<String> = header.channels.list.iter().map(|channel| channel.name.to_string()).collect();
+    assert_eq!(names, vec![ "B", "G", "R", "Z" ]);
+    assert_eq!(header.channels.list[3].sample_type, SampleType::F32);
+
+    MetaData::validate(std::slice::from_ref(&header), true).expect("renamed header should stay valid");
+
+    let mut colliding = header.clone();
+    colliding.own_attributes.layer_name = Some(Text::from("x"));
+    colliding.channels.list[0].name = Text::from("x.B");
+    colliding.channels.list[1].name = Text::from("y.B");
+    assert!(colliding.set_layer_name("y").is_err());
+    assert_eq!(colliding.own_attributes.layer_name, Some(Text::from("x")));
+}
+
+#[test]
+fn parse_custom_attribute_types() {
+    use exr::meta::attribute::AttributeParserRegistry;
+    use exr::io::Data;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct LensData { focal_length: f32, serial_number: i32 }
+
+    let parsers = AttributeParserRegistry::new().with_type(
+        "lensData",
+
+        |mut bytes: &[u8]| Ok(LensData {
+            focal_length: f32::read(&mut bytes)?,
+            serial_number: i32::read(&mut bytes)?,
+        }),
+
+        |lens: &LensData| {
+            let mut bytes = Vec::new();
+            lens.focal_length.write(&mut bytes)?;
+            lens.serial_number.write(&mut bytes)?;
+            Ok(bytes)
+        }
+    );
+
+    let lens = LensData { focal_length: 35.0, serial_number: 1234 };
+    let lens_bytes = parsers.to_attribute_value("lensData", lens).unwrap();
+    let raw_bytes = match &lens_bytes {
+        AttributeValue::Parsed(parsed) => parsed.bytes().to_vec(),
+        other => panic!("unexpected attribute value {:?}", other),
+    };
+
+    assert_eq!(raw_bytes.len(), 8);
+    assert!(parsers.to_attribute_value("unknownType", lens).is_err());
+
+    let mut image = Image::from_channels((4, 4), SpecificChannels::rgb(|_| (0.5_f32, 0.5_f32, 0.5_f32)));
+    image.layer_data.attributes.other.insert(Text::from("lens"), AttributeValue::Custom { kind: Text::from("lensData"), bytes: raw_bytes.clone() });
+    image.layer_data.attributes.other.insert(Text::from("rig"), AttributeValue::Custom { kind: Text::from("rigData"), bytes: vec![ 1, 2, 3 ] });
+
+    let mut bytes = Vec::new();
+    image.write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+    // registered types are parsed, and other types keep their raw bytes
+    let meta = MetaData::read_with_attribute_parsers(Cursor::new(&bytes), true, &parsers).unwrap();
+    let attributes = &meta.headers[0].own_attributes.other;
+
+    match &attributes[&Text::from("lens")] {
+        AttributeValue::Parsed(parsed) => {
+            assert_eq!(parsed.kind(), "lensData");
+            assert_eq!(parsed.value::<LensData>(), Some(&lens));
+        },
+
+        other => panic!("unexpected attribute value {:?}", other),
+    }
+
+    assert_eq!(attributes[&Text::from("rig")], AttributeValue::Custom { kind: Text::from("rigData"), bytes: vec![ 1, 2, 3 ] });
+
+    // the high-level reader parses the attributes as well
+    let image = read().no_deep_data().largest_resolution_level().all_channels().first_valid_layer().all_attributes()
+        .attribute_parsers(parsers.clone())
+        .from_buffered(Cursor::new(&bytes)).unwrap();
+
+    let parsed = &image.layer_data.attributes.other[&Text::from("lens")];
+    assert_eq!(parsed, &lens_bytes);
+
+    // writing the parsed value reproduces the original bytes
+    let mut rewritten = Vec::new();
+    image.write().to_buffered(Cursor::new(&mut rewritten)).unwrap();
+
+    let meta = MetaData::read_from_buffered(Cursor::new(&rewritten), true).unwrap();
+    assert_eq!(
+        meta.headers[0].own_attributes.other[&Text::from("lens")],
+        AttributeValue::Custom { kind: Text::from("lensData"), bytes: raw_bytes }
+    );
+}
+
+#[test]
+fn preserve_custom_attributes_through_rgba_reading() {
+    use exr::meta::attribute::{Chromaticities, FloatRect, TimeCode};
+    use std::collections::BTreeMap;
+
+    let size = Vec2(8, 8);
+    let mut attributes = LayerAttributes::named("beauty");
+
+    // emulate a file written by other software, which stores image attributes only in the second header,
+    // and uses a reserved name for an attribute of an unusual type
+    let custom_attributes = vec![
+        ("nuke/node_hash", AttributeValue::Text(Text::from("b5f9a1"))),
+        ("vendor/f64", AttributeValue::F64(0.125)),
+        ("vendor/matrix", AttributeValue::Matrix3x3([ 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0 ])),
+        ("vendor/ints", AttributeValue::IntVec3((1, -2, 3))),
+        ("vendor/rect", AttributeValue::FloatRect(FloatRect { min: Vec2(0.0, 1.0), max: Vec2(2.0, 3.0) })),
+        ("vendor/views", AttributeValue::TextVector(vec![ Text::from("a"), Text::from("b") ])),
+        ("vendor/blob", AttributeValue::Custom { kind: Text::from("blobData"), bytes: vec![ 9, 8, 7, 6 ] }),
+        ("vendor/chromaticities", AttributeValue::Chromaticities(Chromaticities::SRGB)),
+        ("vendor/time_code", AttributeValue::TimeCode(TimeCode::from_time(1, 2, 3, 4).unwrap())),
+        ("owner", AttributeValue::I32(7)),
+    ];
+
+    for (name, value) in &custom_attributes {
+        attributes.other.insert(Text::from(*name), value.clone());
+    }
+
+    let depth = Layer::new(size, LayerAttributes::named("depth"), Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(smallvec::smallvec![ AnyChannel::new("Z", FlatSamples::F32(vec![ 1.0; size.area() ])) ]));
+
+    let beauty = Layer::new(size, attributes, Encoding::FAST_LOSSLESS,
+        SpecificChannels::rgba(|_| (0.5_f32, 0.5_f32, 0.5_f32, 1.0_f32)));
+
+    let image = Image::empty(ImageAttributes::with_size(size)).with_layer(depth).with_layer(beauty);
+
+    let mut original = Vec::new();
+    image.write().skip_compatibility_checks().to_buffered(Cursor::new(&mut original)).unwrap();
+
+    let image = read().no_deep_data().largest_resolution_level()
+        .rgba_channels(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+        .first_valid_layer().all_attributes()
+        .from_buffered(Cursor::new(&original)).unwrap();
+
+    let mut rewritten = Vec::new();
+    image.write().to_buffered(Cursor::new(&mut rewritten)).unwrap();
+
+    fn custom_attribute_payloads(bytes: &[u8], header_index: usize) -> BTreeMap<Text, (Text, Vec<u8>)> {
+        let meta = MetaData::read_from_buffered(Cursor::new(bytes), true).unwrap();
+        let header = &meta.headers[header_index];
+
+        header.own_attributes.other.iter().chain(&header.shared_attributes.other)
+            .map(|(name, value)| {
+                let mut payload = Vec::new();
+                value.write(&mut payload).unwrap();
+                (name.clone(), (Text::from_slice_unchecked(value.kind_name()), payload))
+            })
+            .collect()
+    }
+
+    let original_payloads = custom_attribute_payloads(&original, 1);
+    assert_eq!(original_payloads.len(), custom_attributes.len());
+    assert_eq!(custom_attribute_payloads(&rewritten, 0), original_payloads);
+}
+
+#[test]
+fn validation_options_only_reject_the_chosen_inconsistencies() {
+    use exr::meta::ValidationOptions;
+
+    let strict_tables = ValidationOptions { strict_offset_tables: true, ..ValidationOptions::lenient() };
+    let strict_attributes = ValidationOptions { strict_attributes: true, ..ValidationOptions::lenient() };
+
+    assert_eq!(ValidationOptions::from(true), ValidationOptions::strict());
+    assert_eq!(ValidationOptions::from(false), ValidationOptions::lenient());
+
+    let write_image = |attributes: &[(&str, AttributeValue)]| {
+        let mut image = Image::from_channels((8, 16), SpecificChannels::rgb(|position: Vec2<usize>|
+            (position.x() as f32, position.y() as f32, 0.5_f32)
+        ));
+
+        for (name, value) in attributes {
+            image.layer_data.attributes.other.insert(Text::from(*name), value.clone());
+        }
+
+        let mut bytes = Vec::new();
+        image.write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+        bytes
+    };
+
+    let read_image = read().no_deep_data().largest_resolution_level().all_channels().first_valid_layer().all_attributes();
+    let expected = read_image.clone().from_buffered(Cursor::new(write_image(&[]))).unwrap();
+
+    // an offset that points far beyond the end of the file
+    let mut broken_tables = write_image(&[]);
+    let chunk_count = exr::block::read(Cursor::new(&broken_tables), true).unwrap().headers()[0].chunk_count;
+    let table_start = (0 .. broken_tables.len() - 8)
+        .find(|&position| {
+            let offset = u64::from_le_bytes(std::convert::TryInto::try_into(&broken_tables[position .. position + 8]).unwrap());
+            offset == (position + chunk_count * 8) as u64
+        })
+        .unwrap();
+
+    broken_tables[table_start .. table_start + 8].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+
+    match read_image.clone().validation(strict_tables).from_buffered(Cursor::new(&broken_tables)) {
+        Err(Error::Invalid(message)) => assert!(message.contains("offset table"), "{}", message),
+        other => panic!("corrupt offset table not rejected: {:?}", other.map(|_| ())),
+    }
+
+    assert!(exr::block::read(Cursor::new(&broken_tables), strict_tables).unwrap().all_chunks(strict_tables).is_err());
+
+    let recovered = read_image.clone().validation(strict_attributes).from_buffered(Cursor::new(&broken_tables)).unwrap();
+    expected.assert_equals_result(&recovered);
+
+    // an attribute of a standard type with too few bytes
+    let broken_attribute = write_image(&[
+        ("broken", AttributeValue::Custom { kind: Text::from("v2i"), bytes: vec![ 1, 2, 3, 4 ] })
+    ]);
+
+    match read_image.clone().validation(strict_attributes).from_buffered(Cursor::new(&broken_attribute)) {
+        Err(Error::Invalid(message)) => assert!(message.contains("missing bytes"), "{}", message),
+        other => panic!("malformed attribute not rejected: {:?}", other.map(|_| ())),
+    }
+
+    assert!(MetaData::read_from_buffered(Cursor::new(&broken_attribute), strict_attributes).is_err());
+
+    let skipped = read_image.clone().validation(strict_tables).from_buffered(Cursor::new(&broken_attribute)).unwrap();
+    assert!(!skipped.layer_data.attributes.other.contains_key(&Text::from("broken")));
+    expected.layer_data.channel_data.assert_equals_result(&skipped.layer_data.channel_data);
+
+    // the second attribute is renamed to the name of the first attribute
+    let mut duplicate_attributes = write_image(&[
+        ("dupA", AttributeValue::I32(1)),
+        ("dupB", AttributeValue::I32(2)),
+    ]);
+
+    let renamed_start = duplicate_attributes.windows(5).position(|window| window == b"dupB\0").unwrap();
+    duplicate_attributes[renamed_start .. renamed_start + 5].copy_from_slice(b"dupA\0");
+
+    let reject_duplicates = ValidationOptions { allow_duplicate_attributes: false, ..ValidationOptions::lenient() };
+    assert!(MetaData::read_from_buffered(Cursor::new(&duplicate_attributes), reject_duplicates).is_err());
+    assert!(MetaData::read_from_buffered(Cursor::new(&duplicate_attributes), ValidationOptions::strict()).is_err());
+
+    let meta = MetaData::read_from_buffered(Cursor::new(&duplicate_attributes), ValidationOptions::lenient()).unwrap();
+    assert!(meta.headers[0].own_attributes.other.contains_key(&Text::from("dupA")));
+    assert!(!meta.headers[0].own_attributes.other.contains_key(&Text::from("dupB")));
+}
+
+#[test]
+fn non_utf8_texts_survive_read_write_cycles() {
+    let bytes = |bytes: &[u8]| Text::from_slice_unchecked(bytes);
+    let size = Vec2(3, 2);
+
+    // latin-1 encoded names, as written by some legacy software
+    let mut attributes = LayerAttributes::named(bytes(b"Ebene \xe4"));
+    attributes.owner = Some(bytes(b"Ren\xe9 M\xfcller"));
+    attributes.comments = Some(bytes("Größe".as_bytes()));
+    attributes.other.insert(bytes(b"\xc4rger"), AttributeValue::TextVector(vec![ bytes(b"\xff\xfe"), bytes(b"ok") ]));
+
+    let image = Image::from_layer(Layer::new(size, attributes, Encoding::UNCOMPRESSED, AnyChannels::sort(smallvec::smallvec![
+        AnyChannel::new(bytes(b"r\xf6t"), FlatSamples::F32(vec![ 1.0; size.area() ])),
+        AnyChannel::new(bytes(b"\xa0"), FlatSamples::F16(vec![ f16::ONE; size.area() ])),
+    ])));
+
+    let read_image = read().no_deep_data().largest_resolution_level().all_channels().all_layers().all_attributes().pedantic();
+
+    let mut first_bytes = Vec::new();
+    image.write().to_buffered(Cursor::new(&mut first_bytes)).unwrap();
+    let first = read_image.clone().from_buffered(Cursor::new(&first_bytes)).unwrap();
+
+    let mut second_bytes = Vec::new();
+    first.write().to_buffered(Cursor::new(&mut second_bytes)).unwrap();
+    let second = read_image.from_buffered(Cursor::new(&second_bytes)).unwrap();
+
+    assert_eq!(first_bytes, second_bytes);
+    assert_eq!(first, second);
+
+    let layer = &second.layer_data[0];
+    assert_eq!(layer.attributes.layer_name.as_ref().unwrap().as_bytes(), b"Ebene \xe4");
+    assert_eq!(layer.channel_data.list[0].name.as_bytes(), b"r\xf6t");
+    assert_eq!(layer.channel_data.list[1].name.as_bytes(), b"\xa0");
+    assert_eq!(layer.attributes.other.get(&bytes(b"\xc4rger")), Some(&AttributeValue::TextVector(vec![ bytes(b"\xff\xfe"), bytes(b"ok") ])));
+
+    // each byte is a char when displayed, but utf-8 can be decoded explicitly
+    let owner = layer.attributes.owner.as_ref().unwrap();
+    assert_eq!(owner.as_bytes(), b"Ren\xe9 M\xfcller");
+    assert_eq!(owner.to_string(), "René Müller");
+    assert_eq!(owner.to_string_lossy(), "Ren\u{FFFD} M\u{FFFD}ller");
+    assert_eq!(layer.attributes.comments.as_ref().unwrap().to_string_lossy(), "Größe");
+}
+
+#[test]
+fn read_limits_reject_hostile_headers() {
+    use exr::meta::{ValidationOptions, ReadLimits};
+
+    let limited = |limits: ReadLimits| ValidationOptions { limits, ..ValidationOptions::lenient() };
+    let unlimited = limited(ReadLimits::unlimited());
+
+    let assert_exceeds = |result: Result<MetaData>, limit_name: &str| match result {
+        Err(Error::Invalid(message)) => assert!(message.contains(limit_name), "{}", message),
+        other => panic!("{} not exceeded: {:?}", limit_name, other.map(|_| ())),
+    };
+
+    let write_image = |channels: usize| {
+        let size = Vec2(3, 2);
+        let channels = (0 .. channels)
+            .map(|index| AnyChannel::new(format!("c{:05}", index).as_str(), FlatSamples::F16(vec![ f16::ONE; size.area() ])))
+            .collect();
+
+        let image = Image::from_layer(Layer::new(size, LayerAttributes::named("hostile"), Encoding::UNCOMPRESSED, AnyChannels::sort(channels)));
+        let mut bytes = Vec::new();
+        image.write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+        bytes
+    };
+
+    let valid = write_image(1);
+
+    // a data window of a billion by a billion pixels
+    let mut huge_window = valid.clone();
+    let window_start = huge_window.windows(17).position(|window| window == b"dataWindow\0box2i\0").unwrap() + 17 + 4;
+    for (index, value) in [0_i32, 0, 999_999_999, 999_999_999].iter().enumerate() {
+        huge_window[window_start + index * 4 .. window_start + index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    assert_exceeds(MetaData::read_from_buffered(Cursor::new(&huge_window), ValidationOptions::lenient()), "max_image_area");
+    assert_exceeds(MetaData::read_from_buffered(Cursor::new(&huge_window), ValidationOptions::strict()), "max_image_area");
+
+    // an attribute that declares two gigabytes, but is followed by nothing
+    let mut huge_attribute = valid[.. 8].to_vec();
+    huge_attribute.extend_from_slice(b"evil\0string\0");
+    huge_attribute.extend_from_slice(&i32::MAX.to_le_bytes());
+
+    assert_exceeds(MetaData::read_from_buffered(Cursor::new(&huge_attribute), ValidationOptions::lenient()), "max_attribute_byte_size");
+    assert!(MetaData::read_from_buffered(Cursor::new(&huge_attribute), unlimited).is_err(), "missing bytes must not be allocated");
+
+    // many channels
+    let many_channels = write_image(40);
+    let few_channels = limited(ReadLimits { max_channel_count: 39, ..ReadLimits::default() });
+    assert_exceeds(MetaData::read_from_buffered(Cursor::new(&many_channels), few_channels), "max_channel_count");
+    assert_eq!(MetaData::read_from_buffered(Cursor::new(&many_channels), ValidationOptions::lenient()).unwrap().headers[0].channels.list.len(), 40);
+
+    // the sum of all attribute sizes
+    let small_headers = limited(ReadLimits { max_header_byte_size: 64, ..ReadLimits::default() });
+    assert_exceeds(MetaData::read_from_buffered(Cursor::new(&valid), small_headers), "max_header_byte_size");
+
+    // the decompressed size of a chunk, which is an uncompressed line of 3 pixels with 40 f16 channels
+    let read_image = read().no_deep_data().largest_resolution_level().all_channels().first_valid_layer().all_attributes();
+    let small_chunks = limited(ReadLimits { max_chunk_byte_size: 3 * 40 * 2 - 1, ..ReadLimits::default() });
+
+    match read_image.clone().validation(small_chunks).from_buffered(Cursor::new(&many_channels)) {
+        Err(Error::Invalid(message)) => assert!(message.contains("max_chunk_byte_size"), "{}", message),
+        other => panic!("chunk size limit not exceeded: {:?}", other.map(|_| ())),
+    }
+
+    assert!(read_image.clone().validation(unlimited).from_buffered(Cursor::new(&many_channels)).is_ok());
+    assert!(read_image.validation(ValidationOptions::strict()).from_buffered(Cursor::new(&valid)).is_ok());
+}
+
+#[test]
+fn normalize_unsorted_channel_lists_when_reading() {
+    use exr::meta::ValidationOptions;
+    use exr::meta::attribute::ChannelListProblem;
+
+    let size = Vec2(4, 2);
+    let layer = Layer::new(
+        size, LayerAttributes::named("channels"), Encoding::UNCOMPRESSED,
+        AnyChannels::sort(smallvec::smallvec![
+            AnyChannel::new("chanA", FlatSamples::F32(vec![ 1.0; size.area() ])),
+            AnyChannel::new("chanB", FlatSamples::F32(vec![ 2.0; size.area() ])),
+        ])
+    );
+
+    let mut bytes = Vec::new();
+    Image::from_layer(layer).write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+    // rename the first channel, such that the channel list is no longer sorted
+    let renamed_start = bytes.windows(6).position(|window| window == b"chanA\0").unwrap();
+    bytes[renamed_start .. renamed_start + 6].copy_from_slice(b"chanC\0");
+
+    let meta = MetaData::read_from_buffered(Cursor::new(&bytes), ValidationOptions::lenient()).unwrap();
+    match meta.headers[0].channels.validate_names() {
+        Err(error) => assert_eq!(error.problems, vec![ ChannelListProblem::Unsorted(1, Text::from("chanB")) ]),
+        Ok(()) => panic!("channels should not be sorted"),
+    }
+
+    let read_image = read().no_deep_data().largest_resolution_level().all_channels().first_valid_layer().all_attributes();
+    assert!(read_image.clone().from_buffered(Cursor::new(&bytes)).is_err(), "unsorted channels should be rejected by default");
+
+    let normalize = ValidationOptions { normalize_channel_lists: true, ..ValidationOptions::lenient() };
+    let meta = MetaData::read_from_buffered(Cursor::new(&bytes), normalize).unwrap();
+
+    let channels = &meta.headers[0].channels;
+    assert!(channels.validate_names().is_ok());
+    assert_eq!(channels.list[0].name, Text::from("chanB"));
+    assert_eq!(channels.list[1].name, Text::from("chanC"));
+
+    let image = read_image.validation(normalize).from_buffered(Cursor::new(&bytes)).unwrap();
+
+    assert_eq!(image.layer_data.channel_data.list.len(), 2);
+}
+
+#[test]
+fn write_headers_with_modified_compression_and_tiles() {
+    use exr::block::writer::ChunksWriter;
+    use exr::image::write::layers::{WritableLayers, LayersWriter};
+    use exr::meta::{BlockDescription, compute_chunk_count};
+    use exr::meta::attribute::{TileDescription, LevelMode};
+    use exr::math::RoundingMode;
+
+    let size = Vec2(37, 45);
+    let layer = Layer::new(
+        size, LayerAttributes::named("modified"),
+        Encoding { compression: Compression::ZIP16, blocks: Blocks::ScanLines, line_order: LineOrder::Increasing },
+        AnyChannels::sort(smallvec::smallvec![
+            AnyChannel::new("Y", FlatSamples::F32((0 .. size.area()).map(|index| index as f32).collect())),
+        ])
+    );
+
+    let mut bytes = Vec::new();
+    Image::from_layer(layer).write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+
+    let read_layer = || read().no_deep_data().largest_resolution_level().all_channels().first_valid_layer().all_attributes()
+        .from_buffered(Cursor::new(&bytes)).unwrap().layer_data;
+
+    let original = read_layer();
+    let original_header = MetaData::read_from_buffered(Cursor::new(&bytes), true).unwrap().headers[0].clone();
+    assert_eq!(original_header.chunk_count, 3);
+
+    let mut scan_lines = original_header.clone();
+    scan_lines.compression = Compression::ZIP1;
+
+    let mut tiles = original_header.clone();
+    tiles.blocks = BlockDescription::Tiles(TileDescription {
+        tile_size: Vec2(16, 8), level_mode: LevelMode::Singular, rounding_mode: RoundingMode::Down
+    });
+
+    for (mut header, expected_chunk_count) in vec![ (scan_lines, 45), (tiles, 3 * 6) ] {
+        // the stale chunk count is named in the error
+        match MetaData::validate(std::slice::from_ref(&header), false) {
+            Err(Error::Invalid(message)) => assert!(message.contains("chunk count"), "{}", message),
+            other => panic!("stale chunk count not detected: {:?}", other),
+        }
+
+        header.recompute_derived_fields();
+        assert_eq!(header.chunk_count, expected_chunk_count);
+        assert_eq!(header.chunk_count, compute_chunk_count(header.compression, header.layer_size, header.blocks));
+        MetaData::validate(std::slice::from_ref(&header), true).unwrap();
+
+        // writing recomputes the derived fields automatically
+        header.chunk_count = original_header.chunk_count;
+
+        let headers: exr::meta::Headers = smallvec::smallvec![ header ];
+        let layers = smallvec::smallvec![ original.clone() ] as Layers<_>;
+        let layers_writer = layers.create_writer(&headers);
+
+        let mut written = Vec::new();
+        exr::block::write(Cursor::new(&mut written), headers, true, |meta, chunk_writer| {
+            let blocks = meta.collect_ordered_block_data(|block_index| {
+                layers_writer.extract_uncompressed_block(&meta.headers, block_index)
+            });
+
+            let mut compressor = chunk_writer.sequential_blocks_compressor(&meta);
+            for (index_in_header_increasing_y, block) in blocks {
+                compressor.compress_block(index_in_header_increasing_y, block)?;
+            }
+
+            Ok(())
+        }).unwrap();
+
+        let written_header = &MetaData::read_from_buffered(Cursor::new(&written), true).unwrap().headers[0];
+        assert_eq!(written_header.chunk_count, expected_chunk_count);
+
+        let rewritten = read().no_deep_data().largest_resolution_level().all_channels().first_valid_layer().all_attributes()
+            .from_buffered(Cursor::new(&written)).unwrap().layer_data;
+
+        assert_eq!(rewritten.channel_data, original.channel_data);
+    }
+}
+
+#[test]
+fn size_estimates_are_upper_bounds_for_all_files_in_repository(){
+    check_all_files_in_repo(|path|{
+        let file = std::fs::read(path).expect("cannot open file");
+
+        let mut meta_data_bytes = Cursor::new(file.as_slice());
+        let meta_data = MetaData::read_from_buffered(&mut meta_data_bytes, false)?;
+        let meta_data_byte_size = meta_data_bytes.position() as usize;
+
+        let worst_case_file_bytes = meta_data.headers.iter()
+            .fold(meta_data_byte_size, |sum, header| sum.saturating_add(header.worst_case_compressed_bytes()));
+
+        assert!(worst_case_file_bytes >= file.len(), "{} < {}", worst_case_file_bytes, file.len());
+        if meta_data.headers.iter().any(|header| header.deep) { return Ok(()) }
+
+        let mut decoded_byte_count = 0;
+        for chunk in exr::block::read(Cursor::new(&file), false)?.all_chunks(false)? {
+            decoded_byte_count += exr::block::UncompressedBlock::decompress_chunk(chunk?, &meta_data, false)?.data.len();
+        }
+
+        assert!(meta_data.estimated_decoded_bytes() >= decoded_byte_count, "{} < {}", meta_data.estimated_decoded_bytes(), decoded_byte_count);
+        Ok(())
+    });
+}
+
+#[test]
+fn size_estimates_are_upper_bounds_for_written_images() -> UnitResult {
+    let size = Vec2(97, 61);
+    let pixels = |position: Vec2<usize>| (
+        f16::from_f32(position.x() as f32 / 97.0),
+        position.y() as f32 / 61.0,
+        (position.x() * position.y()) as u32,
+    );
+
+    for &compression in &[Compression::Uncompressed, Compression::RLE, Compression::ZIP1, Compression::PIZ, Compression::PXR24] {
+        let channels = SpecificChannels::build()
+            .with_channel("A").with_channel("B").with_channel("C")
+            .with_pixel_fn(pixels);
+
+        let layer = Layer::new(size, LayerAttributes::named("main"), Encoding {
+            compression, blocks: Blocks::Tiles(Vec2(16, 16)),
+            .. Encoding::default()
+        }, channels);
+
+        let image = Image::from_layer(layer);
+        let writer = image.write().non_parallel();
+
+        let mut bytes = Vec::new();
+        writer.clone().to_buffered(Cursor::new(&mut bytes))?;
+        assert!(writer.worst_case_file_bytes()? >= bytes.len(), "{:?}", compression);
+
+        let meta_data = MetaData::read_from_buffered(bytes.as_slice(), false)?;
+        assert_eq!(writer.estimated_decoded_bytes(), meta_data.estimated_decoded_bytes());
+        assert_eq!(meta_data.estimated_decoded_bytes(), (2 + 4 + 4) * size.area());
+    }
+
+    // estimating the size does not generate the preview, which would look at all pixels
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pixel_requests = AtomicUsize::new(0);
+    let channels = SpecificChannels::rgb(|position: Vec2<usize>| {
+        pixel_requests.fetch_add(1, Ordering::Relaxed);
+        (position.x() as f32 / 97.0, position.y() as f32 / 61.0, 0.5_f32)
+    });
+
+    let image = Image::from_channels(size, channels);
+    let writer = image.write().non_parallel().generate_preview(Vec2(32, 32));
+
+    let worst_case_file_bytes = writer.worst_case_file_bytes()?;
+    let estimated_decoded_bytes = writer.estimated_decoded_bytes();
+    assert_eq!(pixel_requests.load(Ordering::Relaxed), 0);
+
+    let mut bytes = Vec::new();
+    writer.to_buffered(Cursor::new(&mut bytes))?;
+    assert!(pixel_requests.load(Ordering::Relaxed) > 0);
+    assert!(worst_case_file_bytes >= bytes.len(), "{} < {}", worst_case_file_bytes, bytes.len());
+
+    let meta_data = MetaData::read_from_buffered(bytes.as_slice(), false)?;
+    assert!(meta_data.headers[0].own_attributes.preview.is_some());
+    assert_eq!(estimated_decoded_bytes, meta_data.estimated_decoded_bytes());
+
+    Ok(())
+}
+
+#[test]
+fn meta_data_roundtrip_without_chunks_for_all_files_in_repository(){
+    check_all_files_in_repo(|path|{
+        let meta_data = MetaData::read_from_file(path, false)?;
+
+        let mut meta_data_bytes = Vec::new();
+        meta_data.write_to_buffered(&mut meta_data_bytes)?;
+
+        let sidecar_meta_data = MetaData::read_from_buffered(meta_data_bytes.as_slice(), false)?;
+        assert_eq!(sidecar_meta_data, meta_data);
+        Ok(())
+    });
+}
+
+#[test]
+fn meta_data_without_chunks_is_the_start_of_the_file() -> UnitResult {
+    let image = Image::empty(ImageAttributes::new(IntegerBounds::from_dimensions((24, 31))))
+        .with_layer(Layer::new((24, 17), LayerAttributes::named("first"), Encoding::FAST_LOSSLESS, SpecificChannels::rgb(|_| (0.5_f32, 0.25_f32, 1.0_f32))))
+        .with_layer(Layer::new((9, 31), LayerAttributes::named("second"), Encoding::SMALL_LOSSLESS, SpecificChannels::rgba(|_| (f16::ONE, f16::ZERO, f16::ONE, f16::ONE))));
+
+    let mut file = Vec::new();
+    image.write().to_buffered(Cursor::new(&mut file))?;
+
+    let meta_data = MetaData::read_from_buffered(file.as_slice(), true)?;
+
+    let mut meta_data_bytes = Vec::new();
+    meta_data.write_to_buffered(&mut meta_data_bytes)?;
+    assert_eq!(meta_data_bytes.as_slice(), &file[.. meta_data_bytes.len()]);
+
+    // the offset tables follow directly after the meta data
+    let chunk_count: usize = meta_data.headers.iter().map(|header| header.chunk_count).sum();
+    assert!(file.len() > meta_data_bytes.len() + chunk_count * 8);
+
+    Ok(())
+}
+
+#[test]
+fn read_channels_chosen_at_runtime() {
+    let path = "tests/images/valid/openexr/Beachball/singlepart.0001.exr";
+    let channel_names: Vec<String> = ["forward.left.u", "whitebarmask.right.mask", "disparityL.y", "crypto00"]
+        .iter().map(|name| name.to_string()).collect();
+
+    let image = read().no_deep_data().largest_resolution_level()
+        .channels_by_name(
+            channel_names.iter().map(String::as_str),
+            |resolution, channels: &[Option<ChannelDescription>]| {
+                assert!(channels[.. 3].iter().all(Option::is_some));
+                assert!(channels[3].is_none());
+                (resolution, vec![f32::NAN; resolution.area() * channels.len()])
+            },
+            |(resolution, samples), position, pixel: &[f32]| {
+                let index = (position.y() * resolution.width() + position.x()) * pixel.len();
+                samples[index .. index + pixel.len()].copy_from_slice(pixel);
+            }
+        )
+        .default_sample("crypto00", 7.0)
+        .first_valid_layer().all_attributes()
+        .from_file(path).unwrap();
+
+    let all_channels = read_first_flat_layer_from_file(path).unwrap();
+    let channels = &image.layer_data.channel_data;
+    let (resolution, samples) = &channels.pixels;
+    assert_eq!(*resolution, all_channels.layer_data.size);
+
+    for (channel_index, name) in channel_names[.. 3].iter().enumerate() {
+        let description = channels.channels[channel_index].as_ref().unwrap();
+        assert_eq!(description.name, Text::from(name.as_str()));
+
+        let expected = all_channels.layer_data.channel_data.list.iter()
+            .find(|channel| channel.name == description.name).unwrap()
+            .sample_data.values_as_f32();
+
+        let actual = samples.iter().skip(channel_index).step_by(4).cloned();
+        assert!(actual.eq(expected), "channel {} differs", name);
+    }
+
+    assert!(samples.iter().skip(3).step_by(4).all(|&sample| sample == 7.0));
+
+    let missing_channel = read().no_deep_data().largest_resolution_level()
+        .channels_by_name(["R", "crypto00"], |_, _| (), |_, _, _: &[f16]| ())
+        .first_valid_layer().all_attributes()
+        .from_file(path);
+
+    assert!(missing_channel.is_err());
+
+    // invalid user input is an error instead of a panic
+    let read_names = |names: &[&str], default_name: &str| {
+        read().no_deep_data().largest_resolution_level()
+            .channels_by_name(names.to_vec(), |_, _| (), |_, _, _: &[f16]| ())
+            .default_sample(default_name, f16::ZERO)
+            .all_layers().all_attributes()
+            .from_file(path)
+    };
+
+    let assert_invalid = |result: Result<Image<Layers<SpecificChannels<(), Vec<Option<ChannelDescription>>>>>>, expected: &str| match result {
+        Err(Error::Invalid(message)) => assert!(message.contains(expected), "`{}` does not contain `{}`", message, expected),
+        other => panic!("expected an error, but got {:?}", other.map(|_| ())),
+    };
+
+    assert!(read_names(&["disparityL.y", "crypto00"], "crypto00").is_ok());
+    assert_invalid(read_names(&["disparityL.y", "crypto00", "disparityL.y"], "crypto00"), "more than once");
+    assert_invalid(read_names(&["disparityL.y", ""], "disparityL.y"), "must not be empty");
+    assert_invalid(read_names(&[], "crypto00"), "at least one channel");
+    assert_invalid(read_names(&["disparityL.y"], "crypto00"), "`crypto00`");
+}
+
+#[test]
+fn roundtrip_six_specific_channels_with_optional_channels_in_any_slot() -> UnitResult {
+    type Pixel = (f16, f32, u32, f16, f32, f32);
+
+    let size = Vec2(19, 7);
+    let pixels = PixelVec::<Pixel> {
+        resolution: size,
+        pixels: (0 .. size.area()).map(|index| (
+            f16::from_f32(index as f32 * 0.25), index as f32 / 3.0, index as u32 * 7,
+            f16::from_f32(1.0 - index as f32 / 200.0), -(index as f32), f32::NAN,
+        )).collect()
+    };
+
+    let channels = SpecificChannels::build()
+        .with_channel("R").with_channel("G").with_channel("id")
+        .with_channel("A").with_channel("motion.u").with_channel("motion.v")
+        .with_pixels(pixels.clone());
+
+    let image = Image::from_channels(size, channels);
+
+    let mut bytes = Vec::new();
+    image.write().non_parallel().to_buffered(Cursor::new(&mut bytes))?;
+
+    let read_pixels = read().no_deep_data().largest_resolution_level()
+        .specific_channels()
+            .required("R").optional("G", 0.0).required("id")
+            .optional("A", f16::ONE).required("motion.u").optional("motion.v", 0.0)
+        .collect_pixels(PixelVec::<Pixel>::constructor, PixelVec::set_pixel)
+        .first_valid_layer().all_attributes().non_parallel()
+        .from_buffered(Cursor::new(&bytes))?;
+
+    let (_, green, _, alpha, _, motion_v) = &read_pixels.layer_data.channel_data.channels;
+    assert!(green.is_some() && alpha.is_some() && motion_v.is_some());
+    read_pixels.layer_data.channel_data.pixels.assert_equals_result(&pixels);
+
+    // a missing optional channel in the middle of the pixel uses the default sample
+    let defaulted_pixels = read().no_deep_data().largest_resolution_level()
+        .specific_channels()
+            .required("R").optional("G", 0.0).optional("B", 0.5).required("id")
+            .optional("A", f16::ONE).required("motion.u")
+        .collect_pixels(PixelVec::<(f16, f32, f32, u32, f16, f32)>::constructor, PixelVec::set_pixel)
+        .first_valid_layer().all_attributes().non_parallel()
+        .from_buffered(Cursor::new(&bytes))?;
+
+    assert!(defaulted_pixels.layer_data.channel_data.channels.2.is_none());
+
+    let expected_pixels = PixelVec {
+        resolution: size,
+        pixels: pixels.pixels.iter().map(|&(r, g, id, a, u, _)| (r, g, 0.5, id, a, u)).collect::<Vec<_>>()
+    };
+
+    defaulted_pixels.layer_data.channel_data.pixels.assert_equals_result(&expected_pixels);
+    Ok(())
+}
+
+#[test]
+fn planar_channels_equal_interleaved_pixels_for_all_files_in_repository() {
+    check_all_files_in_repo(|path| {
+        let interleaved = read().no_deep_data().largest_resolution_level()
+            .rgba_channels(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+            .first_valid_layer().all_attributes().non_parallel()
+            .from_file(path);
+
+        // files without rgba channels are not relevant here
+        let interleaved = match interleaved { Ok(image) => image, Err(_) => return Ok(()) };
+
+        let planar = read().no_deep_data().largest_resolution_level()
+            .specific_channels().required("R").required("G").required("B").optional("A", 1.0)
+            .planar()
+            .first_valid_layer().all_attributes().non_parallel()
+            .from_file(path)?;
+
+        let pixels = &interleaved.layer_data.channel_data.pixels;
+        let planar = &planar.layer_data.channel_data;
+
+        assert_eq!(planar.resolution, pixels.resolution);
+        assert_eq!(
+            planar.channels.iter().map(|(name, _)| name.to_string()).collect::<Vec<_>>(),
+            vec!["R", "G", "B", "A"]
+        );
+
+        for (index, &(r, g, b, a)) in pixels.pixels.iter().enumerate() {
+            for (channel_index, sample) in [r, g, b, a].iter().enumerate() {
+                assert_eq!(planar.channels[channel_index].1[index].to_bits(), sample.to_bits());
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn roundtrip_planar_channels() -> UnitResult {
+    let size = Vec2(27, 13);
+    let channel = |offset: f32| (0 .. size.area()).map(|index| index as f32 * 0.5 + offset).collect::<Vec<f32>>();
+
+    // the channels are not sorted alphabetically
+    let channels = PlanarChannels {
+        resolution: size,
+        channels: vec![
+            ("Y".into(), channel(0.0)),
+            ("BY".into(), channel(-3.0)),
+            ("RY".into(), channel(100.0)),
+        ]
+    };
+
+    let encoding = Encoding { blocks: Blocks::Tiles(Vec2(8, 8)), .. Encoding::SMALL_LOSSLESS };
+    let image = Image::from_layer(Layer::new(size, LayerAttributes::default(), encoding, channels.clone()));
+
+    let mut bytes = Vec::new();
+    image.write().non_parallel().to_buffered(Cursor::new(&mut bytes))?;
+
+    let image2 = read().no_deep_data().largest_resolution_level()
+        .specific_channels().required("Y").required("BY").required("RY").optional("A", 1.0)
+        .planar()
+        .first_valid_layer().all_attributes().non_parallel()
+        .from_buffered(Cursor::new(&bytes))?;
+
+    let mut expected_channels = channels;
+    expected_channels.channels.push(("A".into(), vec![1.0; size.area()]));
+    assert_eq!(image2.layer_data.channel_data, expected_channels);
+
+    Ok(())
+}
+
+#[test]
+fn luminance_chroma_is_converted_to_rgba() -> UnitResult {
+    use exr::meta::attribute::Chromaticities;
+
+    let size = Vec2(21, 11);
+    let weights = Chromaticities::REC_2020.luminance_weights();
+
+    let rgb = |Vec2(x, y): Vec2<usize>| (0.1 + x as f32 * 0.05, 0.2 + y as f32 * 0.1, 0.3 + (x * y) as f32 * 0.01);
+
+    // encode the pixels like the OpenEXR `RGBAtoYCA` function, but without subsampling
+    let luminance_chroma = SpecificChannels::build()
+        .with_channel("A").with_channel("BY").with_channel("RY").with_channel("Y")
+        .with_pixel_fn(|position| {
+            let (r, g, b) = rgb(position);
+            let luminance = r * weights[0] + g * weights[1] + b * weights[2];
+            (0.5_f32, b / luminance - 1.0, r / luminance - 1.0, luminance)
+        });
+
+    let mut attributes = ImageAttributes::new(IntegerBounds::from_dimensions(size));
+    attributes.chromaticities = Some(Chromaticities::REC_2020);
+
+    let image = Image::new(attributes, Layer::new(size, LayerAttributes::default(), Encoding::FAST_LOSSLESS, luminance_chroma));
+
+    let mut bytes = Vec::new();
+    image.write().non_parallel().to_buffered(Cursor::new(&mut bytes))?;
+
+    let rgba = read().no_deep_data().largest_resolution_level()
+        .rgba_channels_or_luminance_chroma(PixelVec::<(f32, f32, f32, f16)>::constructor, PixelVec::set_pixel)
+        .first_valid_layer().all_attributes().non_parallel()
+        .from_buffered(Cursor::new(&bytes))?;
+
+    let (red, green, blue, alpha) = &rgba.layer_data.channel_data.channels;
+    assert_eq!((red.name.to_string(), green.name.to_string(), blue.name.to_string()), ("R".into(), "G".into(), "B".into()));
+    assert!(alpha.is_some());
+
+    let pixels = &rgba.layer_data.channel_data.pixels;
+    for (index, &(r, g, b, a)) in pixels.pixels.iter().enumerate() {
+        let (expected_r, expected_g, expected_b) = rgb(Vec2(index % size.width(), index / size.width()));
+        assert!(
+            (r - expected_r).abs() < 1e-4 && (g - expected_g).abs() < 1e-4 && (b - expected_b).abs() < 1e-4,
+            "pixel {} is {:?} instead of {:?}", index, (r, g, b), (expected_r, expected_g, expected_b)
+        );
+
+        assert_eq!(a.to_f32(), 0.5);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn luminance_only_is_read_as_gray_rgba() -> UnitResult {
+    let path = "tests/images/valid/openexr/LuminanceChroma/Garden.exr";
+
+    let luminance = read().no_deep_data().largest_resolution_level()
+        .specific_channels().required("Y")
+        .collect_pixels(PixelVec::<(f32,)>::constructor, PixelVec::set_pixel)
+        .first_valid_layer().all_attributes().non_parallel()
+        .from_file(path)?;
+
+    let rgba = read().no_deep_data().largest_resolution_level()
+        .rgba_channels_or_luminance_chroma(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+        .first_valid_layer().all_attributes().non_parallel()
+        .from_file(path)?;
+
+    let pixels = &rgba.layer_data.channel_data.pixels;
+    assert_eq!(pixels.resolution, luminance.layer_data.channel_data.pixels.resolution);
+    assert!(rgba.layer_data.channel_data.channels.3.is_none());
+
+    for (&(r, g, b, a), &(y,)) in pixels.pixels.iter().zip(&luminance.layer_data.channel_data.pixels.pixels) {
+        assert_eq!((r.to_bits(), g.to_bits(), b.to_bits(), a), (y.to_bits(), y.to_bits(), y.to_bits(), 1.0));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn subsampled_chroma_is_upsampled_bilinearly() -> UnitResult {
+    use exr::block::writer::ChunksWriter;
+    use exr::block::BlockIndex;
+    use exr::image::read::luminance_chroma::luminance_chroma_to_rgb;
+    use exr::meta::attribute::{ChannelDescription, Chromaticities};
+
+    let size = Vec2(14, 70); // contains multiple blocks of each compression method
+    let weights = Chromaticities::REC_709.luminance_weights();
+
+    // bilinear interpolation reproduces chroma that changes linearly
+    let luminance = |Vec2(x, y): Vec2<usize>| 0.25 + (x + 2 * y) as f32 * 0.01;
+    let red_chroma = |Vec2(x, y): Vec2<usize>| x as f32 * 0.02 - y as f32 * 0.01;
+    let blue_chroma = |Vec2(_, y): Vec2<usize>| y as f32 * 0.015 - 0.1;
+
+    let chroma_channel = |name: &str| ChannelDescription { sampling: Vec2(2, 2), .. ChannelDescription::named(name, SampleType::F32) };
+    let channels = smallvec::smallvec![ chroma_channel("BY"), chroma_channel("RY"), ChannelDescription::named("Y", SampleType::F32) ];
+
+    // chroma lines and columns are only stored at even coordinates
+    let native_block_bytes = |block: BlockIndex| {
+        let mut bytes = Vec::new();
+
+        for y in block.pixel_position.y() .. block.pixel_position.y() + block.pixel_size.height() {
+            let line = |sample: &dyn Fn(Vec2<usize>) -> f32, step: usize|
+                (0 .. size.width()).step_by(step).flat_map(move |x| sample(Vec2(x, y)).to_ne_bytes()).collect::<Vec<u8>>();
+
+            if y % 2 == 0 {
+                bytes.extend(line(&blue_chroma, 2));
+                bytes.extend(line(&red_chroma, 2));
+            }
+
+            bytes.extend(line(&luminance, 1));
+        }
+
+        bytes
+    };
+
+    for compression in [Compression::Uncompressed, Compression::RLE, Compression::ZIP16, Compression::PIZ, Compression::B44] {
+        let header = Header::new("subsampled".into(), size, channels.clone())
+            .with_encoding(compression, exr::meta::BlockDescription::ScanLines, LineOrder::Increasing);
+
+        let mut bytes = Vec::new();
+        exr::block::write(Cursor::new(&mut bytes), smallvec::smallvec![ header ], true, |meta, chunk_writer| {
+            for (index_in_header_increasing_y, block) in meta.collect_ordered_block_data(native_block_bytes) {
+                chunk_writer.write_chunk(index_in_header_increasing_y, block.compress_to_chunk(&meta.headers)?)?;
+            }
+
+            Ok(())
+        })?;
+
+        let rgba = read().no_deep_data().largest_resolution_level()
+            .rgba_channels_or_luminance_chroma(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+            .first_valid_layer().all_attributes().from_buffered(Cursor::new(&bytes))?;
+
+        let pixels = &rgba.layer_data.channel_data.pixels;
+        for (index, &(r, g, b, a)) in pixels.pixels.iter().enumerate() {
+            let position = Vec2(index % size.width(), index / size.width());
+
+            // pixels after the last chroma sample use that sample
+            let chroma_position = Vec2(position.x().min(size.width() - 2), position.y().min(size.height() - 2));
+            let (expected_r, expected_g, expected_b) = luminance_chroma_to_rgb(
+                luminance(position), red_chroma(chroma_position), blue_chroma(chroma_position), weights
+            );
+
+            assert!(
+                (r - expected_r).abs() < 1e-5 && (g - expected_g).abs() < 1e-5 && (b - expected_b).abs() < 1e-5,
+                "{:?} pixel {:?} is {:?} instead of {:?}", compression, position, (r, g, b), (expected_r, expected_g, expected_b)
+            );
+
+            assert_eq!(a, 1.0);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn subsampled_luminance_chroma_files_are_read_as_rgba() -> UnitResult {
+    for name in ["Flowers", "MtTamNorth", "CrissyField", "StarField"] {
+        let path = format!("tests/images/valid/openexr/LuminanceChroma/{}.exr", name);
+        let meta = MetaData::read_from_file(&path, true)?;
+        assert!(meta.headers[0].channels.is_subsampled());
+
+        let read_rgba = |parallel: bool| {
+            let reader = read().no_deep_data().largest_resolution_level()
+                .rgba_channels_or_luminance_chroma(PixelVec::<(f32, f32, f32, f32)>::constructor, PixelVec::set_pixel)
+                .first_valid_layer().all_attributes();
+
+            if parallel { reader.from_file(&path) } else { reader.non_parallel().from_file(&path) }
+        };
+
+        // the pixels do not depend on the order in which the blocks are decompressed
+        let rgba = read_rgba(true)?;
+        assert_eq!(rgba, read_rgba(false)?);
+
+        let pixels = &rgba.layer_data.channel_data.pixels;
+        assert_eq!(pixels.resolution, meta.headers[0].layer_size);
+
+        assert!(pixels.pixels.iter().all(|&(r, g, b, a)| r.is_finite() && g.is_finite() && b.is_finite() && a == 1.0), "{}", name);
+        assert!(pixels.pixels.iter().any(|&(r, g, b, _)| r != g || g != b), "{} contains no colors", name);
+    }
+
+    // the other readers do not support subsampled channels yet
+    let path = "tests/images/valid/openexr/LuminanceChroma/Flowers.exr";
+    assert!(matches!(read_all_data_from_file(path), Err(Error::NotSupported(_))));
+
+    Ok(())
+}